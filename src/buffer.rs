@@ -0,0 +1,1511 @@
+//! Storage for the text of the documents being edited, along with the edits that can be made to it.
+use std::cmp;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::iter;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::errors::{EditorError, Result};
+use crate::git::LineChange;
+use crate::lsp::Diagnostic;
+use crate::marks::Marks;
+use crate::undo::{Change, UndoStack};
+use crate::unicode;
+
+mod rope;
+
+pub use rope::Rope;
+
+/// How much of a file is read in at a time.
+const READ_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// How big a buffer has to be before the editor stops doing the things that would take too long with it, like keeping a swap file.
+const LARGE_FILE_SIZE: usize = 64 * 1024 * 1024;
+
+/// A position in a document, with both the line and the column (in bytes) counted from zero.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self { line, column }
+    }
+}
+
+/// The different movements that can can be handled in `Navigation` mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorMovement {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// The line endings that a file is written with, named after Vim's `fileformat` option.
+///
+/// Inside the buffer lines always end with a plain `\n`, and they're only turned into `\r\n` on the way out.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    #[default]
+    Unix,
+    Dos,
+}
+
+impl FileFormat {
+    /// Picks whichever line ending most of the lines in `text` end with.
+    fn detect(text: &str) -> Self {
+        let lines = text.matches('\n').count();
+        let dos_lines = text.matches("\r\n").count();
+        match dos_lines > lines - dos_lines {
+            true => FileFormat::Dos,
+            false => FileFormat::Unix,
+        }
+    }
+
+    /// The name that `:set fileformat` knows the format by.
+    pub fn name(self) -> &'static str {
+        match self {
+            FileFormat::Unix => "unix",
+            FileFormat::Dos => "dos",
+        }
+    }
+
+    /// Finds the format with the given name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(FileFormat::Unix),
+            "dos" => Some(FileFormat::Dos),
+            _ => None,
+        }
+    }
+}
+
+/// A document that is being edited, and the position of the cursor within it.
+pub struct Buffer {
+    /// The path to the file that this buffer should be written into.
+    path: Option<PathBuf>,
+    /// The contents of the buffer.
+    contents: Rope,
+    /// Whether the buffer has been changed since it was last written in a way that the history doesn't cover, like its line
+    /// endings being changed. Changes that can be undone are tracked by the history itself.
+    modified: bool,
+    /// When the file was last modified as of reading or writing it, so that changes made by something else can be spotted.
+    mtime: Option<SystemTime>,
+    /// The line endings to write the file with.
+    format: FileFormat,
+    /// Whether the buffer is only there to be looked at, because its file can't be written or the user asked for it to be.
+    read_only: bool,
+    /// How many times the contents have been changed, so that it's easy to tell whether something changed them.
+    revision: u64,
+    /// The changes that have been made to the buffer, so that they can be undone.
+    history: UndoStack,
+    /// The marks set in the document, and the places the cursor has jumped from.
+    marks: Marks,
+    /// The position of the cursor in the document.
+    cursor: Position,
+    /// The cell of the display that moving up and down keeps the cursor in (like Vim's `curswant`), along with where the cursor
+    /// was left when it was set. Once the cursor has been moved any other way, the cell is worked out afresh from wherever it is.
+    desired_cell: Option<(Position, usize)>,
+    /// The problems that a language server found with the document, as of the last time it said.
+    diagnostics: Vec<Diagnostic>,
+    /// The problems that a linter found with the file, as of the last time one was run on it.
+    lint: Vec<Diagnostic>,
+    /// How the lines differ from the last commit, in order, as of the last time the file was read or written.
+    line_changes: Vec<(usize, LineChange)>,
+    /// The parts of changed lines that differ from the file this one is being compared with, when there is one.
+    changed_text: Vec<Range<usize>>,
+}
+
+impl Buffer {
+    /// Creates a new `Buffer` with the supplied string copied into it.
+    pub fn new(s: &str) -> Self {
+        Self {
+            path: None,
+            contents: Rope::from(s),
+            modified: false,
+            mtime: None,
+            format: Default::default(),
+            read_only: false,
+            revision: 0,
+            history: Default::default(),
+            marks: Default::default(),
+            cursor: Default::default(),
+            diagnostics: Vec::new(),
+            lint: Vec::new(),
+            line_changes: Vec::new(),
+            changed_text: Vec::new(),
+            desired_cell: None,
+        }
+    }
+
+    /// Wraps a new `Buffer` around a path on the filesystem.
+    ///
+    /// If the file exists it will be read into the buffer, if it does not then an empty buffer will be initialised.
+    /// The directory that the file is in must exist, because we don't currently have a way of displaying the error we would receive when we try to write the file.
+    pub fn from_path<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut file = PathBuf::from("./");
+        // If `path` is absolute then this will flush out the `"./"` currently in the buffer, which is pretty neat!
+        file.push(&path);
+
+        match file.parent().map(|d| d.exists()) {
+            Some(true) => (),
+            Some(false) => {
+                return Err(EditorError::DirectoryDoesNotExist(
+                    file.parent().unwrap().to_path_buf(),
+                ))
+            }
+            None => return Err(EditorError::CannotOpenRoot),
+        }
+
+        let mut buffer = match file.exists() {
+            true => Self::from_reader(File::open(&path).map_err(EditorError::FileIo)?)?,
+            false => Self::new(""),
+        };
+        buffer.path = Some(path.as_ref().to_path_buf());
+        buffer.mtime = modified_time(path.as_ref());
+        // Opening the file for writing (without truncating it) is the surest way to find out whether it could be written.
+        buffer.read_only = file.exists()
+            && matches!(
+                OpenOptions::new().write(true).open(&path),
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied
+            );
+        Ok(buffer)
+    }
+
+    /// Creates a new `Buffer` from everything that can be read out of `reader`.
+    ///
+    /// This is mostly useful for reading from stdin, in which case the buffer won't have a path to write back into.
+    ///
+    /// The text is read a block at a time and added onto the end of the rope, so a large file is never held in one big `String` as well.
+    /// Line endings are turned into plain `\n`s if most of them in the first block with any in were `\r\n`.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self> {
+        let mut buffer = Self::new("");
+        let mut block = vec![0; READ_BLOCK_SIZE];
+        let mut pending = Vec::new();
+        let mut detected = false;
+        loop {
+            let read = match reader.read(&mut block) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(EditorError::FileIo(e)),
+            };
+            pending.extend_from_slice(&block[..read]);
+
+            // A character can be split across two blocks, in which case its start waits for the rest of it.
+            let valid = match std::str::from_utf8(&pending) {
+                Ok(text) => text.len(),
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(_) => return Err(invalid_utf8()),
+            };
+            let mut text = std::str::from_utf8(&pending[..valid]).unwrap_or_default();
+            if !detected && text.contains('\n') {
+                buffer.format = FileFormat::detect(text);
+                detected = true;
+            }
+            if buffer.format == FileFormat::Dos || !detected {
+                // The same goes for a `\r\n`, so that it gets turned into a `\n` once the rest of it turns up.
+                text = text.strip_suffix('\r').unwrap_or(text);
+                let end = buffer.contents.len();
+                buffer.contents.insert(end, &text.replace("\r\n", "\n"));
+            } else {
+                let end = buffer.contents.len();
+                buffer.contents.insert(end, text);
+            }
+            let used = text.len();
+            pending.drain(..used);
+        }
+
+        match std::str::from_utf8(&pending) {
+            Ok(rest) => {
+                let end = buffer.contents.len();
+                buffer.contents.insert(end, rest);
+                Ok(buffer)
+            }
+            Err(_) => Err(invalid_utf8()),
+        }
+    }
+
+    /// Whether the buffer is big enough that anything which goes over the whole of it should be avoided.
+    pub fn is_large(&self) -> bool {
+        self.contents.len() >= LARGE_FILE_SIZE
+    }
+
+    /// A number that goes up every time the contents of the buffer change.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Replaces the problems found with the document with the ones a language server has just sent.
+    pub fn set_diagnostics(&mut self, diagnostics: Vec<Diagnostic>) {
+        self.diagnostics = diagnostics;
+    }
+
+    /// The worst problem that starts on a line, if there are any.
+    pub fn diagnostic_on(&self, line: usize) -> Option<&Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.line == line)
+            .min_by_key(|d| d.severity)
+    }
+
+    /// Replaces the problems found with the file with the ones a linter has just found.
+    pub fn set_lint(&mut self, lint: Vec<Diagnostic>) {
+        self.lint = lint;
+    }
+
+    /// The worst problem that a linter found on a line, if it found any.
+    pub fn lint_on(&self, line: usize) -> Option<&Diagnostic> {
+        self.lint
+            .iter()
+            .filter(|d| d.line == line)
+            .min_by_key(|d| d.severity)
+    }
+
+    /// Whether there are any changed lines or problems from a linter to mark in the gutter.
+    pub fn has_signs(&self) -> bool {
+        self.has_line_changes() || !self.lint.is_empty()
+    }
+
+    /// Replaces the lines that have changed since the last commit, which need to be in order.
+    pub fn set_line_changes(&mut self, line_changes: Vec<(usize, LineChange)>) {
+        self.line_changes = line_changes;
+    }
+
+    /// Whether any lines have changed since the last commit, so there's something to show in the gutter.
+    pub fn has_line_changes(&self) -> bool {
+        !self.line_changes.is_empty()
+    }
+
+    /// How a line has changed since the last commit, if it has.
+    pub fn line_change(&self, line: usize) -> Option<LineChange> {
+        let i = self
+            .line_changes
+            .binary_search_by_key(&line, |&(l, _)| l)
+            .ok()?;
+        Some(self.line_changes[i].1)
+    }
+
+    /// Replaces the parts of lines that differ from the file this one is being compared with.
+    pub fn set_changed_text(&mut self, changed_text: Vec<Range<usize>>) {
+        self.changed_text = changed_text;
+    }
+
+    /// The parts of lines that differ from the file this one is being compared with.
+    pub fn changed_text(&self) -> &[Range<usize>] {
+        &self.changed_text
+    }
+
+    /// Whether the buffer shouldn't be changed.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Makes the buffer read-only, or lets it be changed again.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// The line endings that the file gets written with.
+    pub fn file_format(&self) -> FileFormat {
+        self.format
+    }
+
+    /// Changes the line endings that the file gets written with, which leaves it needing to be written.
+    pub fn set_file_format(&mut self, format: FileFormat) {
+        if format != self.format {
+            self.format = format;
+            self.modified = true;
+        }
+    }
+
+    /// The path to the file that this buffer is written into, if it has one.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The name to show the user for the buffer, which is the path to its file if it has one.
+    pub fn name(&self) -> String {
+        match &self.path {
+            Some(path) => path.display().to_string(),
+            None => "[No Name]".to_string(),
+        }
+    }
+
+    /// Attaches the buffer to a different file, which it will be written into from now on.
+    pub fn set_path(&mut self, path: PathBuf) {
+        self.path = Some(path);
+        self.mtime = None;
+    }
+
+    /// Changes the path that the buffer's file goes by without it becoming a different file, like when the working
+    /// directory changes and a relative path needs to be found another way.
+    pub fn relocate(&mut self, relocate: impl Fn(&Path) -> PathBuf) {
+        if let Some(path) = &mut self.path {
+            *path = relocate(path);
+        }
+    }
+
+    /// Stops treating the file as changed by something else, once the user has decided to keep the buffer as it is.
+    pub fn ignore_disk_changes(&mut self) {
+        if let Some(path) = &self.path {
+            self.mtime = modified_time(path);
+        }
+    }
+
+    /// Whether something else has changed the file since the buffer last read or wrote it.
+    ///
+    /// A file that has gone missing hasn't changed, as far as this is concerned, since writing it again won't lose anything.
+    pub fn changed_on_disk(&self) -> bool {
+        match (&self.path, self.mtime) {
+            (Some(path), Some(mtime)) => modified_time(path).is_some_and(|now| now != mtime),
+            _ => false,
+        }
+    }
+
+    /// Whether the buffer has been changed since it was last written.
+    pub fn is_modified(&self) -> bool {
+        self.modified || !self.history.is_at_saved()
+    }
+
+    pub fn contents(&self) -> &Rope {
+        &self.contents
+    }
+
+    pub fn cursor(&self) -> Position {
+        self.cursor
+    }
+
+    /// Counts the lines in the buffer, in the same way as `str::lines` would.
+    pub fn num_lines(&self) -> usize {
+        let ends_with_newline = self.contents.char_before(self.contents.len()) == Some('\n');
+        match self.contents.is_empty() || ends_with_newline {
+            true => self.contents.line_count() - 1,
+            false => self.contents.line_count(),
+        }
+    }
+
+    /// Finds the part of the `contents` buffer that holds a line, without its line ending.
+    pub fn line_range(&self, line: usize) -> Option<Range<usize>> {
+        let start = self.contents.line_start(line)?;
+        let mut end = self.contents.line_end(line)?;
+        if end > start && self.contents.char_before(end) == Some('\r') {
+            end -= 1;
+        }
+        Some(start..end)
+    }
+
+    /// Fetches a single line from the buffer, without its line ending.
+    #[cfg(test)]
+    fn line(&self, line: usize) -> Option<String> {
+        self.line_range(line)
+            .map(|range| self.contents.slice(range))
+    }
+
+    /// Determines the length of a line, without its line ending.
+    pub fn line_length(&self, line: usize) -> usize {
+        self.line_range(line).map(|range| range.len()).unwrap_or(0)
+    }
+
+    /// Finds the column that the last character of a line starts at, which is as far as the cursor goes outside of Edit mode.
+    pub fn last_column(&self, line: usize) -> usize {
+        match self.line_range(line) {
+            Some(range) if !range.is_empty() => {
+                self.contents.previous_grapheme(range.end) - range.start
+            }
+            _ => 0,
+        }
+    }
+
+    /// Splits part of a line up into the characters that the user sees, along with how many cells of the display each one takes up.
+    ///
+    /// Tabs stretch out to the next multiple of `tab_width` cells, counting from the start of `range`.
+    pub fn cells(
+        &self,
+        range: Range<usize>,
+        tab_width: usize,
+    ) -> impl Iterator<Item = (Range<usize>, usize)> + '_ {
+        let mut i = range.start;
+        let mut cell = 0;
+        iter::from_fn(move || {
+            if i >= range.end {
+                return None;
+            }
+            let start = i;
+            i = cmp::min(self.contents.next_grapheme(i), range.end);
+            if self.contents.char_at(start) == Some('\t') {
+                let width = tab_width - cell % tab_width;
+                cell += width;
+                return Some((start..i, width));
+            }
+
+            // Whatever is attached to the first character is drawn on top of it, but emoji joined together can come out wider.
+            let mut width = 0;
+            let mut j = start;
+            while let Some(c) = self.contents.char_at(j).filter(|_| j < i) {
+                width = cmp::max(width, unicode::width(c));
+                j += c.len_utf8();
+            }
+            cell += width;
+            Some((start..i, width))
+        })
+    }
+
+    /// Works out how many cells of the display come before a position on its line.
+    pub fn display_column(&self, position: Position, tab_width: usize) -> usize {
+        let range = match self.line_range(position.line) {
+            Some(range) => range,
+            None => return 0,
+        };
+        let end = cmp::min(range.start + position.column, range.end);
+        let width: usize = self
+            .cells(range.start..end, tab_width)
+            .map(|(_, width)| width)
+            .sum();
+        width + (range.start + position.column).saturating_sub(end)
+    }
+
+    /// Finds the column of the character drawn in a cell of the display, counting cells from the start of the line.
+    ///
+    /// Cells past the end of the line give the column after its last character.
+    pub fn column_at(&self, line: usize, cell: usize, tab_width: usize) -> usize {
+        let range = match self.line_range(line) {
+            Some(range) => range,
+            None => return 0,
+        };
+        let mut end = 0;
+        for (character, width) in self.cells(range.clone(), tab_width) {
+            end += width;
+            if end > cell {
+                return character.start - range.start;
+            }
+        }
+        range.len()
+    }
+
+    /// Finds the text of a line that's drawn in some of the cells of the display, including any characters only partly in them.
+    ///
+    /// A line that's too short to reach the cells gives an empty range at its end.
+    pub fn cell_span(&self, line: usize, cells: Range<usize>, tab_width: usize) -> Range<usize> {
+        let range = match self.line_range(line) {
+            Some(range) => range,
+            None => return self.contents.len()..self.contents.len(),
+        };
+        let (mut start, mut end) = (None, range.end);
+        let mut cell = 0;
+        for (character, width) in self.cells(range.clone(), tab_width) {
+            let character_cells = cell..cell + width;
+            cell = character_cells.end;
+            if character_cells.end <= cells.start {
+                continue;
+            }
+            if character_cells.start >= cells.end {
+                end = character.start;
+                break;
+            }
+            start.get_or_insert(character.start);
+        }
+        let start = start.unwrap_or(end);
+        start..cmp::max(start, end)
+    }
+
+    /// Determines the index in the `contents` buffer of a position in the document.
+    pub fn index_of(&self, position: Position) -> usize {
+        self.contents
+            .line_start(position.line)
+            .unwrap_or(self.contents.len())
+            + position.column
+    }
+
+    /// Determines the position of the cursor in the `contents` buffer.
+    pub fn cursor_index(&self) -> usize {
+        self.index_of(self.cursor)
+    }
+
+    /// Moves the cursor to an index in the `contents` buffer.
+    pub fn set_cursor_index(&mut self, idx: usize) {
+        let line = self.contents.line_of(idx);
+        let column = idx - self.contents.line_start(line).unwrap_or(0);
+        self.cursor = Position::new(line, column);
+    }
+
+    /// Moves the cursor a single step, over a whole character as the user sees it (even if it's made up of several `char`s.)
+    ///
+    /// The cursor can only move onto the end of a line (after its last character) if `past_end` is set.
+    /// Moving up and down keeps the cursor in the same cell of the display, as far as the lines are long enough for it, with tabs
+    /// reaching the stops `tab_width` cells apart.
+    pub fn move_cursor(&mut self, direction: CursorMovement, past_end: bool, tab_width: usize) {
+        let idx = self.cursor_index();
+        match direction {
+            CursorMovement::Left => {
+                if self.cursor.column > 0 {
+                    self.cursor.column -= idx - self.contents.previous_grapheme(idx);
+                }
+            }
+            CursorMovement::Right => {
+                let last_column = match past_end {
+                    true => self.line_length(self.cursor.line),
+                    false => self.last_column(self.cursor.line),
+                };
+                if self.cursor.column < last_column {
+                    self.cursor.column += self.contents.next_grapheme(idx) - idx;
+                }
+            }
+            CursorMovement::Up | CursorMovement::Down => {
+                let cell = self.desired_cell(tab_width);
+                let line = match direction {
+                    CursorMovement::Up => self.cursor.line.saturating_sub(1),
+                    _ => cmp::min(self.cursor.line + 1, self.num_lines().saturating_sub(1)),
+                };
+                self.cursor = Position::new(line, self.column_at(line, cell, tab_width));
+                self.clamp_cursor_column();
+                self.set_desired_cell(cell);
+            }
+        }
+    }
+
+    /// Finds the cell of the display that moving up or down should keep the cursor in.
+    ///
+    /// That's the cell the cursor was in before it started going up and down, unless it's been moved some other way since.
+    pub fn desired_cell(&self, tab_width: usize) -> usize {
+        match self.desired_cell {
+            Some((position, cell)) if position == self.cursor => cell,
+            _ => self.display_column(self.cursor, tab_width),
+        }
+    }
+
+    /// Keeps the cursor in a cell of the display the next time it moves up or down, unless it's moved some other way first.
+    pub fn set_desired_cell(&mut self, cell: usize) {
+        self.desired_cell = Some((self.cursor, cell));
+    }
+
+    /// Moves the cursor to a position in the document.
+    ///
+    /// Both `line` and `column` are clamped to the contents of the buffer.
+    pub fn goto(&mut self, line: usize, column: usize) {
+        self.cursor.line = cmp::min(line, self.num_lines().saturating_sub(1));
+        self.cursor.column = cmp::min(column, self.last_column(self.cursor.line));
+        self.clamp_cursor_column();
+    }
+
+    /// Sets the mark `name` to where the cursor is.
+    pub fn set_mark(&mut self, name: char) {
+        self.marks.set(name, self.cursor);
+    }
+
+    /// Finds where a mark has been set, if it has.
+    pub fn mark(&self, name: char) -> Option<Position> {
+        self.marks.get(name)
+    }
+
+    /// Remembers where the cursor is before it makes a long jump, so that `Ctrl-O` can come back to it.
+    pub fn push_jump(&mut self) {
+        self.marks.push_jump(self.cursor);
+    }
+
+    /// Moves the cursor back to where it last jumped from, returning whether there was anywhere to go.
+    pub fn jump_back(&mut self) -> bool {
+        match self.marks.jump_back(self.cursor) {
+            Some(position) => {
+                self.goto(position.line, position.column);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the cursor forward again through the places it went back from, returning whether there was anywhere to go.
+    pub fn jump_forward(&mut self) -> bool {
+        match self.marks.jump_forward(self.cursor) {
+            Some(position) => {
+                self.goto(position.line, position.column);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the cursor onto another line, keeping it in the same column if that line is long enough.
+    pub fn goto_line(&mut self, line: usize) {
+        self.cursor.line = cmp::min(line, self.num_lines().saturating_sub(1));
+        self.clamp_cursor_column();
+    }
+
+    /// Pulls the cursor back onto the line it currently sits on, and onto the start of the character it's in the middle of.
+    fn clamp_cursor_column(&mut self) {
+        self.cursor.column = cmp::min(self.cursor.column, self.line_length(self.cursor.line));
+        let idx = self.cursor_index();
+        self.cursor.column -= idx - self.contents.grapheme_start(idx);
+    }
+
+    /// Inserts `text` into the `contents` buffer at `idx`.
+    fn insert_text(&mut self, idx: usize, text: &str) {
+        self.contents.insert(idx, text);
+        self.revision += 1;
+    }
+
+    /// Removes a range of text from the `contents` buffer.
+    fn remove_text(&mut self, range: Range<usize>) -> String {
+        self.revision += 1;
+        self.contents.remove(range)
+    }
+
+    /// Inserts a character into the buffer at the cursor position, leaving the cursor after it.
+    pub fn insert(&mut self, c: char) {
+        let idx = self.cursor_index();
+        let text = c.to_string();
+        self.insert_text(idx, &text);
+        self.history
+            .record(Change::Insert { index: idx, text }, idx);
+        self.set_cursor_index(idx + c.len_utf8());
+    }
+
+    /// Removes the character before the cursor position, along with any accents or modifiers attached to it.
+    pub fn remove(&mut self) {
+        let idx = self.cursor_index();
+        let start = self.contents.previous_grapheme(idx);
+        if start < idx {
+            let text = self.remove_text(start..idx);
+            self.history
+                .record(Change::Remove { index: start, text }, idx);
+            self.set_cursor_index(start);
+        }
+    }
+
+    /// Removes a range of text from the buffer, leaving the cursor where it started.
+    pub fn delete(&mut self, range: Range<usize>) -> String {
+        let cursor = self.cursor_index();
+        let start = range.start;
+        let text = self.remove_text(range);
+        self.history.record(
+            Change::Remove {
+                index: start,
+                text: text.clone(),
+            },
+            cursor,
+        );
+        self.set_cursor_index(start);
+        text
+    }
+
+    /// Replaces a range of text in the buffer with `text`, leaving the cursor where the range started.
+    pub fn replace(&mut self, range: Range<usize>, text: &str) {
+        let cursor = self.cursor_index();
+        let start = range.start;
+        if !range.is_empty() {
+            let removed = self.remove_text(range);
+            self.history.record(
+                Change::Remove {
+                    index: start,
+                    text: removed,
+                },
+                cursor,
+            );
+        }
+        if !text.is_empty() {
+            self.insert_text(start, text);
+            self.history.record(
+                Change::Insert {
+                    index: start,
+                    text: text.to_string(),
+                },
+                cursor,
+            );
+        }
+        self.set_cursor_index(start);
+    }
+
+    /// Closes off the current step in the history, so that the next change is undone separately.
+    pub fn commit(&mut self) {
+        self.history.commit();
+    }
+
+    /// Makes a change to the buffer, without recording it in the history.
+    fn apply(&mut self, change: &Change) {
+        match change {
+            Change::Insert { index, text } => self.insert_text(*index, text),
+            Change::Remove { index, text } => {
+                self.remove_text(*index..index + text.len());
+            }
+        }
+    }
+
+    /// Reverses the most recent step in the history, returning whether there was one to reverse.
+    pub fn undo(&mut self) -> bool {
+        match self.history.undo() {
+            Some(step) => {
+                for change in step.changes.iter().rev() {
+                    self.apply(&change.inverse());
+                }
+                self.set_cursor_index(step.cursor);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Makes the most recently undone step again, returning whether there was one to make.
+    pub fn redo(&mut self) -> bool {
+        match self.history.redo() {
+            Some(step) => {
+                for change in step.changes.iter() {
+                    self.apply(change);
+                }
+                // The cursor was where it was before the changes, which could now be in the middle of a character they made.
+                self.set_cursor_index(self.contents.grapheme_start(step.cursor));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Normalizes the buffer into Unicode NFC, returning the number of lines that were changed.
+    ///
+    /// Each changed line is replaced through the history, so normalizing can be undone in a single step like any other edit.
+    pub fn normalize(&mut self) -> usize {
+        let cursor = self.cursor;
+        self.commit();
+        let mut changed = 0;
+        // Normalization never touches a newline, so going from the bottom up leaves the lines still to do where they were.
+        for line in (0..self.num_lines()).rev() {
+            let range = match self.line_range(line) {
+                Some(range) => range,
+                None => continue,
+            };
+            let text = self.contents.slice(range.clone());
+            let normalized = unicode::nfc(&text);
+            if normalized != text {
+                self.replace(range, &normalized);
+                changed += 1;
+            }
+        }
+        self.commit();
+
+        // Composing characters shortens the line, so the cursor might now be sitting in the middle of one.
+        self.cursor = cursor;
+        self.clamp_cursor_column();
+        while !self.contents.is_char_boundary(self.cursor_index()) {
+            self.cursor.column -= 1;
+        }
+
+        changed
+    }
+
+    /// Finds the swap file that unsaved changes to the buffer are kept in, which sits next to its file like Vim's `.file.swp`.
+    pub fn swap_path(&self) -> Option<PathBuf> {
+        let path = self.path.as_ref()?;
+        let name = path.file_name()?.to_string_lossy();
+        Some(path.with_file_name(format!(".{}.swp", name)))
+    }
+
+    /// Copies the file as it currently is on disk to `file~`, before the buffer gets written over it.
+    pub fn write_backup(&self) -> Result<()> {
+        let path = self.path.as_ref().ok_or(EditorError::NoFileName)?;
+        if !path.exists() {
+            return Ok(());
+        }
+        let mut backup = path.clone().into_os_string();
+        backup.push("~");
+        fs::copy(path, backup).map_err(EditorError::FileIo)?;
+        Ok(())
+    }
+
+    /// Writes the buffer to the file at `path`.
+    ///
+    /// The contents go into a temporary file next to the real one first, which is then renamed over it.
+    /// That way a crash part way through leaves the old file where it was, rather than half of the new one.
+    pub fn write(&mut self) -> Result<()> {
+        let path = self.path.as_ref().ok_or(EditorError::NoFileName)?;
+
+        // Renaming over a symlink would replace the link itself, so the file it points at is written instead.
+        let path = match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                fs::canonicalize(path).map_err(EditorError::FileIo)?
+            }
+            _ => path.clone(),
+        };
+        let temp_path = temp_path_for(&path);
+        let written = self.write_through(&temp_path, &path);
+        if !matches!(written, Ok(true)) {
+            let _ = fs::remove_file(&temp_path);
+        }
+
+        // When the directory can't be written into, or the file belongs to somebody that the new one can't be given to, it gets written over in place instead.
+        // That isn't atomic, but it's better than not being able to write at all or taking the file from its owner.
+        if !written.map_err(EditorError::FileIo)? {
+            self.write_in_place(&path).map_err(EditorError::FileIo)?;
+        }
+
+        self.mtime = modified_time(&path);
+        self.modified = false;
+        self.history.mark_saved();
+        Ok(())
+    }
+
+    /// Writes the contents into a new file at `temp_path`, then moves it into place at `path` with the same permissions and owner as the file it replaces.
+    ///
+    /// Returns false without touching `path` if the new file couldn't be made to match the old one.
+    fn write_through(&self, temp_path: &Path, path: &Path) -> std::io::Result<bool> {
+        let mut file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(temp_path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        if let Ok(metadata) = fs::metadata(path) {
+            if !same_owner(&file, &metadata) {
+                return Ok(false);
+            }
+            file.set_permissions(metadata.permissions())?;
+        }
+        self.write_contents(&mut file)?;
+        file.sync_all()?;
+        fs::rename(temp_path, path)?;
+
+        // The rename only survives a crash once the directory it happened in has been synced too.
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            let _ = File::open(dir).and_then(|dir| dir.sync_all());
+        }
+        Ok(true)
+    }
+
+    /// Writes the contents straight over the file at `path`, which keeps everything about the file but its contents.
+    fn write_in_place(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        self.write_contents(&mut file)?;
+        file.sync_all()
+    }
+
+    /// Writes the contents into `file`, with the line endings that the buffer's file format calls for.
+    ///
+    /// Lines that already end in `\r\n`, like ones pasted in from somewhere else, keep it rather than getting another `\r`.
+    fn write_contents(&self, file: &mut File) -> std::io::Result<()> {
+        // The `\r` of a `\r\n` can be at the end of the chunk before the `\n`.
+        let mut after_cr = false;
+        for chunk in self.contents.chunks() {
+            match self.format {
+                FileFormat::Unix => file.write_all(chunk.as_bytes())?,
+                FileFormat::Dos => {
+                    let mut converted = String::with_capacity(chunk.len());
+                    for c in chunk.chars() {
+                        if c == '\n' && !after_cr {
+                            converted.push('\r');
+                        }
+                        converted.push(c);
+                        after_cr = c == '\r';
+                    }
+                    file.write_all(converted.as_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The error for a file that isn't UTF-8, which is what reading it into a `String` would have given.
+fn invalid_utf8() -> EditorError {
+    EditorError::FileIo(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "stream did not contain valid UTF-8",
+    ))
+}
+
+/// Finds when the file at `path` was last modified, if it exists.
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Gives a newly made file the same owner and group as an existing one, returning whether that worked.
+#[cfg(unix)]
+fn same_owner(file: &File, metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::{fchown, MetadataExt};
+
+    fchown(file, Some(metadata.uid()), Some(metadata.gid())).is_ok()
+}
+
+#[cfg(not(unix))]
+fn same_owner(_: &File, _: &fs::Metadata) -> bool {
+    true
+}
+
+/// Picks a name for the temporary file that gets written before being renamed over `path`.
+///
+/// It has to go in the same directory, since a rename can't move a file onto another file system.
+pub fn temp_path_for(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.te-tmp", name, std::process::id()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    /// Lists the index at which each line of the buffer starts.
+    fn line_starts(buffer: &Buffer) -> Vec<usize> {
+        (0..)
+            .map_while(|line| buffer.contents.line_start(line))
+            .collect()
+    }
+
+    #[test]
+    fn test_moving_cursor_left() {
+        let mut buffer = Buffer::new("...\n...\n...");
+        buffer.cursor = Position::new(1, 1);
+        buffer.move_cursor(CursorMovement::Left, false, 8);
+        assert_eq!(buffer.cursor, Position::new(1, 0));
+    }
+
+    #[test]
+    fn test_moving_cursor_right() {
+        let mut buffer = Buffer::new("...\n...\n...");
+        buffer.cursor = Position::new(1, 1);
+        buffer.move_cursor(CursorMovement::Right, false, 8);
+        assert_eq!(buffer.cursor, Position::new(1, 2));
+    }
+
+    #[test]
+    fn test_moving_cursor_up() {
+        let mut buffer = Buffer::new("...\n...\n...");
+        buffer.cursor = Position::new(1, 1);
+        buffer.move_cursor(CursorMovement::Up, false, 8);
+        assert_eq!(buffer.cursor, Position::new(0, 1));
+    }
+
+    #[test]
+    fn test_moving_cursor_down() {
+        let mut buffer = Buffer::new("...\n...\n...");
+        buffer.cursor = Position::new(1, 1);
+        buffer.move_cursor(CursorMovement::Down, false, 8);
+        assert_eq!(buffer.cursor, Position::new(2, 1));
+    }
+
+    #[test]
+    fn test_moving_cursor_left_at_edge() {
+        let mut buffer = Buffer::new("...\n...\n...");
+        buffer.cursor = Position::new(1, 0);
+        buffer.move_cursor(CursorMovement::Left, false, 8);
+        assert_eq!(buffer.cursor, Position::new(1, 0));
+    }
+
+    #[test]
+    fn test_moving_cursor_right_at_edge() {
+        let mut buffer = Buffer::new("...\n...\n...");
+        buffer.cursor = Position::new(1, 2);
+        buffer.move_cursor(CursorMovement::Right, false, 8);
+        assert_eq!(buffer.cursor, Position::new(1, 2));
+
+        // Edit mode lets the cursor onto the end of the line, so that text can be added there.
+        buffer.move_cursor(CursorMovement::Right, true, 8);
+        assert_eq!(buffer.cursor, Position::new(1, 3));
+    }
+
+    #[test]
+    fn test_moving_cursor_up_at_edge() {
+        let mut buffer = Buffer::new("...\n...\n...");
+        buffer.cursor = Position::new(0, 1);
+        buffer.move_cursor(CursorMovement::Up, false, 8);
+        assert_eq!(buffer.cursor, Position::new(0, 1));
+    }
+
+    #[test]
+    fn test_moving_cursor_down_at_edge() {
+        let mut buffer = Buffer::new("...\n...\n...");
+        buffer.cursor = Position::new(2, 1);
+        buffer.move_cursor(CursorMovement::Down, false, 8);
+        assert_eq!(buffer.cursor, Position::new(2, 1));
+    }
+
+    #[test]
+    fn test_moving_onto_shorter_line() {
+        let mut buffer = Buffer::new("abcdef\nab");
+        buffer.cursor = Position::new(0, 5);
+        buffer.move_cursor(CursorMovement::Down, false, 8);
+        assert_eq!(buffer.cursor, Position::new(1, 2));
+    }
+
+    #[test]
+    fn test_moving_cursor_keeps_display_column() {
+        // Wide characters take up two cells and tabs reach the next stop, so the cursor keeps to cells rather than bytes.
+        let mut buffer = Buffer::new("abcde\n漢字x\n\tx\nab\nabcdefghij");
+        buffer.cursor = Position::new(0, 4);
+        let mut moves = Vec::new();
+        for _ in 0..4 {
+            buffer.move_cursor(CursorMovement::Down, false, 8);
+            moves.push(buffer.cursor);
+        }
+        for _ in 0..4 {
+            buffer.move_cursor(CursorMovement::Up, false, 8);
+            moves.push(buffer.cursor);
+        }
+        assert_eq!(
+            moves,
+            [
+                (1, 6),
+                (2, 0),
+                (3, 2),
+                (4, 4),
+                (3, 2),
+                (2, 0),
+                (1, 6),
+                (0, 4)
+            ]
+            .map(|(line, column)| Position::new(line, column))
+        );
+
+        // Moving sideways starts again from the cell that the cursor ends up in.
+        let mut buffer = Buffer::new("\tx\nabcdefghij\n漢字x");
+        buffer.cursor = Position::new(0, 1);
+        buffer.move_cursor(CursorMovement::Down, false, 8);
+        assert_eq!(buffer.cursor, Position::new(1, 8));
+        for _ in 0..5 {
+            buffer.move_cursor(CursorMovement::Left, false, 8);
+        }
+        buffer.move_cursor(CursorMovement::Down, false, 8);
+        assert_eq!(buffer.cursor, Position::new(2, 3));
+        buffer.move_cursor(CursorMovement::Up, false, 8);
+        assert_eq!(buffer.cursor, Position::new(1, 3));
+    }
+
+    #[test]
+    fn test_goto_clamps_to_document() {
+        let mut buffer = Buffer::new("abc\nabcdef");
+        buffer.goto(usize::MAX, 10);
+        assert_eq!(buffer.cursor, Position::new(1, 5));
+    }
+
+    #[test]
+    fn test_inserting_a_char() {
+        let mut buffer = Buffer::new("");
+        buffer.insert('a');
+        assert_eq!(buffer.contents, "a");
+    }
+
+    #[test]
+    fn test_inserting_multiple_chars() {
+        let mut buffer = Buffer::new("");
+        buffer.insert('a');
+        buffer.insert('b');
+        buffer.insert('c');
+        assert_eq!(buffer.contents, "abc");
+    }
+
+    #[test]
+    fn test_inserting_multiple_lines() {
+        let mut buffer = Buffer::new("");
+        buffer.insert('a');
+        buffer.insert('\n');
+        buffer.insert('b');
+        buffer.insert('\n');
+        buffer.insert('c');
+        assert_eq!(buffer.contents, "a\nb\nc");
+        assert_eq!(buffer.cursor, Position::new(2, 1));
+    }
+
+    #[test]
+    fn test_inserting_multibyte_chars() {
+        let mut buffer = Buffer::new("");
+        buffer.insert('é');
+        buffer.insert('e');
+        assert_eq!(buffer.contents, "ée");
+        assert_eq!(buffer.cursor, Position::new(0, 3));
+    }
+
+    #[test]
+    fn test_moving_over_multibyte_chars() {
+        // The second e has its accent as a separate combining character.
+        let mut buffer = Buffer::new("ée\u{301}x\nab");
+        buffer.move_cursor(CursorMovement::Right, false, 8);
+        assert_eq!(buffer.cursor, Position::new(0, 2));
+        buffer.move_cursor(CursorMovement::Right, false, 8);
+        assert_eq!(buffer.cursor, Position::new(0, 5));
+        buffer.move_cursor(CursorMovement::Right, false, 8);
+        assert_eq!(buffer.cursor, Position::new(0, 5));
+        buffer.move_cursor(CursorMovement::Left, false, 8);
+        assert_eq!(buffer.cursor, Position::new(0, 2));
+
+        // The b is below the second character, rather than the second byte.
+        buffer.goto(1, 1);
+        buffer.move_cursor(CursorMovement::Up, false, 8);
+        assert_eq!(buffer.cursor, Position::new(0, 2));
+        buffer.goto(0, 4);
+        assert_eq!(buffer.cursor, Position::new(0, 2));
+    }
+
+    #[test]
+    fn test_wide_characters() {
+        let buffer = Buffer::new("a漢e\u{301}字\nb");
+        assert_eq!(buffer.display_column(Position::new(0, 1), 8), 1);
+        assert_eq!(buffer.display_column(Position::new(0, 4), 8), 3);
+        assert_eq!(buffer.display_column(Position::new(0, 7), 8), 4);
+        assert_eq!(buffer.display_column(Position::new(0, 10), 8), 6);
+        assert_eq!(buffer.column_at(0, 2, 8), 1);
+        assert_eq!(buffer.column_at(0, 3, 8), 4);
+        assert_eq!(buffer.column_at(0, 5, 8), 7);
+        assert_eq!(buffer.column_at(0, 9, 8), 10);
+        assert_eq!(buffer.column_at(1, 0, 8), 0);
+        // Characters only partly in the cells count too.
+        assert_eq!(buffer.cell_span(0, 2..4, 8), 1..7);
+        assert_eq!(buffer.cell_span(0, 0..1, 8), 0..1);
+        assert_eq!(buffer.cell_span(0, 6..8, 8), 10..10);
+        assert_eq!(buffer.cell_span(1, 0..1, 8), 11..12);
+    }
+
+    #[test]
+    fn test_tabs() {
+        let buffer = Buffer::new("\tab\tc");
+        let widths: Vec<_> = buffer.cells(0..5, 4).map(|(_, width)| width).collect();
+        assert_eq!(widths, [4, 1, 1, 2, 1]);
+        assert_eq!(buffer.display_column(Position::new(0, 1), 4), 4);
+        assert_eq!(buffer.display_column(Position::new(0, 4), 4), 8);
+        assert_eq!(buffer.display_column(Position::new(0, 4), 8), 16);
+        assert_eq!(buffer.column_at(0, 2, 4), 0);
+        assert_eq!(buffer.column_at(0, 7, 4), 3);
+    }
+
+    #[test]
+    fn test_typing_after_multibyte_chars() {
+        let mut buffer = Buffer::new("ée\u{301}");
+        buffer.move_cursor(CursorMovement::Right, true, 8);
+        buffer.move_cursor(CursorMovement::Right, true, 8);
+        buffer.insert('!');
+        assert_eq!(buffer.contents, "ée\u{301}!");
+        buffer.remove();
+        buffer.remove();
+        assert_eq!(buffer.contents, "é");
+        buffer.remove();
+        assert_eq!(buffer.contents, "");
+    }
+
+    #[test]
+    fn test_removing_a_char() {
+        let mut buffer = Buffer::new("abc");
+        buffer.cursor = Position::new(0, 2);
+        buffer.remove();
+        assert_eq!(buffer.contents, "ac");
+        assert_eq!(buffer.cursor, Position::new(0, 1));
+    }
+
+    #[test]
+    fn test_removing_a_newline() {
+        let mut buffer = Buffer::new("ab\ncd");
+        buffer.cursor = Position::new(1, 0);
+        buffer.remove();
+        assert_eq!(buffer.contents, "abcd");
+        assert_eq!(buffer.cursor, Position::new(0, 2));
+    }
+
+    #[test]
+    fn test_removing_from_empty_buffer() {
+        let mut buffer = Buffer::new("");
+        buffer.remove();
+        assert_eq!(buffer.contents, "");
+    }
+
+    #[test]
+    fn test_deleting_a_range() {
+        let mut buffer = Buffer::new("ab\ncd\nef");
+        buffer.cursor = Position::new(2, 1);
+        assert_eq!(buffer.delete(1..5), "b\ncd");
+        assert_eq!(buffer.contents, "a\nef");
+        assert_eq!(buffer.cursor, Position::new(0, 1));
+
+        assert!(buffer.undo());
+        assert_eq!(buffer.contents, "ab\ncd\nef");
+        assert_eq!(buffer.cursor, Position::new(2, 1));
+    }
+
+    #[test]
+    fn test_line_index_follows_edits() {
+        let mut buffer = Buffer::new("ab\ncd");
+        buffer.cursor = Position::new(0, 1);
+        buffer.insert('\n');
+        assert_eq!(line_starts(&buffer), vec![0, 2, 4]);
+        assert_eq!(buffer.line(1).as_deref(), Some("b"));
+
+        buffer.insert('é');
+        assert_eq!(line_starts(&buffer), vec![0, 2, 6]);
+        assert_eq!(buffer.line(2).as_deref(), Some("cd"));
+
+        buffer.cursor = Position::new(1, 0);
+        buffer.remove();
+        assert_eq!(buffer.contents, "aéb\ncd");
+        assert_eq!(line_starts(&buffer), vec![0, 5]);
+        assert_eq!(
+            buffer.num_lines(),
+            buffer.contents.to_string().lines().count()
+        );
+    }
+
+    #[test]
+    fn test_line_strips_line_endings() {
+        let buffer = Buffer::new("ab\r\ncd\n");
+        assert_eq!(buffer.line(0).as_deref(), Some("ab"));
+        assert_eq!(buffer.line(1).as_deref(), Some("cd"));
+        assert_eq!(buffer.num_lines(), 2);
+    }
+
+    #[test]
+    fn test_normalizing_buffer() {
+        let mut buffer = Buffer::new("cafe\u{301}\nabc\nnai\u{308}ve");
+        buffer.cursor = Position::new(0, 4);
+        assert_eq!(buffer.normalize(), 2);
+        assert_eq!(buffer.contents, "caf\u{e9}\nabc\nna\u{ef}ve");
+        assert_eq!(line_starts(&buffer), vec![0, 6, 10]);
+        assert_eq!(buffer.cursor, Position::new(0, 3));
+        assert!(buffer.is_modified());
+
+        assert!(buffer.undo());
+        assert_eq!(buffer.contents, "cafe\u{301}\nabc\nnai\u{308}ve");
+        assert_eq!(buffer.cursor, Position::new(0, 4));
+        assert!(!buffer.is_modified());
+        assert!(buffer.redo());
+        assert_eq!(buffer.contents, "caf\u{e9}\nabc\nna\u{ef}ve");
+
+        // The whole normalization is a single step of the history.
+        assert!(buffer.undo());
+        assert_eq!(buffer.contents, "cafe\u{301}\nabc\nnai\u{308}ve");
+        assert!(!buffer.undo());
+    }
+
+    #[test]
+    fn test_normalizing_normalized_buffer() {
+        let mut buffer = Buffer::new("caf\u{e9}");
+        assert_eq!(buffer.normalize(), 0);
+        assert_eq!(buffer.contents, "caf\u{e9}");
+        assert!(!buffer.is_modified());
+    }
+
+    #[test]
+    fn test_undoing_an_insert_session() {
+        let mut buffer = Buffer::new("abc");
+        buffer.cursor = Position::new(0, 1);
+        buffer.insert('x');
+        buffer.insert('\n');
+        buffer.insert('y');
+        buffer.commit();
+        assert_eq!(buffer.contents, "ax\nybc");
+
+        assert!(buffer.undo());
+        assert_eq!(buffer.contents, "abc");
+        assert_eq!(line_starts(&buffer), vec![0]);
+        assert_eq!(buffer.cursor, Position::new(0, 1));
+
+        assert!(buffer.redo());
+        assert_eq!(buffer.contents, "ax\nybc");
+        assert_eq!(line_starts(&buffer), vec![0, 3]);
+    }
+
+    #[test]
+    fn test_undoing_removals() {
+        let mut buffer = Buffer::new("ab\ncd");
+        buffer.cursor = Position::new(1, 1);
+        buffer.remove();
+        buffer.remove();
+        buffer.commit();
+        assert_eq!(buffer.contents, "abd");
+
+        assert!(buffer.undo());
+        assert_eq!(buffer.contents, "ab\ncd");
+        assert_eq!(line_starts(&buffer), vec![0, 3]);
+        assert_eq!(buffer.cursor, Position::new(1, 1));
+    }
+
+    #[test]
+    fn test_undoing_replacements() {
+        let mut buffer = Buffer::new("one two\nthree");
+        buffer.cursor = Position::new(1, 2);
+        buffer.replace(4..7, "2");
+        buffer.replace(0..3, "1\n");
+        buffer.commit();
+        assert_eq!(buffer.contents, "1\n 2\nthree");
+        assert_eq!(line_starts(&buffer), vec![0, 2, 5]);
+
+        assert!(buffer.undo());
+        assert_eq!(buffer.contents, "one two\nthree");
+        assert_eq!(buffer.cursor, Position::new(1, 2));
+    }
+
+    #[test]
+    fn test_undo_with_empty_history() {
+        let mut buffer = Buffer::new("abc");
+        assert!(!buffer.undo());
+        assert!(!buffer.redo());
+        assert_eq!(buffer.contents, "abc");
+    }
+
+    #[test]
+    fn test_writing_replaces_file() {
+        let dir = TempDir::new("write");
+        let path = dir.write("file.txt", "old contents");
+
+        let mut buffer = Buffer::from_path(&path).unwrap();
+        buffer.insert('x');
+        buffer.write().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "xold contents");
+        assert!(!buffer.is_modified());
+
+        // Nothing is left behind besides the file itself.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_undoing_back_to_written_contents() {
+        let dir = TempDir::new("undo-saved");
+        let path = dir.write("file.txt", "abc");
+
+        let mut buffer = Buffer::from_path(&path).unwrap();
+        buffer.insert('x');
+        assert!(buffer.is_modified());
+        buffer.undo();
+        assert!(!buffer.is_modified());
+        buffer.redo();
+        buffer.write().unwrap();
+
+        buffer.insert('y');
+        assert!(buffer.is_modified());
+        buffer.undo();
+        assert!(!buffer.is_modified());
+        buffer.undo();
+        assert!(buffer.is_modified());
+        buffer.redo();
+        assert!(!buffer.is_modified());
+    }
+
+    #[test]
+    fn test_keeping_line_endings() {
+        let dir = TempDir::new("eol");
+        let path = dir.write("file.txt", "one\r\ntwo\r\nthree\n");
+
+        let mut buffer = Buffer::from_path(&path).unwrap();
+        assert_eq!(buffer.file_format(), FileFormat::Dos);
+        assert_eq!(buffer.contents(), &"one\ntwo\nthree\n");
+        buffer.goto(1, 0);
+        buffer.insert('\n');
+        buffer.write().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "one\r\n\r\ntwo\r\nthree\r\n"
+        );
+
+        buffer.set_file_format(FileFormat::Unix);
+        assert!(buffer.is_modified());
+        buffer.write().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\n\ntwo\nthree\n");
+
+        // A file with only the odd `\r\n` in it is left just as it was.
+        let buffer = Buffer::from_reader("a\r\nb\nc\n".as_bytes()).unwrap();
+        assert_eq!(buffer.file_format(), FileFormat::Unix);
+        assert_eq!(buffer.contents(), &"a\r\nb\nc\n");
+
+        // Switching it over to DOS line endings only adds a `\r` where there isn't one already.
+        let path = dir.write("mixed.txt", "a\r\nb\nc\n");
+        let mut buffer = Buffer::from_path(&path).unwrap();
+        assert_eq!(buffer.contents(), &"a\r\nb\nc\n");
+        buffer.set_file_format(FileFormat::Dos);
+        buffer.write().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\r\nb\r\nc\r\n");
+    }
+
+    #[test]
+    fn test_reading_in_blocks() {
+        /// Hands out its text a few bytes at a time, to split it up in awkward places.
+        struct Trickle<'a>(&'a [u8]);
+
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let len = cmp::min(3, cmp::min(buf.len(), self.0.len()));
+                buf[..len].copy_from_slice(&self.0[..len]);
+                self.0 = &self.0[len..];
+                Ok(len)
+            }
+        }
+
+        let buffer = Buffer::from_reader(Trickle("héllo\r\nwörld\r\n".as_bytes())).unwrap();
+        assert_eq!(buffer.contents(), &"héllo\nwörld\n");
+        assert_eq!(buffer.file_format(), FileFormat::Dos);
+        assert!(!buffer.is_large());
+
+        assert!(Buffer::from_reader(Trickle(b"ab\xffcd")).is_err());
+        assert!(Buffer::from_reader(Trickle(b"ab\xc3")).is_err());
+    }
+
+    #[test]
+    fn test_spotting_changes_on_disk() {
+        let dir = TempDir::new("changed");
+        let path = dir.write("file.txt", "old");
+
+        let mut buffer = Buffer::from_path(&path).unwrap();
+        assert!(!buffer.changed_on_disk());
+
+        let file = File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        assert!(buffer.changed_on_disk());
+
+        // Writing the file catches the buffer up with it again.
+        buffer.write().unwrap();
+        assert!(!buffer.changed_on_disk());
+
+        fs::remove_file(&path).unwrap();
+        assert!(!buffer.changed_on_disk());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_writing_keeps_permissions_and_symlinks() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = TempDir::new("write-link");
+        let target = dir.join("script.sh");
+        let link = dir.join("link.sh");
+        fs::write(&target, "echo hi\n").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o750)).unwrap();
+        symlink(&target, &link).unwrap();
+
+        let mut buffer = Buffer::from_path(&link).unwrap();
+        buffer.insert('#');
+        buffer.write().unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "#echo hi\n");
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+    }
+
+    #[test]
+    fn test_worst_diagnostic_on_line() {
+        use crate::lsp::Severity;
+
+        let diagnostic = |line, severity, message: &str| Diagnostic {
+            line,
+            character: 0,
+            severity,
+            message: message.to_string(),
+        };
+        let mut buffer = Buffer::new("one\ntwo\n");
+        buffer.set_diagnostics(vec![
+            diagnostic(1, Severity::Warning, "unused"),
+            diagnostic(1, Severity::Error, "broken"),
+            diagnostic(0, Severity::Hint, "tidy"),
+        ]);
+        assert_eq!(buffer.diagnostic_on(1).unwrap().message, "broken");
+        assert_eq!(buffer.diagnostic_on(0).unwrap().message, "tidy");
+        assert!(buffer.diagnostic_on(2).is_none());
+    }
+}
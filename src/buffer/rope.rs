@@ -0,0 +1,641 @@
+use std::fmt::{self, Display};
+use std::ops::Range;
+
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete};
+
+/// The most text that a single leaf of the rope will hold.
+/// Small enough that editing inside a leaf is cheap, big enough that the tree stays shallow.
+const MAX_LEAF: usize = 1024;
+
+#[derive(Debug, Clone)]
+enum Node {
+    Leaf {
+        text: String,
+        newlines: usize,
+    },
+    Branch {
+        left: Box<Node>,
+        right: Box<Node>,
+        len: usize,
+        newlines: usize,
+        height: usize,
+    },
+}
+
+impl Node {
+    fn leaf(text: String) -> Self {
+        let newlines = text.matches('\n').count();
+        Node::Leaf { text, newlines }
+    }
+
+    fn branch(left: Node, right: Node) -> Self {
+        Node::Branch {
+            len: left.len() + right.len(),
+            newlines: left.newlines() + right.newlines(),
+            height: 1 + left.height().max(right.height()),
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    /// Builds a balanced tree from a string, splitting it up into leaves at character boundaries.
+    fn from_str(s: &str) -> Self {
+        if s.len() <= MAX_LEAF {
+            return Node::leaf(s.to_string());
+        }
+
+        let mut middle = s.len() / 2;
+        while !s.is_char_boundary(middle) {
+            middle += 1;
+        }
+        Node::branch(Node::from_str(&s[..middle]), Node::from_str(&s[middle..]))
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Node::Leaf { text, .. } => text.len(),
+            Node::Branch { len, .. } => *len,
+        }
+    }
+
+    fn newlines(&self) -> usize {
+        match self {
+            Node::Leaf { newlines, .. } | Node::Branch { newlines, .. } => *newlines,
+        }
+    }
+
+    fn height(&self) -> usize {
+        match self {
+            Node::Leaf { .. } => 0,
+            Node::Branch { height, .. } => *height,
+        }
+    }
+
+    /// Joins two trees end to end, keeping the result balanced.
+    fn join(left: Node, right: Node) -> Node {
+        if left.len() == 0 {
+            return right;
+        }
+        if right.len() == 0 {
+            return left;
+        }
+
+        match (left, right) {
+            // Gluing small leaves together stops the tree filling up with tiny fragments as it gets edited.
+            (Node::Leaf { text: mut l, .. }, Node::Leaf { text: r, .. })
+                if l.len() + r.len() <= MAX_LEAF =>
+            {
+                l.push_str(&r);
+                Node::leaf(l)
+            }
+            (
+                Node::Branch {
+                    left: ll,
+                    right: lr,
+                    height,
+                    ..
+                },
+                right,
+            ) if height > right.height() + 1 => Node::balance(*ll, Node::join(*lr, right)),
+            (
+                left,
+                Node::Branch {
+                    left: rl,
+                    right: rr,
+                    height,
+                    ..
+                },
+            ) if height > left.height() + 1 => Node::balance(Node::join(left, *rl), *rr),
+            (left, right) => Node::branch(left, right),
+        }
+    }
+
+    /// Creates a branch from two trees whose heights differ by at most two, rotating to bring them back into balance.
+    fn balance(left: Node, right: Node) -> Node {
+        if left.height() > right.height() + 1 {
+            if let Node::Branch {
+                left: ll,
+                right: lr,
+                ..
+            } = left
+            {
+                return match *lr {
+                    Node::Branch {
+                        left: lrl,
+                        right: lrr,
+                        ..
+                    } if lrl.height().max(lrr.height()) >= ll.height() => {
+                        Node::branch(Node::branch(*ll, *lrl), Node::branch(*lrr, right))
+                    }
+                    lr => Node::branch(*ll, Node::branch(lr, right)),
+                };
+            }
+        }
+
+        if right.height() > left.height() + 1 {
+            if let Node::Branch {
+                left: rl,
+                right: rr,
+                ..
+            } = right
+            {
+                return match *rl {
+                    Node::Branch {
+                        left: rll,
+                        right: rlr,
+                        ..
+                    } if rll.height().max(rlr.height()) >= rr.height() => {
+                        Node::branch(Node::branch(left, *rll), Node::branch(*rlr, *rr))
+                    }
+                    rl => Node::branch(Node::branch(left, rl), *rr),
+                };
+            }
+        }
+
+        Node::branch(left, right)
+    }
+
+    /// Splits a tree in two at a byte index, which needs to be on a character boundary.
+    fn split(self, idx: usize) -> (Node, Node) {
+        match self {
+            Node::Leaf { mut text, .. } => {
+                let right = text.split_off(idx);
+                (Node::leaf(text), Node::leaf(right))
+            }
+            Node::Branch { left, right, .. } => {
+                if idx <= left.len() {
+                    let (a, b) = left.split(idx);
+                    (a, Node::join(b, *right))
+                } else {
+                    let (a, b) = right.split(idx - left.len());
+                    (Node::join(*left, a), b)
+                }
+            }
+        }
+    }
+
+    /// Finds the byte index of the `n`th newline (counting from zero.)
+    fn newline_index(&self, n: usize) -> usize {
+        match self {
+            Node::Leaf { text, .. } => text.match_indices('\n').nth(n).unwrap().0,
+            Node::Branch { left, right, .. } => {
+                if n < left.newlines() {
+                    left.newline_index(n)
+                } else {
+                    left.len() + right.newline_index(n - left.newlines())
+                }
+            }
+        }
+    }
+
+    /// Counts the newlines that come before a byte index.
+    fn newlines_before(&self, idx: usize) -> usize {
+        match self {
+            Node::Leaf { text, .. } => text[..idx].matches('\n').count(),
+            Node::Branch { left, right, .. } => {
+                if idx <= left.len() {
+                    left.newlines_before(idx)
+                } else {
+                    left.newlines() + right.newlines_before(idx - left.len())
+                }
+            }
+        }
+    }
+
+    /// Finds the leaf containing a byte index, along with the index within that leaf.
+    fn leaf_at(&self, idx: usize) -> (&str, usize) {
+        match self {
+            Node::Leaf { text, .. } => (text, idx),
+            Node::Branch { left, right, .. } => {
+                if idx < left.len() {
+                    left.leaf_at(idx)
+                } else {
+                    right.leaf_at(idx - left.len())
+                }
+            }
+        }
+    }
+
+    /// Copies the text in `range` onto the end of `out`.
+    fn append_slice(&self, range: Range<usize>, out: &mut String) {
+        match self {
+            Node::Leaf { text, .. } => out.push_str(&text[range]),
+            Node::Branch { left, right, .. } => {
+                let left_len = left.len();
+                if range.start < left_len {
+                    left.append_slice(range.start..range.end.min(left_len), out);
+                }
+                if range.end > left_len {
+                    right.append_slice(
+                        range.start.saturating_sub(left_len)..range.end - left_len,
+                        out,
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Node::leaf(String::new())
+    }
+}
+
+/// A string stored as a balanced tree of smaller strings.
+///
+/// Inserting, removing and finding lines all take logarithmic time, rather than having to shuffle or scan the whole of the text.
+/// Indices are byte offsets into the text, and just like with `String` they need to land on character boundaries.
+#[derive(Debug, Clone)]
+pub struct Rope {
+    root: Node,
+}
+
+impl Default for Rope {
+    fn default() -> Self {
+        Self::from("")
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(s: &str) -> Self {
+        Self {
+            root: Node::from_str(s),
+        }
+    }
+}
+
+impl Rope {
+    /// The length of the text in bytes.
+    pub fn len(&self) -> usize {
+        self.root.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of lines in the text, where the empty text still has a line and a trailing newline starts a new one.
+    pub fn line_count(&self) -> usize {
+        self.root.newlines() + 1
+    }
+
+    /// Inserts `text` at a byte index.
+    pub fn insert(&mut self, idx: usize, text: &str) {
+        let root = std::mem::take(&mut self.root);
+        let (left, right) = root.split(idx);
+        self.root = Node::join(Node::join(left, Node::from_str(text)), right);
+    }
+
+    /// Removes a range of bytes from the text, handing back what was removed.
+    pub fn remove(&mut self, range: Range<usize>) -> String {
+        let root = std::mem::take(&mut self.root);
+        let (left, rest) = root.split(range.start);
+        let (removed, right) = rest.split(range.end - range.start);
+        self.root = Node::join(left, right);
+
+        let mut out = String::with_capacity(removed.len());
+        removed.append_slice(0..removed.len(), &mut out);
+        out
+    }
+
+    /// Copies a range of bytes out of the text.
+    pub fn slice(&self, range: Range<usize>) -> String {
+        let mut out = String::with_capacity(range.len());
+        if !range.is_empty() {
+            self.root.append_slice(range, &mut out);
+        }
+        out
+    }
+
+    /// Finds the byte index at which a line starts, if the text has that many lines.
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        match line {
+            0 => Some(0),
+            _ if line <= self.root.newlines() => Some(self.root.newline_index(line - 1) + 1),
+            _ => None,
+        }
+    }
+
+    /// Finds the byte index at which a line ends, which is where its newline is (or the end of the text.)
+    pub fn line_end(&self, line: usize) -> Option<usize> {
+        match line {
+            _ if line < self.root.newlines() => Some(self.root.newline_index(line)),
+            _ if line == self.root.newlines() => Some(self.len()),
+            _ => None,
+        }
+    }
+
+    /// Determines which line a byte index falls on.
+    pub fn line_of(&self, idx: usize) -> usize {
+        self.root.newlines_before(idx.min(self.len()))
+    }
+
+    /// Finds the character that starts at a byte index.
+    pub fn char_at(&self, idx: usize) -> Option<char> {
+        if idx >= self.len() {
+            return None;
+        }
+        let (leaf, i) = self.root.leaf_at(idx);
+        leaf.get(i..).and_then(|s| s.chars().next())
+    }
+
+    /// Finds the character that ends at a byte index.
+    pub fn char_before(&self, idx: usize) -> Option<char> {
+        if idx == 0 || idx > self.len() {
+            return None;
+        }
+        // Leaves are always split on character boundaries, so the whole character is in the leaf holding its last byte.
+        let (leaf, i) = self.root.leaf_at(idx - 1);
+        leaf.get(..i + 1).and_then(|s| s.chars().next_back())
+    }
+
+    /// Checks whether a byte index falls on a character boundary, as `str::is_char_boundary` does.
+    pub fn is_char_boundary(&self, idx: usize) -> bool {
+        if idx >= self.len() {
+            return idx == self.len();
+        }
+        let (leaf, i) = self.root.leaf_at(idx);
+        leaf.is_char_boundary(i)
+    }
+
+    /// Finds the leaf holding a byte index, along with the index that the leaf starts at.
+    fn chunk_at(&self, idx: usize) -> (&str, usize) {
+        let (leaf, i) = self.root.leaf_at(idx);
+        (leaf, idx - i)
+    }
+
+    /// Finds the text of the leaf that comes before a byte index, along with the index that the leaf starts at.
+    fn chunk_ending_at(&self, idx: usize) -> (&str, usize) {
+        let (leaf, i) = self.root.leaf_at(idx - 1);
+        (&leaf[..i + 1], idx - 1 - i)
+    }
+
+    /// Runs `step` with a cursor over the grapheme clusters at a byte index, handing it leaves of the rope until it has seen as
+    /// much of the text around the index as it needs.
+    fn with_graphemes<T>(
+        &self,
+        idx: usize,
+        mut step: impl FnMut(&mut GraphemeCursor, &str, usize) -> Result<T, GraphemeIncomplete>,
+    ) -> T {
+        let mut cursor = GraphemeCursor::new(idx, self.len(), true);
+        let (mut chunk, mut start) = self.chunk_at(idx);
+        loop {
+            match step(&mut cursor, chunk, start) {
+                Ok(result) => return result,
+                Err(GraphemeIncomplete::PreContext(end)) => {
+                    let (context, context_start) = self.chunk_ending_at(end);
+                    cursor.provide_context(context, context_start);
+                }
+                Err(GraphemeIncomplete::NextChunk) => {
+                    (chunk, start) = self.chunk_at(start + chunk.len());
+                }
+                Err(GraphemeIncomplete::PrevChunk) => {
+                    (chunk, start) = self.chunk_ending_at(start);
+                }
+                Err(GraphemeIncomplete::InvalidOffset) => {
+                    unreachable!("grapheme cursor given a leaf that doesn't hold its index")
+                }
+            }
+        }
+    }
+
+    /// Checks whether a byte index falls between two grapheme clusters, which are what the user sees as single characters.
+    pub fn is_grapheme_boundary(&self, idx: usize) -> bool {
+        if !self.is_char_boundary(idx) {
+            return false;
+        }
+        self.with_graphemes(idx, |cursor, chunk, start| cursor.is_boundary(chunk, start))
+    }
+
+    /// Finds the start of the grapheme cluster that a byte index falls inside of.
+    pub fn grapheme_start(&self, idx: usize) -> usize {
+        let mut i = idx.min(self.len());
+        while !self.is_char_boundary(i) {
+            i -= 1;
+        }
+        match self.is_grapheme_boundary(i) {
+            true => i,
+            false => self.previous_grapheme(i),
+        }
+    }
+
+    /// Finds the end of the grapheme cluster that starts at a byte index.
+    pub fn next_grapheme(&self, idx: usize) -> usize {
+        if idx >= self.len() {
+            return idx;
+        }
+        self.with_graphemes(idx, |cursor, chunk, start| {
+            cursor.next_boundary(chunk, start)
+        })
+        .unwrap_or(self.len())
+    }
+
+    /// Finds the start of the grapheme cluster that ends at a byte index.
+    pub fn previous_grapheme(&self, idx: usize) -> usize {
+        if idx == 0 || idx > self.len() {
+            return idx.min(self.len());
+        }
+        self.with_graphemes(idx, |cursor, chunk, start| {
+            cursor.prev_boundary(chunk, start)
+        })
+        .unwrap_or(0)
+    }
+
+    /// Iterates over the pieces of text that make up the rope, in order.
+    pub fn chunks(&self) -> Chunks<'_> {
+        Chunks {
+            stack: vec![&self.root],
+        }
+    }
+}
+
+impl Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chunks().try_for_each(|chunk| f.write_str(chunk))
+    }
+}
+
+impl PartialEq<&str> for Rope {
+    fn eq(&self, other: &&str) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+        let mut rest = other.as_bytes();
+        self.chunks().all(|chunk| {
+            let (head, tail) = rest.split_at(chunk.len());
+            rest = tail;
+            head == chunk.as_bytes()
+        })
+    }
+}
+
+/// An iterator over the strings stored in the leaves of a `Rope`.
+pub struct Chunks<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            match node {
+                Node::Leaf { text, .. } if text.is_empty() => (),
+                Node::Leaf { text, .. } => return Some(text),
+                Node::Branch { left, right, .. } => {
+                    self.stack.push(right);
+                    self.stack.push(left);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Checks that every branch in the tree is balanced, and that the cached lengths are right.
+    fn assert_balanced(node: &Node) {
+        if let Node::Branch {
+            left,
+            right,
+            len,
+            newlines,
+            height,
+        } = node
+        {
+            assert!(left.height().abs_diff(right.height()) <= 1);
+            assert_eq!(*height, 1 + left.height().max(right.height()));
+            assert_eq!(*len, left.len() + right.len());
+            assert_eq!(*newlines, left.newlines() + right.newlines());
+            assert_balanced(left);
+            assert_balanced(right);
+        }
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut rope = Rope::from("hello world");
+        rope.insert(5, ",");
+        assert_eq!(rope, "hello, world");
+        assert_eq!(rope.remove(0..7), "hello, ");
+        assert_eq!(rope, "world");
+    }
+
+    #[test]
+    fn test_lines() {
+        let rope = Rope::from("ab\ncd\n\nef");
+        assert_eq!(rope.line_count(), 4);
+        assert_eq!(rope.line_start(0), Some(0));
+        assert_eq!(rope.line_start(1), Some(3));
+        assert_eq!(rope.line_start(2), Some(6));
+        assert_eq!(rope.line_start(3), Some(7));
+        assert_eq!(rope.line_start(4), None);
+        assert_eq!(rope.line_end(0), Some(2));
+        assert_eq!(rope.line_end(2), Some(6));
+        assert_eq!(rope.line_end(3), Some(9));
+        assert_eq!(rope.line_end(4), None);
+        assert_eq!(rope.line_of(0), 0);
+        assert_eq!(rope.line_of(2), 0);
+        assert_eq!(rope.line_of(3), 1);
+        assert_eq!(rope.line_of(9), 3);
+    }
+
+    #[test]
+    fn test_chars() {
+        let rope = Rope::from("aé漢");
+        assert_eq!(rope.char_at(0), Some('a'));
+        assert_eq!(rope.char_at(1), Some('é'));
+        assert_eq!(rope.char_at(3), Some('漢'));
+        assert_eq!(rope.char_at(6), None);
+        assert_eq!(rope.char_before(3), Some('é'));
+        assert_eq!(rope.char_before(6), Some('漢'));
+        assert_eq!(rope.char_before(0), None);
+        assert!(rope.is_char_boundary(3));
+        assert!(!rope.is_char_boundary(4));
+        assert!(rope.is_char_boundary(6));
+    }
+
+    #[test]
+    fn test_graphemes() {
+        // An e with a separate accent, a thumbs up with a skin tone, and two flags.
+        let rope = Rope::from("e\u{301}\u{1f44d}\u{1f3fd}\u{1f1ec}\u{1f1e7}\u{1f1eb}\u{1f1f7}");
+        assert_eq!(rope.next_grapheme(0), 3);
+        assert_eq!(rope.next_grapheme(3), 11);
+        assert_eq!(rope.next_grapheme(11), 19);
+        assert_eq!(rope.next_grapheme(19), 27);
+        assert_eq!(rope.next_grapheme(27), 27);
+        assert_eq!(rope.previous_grapheme(27), 19);
+        assert_eq!(rope.previous_grapheme(11), 3);
+        assert_eq!(rope.previous_grapheme(3), 0);
+        assert_eq!(rope.grapheme_start(2), 0);
+        assert_eq!(rope.grapheme_start(23), 19);
+        assert!(!rope.is_grapheme_boundary(15));
+
+        // Enough accented letters that some of them get split from their accents between leaves.
+        let rope = Rope::from("e\u{301}".repeat(1000).as_str());
+        let mut i = 0;
+        for _ in 0..1000 {
+            assert_eq!(rope.grapheme_start(i + 1), i);
+            assert_eq!(rope.next_grapheme(i), i + 3);
+            i += 3;
+            assert_eq!(rope.previous_grapheme(i), i - 3);
+        }
+    }
+
+    #[test]
+    fn test_large_text_stays_balanced() {
+        let line = "the quick brown fox jumps over the lazy dog\n";
+        let text = line.repeat(2000);
+        let mut rope = Rope::from(text.as_str());
+        let mut expected = text.clone();
+        assert_balanced(&rope.root);
+
+        // Hammer away at the same spot, which would leave an unbalanced tree lopsided.
+        for i in 0..500 {
+            rope.insert(1000 + i, "x");
+            expected.insert(1000 + i, 'x');
+        }
+        assert_balanced(&rope.root);
+        assert_eq!(rope.to_string(), expected);
+
+        for _ in 0..300 {
+            rope.remove(5000..5050);
+            expected.replace_range(5000..5050, "");
+        }
+        assert_balanced(&rope.root);
+        assert_eq!(rope.to_string(), expected);
+        assert_eq!(rope.line_count(), expected.matches('\n').count() + 1);
+
+        let line_10 = rope.line_start(10).unwrap();
+        assert_eq!(
+            line_10,
+            expected
+                .match_indices('\n')
+                .nth(9)
+                .map(|(i, _)| i + 1)
+                .unwrap()
+        );
+        assert_eq!(rope.line_of(line_10), 10);
+        assert_eq!(
+            rope.slice(line_10..rope.line_end(10).unwrap()),
+            expected.lines().nth(10).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_empty_rope() {
+        let mut rope = Rope::default();
+        assert!(rope.is_empty());
+        assert_eq!(rope.line_count(), 1);
+        assert_eq!(rope.line_end(0), Some(0));
+        rope.insert(0, "a");
+        assert_eq!(rope, "a");
+        rope.remove(0..1);
+        assert_eq!(rope, "");
+        assert_eq!(rope.chunks().count(), 0);
+    }
+}
@@ -0,0 +1,178 @@
+//! Keeping hold of all of the buffers that are open, while only one of them is being edited.
+use std::mem;
+use std::path::Path;
+
+use crate::buffer::Buffer;
+
+/// The list of buffers that are open in the editor, in the order they were opened.
+///
+/// The buffer being edited is held on to by the editor itself, so it's passed in as `active` whenever it's needed.
+/// Switching buffers swaps a different one into `active`, keeping the one that was there safe in the list.
+#[derive(Default)]
+pub struct BufferManager {
+    /// Every buffer in the list apart from the active one.
+    hidden: Vec<Buffer>,
+    /// Where the active buffer sits in the list.
+    current: usize,
+}
+
+impl BufferManager {
+    /// The number of buffers that are open, including the active one.
+    pub fn len(&self) -> usize {
+        self.hidden.len() + 1
+    }
+
+    /// Where the active buffer sits in the list, counting from zero.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Goes through every buffer in the list, in order.
+    pub fn iter<'a>(&'a self, active: &'a Buffer) -> impl Iterator<Item = &'a Buffer> {
+        let (before, after) = self.hidden.split_at(self.current);
+        before.iter().chain([active]).chain(after)
+    }
+
+    /// Goes through every buffer in the list in order, so that they can all be changed.
+    pub fn iter_mut<'a>(
+        &'a mut self,
+        active: &'a mut Buffer,
+    ) -> impl Iterator<Item = &'a mut Buffer> {
+        let (before, after) = self.hidden.split_at_mut(self.current);
+        before.iter_mut().chain([active]).chain(after)
+    }
+
+    /// Looks up the buffer at `index` in the list, so that it can be changed.
+    pub fn get_mut<'a>(
+        &'a mut self,
+        active: &'a mut Buffer,
+        index: usize,
+    ) -> Option<&'a mut Buffer> {
+        self.iter_mut(active).nth(index)
+    }
+
+    /// Looks up the buffer at `index` in the list.
+    pub fn get<'a>(&'a self, active: &'a Buffer, index: usize) -> Option<&'a Buffer> {
+        self.iter(active).nth(index)
+    }
+
+    /// Finds the buffer that belongs to the file at `path`, if it's already open.
+    pub fn find(&self, active: &Buffer, path: &Path) -> Option<usize> {
+        self.iter(active)
+            .position(|buffer| buffer.path() == Some(path))
+    }
+
+    /// Adds a buffer onto the end of the list, which takes over as the active one.
+    pub fn add(&mut self, active: &mut Buffer, buffer: Buffer) {
+        let previous = mem::replace(active, buffer);
+        self.hidden.insert(self.current, previous);
+        self.current = self.hidden.len();
+    }
+
+    /// Makes the buffer at `index` in the list the active one, returning whether there is one there.
+    pub fn switch(&mut self, active: &mut Buffer, index: usize) -> bool {
+        if index >= self.len() {
+            return false;
+        }
+        if index == self.current {
+            return true;
+        }
+
+        // Both buffers are taken out of the list before either is put back in, so the indices need adjusting around each other.
+        let next = match index < self.current {
+            true => self.hidden.remove(index),
+            false => self.hidden.remove(index - 1),
+        };
+        let previous = mem::replace(active, next);
+        match self.current < index {
+            true => self.hidden.insert(self.current, previous),
+            false => self.hidden.insert(self.current - 1, previous),
+        }
+        self.current = index;
+        true
+    }
+
+    /// Finds the next buffer in the list, going back around to the first after the last.
+    pub fn next(&self) -> usize {
+        (self.current + 1) % self.len()
+    }
+
+    /// Finds the previous buffer in the list, going back around to the last before the first.
+    pub fn previous(&self) -> usize {
+        (self.current + self.len() - 1) % self.len()
+    }
+
+    /// Throws away the active buffer, replacing it with the one after it (or before it, if it was the last.)
+    ///
+    /// There always needs to be a buffer to edit, so closing the only one leaves an empty one in its place.
+    pub fn close(&mut self, active: &mut Buffer) {
+        *active = match (self.hidden.is_empty(), self.current < self.hidden.len()) {
+            (true, _) => Buffer::new(""),
+            (false, true) => self.hidden.remove(self.current),
+            (false, false) => {
+                self.current -= 1;
+                self.hidden.remove(self.current)
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn contents(buffers: &BufferManager, active: &Buffer) -> Vec<String> {
+        buffers
+            .iter(active)
+            .map(|buffer| buffer.contents().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_adding_and_switching() {
+        let mut buffers = BufferManager::default();
+        let mut active = Buffer::new("a");
+        buffers.add(&mut active, Buffer::new("b"));
+        buffers.add(&mut active, Buffer::new("c"));
+        assert_eq!(contents(&buffers, &active), ["a", "b", "c"]);
+        assert_eq!(
+            (buffers.current(), active.contents().to_string()),
+            (2, "c".to_string())
+        );
+
+        assert!(buffers.switch(&mut active, 0));
+        assert_eq!(active.contents(), &"a");
+        assert_eq!(contents(&buffers, &active), ["a", "b", "c"]);
+        assert!(!buffers.switch(&mut active, 3));
+
+        buffers.switch(&mut active, buffers.previous());
+        assert_eq!(
+            (buffers.current(), active.contents().to_string()),
+            (2, "c".to_string())
+        );
+        buffers.switch(&mut active, buffers.next());
+        assert_eq!(active.contents(), &"a");
+        buffers.switch(&mut active, buffers.next());
+        assert_eq!(active.contents(), &"b");
+        assert_eq!(contents(&buffers, &active), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_closing() {
+        let mut buffers = BufferManager::default();
+        let mut active = Buffer::new("a");
+        buffers.add(&mut active, Buffer::new("b"));
+        buffers.add(&mut active, Buffer::new("c"));
+        buffers.switch(&mut active, 1);
+
+        buffers.close(&mut active);
+        assert_eq!(active.contents(), &"c");
+        buffers.close(&mut active);
+        assert_eq!(
+            (buffers.current(), active.contents().to_string()),
+            (0, "a".to_string())
+        );
+        buffers.close(&mut active);
+        assert_eq!(contents(&buffers, &active), [""]);
+    }
+}
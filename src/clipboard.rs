@@ -0,0 +1,95 @@
+//! Copying text into the system clipboard, by way of the terminal.
+//!
+//! The OSC 52 escape sequence asks the terminal to put some text into the clipboard for us, which works over SSH too.
+//! Terminal multiplexers like tmux might need to be told to pass the sequence through (`set -g set-clipboard on`.)
+//!
+//! Most terminals won't let the clipboard be read back the same way, so pasting asks the desktop's clipboard tools for it
+//! instead (`wl-paste`, `xclip`, `xsel` or `pbpaste`.) Without any of them, like over SSH, pasting from the `+` register
+//! gives back whatever was last copied from Té.
+use std::process::{Command, Stdio};
+
+/// The commands that print out what's in the system clipboard, which are tried in turn until one of them works.
+const PASTE_COMMANDS: &[(&str, &[&str])] = &[
+    ("wl-paste", &["--no-newline"]),
+    ("xclip", &["-selection", "clipboard", "-o"]),
+    ("xsel", &["--clipboard", "--output"]),
+    ("pbpaste", &[]),
+];
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes some bytes as Base64, with padding.
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(char::from(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize])),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+/// Builds the escape sequence that asks the terminal to copy `text` into the clipboard.
+pub fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64(text.as_bytes()))
+}
+
+/// Reads what's in the system clipboard, if any of the clipboard tools are there and can get at it.
+pub fn paste() -> Option<String> {
+    paste_with(PASTE_COMMANDS)
+}
+
+/// Runs each of `commands` until one of them prints out some text and succeeds.
+fn paste_with(commands: &[(&str, &[&str])]) -> Option<String> {
+    commands.iter().find_map(|(program, args)| {
+        let output = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        match output.status.success() {
+            true => String::from_utf8(output.stdout).ok(),
+            false => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64("té\n".as_bytes()), "dMOpCg==");
+    }
+
+    #[test]
+    fn test_pasting() {
+        let commands: &[(&str, &[&str])] = &[
+            ("te-no-such-clipboard", &[]),
+            ("sh", &["-c", "exit 1"]),
+            ("sh", &["-c", "printf 'from the\\nclipboard'"]),
+            ("sh", &["-c", "printf never"]),
+        ];
+        assert_eq!(paste_with(commands).as_deref(), Some("from the\nclipboard"));
+        assert_eq!(paste_with(&commands[..2]), None);
+    }
+
+    #[test]
+    fn test_osc52() {
+        assert_eq!(osc52("hi"), "\x1b]52;c;aGk=\x07");
+    }
+}
@@ -0,0 +1,1007 @@
+//! The commands that can be typed in at the `:` prompt.
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+
+use crate::keymap;
+use crate::windows::Split;
+
+/// A command that has been parsed from the prompt, ready to be carried out by the `Editor`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Write the buffer into its file, or into a new one that the buffer then belongs to.
+    ///
+    /// This needs to be forced if the file has been changed by something else since it was read.
+    Write { path: Option<PathBuf>, force: bool },
+    /// Write the buffer like `Write`, then quit.
+    WriteQuit { path: Option<PathBuf>, force: bool },
+    /// Quit the editor, which needs to be forced if there are unsaved changes.
+    Quit { force: bool },
+    /// Switch to the buffer for a file, opening it in a new one if it isn't open already.
+    ///
+    /// Forcing it reads a buffer that's already open back in from the file, throwing away any unsaved changes.
+    Edit { path: PathBuf, force: bool },
+    /// Switch to the buffer for a file like `Edit`, but making it read-only.
+    View(PathBuf),
+    /// Edit the next file in the argument list.
+    NextFile,
+    /// Edit the previous file in the argument list.
+    PreviousFile,
+    /// Split the current window in two, editing another file in the new one if a path is given.
+    Split(Split, Option<PathBuf>),
+    /// Close the current window.
+    CloseWindow,
+    /// Split the current window side by side with another file, comparing the two.
+    DiffSplit(PathBuf),
+    /// Stop comparing files.
+    DiffOff,
+    /// Save the open files and windows in a session file, which needs to be forced to write over one that's there.
+    MakeSession { path: Option<PathBuf>, force: bool },
+    /// Run the buffer through the formatter for its type of file.
+    Format,
+    /// Switch to the next buffer in the list.
+    NextBuffer,
+    /// Switch to the previous buffer in the list.
+    PreviousBuffer,
+    /// Switch to a buffer by its number in the list, counted from one.
+    Buffer(usize),
+    /// Close the current buffer, which needs to be forced if there are unsaved changes.
+    CloseBuffer { force: bool },
+    /// Show the list of open buffers.
+    ListBuffers,
+    /// Show the files that have been edited recently, in this run or an earlier one.
+    OldFiles,
+    /// Stop highlighting the matches of the last search, until the next one.
+    NoHighlight,
+    /// Run the build command with some extra arguments, and load the errors in its output into the quickfix list.
+    Make(Option<String>),
+    /// Load the errors in a file of compiler output into the quickfix list.
+    ErrorFile(PathBuf),
+    /// Jump to the next error in the quickfix list.
+    NextError,
+    /// Jump to the previous error in the quickfix list.
+    PreviousError,
+    /// Run a command in the shell, showing its output until the user presses Enter (like `:!ls`.)
+    Shell(String),
+    /// Pipe some lines through a shell command, replacing them with its output (like `:%!sort`.)
+    Filter { range: LineRange, command: String },
+    /// Insert the output of a shell command below the cursor's line (like `:r !date`.)
+    InsertOutput(String),
+    /// Change an option to `value`, or show what it's set to without one (like `:set fileformat=dos`.)
+    Set {
+        option: String,
+        value: Option<String>,
+    },
+    /// Replace the text matching a regular expression on some lines of the buffer.
+    Substitute {
+        range: LineRange,
+        pattern: String,
+        replacement: String,
+        /// Whether every match on a line is replaced, rather than just the first (the `g` flag.)
+        global: bool,
+        /// Whether the user is asked about each match before it's replaced (the `c` flag.)
+        confirm: bool,
+    },
+    /// Sort some lines (like `:%sort`), numbers by their value with the `n` flag and dropping repeated lines with `u`.
+    ///
+    /// The `!` sorts them backwards.
+    Sort {
+        range: LineRange,
+        reverse: bool,
+        numeric: bool,
+        unique: bool,
+    },
+    /// Turn some lines upside down, so that the last one comes first.
+    Reverse(LineRange),
+    /// Run some commands in every buffer in the list, one buffer after another (like `:bufdo %s/a/b/ | w`.)
+    BufDo(Vec<String>),
+    /// Run some commands on every file in the argument list, one file after another.
+    ArgDo(Vec<String>),
+    /// Change the editor's working directory, which relative paths are found from (to the home directory without a path.)
+    ChangeDirectory(Option<PathBuf>),
+    /// Show the editor's working directory.
+    PrintDirectory,
+    /// Map some keys in Navigate mode onto the macro in a register, saving the mapping in the configuration file so that
+    /// it's still there next time (like `:savemacro q <M-q>`.)
+    SaveMacro { register: char, keys: String },
+}
+
+/// The full names of the commands, for completing them at the prompt.
+pub const NAMES: &[&str] = &[
+    "argdo",
+    "bNext",
+    "cNext",
+    "cd",
+    "cfile",
+    "chdir",
+    "cnext",
+    "cprevious",
+    "bdelete",
+    "bnext",
+    "bprevious",
+    "bufdo",
+    "buffer",
+    "buffers",
+    "close",
+    "diffoff",
+    "diffsplit",
+    "edit",
+    "exit",
+    "format",
+    "make",
+    "mksession",
+    "next",
+    "nohlsearch",
+    "oldfiles",
+    "previous",
+    "pwd",
+    "quit",
+    "reverse",
+    "savemacro",
+    "set",
+    "sort",
+    "split",
+    "view",
+    "vsplit",
+    "wq",
+    "write",
+];
+
+/// The names of the options that `:set` knows about, for completing them at the prompt.
+pub const OPTIONS: &[&str] = &[
+    "colorcolumn",
+    "cursorline",
+    "fileformat",
+    "fillchars",
+    "list",
+    "listchars",
+    "makeprg",
+    "nocursorline",
+    "nolist",
+    "noreadonly",
+    "nowrap",
+    "readonly",
+    "scrolloff",
+    "wrap",
+];
+
+/// Whether the command called `name` (which may have a `!` on the end) takes the path of a file.
+pub fn takes_path(name: &str) -> bool {
+    matches!(
+        name.strip_suffix('!').unwrap_or(name),
+        "w" | "write"
+            | "wq"
+            | "x"
+            | "exit"
+            | "e"
+            | "edit"
+            | "vie"
+            | "view"
+            | "sp"
+            | "split"
+            | "vs"
+            | "vsplit"
+            | "cf"
+            | "cfile"
+            | "diffs"
+            | "diffsplit"
+            | "mks"
+            | "mksession"
+            | "cd"
+            | "chd"
+            | "chdir"
+    )
+}
+
+/// A way of pointing at a line of the buffer, in a range given before a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Address {
+    /// The line that the cursor is on (`.`, or nothing at all.)
+    Current,
+    /// The last line of the buffer (`$`.)
+    Last,
+    /// A line number, counted from one.
+    Line(usize),
+}
+
+/// The lines that a command applies to, including both ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: Address,
+    pub end: Address,
+}
+
+impl LineRange {
+    /// The range that commands apply to when they aren't given one, which is the line the cursor is on.
+    pub fn current() -> Self {
+        Self {
+            start: Address::Current,
+            end: Address::Current,
+        }
+    }
+
+    /// The range that covers every line of the buffer (`%`.)
+    pub fn all() -> Self {
+        Self {
+            start: Address::Line(1),
+            end: Address::Last,
+        }
+    }
+}
+
+/// The ways in which a line typed at the prompt can fail to be a command.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CommandError {
+    /// The name at the start of the line isn't one that we know.
+    UnknownCommand(String),
+    /// The command needs an argument that wasn't given.
+    MissingArgument(String),
+    /// The command doesn't take an argument, but one was given.
+    UnexpectedArgument(String),
+    /// The argument given to the command isn't one that it can use.
+    InvalidArgument(String),
+    /// The range before the command can't be made sense of, or the command doesn't take one.
+    InvalidRange,
+    /// There was something left over after the end of the command.
+    TrailingCharacters(String),
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::UnknownCommand(name) => write!(f, "Not an editor command: {}", name),
+            CommandError::MissingArgument(name) => write!(f, ":{} needs an argument", name),
+            CommandError::UnexpectedArgument(name) => {
+                write!(f, ":{} doesn't take an argument", name)
+            }
+            CommandError::InvalidArgument(argument) => write!(f, "Invalid argument: {}", argument),
+            CommandError::InvalidRange => write!(f, "Invalid range"),
+            CommandError::TrailingCharacters(rest) => write!(f, "Trailing characters: {}", rest),
+        }
+    }
+}
+
+impl Error for CommandError {}
+
+/// Parses a line typed at the prompt (without the leading `:`) into a `Command`.
+///
+/// Commands can be shortened to their first letter, like in Vim, and a trailing `!` forces them.
+pub fn parse(line: &str) -> Result<Command, CommandError> {
+    let (range, line) = parse_range(line.trim())?;
+    if let Some(rest) = line.strip_prefix('s') {
+        if let Some(delimiter) = rest.chars().next().filter(|&c| is_delimiter(c)) {
+            return parse_substitute(range.unwrap_or_else(LineRange::current), rest, delimiter);
+        }
+    }
+    if let Some(command) = line.strip_prefix('!') {
+        let command = command.trim().to_string();
+        return match (range, command.is_empty()) {
+            (_, true) => Err(CommandError::MissingArgument("!".to_string())),
+            (Some(range), false) => Ok(Command::Filter { range, command }),
+            (None, false) => Ok(Command::Shell(command)),
+        };
+    }
+    // Sorting and reversing go over the whole buffer unless they're given a range.
+    let (name, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    match (name, argument.trim()) {
+        ("sor" | "sort" | "sor!" | "sort!", flags) => {
+            return parse_sort(
+                range.unwrap_or_else(LineRange::all),
+                name.ends_with('!'),
+                flags,
+            )
+        }
+        ("rev" | "reverse", "") => {
+            return Ok(Command::Reverse(range.unwrap_or_else(LineRange::all)))
+        }
+        ("rev" | "reverse", _) => return Err(CommandError::UnexpectedArgument(name.to_string())),
+        _ => (),
+    }
+    if range.is_some() {
+        return Err(CommandError::InvalidRange);
+    }
+    // The `!` of `:r !date` can come straight after the name, so it doesn't get mistaken for forcing the command.
+    for name in ["r", "read"] {
+        let command = line
+            .strip_prefix(name)
+            .and_then(|rest| rest.trim_start().strip_prefix('!'));
+        if let Some(command) = command {
+            return match command.trim() {
+                "" => Err(CommandError::MissingArgument(name.to_string())),
+                command => Ok(Command::InsertOutput(command.to_string())),
+            };
+        }
+    }
+
+    let (name, argument) = match line.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, Some(argument.trim_start())),
+        None => (line, None),
+    };
+    let (base, force) = match name.strip_suffix('!') {
+        Some(base) => (base, true),
+        None => (name, false),
+    };
+
+    match (base, argument) {
+        ("w" | "write", path) => Ok(Command::Write {
+            path: path.map(PathBuf::from),
+            force,
+        }),
+        ("wq" | "x" | "exit", path) => Ok(Command::WriteQuit {
+            path: path.map(PathBuf::from),
+            force,
+        }),
+        ("q" | "quit", None) => Ok(Command::Quit { force }),
+        ("e" | "edit", Some(path)) => Ok(Command::Edit {
+            path: PathBuf::from(path),
+            force,
+        }),
+        ("vie" | "view", Some(path)) => Ok(Command::View(PathBuf::from(path))),
+        ("sp" | "split", path) => Ok(Command::Split(Split::Horizontal, path.map(PathBuf::from))),
+        ("vs" | "vsplit", path) => Ok(Command::Split(Split::Vertical, path.map(PathBuf::from))),
+        ("clo" | "close", None) => Ok(Command::CloseWindow),
+        ("diffs" | "diffsplit", Some(path)) => Ok(Command::DiffSplit(PathBuf::from(path))),
+        ("diffo" | "diffoff", None) => Ok(Command::DiffOff),
+        ("format", None) => Ok(Command::Format),
+        ("mks" | "mksession", path) => Ok(Command::MakeSession {
+            path: path.map(PathBuf::from),
+            force,
+        }),
+        ("n" | "next", None) => Ok(Command::NextFile),
+        ("prev" | "previous" | "N" | "Next", None) => Ok(Command::PreviousFile),
+        ("bn" | "bnext", None) => Ok(Command::NextBuffer),
+        ("bp" | "bprevious" | "bN" | "bNext", None) => Ok(Command::PreviousBuffer),
+        ("b" | "buffer", Some(number)) => match number.parse() {
+            Ok(number) if number > 0 => Ok(Command::Buffer(number)),
+            _ => Err(CommandError::InvalidArgument(number.to_string())),
+        },
+        ("bd" | "bdelete", None) => Ok(Command::CloseBuffer { force }),
+        ("ls" | "buffers", None) => Ok(Command::ListBuffers),
+        ("ol" | "oldfiles", None) => Ok(Command::OldFiles),
+        ("noh" | "nohlsearch", None) => Ok(Command::NoHighlight),
+        ("mak" | "make", arguments) => Ok(Command::Make(arguments.map(str::to_string))),
+        ("cf" | "cfile", Some(path)) => Ok(Command::ErrorFile(PathBuf::from(path))),
+        ("cn" | "cnext", None) => Ok(Command::NextError),
+        ("cp" | "cprevious" | "cN" | "cNext", None) => Ok(Command::PreviousError),
+        ("cd" | "chd" | "chdir", path) => Ok(Command::ChangeDirectory(path.map(PathBuf::from))),
+        ("pw" | "pwd", None) => Ok(Command::PrintDirectory),
+        ("bufdo", Some(commands)) => Ok(Command::BufDo(split_commands(commands))),
+        ("argdo", Some(commands)) => Ok(Command::ArgDo(split_commands(commands))),
+        ("savemacro", Some(argument)) => parse_save_macro(argument),
+        ("se" | "set", Some(argument)) => Ok(match argument.split_once('=') {
+            Some((option, value)) => Command::Set {
+                option: option.trim().to_string(),
+                value: Some(value.trim().to_string()),
+            },
+            None => Command::Set {
+                option: argument.trim().to_string(),
+                value: None,
+            },
+        }),
+        (
+            "q" | "quit" | "clo" | "close" | "n" | "next" | "prev" | "previous" | "N" | "Next"
+            | "bn" | "bnext" | "bp" | "bprevious" | "bN" | "bNext" | "bd" | "bdelete" | "ls"
+            | "buffers" | "noh" | "nohlsearch" | "cn" | "cnext" | "cp" | "cprevious" | "cN"
+            | "cNext" | "diffo" | "diffoff" | "format" | "ol" | "oldfiles" | "pw" | "pwd",
+            Some(_),
+        ) => Err(CommandError::UnexpectedArgument(name.to_string())),
+        ("b" | "buffer", None) => Err(CommandError::MissingArgument(name.to_string())),
+        (
+            "e" | "edit" | "vie" | "view" | "se" | "set" | "cf" | "cfile" | "diffs" | "diffsplit"
+            | "bufdo" | "argdo" | "savemacro",
+            None,
+        ) => Err(CommandError::MissingArgument(name.to_string())),
+        _ => Err(CommandError::UnknownCommand(name.to_string())),
+    }
+}
+
+/// Parses the arguments of `:savemacro`, which are the name of a register and the keys to map onto it.
+fn parse_save_macro(argument: &str) -> Result<Command, CommandError> {
+    let invalid = || CommandError::InvalidArgument(argument.to_string());
+    let (name, keys) = argument
+        .split_once(char::is_whitespace)
+        .ok_or_else(invalid)?;
+    let register = match name.parse() {
+        Ok(register @ ('a'..='z' | 'A'..='Z')) => register,
+        _ => return Err(invalid()),
+    };
+    let keys = keys.trim();
+    if keymap::parse_keys(keys).map_or(true, |keys| keys.is_empty()) {
+        return Err(invalid());
+    }
+    Ok(Command::SaveMacro {
+        register,
+        keys: keys.to_string(),
+    })
+}
+
+/// Splits up the commands given to `:bufdo` or `:argdo`, which are separated by `|`.
+///
+/// A `|` that's part of a command is written with a `\` in front of it. The `\` is taken off again, except in the pattern of a
+/// substitute command like `:s/a\|b/c/`, where `\|` separates the alternatives like it does in Vim.
+fn split_commands(text: &str) -> Vec<String> {
+    let mut commands = vec![String::new()];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                commands.last_mut().unwrap().push_str("\\|");
+                chars.next();
+            }
+            '|' => commands.push(String::new()),
+            c => commands.last_mut().unwrap().push(c),
+        }
+    }
+    commands
+        .iter()
+        .map(|command| command.trim())
+        .filter(|command| !command.is_empty())
+        .map(|command| match is_substitute(command) {
+            true => command.to_string(),
+            false => command.replace("\\|", "|"),
+        })
+        .collect()
+}
+
+/// Determines whether a command is a substitute command, like `%s/a/b/`.
+fn is_substitute(command: &str) -> bool {
+    match parse_range(command) {
+        Ok((_, line)) => line
+            .strip_prefix('s')
+            .and_then(|rest| rest.chars().next())
+            .is_some_and(is_delimiter),
+        Err(_) => false,
+    }
+}
+
+/// Splits the range off the front of a line, like the `%` in `%s/a/b/` or the `1,10` in `1,10s/a/b/`.
+fn parse_range(line: &str) -> Result<(Option<LineRange>, &str), CommandError> {
+    if let Some(rest) = line.strip_prefix('%') {
+        return Ok((Some(LineRange::all()), rest.trim_start()));
+    }
+
+    let (start, rest) = match parse_address(line) {
+        (Some(start), rest) => (start, rest),
+        (None, rest) => return Ok((None, rest)),
+    };
+    let (end, rest) = match rest.strip_prefix(',') {
+        Some(rest) => match parse_address(rest) {
+            (Some(end), rest) => (end, rest),
+            (None, _) => return Err(CommandError::InvalidRange),
+        },
+        None => (start, rest),
+    };
+    Ok((Some(LineRange { start, end }), rest.trim_start()))
+}
+
+/// Splits a single line address off the front of some text.
+fn parse_address(text: &str) -> (Option<Address>, &str) {
+    if let Some(rest) = text.strip_prefix('.') {
+        return (Some(Address::Current), rest);
+    }
+    if let Some(rest) = text.strip_prefix('$') {
+        return (Some(Address::Last), rest);
+    }
+
+    let digits = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    match text[..digits].parse() {
+        Ok(line) => (Some(Address::Line(line)), &text[digits..]),
+        Err(_) => (None, text),
+    }
+}
+
+/// Determines whether a character can separate the parts of a substitute command, like the `/` in `s/a/b/`.
+fn is_delimiter(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace() && !matches!(c, '\\' | '"' | '|')
+}
+
+/// Splits `text` at the first `delimiter` that hasn't been escaped with a `\`.
+///
+/// Escaped delimiters lose their `\`, but any other escapes are left for the pattern or replacement to deal with.
+fn split_delimited(text: &str, delimiter: char) -> (String, Option<&str>) {
+    let mut part = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, c)) if c == delimiter => part.push(c),
+                Some((_, c)) => {
+                    part.push('\\');
+                    part.push(c);
+                }
+                None => part.push('\\'),
+            },
+            c if c == delimiter => return (part, Some(&text[i + c.len_utf8()..])),
+            c => part.push(c),
+        }
+    }
+    (part, None)
+}
+
+/// Reads the flags after `:sort`, which can be `n` and `u` in any order.
+fn parse_sort(range: LineRange, reverse: bool, flags: &str) -> Result<Command, CommandError> {
+    let (mut numeric, mut unique) = (false, false);
+    for flag in flags.chars().filter(|c| !c.is_whitespace()) {
+        match flag {
+            'n' => numeric = true,
+            'u' => unique = true,
+            _ => return Err(CommandError::InvalidArgument(flags.to_string())),
+        }
+    }
+    Ok(Command::Sort {
+        range,
+        reverse,
+        numeric,
+        unique,
+    })
+}
+
+/// Parses the rest of a substitute command, from its first delimiter onwards.
+///
+/// Like in Vim, the closing delimiter can be left off, as can the replacement.
+/// The pattern can be left empty too, for the last one searched for to be used.
+fn parse_substitute(
+    range: LineRange,
+    text: &str,
+    delimiter: char,
+) -> Result<Command, CommandError> {
+    let text = &text[delimiter.len_utf8()..];
+    let (pattern, rest) = split_delimited(text, delimiter);
+    let (replacement, flags) = match rest {
+        Some(rest) => split_delimited(rest, delimiter),
+        None => (String::new(), None),
+    };
+
+    let mut global = false;
+    let mut confirm = false;
+    let flags = flags.unwrap_or("").trim();
+    for flag in flags.chars() {
+        match flag {
+            'g' => global = true,
+            'c' => confirm = true,
+            _ => return Err(CommandError::TrailingCharacters(flags.to_string())),
+        }
+    }
+
+    Ok(Command::Substitute {
+        range,
+        pattern,
+        replacement,
+        global,
+        confirm,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parsing_write() {
+        let write = |path: Option<&str>, force| Command::Write {
+            path: path.map(PathBuf::from),
+            force,
+        };
+        assert_eq!(parse("w"), Ok(write(None, false)));
+        assert_eq!(parse("write"), Ok(write(None, false)));
+        assert_eq!(parse("w!"), Ok(write(None, true)));
+        assert_eq!(
+            parse("w  some file.txt"),
+            Ok(write(Some("some file.txt"), false))
+        );
+        assert_eq!(
+            parse("wq"),
+            Ok(Command::WriteQuit {
+                path: None,
+                force: false
+            })
+        );
+        assert_eq!(
+            parse("x!"),
+            Ok(Command::WriteQuit {
+                path: None,
+                force: true
+            })
+        );
+    }
+
+    #[test]
+    fn test_parsing_mksession() {
+        assert_eq!(
+            parse("mksession"),
+            Ok(Command::MakeSession {
+                path: None,
+                force: false
+            })
+        );
+        assert_eq!(
+            parse("mks! work.te"),
+            Ok(Command::MakeSession {
+                path: Some(PathBuf::from("work.te")),
+                force: true
+            })
+        );
+    }
+
+    #[test]
+    fn test_parsing_quit() {
+        assert_eq!(parse("q"), Ok(Command::Quit { force: false }));
+        assert_eq!(parse(" quit "), Ok(Command::Quit { force: false }));
+        assert_eq!(parse("q!"), Ok(Command::Quit { force: true }));
+        assert_eq!(
+            parse("q now"),
+            Err(CommandError::UnexpectedArgument("q".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parsing_view() {
+        assert_eq!(
+            parse("view notes.txt"),
+            Ok(Command::View(PathBuf::from("notes.txt")))
+        );
+        assert_eq!(
+            parse("view"),
+            Err(CommandError::MissingArgument("view".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parsing_set() {
+        assert_eq!(
+            parse("set ff=dos"),
+            Ok(Command::Set {
+                option: "ff".to_string(),
+                value: Some("dos".to_string())
+            })
+        );
+        assert_eq!(
+            parse("se fileformat"),
+            Ok(Command::Set {
+                option: "fileformat".to_string(),
+                value: None
+            })
+        );
+        assert_eq!(
+            parse("set"),
+            Err(CommandError::MissingArgument("set".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parsing_edit() {
+        assert_eq!(
+            parse("e src/main.rs"),
+            Ok(Command::Edit {
+                path: PathBuf::from("src/main.rs"),
+                force: false
+            })
+        );
+        assert_eq!(
+            parse("edit! other"),
+            Ok(Command::Edit {
+                path: PathBuf::from("other"),
+                force: true
+            })
+        );
+        assert_eq!(
+            parse("e"),
+            Err(CommandError::MissingArgument("e".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parsing_window_commands() {
+        assert_eq!(parse("sp"), Ok(Command::Split(Split::Horizontal, None)));
+        assert_eq!(
+            parse("vsplit other"),
+            Ok(Command::Split(
+                Split::Vertical,
+                Some(PathBuf::from("other"))
+            ))
+        );
+        assert_eq!(parse("close"), Ok(Command::CloseWindow));
+        assert_eq!(
+            parse("diffsplit other"),
+            Ok(Command::DiffSplit(PathBuf::from("other")))
+        );
+        assert_eq!(parse("diffo"), Ok(Command::DiffOff));
+        assert_eq!(
+            parse("diffs"),
+            Err(CommandError::MissingArgument("diffs".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parsing_argument_list_commands() {
+        assert_eq!(parse("n"), Ok(Command::NextFile));
+        assert_eq!(parse("next"), Ok(Command::NextFile));
+        assert_eq!(parse("prev"), Ok(Command::PreviousFile));
+        assert_eq!(parse("N"), Ok(Command::PreviousFile));
+        assert_eq!(
+            parse("n other"),
+            Err(CommandError::UnexpectedArgument("n".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parsing_buffer_commands() {
+        assert_eq!(parse("bn"), Ok(Command::NextBuffer));
+        assert_eq!(parse("bprevious"), Ok(Command::PreviousBuffer));
+        assert_eq!(parse("b 2"), Ok(Command::Buffer(2)));
+        assert_eq!(parse("bd!"), Ok(Command::CloseBuffer { force: true }));
+        assert_eq!(parse("ls"), Ok(Command::ListBuffers));
+        assert_eq!(parse("oldfiles"), Ok(Command::OldFiles));
+        assert_eq!(parse("noh"), Ok(Command::NoHighlight));
+        assert_eq!(parse("nohlsearch"), Ok(Command::NoHighlight));
+        assert_eq!(parse("make"), Ok(Command::Make(None)));
+        assert_eq!(
+            parse("mak -j4 all"),
+            Ok(Command::Make(Some("-j4 all".to_string())))
+        );
+        assert_eq!(
+            parse("cf errors.txt"),
+            Ok(Command::ErrorFile(PathBuf::from("errors.txt")))
+        );
+        assert_eq!(parse("cn"), Ok(Command::NextError));
+        assert_eq!(parse("cNext"), Ok(Command::PreviousError));
+        assert_eq!(
+            parse("cfile"),
+            Err(CommandError::MissingArgument("cfile".to_string()))
+        );
+        assert_eq!(
+            parse("b 0"),
+            Err(CommandError::InvalidArgument("0".to_string()))
+        );
+        assert_eq!(
+            parse("bn 2"),
+            Err(CommandError::UnexpectedArgument("bn".to_string()))
+        );
+    }
+
+    /// Parses a substitute command, expecting it to be valid.
+    fn substitute(line: &str) -> (LineRange, String, String, bool, bool) {
+        match parse(line) {
+            Ok(Command::Substitute {
+                range,
+                pattern,
+                replacement,
+                global,
+                confirm,
+            }) => (range, pattern, replacement, global, confirm),
+            other => panic!("{:?} isn't a substitute command", other),
+        }
+    }
+
+    #[test]
+    fn test_parsing_bufdo_and_argdo() {
+        assert_eq!(
+            parse("bufdo %s/a\\|b/c/g | w"),
+            Ok(Command::BufDo(vec!["%s/a\\|b/c/g".into(), "w".into()]))
+        );
+        assert_eq!(
+            parse("argdo s/a\\|b/x/ | !echo a\\|b"),
+            Ok(Command::ArgDo(vec![
+                "s/a\\|b/x/".into(),
+                "!echo a|b".into()
+            ]))
+        );
+        assert_eq!(
+            parse("argdo set ff=unix|"),
+            Ok(Command::ArgDo(vec!["set ff=unix".into()]))
+        );
+        assert_eq!(
+            parse("bufdo"),
+            Err(CommandError::MissingArgument("bufdo".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parsing_substitute() {
+        assert_eq!(
+            substitute("s/a b/c/"),
+            (LineRange::current(), "a b".into(), "c".into(), false, false)
+        );
+        assert_eq!(
+            substitute("s/a/b/gc"),
+            (LineRange::current(), "a".into(), "b".into(), true, true)
+        );
+        assert_eq!(
+            substitute("s/a"),
+            (LineRange::current(), "a".into(), "".into(), false, false)
+        );
+        assert_eq!(
+            substitute("s#/usr#/opt\\#x#"),
+            (
+                LineRange::current(),
+                "/usr".into(),
+                "/opt#x".into(),
+                false,
+                false
+            )
+        );
+        assert_eq!(
+            substitute("s/a\\.\\/b/\\1/"),
+            (
+                LineRange::current(),
+                "a\\./b".into(),
+                "\\1".into(),
+                false,
+                false
+            )
+        );
+        assert_eq!(
+            substitute("s//b/"),
+            (LineRange::current(), "".into(), "b".into(), false, false)
+        );
+        assert_eq!(
+            parse("s/a/b/x"),
+            Err(CommandError::TrailingCharacters("x".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parsing_ranges() {
+        let whole = LineRange {
+            start: Address::Line(1),
+            end: Address::Last,
+        };
+        assert_eq!(substitute("%s/a/b/").0, whole);
+        assert_eq!(
+            substitute("1,10s/a/b/").0,
+            LineRange {
+                start: Address::Line(1),
+                end: Address::Line(10),
+            }
+        );
+        assert_eq!(
+            substitute(".,$ s/a/b/").0,
+            LineRange {
+                start: Address::Current,
+                end: Address::Last,
+            }
+        );
+        assert_eq!(
+            substitute("5s/a/b/").0,
+            LineRange {
+                start: Address::Line(5),
+                end: Address::Line(5),
+            }
+        );
+        assert_eq!(parse("1,s/a/b/"), Err(CommandError::InvalidRange));
+        assert_eq!(parse("%q"), Err(CommandError::InvalidRange));
+    }
+
+    #[test]
+    fn test_parsing_directory_commands() {
+        assert_eq!(
+            parse("cd ../other dir"),
+            Ok(Command::ChangeDirectory(Some(PathBuf::from(
+                "../other dir"
+            ))))
+        );
+        assert_eq!(parse("chdir"), Ok(Command::ChangeDirectory(None)));
+        assert_eq!(parse("pwd"), Ok(Command::PrintDirectory));
+        assert_eq!(
+            parse("pwd x"),
+            Err(CommandError::UnexpectedArgument("pwd".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parsing_shell_commands() {
+        assert_eq!(parse("!ls -l"), Ok(Command::Shell("ls -l".to_string())));
+        assert_eq!(
+            parse("%!sort"),
+            Ok(Command::Filter {
+                range: LineRange {
+                    start: Address::Line(1),
+                    end: Address::Last
+                },
+                command: "sort".to_string()
+            })
+        );
+        assert_eq!(
+            parse("2,3! jq ."),
+            Ok(Command::Filter {
+                range: LineRange {
+                    start: Address::Line(2),
+                    end: Address::Line(3)
+                },
+                command: "jq .".to_string()
+            })
+        );
+        assert_eq!(
+            parse("r !date"),
+            Ok(Command::InsertOutput("date".to_string()))
+        );
+        assert_eq!(
+            parse("read!echo hi"),
+            Ok(Command::InsertOutput("echo hi".to_string()))
+        );
+        assert_eq!(
+            parse("!"),
+            Err(CommandError::MissingArgument("!".to_string()))
+        );
+        assert_eq!(
+            parse("r !"),
+            Err(CommandError::MissingArgument("r".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parsing_sort_and_reverse() {
+        assert_eq!(
+            parse("sort"),
+            Ok(Command::Sort {
+                range: LineRange::all(),
+                reverse: false,
+                numeric: false,
+                unique: false
+            })
+        );
+        assert_eq!(
+            parse("2,$sort! un"),
+            Ok(Command::Sort {
+                range: LineRange {
+                    start: Address::Line(2),
+                    end: Address::Last
+                },
+                reverse: true,
+                numeric: true,
+                unique: true
+            })
+        );
+        assert_eq!(
+            parse("sor x"),
+            Err(CommandError::InvalidArgument("x".to_string()))
+        );
+        assert_eq!(parse("rev"), Ok(Command::Reverse(LineRange::all())));
+        assert_eq!(
+            parse("3,4reverse"),
+            Ok(Command::Reverse(LineRange {
+                start: Address::Line(3),
+                end: Address::Line(4)
+            }))
+        );
+        assert_eq!(
+            parse("reverse 2"),
+            Err(CommandError::UnexpectedArgument("reverse".to_string()))
+        );
+        // A shell command called sort is still run as a filter.
+        assert!(matches!(parse("%!sort"), Ok(Command::Filter { .. })));
+    }
+
+    #[test]
+    fn test_parsing_save_macro() {
+        assert_eq!(
+            parse("savemacro q <M-q>"),
+            Ok(Command::SaveMacro {
+                register: 'q',
+                keys: "<M-q>".to_string()
+            })
+        );
+        assert_eq!(
+            parse("savemacro"),
+            Err(CommandError::MissingArgument("savemacro".to_string()))
+        );
+        for argument in ["q", "+ <M-q>", "qq <M-q>", "q <Nope>"] {
+            assert_eq!(
+                parse(&format!("savemacro {}", argument)),
+                Err(CommandError::InvalidArgument(argument.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_parsing_unknown_command() {
+        assert_eq!(
+            parse("frobnicate"),
+            Err(CommandError::UnknownCommand("frobnicate".to_string()))
+        );
+        assert_eq!(
+            parse("frobnicate").unwrap_err().to_string(),
+            "Not an editor command: frobnicate"
+        );
+    }
+}
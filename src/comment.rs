@@ -0,0 +1,153 @@
+//! Commenting lines out and back in again, which `gc` does with whatever comments look like in the type of file being edited.
+
+/// How comments are written in some type of file, like `//` or `/* */`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommentString {
+    start: String,
+    /// What closes a comment, which is empty for the kind that carries on to the end of the line.
+    end: String,
+}
+
+impl CommentString {
+    /// Parses a comment string like Vim's `commentstring`, where `%s` stands for the text being commented out.
+    ///
+    /// A string without a `%s` is the start of a comment that carries on to the end of the line.
+    pub fn parse(s: &str) -> Self {
+        let (start, end) = s.split_once("%s").unwrap_or((s, ""));
+        Self {
+            start: start.trim().to_string(),
+            end: end.trim().to_string(),
+        }
+    }
+
+    /// The usual way of writing comments in a type of file, going by its extension (or its name, for files like `Makefile`.)
+    pub fn for_file_type(file_type: &str) -> Option<Self> {
+        let s = match file_type {
+            "rs" | "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" | "js" | "ts" | "jsx" | "tsx"
+            | "java" | "go" | "swift" | "kt" | "scala" | "cs" | "dart" | "zig" => "//",
+            "py" | "sh" | "bash" | "zsh" | "fish" | "rb" | "pl" | "r" | "toml" | "yaml" | "yml"
+            | "conf" | "cfg" | "ini" | "mk" | "cmake" | "nix" | "Makefile" | "Dockerfile" => "#",
+            "lua" | "sql" | "hs" | "elm" | "ada" => "--",
+            "lisp" | "el" | "clj" | "scm" | "asm" => ";",
+            "vim" => "\"",
+            "tex" | "erl" => "%",
+            "css" => "/* %s */",
+            "html" | "xml" | "svg" | "md" => "<!-- %s -->",
+            _ => return None,
+        };
+        Some(Self::parse(s))
+    }
+
+    /// Whether a line (without its line ending) is commented out already.
+    fn is_commented(&self, line: &str) -> bool {
+        let line = line.trim();
+        line.starts_with(&self.start) && line[self.start.len()..].ends_with(&self.end)
+    }
+
+    /// Comments a line out, putting the start of the comment after the first `indent` bytes.
+    fn comment(&self, line: &str, indent: usize) -> String {
+        let (indent, text) = line.split_at(indent);
+        match self.end.as_str() {
+            "" => format!("{}{} {}", indent, self.start, text),
+            end => format!("{}{} {} {}", indent, self.start, text, end),
+        }
+    }
+
+    /// Takes the comment off a line, along with the space that was put between it and the text.
+    fn uncomment(&self, line: &str) -> String {
+        let text = line.trim_start();
+        let indent = &line[..line.len() - text.len()];
+        let text = text.trim_end();
+        let text = &text[self.start.len()..text.len() - self.end.len()];
+        let text = text.strip_prefix(' ').unwrap_or(text);
+        let text = match self.end.is_empty() {
+            true => text,
+            false => text.strip_suffix(' ').unwrap_or(text),
+        };
+        format!("{}{}", indent, text)
+    }
+}
+
+/// Comments some lines out, or takes the comments off them if they're all commented out already.
+///
+/// Blank lines are left as they are either way, and comments all start at the same column as the least indented line so
+/// that they line up.
+pub fn toggle(comment: &CommentString, lines: &[String]) -> Vec<String> {
+    let filled = || lines.iter().filter(|line| !line.trim().is_empty());
+    if filled().all(|line| comment.is_commented(line)) {
+        return lines
+            .iter()
+            .map(|line| match line.trim().is_empty() {
+                true => line.clone(),
+                false => comment.uncomment(line),
+            })
+            .collect();
+    }
+
+    let indent = filled()
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| match line.trim().is_empty() {
+            true => line.clone(),
+            false => comment.comment(line, indent),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parsing_comment_strings() {
+        assert_eq!(
+            CommentString::parse("/* %s */"),
+            CommentString {
+                start: "/*".to_string(),
+                end: "*/".to_string()
+            }
+        );
+        assert_eq!(CommentString::parse("#"), CommentString::parse("# %s"));
+        assert_eq!(
+            CommentString::for_file_type("rs"),
+            Some(CommentString::parse("//"))
+        );
+        assert_eq!(CommentString::for_file_type("txt"), None);
+    }
+
+    #[test]
+    fn test_toggling_line_comments() {
+        let comment = CommentString::parse("//");
+        let code = lines(&["    if x {", "", "        y();", "    }"]);
+        let commented = toggle(&comment, &code);
+        assert_eq!(
+            commented,
+            lines(&["    // if x {", "", "    //     y();", "    // }"])
+        );
+        assert_eq!(toggle(&comment, &commented), code);
+
+        // Lines that are only partly commented out all get commented.
+        let mixed = lines(&["// a", "b"]);
+        assert_eq!(toggle(&comment, &mixed), lines(&["// // a", "// b"]));
+        assert_eq!(toggle(&comment, &lines(&["\t//a"])), lines(&["\ta"]));
+    }
+
+    #[test]
+    fn test_toggling_block_comments() {
+        let comment = CommentString::parse("<!-- %s -->");
+        let markup = lines(&["<p>", "  text", "</p>"]);
+        let commented = toggle(&comment, &markup);
+        assert_eq!(
+            commented,
+            lines(&["<!-- <p> -->", "<!--   text -->", "<!-- </p> -->"])
+        );
+        assert_eq!(toggle(&comment, &commented), markup);
+    }
+}
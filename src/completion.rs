@@ -0,0 +1,215 @@
+//! Finishing off the word being typed at the `:` prompt, which might be a command, an option or a file.
+use std::fs;
+use std::path::Path;
+
+use crate::command;
+
+/// The ways that the end of a line at the prompt could be finished off, which Tab and Shift-Tab cycle through.
+#[derive(Debug)]
+pub struct Completion {
+    /// The line up to the start of the word being completed, which stays the same while cycling.
+    head: String,
+    /// What had been typed of the word, which cycling comes back round to after the last candidate.
+    word: String,
+    candidates: Vec<String>,
+    /// The candidate being shown, or `None` while it's `word`.
+    selected: Option<usize>,
+}
+
+impl Completion {
+    /// Works out what the last word of `line` could be finished off with, if there's anything.
+    ///
+    /// That's a command name for the first word, an option name after `:set`, or a path after a command that takes one.
+    pub fn new(line: &str) -> Option<Self> {
+        let trimmed = line.trim_start();
+        let (word, candidates) = match trimmed.split_once(char::is_whitespace) {
+            None => (trimmed, names(command::NAMES, trimmed)),
+            Some((name, argument)) => {
+                let argument = argument.trim_start();
+                match name {
+                    "se" | "set" => (argument, names(command::OPTIONS, argument)),
+                    name if command::takes_path(name) => (argument, paths(argument)),
+                    _ => return None,
+                }
+            }
+        };
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(Self {
+            head: line[..line.len() - word.len()].to_string(),
+            word: word.to_string(),
+            candidates,
+            selected: None,
+        })
+    }
+
+    /// The words that the line could be finished off with.
+    pub fn candidates(&self) -> &[String] {
+        &self.candidates
+    }
+
+    /// What to show for a candidate above the prompt, which leaves off the directory that a path is in.
+    pub fn label(&self, index: usize) -> &str {
+        let candidate = &self.candidates[index];
+        let name_start = candidate
+            .trim_end_matches('/')
+            .rfind('/')
+            .map_or(0, |i| i + 1);
+        &candidate[name_start..]
+    }
+
+    /// Which of the candidates is in the line at the moment, if any of them are.
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Moves on to the next candidate, returning the line finished off with it.
+    pub fn next(&mut self) -> String {
+        self.selected = match self.selected {
+            None => Some(0),
+            Some(i) if i + 1 < self.candidates.len() => Some(i + 1),
+            Some(_) => None,
+        };
+        self.line()
+    }
+
+    /// Moves back to the previous candidate, returning the line finished off with it.
+    pub fn previous(&mut self) -> String {
+        self.selected = match self.selected {
+            None => self.candidates.len().checked_sub(1),
+            Some(0) => None,
+            Some(i) => Some(i - 1),
+        };
+        self.line()
+    }
+
+    /// The line with the selected candidate (or what was typed) on the end.
+    fn line(&self) -> String {
+        let word = match self.selected {
+            Some(i) => &self.candidates[i],
+            None => &self.word,
+        };
+        format!("{}{}", self.head, word)
+    }
+}
+
+/// Picks out the names that start with `word`.
+fn names(names: &[&str], word: &str) -> Vec<String> {
+    names
+        .iter()
+        .filter(|name| name.starts_with(word))
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Lists the files and directories that start with `word`, with a `/` on the end of the directories so that they can be completed into.
+///
+/// Hidden files are left out unless `word` asks for them by starting their name with a `.`.
+fn paths(word: &str) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => word.split_at(i + 1),
+        None => ("", word),
+    };
+    let read_from = match dir {
+        "" => Path::new("."),
+        dir => Path::new(dir),
+    };
+    let entries = match fs::read_dir(read_from) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut paths: Vec<String> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", dir, name, slash))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_completing_commands() {
+        let mut completion = Completion::new("b").unwrap();
+        assert_eq!(
+            completion.candidates(),
+            [
+                "bNext",
+                "bdelete",
+                "bnext",
+                "bprevious",
+                "bufdo",
+                "buffer",
+                "buffers"
+            ]
+        );
+        assert_eq!(completion.label(0), "bNext");
+        assert_eq!(completion.next(), "bNext");
+        assert_eq!(completion.next(), "bdelete");
+        assert_eq!(completion.selected(), Some(1));
+        assert_eq!(completion.previous(), "bNext");
+        assert_eq!(completion.previous(), "b");
+        assert_eq!(completion.selected(), None);
+        assert_eq!(completion.previous(), "buffers");
+        assert_eq!(completion.next(), "b");
+
+        assert!(Completion::new("xyz").is_none());
+        assert!(Completion::new("quit ").is_none());
+    }
+
+    #[test]
+    fn test_completing_options() {
+        let mut completion = Completion::new("set no").unwrap();
+        assert_eq!(
+            completion.candidates(),
+            ["nocursorline", "nolist", "noreadonly", "nowrap"]
+        );
+        assert_eq!(completion.next(), "set nocursorline");
+        assert_eq!(Completion::new("se   s").unwrap().next(), "se   scrolloff");
+    }
+
+    #[test]
+    fn test_completing_paths() {
+        let dir = TempDir::new("complete");
+        fs::create_dir_all(dir.join("subdir")).unwrap();
+        dir.write("file.txt", "");
+        dir.write("film.txt", "");
+        dir.write(".hidden", "");
+        let dir_name = dir.to_str().unwrap();
+
+        let line = format!("e {}/fi", dir_name);
+        let completion = Completion::new(&line).unwrap();
+        assert_eq!(
+            completion.candidates(),
+            [
+                format!("{}/file.txt", dir_name),
+                format!("{}/film.txt", dir_name)
+            ]
+        );
+
+        let line = format!("w! {}/", dir_name);
+        let mut completion = Completion::new(&line).unwrap();
+        assert_eq!(completion.candidates().len(), 3);
+        assert_eq!(completion.label(2), "subdir/");
+        assert_eq!(completion.previous(), format!("w! {}/subdir/", dir_name));
+
+        let line = format!("sp {}/.", dir_name);
+        let completion = Completion::new(&line).unwrap();
+        assert_eq!(completion.candidates(), [format!("{}/.hidden", dir_name)]);
+
+        // Commands that don't take a path don't get one.
+        assert!(Completion::new(&format!("bd {}/", dir_name)).is_none());
+    }
+}
@@ -0,0 +1,908 @@
+//! The options that can be set in the configuration file, which is written in a small subset of TOML.
+//!
+//! Only `key = value` pairs, `[table]` headers and comments are understood.
+//! Values can be booleans, integers, or strings in either quote style, which is all that the options need.
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::hooks::{Hook, HookEvent};
+use crate::keymap::{Binding, MapMode};
+use crate::view::{self, FillChars, LineNumbers, ListChars, DEFAULT_TAB_WIDTH};
+
+/// Wrappers for the different errors that can be encountered while reading the configuration file.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file exists, but couldn't be read.
+    Io(io::Error),
+    /// A line of the file couldn't be understood, for the reason given.
+    Invalid { line: usize, reason: String },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{}", e),
+            ConfigError::Invalid { line, reason } => write!(f, "line {}: {}", line, reason),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io(e) => Some(e),
+            ConfigError::Invalid { .. } => None,
+        }
+    }
+}
+
+/// A value given to a key in the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    Integer(i64),
+    String(String),
+}
+
+impl Value {
+    /// Describes the kind of value, for error messages about the wrong kind being given.
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::Bool(_) => "a boolean",
+            Value::Integer(_) => "a number",
+            Value::String(_) => "a string",
+        }
+    }
+}
+
+/// A single `key = value` line from the file, along with the table that it's in.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// The name of the table, which is empty for keys that come before the first table header.
+    pub table: String,
+    pub key: String,
+    pub value: Value,
+    /// The line of the file that the entry is on, counted from one like an editor would.
+    pub line: usize,
+}
+
+impl Entry {
+    /// The full name of the entry, including the table it's in (like `formatters.rs`.)
+    pub fn name(&self) -> String {
+        match self.table.as_str() {
+            "" => self.key.clone(),
+            table => format!("{}.{}", table, self.key),
+        }
+    }
+
+    /// Whether the entry sets something that runs a command, like a formatter or a hook.
+    ///
+    /// Key bindings count as well, since a binding can type out a `:!` command.
+    pub fn runs_commands(&self) -> bool {
+        match self.table.as_str() {
+            "" => self.key == "make_command",
+            "lsp" | "formatters" | "linters" => true,
+            table => table.starts_with("hooks.") || table.starts_with("keys."),
+        }
+    }
+}
+
+/// Everything that can be changed from the configuration file.
+///
+/// Options given on the command line take precedence over these.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// How many cells apart tab stops are.
+    pub tab_width: usize,
+    /// Whether Tab types spaces rather than a tab.
+    pub expand_tab: bool,
+    /// How many cells `>>` and `<<` indent lines by, where 0 means the same as `tab_width`.
+    pub shift_width: usize,
+    /// How line numbers are shown in the gutter.
+    pub line_numbers: LineNumbers,
+    /// Whether yanked and deleted text goes to the system clipboard by default.
+    pub clipboard: bool,
+    /// Whether the mouse is captured, so that it can move the cursor and scroll.
+    pub mouse: bool,
+    /// How many lines are kept on the display above and below the cursor.
+    pub scroll_off: usize,
+    /// Whether long lines carry on onto the rows below them.
+    pub wrap: bool,
+    /// Whether tabs, trailing spaces and non-breaking spaces are shown as glyphs.
+    pub list: bool,
+    /// The glyphs they're shown as, written like Vim's `listchars`.
+    pub list_chars: ListChars,
+    /// What the rows past the end of the buffer start with, written like Vim's `fillchars`.
+    pub fill_chars: FillChars,
+    /// Whether the line the cursor is on is picked out.
+    pub cursor_line: bool,
+    /// The columns to draw rulers at, counted from one, written as a number or a list like `"80,100"`.
+    pub color_columns: Vec<usize>,
+    /// The command that `:make` runs to build the project, which is handed to the shell.
+    pub make_command: String,
+    /// Whether the old version of a file is copied to `file~` before it gets written over.
+    pub backup: bool,
+    /// How many seconds the user has to stop typing for before changes are written out, if they should be at all.
+    pub autosave: Option<u64>,
+    /// Keys mapped onto other keys, from the `[keys.navigate]`, `[keys.edit]` and `[keys.visual]` tables.
+    pub bindings: Vec<Binding>,
+    /// The commands that start a language server for each file extension, from the `[lsp]` table.
+    pub language_servers: Vec<(String, String)>,
+    /// How comments are written for each file extension, from the `[comments]` table, like `c = "/* %s */"`.
+    pub comment_strings: Vec<(String, String)>,
+    /// The commands that tidy up each type of file, from the `[formatters]` table, like `rs = "rustfmt --emit stdout"`.
+    pub formatters: Vec<(String, String)>,
+    /// Whether files are run through their formatter whenever they're written with `:w`.
+    pub format_on_save: bool,
+    /// The commands that check each type of file for problems after it's written, from the `[linters]` table.
+    pub linters: Vec<(String, String)>,
+    /// The commands to run when things happen to each type of file, from the `[hooks.BufWritePre]` tables and the like.
+    pub hooks: Vec<Hook>,
+    /// The name of the theme file to draw the display with, from the `themes` directory next to the configuration file.
+    pub theme: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tab_width: DEFAULT_TAB_WIDTH,
+            expand_tab: false,
+            shift_width: 0,
+            line_numbers: LineNumbers::Off,
+            clipboard: false,
+            mouse: true,
+            scroll_off: 0,
+            wrap: false,
+            list: false,
+            list_chars: ListChars::default(),
+            fill_chars: FillChars::default(),
+            cursor_line: false,
+            color_columns: Vec::new(),
+            make_command: "make".to_string(),
+            backup: false,
+            autosave: None,
+            bindings: Vec::new(),
+            language_servers: Vec::new(),
+            comment_strings: Vec::new(),
+            formatters: Vec::new(),
+            format_on_save: false,
+            linters: Vec::new(),
+            hooks: Vec::new(),
+            theme: None,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration file at `path`, falling back to the defaults if there isn't one.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(ConfigError::Io(e)),
+        }
+    }
+
+    /// Parses the contents of a configuration file, starting from the defaults for anything that it leaves out.
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        for entry in parse_entries(text)? {
+            config
+                .apply(&entry)
+                .map_err(|reason| ConfigError::Invalid {
+                    line: entry.line,
+                    reason,
+                })?;
+        }
+        Ok(config)
+    }
+
+    /// Layers the options set in a project's configuration file over these ones.
+    ///
+    /// Unless the file is `trusted`, the entries that can run commands are left out, and their names are handed back so
+    /// that the user can be told about them.
+    pub fn layer(&mut self, text: &str, trusted: bool) -> Result<Vec<String>, ConfigError> {
+        let mut skipped = Vec::new();
+        for entry in parse_entries(text)? {
+            if !trusted && entry.runs_commands() {
+                skipped.push(entry.name());
+                continue;
+            }
+            self.apply(&entry).map_err(|reason| ConfigError::Invalid {
+                line: entry.line,
+                reason,
+            })?;
+        }
+        Ok(skipped)
+    }
+
+    /// Sets the option named by an entry in the file.
+    fn apply(&mut self, entry: &Entry) -> Result<(), String> {
+        let key = entry.key.as_str();
+        match (entry.table.as_str(), key) {
+            ("", "tab_width") => match expect_integer(key, &entry.value)? {
+                width @ 1..=64 => self.tab_width = width as usize,
+                _ => return Err(format!("{} should be between 1 and 64", key)),
+            },
+            ("", "expand_tab") => self.expand_tab = expect_bool(key, &entry.value)?,
+            ("", "shift_width") => match expect_integer(key, &entry.value)? {
+                width @ 0..=64 => self.shift_width = width as usize,
+                _ => return Err(format!("{} should be between 0 and 64", key)),
+            },
+            ("", "line_numbers") => {
+                self.line_numbers = match expect_string(key, &entry.value)? {
+                    "off" => LineNumbers::Off,
+                    "absolute" => LineNumbers::Absolute,
+                    "relative" => LineNumbers::Relative,
+                    other => {
+                        return Err(format!(
+                            "{} should be \"off\", \"absolute\" or \"relative\", not \"{}\"",
+                            key, other
+                        ))
+                    }
+                }
+            }
+            ("", "clipboard") => self.clipboard = expect_bool(key, &entry.value)?,
+            ("", "mouse") => self.mouse = expect_bool(key, &entry.value)?,
+            ("", "scroll_off") => match expect_integer(key, &entry.value)? {
+                lines @ 0..=999 => self.scroll_off = lines as usize,
+                _ => return Err(format!("{} should be between 0 and 999", key)),
+            },
+            ("", "wrap") => self.wrap = expect_bool(key, &entry.value)?,
+            ("", "list") => self.list = expect_bool(key, &entry.value)?,
+            ("", "list_chars") => {
+                self.list_chars = ListChars::parse(expect_string(key, &entry.value)?)
+                    .map_err(|reason| format!("{}: {}", key, reason))?
+            }
+            ("", "fill_chars") => {
+                self.fill_chars = FillChars::parse(expect_string(key, &entry.value)?)
+                    .map_err(|reason| format!("{}: {}", key, reason))?
+            }
+            ("", "cursor_line") => self.cursor_line = expect_bool(key, &entry.value)?,
+            ("", "color_column") => {
+                self.color_columns = match &entry.value {
+                    Value::Integer(column @ 1..=9999) => vec![*column as usize],
+                    Value::String(columns) => view::parse_color_columns(columns)
+                        .map_err(|reason| format!("{}: {}", key, reason))?,
+                    _ => {
+                        return Err(format!(
+                            "{} should be a column or a list of them like \"80,100\"",
+                            key
+                        ))
+                    }
+                }
+            }
+            ("", "make_command") => {
+                self.make_command = expect_string(key, &entry.value)?.to_string()
+            }
+            ("", "theme") => {
+                let name = expect_string(key, &entry.value)?;
+                if name.is_empty() || name.contains('/') {
+                    return Err(format!(
+                        "{} should be the name of a theme, not \"{}\"",
+                        key, name
+                    ));
+                }
+                self.theme = Some(name.to_string());
+            }
+            ("", "backup") => self.backup = expect_bool(key, &entry.value)?,
+            ("", "format_on_save") => self.format_on_save = expect_bool(key, &entry.value)?,
+            // Autosaving is turned off with 0, since there's no null in TOML.
+            ("", "autosave") => match expect_integer(key, &entry.value)? {
+                0 => self.autosave = None,
+                seconds @ 1..=3600 => self.autosave = Some(seconds as u64),
+                _ => return Err(format!("{} should be between 0 and 3600 seconds", key)),
+            },
+            ("", _) => return Err(format!("Unknown option {}", key)),
+            (table, _) if table.starts_with("keys.") => {
+                let mode = MapMode::from_name(&table["keys.".len()..])
+                    .ok_or_else(|| format!("Unknown table [{}]", table))?;
+                let replacement = expect_string(key, &entry.value)?;
+                let binding = Binding::parse(mode, key, replacement)?;
+                self.bindings.push(binding);
+            }
+            ("lsp", extension) => {
+                let command = expect_string(key, &entry.value)?.to_string();
+                self.language_servers.retain(|(e, _)| e != extension);
+                self.language_servers.push((extension.to_string(), command));
+            }
+            ("comments", extension) => {
+                let comment = expect_string(key, &entry.value)?.to_string();
+                if comment.trim().is_empty() {
+                    return Err(format!("{} should be what a comment starts with", key));
+                }
+                self.comment_strings.retain(|(e, _)| e != extension);
+                self.comment_strings.push((extension.to_string(), comment));
+            }
+            ("formatters", file_type) => {
+                let command = expect_string(key, &entry.value)?.to_string();
+                self.formatters.retain(|(t, _)| t != file_type);
+                self.formatters.push((file_type.to_string(), command));
+            }
+            ("linters", file_type) => {
+                let command = expect_string(key, &entry.value)?.to_string();
+                self.linters.retain(|(t, _)| t != file_type);
+                self.linters.push((file_type.to_string(), command));
+            }
+            (table, file_type) if table.starts_with("hooks.") => {
+                let event = HookEvent::from_name(&table["hooks.".len()..])
+                    .ok_or_else(|| format!("Unknown table [{}]", table))?;
+                let command = expect_string(key, &entry.value)?.to_string();
+                self.hooks
+                    .retain(|h| h.event != event || h.file_type != file_type);
+                self.hooks.push(Hook {
+                    event,
+                    file_type: file_type.to_string(),
+                    command,
+                });
+            }
+            (table, _) => return Err(format!("Unknown table [{}]", table)),
+        }
+        Ok(())
+    }
+}
+
+pub fn expect_bool(key: &str, value: &Value) -> Result<bool, String> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        _ => Err(format!(
+            "{} should be true or false, not {}",
+            key,
+            value.kind()
+        )),
+    }
+}
+
+fn expect_integer(key: &str, value: &Value) -> Result<i64, String> {
+    match value {
+        Value::Integer(n) => Ok(*n),
+        _ => Err(format!("{} should be a number, not {}", key, value.kind())),
+    }
+}
+
+fn expect_string<'a>(key: &str, value: &'a Value) -> Result<&'a str, String> {
+    match value {
+        Value::String(s) => Ok(s),
+        _ => Err(format!("{} should be a string, not {}", key, value.kind())),
+    }
+}
+
+/// The name of the configuration file that a project can keep alongside its files, to override the user's own.
+pub const PROJECT_FILE: &str = ".te.toml";
+
+/// Looks for a project's configuration file in `dir` and then in each of the directories above it.
+pub fn find_project_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Lists the entries in a configuration file that run commands, by their full names.
+pub fn commands_in(text: &str) -> Result<Vec<String>, ConfigError> {
+    Ok(parse_entries(text)?
+        .iter()
+        .filter(|entry| entry.runs_commands())
+        .map(Entry::name)
+        .collect())
+}
+
+/// Saves a binding into the configuration file at `path` (creating it if need be), so that it's there the next time the
+/// editor starts.
+pub fn save_binding(
+    path: &Path,
+    keys: &str,
+    mode: MapMode,
+    replacement: &str,
+) -> Result<(), ConfigError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(ConfigError::Io(e)),
+    };
+    let text = add_binding(&text, keys, mode, replacement)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(ConfigError::Io)?;
+    }
+    fs::write(path, text).map_err(ConfigError::Io)
+}
+
+/// Adds a binding to the text of a configuration file, leaving the rest of it the way the user wrote it.
+///
+/// A binding that's already there for the same keys is replaced where it stands. Otherwise the new one goes at the end of
+/// the mode's table, which is started at the end of the file if there isn't one yet.
+fn add_binding(
+    text: &str,
+    keys: &str,
+    mode: MapMode,
+    replacement: &str,
+) -> Result<String, ConfigError> {
+    let table = format!("keys.{}", mode.name());
+    let line = format!("{} = {}", quote(keys), quote(replacement));
+    let entries = parse_entries(text)?;
+    let mut lines: Vec<&str> = text.lines().collect();
+
+    if let Some(entry) = entries.iter().find(|e| e.table == table && e.key == keys) {
+        lines[entry.line - 1] = &line;
+    } else if let Some(header) = lines
+        .iter()
+        .position(|l| table_name(l).as_deref() == Some(&table))
+    {
+        let last = entries
+            .iter()
+            .filter(|e| e.table == table && e.line > header)
+            .map(|e| e.line)
+            .max()
+            .unwrap_or(header + 1);
+        lines.insert(last, &line);
+    } else {
+        let header = format!("[{}]", table);
+        let mut text = text.to_string();
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        return Ok(format!("{}{}\n{}\n", text, header, line));
+    }
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Reads the name of the table that a line starts, if it's a `[table]` header.
+fn table_name(line: &str) -> Option<String> {
+    let name = strip_comment(line)
+        .trim()
+        .strip_prefix('[')?
+        .strip_suffix(']')?;
+    Some(name.split('.').map(str::trim).collect::<Vec<_>>().join("."))
+}
+
+/// Writes out a string in double quotes, escaping anything that can't go between them as it is.
+fn quote(text: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            _ if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Splits a file up into its `key = value` entries, keeping track of which table each one is in.
+pub fn parse_entries(text: &str) -> Result<Vec<Entry>, ConfigError> {
+    let mut entries = Vec::new();
+    let mut table = String::new();
+    for (i, line) in text.lines().enumerate() {
+        let invalid = |reason: String| ConfigError::Invalid {
+            line: i + 1,
+            reason,
+        };
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let name = header
+                .strip_suffix(']')
+                .ok_or_else(|| invalid("Expected ] at the end of the table name".to_string()))?
+                .trim();
+            if !name.split('.').all(|part| is_bare_key(part.trim())) {
+                return Err(invalid(format!("Invalid table name [{}]", name)));
+            }
+            table = name.split('.').map(str::trim).collect::<Vec<_>>().join(".");
+            continue;
+        }
+
+        let (key, rest) = parse_key(line).map_err(invalid)?;
+        let value = match rest.trim_start().strip_prefix('=') {
+            Some(value) => parse_value(value.trim()).map_err(invalid)?,
+            None => return Err(invalid(format!("Expected = after {}", key))),
+        };
+        entries.push(Entry {
+            table: table.clone(),
+            key,
+            value,
+            line: i + 1,
+        });
+    }
+    Ok(entries)
+}
+
+/// Cuts a `#` comment off the end of a line, as long as the `#` isn't inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), _) if c == q && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => (),
+        }
+        escaped = false;
+    }
+    line
+}
+
+/// Checks whether a key can be written without quotes around it.
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Parses the key at the start of a line, handing back whatever comes after it.
+fn parse_key(line: &str) -> Result<(String, &str), String> {
+    if line.starts_with(['"', '\'']) {
+        return parse_string(line);
+    }
+    let end = line
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(line.len());
+    match end {
+        0 => Err(format!("Expected a key, found {}", line)),
+        _ => Ok((line[..end].to_string(), &line[end..])),
+    }
+}
+
+/// Parses the value on the right hand side of an `=`, which needs to take up the rest of the line.
+fn parse_value(text: &str) -> Result<Value, String> {
+    match text {
+        "" => Err("Expected a value after =".to_string()),
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        _ if text.starts_with(['"', '\'']) => match parse_string(text)? {
+            (s, "") => Ok(Value::String(s)),
+            (_, rest) => Err(format!("Unexpected {} after the string", rest.trim())),
+        },
+        _ => text
+            .replace('_', "")
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| format!("Invalid value {} (strings need quotes around them)", text)),
+    }
+}
+
+/// Parses a string in either double quotes (with escapes) or single quotes (without), handing back whatever comes after it.
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let mut chars = text.char_indices();
+    let quote = match chars.next() {
+        Some((_, q @ ('"' | '\''))) => q,
+        _ => return Err("Expected a string".to_string()),
+    };
+
+    let mut s = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            _ if c == quote => return Ok((s, &text[i + 1..])),
+            '\\' if quote == '"' => {
+                let escaped = match chars.next().map(|(_, e)| e) {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some(u @ ('u' | 'U')) => {
+                        let digits = if u == 'u' { 4 } else { 8 };
+                        let hex: String = chars.by_ref().take(digits).map(|(_, h)| h).collect();
+                        u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .filter(|_| hex.len() == digits)
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| format!("Invalid escape \\{}{}", u, hex))?
+                    }
+                    Some(e) => return Err(format!("Invalid escape \\{}", e)),
+                    None => break,
+                };
+                s.push(escaped);
+            }
+            _ => s.push(c),
+        }
+    }
+    Err(format!("Missing closing {}", quote))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn invalid_line(text: &str) -> usize {
+        match Config::parse(text) {
+            Err(ConfigError::Invalid { line, .. }) => line,
+            other => panic!("Expected an error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_empty_file_is_default() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert_eq!(
+            Config::parse("# nothing here\n\n").unwrap(),
+            Config::default()
+        );
+    }
+
+    #[test]
+    fn test_setting_options() {
+        let config = Config::parse(
+            "tab_width = 4 # spaces\nexpand_tab = true\nshift_width = 2\nline_numbers = 'relative'\nmouse = false\n",
+        )
+        .unwrap();
+        assert_eq!(config.tab_width, 4);
+        assert!(config.expand_tab);
+        assert_eq!(config.shift_width, 2);
+        assert_eq!(config.line_numbers, LineNumbers::Relative);
+        assert!(!config.mouse);
+        assert!(!config.clipboard);
+        assert!(!config.backup);
+        assert_eq!(config.autosave, None);
+
+        let config = Config::parse(
+            "backup = true\nautosave = 30\nscroll_off = 3\nwrap = true\nmake_command = \"cargo build\"",
+        )
+        .unwrap();
+        assert!(config.backup);
+        assert_eq!(config.autosave, Some(30));
+        assert_eq!(config.scroll_off, 3);
+        assert!(config.wrap);
+        assert_eq!(config.make_command, "cargo build");
+
+        let config = Config::parse("theme = 'dusk'").unwrap();
+        assert_eq!(config.theme.as_deref(), Some("dusk"));
+
+        let config = Config::parse("list = true\nlist_chars = 'tab:>-,trail:~'").unwrap();
+        assert!(config.list);
+        assert_eq!(config.list_chars.tab, ('>', '-'));
+        assert_eq!(config.list_chars.trail, '~');
+        assert_eq!(config.list_chars.nbsp, '␣');
+
+        let config = Config::parse("fill_chars = 'eob: '").unwrap();
+        assert_eq!(config.fill_chars.eob, ' ');
+        assert!(Config::parse("fill_chars = 'eob:~~'").is_err());
+
+        let config = Config::parse("cursor_line = true\ncolor_column = 80").unwrap();
+        assert!(config.cursor_line);
+        assert_eq!(config.color_columns, [80]);
+        let config = Config::parse("color_column = '80,100'").unwrap();
+        assert_eq!(config.color_columns, [80, 100]);
+        assert!(Config::parse("color_column = 0").is_err());
+    }
+
+    #[test]
+    fn test_parsing_entries() {
+        let entries =
+            parse_entries("a = \"x#\\\"y\\u00e9\"\n[keys . edit]\n\"jk\" = 1_000\n").unwrap();
+        assert_eq!(
+            entries,
+            [
+                Entry {
+                    table: "".to_string(),
+                    key: "a".to_string(),
+                    value: Value::String("x#\"yé".to_string()),
+                    line: 1,
+                },
+                Entry {
+                    table: "keys.edit".to_string(),
+                    key: "jk".to_string(),
+                    value: Value::Integer(1000),
+                    line: 3,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_key_bindings() {
+        let config =
+            Config::parse("[keys.edit]\njk = \"<Esc>\"\n[keys.navigate]\n\"<C-h>\" = \"<C-w>h\"\n")
+                .unwrap();
+        assert_eq!(
+            config.bindings,
+            [
+                Binding::parse(MapMode::Edit, "jk", "<Esc>").unwrap(),
+                Binding::parse(MapMode::Navigate, "<C-h>", "<C-w>h").unwrap(),
+            ]
+        );
+        assert_eq!(invalid_line("[keys.command]\na = \"b\""), 2);
+        assert_eq!(invalid_line("[keys.edit]\njk = 1"), 2);
+        assert_eq!(invalid_line("[keys.edit]\njk = \"<Nope>\""), 2);
+    }
+
+    #[test]
+    fn test_adding_bindings() {
+        let add = |text: &str, keys: &str, replacement: &str| {
+            add_binding(text, keys, MapMode::Navigate, replacement).unwrap()
+        };
+        assert_eq!(
+            add("", "<M-q>", "A;<Esc>"),
+            "[keys.navigate]\n\"<M-q>\" = \"A;<Esc>\"\n"
+        );
+        assert_eq!(
+            add("wrap = false", "Q", "\"ayy"),
+            "wrap = false\n\n[keys.navigate]\n\"Q\" = \"\\\"ayy\"\n"
+        );
+        // It goes at the end of the table that's there already, not after whatever table comes next.
+        let text = "[keys.navigate]\n\"<C-h>\" = \"<C-w>h\"\n\n[lsp]\nrs = \"ra\"\n";
+        assert_eq!(
+            add(text, "<M-q>", "x"),
+            "[keys.navigate]\n\"<C-h>\" = \"<C-w>h\"\n\"<M-q>\" = \"x\"\n\n[lsp]\nrs = \"ra\"\n"
+        );
+        // Saving the same keys again replaces the binding, rather than leaving two of them.
+        let text = "[ keys.navigate ]  # Mine\n\"<M-q>\" = \"x\"\n";
+        assert_eq!(
+            add(text, "<M-q>", "y\\"),
+            "[ keys.navigate ]  # Mine\n\"<M-q>\" = \"y\\\\\"\n"
+        );
+
+        let config = Config::parse(&add("", "<M-q>", "\"a\\p\t")).unwrap();
+        assert_eq!(
+            config.bindings,
+            [Binding::parse(MapMode::Navigate, "<M-q>", "\"a\\p\t").unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_language_servers() {
+        let config =
+            Config::parse("[lsp]\nrs = \"rust-analyzer\"\npy = \"pylsp\"\nrs = \"ra-multiplex\"\n")
+                .unwrap();
+        assert_eq!(
+            config.language_servers,
+            [
+                ("py".to_string(), "pylsp".to_string()),
+                ("rs".to_string(), "ra-multiplex".to_string()),
+            ]
+        );
+        assert_eq!(invalid_line("[lsp]\nrs = true"), 2);
+    }
+
+    #[test]
+    fn test_comment_strings() {
+        let config = Config::parse("[comments]\nc = \"// %s\"\nsql = '#'\n").unwrap();
+        assert_eq!(
+            config.comment_strings,
+            [
+                ("c".to_string(), "// %s".to_string()),
+                ("sql".to_string(), "#".to_string()),
+            ]
+        );
+        assert_eq!(invalid_line("[comments]\nc = ' '"), 2);
+    }
+
+    #[test]
+    fn test_formatters() {
+        let config = Config::parse(
+            "format_on_save = true\n[formatters]\nrs = \"rustfmt\"\njs = 'prettier --stdin-filepath x.js'\n",
+        )
+        .unwrap();
+        assert!(config.format_on_save);
+        assert_eq!(
+            config.formatters,
+            [
+                ("rs".to_string(), "rustfmt".to_string()),
+                (
+                    "js".to_string(),
+                    "prettier --stdin-filepath x.js".to_string()
+                ),
+            ]
+        );
+        assert_eq!(invalid_line("[formatters]\nrs = 1"), 2);
+    }
+
+    #[test]
+    fn test_linters() {
+        let config =
+            Config::parse("[linters]\npy = \"flake8\"\nsh = 'shellcheck -f gcc'\n").unwrap();
+        assert_eq!(
+            config.linters,
+            [
+                ("py".to_string(), "flake8".to_string()),
+                ("sh".to_string(), "shellcheck -f gcc".to_string()),
+            ]
+        );
+        assert_eq!(invalid_line("[linters]\npy = true"), 2);
+    }
+
+    #[test]
+    fn test_hooks() {
+        let config = Config::parse(
+            "[hooks.BufWritePre]\nrs = '%s/\\s+$//'\n\"*\" = \"retab\"\n[hooks.ModeChanged]\n\"*\" = \"echo\"\n",
+        )
+        .unwrap();
+        let hooks: Vec<_> = config
+            .hooks
+            .iter()
+            .map(|h| (h.event, h.file_type.as_str(), h.command.as_str()))
+            .collect();
+        assert_eq!(
+            hooks,
+            [
+                (HookEvent::BufWritePre, "rs", "%s/\\s+$//"),
+                (HookEvent::BufWritePre, "*", "retab"),
+                (HookEvent::ModeChanged, "*", "echo"),
+            ]
+        );
+        assert_eq!(invalid_line("[hooks.BufLeave]\nrs = 'w'"), 2);
+    }
+
+    #[test]
+    fn test_layering_project_config() {
+        let project = "tab_width = 2\nmake_command = \"cargo build\"\n[formatters]\nrs = \"rustfmt\"\n[keys.navigate]\nQ = \":!x<CR>\"\n";
+        assert_eq!(
+            commands_in(project).unwrap(),
+            ["make_command", "formatters.rs", "keys.navigate.Q"]
+        );
+
+        // Without being trusted, only the options that don't run anything are taken from the project.
+        let mut config = Config::parse("tab_width = 8\nwrap = false\n").unwrap();
+        assert_eq!(
+            config.layer(project, false).unwrap(),
+            ["make_command", "formatters.rs", "keys.navigate.Q"]
+        );
+        assert_eq!((config.tab_width, config.wrap), (2, false));
+        assert_eq!(config.make_command, "make");
+        assert!(config.formatters.is_empty() && config.bindings.is_empty());
+
+        assert!(config.layer(project, true).unwrap().is_empty());
+        assert_eq!(config.make_command, "cargo build");
+        assert_eq!(
+            config.formatters,
+            [("rs".to_string(), "rustfmt".to_string())]
+        );
+        assert!(matches!(
+            config.layer("tab_width = 0", true),
+            Err(ConfigError::Invalid { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_finding_project_file() {
+        let dir = TempDir::new("project");
+        fs::create_dir_all(dir.join("src").join("bin")).unwrap();
+        assert_eq!(find_project_file(&dir.join("src").join("bin")), None);
+        fs::write(dir.join(PROJECT_FILE), "").unwrap();
+        assert_eq!(
+            find_project_file(&dir.join("src").join("bin")),
+            Some(dir.join(PROJECT_FILE))
+        );
+    }
+
+    #[test]
+    fn test_errors_point_at_the_line() {
+        assert_eq!(invalid_line("\n\ntab_width = \"4\""), 3);
+        assert_eq!(invalid_line("tab_width = 0"), 1);
+        assert_eq!(invalid_line("mouse = true\nmouse = yes"), 2);
+        assert_eq!(invalid_line("line_numbers = \"on\""), 1);
+        assert_eq!(invalid_line("colour = 1"), 1);
+        assert_eq!(invalid_line("[theme]\nx = 1"), 2);
+        assert_eq!(invalid_line("[unfinished"), 1);
+        assert_eq!(invalid_line("a = \"unfinished"), 1);
+        assert_eq!(invalid_line("a 1"), 1);
+    }
+
+    #[test]
+    fn test_error_messages() {
+        let error = Config::parse("tab_width = true").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 1: tab_width should be a number, not a boolean"
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Comparing two files line by line, for showing them side by side with the differences marked.
+use std::cmp;
+use std::ops::Range;
+
+use crate::git::LineChange;
+
+/// How many edits the comparison goes looking through before giving up and calling the rest of the files different,
+/// which keeps files that have next to nothing in common from taking forever (and a lot of memory) to compare.
+const MAX_EDITS: usize = 4000;
+
+/// A run of lines that differ between the two files, as the lines each file has there (either of which can be empty.)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// Finds the hunks that turn the `old` lines into the `new` ones, with as few lines added and taken out as possible.
+///
+/// This is Myers' algorithm, run on whatever is left once the lines that both files start and end with are taken off.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut hunks = Vec::new();
+    let mut last = (0, 0);
+    for (x, y) in matching_lines(a, b).into_iter().chain([(a.len(), b.len())]) {
+        if x > last.0 || y > last.1 {
+            hunks.push(Hunk {
+                old: prefix + last.0..prefix + x,
+                new: prefix + last.1..prefix + y,
+            });
+        }
+        last = (x + 1, y + 1);
+    }
+    hunks
+}
+
+/// Finds the pairs of lines that are kept the same going from `a` to `b`, in order.
+fn matching_lines<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = cmp::min(a.len() + b.len(), MAX_EDITS) as isize;
+    // `v[k]` is how far along `a` the furthest path that's made d edits has got on diagonal `k`, offset to keep it positive.
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    let mut edits = None;
+    'search: for d in 0..=max {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let down =
+                k == -d || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize]);
+            let mut x = match down {
+                true => v[(offset + k + 1) as usize],
+                false => v[(offset + k - 1) as usize] + 1,
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(offset + k) as usize] = x;
+            if x >= n && y >= m {
+                edits = Some(d);
+                break 'search;
+            }
+        }
+    }
+    // Files too different to compare are treated as having nothing in common.
+    let edits = match edits {
+        Some(edits) => edits,
+        None => return Vec::new(),
+    };
+
+    // Going back through the paths from the end picks out the diagonals, which are the lines that match.
+    let mut matches = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (0..=edits).rev() {
+        let v = &trace[d as usize];
+        let at = |k: isize| v[(k + d) as usize];
+        let k = x - y;
+        let previous_k = match k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            true => k + 1,
+            false => k - 1,
+        };
+        let (previous_x, previous_y) = match d {
+            0 => (0, 0),
+            _ => (at(previous_k), at(previous_k) - previous_k),
+        };
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        (x, y) = (previous_x, previous_y);
+    }
+    matches.reverse();
+    matches
+}
+
+/// What to mark in one of the files being compared.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Marks {
+    /// How each line that differs from the other file does, in order.
+    pub line_changes: Vec<(usize, LineChange)>,
+    /// The parts of the changed lines that differ, by line and then where they are in the line.
+    pub changed_parts: Vec<(usize, Range<usize>)>,
+}
+
+/// Works out what to mark in the `old` and `new` files from the hunks between them.
+///
+/// The lines on both sides of a hunk are paired up as changed versions of each other, and any left over are only in one
+/// of the files. A file with nothing on its side of a hunk gets a mark where the lines are missing.
+pub fn marks(hunks: &[Hunk], old: &[&str], new: &[&str]) -> (Marks, Marks) {
+    let (mut old_marks, mut new_marks) = (Marks::default(), Marks::default());
+    for hunk in hunks {
+        let paired = cmp::min(hunk.old.len(), hunk.new.len());
+        for (lines, marks) in [(&hunk.old, &mut old_marks), (&hunk.new, &mut new_marks)] {
+            if lines.is_empty() {
+                marks
+                    .line_changes
+                    .push((lines.start.saturating_sub(1), LineChange::Removed));
+            }
+            for (i, line) in lines.clone().enumerate() {
+                let change = match i < paired {
+                    true => LineChange::Modified,
+                    false => LineChange::Added,
+                };
+                marks.line_changes.push((line, change));
+            }
+        }
+        for (o, n) in hunk.old.clone().zip(hunk.new.clone()) {
+            let (old_part, new_part) = changed_parts(old[o], new[n]);
+            if !old_part.is_empty() {
+                old_marks.changed_parts.push((o, old_part));
+            }
+            if !new_part.is_empty() {
+                new_marks.changed_parts.push((n, new_part));
+            }
+        }
+    }
+    (old_marks, new_marks)
+}
+
+/// Finds the parts of two versions of a line that differ, leaving out what they start and end with in common.
+pub fn changed_parts(old: &str, new: &str) -> (Range<usize>, Range<usize>) {
+    let prefix: usize = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    let suffix: usize = old[prefix..]
+        .chars()
+        .rev()
+        .zip(new[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    (prefix..old.len() - suffix, prefix..new.len() - suffix)
+}
+
+/// Finds the line in the other file that sits alongside `line`, going by the hunks between them.
+///
+/// `line` is in the old file unless `from_new` is set, in which case it's in the new one.
+pub fn corresponding_line(hunks: &[Hunk], line: usize, from_new: bool) -> usize {
+    let sides = |hunk: &Hunk| match from_new {
+        true => (hunk.new.clone(), hunk.old.clone()),
+        false => (hunk.old.clone(), hunk.new.clone()),
+    };
+    let mut shift = 0isize;
+    for (this, other) in hunks.iter().map(sides) {
+        if line < this.start {
+            break;
+        }
+        if line < this.end {
+            let along = cmp::min(line - this.start, other.len().saturating_sub(1));
+            return other.start + along;
+        }
+        shift += other.len() as isize - this.len() as isize;
+    }
+    line.saturating_add_signed(shift)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hunk(old: Range<usize>, new: Range<usize>) -> Hunk {
+        Hunk { old, new }
+    }
+
+    #[test]
+    fn test_diffing_lines() {
+        let old = ["a", "b", "c", "d", "e"];
+        let new = ["a", "x", "c", "e", "f"];
+        assert_eq!(
+            diff(&old, &new),
+            [hunk(1..2, 1..2), hunk(3..4, 3..3), hunk(5..5, 4..5)]
+        );
+        assert_eq!(diff(&old, &old), []);
+        assert_eq!(diff(&[] as &[&str], &["a"]), [hunk(0..0, 0..1)]);
+        assert_eq!(diff(&["a", "b"], &["c"]), [hunk(0..2, 0..1)]);
+
+        // Lines that move are taken out in one place and added in another.
+        let old = ["x", "a", "b", "c"];
+        let new = ["a", "b", "x", "c"];
+        assert_eq!(diff(&old, &new), [hunk(0..1, 0..0), hunk(3..3, 2..3)]);
+    }
+
+    #[test]
+    fn test_changed_parts() {
+        assert_eq!(changed_parts("let x = 1;", "let y = 1;"), (4..5, 4..5));
+        assert_eq!(changed_parts("abc", "abXYc"), (2..2, 2..4));
+        assert_eq!(changed_parts("é1", "é2"), (2..3, 2..3));
+        assert_eq!(changed_parts("same", "same"), (4..4, 4..4));
+    }
+
+    #[test]
+    fn test_marking_differences() {
+        let old = ["a", "let x = 1;", "b", "gone", "c"];
+        let new = ["a", "let y = 1;", "more", "b", "c"];
+        let (old_marks, new_marks) = marks(&diff(&old, &new), &old, &new);
+        assert_eq!(
+            old_marks,
+            Marks {
+                line_changes: vec![(1, LineChange::Modified), (3, LineChange::Added)],
+                changed_parts: vec![(1, 4..5)],
+            }
+        );
+        assert_eq!(
+            new_marks.line_changes,
+            [
+                (1, LineChange::Modified),
+                (2, LineChange::Added),
+                (3, LineChange::Removed)
+            ]
+        );
+        assert_eq!(new_marks.changed_parts, [(1, 4..5)]);
+    }
+
+    #[test]
+    fn test_corresponding_lines() {
+        let hunks = [hunk(1..2, 1..4), hunk(5..7, 8..8)];
+        assert_eq!(corresponding_line(&hunks, 0, false), 0);
+        assert_eq!(corresponding_line(&hunks, 1, false), 1);
+        assert_eq!(corresponding_line(&hunks, 3, false), 5);
+        assert_eq!(corresponding_line(&hunks, 6, false), 8);
+        assert_eq!(corresponding_line(&hunks, 9, false), 9);
+        assert_eq!(corresponding_line(&hunks, 3, true), 1);
+        assert_eq!(corresponding_line(&hunks, 10, true), 10);
+    }
+}
@@ -0,0 +1,22 @@
+//! Locations on the filesystem where Té keeps things, following the XDG base directory specification.
+use std::env;
+use std::path::PathBuf;
+
+/// Finds an XDG base directory from its environment variable, falling back to a path under the home directory.
+fn xdg_dir(variable: &str, fallback: &str) -> Option<PathBuf> {
+    // The spec says that relative paths should be ignored, as should empty ones.
+    match env::var_os(variable).map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => Some(dir),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)),
+    }
+}
+
+/// The directory that the user's configuration file goes in.
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config").map(|d| d.join("te"))
+}
+
+/// The directory to keep state that should persist between runs, but isn't worth backing up.
+pub fn state_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state").map(|d| d.join("te"))
+}
@@ -1,567 +1,8521 @@
 use std::cmp;
-use std::fs::File;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Display;
 use std::io::{Read, Write};
+use std::iter;
+use std::mem;
+use std::ops::{Range, RangeInclusive};
 use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
+use std::time::{Duration, Instant, SystemTime};
 
-use crossterm::event::{Event, KeyCode, KeyEvent};
+use crossterm::cursor::CursorShape;
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use crossterm::style;
 use crossterm::tty::IsTty;
 use crossterm::{cursor, event, queue, terminal};
 
+use crate::buffer::{Buffer, CursorMovement, FileFormat, Position};
+use crate::buffers::BufferManager;
+use crate::clipboard;
+use crate::command::{self, Address, Command, LineRange};
+use crate::comment::{self, CommentString};
+use crate::completion::Completion;
+use crate::config;
+use crate::diff::{self, Hunk};
 use crate::errors::{EditorError, Result};
+use crate::git;
+use crate::history::History;
+use crate::hooks::{Hook, HookEvent};
+use crate::keymap::{self, Binding, Keymap, MapMode};
+use crate::line_input::{Input, LineInput};
+use crate::lint::LintRun;
+use crate::lsp::{self, LanguageServer, Notice, Request};
+use crate::marks::Marks;
+use crate::motions::{self, CharSearch, Motion, MotionKind};
+use crate::numbers;
+use crate::quickfix::QuickfixList;
+use crate::range::TextRange;
+use crate::regex::Regex;
+use crate::registers::{Register, Registers};
+use crate::screen::Screen;
+use crate::search;
+use crate::session::{Session, SessionWindow, DEFAULT_SESSION_FILE};
+use crate::sort;
+use crate::state::{self, State};
+use crate::substitute::Substitution;
+use crate::surround;
+use crate::theme::Theme;
+use crate::timers::{TimerEvent, Timers};
+use crate::unicode;
+use crate::view::{self, FillChars, LineNumbers, ListChars, View};
+use crate::windows::{Direction, Rect, Split, Window, WindowManager};
+use crate::word_completion::WordCompletion;
+
+/// How many macros can be playing inside each other at once, which keeps one that plays itself from going on forever.
+const MAX_MACRO_DEPTH: usize = 100;
+
+/// The key that starts a window command when it's pressed with Ctrl, like `Ctrl-W` `s` to split the window.
+const WINDOW_KEY: char = '\x17';
+
+/// How many lines the display scrolls by for each notch of the mouse wheel.
+const MOUSE_SCROLL_LINES: isize = 3;
+
+/// How long to wait for the rest of a key mapping before using the keys that have been typed as they are.
+const KEY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often to check whether something else has changed the file being edited.
+const FILE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often to pick up whatever the language servers have sent.
+const LANGUAGE_SERVER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often to check whether the linters have finished.
+const LINT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How many of the words that `Ctrl-N` and `Ctrl-P` go through are listed at once.
+const WORD_POPUP_ROWS: usize = 8;
+
+/// How many characters are looked through for the bracket that pairs up with the one by the cursor, so that a bracket
+/// with no partner in a big file doesn't slow down every key press.
+const BRACKET_SCAN_LIMIT: usize = 20_000;
+
+/// How many of the recent files are listed on the start screen.
+const START_SCREEN_FILES: usize = 5;
+
+/// How long a message stays at the bottom of the display.
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the user has to stop typing for before unsaved changes are copied into the recovery file.
+const RECOVERY_IDLE_TIMEOUT: Duration = Duration::from_secs(4);
 
 /// The different modes that Té currently provides.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 enum EditorMode {
     /// Navigation mode provides movement through the document.
+    #[default]
     Navigate,
     /// Edit mode allows for insertion and removal of text in the document (like Vim's insert mode.)
     Edit,
+    /// Replace mode types over the text that's already there, rather than pushing it along (like Vim's `R`.)
+    Replace,
+    /// Command mode takes a command typed in at the prompt on the bottom row (like Vim's `:` commands.)
+    Command,
+    /// Visual mode selects the text between the cursor and the `anchor` index, so that an operator can be applied to it.
+    Visual { anchor: usize, kind: VisualKind },
+    /// Search mode takes a pattern typed in at the prompt, moving the cursor (from the `origin` index) to the first match as it's typed.
+    Search { origin: usize },
+    /// Confirm mode asks the user whether each match of a `:s` command with the `c` flag should be replaced.
+    Confirm,
+    /// Reload mode asks the user what to do about something else having changed the file: read it in again, write over it, or keep the buffer as it is.
+    Reload,
+    /// Recover mode asks the user what to do with a swap file that was left behind by an editor that never got to exit.
+    Recover,
+    /// Save As mode takes the file name to write a buffer into when it doesn't have one yet, quitting afterwards if `quit` is set.
+    SaveAs { quit: bool },
+    /// Tag mode takes the tag (and any attributes) that `ys` or `cs` should surround some text with, at a `<` prompt.
+    Tag,
 }
 
-impl Default for EditorMode {
-    fn default() -> Self {
-        EditorMode::Navigate
-    }
-}
-
-#[derive(Debug)]
-struct DisplaySize {
-    columns: u16,
-    rows: u16,
+/// How much of the text between the anchor and the cursor Visual mode selects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VisualKind {
+    /// Every character from one to the other (like Vim's `v`.)
+    Characters,
+    /// The whole of every line from one to the other (like `V`.)
+    Lines,
+    /// The rectangle of cells with one in each corner (like `Ctrl-V`.)
+    Block,
 }
 
-impl DisplaySize {
-    fn new(columns: u16, rows: u16) -> Self {
-        Self { columns, rows }
-    }
+/// The rectangle selected in Visual block mode, as the lines it's on and the cells of the display it covers in each of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Block {
+    lines: RangeInclusive<usize>,
+    cells: Range<usize>,
+    /// Whether the block was stretched to the end of every line by `$`, however long each of them is.
+    to_end: bool,
 }
 
-impl Default for DisplaySize {
-    fn default() -> Self {
-        DisplaySize::new(80, 24)
-    }
+/// Text being typed into the first line of a block by `I`, `A` or `c`, which goes into the rest of its `lines` on leaving Edit mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BlockInsert {
+    /// Where the typing started on the first line.
+    start: usize,
+    /// The lines that the text gets copied onto.
+    lines: RangeInclusive<usize>,
+    /// The cell of the display that the text goes in at.
+    cell: usize,
+    /// Whether lines too short to reach the cell get padded out with spaces, rather than being left alone.
+    pad: bool,
+    /// Whether the text goes onto the end of each line instead, after appending to a block that reaches all their ends.
+    to_end: bool,
 }
 
-#[derive(Debug, Default, PartialEq, Eq)]
-struct DisplayPosition {
-    column: usize,
-    row: usize,
+/// Why the editor stopped running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exit {
+    /// The user quit, so the editor is done with.
+    Quit,
+    /// The user pressed Ctrl-Z, so the terminal should be handed back to the shell until the editor is resumed and run again.
+    Suspend,
+    /// The user ran a command with `:!`, so the terminal should be handed over to it until it's done.
+    Shell(String),
 }
 
-impl DisplayPosition {
-    fn new(column: usize, row: usize) -> Self {
-        Self { column, row }
-    }
+/// The things that can be done to the text selected in Visual mode, or to the text that a motion moves over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    /// Remove the text, keeping a copy of it in a register.
+    Delete,
+    /// Copy the text into a register.
+    Yank,
+    /// Indent the lines that the text is on by another `shift_width`.
+    ShiftRight,
+    /// Take one `shift_width` off the indent of the lines that the text is on.
+    ShiftLeft,
+    /// Pipe the lines that the text is on through a shell command, which is typed in at the prompt.
+    Filter,
+    /// Comment out the lines that the text is on, or take the comments off them if they're all commented out already.
+    Comment,
+    /// Put brackets, quotes or a tag around the text, which are picked with the next key (like vim-surround's `ys`.)
+    Surround,
+    /// Remove the text like `Delete`, then start Edit mode in its place.
+    Change,
 }
 
-#[derive(Debug, Default, PartialEq, Eq)]
-struct CursorPosition {
-    column: u16,
-    row: u16,
+/// Two buffers being compared side by side, along with the differences that were found between them.
+#[derive(Debug)]
+struct Comparison {
+    /// Where the buffers sit in the buffer list.
+    old: usize,
+    new: usize,
+    /// The revisions of the buffers as of the last comparison, or `None` when they need comparing again.
+    revisions: Option<(u64, u64)>,
+    hunks: Vec<Hunk>,
 }
 
-impl CursorPosition {
-    fn new(column: u16, row: u16) -> Self {
-        Self { column, row }
-    }
+/// A surround command that's waiting for a key to say which brackets, quotes or tag it's about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Surround {
+    /// `ys` (or `S` in Visual mode) waiting for what to put around the text in the range.
+    Add(Range<usize>),
+    /// `ds` waiting for what to take away from around the cursor.
+    Delete,
+    /// `cs` waiting for what to change around the cursor.
+    Change,
+    /// `cs` waiting for what to change the delimiters it found into.
+    ChangeTo(Range<usize>, Range<usize>),
 }
 
-/// The different movements that can can be handled in `Navigation` mode.
-#[derive(Debug)]
-enum CursorMovement {
-    Left,
-    Right,
-    Up,
-    Down,
+/// The ways that a key can move the cursor in Navigate or Visual mode, which an operator can be applied over too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Movement {
+    /// A step of one character or line (like Vim's `h`, `j`, `k` and `l`.)
+    Step(CursorMovement),
+    Motion(Motion),
+    /// A jump to a mark, either to the exact place (like Vim's `` `a ``) or to the start of its line (like `'a`.)
+    Mark {
+        to: Position,
+        linewise: bool,
+    },
 }
 
 /// The core class of the application.
-/// This provides both the text buffer and the rendering of the buffer in the terminal.
-/// These functions should really be separated at some point, but it was quick to implement in this fashion.
+/// This ties a `Buffer` to the `View` of it in the terminal, and turns the user's key presses into changes to both.
 pub struct Editor {
-    /// The path to the file that this buffer should be written into.
-    path: Option<PathBuf>,
-    /// The contents of the buffer.
-    contents: String,
-    /// The current position of the cursor on the display.
-    cursor: CursorPosition,
-    /// The size of the display.
-    display_size: DisplaySize,
-    /// The position of the buffer in the display.
-    display_position: DisplayPosition,
+    /// The document being edited.
+    buffer: Buffer,
+    /// All of the other documents that are open.
+    buffers: BufferManager,
+    /// The files that were given on the command line, which `:n` and `:prev` move through.
+    args: Vec<PathBuf>,
+    /// The file in `args` that was moved to most recently.
+    arg_index: usize,
+    /// The part of the document that is visible in the focused window.
+    view: View,
+    /// The other windows that the display is split between.
+    windows: WindowManager,
+    /// The part of the display that the windows go in, which is all of it apart from the status line.
+    area: Rect,
+    /// What has already been drawn onto the terminal.
+    screen: Screen,
+    /// The colours that everything is drawn in.
+    theme: Theme,
+    /// The shape that the terminal's cursor was last set to, if it's known.
+    cursor_shape: Option<CursorShape>,
+    /// The title that the terminal was last given, if it's known.
+    title: Option<String>,
+    /// What Replace mode has typed over so far, so that Backspace can put it back, or `None` where it added to the end of a line.
+    replaced: Vec<Option<String>>,
+    /// The text being typed into a Visual block, if there is one.
+    block_insert: Option<BlockInsert>,
+    /// Whether the start screen is shown over the empty buffer, which it is from starting up without a file until a key is pressed.
+    start_screen: bool,
     /// The current mode that the editor is in.
     mode: EditorMode,
+    /// Whether the editor is acting as a read-only pager.
+    pager: bool,
+    /// The user's key mappings, which every key press goes through before it's handled.
+    keymap: Keymap,
+    /// Whether pressing Tab in Edit mode types spaces up to the next tab stop, rather than a tab.
+    expand_tab: bool,
+    /// How many cells `>>` and `<<` indent lines by, or 0 to go by the tab width.
+    shift_width: usize,
+    /// Whether the old version of a file is kept in `file~` when the buffer is written over it.
+    backup: bool,
+    /// Whether the buffer should be normalized into Unicode NFC before it gets written.
+    normalize_on_save: bool,
+    /// A message to show the user on the bottom row of the display.
+    message: Option<String>,
+    /// Whether the message is telling the user that something went wrong, so should stand out.
+    message_is_error: bool,
+    /// The command, pattern or file name being typed in at the prompt while in Command, Search or Save As mode.
+    command_line: LineInput,
+    /// The lines that have been typed in at the `:` prompt, for Up and Down to bring back.
+    command_history: History,
+    /// The patterns that have been typed in at the `/` prompt.
+    search_history: History,
+    /// The errors from the last `:make` or `:cfile`, for `:cn` and `:cp` to step through.
+    quickfix: QuickfixList,
+    /// The command from `:!` that's waiting for the terminal to be handed over to it.
+    shell_command: Option<String>,
+    /// The shell command that `:make` runs.
+    make_command: String,
+    /// The commands that start a language server, by the file extension that they're for.
+    language_servers: Vec<(String, String)>,
+    /// The language servers that are running, by the command that started them.
+    servers: Vec<(String, LanguageServer)>,
+    /// The surround command waiting for the key (or tag) that says what it's about.
+    surround: Option<Surround>,
+    /// The buffers being compared side by side, if there are any.
+    comparison: Option<Comparison>,
+    /// How comments are written for each file extension, ahead of the usual ways.
+    comment_strings: Vec<(String, String)>,
+    /// The shell commands that tidy up each type of file, which get the buffer on stdin and print it back out.
+    formatters: Vec<(String, String)>,
+    /// Whether `:w` runs the buffer through its formatter first.
+    format_on_save: bool,
+    /// The shell commands that check each type of file for problems, which get the path of the file written.
+    linters: Vec<(String, String)>,
+    /// The linters that are still running, which there's at most one of for each file.
+    lint_runs: Vec<LintRun>,
+    /// The commands to run when things happen in the editor.
+    hooks: Vec<Hook>,
+    /// Whether the hooks are being run, so that the commands they run don't set off any more.
+    running_hooks: bool,
+    /// The ways of finishing off the line at the `:` prompt that Tab is cycling through.
+    completion: Option<Completion>,
+    /// The words from the buffers that `Ctrl-N` and `Ctrl-P` are going through in Edit mode.
+    word_completion: Option<WordCompletion>,
+    /// The file that the histories, registers and the like are kept in between runs.
+    state_file: Option<PathBuf>,
+    /// The configuration file, which macros are saved into as key bindings with `:savemacro`.
+    config_file: Option<PathBuf>,
+    /// The files that were edited before, newest first, and where their cursors were left.
+    recent_files: Vec<(PathBuf, Position)>,
+    /// The marks `A` to `Z`, which can be jumped to from any buffer.
+    global_marks: BTreeMap<char, (PathBuf, Position)>,
+    /// The pattern that was last searched for, which `n` and `N` look for again.
+    last_search: Option<String>,
+    /// Whether the matches of the last search are highlighted, until `:noh` or `Esc` turns them off.
+    highlight_search: bool,
+    /// The last character searched for with `f`, `t`, `F` or `T`, for `;` and `,` to look for again.
+    last_find: Option<CharSearch>,
+    /// The first key of a sequence (like `gg`) that is waiting for the rest of it.
+    pending_key: Option<char>,
+    /// The operator waiting for a motion to apply over (like the `d` in `dw`), along with the count typed in before it.
+    operator: Option<(Operator, Option<usize>)>,
+    /// The count typed in before a command (like the 3 in `3w`), which repeats it that many times.
+    count: Option<usize>,
+    /// Text that has been yanked or deleted.
+    registers: Registers,
+    /// Whether putting from the clipboard register reads the system clipboard first, rather than only what was copied here.
+    read_clipboard: bool,
+    /// The register picked with `"` for the next yank, delete or paste to use.
+    register: Option<char>,
+    /// The register that keys are being recorded into with `q`, along with the keys so far.
+    recording: Option<(char, Vec<KeyEvent>)>,
+    /// The register that a macro was last played from, for `@@` to play again.
+    last_macro: Option<char>,
+    /// How many macros are playing inside each other right now.
+    macro_depth: usize,
+    /// The keys of the last change made to the buffer, for `.` to repeat.
+    last_change: Option<Vec<KeyEvent>>,
+    /// The keys of the command being typed in, along with the revision of the buffer before it, in case it turns out to be a change.
+    pending_change: Option<(Vec<KeyEvent>, u64)>,
+    /// Whether `.` is repeating the last change right now, so its keys shouldn't be remembered all over again.
+    repeating: bool,
+    /// The `:s` command that is waiting on the user in Confirm mode.
+    substitution: Option<Substitution>,
+    /// The directory to keep a copy of unsaved changes in, in case of a crash.
+    recovery_dir: Option<PathBuf>,
+    /// The file that unsaved changes are being copied into, once there have been some.
+    recovery_path: Option<PathBuf>,
+    /// The swap files that still hold unsaved changes to buffers that have been switched away from.
+    swap_files: Vec<PathBuf>,
+    /// Things that are waiting to happen at some point in the future.
+    timers: Timers,
 }
 
 impl Editor {
-    /// Creates a new `Editor` instance with the supplied string copied into its buffer.
-    pub fn new(s: &str) -> Self {
+    /// Creates a new `Editor` instance around a buffer.
+    pub fn new(buffer: Buffer) -> Self {
         Self {
-            path: None,
-            contents: s.to_string(),
-            cursor: Default::default(),
-            display_size: Default::default(),
-            display_position: Default::default(),
+            buffer,
+            buffers: Default::default(),
+            args: Vec::new(),
+            arg_index: 0,
+            view: Default::default(),
+            windows: Default::default(),
+            area: Rect::new(0, 0, 80, 24),
+            screen: Default::default(),
+            theme: Theme::default(),
+            cursor_shape: None,
+            title: None,
+            replaced: Vec::new(),
+            block_insert: None,
+            start_screen: false,
             mode: Default::default(),
+            pager: false,
+            keymap: Default::default(),
+            expand_tab: false,
+            shift_width: 0,
+            backup: false,
+            normalize_on_save: false,
+            message: None,
+            message_is_error: false,
+            command_line: Default::default(),
+            command_history: Default::default(),
+            search_history: Default::default(),
+            quickfix: Default::default(),
+            shell_command: None,
+            make_command: "make".to_string(),
+            language_servers: Vec::new(),
+            servers: Vec::new(),
+            surround: None,
+            comparison: None,
+            comment_strings: Vec::new(),
+            formatters: Vec::new(),
+            format_on_save: false,
+            linters: Vec::new(),
+            lint_runs: Vec::new(),
+            hooks: Vec::new(),
+            running_hooks: false,
+            completion: None,
+            word_completion: None,
+            state_file: None,
+            config_file: None,
+            recent_files: Vec::new(),
+            global_marks: BTreeMap::new(),
+            last_search: None,
+            highlight_search: false,
+            last_find: None,
+            pending_key: None,
+            operator: None,
+            count: None,
+            registers: Default::default(),
+            read_clipboard: false,
+            register: None,
+            recording: None,
+            last_macro: None,
+            macro_depth: 0,
+            last_change: None,
+            pending_change: None,
+            repeating: false,
+            substitution: None,
+            recovery_dir: None,
+            recovery_path: None,
+            swap_files: Vec::new(),
+            timers: Default::default(),
         }
     }
 
-    /// Wraps a new `Editor` instance around a path on the filesystem.
-    ///
-    /// If the file exists it will be read into the buffer, if it does not then an empty buffer will be initialised.
-    /// The directory that the file is in must exist, because we don't currently have a way of displaying the error we would receive when we try to write the file.
+    /// Wraps a new `Editor` instance around a path on the filesystem, see `Buffer::from_path`.
     pub fn from_path<P>(path: P) -> Result<Self>
     where
         P: AsRef<Path>,
     {
-        let mut contents = String::new();
-        let mut file = PathBuf::from("./");
-        // If `path` is absolute then this will flush out the `"./"` currently in the buffer, which is pretty neat!
-        file.push(&path);
+        Buffer::from_path(path).map(Self::new)
+    }
 
-        match file.parent().map(|d| d.exists()) {
-            Some(true) => (),
-            Some(false) => {
-                return Err(EditorError::DirectoryDoesNotExist(
-                    file.parent().unwrap().to_path_buf(),
-                ))
+    /// Opens each of `paths` in a buffer of its own, starting on the first one.
+    ///
+    /// They make up the argument list too, so that `:n` and `:prev` can move through them in order.
+    /// Without any paths there's just an empty buffer, which the user is asked to pick a file name for when it's written.
+    pub fn from_paths(paths: Vec<PathBuf>) -> Result<Self> {
+        let mut editor = match paths.first() {
+            Some(first) => Self::from_path(first)?,
+            None => Self::new(Buffer::new("")),
+        };
+        for path in paths.iter().skip(1) {
+            if editor.buffers.find(&editor.buffer, path).is_none() {
+                let buffer = Buffer::from_path(path)?;
+                editor.buffers.add(&mut editor.buffer, buffer);
             }
-            None => return Err(EditorError::CannotOpenRoot),
         }
+        editor.buffers.switch(&mut editor.buffer, 0);
+        editor.args = paths;
+        editor.refresh_line_changes();
+        Ok(editor)
+    }
+
+    /// Creates a new `Editor` instance from everything that can be read out of `reader`, see `Buffer::from_reader`.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        Buffer::from_reader(reader).map(Self::new)
+    }
 
-        if file.exists() {
-            let mut file = File::open(&path).map_err(|e| EditorError::FileIo(e))?;
-            file.read_to_string(&mut contents)
-                .map_err(|e| EditorError::FileIo(e))?;
+    /// Turns the editor into a read-only pager, with `less`-like keys layered over the navigation commands.
+    pub fn set_pager(&mut self, pager: bool) {
+        self.pager = pager;
+    }
+
+    /// Asks for the buffer to be normalized into Unicode NFC every time it is written.
+    pub fn set_normalize_on_save(&mut self, normalize: bool) {
+        self.normalize_on_save = normalize;
+    }
+
+    /// Normalizes the buffer into Unicode NFC, letting the user know if that changed anything.
+    ///
+    /// A large buffer is left alone, since going over every line of it would hold everything up.
+    pub fn normalize(&mut self) {
+        if self.buffer.is_large() {
+            return;
+        }
+        let changed = self.buffer.normalize();
+        if changed > 0 {
+            self.show_message(format!(
+                "Normalized {} line{} to NFC",
+                changed,
+                if changed == 1 { "" } else { "s" }
+            ));
         }
+    }
 
-        Ok(Self::new(&contents))
+    /// Shows a message at the bottom of the display for a little while.
+    fn show_message(&mut self, message: String) {
+        self.message = Some(message);
+        self.message_is_error = false;
+        self.timers.cancel(TimerEvent::ClearMessage);
+        self.timers
+            .after(Instant::now(), MESSAGE_TIMEOUT, TimerEvent::ClearMessage);
     }
 
-    /// Determines the length of the row the cursor currently sits on.
-    fn row_length(&self) -> usize {
-        self.contents
-            .lines()
-            .nth(self.display_position.row + self.cursor.row as usize)
-            .map(|s| s.len())
-            .unwrap_or(0)
+    /// Tells the user about something that went wrong, in place of the status line like any other message.
+    fn show_error<E: Display>(&mut self, error: E) {
+        self.show_message(error.to_string());
+        self.message_is_error = true;
     }
 
-    /// Determines the position of the cursor in the `contents` buffer.
-    fn cursor_index(&self) -> usize {
-        self.contents
-            .lines()
-            .take(self.cursor.row as usize + self.display_position.row)
-            .map(|s| s.len() + 1)
-            .sum::<usize>()
-            + self.cursor.column as usize
+    /// Tells the user about a problem from before the editor started, like part of a configuration file being left out.
+    pub fn show_warning(&mut self, warning: String) {
+        self.show_error(warning);
     }
 
-    fn move_cursor(&mut self, direction: CursorMovement) {
-        match direction {
-            CursorMovement::Left => match self.cursor.column.checked_sub(1) {
-                Some(c) => self.cursor.column = c,
-                None => {
-                    self.display_position.column = self.display_position.column.saturating_sub(1)
-                }
-            },
-            CursorMovement::Right => {
-                let last_column = match self.mode {
-                    EditorMode::Edit => self.row_length(),
-                    _ => self.row_length().saturating_sub(1),
-                };
+    /// Sends yanked and deleted text to the system clipboard when no other register is picked, rather than only for `"+`.
+    pub fn set_use_clipboard(&mut self, use_clipboard: bool) {
+        self.registers.set_use_clipboard(use_clipboard);
+    }
 
-                let can_move_right =
-                    self.display_position.column + (self.cursor.column as usize) < last_column;
-                let at_right_of_display = self.cursor.column == self.display_size.columns - 1;
+    /// Reads the system clipboard when putting from the `+` register, so that text copied in other programs can be pasted.
+    pub fn set_read_clipboard(&mut self, read_clipboard: bool) {
+        self.read_clipboard = read_clipboard;
+    }
+
+    /// Reads in the histories, registers, global marks and recent files left behind by an earlier run, and keeps them in
+    /// `path` from now on.
+    ///
+    /// Files that are already open go back to where their cursors were left last time.
+    pub fn set_state_file(&mut self, path: PathBuf) {
+        let state = std::fs::read_to_string(&path)
+            .map(|text| State::parse(&text))
+            .unwrap_or_default();
+        for entry in &state.command_history {
+            self.command_history.add(entry);
+        }
+        for entry in &state.search_history {
+            self.search_history.add(entry);
+        }
+        for (name, register) in state.registers {
+            self.registers.restore(name, register);
+        }
+        self.global_marks = state
+            .marks
+            .into_iter()
+            .map(|(name, path, position)| (name, (path, position)))
+            .collect();
+        self.recent_files = state.files;
 
-                match (can_move_right, at_right_of_display) {
-                    (true, true) => self.display_position.column = self.display_position.column + 1,
-                    (true, _) => self.cursor.column = self.cursor.column + 1,
-                    (_, _) => (),
+        for i in 0..self.buffers.len() {
+            if let Some(buffer) = self.buffers.get_mut(&mut self.buffer, i) {
+                if buffer.cursor() == Position::new(0, 0) {
+                    restore_cursor(buffer, &self.recent_files);
                 }
             }
-            CursorMovement::Up => {
-                match self.cursor.row.checked_sub(1) {
-                    Some(r) => self.cursor.row = r,
-                    None => self.display_position.row = self.display_position.row.saturating_sub(1),
-                }
+        }
+        self.view.center_on(self.cursor_cell());
+        self.state_file = Some(path);
+    }
 
-                self.display_position.column =
-                    cmp::min(self.display_position.column, self.row_length());
+    /// Writes the histories, registers, global marks and recent files out to the state file, if there is one, for the next
+    /// run to pick up.
+    pub fn write_state(&self) -> Result<()> {
+        let path = match &self.state_file {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let state = State {
+            command_history: self.command_history.entries().to_vec(),
+            search_history: self.search_history.entries().to_vec(),
+            files: self.recent_files(),
+            marks: self
+                .global_marks
+                .iter()
+                .map(|(&name, (path, position))| (name, path.clone(), *position))
+                .collect(),
+            registers: self.registers.saved(),
+        };
+        // Another editor might have written the file since this one read it, and what it saved shouldn't be lost.
+        let saved = std::fs::read_to_string(path)
+            .map(|text| State::parse(&text))
+            .unwrap_or_default();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(EditorError::FileIo)?;
+        }
+        state.merge(saved).write(path).map_err(EditorError::FileIo)
+    }
 
-                self.cursor.column = cmp::min(
-                    self.cursor.column,
-                    (self.row_length() - self.display_position.column)
-                        .try_into()
-                        .unwrap_or(u16::MAX),
-                );
-            }
-            CursorMovement::Down => {
-                let num_lines = self
-                    .contents
-                    .lines()
-                    .skip(self.display_position.row)
-                    .count();
-                let can_move_down = self.cursor.row + 1 < num_lines.try_into().unwrap_or(u16::MAX);
-                let at_bottom_of_display = self.display_size.rows == (self.cursor.row + 1).into();
-
-                match (can_move_down, at_bottom_of_display) {
-                    (true, true) => self.display_position.row = self.display_position.row + 1,
-                    (true, _) => self.cursor.row = self.cursor.row + 1,
-                    (_, _) => (),
-                }
-
-                self.display_position.column =
-                    cmp::min(self.display_position.column, self.row_length());
-
-                self.cursor.column = cmp::min(
-                    self.cursor.column,
-                    (self.row_length() - self.display_position.column)
-                        .try_into()
-                        .unwrap_or(u16::MAX),
-                );
+    /// The files that have been edited, newest first, with the ones that are open now before the rest.
+    fn recent_files(&self) -> Vec<(PathBuf, Position)> {
+        let open = self.buffers.iter(&self.buffer).filter_map(|buffer| {
+            let path = lsp::absolute_path(buffer.path()?);
+            Some((path, buffer.cursor()))
+        });
+        let mut files: Vec<(PathBuf, Position)> = Vec::new();
+        for (path, cursor) in open.chain(self.recent_files.iter().cloned()) {
+            if !files.iter().any(|(p, _)| *p == path) {
+                files.push((path, cursor));
             }
         }
+        files.truncate(state::MAX_FILES);
+        files
     }
 
-    /// Inserts a character into the `contents` buffer at the cursor position.
-    fn insert(&mut self, c: char) {
-        self.contents.insert(self.cursor_index(), c);
+    /// Remembers where the cursor was left in the current buffer's file, for when it's opened again.
+    fn remember_file(&mut self) {
+        let path = match self.buffer.path() {
+            Some(path) => lsp::absolute_path(path),
+            None => return,
+        };
+        self.recent_files.retain(|(p, _)| *p != path);
+        self.recent_files.insert(0, (path, self.buffer.cursor()));
+        self.recent_files.truncate(state::MAX_FILES);
+    }
 
-        if c == '\n' {
-            self.cursor.column = 0;
-            self.cursor.row = self.cursor.row + 1;
-        } else {
-            self.cursor.column = self.cursor.column + 1;
+    /// Shows the files that have been edited recently, like Vim's `:oldfiles` (but squeezed onto the bottom row.)
+    fn list_old_files(&mut self) {
+        let list = self
+            .recent_files()
+            .iter()
+            .enumerate()
+            .map(|(i, (path, _))| format!("{} \"{}\"", i + 1, lsp::relative_path(path).display()))
+            .collect::<Vec<_>>();
+        match list.is_empty() {
+            true => self.show_message("No old files".to_string()),
+            false => self.show_message(list.join("  ")),
         }
     }
 
-    /// Removes a character from the `contents` buffer at the cursor position.
-    fn remove(&mut self) {
-        if let Some(idx) = self.cursor_index().checked_sub(1) {
-            let current_length = self.row_length();
-            match self.contents.remove(idx) {
-                '\n' => {
-                    self.cursor.row = self.cursor.row - 1;
-                    self.cursor.column = (self.row_length() - current_length)
-                        .try_into()
-                        .unwrap_or(u16::MAX);
-                }
-                _ => self.cursor.column = self.cursor.column - 1,
+    /// Sets one of the marks `A` to `Z` to where the cursor is in the current file.
+    fn set_global_mark(&mut self, name: char) {
+        match self.buffer.path() {
+            Some(path) => {
+                let mark = (lsp::absolute_path(path), self.buffer.cursor());
+                self.global_marks.insert(name, mark);
             }
+            None => self.show_error(EditorError::UnnamedBuffer(self.buffers.current() + 1)),
         }
     }
 
-    pub fn set_display_columns(&mut self, c: u16) {
-        self.display_size.columns = c;
-        self.display_position.column = cmp::min(self.display_position.column, c.into());
+    /// Jumps to one of the marks `A` to `Z`, opening its file if it's a different one.
+    ///
+    /// An operator can only reach as far as a mark in the same file.
+    fn jump_to_global_mark(&mut self, name: char, linewise: bool) {
+        let (path, to) = match self.global_marks.get(&name) {
+            Some(mark) => mark.clone(),
+            None => {
+                self.count = None;
+                self.operator = None;
+                self.show_error("Mark not set");
+                return;
+            }
+        };
+        let same_file = self.buffer.path().map(lsp::absolute_path).as_ref() == Some(&path);
+        if !same_file && self.operator.take().is_some() {
+            self.count = None;
+            self.show_error("Mark is in another file");
+            return;
+        }
+        if !same_file {
+            self.buffer.push_jump();
+            if let Err(e) = self.edit(path, false) {
+                self.show_error(e);
+                return;
+            }
+        }
+        self.make_movement(Movement::Mark { to, linewise });
     }
 
-    pub fn set_display_rows(&mut self, r: u16) {
-        self.display_size.rows = r;
-        self.display_position.row = cmp::min(self.display_position.row, r.into());
+    /// Keeps a copy of any unsaved changes in a swap file (or in `dir` for a buffer without a file), refreshed whenever the user stops typing for a moment.
+    ///
+    /// A swap file that's been left behind for the buffer is offered up for recovery straight away.
+    pub fn set_recovery_dir(&mut self, dir: PathBuf) {
+        self.recovery_dir = Some(dir);
+        self.check_swap_file();
+        self.timers.cancel(TimerEvent::WriteRecoveryFile);
+        self.timers.on_idle(
+            Instant::now(),
+            RECOVERY_IDLE_TIMEOUT,
+            TimerEvent::WriteRecoveryFile,
+        );
     }
 
-    /// Renders the editor to a stream, assuming that a TTY is on the other end.
-    fn render<S: Write + IsTty>(&mut self, stream: &mut S) -> Result<()> {
-        queue!(
-            stream,
-            cursor::Hide,
-            terminal::Clear(terminal::ClearType::All),
-            cursor::MoveTo(0, 0)
-        )
-        .map_err(|e| EditorError::TermIo(e))?;
+    /// Copies a file to `file~` before writing over it, like Vim's `backup` option.
+    pub fn set_backup(&mut self, backup: bool) {
+        self.backup = backup;
+    }
 
-        for (row, line) in self
-            .contents
-            .lines()
-            .skip(self.display_position.row)
-            .take(self.display_size.rows.into())
-            .enumerate()
-        {
-            queue!(stream, cursor::MoveTo(0, row.try_into().unwrap()))
-                .map_err(|e| EditorError::TermIo(e))?;
-            match line.get(self.display_position.column..) {
-                Some(s) => match s.get(..self.display_size.columns.into()) {
-                    Some(s2) => write!(stream, "{}", s2).map_err(|e| EditorError::TermIo(e))?,
-                    None => write!(stream, "{}", s).map_err(|e| EditorError::TermIo(e))?,
-                },
-                None => (),
+    /// Writes unsaved changes into the buffer's file whenever the user stops typing for `idle`.
+    pub fn set_autosave(&mut self, idle: Duration) {
+        self.timers.cancel(TimerEvent::Autosave);
+        self.timers
+            .on_idle(Instant::now(), idle, TimerEvent::Autosave);
+    }
+
+    /// Moves the cursor to a position in the document, centering the display on it.
+    ///
+    /// Both `line` and `column` are counted from zero, and are clamped to the contents of the buffer.
+    pub fn goto(&mut self, line: usize, column: usize) {
+        self.buffer.goto(line, column);
+        self.fit_gutter();
+        self.view.center_on(self.cursor_cell());
+    }
+
+    /// Sets the shell command that `:make` runs to build the project.
+    pub fn set_make_command(&mut self, command: String) {
+        self.make_command = command;
+    }
+
+    /// Sets the commands that start a language server for each file extension, starting one for the buffer if it needs it.
+    pub fn set_language_servers(&mut self, servers: Vec<(String, String)>) {
+        self.language_servers = servers;
+        self.attach_language_server();
+    }
+
+    /// Sets how comments are written for each file extension, for `gc` to use instead of the usual ways.
+    pub fn set_comment_strings(&mut self, comment_strings: Vec<(String, String)>) {
+        self.comment_strings = comment_strings;
+    }
+
+    /// Sets the shell command that tidies up each type of file, for `:format` to run.
+    pub fn set_formatters(&mut self, formatters: Vec<(String, String)>) {
+        self.formatters = formatters;
+    }
+
+    /// Asks for buffers to be formatted every time they're written with `:w`, if there's a formatter for them.
+    pub fn set_format_on_save(&mut self, format_on_save: bool) {
+        self.format_on_save = format_on_save;
+    }
+
+    /// Sets the shell command that checks each type of file for problems, starting on the buffer if there's one for it.
+    pub fn set_linters(&mut self, linters: Vec<(String, String)>) {
+        self.linters = linters;
+        self.start_lint();
+    }
+
+    /// Sets the commands to run when things happen in the editor.
+    ///
+    /// The buffer has already been read in by now, so its hooks for that are run straight away.
+    pub fn set_hooks(&mut self, hooks: Vec<Hook>) {
+        self.hooks = hooks;
+        self.run_hooks(HookEvent::BufRead);
+        self.run_hooks(HookEvent::BufEnter);
+    }
+
+    /// Runs the commands hooked onto `event` for the buffer's type of file, one after another.
+    ///
+    /// Hooks go about their business quietly, leaving the cursor and the message where they were, unless a command fails.
+    fn run_hooks(&mut self, event: HookEvent) {
+        if self.running_hooks {
+            return;
+        }
+        let file_type = self.file_type();
+        let commands: Vec<String> = self
+            .hooks
+            .iter()
+            .filter(|hook| hook.applies(event, file_type))
+            .map(|hook| hook.command.clone())
+            .collect();
+        if commands.is_empty() {
+            return;
+        }
+
+        let (buffer, cursor) = (self.buffers.current(), self.buffer.cursor());
+        let message = (self.message.clone(), self.message_is_error);
+        let mut failed = None;
+        self.running_hooks = true;
+        for line in commands {
+            if let Err(e) = self.execute_line(&line) {
+                failed = Some(format!("{} hook failed: {}", event.name(), e));
             }
         }
+        self.running_hooks = false;
 
-        let mut last_column = self.row_length();
-        match self.mode {
-            EditorMode::Edit => (),
-            _ => last_column = last_column.saturating_sub(1),
+        if self.buffers.current() == buffer {
+            self.buffer.goto(cursor.line, cursor.column);
         }
+        (self.message, self.message_is_error) = message;
+        if let Some(failed) = failed {
+            self.show_error(failed);
+        }
+    }
 
-        queue!(
-            stream,
-            cursor::MoveTo(
-                cmp::min(self.cursor.column as usize, last_column)
-                    .try_into()
-                    .unwrap(),
-                self.cursor.row
-            ),
-            cursor::Show
-        )
-        .map_err(|e| EditorError::TermIo(e))?;
+    /// Shows the start screen over the empty buffer until a key is pressed.
+    pub fn show_start_screen(&mut self) {
+        self.start_screen = true;
+    }
 
-        stream.flush().map_err(|e| EditorError::TermIo(e))
+    /// Draws everything in the colours from a theme, starting again from scratch so that the old colours don't linger.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.screen.set_background(theme.text.background_color);
+        self.screen.invalidate();
+        self.theme = theme;
     }
 
-    /// Writes the `contents` buffer to the file at `path`.
-    fn write(&self) -> Result<()> {
-        let mut file =
-            File::create(self.path.as_ref().unwrap()).map_err(|e| EditorError::FileIo(e))?;
-        file.write(self.contents.as_bytes())
-            .map_err(|e| EditorError::FileIo(e))?;
-        Ok(())
+    /// Shows line numbers in a gutter down the left hand side of the display.
+    pub fn set_line_numbers(&mut self, line_numbers: LineNumbers) {
+        self.view.set_line_numbers(line_numbers);
     }
 
-    /// Runs the `Editor`'s main loop.
-    pub fn run<T>(&mut self, stream: &mut T) -> Result<()>
-    where
-        T: Write + IsTty,
-    {
-        loop {
-            self.render(stream)?;
+    /// Spaces tab stops `tab_width` cells apart in every window.
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.view.set_tab_width(tab_width);
+        for window in self.windows.iter_mut() {
+            window.view.set_tab_width(tab_width);
+        }
+    }
 
-            match self.mode {
-                EditorMode::Navigate => match event::read().map_err(|e| EditorError::TermIo(e))? {
-                    Event::Key(KeyEvent {
-                        code: KeyCode::Char('q'),
-                        ..
-                    }) => break,
-                    Event::Key(KeyEvent {
-                        code: KeyCode::Char('h'),
-                        ..
-                    }) => self.move_cursor(CursorMovement::Left),
-                    Event::Key(KeyEvent {
-                        code: KeyCode::Char('j'),
-                        ..
-                    }) => self.move_cursor(CursorMovement::Down),
-                    Event::Key(KeyEvent {
-                        code: KeyCode::Char('k'),
-                        ..
-                    }) => self.move_cursor(CursorMovement::Up),
-                    Event::Key(KeyEvent {
-                        code: KeyCode::Char('l'),
-                        ..
-                    }) => self.move_cursor(CursorMovement::Right),
-                    Event::Key(KeyEvent {
-                        code: KeyCode::Char('i'),
-                        ..
-                    }) => self.mode = EditorMode::Edit,
-                    Event::Key(KeyEvent {
-                        code: KeyCode::Char('w'),
-                        ..
-                    }) => self.write()?,
-                    _ => (),
-                },
-                EditorMode::Edit => match event::read().map_err(|e| EditorError::TermIo(e))? {
-                    Event::Key(KeyEvent {
-                        code: KeyCode::Esc, ..
-                    }) => self.mode = EditorMode::Navigate,
-                    Event::Key(KeyEvent {
-                        code: KeyCode::Char(c),
-                        ..
-                    }) => self.insert(c),
-                    Event::Key(KeyEvent {
-                        code: KeyCode::Enter,
-                        ..
-                    }) => self.insert('\n'),
-                    Event::Key(KeyEvent {
-                        code: KeyCode::Backspace,
-                        ..
-                    }) => self.remove(),
-                    _ => (),
-                },
-            }
+    /// Carries long lines on onto the rows below them in every window, rather than letting them run off the side.
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.view.set_wrap(wrap);
+        for window in self.windows.iter_mut() {
+            window.view.set_wrap(wrap);
         }
+    }
 
-        Ok(())
+    /// Shows tabs, trailing spaces and non-breaking spaces as glyphs in every window, like Vim's `:set list`.
+    pub fn set_list(&mut self, list: bool) {
+        self.view.set_list(list);
+        for window in self.windows.iter_mut() {
+            window.view.set_list(list);
+        }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    /// Picks the glyphs that whitespace is shown as in every window.
+    pub fn set_list_chars(&mut self, list_chars: ListChars) {
+        self.view.set_list_chars(list_chars);
+        for window in self.windows.iter_mut() {
+            window.view.set_list_chars(list_chars);
+        }
+    }
 
-    #[test]
-    fn test_moving_cursor_left() {
-        let mut editor = Editor::new("...\n...\n...");
-        editor.cursor = CursorPosition::new(1, 1);
-        editor.move_cursor(CursorMovement::Left);
-        assert_eq!(editor.cursor, CursorPosition::new(0, 1));
+    /// Picks what fills up the rows past the end of the buffer in every window.
+    pub fn set_fill_chars(&mut self, fill_chars: FillChars) {
+        self.view.set_fill_chars(fill_chars);
+        for window in self.windows.iter_mut() {
+            window.view.set_fill_chars(fill_chars);
+        }
     }
 
-    #[test]
-    fn test_moving_cursor_right() {
-        let mut editor = Editor::new("...\n...\n...");
-        editor.cursor = CursorPosition::new(1, 1);
-        editor.move_cursor(CursorMovement::Right);
-        assert_eq!(editor.cursor, CursorPosition::new(2, 1));
+    /// Picks out the line the cursor is on in every window, like Vim's `:set cursorline`.
+    pub fn set_cursor_line(&mut self, cursor_line: bool) {
+        self.view.set_cursor_line(cursor_line);
+        for window in self.windows.iter_mut() {
+            window.view.set_cursor_line(cursor_line);
+        }
     }
 
-    #[test]
-    fn test_moving_cursor_up() {
-        let mut editor = Editor::new("...\n...\n...");
-        editor.cursor = CursorPosition::new(1, 1);
-        editor.move_cursor(CursorMovement::Up);
-        assert_eq!(editor.cursor, CursorPosition::new(1, 0));
+    /// Draws rulers down every window at the given columns, counted from one.
+    pub fn set_color_columns(&mut self, color_columns: Vec<usize>) {
+        for window in self.windows.iter_mut() {
+            window.view.set_color_columns(color_columns.clone());
+        }
+        self.view.set_color_columns(color_columns);
     }
 
-    #[test]
-    fn test_moving_cursor_down() {
-        let mut editor = Editor::new("...\n...\n...");
-        editor.cursor = CursorPosition::new(1, 1);
-        editor.move_cursor(CursorMovement::Down);
-        assert_eq!(editor.cursor, CursorPosition::new(1, 2));
+    /// Keeps `scroll_off` lines on the display above and below the cursor in every window.
+    pub fn set_scroll_off(&mut self, scroll_off: usize) {
+        self.view.set_scroll_off(scroll_off);
+        for window in self.windows.iter_mut() {
+            window.view.set_scroll_off(scroll_off);
+        }
     }
 
-    #[test]
-    fn test_moving_cursor_left_at_edge() {
-        let mut editor = Editor::new("...\n...\n...");
-        editor.cursor = CursorPosition::new(0, 1);
-        editor.move_cursor(CursorMovement::Left);
-        assert_eq!(editor.cursor, CursorPosition::new(0, 1));
+    /// Maps keys onto other keys in some of the modes.
+    pub fn set_key_bindings(&mut self, bindings: Vec<Binding>) {
+        self.keymap = Keymap::new(bindings);
     }
 
-    #[test]
-    fn test_moving_cursor_right_at_edge() {
-        let mut editor = Editor::new("...\n...\n...");
-        editor.cursor = CursorPosition::new(2, 1);
-        editor.move_cursor(CursorMovement::Right);
-        assert_eq!(editor.cursor, CursorPosition::new(2, 1));
+    /// Saves the bindings made with `:savemacro` into `path`, so that they're read back in on the next run.
+    pub fn set_config_file(&mut self, path: PathBuf) {
+        self.config_file = Some(path);
     }
 
-    #[test]
-    fn test_moving_cursor_up_at_edge() {
-        let mut editor = Editor::new("...\n...\n...");
-        editor.cursor = CursorPosition::new(1, 0);
-        editor.move_cursor(CursorMovement::Up);
-        assert_eq!(editor.cursor, CursorPosition::new(1, 0));
+    /// Types spaces in place of tabs, like Vim's `expandtab`.
+    pub fn set_expand_tab(&mut self, expand_tab: bool) {
+        self.expand_tab = expand_tab;
     }
 
-    #[test]
-    fn test_moving_cursor_down_at_edge() {
-        let mut editor = Editor::new("...\n...\n...");
-        editor.cursor = CursorPosition::new(1, 2);
-        editor.move_cursor(CursorMovement::Down);
-        assert_eq!(editor.cursor, CursorPosition::new(1, 2));
+    /// Indents lines with `>>` and `<<` by `shift_width` cells, like Vim's `shiftwidth` (where 0 means the same as the tab width.)
+    pub fn set_shift_width(&mut self, shift_width: usize) {
+        self.shift_width = shift_width;
     }
 
-    #[test]
-    fn test_scrolling_down() {
-        let mut editor = Editor::new("1\n2\n3\n4\n5");
-        editor.set_display_rows(3);
-        editor.cursor = CursorPosition::new(0, 2);
-        editor.display_position = DisplayPosition::new(0, 0);
+    pub fn set_display_columns(&mut self, c: u16) {
+        self.area.columns = c;
+        self.arrange_windows();
+    }
+
+    /// Sets the height of the display, keeping the bottom row back for the status line.
+    pub fn set_display_rows(&mut self, r: u16) {
+        self.area.rows = r.saturating_sub(1);
+        self.arrange_windows();
+    }
 
-        editor.move_cursor(CursorMovement::Down);
+    /// Draws the whole display again at a new size on the next render, for when something else has had the terminal.
+    pub fn redraw(&mut self, columns: u16, rows: u16) {
+        self.set_display_columns(columns);
+        self.set_display_rows(rows);
+        self.screen.invalidate();
+        self.cursor_shape = None;
+        self.title = None;
+    }
 
-        assert_eq!(editor.cursor, CursorPosition::new(0, 2));
-        assert_eq!(editor.display_position, DisplayPosition::new(0, 1));
+    /// Opens the file at `path` (or switches to it if it's open already), like `:e` does.
+    pub fn open(&mut self, path: PathBuf) -> Result<()> {
+        self.edit(path, false)
     }
 
-    #[test]
-    fn test_scrolling_right() {
-        let mut editor = Editor::new("12345");
-        editor.set_display_columns(3);
-        editor.cursor = CursorPosition::new(2, 0);
-        editor.display_position = DisplayPosition::new(0, 0);
+    /// Handles keys as if the user had typed them, passing back whatever they ask the editor to do if it's to stop running.
+    ///
+    /// Any keys after the one that stopped it are left untyped.
+    pub fn send_keys(&mut self, keys: &[KeyEvent]) -> Option<Exit> {
+        for &key in keys {
+            self.timers.input(Instant::now());
+            self.record_key(key);
+            self.map_key(key);
+            if let Some(exit) = self.handle_mapped_keys() {
+                return Some(exit);
+            }
+        }
+        None
+    }
 
-        editor.move_cursor(CursorMovement::Right);
+    /// Sees to any timers that are due, for when there's no terminal being waited on to do it in between keys.
+    pub fn tick(&mut self) -> Option<Exit> {
+        for timer in self.timers.expired(Instant::now()) {
+            self.handle_timer(timer);
+        }
+        // A key mapping that's timed out leaves its keys to be handled.
+        self.handle_mapped_keys()
+    }
 
-        assert_eq!(editor.cursor, CursorPosition::new(2, 0));
-        assert_eq!(editor.display_position, DisplayPosition::new(1, 0));
+    /// Handles the keys that have made it through the key mappings, for when there's no terminal.
+    ///
+    /// There's no terminal to hand over to a `:!` command either, so it's run there and then like `execute_line` does.
+    fn handle_mapped_keys(&mut self) -> Option<Exit> {
+        while let Some(key) = self.keymap.next_key() {
+            match self.handle_event(Event::Key(key)) {
+                Some(Exit::Shell(command)) => {
+                    if let Err(e) = self.run_shell_command(&command) {
+                        self.show_error(e);
+                    }
+                }
+                Some(exit) => return Some(exit),
+                None => (),
+            }
+        }
+        None
     }
 
-    #[test]
-    fn test_scrolling_left() {
-        let mut editor = Editor::new("12345");
-        editor.cursor = CursorPosition::new(0, 0);
-        editor.display_position = DisplayPosition::new(1, 0);
+    /// Runs a command from `:!` without a terminal, showing what it printed as the message.
+    fn run_shell_command(&mut self, command: &str) -> Result<()> {
+        let output = run_shell(command, None)?;
+        self.show_message(output.trim_end().to_string());
+        Ok(())
+    }
 
-        editor.move_cursor(CursorMovement::Left);
+    /// Draws the display as it would appear on the terminal, as plain text a row at a time.
+    pub fn render_text(&mut self) -> Result<Vec<String>> {
+        self.render(&mut Vec::new())?;
+        Ok(self.screen.text())
+    }
 
-        assert_eq!(editor.cursor, CursorPosition::new(0, 0));
-        assert_eq!(editor.display_position, DisplayPosition::new(0, 0));
+    /// The buffer being edited.
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
     }
 
-    #[test]
-    fn test_scrolling_up() {
-        let mut editor = Editor::new("1\n2\n3\n4\n5");
-        editor.cursor = CursorPosition::new(0, 0);
-        editor.display_position = DisplayPosition::new(0, 2);
+    /// The message being shown at the bottom of the display, if there is one.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
 
-        editor.move_cursor(CursorMovement::Up);
+    /// Fits each of the windows into its own part of the display.
+    fn arrange_windows(&mut self) {
+        let focused = self.windows.focused();
+        for (id, rect) in self.windows.arrange(self.area).windows {
+            let view = match self.windows.get_mut(id) {
+                Some(window) => &mut window.view,
+                None if id == focused => &mut self.view,
+                None => continue,
+            };
+            view.set_origin(rect.x, rect.y);
+            view.set_columns(rect.columns);
+            view.set_rows(rect.rows);
+        }
+    }
 
-        assert_eq!(editor.cursor, CursorPosition::new(0, 0));
-        assert_eq!(editor.display_position, DisplayPosition::new(0, 1));
+    /// Splits the focused window in two, both showing the same buffer to begin with.
+    ///
+    /// The focus stays in the top or left hand window, which goes on to edit the file at `path` if one is given.
+    fn split_window(&mut self, split: Split, path: Option<PathBuf>) -> Result<()> {
+        let window = Window {
+            view: self.view.clone(),
+            buffer: self.buffers.current(),
+            cursor: self.buffer.cursor(),
+        };
+        if !self.windows.split(split, window, self.area) {
+            return Err(EditorError::NotEnoughRoom);
+        }
+        self.arrange_windows();
+        match path {
+            Some(path) => self.edit(path, false),
+            None => Ok(()),
+        }
     }
 
-    #[test]
-    fn test_scrolling_down_at_end() {
-        let mut editor = Editor::new("1\n2\n3\n4\n5");
-        editor.cursor = CursorPosition::new(0, 2);
-        editor.display_position.row = 2;
-        editor.move_cursor(CursorMovement::Down);
-        assert_eq!(editor.cursor, CursorPosition::new(0, 2));
-        assert_eq!(editor.display_position, DisplayPosition::new(0, 2));
+    /// Changes the working directory that relative paths are found from, going to the home directory without a path (like
+    /// Vim's `:cd`.)
+    ///
+    /// The paths that are already known are changed to go from the new directory, or made absolute if they aren't inside
+    /// it, so that they still point at the same files.
+    /// A project's `.te.toml` is only read when the editor starts, so the user is told when the new directory has a different
+    /// one that isn't being used.
+    fn change_directory(&mut self, path: Option<PathBuf>) -> Result<()> {
+        let old = env::current_dir().map_err(EditorError::FileIo)?;
+        let path = path
+            .or_else(|| env::var_os("HOME").map(PathBuf::from))
+            .unwrap_or_else(|| old.clone());
+        if !path.is_dir() {
+            return Err(EditorError::DirectoryDoesNotExist(path));
+        }
+        env::set_current_dir(&path).map_err(EditorError::FileIo)?;
+        let new = env::current_dir().map_err(EditorError::FileIo)?;
+
+        let relocate = |path: &Path| {
+            let path = old.join(path);
+            match path.strip_prefix(&new) {
+                Ok(relative) => relative.to_path_buf(),
+                Err(_) => path,
+            }
+        };
+        for i in 0..self.buffers.len() {
+            if let Some(buffer) = self.buffers.get_mut(&mut self.buffer, i) {
+                buffer.relocate(relocate);
+            }
+        }
+        for arg in &mut self.args {
+            *arg = relocate(arg);
+        }
+        self.quickfix.relocate(relocate);
+        // The swap files are removed when the editor exits, from wherever it has got to by then.
+        for swap in self.swap_files.iter_mut().chain(&mut self.recovery_path) {
+            *swap = old.join(&*swap);
+        }
+        match config::find_project_file(&new) {
+            Some(project) if config::find_project_file(&old).as_ref() != Some(&project) => self
+                .show_warning(format!(
+                    "{} (its {} isn't used until Té is started there)",
+                    new.display(),
+                    config::PROJECT_FILE
+                )),
+            _ => self.show_message(new.display().to_string()),
+        }
+        Ok(())
     }
 
-    #[test]
-    fn test_scrolling_up_at_end() {
-        let mut editor = Editor::new("1\n2\n3\n4\n5");
-        editor.cursor = CursorPosition::new(0, 0);
-        editor.move_cursor(CursorMovement::Up);
-        assert_eq!(editor.cursor, CursorPosition::new(0, 0));
-        assert_eq!(editor.display_position, DisplayPosition::new(0, 0));
+    /// Saves the open files and the windows showing them into a session file, for `te --session` to open again.
+    fn make_session(&mut self, path: Option<PathBuf>, force: bool) -> Result<()> {
+        let path = path.unwrap_or_else(|| PathBuf::from(DEFAULT_SESSION_FILE));
+        if path.exists() && !force {
+            return Err(EditorError::FileExists(path));
+        }
+        let session = self.session()?;
+        std::fs::write(&path, session.to_string()).map_err(EditorError::FileIo)?;
+        self.show_message(format!("Session saved to \"{}\"", path.display()));
+        Ok(())
     }
 
-    #[test]
-    fn test_inserting_a_char() {
-        let mut editor = Editor::new("");
-        editor.insert('a');
-        assert_eq!(editor.contents, "a");
+    /// Describes the open files and windows, along with where their cursors are.
+    fn session(&self) -> Result<Session> {
+        let buffers = self
+            .buffers
+            .iter(&self.buffer)
+            .enumerate()
+            .map(|(i, buffer)| match buffer.path() {
+                Some(path) => Ok((path.to_path_buf(), buffer.cursor())),
+                None => Err(EditorError::UnnamedBuffer(i + 1)),
+            })
+            .collect::<Result<_>>()?;
+        let focused = SessionWindow {
+            id: self.windows.focused(),
+            buffer: self.buffers.current(),
+            cursor: self.buffer.cursor(),
+        };
+        let others = self.windows.iter().map(|(id, window)| SessionWindow {
+            id,
+            buffer: window.buffer,
+            cursor: window.cursor,
+        });
+        Ok(Session {
+            directory: env::current_dir().map_err(EditorError::FileIo)?,
+            buffers,
+            windows: iter::once(focused).chain(others).collect(),
+            layout: self.windows.layout().clone(),
+            focused: self.windows.focused(),
+        })
     }
 
-    #[test]
-    fn test_inserting_multiple_chars() {
-        let mut editor = Editor::new("");
-        editor.insert('a');
-        editor.insert('b');
-        editor.insert('c');
-        assert_eq!(editor.contents, "abc");
+    /// Puts the cursors and windows back the way they were in a session, once its files have been opened.
+    pub fn restore_session(&mut self, session: &Session) -> Result<()> {
+        for (i, (_, cursor)) in session.buffers.iter().enumerate() {
+            if let Some(buffer) = self.buffers.get_mut(&mut self.buffer, i) {
+                buffer.goto(cursor.line, cursor.column);
+            }
+        }
+        let mut hidden = BTreeMap::new();
+        for window in &session.windows {
+            if window.id == session.focused {
+                self.switch_buffer(window.buffer)?;
+                self.buffer.goto(window.cursor.line, window.cursor.column);
+            } else {
+                let view = self.view.clone();
+                let (buffer, cursor) = (window.buffer, window.cursor);
+                hidden.insert(
+                    window.id,
+                    Window {
+                        view,
+                        buffer,
+                        cursor,
+                    },
+                );
+            }
+        }
+        self.windows = WindowManager::restore(session.layout.clone(), hidden, session.focused);
+        self.arrange_windows();
+        for window in self.windows.iter_mut() {
+            if let Some(buffer) = self.buffers.get(&self.buffer, window.buffer) {
+                window.view.scroll_to(buffer, window.cursor);
+            }
+        }
+        self.fit_window_gutters();
+        Ok(())
     }
 
-    #[test]
-    fn test_inserting_multiple_lines() {
-        let mut editor = Editor::new("");
-        editor.insert('a');
-        editor.insert('\n');
-        editor.insert('b');
-        editor.insert('\n');
-        editor.insert('c');
-        assert_eq!(editor.contents, "a\nb\nc");
+    /// Compares the first two buffers side by side, with the first on the left, like `vimdiff`.
+    pub fn diff_files(&mut self) -> Result<()> {
+        self.switch_buffer(1)?;
+        self.split_window(Split::Vertical, None)?;
+        self.switch_buffer(0)?;
+        self.start_diff(0, 1);
+        Ok(())
     }
 
-    #[test]
-    fn test_removing_a_char() {
-        let mut editor = Editor::new("abc");
-        editor.cursor = CursorPosition::new(2, 0);
-        editor.remove();
-        assert_eq!(editor.contents, "ac");
+    /// Splits the window side by side with the file at `path`, comparing it with the buffer that was being edited.
+    fn diff_split(&mut self, path: PathBuf) -> Result<()> {
+        let old = self.buffers.current();
+        self.split_window(Split::Vertical, Some(path))?;
+        self.start_diff(old, self.buffers.current());
+        Ok(())
     }
 
-    #[test]
-    fn test_removing_from_empty_buffer() {
-        let mut editor = Editor::new("");
-        editor.remove();
-        assert_eq!(editor.contents, "");
+    /// Starts comparing the buffers at `old` and `new` in the list, marking the differences between them.
+    fn start_diff(&mut self, old: usize, new: usize) {
+        self.stop_diff();
+        self.comparison = Some(Comparison {
+            old,
+            new,
+            revisions: None,
+            hunks: Vec::new(),
+        });
+        self.update_diff();
+        self.sync_diff_windows();
+    }
+
+    /// Stops comparing buffers, taking the differences back out of them.
+    fn stop_diff(&mut self) {
+        let comparison = match self.comparison.take() {
+            Some(comparison) => comparison,
+            None => return,
+        };
+        for index in [comparison.old, comparison.new] {
+            if let Some(buffer) = self.buffers.get_mut(&mut self.buffer, index) {
+                buffer.set_line_changes(Vec::new());
+                buffer.set_changed_text(Vec::new());
+            }
+        }
+        self.refresh_line_changes();
+        self.fit_window_gutters();
+    }
+
+    /// Compares the buffers again if either of them has changed since the last time, marking the differences in them.
+    fn update_diff(&mut self) {
+        let comparison = match &self.comparison {
+            Some(comparison) => comparison,
+            None => return,
+        };
+        let (old, new) = (comparison.old, comparison.new);
+        let (old_text, new_text, revisions) = match (
+            self.buffers.get(&self.buffer, old),
+            self.buffers.get(&self.buffer, new),
+        ) {
+            (Some(a), Some(b)) => (
+                a.contents().to_string(),
+                b.contents().to_string(),
+                (a.revision(), b.revision()),
+            ),
+            _ => return self.stop_diff(),
+        };
+        if comparison.revisions == Some(revisions) {
+            return;
+        }
+
+        let old_lines: Vec<&str> = old_text.lines().collect();
+        let new_lines: Vec<&str> = new_text.lines().collect();
+        let hunks = diff::diff(&old_lines, &new_lines);
+        let (old_marks, new_marks) = diff::marks(&hunks, &old_lines, &new_lines);
+        for (index, marks) in [(old, old_marks), (new, new_marks)] {
+            if let Some(buffer) = self.buffers.get_mut(&mut self.buffer, index) {
+                let contents = buffer.contents();
+                let changed_text = marks
+                    .changed_parts
+                    .into_iter()
+                    .filter_map(|(line, part)| {
+                        let start = contents.line_start(line)?;
+                        Some(start + part.start..start + part.end)
+                    })
+                    .collect();
+                buffer.set_line_changes(marks.line_changes);
+                buffer.set_changed_text(changed_text);
+            }
+        }
+        self.comparison = Some(Comparison {
+            old,
+            new,
+            revisions: Some(revisions),
+            hunks,
+        });
+        self.fit_window_gutters();
+    }
+
+    /// Keeps the windows on the other buffer being compared lined up with this one, scrolling them and moving their cursors along with it.
+    fn sync_diff_windows(&mut self) {
+        let comparison = match &self.comparison {
+            Some(comparison) => comparison,
+            None => return,
+        };
+        let current = self.buffers.current();
+        let (other, from_new) = match current {
+            _ if current == comparison.old => (comparison.new, false),
+            _ if current == comparison.new => (comparison.old, true),
+            _ => return,
+        };
+        let hunks = &comparison.hunks;
+        let top = diff::corresponding_line(hunks, self.view.visible_lines().start, from_new);
+        let line = diff::corresponding_line(hunks, self.buffer.cursor().line, from_new);
+        for window in self.windows.iter_mut().filter(|w| w.buffer == other) {
+            window.view.scroll_to_top(top);
+            window.cursor = Position::new(line, window.cursor.column);
+        }
+    }
+
+    /// Moves the cursor to the start of the `count`th change after it (or before it, when going backwards) in the buffers being compared.
+    fn jump_to_change(&mut self, forward: bool, count: usize) -> Result<()> {
+        self.update_diff();
+        let comparison = self.comparison.as_ref().ok_or(EditorError::NoMoreChanges)?;
+        let current = self.buffers.current();
+        let last_line = self.buffer.num_lines().saturating_sub(1);
+        let starts = comparison.hunks.iter().map(|hunk| {
+            let start = match current == comparison.new {
+                true => hunk.new.start,
+                false => hunk.old.start,
+            };
+            cmp::min(start, last_line)
+        });
+        let line = self.buffer.cursor().line;
+        let found = match forward {
+            true => starts.filter(|&start| start > line).nth(count - 1),
+            false => starts.rev().filter(|&start| start < line).nth(count - 1),
+        };
+        let target = found.ok_or(EditorError::NoMoreChanges)?;
+        self.buffer.push_jump();
+        self.buffer.goto(target, 0);
+        self.move_by(Motion::FirstNonBlank);
+        Ok(())
+    }
+
+    /// Fits the gutters of every window to what's shown in them, after the marks in their buffers have changed.
+    fn fit_window_gutters(&mut self) {
+        self.fit_gutter();
+        for window in self.windows.iter_mut() {
+            if let Some(buffer) = self.buffers.get(&self.buffer, window.buffer) {
+                window
+                    .view
+                    .fit_gutter(buffer.num_lines(), buffer.has_signs());
+            }
+        }
+    }
+
+    /// Closes the focused window, moving the focus on to one of its neighbours.
+    fn close_window(&mut self) -> Result<()> {
+        let window = self.windows.close().ok_or(EditorError::LastWindow)?;
+        self.show_window(window);
+        self.arrange_windows();
+        Ok(())
+    }
+
+    /// Moves the focus on to window `id`.
+    fn focus_window(&mut self, id: usize) {
+        let focused = Window {
+            view: self.view.clone(),
+            buffer: self.buffers.current(),
+            cursor: self.buffer.cursor(),
+        };
+        if let Some(window) = self.windows.focus(id, focused) {
+            self.show_window(window);
+        }
+    }
+
+    /// Picks up where a window that has just been focused left off, with its own buffer, cursor and scroll position.
+    fn show_window(&mut self, window: Window) {
+        self.view = window.view;
+        if window.buffer != self.buffers.current() {
+            self.leave_buffer();
+            self.buffers.switch(&mut self.buffer, window.buffer);
+        }
+        self.buffer.goto(window.cursor.line, window.cursor.column);
+    }
+
+    /// Finds the window under a cell of the display.
+    fn window_at(&self, column: u16, row: u16) -> Option<usize> {
+        self.windows
+            .arrange(self.area)
+            .windows
+            .into_iter()
+            .find(|(_, rect)| rect.contains(column, row))
+            .map(|(id, _)| id)
+    }
+
+    /// Clicking moves the cursor (and the focus, between windows), dragging selects some text, and the wheel scrolls.
+    ///
+    /// The mouse is ignored while the user is being asked for something on the bottom row.
+    fn handle_mouse(&mut self, event: MouseEvent) {
+        if self.prompt().is_some()
+            || matches!(
+                self.mode,
+                EditorMode::Confirm | EditorMode::Reload | EditorMode::Recover
+            )
+        {
+            return;
+        }
+        let window = match self.window_at(event.column, event.row) {
+            Some(window) => window,
+            None => return,
+        };
+
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if window != self.windows.focused() {
+                    self.focus_window(window);
+                }
+                if let EditorMode::Visual { .. } = self.mode {
+                    self.mode = EditorMode::Navigate;
+                }
+                self.move_to_cell(event.column, event.row);
+            }
+            // The selection starts from wherever the button was pressed, which is where the cursor was left.
+            MouseEventKind::Drag(MouseButton::Left) if window == self.windows.focused() => {
+                if let (EditorMode::Navigate, false) = (&self.mode, self.pager) {
+                    self.start_visual(VisualKind::Characters);
+                }
+                self.move_to_cell(event.column, event.row);
+            }
+            MouseEventKind::ScrollDown => self.scroll_window(window, MOUSE_SCROLL_LINES),
+            MouseEventKind::ScrollUp => self.scroll_window(window, -MOUSE_SCROLL_LINES),
+            _ => (),
+        }
+    }
+
+    /// Moves the cursor to whatever is under a cell of the focused window, or as close as it can get.
+    fn move_to_cell(&mut self, column: u16, row: u16) {
+        let cell = match self.view.position_at(&self.buffer, column, row) {
+            Some(cell) => cell,
+            None => return,
+        };
+        let line = cmp::min(cell.line, self.buffer.num_lines().saturating_sub(1));
+        // Outside of Edit mode the cursor can't go past the end of a line, it sits on the last character instead.
+        let last_column = match self.mode {
+            EditorMode::Edit | EditorMode::Replace => self.buffer.line_length(line),
+            _ => self.buffer.last_column(line),
+        };
+        let column = cmp::min(
+            self.buffer
+                .column_at(line, cell.column, self.view.tab_width()),
+            last_column,
+        );
+        let index = self.buffer.index_of(Position::new(line, column));
+        self.buffer
+            .set_cursor_index(self.buffer.contents().grapheme_start(index));
+    }
+
+    /// Scrolls a window by some lines, dragging its cursor along if it would otherwise go off the display.
+    fn scroll_window(&mut self, id: usize, lines: isize) {
+        match self.windows.get_mut(id) {
+            Some(window) => {
+                if let Some(buffer) = self.buffers.get(&self.buffer, window.buffer) {
+                    window.view.scroll_by(lines, buffer.num_lines());
+                    window.cursor.line =
+                        window.view.nearest_visible_line(buffer, window.cursor.line);
+                }
+            }
+            None => {
+                self.view.scroll_by(lines, self.buffer.num_lines());
+                let line = self
+                    .view
+                    .nearest_visible_line(&self.buffer, self.buffer.cursor().line);
+                if line != self.buffer.cursor().line {
+                    self.buffer.goto_line(line);
+                }
+            }
+        }
+    }
+
+    /// Carries out the window command that follows `Ctrl-W`.
+    fn window_command(&mut self, key: char) -> Result<()> {
+        let direction = match key {
+            'h' => Direction::Left,
+            'j' => Direction::Down,
+            'k' => Direction::Up,
+            'l' => Direction::Right,
+            's' => return self.split_window(Split::Horizontal, None),
+            'v' => return self.split_window(Split::Vertical, None),
+            'c' | 'q' => return self.close_window(),
+            'w' => {
+                self.focus_window(self.windows.next(self.area));
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        if let Some(id) = self.windows.neighbour(direction, self.area) {
+            self.focus_window(id);
+        }
+        Ok(())
+    }
+
+    /// Reverses the most recent change to the buffer.
+    fn undo(&mut self) {
+        if !self.buffer.undo() {
+            self.show_message("Already at oldest change".to_string());
+        }
+    }
+
+    /// Makes the most recently undone change again.
+    fn redo(&mut self) {
+        if !self.buffer.redo() {
+            self.show_message("Already at newest change".to_string());
+        }
+    }
+
+    /// Determines where the cursor should be drawn in the document.
+    ///
+    /// Outside of Edit mode the cursor sits on the last character of a line, rather than after it.
+    fn displayed_cursor(&self) -> Position {
+        let mut position = self.buffer.cursor();
+        match self.mode {
+            EditorMode::Edit | EditorMode::Replace => (),
+            _ => {
+                position.column = cmp::min(position.column, self.buffer.last_column(position.line));
+            }
+        }
+        position
+    }
+
+    /// Types a tab at the cursor, or enough spaces to reach the next tab stop if tabs are being expanded.
+    fn insert_tab(&mut self) {
+        if !self.expand_tab {
+            self.buffer.insert('\t');
+            return;
+        }
+        let tab_width = self.view.tab_width();
+        let spaces = tab_width - self.cursor_cell().column % tab_width;
+        for _ in 0..spaces {
+            self.buffer.insert(' ');
+        }
+    }
+
+    /// Types `c` over the character under the cursor in Replace mode, or adds it to the end of the line if there isn't one.
+    fn type_over(&mut self, c: char) {
+        let idx = self.buffer.cursor_index();
+        let contents = self.buffer.contents();
+        let end = motions::content_end(contents, contents.line_of(idx));
+        if idx >= end {
+            self.buffer.insert(c);
+            self.replaced.push(None);
+            return;
+        }
+        let range = idx..contents.next_grapheme(idx);
+        let old = contents.slice(range.clone()).to_string();
+        self.buffer.replace(range, &c.to_string());
+        self.buffer.set_cursor_index(idx + c.len_utf8());
+        self.replaced.push(Some(old));
+    }
+
+    /// Puts back the last character that Replace mode typed over, or just moves back over the text from before it started.
+    fn untype(&mut self) {
+        match self.replaced.pop() {
+            Some(Some(old)) => {
+                let idx = self.buffer.cursor_index();
+                let start = self.buffer.contents().previous_grapheme(idx);
+                self.buffer.replace(start..idx, &old);
+            }
+            Some(None) => self.buffer.remove(),
+            None => self
+                .buffer
+                .move_cursor(CursorMovement::Left, true, self.view.tab_width()),
+        }
+    }
+
+    /// Inserts text that has been pasted into the terminal just as it is, as a change that's undone on its own.
+    fn insert_pasted(&mut self, text: &str) {
+        let index = self.buffer.cursor_index();
+        self.buffer.commit();
+        self.buffer.replace(index..index, text);
+        self.buffer.set_cursor_index(index + text.len());
+        self.buffer.commit();
+    }
+
+    /// Puts text that has been pasted into the terminal wherever the user is typing, skipping the key bindings and tab expansion.
+    ///
+    /// Terminals send the line breaks in a paste as carriage returns, the same as the Enter key.
+    fn handle_paste(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        match self.mode {
+            EditorMode::Edit => {
+                // Macros and `.` still only know about keys, so they're given the keys that would type the text.
+                for key in text.chars().map(typing_key) {
+                    self.record_key(key);
+                    self.track_change_key(key);
+                }
+                self.insert_pasted(&text);
+            }
+            _ if self.prompt().is_some() => {
+                self.command_line
+                    .insert_str(text.lines().next().unwrap_or(""));
+            }
+            _ => (),
+        }
+    }
+
+    /// Makes up an indent that is `width` cells wide, out of tabs (unless they're being expanded) and spaces.
+    fn indent(&self, width: usize) -> String {
+        let tab_width = self.view.tab_width();
+        match self.expand_tab {
+            true => " ".repeat(width),
+            false => "\t".repeat(width / tab_width) + &" ".repeat(width % tab_width),
+        }
+    }
+
+    /// Indents every line from `first` to `last` by another `shift_width` (like Vim's `>>`), or takes one off (like `<<`.)
+    ///
+    /// Empty lines are left alone rather than being filled up with blanks.
+    fn shift_lines(&mut self, first: usize, last: usize, right: bool) {
+        let tab_width = self.view.tab_width();
+        let shift_width = match self.shift_width {
+            0 => tab_width,
+            width => width,
+        };
+
+        self.buffer.commit();
+        for line in first..=last {
+            let contents = self.buffer.contents();
+            let start = contents.line_start(line).unwrap_or(contents.len());
+            let end = motions::content_end(contents, line);
+            let mut indent_end = start;
+            let mut width = 0;
+            while let Some(c @ (' ' | '\t')) = contents.char_at(indent_end) {
+                width = match c {
+                    '\t' => width + tab_width - width % tab_width,
+                    _ => width + 1,
+                };
+                indent_end += 1;
+            }
+
+            let width = match right {
+                _ if start == end => continue,
+                true => width + shift_width,
+                false => width.saturating_sub(shift_width),
+            };
+            let indent = self.indent(width);
+            self.buffer.replace(start..indent_end, &indent);
+        }
+        self.buffer.commit();
+
+        self.buffer.goto_line(first);
+        self.move_by(Motion::FirstNonBlank);
+    }
+
+    /// Finishes off a surround command with the key that says which brackets, quotes or tag it's about.
+    fn complete_surround(&mut self, surround: Surround, event: &Event) {
+        let c = match event {
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                ..
+            }) => *c,
+            _ => return,
+        };
+        match surround {
+            Surround::Add(_) | Surround::ChangeTo(..) if surround::is_tag(c) => {
+                self.surround = Some(surround);
+                self.command_line.clear();
+                self.mode = EditorMode::Tag;
+            }
+            Surround::Add(range) => match surround::delimiters(c) {
+                Some((open, close)) => self.put_around(
+                    range.start..range.start,
+                    range.end..range.end,
+                    &open,
+                    &close,
+                ),
+                None => self.show_error(EditorError::UnknownSurrounding(c)),
+            },
+            Surround::ChangeTo(open_range, close_range) => match surround::delimiters(c) {
+                Some((open, close)) => self.put_around(open_range, close_range, &open, &close),
+                None => self.show_error(EditorError::UnknownSurrounding(c)),
+            },
+            Surround::Delete | Surround::Change => {
+                let text = self.buffer.contents().to_string();
+                match surround::find(&text, self.buffer.cursor_index(), c) {
+                    Some((open, close)) if surround == Surround::Delete => {
+                        self.put_around(open, close, "", "")
+                    }
+                    Some((open, close)) => self.surround = Some(Surround::ChangeTo(open, close)),
+                    None => self.show_error(EditorError::NoSurrounding(c)),
+                }
+            }
+        }
+    }
+
+    /// Puts the tag typed in at the Tag prompt around the text that the surround command is about.
+    fn finish_tag(&mut self) {
+        self.mode = EditorMode::Navigate;
+        let text = self.command_line.take();
+        let (open, close) = match surround::tag(&text) {
+            Some(tag) => tag,
+            None => return,
+        };
+        match self.surround.take() {
+            Some(Surround::Add(range)) => self.put_around(
+                range.start..range.start,
+                range.end..range.end,
+                &open,
+                &close,
+            ),
+            Some(Surround::ChangeTo(open_range, close_range)) => {
+                self.put_around(open_range, close_range, &open, &close)
+            }
+            _ => (),
+        }
+    }
+
+    /// Replaces `open_range` with `open` and `close_range` with `close`, which is how surrounding text gets added, changed or deleted.
+    ///
+    /// The ranges are empty when adding, and the closing one is done first so that the opening one stays where it was.
+    fn put_around(
+        &mut self,
+        open_range: Range<usize>,
+        close_range: Range<usize>,
+        open: &str,
+        close: &str,
+    ) {
+        let start = open_range.start;
+        self.buffer.commit();
+        self.buffer.replace(close_range, close);
+        self.buffer.replace(open_range, open);
+        self.buffer.commit();
+        self.buffer.set_cursor_index(start);
+    }
+
+    /// Comments out the lines from `first` to `last`, or takes the comments off them if they're all commented out already.
+    fn toggle_comments(&mut self, first: usize, last: usize) {
+        let comment = match self.comment_string() {
+            Some(comment) => comment,
+            None => return self.show_error(EditorError::NoCommentString),
+        };
+        let contents = self.buffer.contents();
+        let ranges: Vec<Range<usize>> = (first..=last)
+            .map(|line| {
+                let start = contents.line_start(line).unwrap_or(contents.len());
+                start..motions::content_end(contents, line)
+            })
+            .collect();
+        let lines: Vec<String> = ranges
+            .iter()
+            .map(|range| contents.slice(range.clone()))
+            .collect();
+        let toggled = comment::toggle(&comment, &lines);
+
+        self.buffer.commit();
+        // Going from the bottom up keeps the ranges of the lines still to do where they were.
+        for ((range, line), toggled) in ranges.into_iter().zip(&lines).zip(&toggled).rev() {
+            if line != toggled {
+                self.buffer.replace(range, toggled);
+            }
+        }
+        self.buffer.commit();
+
+        self.buffer.goto_line(first);
+        self.move_by(Motion::FirstNonBlank);
+    }
+
+    /// Finds how comments are written in the buffer's type of file, from the configuration or else the usual way.
+    fn comment_string(&self) -> Option<CommentString> {
+        let file_type = self.file_type()?;
+        match self.comment_strings.iter().find(|(t, _)| t == file_type) {
+            Some((_, comment)) => Some(CommentString::parse(comment)),
+            None => CommentString::for_file_type(file_type),
+        }
+    }
+
+    /// The type of file in the buffer, which is its extension (or its name, for files like `Makefile` that don't have one.)
+    fn file_type(&self) -> Option<&str> {
+        let path = self.buffer.path()?;
+        path.extension()
+            .or_else(|| path.file_name())
+            .and_then(|name| name.to_str())
+    }
+
+    /// Runs the whole buffer through the formatter for its type of file, as a single change that can be undone.
+    ///
+    /// The cursor stays on the same line and column, which is usually close to where it was in the formatted text.
+    fn format(&mut self) -> Result<()> {
+        self.check_editable()?;
+        let file_type = self.file_type().ok_or(EditorError::NoFormatter)?;
+        let command = match self.formatters.iter().find(|(t, _)| t == file_type) {
+            Some((_, command)) => command.clone(),
+            None => return Err(EditorError::NoFormatter),
+        };
+        let input = self.buffer.contents().to_string();
+        let output = match run_shell(&command, Some(input.clone())) {
+            Ok(output) => output,
+            Err(EditorError::ShellCommandFailed(reason)) => {
+                return Err(EditorError::FormatterFailed(reason))
+            }
+            Err(e) => return Err(e),
+        };
+        // A formatter that prints nothing has almost certainly gone wrong, rather than wanting the file emptied.
+        if output == input || (output.is_empty() && !input.is_empty()) {
+            return Ok(());
+        }
+
+        let cursor = self.buffer.cursor();
+        self.buffer.commit();
+        self.buffer.replace(0..input.len(), &output);
+        self.buffer.commit();
+        self.buffer.goto(cursor.line, cursor.column);
+        Ok(())
+    }
+
+    /// Works out where the cursor is drawn, with its column counted in cells of the display rather than in bytes.
+    fn cursor_cell(&self) -> Position {
+        let cursor = self.displayed_cursor();
+        Position::new(
+            cursor.line,
+            self.buffer.display_column(cursor, self.view.tab_width()),
+        )
+    }
+
+    /// Moves the cursor onto the row of the display below or above it (like Vim's `gj` and `gk`), which is only a different line when lines aren't wrapped.
+    fn move_display_row(&mut self, down: bool) {
+        let cell = self.cursor_cell();
+        let breaks = self.view.line_breaks(&self.buffer, cell.line);
+        let (row, _) = self.view.wrapped_cell(&self.buffer, cell);
+        // Like moving up and down a line, the cursor keeps to the cell it started going up and down from, rather than the one
+        // that a shorter row left it in.
+        let offset = self
+            .buffer
+            .desired_cell(self.view.tab_width())
+            .saturating_sub(breaks[row]);
+        let (line, row_start) = match down {
+            true if row + 1 < breaks.len() => (cell.line, breaks[row + 1]),
+            true if cell.line + 1 < self.buffer.num_lines() => (cell.line + 1, 0),
+            false if row > 0 => (cell.line, breaks[row - 1]),
+            false if cell.line > 0 => {
+                let breaks = self.view.line_breaks(&self.buffer, cell.line - 1);
+                (cell.line - 1, breaks.last().copied().unwrap_or(0))
+            }
+            _ => return,
+        };
+
+        // The cursor stays on the row it lands on, even when that row is shorter than the one it came from.
+        let desired = row_start.saturating_add(offset);
+        let mut target = desired;
+        if let Some(next_row) = self
+            .view
+            .line_breaks(&self.buffer, line)
+            .into_iter()
+            .find(|&start| start > row_start)
+        {
+            target = cmp::min(target, next_row - 1);
+        }
+        let column = self.buffer.column_at(line, target, self.view.tab_width());
+        self.buffer.goto(line, column);
+        self.buffer.set_desired_cell(desired);
+    }
+
+    /// Moves the cursor with a motion, starting from wherever the cursor is shown on the display.
+    fn move_by(&mut self, motion: Motion) {
+        let from = self.buffer.index_of(self.displayed_cursor());
+        let to = motion.apply(self.buffer.contents(), from);
+        self.buffer.set_cursor_index(to);
+    }
+
+    /// Looks up the motion made by a sequence of keys that started with `first`.
+    ///
+    /// Sequences that we don't know about are dropped, like they are in Vim.
+    fn sequence_motion(first: char, event: &Event) -> Option<Motion> {
+        match (first, event) {
+            (
+                'g',
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('g'),
+                    ..
+                }),
+            ) => Some(Motion::DocumentStart),
+            _ => None,
+        }
+    }
+
+    /// Looks up the movement made by a key in Navigate or Visual mode, if it makes one.
+    fn movement(&self, event: &Event) -> Option<Movement> {
+        let code = match event {
+            Event::Key(KeyEvent {
+                code, modifiers, ..
+            }) if !modifiers.contains(KeyModifiers::CONTROL) => code,
+            _ => return None,
+        };
+        match code {
+            KeyCode::Char('h') => Some(Movement::Step(CursorMovement::Left)),
+            KeyCode::Char('j') => Some(Movement::Step(CursorMovement::Down)),
+            KeyCode::Char('k') => Some(Movement::Step(CursorMovement::Up)),
+            KeyCode::Char('l') => Some(Movement::Step(CursorMovement::Right)),
+            KeyCode::Char('w') => Some(Movement::Motion(Motion::NextWordStart)),
+            // The pager pages up with `b` instead.
+            KeyCode::Char('b') if !self.pager => Some(Movement::Motion(Motion::PreviousWordStart)),
+            KeyCode::Char('e') => Some(Movement::Motion(Motion::WordEnd)),
+            KeyCode::Char('0') => Some(Movement::Motion(Motion::LineStart)),
+            KeyCode::Char('^') => Some(Movement::Motion(Motion::FirstNonBlank)),
+            KeyCode::Char('$') => Some(Movement::Motion(Motion::LineEnd)),
+            KeyCode::Char('G') => Some(Movement::Motion(Motion::DocumentEnd)),
+            KeyCode::Char('}') => Some(Movement::Motion(Motion::ParagraphForward)),
+            KeyCode::Char('{') => Some(Movement::Motion(Motion::ParagraphBackward)),
+            KeyCode::Char('%') => Some(Movement::Motion(Motion::MatchingBracket)),
+            KeyCode::Char(';') => self
+                .last_find
+                .map(|search| Movement::Motion(Motion::RepeatFind(search))),
+            KeyCode::Char(',') => self
+                .last_find
+                .map(|search| Movement::Motion(Motion::RepeatFind(search.reversed()))),
+            _ => None,
+        }
+    }
+
+    /// Picks out a digit of a count typed in before a command in Navigate or Visual mode.
+    ///
+    /// A count can't start with 0, which moves to the start of the line instead.
+    fn count_digit(&self, event: &Event) -> Option<usize> {
+        if !matches!(self.mode, EditorMode::Navigate | EditorMode::Visual { .. }) {
+            return None;
+        }
+        match event {
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(c @ '0'..='9'),
+                ..
+            }) if *c != '0' || self.count.is_some() => c.to_digit(10).map(|d| d as usize),
+            _ => None,
+        }
+    }
+
+    /// Takes the count typed in for a command, multiplying it by the count typed in before an operator (so `2d3w` deletes 6 words.)
+    fn take_count(&mut self, operator_count: Option<usize>) -> Option<usize> {
+        match (operator_count, self.count.take()) {
+            (None, None) => None,
+            (before, after) => Some(before.unwrap_or(1).saturating_mul(after.unwrap_or(1))),
+        }
+    }
+
+    /// Moves the cursor as many times as the count says to, then applies any operator that was waiting for a motion over the text that it moved across.
+    fn make_movement(&mut self, movement: Movement) {
+        let operator = self.operator.take();
+        let count = self.take_count(operator.and_then(|(_, count)| count));
+        let from = self.buffer.index_of(self.displayed_cursor());
+        // Long jumps can be gone back from with `Ctrl-O`.
+        if matches!(
+            movement,
+            Movement::Motion(
+                Motion::DocumentStart
+                    | Motion::DocumentEnd
+                    | Motion::ParagraphForward
+                    | Motion::ParagraphBackward
+                    | Motion::MatchingBracket
+            ) | Movement::Mark { .. }
+        ) {
+            self.buffer.push_jump();
+        }
+        // A character or bracket that isn't on the line cancels the operator, rather than leaving it to take in the character under the cursor.
+        if let Movement::Motion(
+            motion @ (Motion::Find(_) | Motion::RepeatFind(_) | Motion::MatchingBracket),
+        ) = movement
+        {
+            if motion.apply(self.buffer.contents(), from) == from {
+                return;
+            }
+        }
+
+        let (to, kind) = match movement {
+            // Like in Vim, `cw` stops at the end of the word, rather than taking the blanks after it too.
+            Movement::Motion(Motion::NextWordStart)
+                if matches!(operator, Some((Operator::Change, _)))
+                    && !self
+                        .buffer
+                        .contents()
+                        .char_at(from)
+                        .is_some_and(char::is_whitespace) =>
+            {
+                let contents = self.buffer.contents();
+                let mut to = motions::end_of_word(contents, from);
+                for _ in 1..count.unwrap_or(1) {
+                    to = Motion::WordEnd.apply(contents, to);
+                }
+                (to, MotionKind::Inclusive)
+            }
+            // A count takes `gg` and `G` to that line instead.
+            Movement::Motion(motion @ (Motion::DocumentStart | Motion::DocumentEnd))
+                if count.is_some() =>
+            {
+                self.buffer.goto_line(count.unwrap_or(1) - 1);
+                self.move_by(Motion::FirstNonBlank);
+                (self.buffer.cursor_index(), motion.kind())
+            }
+            Movement::Motion(motion) => {
+                let mut last = from;
+                for _ in 0..count.unwrap_or(1) {
+                    last = self.buffer.index_of(self.displayed_cursor());
+                    self.move_by(motion);
+                }
+                // Like in Vim, moving up and down after `$` keeps to the end of each line, however long it is.
+                if motion == Motion::LineEnd {
+                    self.buffer.set_desired_cell(usize::MAX);
+                }
+                let to =
+                    motion.operator_end(self.buffer.contents(), last, self.buffer.cursor_index());
+                (to, motion.kind())
+            }
+            Movement::Mark { to, linewise } => {
+                self.buffer.goto(to.line, to.column);
+                let kind = match linewise {
+                    true => {
+                        self.move_by(Motion::FirstNonBlank);
+                        MotionKind::Linewise
+                    }
+                    false => MotionKind::Exclusive,
+                };
+                (self.buffer.cursor_index(), kind)
+            }
+            Movement::Step(step) => {
+                // An operator can take in the last character of a line, even though the cursor can't move past it.
+                for _ in 0..count.unwrap_or(1) {
+                    self.buffer
+                        .move_cursor(step, operator.is_some(), self.view.tab_width());
+                }
+                let kind = match step {
+                    CursorMovement::Left | CursorMovement::Right => MotionKind::Exclusive,
+                    CursorMovement::Up | CursorMovement::Down => MotionKind::Linewise,
+                };
+                (self.buffer.cursor_index(), kind)
+            }
+        };
+
+        if let Some((operator, _)) = operator {
+            let range = TextRange::from_motion(self.buffer.contents(), from, to, kind);
+            self.buffer.set_cursor_index(from);
+            if !range.range.is_empty() {
+                self.apply_operator(operator, range);
+            }
+        }
+    }
+
+    /// Finishes off an operator with a key that isn't a motion, which cancels it unless it's the operator again (like `dd`.)
+    fn complete_operator(&mut self, operator: Operator, count: Option<usize>, event: &Event) {
+        let key = match event {
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                ..
+            }) => *c,
+            _ => {
+                self.count = None;
+                return;
+            }
+        };
+        match (operator, key) {
+            (Operator::Yank, 'y')
+            | (Operator::Delete, 'd')
+            | (Operator::Change, 'c')
+            | (Operator::ShiftRight, '>')
+            | (Operator::ShiftLeft, '<')
+            | (Operator::Filter, '!')
+            | (Operator::Comment, 'c')
+            | (Operator::Surround, 's') => {
+                let count = self.take_count(count);
+                self.apply_to_lines(operator, count);
+            }
+            (Operator::Yank, 's') if self.editable() => {
+                self.operator = Some((Operator::Surround, count))
+            }
+            (Operator::Delete, 's') => self.surround = Some(Surround::Delete),
+            (Operator::Change, 's') => self.surround = Some(Surround::Change),
+            // The operator carries on waiting through the rest of a motion like `gg`, `'a` or `t)`.
+            (_, 'g' | '\'' | '`' | 'f' | 't' | 'F' | 'T') => {
+                self.operator = Some((operator, count));
+                self.pending_key = Some(key);
+            }
+            _ => self.count = None,
+        }
+    }
+
+    /// Finds the bracket under the cursor (or just before it in Edit mode) and the one that it pairs up with, if it does.
+    fn matching_brackets(&self) -> Vec<usize> {
+        let contents = self.buffer.contents();
+        let idx = self.buffer.cursor_index();
+        let mut candidates = vec![idx];
+        if let (EditorMode::Edit, true) = (&self.mode, idx > 0) {
+            candidates.push(contents.previous_grapheme(idx));
+        }
+        candidates
+            .into_iter()
+            .find_map(|bracket| {
+                let partner = motions::bracket_partner(contents, bracket, BRACKET_SCAN_LIMIT)?;
+                Some(vec![bracket, partner])
+            })
+            .unwrap_or_default()
+    }
+
+    /// Starts selecting text in Visual mode, from where the cursor is.
+    fn start_visual(&mut self, kind: VisualKind) {
+        let anchor = self.buffer.index_of(self.displayed_cursor());
+        self.mode = EditorMode::Visual { anchor, kind };
+    }
+
+    /// Switches Visual mode over to selecting another `kind` of text, or leaves it if it's already selecting that kind.
+    fn switch_visual(&mut self, kind: VisualKind) {
+        if let EditorMode::Visual {
+            anchor,
+            kind: current,
+        } = self.mode
+        {
+            self.mode = match current == kind {
+                true => EditorMode::Navigate,
+                false => EditorMode::Visual { anchor, kind },
+            };
+        }
+    }
+
+    /// Finds the cells of the display taken up by the character at an index, which is one cell at the end of a line.
+    fn cells_of(&self, index: usize) -> Range<usize> {
+        let contents = self.buffer.contents();
+        let tab_width = self.view.tab_width();
+        let line = contents.line_of(index);
+        let line_start = contents.line_start(line).unwrap_or(0);
+        let start = self
+            .buffer
+            .display_column(Position::new(line, index - line_start), tab_width);
+        let end = match contents.char_at(index) {
+            Some('\r' | '\n') | None => start,
+            Some(_) => self.buffer.display_column(
+                Position::new(line, contents.next_grapheme(index) - line_start),
+                tab_width,
+            ),
+        };
+        start..cmp::max(end, start + 1)
+    }
+
+    /// Works out the rectangle with the `anchor` in one corner and the cursor in the other.
+    fn block(&self, anchor: usize) -> Block {
+        let contents = self.buffer.contents();
+        let cursor = self.buffer.index_of(self.displayed_cursor());
+        let (first, last) = (contents.line_of(anchor), contents.line_of(cursor));
+        let (from, to) = (self.cells_of(anchor), self.cells_of(cursor));
+        // After `$`, the cursor keeps to the end of every line it moves onto, and so does the block.
+        let to_end = self.buffer.desired_cell(self.view.tab_width()) == usize::MAX;
+        let end = match to_end {
+            true => usize::MAX,
+            false => cmp::max(from.end, to.end),
+        };
+        Block {
+            lines: cmp::min(first, last)..=cmp::max(first, last),
+            cells: cmp::min(from.start, to.start)..end,
+            to_end,
+        }
+    }
+
+    /// Finds the text in each line of a block, from the top down.
+    fn block_ranges(&self, block: &Block) -> Vec<Range<usize>> {
+        let tab_width = self.view.tab_width();
+        block
+            .lines
+            .clone()
+            .map(|line| self.buffer.cell_span(line, block.cells.clone(), tab_width))
+            .collect()
+    }
+
+    /// Finds the text covered by the selection in Visual mode, which includes the characters under both ends.
+    ///
+    /// A block is made up of a range on each of its lines.
+    fn selection(&self) -> Vec<Range<usize>> {
+        let (anchor, kind) = match self.mode {
+            EditorMode::Visual { anchor, kind } => (anchor, kind),
+            _ => return Vec::new(),
+        };
+        match kind {
+            VisualKind::Block => self.block_ranges(&self.block(anchor)),
+            _ => vec![self.selected_range(anchor, kind).range],
+        }
+    }
+
+    /// Finds the text between the `anchor` and the cursor, as characters or as whole lines.
+    fn selected_range(&self, anchor: usize, kind: VisualKind) -> TextRange {
+        let contents = self.buffer.contents();
+        let cursor = self.buffer.index_of(self.displayed_cursor());
+        match kind {
+            VisualKind::Characters => TextRange::selection(contents, anchor, cursor),
+            _ => {
+                let (first, last) = (contents.line_of(anchor), contents.line_of(cursor));
+                TextRange::lines(contents, cmp::min(first, last), cmp::max(first, last))
+            }
+        }
+    }
+
+    /// Applies an operator to the selection, leaving Visual mode.
+    fn apply_to_selection(&mut self, operator: Operator) {
+        let (anchor, kind) = match self.mode {
+            EditorMode::Visual { anchor, kind } => (anchor, kind),
+            _ => return,
+        };
+        let block = self.block(anchor);
+        let range = self.selected_range(anchor, kind);
+        self.mode = EditorMode::Navigate;
+        match (kind, operator) {
+            (VisualKind::Block, Operator::Yank | Operator::Delete | Operator::Change) => {
+                self.apply_to_block(operator, block)
+            }
+            _ => self.apply_operator(operator, range),
+        }
+    }
+
+    /// Leaves Visual mode for the prompt, with the selected lines as the range of the command to type in.
+    fn command_on_selection(&mut self) {
+        let anchor = match self.mode {
+            EditorMode::Visual { anchor, .. } => anchor,
+            _ => return,
+        };
+        let contents = self.buffer.contents();
+        let (start, end) = (contents.line_of(anchor), self.buffer.cursor().line);
+        self.start_range_command(cmp::min(start, end), cmp::max(start, end), "");
+    }
+
+    /// Yanks, deletes or changes the text in each line of a block, which goes into the register as a block too.
+    fn apply_to_block(&mut self, operator: Operator, block: Block) {
+        let name = self.register.take();
+        let ranges = self.block_ranges(&block);
+        let contents = self.buffer.contents();
+        let pieces: Vec<_> = ranges
+            .iter()
+            .map(|range| contents.slice(range.clone()))
+            .collect();
+        self.registers.store(name, Register::block(&pieces));
+
+        let top_left = ranges.first().map_or(0, |range| range.start);
+        if operator != Operator::Yank {
+            self.buffer.commit();
+            // Going from the bottom up leaves the ranges still to delete where they were.
+            for range in ranges.into_iter().rev().filter(|range| !range.is_empty()) {
+                self.buffer.delete(range);
+            }
+        }
+        self.buffer.set_cursor_index(top_left);
+        match operator {
+            Operator::Change => self.start_block_insert(&block, block.cells.start, false, false),
+            Operator::Delete => self.buffer.commit(),
+            _ => (),
+        }
+    }
+
+    /// Starts typing in front of a block (like Vim's `I` in Visual block mode), or after it (like `A`.)
+    fn insert_into_block(&mut self, append: bool) {
+        let block = match self.mode {
+            EditorMode::Visual {
+                anchor,
+                kind: VisualKind::Block,
+            } => self.block(anchor),
+            _ => return,
+        };
+        self.mode = EditorMode::Navigate;
+        self.buffer.commit();
+        let cell = match append {
+            true => block.cells.end,
+            false => block.cells.start,
+        };
+        let to_end = append && block.to_end;
+        let first = *block.lines.start();
+        // The block's corner is on its first line, so that line always reaches the cell.
+        let index = match to_end {
+            true => self.buffer.line_range(first).map(|range| range.end),
+            false => self.insertion_point(first, cell, true),
+        };
+        if let Some(index) = index {
+            self.buffer.set_cursor_index(index);
+            self.start_block_insert(&block, cell, append, to_end);
+        }
+    }
+
+    /// Goes into Edit mode at the cursor, to type text that's copied onto the rest of the block's lines afterwards.
+    fn start_block_insert(&mut self, block: &Block, cell: usize, pad: bool, to_end: bool) {
+        self.block_insert = Some(BlockInsert {
+            start: self.buffer.cursor_index(),
+            lines: block.lines.start() + 1..=*block.lines.end(),
+            cell,
+            pad,
+            to_end,
+        });
+        self.mode = EditorMode::Edit;
+    }
+
+    /// Copies the text typed into the first line of a block onto the rest of its lines, unless it went onto another line.
+    fn finish_block_insert(&mut self) {
+        let insert = match self.block_insert.take() {
+            Some(insert) => insert,
+            None => return,
+        };
+        let contents = self.buffer.contents();
+        let cursor = self.buffer.cursor_index();
+        if cursor <= insert.start || contents.line_of(cursor) != contents.line_of(insert.start) {
+            return;
+        }
+        let text = contents.slice(insert.start..cursor);
+        for line in insert.lines {
+            let index = match insert.to_end {
+                true => self.buffer.line_range(line).map(|range| range.end),
+                false => self.insertion_point(line, insert.cell, insert.pad),
+            };
+            if let Some(index) = index {
+                self.buffer.replace(index..index, &text);
+            }
+        }
+        self.buffer.set_cursor_index(insert.start);
+    }
+
+    /// Finds where text goes into a line to start at a cell of the display.
+    ///
+    /// A line that doesn't reach into the cell is padded out to it with spaces if `pad` is set, and is left alone otherwise.
+    fn insertion_point(&mut self, line: usize, cell: usize, pad: bool) -> Option<usize> {
+        let tab_width = self.view.tab_width();
+        let line_start = self.buffer.contents().line_start(line)?;
+        let length = self.buffer.line_length(line);
+        let width = self
+            .buffer
+            .display_column(Position::new(line, length), tab_width);
+        match (width.cmp(&cell), pad) {
+            (cmp::Ordering::Greater, _) => {
+                Some(line_start + self.buffer.column_at(line, cell, tab_width))
+            }
+            (cmp::Ordering::Equal, true) => Some(line_start + length),
+            (_, false) => None,
+            (cmp::Ordering::Less, true) => {
+                let end = line_start + length;
+                self.buffer.replace(end..end, &" ".repeat(cell - width));
+                Some(end + cell - width)
+            }
+        }
+    }
+
+    /// Applies an operator to a range of the buffer.
+    fn apply_operator(&mut self, operator: Operator, range: TextRange) {
+        let name = self.register.take();
+        match operator {
+            Operator::Yank => {
+                let text = self.buffer.contents().slice(range.range.clone());
+                self.registers.store(
+                    name,
+                    Register::new(Self::register_text(text, range.linewise), range.linewise),
+                );
+
+                // Yanking whole lines keeps the cursor in its column, on the first of them.
+                match range.linewise {
+                    true => {
+                        let first = range.without_line_ending(self.buffer.contents()).start;
+                        self.buffer.goto_line(self.buffer.contents().line_of(first));
+                    }
+                    false => self.buffer.set_cursor_index(range.range.start),
+                }
+            }
+            Operator::ShiftRight | Operator::ShiftLeft | Operator::Filter | Operator::Comment => {
+                let contents = self.buffer.contents();
+                let first = range.without_line_ending(contents).start;
+                // The last line is the one holding the last character, which may be more than one byte long.
+                let end = range.range.end;
+                let last = end - contents.char_before(end).map_or(0, char::len_utf8);
+                let last = cmp::max(first, last);
+                let (first, last) = (contents.line_of(first), contents.line_of(last));
+                match operator {
+                    Operator::Filter => self.start_filter(first, last),
+                    Operator::Comment => self.toggle_comments(first, last),
+                    _ => self.shift_lines(first, last, operator == Operator::ShiftRight),
+                }
+            }
+            Operator::Surround => {
+                let contents = self.buffer.contents();
+                let mut inner = range.without_line_ending(contents);
+                // Whole lines are surrounded from their indent, and the space that a motion like `w` moves over is left outside.
+                let text = contents.slice(inner.clone());
+                let end = inner.start + text.trim_end().len();
+                if range.linewise {
+                    inner.start += text.len() - text.trim_start().len();
+                }
+                inner.end = cmp::max(inner.start, end);
+                self.surround = Some(Surround::Add(inner));
+            }
+            Operator::Delete | Operator::Change => {
+                // Whatever came before is undone separately from the deletion.
+                self.buffer.commit();
+                let removed = match operator {
+                    // Changing whole lines leaves an empty line behind to type into.
+                    Operator::Change => range.without_line_ending(self.buffer.contents()),
+                    _ => range.range.clone(),
+                };
+                let text = self.buffer.delete(removed);
+                self.registers.store(
+                    name,
+                    Register::new(Self::register_text(text, range.linewise), range.linewise),
+                );
+
+                // Anything typed in place of changed text is undone along with it.
+                match operator {
+                    Operator::Change => self.mode = EditorMode::Edit,
+                    _ => {
+                        self.buffer.commit();
+                        if range.linewise {
+                            self.move_by(Motion::FirstNonBlank);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Tidies up text on its way into a register, so that whole lines always end with a line ending of their own.
+    fn register_text(text: String, linewise: bool) -> String {
+        match linewise && !text.ends_with('\n') {
+            true => format!("{}\n", text.trim_start_matches(['\r', '\n'])),
+            false => text,
+        }
+    }
+
+    /// Applies an operator to the line that the cursor is on, and to the lines after it if there's a count (like Vim's `dd`, `yy` and `cc`.)
+    fn apply_to_lines(&mut self, operator: Operator, count: Option<usize>) {
+        let first = self.buffer.cursor().line;
+        let last = first + count.unwrap_or(1).saturating_sub(1);
+        let last = cmp::min(last, self.buffer.num_lines().saturating_sub(1));
+        let range = TextRange::lines(self.buffer.contents(), first, last);
+
+        // There's nothing to delete from an empty buffer, but yanking from it still gives an empty line.
+        if operator == Operator::Delete && range.range.is_empty() {
+            return;
+        }
+        self.apply_operator(operator, range);
+    }
+
+    /// Starts Edit mode after the character under the cursor (like Vim's `a`), or at the end of the line (like `A`.)
+    fn append(&mut self, at_end: bool) {
+        let contents = self.buffer.contents();
+        let cursor = self.buffer.index_of(self.displayed_cursor());
+        let idx = match (at_end, contents.char_at(cursor)) {
+            (true, _) => motions::content_end(contents, self.buffer.cursor().line),
+            (false, Some('\r' | '\n') | None) => cursor,
+            (false, Some(_)) => contents.next_grapheme(cursor),
+        };
+        self.buffer.set_cursor_index(idx);
+        self.mode = EditorMode::Edit;
+    }
+
+    /// Opens up an empty line below the one the cursor is on (like Vim's `o`) or above it (like `O`), and starts Edit mode on it.
+    fn open_line(&mut self, below: bool) {
+        let contents = self.buffer.contents();
+        let line = self.buffer.cursor().line;
+        let idx = match below {
+            true => motions::content_end(contents, line),
+            false => contents.line_start(line).unwrap_or(0),
+        };
+
+        // The new line is undone along with whatever gets typed into it.
+        self.buffer.commit();
+        self.buffer.set_cursor_index(idx);
+        self.buffer.insert('\n');
+        if !below {
+            self.buffer.set_cursor_index(idx);
+        }
+        self.mode = EditorMode::Edit;
+    }
+
+    /// Finds the characters from the cursor onwards that a command like `x` acts on, as many as the count says but never past the end of the line.
+    fn cursor_chars(&self, count: Option<usize>) -> Range<usize> {
+        let contents = self.buffer.contents();
+        let start = self.buffer.index_of(self.displayed_cursor());
+        let mut end = start;
+        for _ in 0..count.unwrap_or(1) {
+            match contents.char_at(end) {
+                Some('\r' | '\n') | None => break,
+                Some(_) => end = contents.next_grapheme(end),
+            }
+        }
+        start..end
+    }
+
+    /// Removes the character under the cursor (and the ones after it, if there's a count), keeping a copy in a register (like Vim's `x`.)
+    fn delete_char(&mut self, count: Option<usize>) {
+        let range = self.cursor_chars(count);
+        if range.is_empty() {
+            return;
+        }
+        self.buffer.commit();
+        let text = self.buffer.delete(range);
+        self.buffer.commit();
+        let name = self.register.take();
+        self.registers.store(name, Register::new(text, false));
+    }
+
+    /// Replaces the character under the cursor (and the ones after it, if there's a count) with `c`, without leaving Navigate mode (like Vim's `r`.)
+    ///
+    /// Nothing is replaced if there aren't enough characters left on the line.
+    fn replace_char(&mut self, c: char, count: Option<usize>) {
+        let range = self.cursor_chars(count);
+        let chars = self.buffer.contents().slice(range.clone()).chars().count();
+        if range.is_empty() || chars < count.unwrap_or(1) {
+            return;
+        }
+        let text = c.to_string().repeat(count.unwrap_or(1));
+        self.buffer.commit();
+        self.buffer.replace(range.clone(), &text);
+        self.buffer.commit();
+        self.buffer
+            .set_cursor_index(range.start + text.len() - c.len_utf8());
+    }
+
+    /// Adds `delta` to the number under the cursor, or the next one after it on the line, leaving the cursor on its last digit (like
+    /// Vim's `Ctrl-A` and `Ctrl-X`.)
+    fn add_to_number(&mut self, delta: i64) {
+        let cursor = self.displayed_cursor();
+        let contents = self.buffer.contents();
+        let start = match contents.line_start(cursor.line) {
+            Some(start) => start,
+            None => return,
+        };
+        let line = contents.slice(start..start + self.buffer.line_length(cursor.line));
+        let (range, text) = match numbers::add(&line, cursor.column, delta) {
+            Some(change) => change,
+            None => return,
+        };
+        let range = start + range.start..start + range.end;
+        self.buffer.commit();
+        self.buffer.replace(range.clone(), &text);
+        self.buffer.commit();
+        self.buffer.set_cursor_index(range.start + text.len() - 1);
+    }
+
+    /// Switches the character under the cursor (and the ones after it, if there's a count) between upper and lower case, moving past them (like Vim's `~`.)
+    fn toggle_case(&mut self, count: Option<usize>) {
+        let range = self.cursor_chars(count);
+        if range.is_empty() {
+            return;
+        }
+        let text: String = self
+            .buffer
+            .contents()
+            .slice(range.clone())
+            .chars()
+            .flat_map(|c| match c.is_lowercase() {
+                true => c.to_uppercase().collect::<Vec<_>>(),
+                false => c.to_lowercase().collect(),
+            })
+            .collect();
+        self.buffer.commit();
+        self.buffer.replace(range.clone(), &text);
+        self.buffer.commit();
+
+        // The cursor stops on the last character of the line, rather than going past it.
+        let contents = self.buffer.contents();
+        let end = range.start + text.len();
+        let end = match contents.char_at(end) {
+            Some('\r' | '\n') | None => contents.previous_grapheme(end),
+            Some(_) => end,
+        };
+        self.buffer.set_cursor_index(end);
+    }
+
+    /// Puts the text from a register back into the buffer, either after the cursor (like Vim's `p`) or before it (like `P`.)
+    ///
+    /// Whole lines go after or before the line that the cursor is on, rather than into the middle of it.
+    fn paste(&mut self, after: bool) {
+        let name = self.register.take();
+        // Without a clipboard tool to read it with, the register has whatever was last copied from here.
+        if self.read_clipboard && self.registers.is_clipboard_name(name) {
+            if let Some(text) = clipboard::paste() {
+                self.registers.sync_clipboard(text);
+            }
+        }
+        let register = match self.registers.get(name) {
+            Some(register) => register.clone(),
+            None => {
+                self.show_error(format!("Nothing in register {}", name.unwrap_or('"')));
+                return;
+            }
+        };
+        if register.blockwise {
+            return self.paste_block(&register.text, after);
+        }
+
+        let contents = self.buffer.contents();
+        let cursor = self.buffer.index_of(self.displayed_cursor());
+        let line = self.buffer.cursor().line;
+        let (index, text) = match (register.linewise, after) {
+            (true, false) => (contents.line_start(line).unwrap_or(0), register.text),
+            (true, true) => match contents.line_start(line + 1) {
+                Some(start) => (start, register.text),
+                // There's no line ending to paste after on the last line, so the text brings its own.
+                None => (
+                    contents.len(),
+                    format!("\n{}", register.text.trim_end_matches('\n')),
+                ),
+            },
+            (false, false) => (cursor, register.text),
+            (false, true) => match contents.char_at(cursor) {
+                Some('\r' | '\n') | None => (cursor, register.text),
+                Some(_) => (contents.next_grapheme(cursor), register.text),
+            },
+        };
+
+        self.buffer.commit();
+        self.buffer.replace(index..index, &text);
+        self.buffer.commit();
+
+        // The cursor ends up on the first pasted line, or on the last pasted character if it all went into one line.
+        match register.linewise {
+            true => {
+                let first_line = index + usize::from(text.starts_with('\n'));
+                self.buffer.set_cursor_index(first_line);
+                self.move_by(Motion::FirstNonBlank);
+            }
+            false if !text.contains('\n') => {
+                let end = index + text.len();
+                self.buffer
+                    .set_cursor_index(self.buffer.contents().previous_grapheme(end));
+            }
+            false => self.buffer.set_cursor_index(index),
+        }
+    }
+
+    /// Puts a block back into the buffer, with each of its pieces going into the same cell of successive lines.
+    ///
+    /// Short lines are padded out to reach the cell, and lines are added to the end of the buffer if it runs out of them.
+    fn paste_block(&mut self, text: &str, after: bool) {
+        let cursor = self.buffer.index_of(self.displayed_cursor());
+        let first = self.buffer.cursor().line;
+        let cells = self.cells_of(cursor);
+        let at_line_end = matches!(
+            self.buffer.contents().char_at(cursor),
+            Some('\r' | '\n') | None
+        );
+        let cell = match after && !at_line_end {
+            true => cells.end,
+            false => cells.start,
+        };
+        let pieces: Vec<&str> = text.split('\n').collect();
+        let piece_width = |piece: &str| piece.chars().map(unicode::width).sum::<usize>();
+        let width = pieces
+            .iter()
+            .map(|piece| piece_width(piece))
+            .max()
+            .unwrap_or(0);
+
+        self.buffer.commit();
+        let mut top_left = None;
+        for (line, piece) in (first..).zip(pieces) {
+            if line >= self.buffer.num_lines() {
+                let end = self.buffer.contents().len();
+                self.buffer.replace(end..end, "\n");
+            }
+            let index = match self.insertion_point(line, cell, true) {
+                Some(index) => index,
+                None => continue,
+            };
+            top_left.get_or_insert(index);
+            // Pieces that go in front of other text are padded out to the width of the block, to keep that text lined up.
+            let text = match self.buffer.contents().char_at(index) {
+                Some('\r' | '\n') | None => piece.to_string(),
+                Some(_) => format!("{}{}", piece, " ".repeat(width - piece_width(piece))),
+            };
+            self.buffer.replace(index..index, &text);
+        }
+        self.buffer.commit();
+        self.buffer.set_cursor_index(top_left.unwrap_or(cursor));
+    }
+
+    /// Finishes off a sequence of keys that started with `first`, such as `gg`, `ZZ` or `"a`, returning whether the editor should quit.
+    fn complete_sequence(&mut self, first: char, event: &Event) -> bool {
+        if let Some(motion) = Self::sequence_motion(first, event) {
+            self.make_movement(Movement::Motion(motion));
+            return false;
+        }
+        let count = self.count;
+        // Only a register name, a mark or a character to find carries a count or an operator on to the command after it.
+        if !matches!(first, '"' | 'r' | '@' | '\'' | '`' | 'f' | 't' | 'F' | 'T') {
+            self.count = None;
+            self.operator = None;
+        }
+        let second = match event {
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                ..
+            }) => *c,
+            _ => {
+                self.count = None;
+                self.operator = None;
+                return false;
+            }
+        };
+        match (first, second) {
+            ('"', name) if Registers::is_valid_name(name) => self.register = Some(name),
+            ('r', c) => {
+                let count = self.count.take();
+                self.replace_char(c, count);
+            }
+            ('m', name) if Marks::is_valid_name(name) => self.buffer.set_mark(name),
+            ('m', name) if name.is_ascii_uppercase() => self.set_global_mark(name),
+            ('g', 'j' | 'k') => {
+                for _ in 0..count.unwrap_or(1) {
+                    self.move_display_row(second == 'j');
+                }
+            }
+            ('g', 'd') => self.ask_language_server(Request::Definition),
+            ('g', 'c') if self.editable() => match self.mode {
+                EditorMode::Visual { .. } => self.apply_to_selection(Operator::Comment),
+                _ => self.operator = Some((Operator::Comment, count)),
+            },
+            (']' | '[', 'c') => {
+                if let Err(e) = self.jump_to_change(first == ']', count.unwrap_or(1)) {
+                    self.show_error(e);
+                }
+            }
+            ('z', 't') => self.view.scroll_to_top(self.buffer.cursor().line),
+            ('z', 'z') => self.view.scroll_to_middle(self.buffer.cursor().line),
+            ('z', 'b') => self.view.scroll_to_bottom(self.buffer.cursor().line),
+            ('f' | 't' | 'F' | 'T', target) => {
+                let search = CharSearch {
+                    target,
+                    forward: first.is_ascii_lowercase(),
+                    till: matches!(first, 't' | 'T'),
+                };
+                self.last_find = Some(search);
+                self.make_movement(Movement::Motion(Motion::Find(search)));
+            }
+            ('\'' | '`', name) if name.is_ascii_uppercase() => {
+                self.jump_to_global_mark(name, first == '\'')
+            }
+            ('\'' | '`', name) => match self.buffer.mark(name) {
+                Some(to) => {
+                    let linewise = first == '\'';
+                    self.make_movement(Movement::Mark { to, linewise });
+                }
+                None => {
+                    self.count = None;
+                    self.operator = None;
+                    self.show_error("Mark not set");
+                }
+            },
+            ('q', name) if name.is_ascii_alphabetic() => {
+                self.recording = Some((name, Vec::new()));
+            }
+            ('@', name) if name == '@' || name.is_ascii_alphabetic() => {
+                let count = self.count.take();
+                return self.play_macro(name, count) == Some(Exit::Quit);
+            }
+            ('Z', 'Z') => return self.write_and_quit(),
+            ('Z', 'Q') => return true,
+            (WINDOW_KEY, key) => {
+                if let Err(e) = self.window_command(key) {
+                    self.show_error(e);
+                }
+            }
+            _ => (),
+        }
+        false
+    }
+
+    /// Makes sure that quitting won't throw away unsaved changes in any of the buffers.
+    fn check_unsaved(&self) -> Result<()> {
+        if self.buffer.is_modified() {
+            return Err(EditorError::UnsavedChanges);
+        }
+        match self.buffers.iter(&self.buffer).find(|b| b.is_modified()) {
+            Some(buffer) => Err(EditorError::UnsavedBuffer(buffer.name())),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks whether the editor can quit without throwing away any unsaved changes, telling the user if it can't.
+    fn can_quit(&mut self) -> bool {
+        match self.check_unsaved() {
+            Ok(()) => true,
+            Err(e) => {
+                self.show_error(e);
+                false
+            }
+        }
+    }
+
+    /// Writes the buffer if it has unsaved changes (like `ZZ` in Vim), returning whether the editor should quit.
+    ///
+    /// A buffer without a file name asks for one first, quitting once it's been written.
+    fn write_and_quit(&mut self) -> bool {
+        if !self.buffer.is_modified() {
+            return self.can_quit();
+        }
+        let result = match self.needs_path() {
+            true => self.start_save_as(true),
+            false => self.write_as(None, false).map(|_| self.can_quit()),
+        };
+        match result {
+            Ok(quit) => quit,
+            Err(e) => {
+                self.show_error(e);
+                false
+            }
+        }
+    }
+
+    /// The prompt shown before whatever is being typed in on the bottom row, in the modes that take some input.
+    fn prompt(&self) -> Option<&'static str> {
+        match self.mode {
+            EditorMode::Command => Some(":"),
+            EditorMode::Search { .. } => Some("/"),
+            EditorMode::SaveAs { .. } => Some("Save as: "),
+            EditorMode::Tag => Some("<"),
+            _ => None,
+        }
+    }
+
+    /// Lays out the status line, with the mode and file on the left and the cursor position on the right.
+    fn status_line(&self, width: usize) -> String {
+        let mode = self.mode_name();
+        // The buffer's place in the list is only worth showing when there's more than one.
+        let path = match self.buffers.len() {
+            1 => self.buffer.name(),
+            len => format!(
+                "[{}/{}] {}",
+                self.buffers.current() + 1,
+                len,
+                self.buffer.name()
+            ),
+        };
+        let modified = if self.buffer.is_modified() {
+            " [+]"
+        } else {
+            ""
+        };
+        let read_only = if self.buffer.is_read_only() && !self.pager {
+            " [RO]"
+        } else {
+            ""
+        };
+        let recording = match &self.recording {
+            Some((name, _)) => format!(" recording @{}", name),
+            None => String::new(),
+        };
+        let left = format!(" {}{}  {}{}{}", mode, recording, path, modified, read_only);
+
+        // Columns are counted in characters for the user's benefit, rather than the bytes the buffer uses.
+        let cursor = self.displayed_cursor();
+        let contents = self.buffer.contents();
+        let line_start = contents.line_start(cursor.line).unwrap_or(contents.len());
+        let column_end = cmp::min(line_start + cursor.column, contents.len());
+        let column = contents.slice(line_start..column_end).chars().count();
+        let right = match self.search_match(cursor) {
+            Some((number, total)) => format!(
+                "match {} of {}  {}:{} ",
+                number,
+                total,
+                cursor.line + 1,
+                column + 1
+            ),
+            None => format!("{}:{} ", cursor.line + 1, column + 1),
+        };
+
+        // The position is more use than the end of a long path, so the left hand side gives way first.
+        let right_width = right.chars().count();
+        let left: String = left
+            .chars()
+            .take(width.saturating_sub(right_width + 1))
+            .collect();
+        let padding = width.saturating_sub(left.chars().count() + right_width);
+        let status = format!("{}{}{}", left, " ".repeat(padding), right);
+        status.chars().take(width).collect()
+    }
+
+    /// Counts the matches of the last search while they're highlighted, if the cursor is at the start of one.
+    ///
+    /// A large buffer doesn't get counted, because that would mean searching all of it every time the screen is drawn.
+    fn search_match(&self, cursor: Position) -> Option<(usize, usize)> {
+        let pattern = self.last_search.as_ref()?;
+        if !self.highlight_search || self.buffer.is_large() {
+            return None;
+        }
+        search::match_number(&self.buffer, pattern, self.buffer.index_of(cursor))
+    }
+
+    /// Makes the gutter wide enough for the buffer's line numbers, and for marking lines that have changed or have problems.
+    fn fit_gutter(&mut self) {
+        self.view
+            .fit_gutter(self.buffer.num_lines(), self.buffer.has_signs());
+    }
+
+    /// Asks git which lines of the buffer's file have changed since the last commit, to mark them in the gutter.
+    ///
+    /// The buffers being compared are marked with the differences between them instead.
+    fn refresh_line_changes(&mut self) {
+        if let Some(comparison) = &mut self.comparison {
+            let current = self.buffers.current();
+            if current == comparison.old || current == comparison.new {
+                comparison.revisions = None;
+                return;
+            }
+        }
+        let changes = match self.buffer.path() {
+            Some(path) => git::line_changes(path),
+            None => Vec::new(),
+        };
+        self.buffer.set_line_changes(changes);
+        self.fit_gutter();
+    }
+
+    /// Scrolls the view so that the cursor is on the display, whatever it was that moved it.
+    fn scroll_to_cursor(&mut self) {
+        self.fit_gutter();
+        self.view.scroll_to(&self.buffer, self.cursor_cell());
+    }
+
+    /// Renders the editor to a stream, assuming that a TTY is on the other end.
+    fn render<S: Write>(&mut self, stream: &mut S) -> Result<()> {
+        self.update_diff();
+        self.scroll_to_cursor();
+        self.sync_diff_windows();
+
+        queue!(stream, cursor::Hide).map_err(EditorError::TermIo)?;
+
+        if let Some(text) = self.registers.take_outgoing() {
+            queue!(stream, style::Print(clipboard::osc52(&text))).map_err(EditorError::TermIo)?;
+        }
+
+        let highlights = match self.mode {
+            EditorMode::Search { .. } => search::matches_in(
+                &self.buffer,
+                self.command_line.text(),
+                self.view.visible_lines(),
+            ),
+            EditorMode::Confirm => self
+                .substitution
+                .as_ref()
+                .and_then(|substitution| substitution.current())
+                .map(|current| current.range.clone())
+                .into_iter()
+                .collect(),
+            _ => {
+                let mut highlights = self.selection();
+                if let (true, Some(pattern)) = (self.highlight_search, &self.last_search) {
+                    highlights.extend(search::matches_in(
+                        &self.buffer,
+                        pattern,
+                        self.view.visible_lines(),
+                    ));
+                }
+                highlights
+            }
+        };
+        // A `:s` command being typed in is shown on the text as if it had been run, with the replacements highlighted.
+        let preview = self.substitution_preview();
+        let mut rows = match &preview {
+            Some((buffer, replaced)) => self.render_windows(buffer, replaced, &[])?,
+            None => self.render_windows(&self.buffer, &highlights, &self.matching_brackets())?,
+        };
+        if self.start_screen && self.windows.len() == 1 {
+            let recent: Vec<PathBuf> = self
+                .recent_files()
+                .into_iter()
+                .take(START_SCREEN_FILES)
+                .map(|(path, _)| path)
+                .collect();
+            render_start_screen(&mut rows, &recent, self.area.columns.into(), &self.theme)?;
+        }
+        if let (Some(completion), Some(row)) = (&self.completion, rows.last_mut()) {
+            *row = render_completion(completion, usize::from(self.area.columns), &self.theme)?;
+        }
+        if let (Some(completion), EditorMode::Edit) = (&self.word_completion, &self.mode) {
+            let line = self.buffer.cursor().line;
+            let line_start = self.buffer.line_range(line).map_or(0, |range| range.start);
+            let start = Position::new(line, completion.start() - line_start);
+            let cell = Position::new(
+                line,
+                self.buffer.display_column(start, self.view.tab_width()),
+            );
+            let (column, row) = self.view.screen_position(&self.buffer, cell);
+            let columns = self.area.columns;
+            render_word_popup(&mut rows, completion, column, row, columns, &self.theme)?;
+        }
+
+        // The prompt and any messages take over the status line for as long as they're needed.
+        let bottom_row = self.area.rows;
+        let width = usize::from(self.area.columns);
+        let bottom_line = match (self.prompt(), &self.mode) {
+            (Some(prompt), _) => Some(format!("{}{}", prompt, self.command_line.text())),
+            (None, EditorMode::Confirm) => self
+                .substitution
+                .as_ref()
+                .and_then(|substitution| substitution.current())
+                .map(|current| format!("replace with {} (y/n/a/q/l)?", current.text)),
+            (None, EditorMode::Reload) => Some(format!(
+                "\"{}\" has changed since reading it: (r)eload, (o)verwrite or (k)eep?",
+                self.buffer.name()
+            )),
+            (None, EditorMode::Recover) => Some(format!(
+                "Found a swap file with unsaved changes to \"{}\": (r)ecover, (d)elete or (k)eep it?",
+                self.buffer.name()
+            )),
+            // The problem a linter found with the cursor's line is shown whenever there's nothing more pressing to say.
+            (None, EditorMode::Navigate) if self.message.is_none() => self
+                .buffer
+                .lint_on(self.buffer.cursor().line)
+                .map(|lint| lint.message.lines().next().unwrap_or("").to_string()),
+            (None, _) => self.message.clone(),
+        };
+        let is_error = self.message_is_error && self.message == bottom_line;
+        let mut status = Vec::new();
+        match bottom_line {
+            Some(line) => {
+                let style = match is_error {
+                    true => self.theme.error_message,
+                    false => self.theme.text,
+                };
+                let line = line.chars().take(width).collect::<String>();
+                queue!(status, style::PrintStyledContent(style.apply(line)))
+            }
+            None => queue!(
+                status,
+                style::PrintStyledContent(self.theme.status_line.apply(self.status_line(width)))
+            ),
+        }
+        .map_err(EditorError::TermIo)?;
+        rows.push(status);
+        self.screen.draw(stream, rows)?;
+        self.update_title(stream)?;
+
+        if let Some(prompt) = self.prompt() {
+            let column = cmp::min(
+                prompt.chars().count() + self.command_line.cursor_column(),
+                width.saturating_sub(1),
+            );
+            self.update_cursor_shape(stream)?;
+            queue!(
+                stream,
+                cursor::MoveTo(column.try_into().unwrap_or(u16::MAX), bottom_row),
+                cursor::Show
+            )
+            .map_err(EditorError::TermIo)?;
+            return stream.flush().map_err(EditorError::TermIo);
+        }
+
+        let (column, row) = self.view.screen_position(&self.buffer, self.cursor_cell());
+
+        self.update_cursor_shape(stream)?;
+        queue!(stream, cursor::MoveTo(column, row), cursor::Show).map_err(EditorError::TermIo)?;
+
+        stream.flush().map_err(EditorError::TermIo)
+    }
+
+    /// Works out what the `:s` command being typed in would do to the lines on the display, so that it can be seen before it's run.
+    ///
+    /// Returns a copy of the buffer with the replacements made, along with where they are in it.
+    /// Copying a large buffer every time a key is typed would be too slow, so it doesn't get a preview.
+    fn substitution_preview(&self) -> Option<(Buffer, Vec<Range<usize>>)> {
+        if !matches!(self.mode, EditorMode::Command)
+            || self.buffer.is_large()
+            || self.check_editable().is_err()
+        {
+            return None;
+        }
+        let (range, pattern, replacement, global) = match command::parse(self.command_line.text()) {
+            Ok(Command::Substitute {
+                range,
+                pattern,
+                replacement,
+                global,
+                ..
+            }) => (range, pattern, replacement, global),
+            _ => return None,
+        };
+        let pattern = match pattern.is_empty() {
+            true => self.last_search.clone()?,
+            false => pattern,
+        };
+        let regex = Regex::new(&pattern).ok()?;
+
+        // Only the lines on the display need replacing, since they're all that can be seen.
+        let (start, end) = (self.line_number(range.start), self.line_number(range.end));
+        let visible = self.view.visible_lines();
+        let first = cmp::max(cmp::min(start, end), visible.start);
+        let last = cmp::min(cmp::max(start, end), visible.end.saturating_sub(1));
+        if first > last {
+            return None;
+        }
+        let substitution =
+            Substitution::new(regex, replacement, global, &self.buffer, first..=last);
+        let (text, replaced) = substitution.preview(&self.buffer);
+        let mut buffer = Buffer::new(&text);
+        let cursor = self.buffer.cursor();
+        buffer.goto(cursor.line, cursor.column);
+        Some((buffer, replaced))
+    }
+
+    /// Changes the shape of the terminal's cursor to suit the mode, if it isn't that shape already.
+    ///
+    /// It's a bar wherever text is being typed in, an underline while typing over text in Replace mode or while `r` waits
+    /// for the character to replace one with, and a block the rest of the time.
+    fn update_cursor_shape<S: Write>(&mut self, stream: &mut S) -> Result<()> {
+        let shape = match (&self.mode, self.pending_key) {
+            (EditorMode::Edit, _) => CursorShape::Line,
+            _ if self.prompt().is_some() => CursorShape::Line,
+            (EditorMode::Replace, _) | (_, Some('r')) => CursorShape::UnderScore,
+            _ => CursorShape::Block,
+        };
+        if self.cursor_shape != Some(shape) {
+            queue!(stream, cursor::SetCursorShape(shape)).map_err(EditorError::TermIo)?;
+            self.cursor_shape = Some(shape);
+        }
+        Ok(())
+    }
+
+    /// Names the file being edited in the terminal's title (or its tab's), with a `+` when it has unsaved changes.
+    fn update_title<S: Write>(&mut self, stream: &mut S) -> Result<()> {
+        let name = match self.buffer.path() {
+            Some(path) => path.file_name().map_or_else(
+                || path.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            ),
+            None => "[No Name]".to_string(),
+        };
+        // A file name with escape sequences in it mustn't be able to end the title early.
+        let name: String = name.chars().filter(|c| !c.is_control()).collect();
+        let title = match self.buffer.is_modified() {
+            true => format!("{} + — te", name),
+            false => format!("{} — te", name),
+        };
+        if self.title.as_ref() != Some(&title) {
+            queue!(stream, terminal::SetTitle(&title)).map_err(EditorError::TermIo)?;
+            self.title = Some(title);
+        }
+        Ok(())
+    }
+
+    /// The name of the mode the editor is in, as it's shown on the status line.
+    pub fn mode_name(&self) -> &'static str {
+        match (&self.mode, self.pager) {
+            (EditorMode::Navigate, true) => "PAGER",
+            (EditorMode::Navigate, false) => "NAVIGATE",
+            (EditorMode::Edit, _) => "EDIT",
+            (EditorMode::Replace, _) => "REPLACE",
+            (EditorMode::Command, _) => "COMMAND",
+            (EditorMode::Search { .. }, _) => "SEARCH",
+            (EditorMode::Visual { kind, .. }, _) => match kind {
+                VisualKind::Characters => "VISUAL",
+                VisualKind::Lines => "VISUAL LINE",
+                VisualKind::Block => "VISUAL BLOCK",
+            },
+            (EditorMode::Confirm, _) => "CONFIRM",
+            (EditorMode::Reload, _) => "CHANGED",
+            (EditorMode::Recover, _) => "RECOVER",
+            (EditorMode::SaveAs { .. }, _) => "SAVE AS",
+            (EditorMode::Tag, _) => "TAG",
+        }
+    }
+
+    /// Draws every window onto the rows of the display, along with the status bars and borders between them.
+    ///
+    /// Only the focused window shows `highlights` and `brackets`, and it shows `buffer` in place of the one being edited, which
+    /// is what lets a preview be drawn in it.
+    fn render_windows(
+        &self,
+        buffer: &Buffer,
+        highlights: &[Range<usize>],
+        brackets: &[usize],
+    ) -> Result<Vec<Vec<u8>>> {
+        let arrangement = self.windows.arrange(self.area);
+        let mut rows = vec![Vec::new(); self.area.rows.into()];
+
+        for &(id, rect) in &arrangement.windows {
+            let rendered = match self.windows.get(id) {
+                Some(window) => match self.buffers.get(&self.buffer, window.buffer) {
+                    Some(buffer) => {
+                        window
+                            .view
+                            .render(buffer, window.cursor, &[], &[], &self.theme)?
+                    }
+                    None => continue,
+                },
+                None => {
+                    self.view
+                        .render(buffer, buffer.cursor(), highlights, brackets, &self.theme)?
+                }
+            };
+            for (row, rendered) in rows.iter_mut().skip(rect.y.into()).zip(rendered) {
+                row.extend(rendered);
+            }
+        }
+
+        for &(id, rect) in &arrangement.bars {
+            let buffer = match self.windows.get(id) {
+                Some(window) => self.buffers.get(&self.buffer, window.buffer),
+                None => Some(&self.buffer),
+            };
+            let label = match buffer {
+                Some(buffer) if buffer.is_modified() => format!(" {} [+]", buffer.name()),
+                Some(buffer) => format!(" {}", buffer.name()),
+                None => String::new(),
+            };
+            let label = format!("{:width$}", label, width = rect.columns.into())
+                .chars()
+                .take(rect.columns.into())
+                .collect::<String>();
+            let label = match id == self.windows.focused() {
+                true => self.theme.window_title_focused.apply(label),
+                false => self.theme.window_title.apply(label),
+            };
+            if let Some(row) = rows.get_mut(usize::from(rect.y)) {
+                queue!(
+                    row,
+                    cursor::MoveTo(rect.x, rect.y),
+                    style::PrintStyledContent(label)
+                )
+                .map_err(EditorError::TermIo)?;
+            }
+        }
+
+        for rect in &arrangement.borders {
+            for y in rect.y..rect.y + rect.rows {
+                if let Some(row) = rows.get_mut(usize::from(y)) {
+                    let border = self.theme.text.apply('│');
+                    queue!(
+                        row,
+                        cursor::MoveTo(rect.x, y),
+                        style::PrintStyledContent(border)
+                    )
+                    .map_err(EditorError::TermIo)?;
+                }
+            }
+        }
+
+        Ok(rows)
+    }
+
+    /// Writes the buffer to the file it came from.
+    ///
+    /// Unless it's forced, this won't write over changes that something else has made to the file since it was read.
+    fn write(&mut self, force: bool) -> Result<()> {
+        if !force && self.buffer.changed_on_disk() {
+            return Err(EditorError::FileChanged);
+        }
+        self.run_hooks(HookEvent::BufWritePre);
+        if self.normalize_on_save {
+            self.normalize();
+        }
+
+        if self.backup {
+            self.buffer.write_backup()?;
+        }
+        self.buffer.write()?;
+        self.remove_recovery_file();
+        self.refresh_line_changes();
+        self.start_lint();
+        self.run_hooks(HookEvent::BufWritePost);
+        Ok(())
+    }
+
+    /// Makes sure that the buffer can be changed, which it can't be in the pager or once it's been made read-only.
+    fn check_editable(&self) -> Result<()> {
+        match (self.pager, self.buffer.is_read_only()) {
+            (true, _) => Err(EditorError::ReadOnly),
+            (false, true) => Err(EditorError::ReadOnlyBuffer),
+            (false, false) => Ok(()),
+        }
+    }
+
+    /// Whether a key that changes the buffer should go ahead, telling the user why not if the buffer is read-only.
+    ///
+    /// The pager has keys of its own layered over the editing ones instead, so nothing needs saying there.
+    fn editable(&mut self) -> bool {
+        match self.check_editable() {
+            Ok(()) => true,
+            Err(EditorError::ReadOnly) => false,
+            Err(e) => {
+                self.show_error(e);
+                false
+            }
+        }
+    }
+
+    /// Makes every buffer that's open read-only, like starting Vim with `-R`.
+    pub fn set_read_only(&mut self) {
+        for buffer in self.buffers.iter_mut(&mut self.buffer) {
+            buffer.set_read_only(true);
+        }
+    }
+
+    /// Writes the buffer like `write`, but into `path` if one is given, which the buffer then belongs to.
+    fn write_as(&mut self, path: Option<PathBuf>, force: bool) -> Result<()> {
+        if self.pager {
+            return Err(EditorError::ReadOnly);
+        }
+        if let Some(path) = path {
+            self.buffer.set_path(path);
+        }
+        let has_formatter = self
+            .file_type()
+            .is_some_and(|file_type| self.formatters.iter().any(|(t, _)| t == file_type));
+        let formatted = match self.format_on_save && has_formatter {
+            true => self.format(),
+            false => Ok(()),
+        };
+
+        self.write(force)?;
+        if let Some(path) = self.buffer.path() {
+            self.show_message(format!("\"{}\" written", path.display()));
+        }
+        // A formatter that fails (on a syntax error, say) doesn't stop the file being written, but the user is told why it wasn't formatted.
+        if let Err(e) = formatted {
+            self.show_error(e);
+        }
+        Ok(())
+    }
+
+    /// Whether the buffer has nowhere to be written to, so the user has to be asked for a file name.
+    fn needs_path(&self) -> bool {
+        self.buffer.path().is_none() && !self.pager
+    }
+
+    /// Asks the user for a file name to write the buffer into, at a prompt on the bottom row.
+    fn start_save_as(&mut self, quit: bool) -> Result<bool> {
+        self.command_line.clear();
+        self.mode = EditorMode::SaveAs { quit };
+        Ok(false)
+    }
+
+    /// Writes the buffer into the file name typed in at the Save As prompt, returning whether the editor should quit.
+    fn finish_save_as(&mut self) -> bool {
+        let quit = matches!(self.mode, EditorMode::SaveAs { quit: true });
+        self.mode = EditorMode::Navigate;
+        let path = self.command_line.take();
+        if path.trim().is_empty() {
+            return false;
+        }
+
+        match self.write_as(Some(PathBuf::from(path)), false) {
+            Ok(()) => quit && self.can_quit(),
+            Err(e) => {
+                self.show_error(e);
+                false
+            }
+        }
+    }
+
+    /// Switches to the buffer for the file at `path`, opening it in a new buffer if it isn't open already.
+    ///
+    /// If `force` is set, a buffer that's already open is read back in from the file, throwing away any unsaved changes.
+    fn edit(&mut self, path: PathBuf, force: bool) -> Result<()> {
+        if let Some(index) = self.buffers.find(&self.buffer, &path) {
+            self.switch_buffer(index)?;
+            if force {
+                self.buffer = Buffer::from_path(path)?;
+                self.remove_recovery_file();
+                self.run_hooks(HookEvent::BufRead);
+                self.enter_buffer();
+            }
+            return Ok(());
+        }
+
+        let mut buffer = Buffer::from_path(path)?;
+        restore_cursor(&mut buffer, &self.recent_files);
+        self.leave_buffer();
+        self.buffers.add(&mut self.buffer, buffer);
+        self.run_hooks(HookEvent::BufRead);
+        self.enter_buffer();
+        Ok(())
+    }
+
+    /// Edits the file at `index` in the argument list, telling the user where they've got to.
+    fn edit_arg(&mut self, index: usize) -> Result<()> {
+        let path = self.args[index].clone();
+        self.edit(path, false)?;
+        self.arg_index = index;
+        self.show_message(format!(
+            "\"{}\" ({} of {})",
+            self.buffer.name(),
+            index + 1,
+            self.args.len()
+        ));
+        Ok(())
+    }
+
+    /// Gets ready to switch away from the current buffer.
+    ///
+    /// A buffer with unsaved changes keeps its recovery file, while the next buffer gets one of its own.
+    /// The recovery file is brought up to date first, since the buffer won't get another chance to write it while it's hidden.
+    fn leave_buffer(&mut self) {
+        self.remember_file();
+        match self.buffer.is_modified() {
+            true => {
+                if let Err(e) = self.write_recovery_file() {
+                    self.show_error(format!("Failed to write recovery file: {}", e));
+                }
+                self.swap_files.extend(self.recovery_path.take());
+            }
+            false => self.remove_recovery_file(),
+        }
+    }
+
+    /// Shows the buffer that has just been switched to, with the cursor wherever it was left.
+    fn enter_buffer(&mut self) {
+        self.refresh_line_changes();
+        self.view.center_on(self.cursor_cell());
+        self.check_file();
+        self.check_swap_file();
+        self.attach_language_server();
+        self.start_lint();
+        self.run_hooks(HookEvent::BufEnter);
+    }
+
+    /// Asks the user what to do if there's a swap file for the buffer that some other editor left behind, as long as they aren't in the middle of something else.
+    fn check_swap_file(&mut self) {
+        let swap_path = match self.buffer.swap_path() {
+            Some(path) => path,
+            None => return,
+        };
+        // The buffer picks its own swap file back up from when it was switched away from.
+        if let Some(index) = self.swap_files.iter().position(|p| *p == swap_path) {
+            self.recovery_path = Some(self.swap_files.swap_remove(index));
+            return;
+        }
+        if self.recovery_dir.is_none()
+            || self.pager
+            || !matches!(self.mode, EditorMode::Navigate)
+            || self.recovery_path.as_ref() == Some(&swap_path)
+        {
+            return;
+        }
+
+        match std::fs::read_to_string(&swap_path) {
+            // There's nothing to ask about if the changes made it into the file after all.
+            Ok(text) if self.buffer.contents() == &text.as_str() => {
+                let _ = std::fs::remove_file(&swap_path);
+            }
+            Ok(_) => self.mode = EditorMode::Recover,
+            Err(_) => (),
+        }
+    }
+
+    /// Puts what was left in the buffer's swap file into the buffer, as a change that can be undone.
+    ///
+    /// The swap file then carries on being used for the buffer, until it's written.
+    fn recover(&mut self) -> Result<()> {
+        let swap_path = match self.buffer.swap_path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let text = std::fs::read_to_string(&swap_path).map_err(EditorError::FileIo)?;
+        let cursor = self.buffer.cursor();
+        self.buffer.replace(0..self.buffer.contents().len(), &text);
+        self.buffer.goto(cursor.line, cursor.column);
+        self.recovery_path = Some(swap_path);
+        self.fit_gutter();
+        self.show_message(format!(
+            "Recovered unsaved changes to \"{}\"",
+            self.buffer.name()
+        ));
+        Ok(())
+    }
+
+    /// Leaves another editor's swap file where it is, keeping this buffer's unsaved changes in the recovery directory instead.
+    fn keep_swap_file(&mut self) {
+        self.recovery_path = self
+            .recovery_dir
+            .as_ref()
+            .map(|dir| dated_recovery_path(dir, &self.buffer));
+    }
+
+    /// Asks the user what to do if something else has changed the buffer's file, as long as they aren't in the middle of something else.
+    fn check_file(&mut self) {
+        if matches!(self.mode, EditorMode::Navigate) && !self.pager && self.buffer.changed_on_disk()
+        {
+            self.mode = EditorMode::Reload;
+        }
+    }
+
+    /// Catches up with whatever happened to the open files while the terminal was out of focus.
+    ///
+    /// Buffers without unsaved changes are quietly read in again, while the current buffer asks first if it has some. Other
+    /// buffers with unsaved changes ask when they're switched to.
+    fn check_files(&mut self) {
+        let current = self.buffers.current();
+        let mut reloaded = Vec::new();
+        for (i, buffer) in self.buffers.iter_mut(&mut self.buffer).enumerate() {
+            if i == current || buffer.is_modified() || !buffer.changed_on_disk() {
+                continue;
+            }
+            let path = match buffer.path() {
+                Some(path) => path.to_path_buf(),
+                None => continue,
+            };
+            let cursor = buffer.cursor();
+            // A file that can't be read any more is left to `check_file` to bring up when the buffer is switched to.
+            if let Ok(mut fresh) = Buffer::from_path(path) {
+                fresh.goto(cursor.line, cursor.column);
+                reloaded.push(fresh.name());
+                *buffer = fresh;
+            }
+        }
+
+        if matches!(self.mode, EditorMode::Navigate)
+            && !self.pager
+            && !self.buffer.is_modified()
+            && self.buffer.changed_on_disk()
+        {
+            match self.reload() {
+                Ok(()) => reloaded.push(self.buffer.name()),
+                Err(e) => return self.show_error(e),
+            }
+        }
+        self.check_file();
+
+        match reloaded.as_slice() {
+            [] => (),
+            [name] => self.show_message(format!("Reloaded \"{}\"", name)),
+            names => self.show_message(format!("Reloaded {} changed files", names.len())),
+        }
+    }
+
+    /// Reads the buffer's file in again, throwing away whatever hasn't been written but keeping the cursor where it was.
+    fn reload(&mut self) -> Result<()> {
+        let path = match self.buffer.path() {
+            Some(path) => path.to_path_buf(),
+            None => return Ok(()),
+        };
+        let cursor = self.buffer.cursor();
+        self.buffer = Buffer::from_path(path)?;
+        self.buffer.goto(cursor.line, cursor.column);
+        self.remove_recovery_file();
+        self.refresh_line_changes();
+        self.start_lint();
+        self.run_hooks(HookEvent::BufRead);
+        Ok(())
+    }
+
+    /// Switches to the buffer at `index` in the list, counting from zero.
+    fn switch_buffer(&mut self, index: usize) -> Result<()> {
+        if index >= self.buffers.len() {
+            return Err(EditorError::NoSuchBuffer(index + 1));
+        }
+        if index != self.buffers.current() {
+            self.leave_buffer();
+            self.buffers.switch(&mut self.buffer, index);
+            self.enter_buffer();
+        }
+        Ok(())
+    }
+
+    /// Closes the current buffer, as long as that won't throw away any unsaved changes (unless `force` is set.)
+    fn close_buffer(&mut self, force: bool) -> Result<()> {
+        if self.buffer.is_modified() && !force {
+            return Err(EditorError::UnsavedChanges);
+        }
+        self.remove_recovery_file();
+        self.remember_file();
+        // The buffers after this one move up the list, so the comparison would end up on the wrong ones.
+        self.stop_diff();
+        let closed = self.buffers.current();
+        self.buffers.close(&mut self.buffer);
+        self.enter_buffer();
+
+        // Any other windows showing the buffer move on with this one, and the buffers after it in the list move up.
+        let current = self.buffers.current();
+        for window in self.windows.iter_mut() {
+            match window.buffer.cmp(&closed) {
+                cmp::Ordering::Equal => window.buffer = current,
+                cmp::Ordering::Greater => window.buffer -= 1,
+                cmp::Ordering::Less => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Changes one of the buffer's options, or shows what it's set to if there's no `value`.
+    fn set_option(&mut self, option: &str, value: Option<String>) -> Result<()> {
+        match (option, value) {
+            ("ff" | "fileformat", None) => {
+                self.show_message(format!("fileformat={}", self.buffer.file_format().name()));
+            }
+            ("ff" | "fileformat", Some(value)) => {
+                self.check_editable()?;
+                let format =
+                    FileFormat::from_name(&value).ok_or(EditorError::InvalidOptionValue(value))?;
+                self.buffer.set_file_format(format);
+            }
+            ("so" | "scrolloff", Some(value)) => {
+                let scroll_off = value
+                    .parse()
+                    .map_err(|_| EditorError::InvalidOptionValue(value))?;
+                self.set_scroll_off(scroll_off);
+            }
+            ("mp" | "makeprg", None) => {
+                self.show_message(format!("makeprg={}", self.make_command));
+            }
+            ("mp" | "makeprg", Some(value)) => self.make_command = value,
+            ("wrap", None) => self.set_wrap(true),
+            ("nowrap", None) => self.set_wrap(false),
+            ("list", None) => self.set_list(true),
+            ("nolist", None) => self.set_list(false),
+            ("list!" | "invlist", None) => self.set_list(!self.view.list()),
+            ("lcs" | "listchars", None) => {
+                self.show_message(format!("listchars={}", self.view.list_chars()));
+            }
+            ("lcs" | "listchars", Some(value)) => {
+                let list_chars =
+                    ListChars::parse(&value).map_err(|_| EditorError::InvalidOptionValue(value))?;
+                self.set_list_chars(list_chars);
+            }
+            ("fcs" | "fillchars", None) => {
+                self.show_message(format!("fillchars={}", self.view.fill_chars()));
+            }
+            ("fcs" | "fillchars", Some(value)) => {
+                let fill_chars =
+                    FillChars::parse(&value).map_err(|_| EditorError::InvalidOptionValue(value))?;
+                self.set_fill_chars(fill_chars);
+            }
+            ("cul" | "cursorline", None) => self.set_cursor_line(true),
+            ("nocul" | "nocursorline", None) => self.set_cursor_line(false),
+            ("cursorline!" | "invcursorline", None) => {
+                self.set_cursor_line(!self.view.cursor_line())
+            }
+            ("cc" | "colorcolumn", None) => {
+                let columns: Vec<String> = self
+                    .view
+                    .color_columns()
+                    .iter()
+                    .map(|column| column.to_string())
+                    .collect();
+                self.show_message(format!("colorcolumn={}", columns.join(",")));
+            }
+            ("cc" | "colorcolumn", Some(value)) => {
+                let columns = view::parse_color_columns(&value)
+                    .map_err(|_| EditorError::InvalidOptionValue(value))?;
+                self.set_color_columns(columns);
+            }
+            ("ro" | "readonly", None) => self.buffer.set_read_only(true),
+            ("noro" | "noreadonly", None) => self.buffer.set_read_only(false),
+            (option, _) => return Err(EditorError::UnknownOption(option.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Runs the build command (with any extra `arguments`) and jumps to the first error in its output.
+    ///
+    /// The command's output isn't shown, since the errors in it are what matter.
+    fn make(&mut self, arguments: Option<String>) -> Result<()> {
+        let command = match arguments {
+            Some(arguments) => format!("{} {}", self.make_command, arguments),
+            None => self.make_command.clone(),
+        };
+        // Errors and the lines that point at where they are can go to different streams, so they're kept together.
+        let output = process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} 2>&1", command))
+            .stdin(Stdio::null())
+            .output()
+            .map_err(EditorError::FileIo)?;
+        self.load_errors(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Fills the quickfix list from a compiler's output, jumping to the first error if there are any.
+    fn load_errors(&mut self, output: &str) -> Result<()> {
+        self.quickfix = QuickfixList::parse(output);
+        match self.quickfix.is_empty() {
+            true => {
+                self.show_message("No errors".to_string());
+                Ok(())
+            }
+            false => self.jump_to_error(true),
+        }
+    }
+
+    /// Jumps to the next (or previous) error in the quickfix list, opening the file it's in and showing its message.
+    fn jump_to_error(&mut self, forward: bool) -> Result<()> {
+        if self.quickfix.is_empty() {
+            return Err(EditorError::NoErrors);
+        }
+        let found = match forward {
+            true => self.quickfix.next(),
+            false => self.quickfix.previous(),
+        };
+        let (index, entry) = found
+            .map(|(index, entry)| (index, entry.clone()))
+            .ok_or(EditorError::NoMoreErrors)?;
+
+        match self.buffer.path() == Some(entry.path.as_path()) {
+            true => self.buffer.push_jump(),
+            false => self.edit(entry.path, false)?,
+        }
+        self.goto(entry.line, entry.column);
+        self.show_message(format!(
+            "({} of {}) {}",
+            index + 1,
+            self.quickfix.len(),
+            entry.message
+        ));
+        Ok(())
+    }
+
+    /// Tells the language server for the buffer's type of file about it, starting the server first if it isn't running yet.
+    fn attach_language_server(&mut self) {
+        if let Err(e) = self.open_in_language_server() {
+            self.show_error(e);
+        }
+    }
+
+    fn open_in_language_server(&mut self) -> Result<()> {
+        let path = match self.buffer.path() {
+            Some(path) if !self.buffer.is_large() => path,
+            _ => return Ok(()),
+        };
+        let extension = match path.extension().and_then(|e| e.to_str()) {
+            Some(extension) => extension,
+            None => return Ok(()),
+        };
+        let command = match self.language_servers.iter().find(|(e, _)| e == extension) {
+            Some((_, command)) => command.clone(),
+            None => return Ok(()),
+        };
+        let language = lsp::language_id(extension).to_string();
+        let path = lsp::absolute_path(path);
+
+        let index = match self.servers.iter().position(|(c, _)| *c == command) {
+            Some(index) => index,
+            None => {
+                let root = env::current_dir().map_err(EditorError::LanguageServer)?;
+                let server =
+                    LanguageServer::start(&command, &root).map_err(EditorError::LanguageServer)?;
+                if self.servers.is_empty() {
+                    self.timers.after(
+                        Instant::now(),
+                        LANGUAGE_SERVER_POLL_INTERVAL,
+                        TimerEvent::PollLanguageServers,
+                    );
+                }
+                self.servers.push((command, server));
+                self.servers.len() - 1
+            }
+        };
+        let server = &mut self.servers[index].1;
+        if !server.is_open(&path) {
+            let text = self.buffer.contents().to_string();
+            server
+                .open(&path, &language, &text, self.buffer.revision())
+                .map_err(EditorError::LanguageServer)?;
+        }
+        Ok(())
+    }
+
+    /// Finds the running language server for the buffer, along with the path that the server knows the buffer by.
+    fn language_server_index(&self) -> Option<(usize, PathBuf)> {
+        let path = self.buffer.path()?;
+        let extension = path.extension()?.to_str()?;
+        let (_, command) = self.language_servers.iter().find(|(e, _)| e == extension)?;
+        let index = self.servers.iter().position(|(c, _)| c == command)?;
+        Some((index, lsp::absolute_path(path)))
+    }
+
+    /// Sends the buffer's language server the text of the buffer, if it's been changed since the server last had it.
+    fn sync_language_server(&mut self) -> Result<()> {
+        if let Some((index, path)) = self.language_server_index() {
+            let buffer = &self.buffer;
+            self.servers[index]
+                .1
+                .change(&path, buffer.revision(), || buffer.contents().to_string())
+                .map_err(EditorError::LanguageServer)?;
+        }
+        Ok(())
+    }
+
+    /// Asks the buffer's language server about the thing under the cursor, the answer to which turns up later.
+    fn ask_language_server(&mut self, request: Request) {
+        let asked = self.sync_language_server().and_then(|_| {
+            let (index, path) = self
+                .language_server_index()
+                .ok_or(EditorError::NoLanguageServer)?;
+            let line = self.buffer.cursor().line;
+            let character = lsp::utf16_len(&self.text_before_cursor());
+            self.servers[index]
+                .1
+                .ask(request, &path, line, character)
+                .map_err(EditorError::LanguageServer)
+        });
+        if let Err(e) = asked {
+            self.show_error(e);
+        }
+    }
+
+    /// The text on the cursor's line before the cursor.
+    fn text_before_cursor(&self) -> String {
+        let start = self
+            .buffer
+            .line_range(self.buffer.cursor().line)
+            .map_or(0, |range| range.start);
+        self.buffer
+            .contents()
+            .slice(start..self.buffer.cursor_index())
+    }
+
+    /// Picks up whatever the language servers have sent since last time, and acts on it.
+    fn poll_language_servers(&mut self) {
+        // A server that has stopped listening says so when it's polled, so failing to send it the changes isn't worth a message every time.
+        let _ = self.sync_language_server();
+        let notices: Vec<Notice> = self
+            .servers
+            .iter_mut()
+            .flat_map(|(_, server)| server.poll())
+            .collect();
+        for notice in notices {
+            if let Err(e) = self.handle_notice(notice) {
+                self.show_error(e);
+            }
+        }
+    }
+
+    /// Starts the linter for the buffer's type of file on the file as it was last written, leaving it to run in the background.
+    ///
+    /// Any run that hasn't finished with the file yet is forgotten about, since its results would be out of date.
+    fn start_lint(&mut self) {
+        let path = match self.buffer.path() {
+            Some(path) if path.exists() && !self.buffer.is_large() => path.to_path_buf(),
+            _ => return,
+        };
+        let command = match self
+            .file_type()
+            .and_then(|file_type| self.linters.iter().find(|(t, _)| t == file_type))
+        {
+            Some((_, command)) => command.clone(),
+            None => return,
+        };
+        self.lint_runs.retain(|run| run.path() != path);
+        self.lint_runs.push(LintRun::start(&command, &path));
+        self.timers.cancel(TimerEvent::PollLinters);
+        self.timers
+            .after(Instant::now(), LINT_POLL_INTERVAL, TimerEvent::PollLinters);
+    }
+
+    /// Marks the problems from any linters that have finished in the buffers they were run on, checking back later for the rest.
+    fn poll_linters(&mut self) {
+        let mut finished = Vec::new();
+        self.lint_runs.retain(|run| match run.poll() {
+            Some(lint) => {
+                finished.push((run.path().to_path_buf(), lint));
+                false
+            }
+            None => true,
+        });
+        for (path, lint) in finished {
+            let buffer = self
+                .buffers
+                .iter_mut(&mut self.buffer)
+                .find(|b| b.path() == Some(&path));
+            if let Some(buffer) = buffer {
+                buffer.set_lint(lint);
+            }
+        }
+        self.fit_window_gutters();
+        if !self.lint_runs.is_empty() {
+            self.timers
+                .after(Instant::now(), LINT_POLL_INTERVAL, TimerEvent::PollLinters);
+        }
+    }
+
+    /// Does whatever a language server's notice calls for, like showing the problems it found or jumping to a definition.
+    fn handle_notice(&mut self, notice: Notice) -> Result<()> {
+        match notice {
+            Notice::Diagnostics { path, diagnostics } => {
+                let buffer = self
+                    .buffers
+                    .iter_mut(&mut self.buffer)
+                    .find(|b| b.path().map(lsp::absolute_path).as_ref() == Some(&path));
+                if let Some(buffer) = buffer {
+                    buffer.set_diagnostics(diagnostics);
+                }
+            }
+            Notice::Definition(location) => {
+                match self.buffer.path().map(lsp::absolute_path).as_ref() == Some(&location.path) {
+                    true => self.buffer.push_jump(),
+                    false => self.edit(lsp::relative_path(&location.path), false)?,
+                }
+                let column = self.buffer.line_range(location.line).map_or(0, |range| {
+                    let line = self.buffer.contents().slice(range);
+                    lsp::byte_index(&line, location.character)
+                });
+                self.goto(location.line, column);
+            }
+            // There's only the one row to show it on.
+            Notice::Hover(text) => {
+                self.show_message(text.split_whitespace().collect::<Vec<_>>().join(" "))
+            }
+            Notice::Completion(words) => self.complete_word(&words),
+            Notice::Error(message) => self.show_error(message),
+        }
+        Ok(())
+    }
+
+    /// Finishes off the word before the cursor with what a language server suggested, as far as the suggestions all agree.
+    ///
+    /// When there's more than one, they're listed at the bottom of the display so that the user can see what to type next.
+    fn complete_word(&mut self, words: &[String]) {
+        // The user might have moved on by the time the suggestions turn up.
+        if !matches!(self.mode, EditorMode::Edit) {
+            return;
+        }
+        let before = self.text_before_cursor();
+        let typed = &before[before
+            .trim_end_matches(|c: char| c.is_alphanumeric() || c == '_')
+            .len()..];
+        let matching: Vec<&str> = words
+            .iter()
+            .map(String::as_str)
+            .filter(|word| word.starts_with(typed))
+            .collect();
+        let mut common = match matching.first() {
+            Some(first) => *first,
+            None => return self.show_error("No completions"),
+        };
+        for word in &matching[1..] {
+            while !word.starts_with(common) {
+                common = &common[..common.char_indices().last().map_or(0, |(i, _)| i)];
+            }
+        }
+        for c in common[typed.len()..].chars() {
+            self.buffer.insert(c);
+        }
+        if matching.len() > 1 {
+            self.show_message(matching.join("  "));
+        }
+    }
+
+    /// Finishes off the word before the cursor with the next (or previous) word from the buffers that starts the same way,
+    /// gathering the words up first if it's the first time.
+    fn complete_from_buffers(&mut self, forward: bool) {
+        if self.word_completion.is_none() {
+            let current = self.buffers.current();
+            let others = self
+                .buffers
+                .iter(&self.buffer)
+                .enumerate()
+                .filter(|&(i, _)| i != current)
+                .map(|(_, buffer)| buffer);
+            self.word_completion = WordCompletion::new(&self.buffer, others);
+        }
+        let completion = match &mut self.word_completion {
+            Some(completion) => completion,
+            None => return self.show_error("No completions"),
+        };
+        let start = completion.start();
+        let word = match forward {
+            true => completion.next(),
+            false => completion.previous(),
+        }
+        .to_string();
+        self.put_completed_word(start, &word);
+    }
+
+    /// Puts back what had been typed before going through the words, and puts the list away.
+    fn cancel_word_completion(&mut self) {
+        if let Some(completion) = self.word_completion.take() {
+            self.put_completed_word(completion.start(), completion.typed());
+        }
+    }
+
+    /// Replaces the word from `start` up to the cursor with `word`, leaving the cursor after it.
+    fn put_completed_word(&mut self, start: usize, word: &str) {
+        let end = self.buffer.cursor_index();
+        self.buffer.replace(start..end, word);
+        self.buffer.set_cursor_index(start + word.len());
+    }
+
+    /// Shows the list of open buffers, like Vim's `:ls` (but squeezed onto the bottom row.)
+    fn list_buffers(&mut self) {
+        let current = self.buffers.current();
+        let list = self
+            .buffers
+            .iter(&self.buffer)
+            .enumerate()
+            .map(|(i, buffer)| {
+                format!(
+                    "{}{} \"{}\"{}",
+                    i + 1,
+                    if i == current { "%" } else { "" },
+                    buffer.name(),
+                    if buffer.is_modified() { " [+]" } else { "" }
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+        self.show_message(list);
+    }
+
+    /// Carries out a command from the prompt, returning whether the editor should quit.
+    fn execute(&mut self, command: Command) -> Result<bool> {
+        match command {
+            // A buffer that doesn't belong to a file yet needs the user to pick one before it can be written.
+            Command::Write { path: None, .. } if self.needs_path() => self.start_save_as(false),
+            Command::WriteQuit { path: None, .. } if self.needs_path() => self.start_save_as(true),
+            Command::Write { path, force } => self.write_as(path, force).map(|_| false),
+            Command::WriteQuit { path, force } => {
+                self.write_as(path, force).map(|_| self.can_quit())
+            }
+            // With the display split, quitting only closes the focused window, since the buffer stays open.
+            Command::Quit { .. } if self.windows.len() > 1 => self.close_window().map(|_| false),
+            Command::Quit { force: true } => Ok(true),
+            Command::Quit { force: false } => self.check_unsaved().map(|_| true),
+            Command::Edit { path, force } => self.edit(path, force).map(|_| false),
+            Command::View(path) => {
+                self.edit(path, false)?;
+                self.buffer.set_read_only(true);
+                Ok(false)
+            }
+            Command::Split(split, path) => self.split_window(split, path).map(|_| false),
+            Command::CloseWindow => self.close_window().map(|_| false),
+            Command::DiffSplit(path) => self.diff_split(path).map(|_| false),
+            Command::DiffOff => {
+                self.stop_diff();
+                Ok(false)
+            }
+            Command::Format => self.format().map(|_| false),
+            Command::MakeSession { path, force } => self.make_session(path, force).map(|_| false),
+            Command::NextFile => match self.arg_index + 1 < self.args.len() {
+                true => self.edit_arg(self.arg_index + 1).map(|_| false),
+                false => Err(EditorError::LastFile),
+            },
+            Command::PreviousFile => match self.arg_index > 0 {
+                true => self.edit_arg(self.arg_index - 1).map(|_| false),
+                false => Err(EditorError::FirstFile),
+            },
+            Command::NextBuffer => self.switch_buffer(self.buffers.next()).map(|_| false),
+            Command::PreviousBuffer => self.switch_buffer(self.buffers.previous()).map(|_| false),
+            Command::Buffer(number) => self.switch_buffer(number - 1).map(|_| false),
+            Command::CloseBuffer { force } => self.close_buffer(force).map(|_| false),
+            Command::ListBuffers => {
+                self.list_buffers();
+                Ok(false)
+            }
+            Command::OldFiles => {
+                self.list_old_files();
+                Ok(false)
+            }
+            Command::ChangeDirectory(path) => self.change_directory(path).map(|_| false),
+            Command::PrintDirectory => {
+                let dir = env::current_dir().map_err(EditorError::FileIo)?;
+                self.show_message(dir.display().to_string());
+                Ok(false)
+            }
+            Command::NoHighlight => {
+                self.highlight_search = false;
+                Ok(false)
+            }
+            Command::Make(arguments) => self.make(arguments).map(|_| false),
+            Command::ErrorFile(path) => {
+                let output = std::fs::read_to_string(path).map_err(EditorError::FileIo)?;
+                self.load_errors(&output).map(|_| false)
+            }
+            Command::NextError => self.jump_to_error(true).map(|_| false),
+            Command::PreviousError => self.jump_to_error(false).map(|_| false),
+            Command::Shell(command) => {
+                self.shell_command = Some(command);
+                Ok(false)
+            }
+            Command::Filter { range, command } => self.filter_lines(range, &command).map(|_| false),
+            Command::InsertOutput(command) => self.read_command(&command).map(|_| false),
+            Command::Set { option, value } => self.set_option(&option, value).map(|_| false),
+            Command::SaveMacro { register, keys } => {
+                self.save_macro(register, &keys).map(|_| false)
+            }
+            Command::Substitute {
+                range,
+                pattern,
+                replacement,
+                global,
+                confirm,
+            } => self
+                .substitute(range, &pattern, replacement, global, confirm)
+                .map(|_| false),
+            Command::Sort {
+                range,
+                reverse,
+                numeric,
+                unique,
+            } => self
+                .rearrange_lines(range, |lines| {
+                    sort::sort_lines(lines, reverse, numeric, unique)
+                })
+                .map(|_| false),
+            Command::Reverse(range) => self
+                .rearrange_lines(range, |lines| lines.into_iter().rev().collect())
+                .map(|_| false),
+            Command::BufDo(commands) => self.run_in_each(false, &commands),
+            Command::ArgDo(commands) => self.run_in_each(true, &commands),
+        }
+    }
+
+    /// Starts off a command at the prompt that pipes some lines through a filter, for the user to finish typing (like Vim's `!j`.)
+    fn start_filter(&mut self, first: usize, last: usize) {
+        self.start_range_command(first, last, "!");
+    }
+
+    /// Starts off a command at the prompt with a range of lines in front of it, for the user to finish typing.
+    fn start_range_command(&mut self, first: usize, last: usize, command: &str) {
+        let range = match first == last {
+            true => format!("{}{}", first + 1, command),
+            false => format!("{},{}{}", first + 1, last + 1, command),
+        };
+        self.command_line.set_text(&range);
+        self.command_history.stop_browsing();
+        self.mode = EditorMode::Command;
+    }
+
+    /// Replaces some lines with what a shell command prints when they're piped through it (like `:%!sort`.)
+    fn filter_lines(&mut self, range: LineRange, command: &str) -> Result<()> {
+        self.check_editable()?;
+        let start = self.line_number(range.start);
+        let end = self.line_number(range.end);
+        let (first, last) = (cmp::min(start, end), cmp::max(start, end));
+        let lines = TextRange::lines(self.buffer.contents(), first, last).range;
+        let input = self.buffer.contents().slice(lines.clone());
+        let mut output = run_shell(command, Some(input.to_string()))?;
+
+        // A last line without a line ending doesn't get one from the filter either.
+        if !input.ends_with('\n') && output.ends_with('\n') {
+            output.pop();
+        }
+        self.buffer.commit();
+        self.buffer.replace(lines, &output);
+        self.buffer.commit();
+        self.buffer.goto_line(first);
+        self.move_by(Motion::FirstNonBlank);
+        Ok(())
+    }
+
+    /// Puts some lines into a new order, like sorting them, as one change that's undone in one go.
+    ///
+    /// The lines are handed over without their line endings, which are put back afterwards.
+    fn rearrange_lines(
+        &mut self,
+        range: LineRange,
+        rearrange: impl FnOnce(Vec<&str>) -> Vec<&str>,
+    ) -> Result<()> {
+        self.check_editable()?;
+        let start = self.line_number(range.start);
+        let end = self.line_number(range.end);
+        let (first, last) = (cmp::min(start, end), cmp::max(start, end));
+        let contents = self.buffer.contents();
+        let lines = contents.line_start(first).unwrap_or(contents.len())
+            ..contents.line_start(last + 1).unwrap_or(contents.len());
+        let text = contents.slice(lines.clone());
+
+        // Each line keeps its own ending, which is whatever comes straight after it in `text` since that's what it's a slice of.
+        let ending = |line: &str| {
+            let end = (line.as_ptr() as usize).checked_sub(text.as_ptr() as usize)? + line.len();
+            text.get(end..)?.strip_prefix("\r\n").map(|_| "\r\n")
+        };
+        let rearranged = rearrange(text.lines().collect());
+        let mut output = String::with_capacity(text.len());
+        for (i, line) in rearranged.iter().enumerate() {
+            output.push_str(line);
+            // The last line only has an ending if the last of the lines had one before.
+            if i + 1 < rearranged.len() || text.ends_with('\n') {
+                output.push_str(ending(line).unwrap_or("\n"));
+            }
+        }
+        if output != text {
+            self.buffer.commit();
+            self.buffer.replace(lines, &output);
+            self.buffer.commit();
+        }
+        self.buffer.goto_line(first);
+        self.move_by(Motion::FirstNonBlank);
+        Ok(())
+    }
+
+    /// Inserts what a shell command prints on new lines below the cursor's (like `:r !date`.)
+    fn read_command(&mut self, command: &str) -> Result<()> {
+        self.check_editable()?;
+        let output = run_shell(command, None)?;
+        if output.is_empty() {
+            return Ok(());
+        }
+
+        let line = self.buffer.cursor().line;
+        let end = TextRange::lines(self.buffer.contents(), line, line)
+            .range
+            .end;
+        let text = match self.buffer.contents().slice(0..end).ends_with('\n') {
+            true if output.ends_with('\n') => output,
+            true => format!("{}\n", output),
+            false => format!("\n{}", output.trim_end_matches('\n')),
+        };
+        self.buffer.commit();
+        self.buffer.replace(end..end, &text);
+        self.buffer.commit();
+        self.buffer.goto_line(line + 1);
+        self.move_by(Motion::FirstNonBlank);
+        Ok(())
+    }
+
+    /// Works out which line of the buffer an address points at, counting from zero.
+    fn line_number(&self, address: Address) -> usize {
+        let last = self.buffer.num_lines().saturating_sub(1);
+        match address {
+            Address::Current => self.buffer.cursor().line,
+            Address::Last => last,
+            Address::Line(line) => cmp::min(line.saturating_sub(1), last),
+        }
+    }
+
+    /// Replaces the matches of `pattern` on some lines, asking about each one first if `confirm` is set.
+    fn substitute(
+        &mut self,
+        range: LineRange,
+        pattern: &str,
+        replacement: String,
+        global: bool,
+        confirm: bool,
+    ) -> Result<()> {
+        self.check_editable()?;
+        // Like in Vim, an empty pattern is the last one searched for, and a new one becomes the one `n` looks for.
+        let pattern = match pattern.is_empty() {
+            true => self
+                .last_search
+                .clone()
+                .ok_or(EditorError::NoPreviousPattern)?,
+            false => pattern.to_string(),
+        };
+        let regex = Regex::new(&pattern).map_err(EditorError::InvalidPattern)?;
+        self.last_search = Some(pattern);
+
+        // A range that's been given backwards is turned around, rather than asking the user about it like Vim does.
+        let start = self.line_number(range.start);
+        let end = self.line_number(range.end);
+        let lines = cmp::min(start, end)..=cmp::max(start, end);
+        let mut substitution = Substitution::new(regex, replacement, global, &self.buffer, lines);
+
+        // All of the replacements are undone together, separately from whatever came before them.
+        self.buffer.commit();
+        match confirm {
+            true => {
+                self.substitution = Some(substitution);
+                self.mode = EditorMode::Confirm;
+                self.confirm_next();
+            }
+            false => {
+                substitution.replace_all(&mut self.buffer);
+                self.substitution = Some(substitution);
+                self.finish_substitution();
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the cursor onto the next match to ask the user about, or finishes up if there are none left.
+    fn confirm_next(&mut self) {
+        let found = match &mut self.substitution {
+            Some(substitution) => substitution.find_next(&self.buffer),
+            None => None,
+        };
+        match found {
+            Some(found) => self.buffer.set_cursor_index(found.start),
+            None => self.finish_substitution(),
+        }
+    }
+
+    /// Tells the user what a `:s` command did, leaving the cursor on the last line that it changed.
+    fn finish_substitution(&mut self) {
+        self.mode = EditorMode::Navigate;
+        let substitution = match self.substitution.take() {
+            Some(substitution) => substitution,
+            None => return,
+        };
+        self.buffer.commit();
+
+        if substitution.found() == 0 {
+            self.show_error(format!("Pattern not found: {}", substitution.pattern()));
+            return;
+        }
+        if let Some(line) = substitution.changed_line() {
+            self.buffer.goto_line(line);
+            self.move_by(Motion::FirstNonBlank);
+        }
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        let (replaced, lines) = (substitution.replaced(), substitution.lines_changed());
+        self.show_message(format!(
+            "{} substitution{} on {} line{}",
+            replaced,
+            plural(replaced),
+            lines,
+            plural(lines)
+        ));
+    }
+
+    /// Runs some commands in every buffer in the list, or on every file in the argument list if `args` is set, returning
+    /// whether the editor should quit.
+    ///
+    /// The changes made in each buffer are undone on their own, apart from whatever came before them.
+    /// Something going wrong in one buffer doesn't stop the commands from being run in the rest, and everything that went
+    /// wrong is reported together at the end.
+    fn run_in_each(&mut self, args: bool, commands: &[String]) -> Result<bool> {
+        let mut failures = Vec::new();
+        let mut index = 0;
+        // A command could close a buffer, so the number of them is looked at again each time around.
+        let count = |editor: &Self| match args {
+            true => editor.args.len(),
+            false => editor.buffers.len(),
+        };
+        while index < count(self) {
+            let entered = match args {
+                true => self.edit_arg(index),
+                false => self.switch_buffer(index),
+            };
+            index += 1;
+            if let Err(e) = entered {
+                failures.push(e.to_string());
+                continue;
+            }
+
+            self.buffer.commit();
+            for line in commands {
+                // Problems like a pattern that isn't found are only shown, rather than being returned.
+                self.message = None;
+                self.message_is_error = false;
+                let result = command::parse(line)
+                    .map_err(|e| e.to_string())
+                    .and_then(|command| self.execute(command).map_err(|e| e.to_string()));
+                match result {
+                    Ok(true) => return Ok(true),
+                    Ok(false) if self.message_is_error => {
+                        let message = self.message.clone().unwrap_or_default();
+                        failures.push(format!("{}: {}", self.buffer.name(), message));
+                    }
+                    Ok(false) => (),
+                    Err(e) => failures.push(format!("{}: {}", self.buffer.name(), e)),
+                }
+            }
+            self.buffer.commit();
+        }
+        match failures.is_empty() {
+            true => Ok(false),
+            false => Err(EditorError::FailedInFiles(failures)),
+        }
+    }
+
+    /// Runs whatever was typed in at the prompt, returning whether the editor should quit.
+    ///
+    /// Anything that goes wrong is shown to the user, rather than bringing the whole editor down.
+    fn run_command(&mut self) -> bool {
+        self.mode = EditorMode::Navigate;
+        let line = self.command_line.take();
+        if line.trim().is_empty() {
+            return false;
+        }
+        self.command_history.add(&line);
+
+        // A `:!` command is left in `shell_command` for the terminal to be handed over to it.
+        match command::parse(&line)
+            .map_err(|e| e.to_string())
+            .and_then(|command| self.execute(command).map_err(|e| e.to_string()))
+        {
+            Ok(quit) => quit,
+            Err(message) => {
+                self.show_error(message);
+                false
+            }
+        }
+    }
+
+    /// Carries out a line as if it had been typed at the prompt, returning whether the editor should quit.
+    ///
+    /// There's no terminal to hand over to a `:!` command from here, so it's run straight away and what it printed becomes the
+    /// message.
+    pub fn execute_line(&mut self, line: &str) -> std::result::Result<bool, String> {
+        let command = command::parse(line).map_err(|e| e.to_string())?;
+        let quit = self.execute(command).map_err(|e| e.to_string())?;
+        if let Some(command) = self.shell_command.take() {
+            self.run_shell_command(&command)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(quit)
+    }
+
+    /// Starts a search from wherever the cursor is.
+    fn start_search(&mut self) {
+        let origin = self.buffer.index_of(self.displayed_cursor());
+        self.command_line.clear();
+        self.search_history.stop_browsing();
+        self.mode = EditorMode::Search { origin };
+    }
+
+    /// Moves the cursor to the first match of the pattern typed in so far, or back to where it started if there isn't one.
+    ///
+    /// In a large buffer that could mean searching the whole of it on every key, so it waits for the search to be finished instead.
+    fn update_search(&mut self) {
+        let origin = match self.mode {
+            EditorMode::Search { origin } if !self.buffer.is_large() => origin,
+            _ => return,
+        };
+        match search::find_next(&self.buffer, self.command_line.text(), origin) {
+            Some(found) => self.buffer.set_cursor_index(found.range.start),
+            None => self.buffer.set_cursor_index(origin),
+        }
+    }
+
+    /// Finishes off the word being typed at the `:` prompt with the next (or previous) thing it could be.
+    ///
+    /// A word that can only be finished off one way is done with straight away, so that Tab goes on to complete inside a directory.
+    fn complete_command_line(&mut self, forward: bool) -> Option<Exit> {
+        if self.completion.is_none() {
+            self.completion = Completion::new(self.command_line.text());
+        }
+        let completion = self.completion.as_mut()?;
+        let line = match forward {
+            true => completion.next(),
+            false => completion.previous(),
+        };
+        self.command_line.set_text(&line);
+        if completion.candidates().len() == 1 {
+            self.completion = None;
+        }
+        None
+    }
+
+    /// Brings back a line typed in at the prompt before, for Up, Down and `Ctrl-R`, returning whether `key` was one of those.
+    ///
+    /// Up only goes back through lines starting with what's been typed, while `Ctrl-R` finds them with it anywhere in them.
+    fn recall_history(&mut self, key: &KeyEvent) -> bool {
+        let history = match self.mode {
+            EditorMode::Command => &mut self.command_history,
+            EditorMode::Search { .. } => &mut self.search_history,
+            _ => return false,
+        };
+        let typed = self.command_line.text();
+        let recalled = match (key.code, key.modifiers) {
+            (KeyCode::Up, _) => history.older(typed).map(str::to_string),
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                history.search_older(typed).map(str::to_string)
+            }
+            (KeyCode::Down, _) => history.newer(),
+            _ => return false,
+        };
+        if let Some(line) = recalled {
+            self.command_line.set_text(&line);
+        }
+        true
+    }
+
+    /// Gives up on a search, putting the cursor back where it was before the search started.
+    fn cancel_search(&mut self) {
+        if let EditorMode::Search { origin } = self.mode {
+            self.buffer.set_cursor_index(origin);
+        }
+        self.command_line.clear();
+        self.mode = EditorMode::Navigate;
+    }
+
+    /// Finishes a search, remembering the pattern so that `n` and `N` can look for it again.
+    ///
+    /// An empty pattern searches for the last one again, like it does in Vim.
+    fn finish_search(&mut self) {
+        let origin = match self.mode {
+            EditorMode::Search { origin } => origin,
+            _ => return,
+        };
+        self.mode = EditorMode::Navigate;
+        let pattern = self.command_line.take();
+        self.search_history.add(&pattern);
+        if !pattern.is_empty() {
+            self.last_search = Some(pattern);
+        }
+
+        self.buffer.set_cursor_index(origin);
+        self.search_again(true);
+    }
+
+    /// Moves the cursor to the next match of `pattern`, which is remembered for `n` and `N` like one typed in at the `/` prompt.
+    pub fn search(&mut self, pattern: String) {
+        self.last_search = Some(pattern);
+        self.search_again(true);
+    }
+
+    /// Moves the cursor to the next (or previous) match of the last search.
+    fn search_again(&mut self, forward: bool) {
+        let pattern = match &self.last_search {
+            Some(pattern) => pattern,
+            None => {
+                self.show_error("No previous search pattern");
+                return;
+            }
+        };
+
+        let from = self.buffer.index_of(self.displayed_cursor());
+        let found = match forward {
+            true => search::find_next(&self.buffer, pattern, from),
+            false => search::find_previous(&self.buffer, pattern, from),
+        };
+        match found {
+            Some(found) => {
+                self.highlight_search = true;
+                self.buffer.push_jump();
+                self.buffer.set_cursor_index(found.range.start);
+                match (found.wrapped, forward) {
+                    (true, true) => {
+                        self.show_message("Search hit BOTTOM, continuing at TOP".to_string())
+                    }
+                    (true, false) => {
+                        self.show_message("Search hit TOP, continuing at BOTTOM".to_string())
+                    }
+                    _ => (),
+                }
+            }
+            None => self.show_error(format!("Pattern not found: {}", pattern)),
+        }
+    }
+
+    /// Copies the buffer into a timestamped file in the recovery directory if it has unsaved changes, so that they can be recovered after a crash.
+    ///
+    /// Returns the path of the recovery file, if one was written.
+    pub fn write_recovery_file(&mut self) -> Result<Option<PathBuf>> {
+        let dir = match (&self.recovery_dir, self.buffer.is_modified()) {
+            (Some(dir), true) => dir.clone(),
+            _ => return Ok(None),
+        };
+
+        // Stick with the same file for the whole session, rather than leaving a trail of them behind.
+        let recovery_path = match &self.recovery_path {
+            Some(path) => path.clone(),
+            None => self
+                .buffer
+                .swap_path()
+                .unwrap_or_else(|| dated_recovery_path(&dir, &self.buffer)),
+        };
+        self.recovery_path = Some(recovery_path.clone());
+
+        if let Some(parent) = recovery_path.parent() {
+            std::fs::create_dir_all(parent).map_err(EditorError::FileIo)?;
+        }
+        // Anyone else who can read the directory shouldn't get to read what the user's typed before they've chosen to save it.
+        state::write_private(&recovery_path, &self.buffer.contents().to_string())
+            .map_err(EditorError::FileIo)?;
+        Ok(Some(recovery_path))
+    }
+
+    /// Copies the unsaved changes in every buffer into timestamped files in the recovery directory, for when the editor has
+    /// crashed.
+    ///
+    /// Returns where each buffer's changes went, or why they couldn't be written.
+    /// Swap files are left as they were, so reopening a file still offers to recover whatever was last kept in its swap file.
+    pub fn write_recovery_files(&mut self) -> Vec<Result<PathBuf>> {
+        let mut written = Vec::new();
+        let dir = match &self.recovery_dir {
+            Some(dir) => dir.clone(),
+            None => return written,
+        };
+
+        for (i, buffer) in self.buffers.iter(&self.buffer).enumerate() {
+            if !buffer.is_modified() {
+                continue;
+            }
+            let mut path = dated_recovery_path(&dir, buffer);
+            // Buffers for files with the same name would all go into the same file if they weren't told apart.
+            if written.iter().any(|w| w.as_ref().ok() == Some(&path)) {
+                path = PathBuf::from(format!("{}.{}", path.display(), i + 1));
+            }
+            let result = std::fs::create_dir_all(&dir)
+                .and_then(|_| state::write_private(&path, &buffer.contents().to_string()))
+                .map(|_| path)
+                .map_err(EditorError::FileIo);
+            written.push(result);
+        }
+        written
+    }
+
+    /// Writes the buffer into its file if it has unsaved changes, which is skipped while that would get in the way.
+    ///
+    /// Edit mode is left alone so that a half-typed change isn't split in two for undo, and a file that something else has changed is left for the user to decide about.
+    fn autosave(&mut self) -> Result<()> {
+        let ready = matches!(self.mode, EditorMode::Navigate)
+            && !self.pager
+            && self.buffer.is_modified()
+            && self.buffer.path().is_some()
+            && !self.buffer.changed_on_disk();
+        match ready {
+            true => self.write(false),
+            false => Ok(()),
+        }
+    }
+
+    /// Deletes the recovery file, once the changes in it are no longer needed.
+    pub fn remove_recovery_file(&mut self) {
+        if let Some(path) = self.recovery_path.take() {
+            // If it's already gone then that's fine too.
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    /// Deletes the recovery files for every buffer, for when the editor exits normally.
+    pub fn remove_recovery_files(&mut self) {
+        self.remove_recovery_file();
+        for path in self.swap_files.drain(..) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    /// Carries out whatever a timer asked for.
+    fn handle_timer(&mut self, event: TimerEvent) {
+        match event {
+            TimerEvent::ClearMessage => {
+                self.message = None;
+                self.message_is_error = false;
+            }
+            // Copying all of a large buffer out every time the user stops typing would be too slow, so it waits for a crash or
+            // for the buffer to be switched away from.
+            TimerEvent::WriteRecoveryFile if self.buffer.is_large() => (),
+            TimerEvent::WriteRecoveryFile => {
+                if let Err(e) = self.write_recovery_file() {
+                    self.show_error(format!("Failed to write recovery file: {}", e));
+                }
+            }
+            TimerEvent::Autosave => {
+                if let Err(e) = self.autosave() {
+                    self.show_error(format!("Failed to autosave: {}", e));
+                }
+            }
+            TimerEvent::KeyTimeout => self.keymap.time_out(self.map_mode()),
+            TimerEvent::CheckFile => {
+                self.check_file();
+                self.timers
+                    .after(Instant::now(), FILE_CHECK_INTERVAL, TimerEvent::CheckFile);
+            }
+            TimerEvent::PollLanguageServers => {
+                self.poll_language_servers();
+                self.timers.after(
+                    Instant::now(),
+                    LANGUAGE_SERVER_POLL_INTERVAL,
+                    TimerEvent::PollLanguageServers,
+                );
+            }
+            TimerEvent::PollLinters => self.poll_linters(),
+        }
+    }
+
+    /// Finds the mode that key mappings are looked up in, which there isn't one of while typing at a prompt or finishing a sequence.
+    fn map_mode(&self) -> Option<MapMode> {
+        if self.pending_key.is_some() {
+            return None;
+        }
+        match self.mode {
+            EditorMode::Navigate => Some(MapMode::Navigate),
+            EditorMode::Edit | EditorMode::Replace => Some(MapMode::Edit),
+            EditorMode::Visual { .. } => Some(MapMode::Visual),
+            _ => None,
+        }
+    }
+
+    /// Runs a key press through the key mappings, waiting a little while for the rest of a mapping that it might be the start of.
+    fn map_key(&mut self, key: KeyEvent) {
+        self.timers.cancel(TimerEvent::KeyTimeout);
+        if self.keymap.feed(self.map_mode(), key) {
+            self.timers
+                .after(Instant::now(), KEY_TIMEOUT, TimerEvent::KeyTimeout);
+        }
+    }
+
+    /// Adds a key that the user has typed onto the macro being recorded, if there is one.
+    fn record_key(&mut self, key: KeyEvent) {
+        if let Some((_, keys)) = &mut self.recording {
+            keys.push(key);
+        }
+    }
+
+    /// Stores the keys that have been recorded into their register, leaving off the `q` that stopped the recording.
+    fn stop_recording(&mut self) {
+        if let Some((name, mut keys)) = self.recording.take() {
+            if let Some(KeyEvent {
+                code: KeyCode::Char('q'),
+                ..
+            }) = keys.last()
+            {
+                keys.pop();
+            }
+            self.registers.record(name, keymap::format_keys(&keys));
+        }
+    }
+
+    /// Maps `keys` in Navigate mode onto the macro in a register, and saves the mapping in the configuration file.
+    ///
+    /// The macro is copied into the mapping as it stands, so it can be tidied up first by putting the register into a buffer,
+    /// editing it as text, and yanking it back (like `"qp` and then `0"qy$`.)
+    fn save_macro(&mut self, name: char, keys: &str) -> Result<()> {
+        let text = match self.registers.get(Some(name)) {
+            Some(register) if !register.text.is_empty() => register.text.clone(),
+            _ => return Err(EditorError::EmptyRegister(name)),
+        };
+        let binding = Binding::parse(MapMode::Navigate, keys, &text)
+            .map_err(EditorError::InvalidOptionValue)?;
+        if let Some(path) = &self.config_file {
+            config::save_binding(path, keys, MapMode::Navigate, &text)
+                .map_err(EditorError::Config)?;
+        }
+        self.keymap.add(binding);
+        self.show_message(format!("{} now plays register {}", keys, name));
+        Ok(())
+    }
+
+    /// Plays back the keys in a register `count` times over, as if they were being typed (`@@` plays the last one again.)
+    ///
+    /// Like in Vim, playing stops as soon as something goes wrong.
+    fn play_macro(&mut self, name: char, count: Option<usize>) -> Option<Exit> {
+        let name = match (name, self.last_macro) {
+            ('@', Some(last)) => last,
+            ('@', None) => {
+                self.show_error("No previously used register");
+                return None;
+            }
+            (name, _) => name,
+        };
+        let keys = match self.registers.get(Some(name)) {
+            Some(register) => keymap::parse_keys(&register.text).unwrap_or_default(),
+            None => return None,
+        };
+        self.last_macro = Some(name);
+        if self.macro_depth >= MAX_MACRO_DEPTH {
+            self.show_error("Too many macros playing inside each other");
+            return None;
+        }
+
+        self.macro_depth += 1;
+        self.message_is_error = false;
+        let mut exit = None;
+        'playing: for _ in 0..count.unwrap_or(1) {
+            for key in &keys {
+                exit = self.handle_event(Event::Key(*key));
+                if exit.is_some() || self.message_is_error {
+                    break 'playing;
+                }
+            }
+        }
+        self.macro_depth -= 1;
+        exit
+    }
+
+    /// Waits for the next event from the terminal, seeing to any timers that fire in the meantime.
+    ///
+    /// Nothing comes back if a timer leaves some keys from the key mappings ready to be handled first.
+    fn next_event<T>(&mut self, stream: &mut T) -> Result<Option<Event>>
+    where
+        T: Write + IsTty,
+    {
+        while let Some(timeout) = self.timers.timeout(Instant::now()) {
+            if event::poll(timeout).map_err(EditorError::TermIo)? {
+                break;
+            }
+
+            for timer in self.timers.expired(Instant::now()) {
+                self.handle_timer(timer);
+            }
+            if self.keymap.has_ready() {
+                return Ok(None);
+            }
+            self.render(stream)?;
+        }
+
+        let event = event::read().map_err(EditorError::TermIo)?;
+        self.timers.input(Instant::now());
+        if let Event::Key(key) = event {
+            self.record_key(key);
+        }
+        Ok(Some(event))
+    }
+
+    /// Runs the `Editor`'s main loop.
+    pub fn run<T>(&mut self, stream: &mut T) -> Result<Exit>
+    where
+        T: Write + IsTty,
+    {
+        // Running again after being suspended mustn't leave two of these going.
+        self.timers.cancel(TimerEvent::CheckFile);
+        self.timers
+            .after(Instant::now(), FILE_CHECK_INTERVAL, TimerEvent::CheckFile);
+        loop {
+            self.render(stream)?;
+            let event = match self.keymap.next_key() {
+                Some(key) => Event::Key(key),
+                None => match self.next_event(stream)? {
+                    Some(Event::Key(key)) => {
+                        self.map_key(key);
+                        continue;
+                    }
+                    Some(event) => event,
+                    None => continue,
+                },
+            };
+
+            if let Some(exit) = self.handle_event(event) {
+                return Ok(exit);
+            }
+        }
+    }
+
+    /// Does whatever an event from the terminal (or a key from the key bindings) asks for, returning whether the editor should stop running.
+    ///
+    /// Any hooks for the mode changing or the cursor moving are run once it's done.
+    fn handle_event(&mut self, event: Event) -> Option<Exit> {
+        let (mode, cursor) = (mem::discriminant(&self.mode), self.buffer.cursor());
+        if let Event::Key(_) = event {
+            self.start_screen = false;
+        }
+        let exit = match event {
+            Event::Key(key) if !self.repeating => self.handle_key(key),
+            _ => self.dispatch_event(event),
+        };
+
+        if mem::discriminant(&self.mode) != mode {
+            self.run_hooks(HookEvent::ModeChanged);
+        }
+        if self.at_rest() && self.buffer.cursor() != cursor {
+            self.run_hooks(HookEvent::CursorMoved);
+        }
+        exit
+    }
+
+    /// Does whatever a key asks for, keeping track of the keys that make up a change so that `.` can repeat it.
+    fn handle_key(&mut self, key: KeyEvent) -> Option<Exit> {
+        if self.at_rest() {
+            self.pending_change = Some((Vec::new(), self.buffer.revision()));
+        }
+        self.track_change_key(key);
+
+        let exit = self.dispatch_event(Event::Key(key));
+        if self.at_rest() {
+            if let Some((keys, revision)) = self.pending_change.take() {
+                if revision != self.buffer.revision() && is_repeatable(&keys) {
+                    self.last_change = Some(keys);
+                }
+            }
+        }
+        exit
+    }
+
+    /// Whether the editor is waiting for a new command in Navigate mode, rather than in the middle of one.
+    fn at_rest(&self) -> bool {
+        matches!(self.mode, EditorMode::Navigate)
+            && self.pending_key.is_none()
+            && self.operator.is_none()
+            && self.count.is_none()
+            && self.register.is_none()
+            && self.surround.is_none()
+    }
+
+    /// Adds a key onto the command being typed in, if it's being kept track of.
+    fn track_change_key(&mut self, key: KeyEvent) {
+        if let Some((keys, _)) = &mut self.pending_change {
+            keys.push(key);
+        }
+    }
+
+    /// Repeats the last change at the cursor, with `count` standing in for the count it was made with.
+    fn repeat_change(&mut self, count: Option<usize>) -> Option<Exit> {
+        let mut keys = match &self.last_change {
+            Some(keys) => keys.clone(),
+            None => return None,
+        };
+        if let Some(count) = count {
+            let digits = count
+                .to_string()
+                .chars()
+                .map(|c| KeyEvent::from(KeyCode::Char(c)))
+                .collect::<Vec<_>>();
+            let old_count = keys
+                .iter()
+                .take_while(|key| matches!(key.code, KeyCode::Char(c) if c.is_ascii_digit()))
+                .count();
+            keys.splice(..old_count, digits);
+            self.last_change = Some(keys.clone());
+        }
+
+        self.repeating = true;
+        let mut exit = None;
+        for key in keys {
+            exit = self.dispatch_event(Event::Key(key));
+            if exit.is_some() {
+                break;
+            }
+        }
+        self.repeating = false;
+        exit
+    }
+
+    /// Does whatever an event asks for, without keeping track of the keys.
+    fn dispatch_event(&mut self, event: Event) -> Option<Exit> {
+        if let Event::Resize(columns, rows) = event {
+            self.redraw(columns, rows);
+            return None;
+        }
+        if let Event::Mouse(mouse) = event {
+            self.handle_mouse(mouse);
+            return None;
+        }
+        // The user may well have been off changing files in another window, so have a look at them on the way back.
+        match event {
+            Event::FocusGained => {
+                self.check_files();
+                return None;
+            }
+            Event::FocusLost => return None,
+            Event::Paste(text) => {
+                self.handle_paste(&text);
+                return None;
+            }
+            _ => (),
+        }
+
+        // The second key of a sequence means whatever the sequence does, rather than what it would do on its own.
+        if let Some(first) = self.pending_key.take() {
+            if self.complete_sequence(first, &event) {
+                return Some(Exit::Quit);
+            }
+            return None;
+        }
+        if !matches!(self.mode, EditorMode::Tag) {
+            if let Some(surround) = self.surround.take() {
+                self.complete_surround(surround, &event);
+                return None;
+            }
+        }
+
+        if let Some(digit) = self.count_digit(&event) {
+            let count = self.count.unwrap_or(0).saturating_mul(10);
+            self.count = Some(count.saturating_add(digit));
+            return None;
+        }
+        if let Some(movement) = self.movement(&event) {
+            if matches!(self.mode, EditorMode::Navigate | EditorMode::Visual { .. }) {
+                self.make_movement(movement);
+                return None;
+            }
+        }
+        if let Some((operator, count)) = self.operator.take() {
+            self.complete_operator(operator, count, &event);
+            return None;
+        }
+
+        match self.mode {
+            EditorMode::Navigate => match event {
+                // The pager quits with `q` like `less` does, rather than recording macros.
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('q'),
+                    ..
+                }) if self.pager && self.can_quit() => return Some(Exit::Quit),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('q'),
+                    ..
+                }) if self.recording.is_some() => self.stop_recording(),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('q'),
+                    ..
+                }) if !self.pager => self.pending_key = Some('q'),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('@'),
+                    ..
+                }) => self.pending_key = Some('@'),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('.'),
+                    ..
+                }) if self.editable() => {
+                    let count = self.count.take();
+                    if let Some(exit) = self.repeat_change(count) {
+                        return Some(exit);
+                    }
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Esc, ..
+                }) => self.highlight_search = false,
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char(' '),
+                    ..
+                }) if self.pager => self.view.page_down(&mut self.buffer),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('b'),
+                    ..
+                }) if self.pager => self.view.page_up(&mut self.buffer),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('w'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                }) => self.pending_key = Some(WINDOW_KEY),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('z'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                }) => return Some(Exit::Suspend),
+                Event::Key(KeyEvent {
+                    code:
+                        KeyCode::Char(
+                            c @ ('g' | 'm' | 'z' | '\'' | '`' | 'f' | 't' | 'F' | 'T' | '[' | ']'),
+                        ),
+                    modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                    ..
+                }) => self.pending_key = Some(c),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('o'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                }) => {
+                    self.buffer.jump_back();
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('f'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                }) => self.view.page_down(&mut self.buffer),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('b'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                }) => self.view.page_up(&mut self.buffer),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('d'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                }) => self.view.half_page_down(&mut self.buffer),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('u'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                }) => self.view.half_page_up(&mut self.buffer),
+                // Terminals send `Ctrl-I` as a Tab.
+                Event::Key(KeyEvent {
+                    code: KeyCode::Tab, ..
+                }) => {
+                    self.buffer.jump_forward();
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('"'),
+                    ..
+                }) => self.pending_key = Some('"'),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('y'),
+                    ..
+                }) => self.operator = Some((Operator::Yank, self.count.take())),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('Z'),
+                    ..
+                }) => self.pending_key = Some('Z'),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('d'),
+                    ..
+                }) if self.editable() => {
+                    self.operator = Some((Operator::Delete, self.count.take()))
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('c'),
+                    ..
+                }) if self.editable() => {
+                    self.operator = Some((Operator::Change, self.count.take()))
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('>'),
+                    ..
+                }) if self.editable() => {
+                    self.operator = Some((Operator::ShiftRight, self.count.take()))
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('<'),
+                    ..
+                }) if self.editable() => {
+                    self.operator = Some((Operator::ShiftLeft, self.count.take()))
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('!'),
+                    ..
+                }) if self.editable() => {
+                    self.operator = Some((Operator::Filter, self.count.take()))
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char(c @ ('a' | 'x')),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                }) if self.editable() => {
+                    // A count too big to add is as big as it can be, which can't go wrong when it's negated.
+                    let count = i64::try_from(self.count.take().unwrap_or(1)).unwrap_or(i64::MAX);
+                    self.add_to_number(if c == 'a' { count } else { -count });
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('x'),
+                    ..
+                }) if self.editable() => {
+                    let count = self.count.take();
+                    self.delete_char(count);
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('r'),
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                }) if self.editable() => self.pending_key = Some('r'),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('K'),
+                    ..
+                }) => self.ask_language_server(Request::Hover),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('~'),
+                    ..
+                }) if self.editable() => {
+                    let count = self.count.take();
+                    self.toggle_case(count);
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('p'),
+                    ..
+                }) if self.editable() => self.paste(true),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('P'),
+                    ..
+                }) if self.editable() => self.paste(false),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('n'),
+                    ..
+                }) => self.search_again(true),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('N'),
+                    ..
+                }) => self.search_again(false),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('i'),
+                    ..
+                }) if self.editable() => self.mode = EditorMode::Edit,
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('a'),
+                    ..
+                }) if self.editable() => self.append(false),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('A'),
+                    ..
+                }) if self.editable() => self.append(true),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('I'),
+                    ..
+                }) if self.editable() => {
+                    self.move_by(Motion::FirstNonBlank);
+                    self.mode = EditorMode::Edit;
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('R'),
+                    ..
+                }) if self.editable() => {
+                    self.replaced.clear();
+                    self.mode = EditorMode::Replace;
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('o'),
+                    ..
+                }) if self.editable() => self.open_line(true),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('O'),
+                    ..
+                }) if self.editable() => self.open_line(false),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char(':'),
+                    ..
+                }) => {
+                    self.command_history.stop_browsing();
+                    self.mode = EditorMode::Command;
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('/'),
+                    ..
+                }) => self.start_search(),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('v'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                }) if !self.pager => self.start_visual(VisualKind::Block),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('v'),
+                    ..
+                }) if !self.pager => self.start_visual(VisualKind::Characters),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('V'),
+                    ..
+                }) if !self.pager => self.start_visual(VisualKind::Lines),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('r'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                }) if self.editable() => self.redo(),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('u'),
+                    ..
+                }) if self.editable() => self.undo(),
+                _ => (),
+            },
+            EditorMode::Edit => {
+                // Any other key keeps whichever word is in the buffer, and puts the list away.
+                if !is_word_completion_key(&event) {
+                    self.word_completion = None;
+                }
+                match event {
+                    Event::Key(KeyEvent {
+                        code: KeyCode::Esc, ..
+                    }) => {
+                        self.finish_block_insert();
+                        // Everything typed in one go is undone in one go.
+                        self.buffer.commit();
+                        self.mode = EditorMode::Navigate;
+                    }
+                    Event::Key(KeyEvent {
+                        code: KeyCode::Char(' '),
+                        modifiers: KeyModifiers::CONTROL,
+                        ..
+                    }) => self.ask_language_server(Request::Completion),
+                    Event::Key(KeyEvent {
+                        code: KeyCode::Char(c @ ('n' | 'p')),
+                        modifiers: KeyModifiers::CONTROL,
+                        ..
+                    }) => self.complete_from_buffers(c == 'n'),
+                    Event::Key(KeyEvent {
+                        code: KeyCode::Char('e'),
+                        modifiers: KeyModifiers::CONTROL,
+                        ..
+                    }) => self.cancel_word_completion(),
+                    Event::Key(KeyEvent {
+                        code: KeyCode::Char(c),
+                        ..
+                    }) => self.buffer.insert(c),
+                    Event::Key(KeyEvent {
+                        code: KeyCode::Enter,
+                        ..
+                    }) => self.buffer.insert('\n'),
+                    Event::Key(KeyEvent {
+                        code: KeyCode::Tab, ..
+                    }) => self.insert_tab(),
+                    Event::Key(KeyEvent {
+                        code: KeyCode::Backspace,
+                        ..
+                    }) => self.buffer.remove(),
+                    _ => (),
+                }
+            }
+            EditorMode::Replace => match event {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Esc, ..
+                }) => {
+                    self.buffer.commit();
+                    self.mode = EditorMode::Navigate;
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char(c),
+                    ..
+                }) => self.type_over(c),
+                // Like in vi, a new line is added rather than typing over anything.
+                Event::Key(KeyEvent {
+                    code: KeyCode::Enter,
+                    ..
+                }) => {
+                    self.buffer.insert('\n');
+                    self.replaced.push(None);
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Tab, ..
+                }) => match self.expand_tab {
+                    true => {
+                        let tab_width = self.view.tab_width();
+                        let spaces = tab_width - self.cursor_cell().column % tab_width;
+                        for _ in 0..spaces {
+                            self.type_over(' ');
+                        }
+                    }
+                    false => self.type_over('\t'),
+                },
+                Event::Key(KeyEvent {
+                    code: KeyCode::Backspace,
+                    ..
+                }) => self.untype(),
+                _ => (),
+            },
+            EditorMode::Visual { .. } => match event {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Esc, ..
+                }) => self.mode = EditorMode::Navigate,
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('v'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                }) => self.switch_visual(VisualKind::Block),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('v'),
+                    ..
+                }) => self.switch_visual(VisualKind::Characters),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('V'),
+                    ..
+                }) => self.switch_visual(VisualKind::Lines),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char(c @ ('I' | 'A')),
+                    ..
+                }) if self.editable() => self.insert_into_block(c == 'A'),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char(':'),
+                    ..
+                }) => self.command_on_selection(),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char(c @ ('g' | '\'' | '`' | 'f' | 't' | 'F' | 'T' | '[' | ']')),
+                    ..
+                }) => self.pending_key = Some(c),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('"'),
+                    ..
+                }) => self.pending_key = Some('"'),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('n'),
+                    ..
+                }) => self.search_again(true),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('N'),
+                    ..
+                }) => self.search_again(false),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('d' | 'x'),
+                    ..
+                }) if self.editable() => self.apply_to_selection(Operator::Delete),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('y'),
+                    ..
+                }) => self.apply_to_selection(Operator::Yank),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('c'),
+                    ..
+                }) if self.editable() => self.apply_to_selection(Operator::Change),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('>'),
+                    ..
+                }) if self.editable() => self.apply_to_selection(Operator::ShiftRight),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('<'),
+                    ..
+                }) if self.editable() => self.apply_to_selection(Operator::ShiftLeft),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('!'),
+                    ..
+                }) if self.editable() => self.apply_to_selection(Operator::Filter),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('S'),
+                    ..
+                }) if self.editable() => self.apply_to_selection(Operator::Surround),
+                _ => (),
+            },
+            EditorMode::Command => {
+                if let Event::Key(key) = event {
+                    match key.code {
+                        KeyCode::Tab => return self.complete_command_line(true),
+                        KeyCode::BackTab => return self.complete_command_line(false),
+                        _ => self.completion = None,
+                    }
+                    if self.recall_history(&key) {
+                        return None;
+                    }
+                    match self.command_line.handle(&key) {
+                        Input::Edited => self.command_history.stop_browsing(),
+                        Input::Submitted if self.run_command() => return Some(Exit::Quit),
+                        Input::Cancelled => {
+                            self.command_line.clear();
+                            self.mode = EditorMode::Navigate;
+                        }
+                        _ => (),
+                    }
+                    if let Some(command) = self.shell_command.take() {
+                        return Some(Exit::Shell(command));
+                    }
+                }
+            }
+            EditorMode::Search { .. } => {
+                if let Event::Key(key) = event {
+                    if self.recall_history(&key) {
+                        self.update_search();
+                        return None;
+                    }
+                    match self.command_line.handle(&key) {
+                        Input::Edited => {
+                            self.search_history.stop_browsing();
+                            self.update_search();
+                        }
+                        Input::Submitted => self.finish_search(),
+                        Input::Cancelled => self.cancel_search(),
+                        Input::Unchanged => (),
+                    }
+                }
+            }
+            EditorMode::SaveAs { .. } => {
+                if let Event::Key(key) = event {
+                    match self.command_line.handle(&key) {
+                        Input::Submitted if self.finish_save_as() => return Some(Exit::Quit),
+                        Input::Cancelled => {
+                            self.command_line.clear();
+                            self.mode = EditorMode::Navigate;
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            EditorMode::Tag => {
+                if let Event::Key(key) = event {
+                    match self.command_line.handle(&key) {
+                        Input::Submitted => self.finish_tag(),
+                        Input::Cancelled => {
+                            self.command_line.clear();
+                            self.surround = None;
+                            self.mode = EditorMode::Navigate;
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            EditorMode::Reload => match event {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('r'),
+                    ..
+                }) => {
+                    self.mode = EditorMode::Navigate;
+                    if let Err(e) = self.reload() {
+                        self.show_error(e);
+                    }
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('o'),
+                    ..
+                }) => {
+                    self.mode = EditorMode::Navigate;
+                    if let Err(e) = self.write_as(None, true) {
+                        self.show_error(e);
+                    }
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('k') | KeyCode::Esc,
+                    ..
+                }) => {
+                    self.mode = EditorMode::Navigate;
+                    self.buffer.ignore_disk_changes();
+                }
+                _ => (),
+            },
+            EditorMode::Recover => match event {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('r'),
+                    ..
+                }) => {
+                    self.mode = EditorMode::Navigate;
+                    if let Err(e) = self.recover() {
+                        self.show_error(e);
+                    }
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('d'),
+                    ..
+                }) => {
+                    self.mode = EditorMode::Navigate;
+                    if let Some(path) = self.buffer.swap_path() {
+                        let _ = std::fs::remove_file(path);
+                    }
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('k') | KeyCode::Esc,
+                    ..
+                }) => {
+                    self.mode = EditorMode::Navigate;
+                    self.keep_swap_file();
+                }
+                _ => (),
+            },
+            EditorMode::Confirm => match event {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('y'),
+                    ..
+                }) => {
+                    if let Some(substitution) = &mut self.substitution {
+                        substitution.replace(&mut self.buffer);
+                    }
+                    self.confirm_next();
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('n'),
+                    ..
+                }) => {
+                    if let Some(substitution) = &mut self.substitution {
+                        substitution.skip(&self.buffer);
+                    }
+                    self.confirm_next();
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('a'),
+                    ..
+                }) => {
+                    if let Some(substitution) = &mut self.substitution {
+                        substitution.replace_all(&mut self.buffer);
+                    }
+                    self.finish_substitution();
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('l'),
+                    ..
+                }) => {
+                    if let Some(substitution) = &mut self.substitution {
+                        substitution.replace(&mut self.buffer);
+                    }
+                    self.finish_substitution();
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('q') | KeyCode::Esc,
+                    ..
+                }) => self.finish_substitution(),
+                _ => (),
+            },
+        }
+
+        // A count only lasts for the command it was typed in front of, unless that command is waiting for more keys.
+        if self.pending_key.is_none() {
+            self.count = None;
+        }
+        None
+    }
+}
+
+/// Runs a command in the shell with `input` piped into it, returning whatever it printed.
+///
+/// A command that fails has what it printed to stderr turned into an error instead, so that its output isn't mistaken for the result.
+fn run_shell(command: &str, input: Option<String>) -> Result<String> {
+    let mut child = process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(match input {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(EditorError::FileIo)?;
+
+    // The input is written from another thread, since a filter could fill up its output pipe before it's read all of it.
+    let writer = match (input, child.stdin.take()) {
+        (Some(input), Some(mut stdin)) => Some(std::thread::spawn(move || {
+            stdin.write_all(input.as_bytes())
+        })),
+        _ => None,
+    };
+    let output = child.wait_with_output().map_err(EditorError::FileIo)?;
+    if let Some(writer) = writer {
+        // A filter that stops reading early (like `head`) breaks the pipe, which is fine.
+        let _ = writer.join();
+    }
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = match stderr.lines().next() {
+            Some(line) => line.to_string(),
+            None => output.status.to_string(),
+        };
+        return Err(EditorError::ShellCommandFailed(reason));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Draws the words that `Ctrl-N` and `Ctrl-P` go through in a list over the text, just below the word being finished off at
+/// `column` and `row` (or above it, when there isn't room below.)
+///
+/// If there are more words than fit in the list, it scrolls to keep the selected one in sight.
+fn render_word_popup(
+    rows: &mut [Vec<u8>],
+    completion: &WordCompletion,
+    column: u16,
+    row: u16,
+    columns: u16,
+    theme: &Theme,
+) -> Result<()> {
+    let candidates = completion.candidates();
+    let height = cmp::min(candidates.len(), WORD_POPUP_ROWS);
+    let row = usize::from(row);
+    let top = match row + 1 + height <= rows.len() || row < height {
+        true => row + 1,
+        false => row - height,
+    };
+    let first = (completion.selected().unwrap_or(0) + 1).saturating_sub(height);
+
+    let longest = candidates
+        .iter()
+        .map(|c| c.chars().count())
+        .max()
+        .unwrap_or(0);
+    let width = cmp::min(longest + 2, usize::from(columns));
+    // The words line up with the one in the buffer, past the space that pads out the start of each row.
+    let column = cmp::min(
+        column.saturating_sub(1),
+        columns.saturating_sub(width.try_into().unwrap_or(u16::MAX)),
+    );
+    for (i, candidate) in candidates.iter().enumerate().skip(first).take(height) {
+        let screen_row = top + i - first;
+        let stream = match rows.get_mut(screen_row) {
+            Some(stream) => stream,
+            None => break,
+        };
+        let text: String = format!(" {:<1$} ", candidate, longest)
+            .chars()
+            .take(width)
+            .collect();
+        let styled = match completion.selected() == Some(i) {
+            true => theme.popup_selected.apply(text),
+            false => theme.popup.apply(text),
+        };
+        queue!(
+            stream,
+            cursor::MoveTo(column, screen_row.try_into().unwrap_or(u16::MAX)),
+            style::PrintStyledContent(styled)
+        )
+        .map_err(EditorError::TermIo)?;
+    }
+    Ok(())
+}
+
+/// Draws the version, the files edited most recently and a few keys to get going with, in the middle of the display.
+fn render_start_screen(
+    rows: &mut [Vec<u8>],
+    recent: &[PathBuf],
+    columns: usize,
+    theme: &Theme,
+) -> Result<()> {
+    let mut lines = vec![format!("Té {}", env!("CARGO_PKG_VERSION")), String::new()];
+    if !recent.is_empty() {
+        lines.push("Recent files".to_string());
+        // A long path loses its start rather than its file name.
+        let room = columns.saturating_sub(3);
+        lines.extend(recent.iter().map(|path| {
+            let path = path.display().to_string();
+            let length = path.chars().count();
+            match length > room {
+                true => "  …"
+                    .chars()
+                    .chain(path.chars().skip(length - room))
+                    .collect(),
+                false => format!("  {}", path),
+            }
+        }));
+        lines.push(String::new());
+    }
+    lines.extend(
+        [
+            ("i", "start typing, Esc to stop"),
+            (":e file", "open a file"),
+            (":w file", "write the buffer out to a file"),
+            (":oldfiles", "list the recent files"),
+            (":q", "quit"),
+        ]
+        .iter()
+        .map(|(keys, help)| format!("{:<12}{}", keys, help)),
+    );
+
+    // The lines are lined up with each other, and the block of them goes in the middle.
+    let width = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let left = columns.saturating_sub(width) / 2;
+    let top = rows.len().saturating_sub(lines.len()) / 2;
+    for (row, line) in rows.iter_mut().skip(top).zip(lines) {
+        row.clear();
+        let text: String = iter::repeat_n(' ', left)
+            .chain(line.chars())
+            .take(columns)
+            .collect();
+        queue!(row, style::PrintStyledContent(theme.text.apply(text)))
+            .map_err(EditorError::TermIo)?;
+    }
+    Ok(())
+}
+
+/// Whether a key goes through (or puts back) the words that `Ctrl-N` and `Ctrl-P` finish a word off with, rather than putting
+/// the list away.
+fn is_word_completion_key(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('n' | 'p' | 'e'),
+            modifiers: KeyModifiers::CONTROL,
+            ..
+        })
+    )
+}
+
+/// Lays out the candidates for finishing off the line at the prompt on a single row, picking out the selected one.
+///
+/// If they don't all fit, the row starts further along so that the selected one can still be seen.
+fn render_completion(completion: &Completion, width: usize, theme: &Theme) -> Result<Vec<u8>> {
+    let labels: Vec<&str> = (0..completion.candidates().len())
+        .map(|i| completion.label(i))
+        .collect();
+    let selected = completion.selected().unwrap_or(0);
+    let widths: Vec<usize> = labels.iter().map(|l| l.chars().count() + 2).collect();
+    let mut first = 0;
+    while first < selected && widths[first..=selected].iter().sum::<usize>() > width {
+        first += 1;
+    }
+
+    let mut row = Vec::new();
+    let mut used = 0;
+    for (i, label) in labels.iter().enumerate().skip(first) {
+        if used >= width {
+            break;
+        }
+        let text: String = label.chars().take(width - used).collect();
+        used += text.chars().count();
+        let style = match completion.selected() == Some(i) {
+            true => theme.popup_selected,
+            false => theme.text,
+        };
+        queue!(row, style::PrintStyledContent(style.apply(text))).map_err(EditorError::TermIo)?;
+        let gap = cmp::min(2, width.saturating_sub(used));
+        let padding = theme.text.apply(" ".repeat(gap));
+        queue!(row, style::PrintStyledContent(padding)).map_err(EditorError::TermIo)?;
+        used += gap;
+    }
+    Ok(row)
+}
+
+/// Whether a command can be repeated with `.`, which leaves out undoing, macros and the command line, even when they change the buffer.
+fn is_repeatable(keys: &[KeyEvent]) -> bool {
+    let mut keys = keys
+        .iter()
+        .skip_while(|key| matches!(key.code, KeyCode::Char(c) if c.is_ascii_digit()));
+    let mut first = keys.next();
+    if let Some(KeyEvent {
+        code: KeyCode::Char('"'),
+        ..
+    }) = first
+    {
+        first = keys.nth(1);
+    }
+    !matches!(
+        first,
+        Some(KeyEvent {
+            code: KeyCode::Char('u' | '.' | ':' | '/' | '?' | '@' | 'q'),
+            modifiers: KeyModifiers::NONE,
+            ..
+        }) | Some(KeyEvent {
+            code: KeyCode::Char('r'),
+            modifiers: KeyModifiers::CONTROL,
+            ..
+        })
+    )
+}
+
+/// Finds the key that would type `c` in Edit mode.
+fn typing_key(c: char) -> KeyEvent {
+    match c {
+        '\n' => KeyEvent::from(KeyCode::Enter),
+        '\t' => KeyEvent::from(KeyCode::Tab),
+        c => KeyEvent::from(KeyCode::Char(c)),
+    }
+}
+
+/// Puts the cursor back where it was left the last time the buffer's file was edited, if it's one of the recent files.
+fn restore_cursor(buffer: &mut Buffer, recent_files: &[(PathBuf, Position)]) {
+    let path = match buffer.path() {
+        Some(path) => lsp::absolute_path(path),
+        None => return,
+    };
+    if let Some((_, cursor)) = recent_files.iter().find(|(p, _)| *p == path) {
+        buffer.goto(cursor.line, cursor.column);
+    }
+}
+
+/// Picks a timestamped file in the recovery directory for a buffer's unsaved changes, for when it can't have a swap file.
+fn dated_recovery_path(dir: &Path, buffer: &Buffer) -> PathBuf {
+    let name = buffer
+        .path()
+        .and_then(|p| p.file_name())
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "unnamed".to_string());
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    dir.join(format!("{}.{}", name, timestamp))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_goto_centers_display() {
+        let mut editor = Editor::new(Buffer::new("1\n2\n3\n4\n5\n6\n7\n8\n9"));
+        editor.set_display_rows(5);
+        editor.goto(5, 0);
+        assert_eq!(editor.buffer.cursor(), Position::new(5, 0));
+        assert_eq!(
+            editor
+                .view
+                .screen_position(&editor.buffer, editor.buffer.cursor()),
+            (0, 2)
+        );
+    }
+
+    #[test]
+    fn test_status_line() {
+        let mut editor = Editor::new(Buffer::new("abc\ndéf"));
+        assert_eq!(editor.status_line(30), " NAVIGATE  [No Name]      1:1 ");
+
+        editor.buffer.goto(1, 3);
+        editor.buffer.set_path(PathBuf::from("some/file.txt"));
+        editor.buffer.insert('x');
+        editor.mode = EditorMode::Edit;
+        assert_eq!(
+            editor.status_line(36),
+            " EDIT  some/file.txt [+]        2:4 "
+        );
+
+        // A long path gets cut short before the cursor position does.
+        assert_eq!(editor.status_line(16), " EDIT  some 2:4 ");
+    }
+
+    #[test]
+    fn test_inserting_in_scrolled_line() {
+        let mut editor = Editor::new(Buffer::new("0123456789"));
+        editor.set_display_columns(4);
+        editor.goto(0, 7);
+        editor.buffer.insert('x');
+        assert_eq!(editor.buffer.contents(), &"0123456x789");
+    }
+
+    #[test]
+    fn test_normalizing_shows_message() {
+        let mut editor = Editor::new(Buffer::new("cafe\u{301}\nabc\nnai\u{308}ve"));
+        editor.normalize();
+        assert_eq!(editor.message.as_deref(), Some("Normalized 2 lines to NFC"));
+    }
+
+    #[test]
+    fn test_normalizing_normalized_buffer() {
+        let mut editor = Editor::new(Buffer::new("caf\u{e9}"));
+        editor.normalize();
+        assert_eq!(editor.message, None);
+    }
+
+    #[test]
+    fn test_recovery_file_only_written_when_modified() {
+        let dir = TempDir::new("recovery");
+
+        let mut editor = Editor::from_path(dir.join("file.txt")).unwrap();
+        editor.set_recovery_dir(dir.to_path_buf());
+        assert!(editor.write_recovery_file().unwrap().is_none());
+
+        editor.buffer.insert('x');
+        let recovery_path = editor.write_recovery_file().unwrap().unwrap();
+        assert_eq!(recovery_path, dir.join(".file.txt.swp"));
+        assert_eq!(std::fs::read_to_string(&recovery_path).unwrap(), "x");
+
+        // Later copies go into the same file.
+        editor.buffer.insert('y');
+        assert_eq!(
+            editor.write_recovery_file().unwrap(),
+            Some(recovery_path.clone())
+        );
+        assert_eq!(std::fs::read_to_string(&recovery_path).unwrap(), "xy");
+
+        editor.remove_recovery_file();
+        assert!(!recovery_path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_writing_recovery_file_privately() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("recovery-private");
+        let path = dir.write("file.txt", "");
+        let swap_path = dir.write(".file.txt.swp", "");
+        std::fs::set_permissions(&swap_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let mut editor = Editor::from_path(&path).unwrap();
+        editor.recovery_dir = Some(dir.join("recovery"));
+        editor.buffer.insert('x');
+        assert_eq!(
+            editor.write_recovery_file().unwrap(),
+            Some(swap_path.clone())
+        );
+        assert_eq!(std::fs::read_to_string(&swap_path).unwrap(), "x");
+        let mode = std::fs::metadata(&swap_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_recovery_files_for_every_buffer() {
+        let dir = TempDir::new("crash");
+        let mut editor = Editor::from_path(dir.join("one.txt")).unwrap();
+        editor.set_recovery_dir(dir.join("recovery"));
+        editor.buffer.insert('x');
+        editor.edit(dir.join("two.txt"), false).unwrap();
+        editor.buffer.insert('y');
+        editor.edit(dir.join("three.txt"), false).unwrap();
+        editor.edit(dir.join("one.txt"), false).unwrap();
+
+        // The buffer being edited goes into the recovery directory along with the rest, rather than into its swap file.
+        let written: Vec<_> = editor
+            .write_recovery_files()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(written.len(), 2);
+        for (path, (name, text)) in written.iter().zip([("one.txt.", "x"), ("two.txt.", "y")]) {
+            assert_eq!(path.parent(), Some(dir.join("recovery").as_path()));
+            assert!(path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with(name));
+            assert_eq!(std::fs::read_to_string(path).unwrap(), text);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_writing_recovery_files_privately() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("crash-private");
+        let mut editor = Editor::new(Buffer::new(""));
+        editor.recovery_dir = Some(dir.join("recovery"));
+        editor.buffer.insert('x');
+
+        let written: Vec<_> = editor
+            .write_recovery_files()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(written.len(), 1);
+        assert_eq!(std::fs::read_to_string(&written[0]).unwrap(), "x");
+        let mode = std::fs::metadata(&written[0]).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_recovering_from_swap_file() {
+        let dir = TempDir::new("swap");
+        let path = dir.write("file.txt", "old");
+        dir.write(".file.txt.swp", "new");
+
+        let mut editor = Editor::from_path(&path).unwrap();
+        editor.set_recovery_dir(dir.join("recovery"));
+        assert!(matches!(editor.mode, EditorMode::Recover));
+
+        editor.mode = EditorMode::Navigate;
+        editor.recover().unwrap();
+        assert_eq!(editor.buffer.contents(), &"new");
+        assert!(editor.buffer.is_modified());
+        editor.undo();
+        assert_eq!(editor.buffer.contents(), &"old");
+
+        // A swap file that matches the file is left over from a session that had nothing to lose.
+        dir.write(".file.txt.swp", "old");
+        let mut editor = Editor::from_path(&path).unwrap();
+        editor.set_recovery_dir(dir.join("recovery"));
+        assert!(matches!(editor.mode, EditorMode::Navigate));
+        assert!(!dir.join(".file.txt.swp").exists());
+
+        // Switching away from a buffer with unsaved changes leaves its swap file for it to pick up again, up to date.
+        editor.buffer.insert('x');
+        editor.write_recovery_file().unwrap();
+        editor.buffer.insert('y');
+        editor.edit(dir.join("other.txt"), false).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join(".file.txt.swp")).unwrap(),
+            "xyold"
+        );
+        editor.switch_buffer(0).unwrap();
+        assert!(matches!(editor.mode, EditorMode::Navigate));
+        editor.remove_recovery_files();
+        assert!(!dir.join(".file.txt.swp").exists());
+    }
+
+    #[test]
+    fn test_setting_file_format() {
+        let mut editor = Editor::new(Buffer::new("a\nb"));
+        type_command(&mut editor, "set ff");
+        assert_eq!(editor.message.as_deref(), Some("fileformat=unix"));
+
+        type_command(&mut editor, "set fileformat=dos");
+        assert_eq!(editor.buffer.file_format(), FileFormat::Dos);
+        assert!(editor.buffer.is_modified());
+
+        type_command(&mut editor, "set ff=mac");
+        assert_eq!(editor.message.as_deref(), Some("Invalid value: mac"));
+        type_command(&mut editor, "set nonsense");
+        assert_eq!(editor.message.as_deref(), Some("Unknown option: nonsense"));
+        assert_eq!(editor.buffer.file_format(), FileFormat::Dos);
+    }
+
+    #[test]
+    fn test_setting_scroll_off() {
+        let text = (1..=30).map(|n| n.to_string()).collect::<Vec<_>>();
+        let mut editor = Editor::new(Buffer::new(&text.join("\n")));
+        editor.view.set_rows(10);
+        type_command(&mut editor, "set so=2");
+        editor.buffer.goto_line(8);
+        editor.scroll_to_cursor();
+        assert_eq!(editor.view.visible_lines(), 1..11);
+
+        type_command(&mut editor, "set scrolloff=lots");
+        assert_eq!(editor.message.as_deref(), Some("Invalid value: lots"));
+    }
+
+    #[test]
+    fn test_showing_whitespace() {
+        let mut editor = Editor::new(Buffer::new("a\tb  "));
+        editor.split_window(Split::Vertical, None).unwrap();
+        type_command(&mut editor, "set list");
+        assert!(editor.view.list());
+        assert!(editor.windows.iter().all(|(_, window)| window.view.list()));
+        type_command(&mut editor, "set list!");
+        assert!(!editor.view.list());
+
+        type_command(&mut editor, "set lcs=tab:>-");
+        type_command(&mut editor, "set listchars");
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("listchars=tab:>-,trail:·,nbsp:␣")
+        );
+        type_command(&mut editor, "set lcs=tab:>--");
+        assert_eq!(editor.message.as_deref(), Some("Invalid value: tab:>--"));
+
+        type_command(&mut editor, "set fcs=eob:-");
+        assert!(editor
+            .windows
+            .iter()
+            .all(|(_, window)| window.view.fill_chars().eob == '-'));
+        type_command(&mut editor, "set fillchars");
+        assert_eq!(editor.message.as_deref(), Some("fillchars=eob:-"));
+    }
+
+    #[test]
+    fn test_cursor_line_and_color_columns() {
+        let mut editor = Editor::new(Buffer::new("abc"));
+        editor.split_window(Split::Vertical, None).unwrap();
+        type_command(&mut editor, "set cul");
+        assert!(editor.view.cursor_line());
+        assert!(editor
+            .windows
+            .iter()
+            .all(|(_, window)| window.view.cursor_line()));
+        type_command(&mut editor, "set invcursorline");
+        assert!(!editor.view.cursor_line());
+
+        type_command(&mut editor, "set cc=80,100");
+        assert!(editor
+            .windows
+            .iter()
+            .all(|(_, window)| window.view.color_columns() == [80, 100]));
+        type_command(&mut editor, "set colorcolumn");
+        assert_eq!(editor.message.as_deref(), Some("colorcolumn=80,100"));
+        type_command(&mut editor, "set cc=0");
+        assert_eq!(editor.message.as_deref(), Some("Invalid value: 0"));
+        type_command(&mut editor, "set cc=");
+        assert!(editor.view.color_columns().is_empty());
+    }
+
+    #[test]
+    fn test_read_only_buffers() {
+        let mut editor = Editor::new(Buffer::new("abc"));
+        editor.set_read_only();
+        assert_eq!(editor.status_line(31), " NAVIGATE  [No Name] [RO]  1:1 ");
+
+        assert!(!editor.editable());
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("The buffer is read-only (use :set noreadonly to change it)")
+        );
+        type_command(&mut editor, "s/a/b/");
+        assert_eq!(editor.buffer.contents(), &"abc");
+
+        type_command(&mut editor, "set noreadonly");
+        assert!(editor.editable());
+        type_command(&mut editor, "s/a/b/");
+        assert_eq!(editor.buffer.contents(), &"bbc");
+    }
+
+    #[test]
+    fn test_inserting_pasted_text() {
+        let mut editor = Editor::new(Buffer::new("ab"));
+        editor.set_expand_tab(true);
+        editor.buffer.goto(0, 1);
+        editor.mode = EditorMode::Edit;
+        editor.buffer.insert('x');
+        let paste =
+            |editor: &mut Editor, text: &str| editor.handle_event(Event::Paste(text.to_string()));
+        paste(&mut editor, "\tone\r\n\ttwo");
+        assert_eq!(editor.buffer.contents(), &"ax\tone\n\ttwob");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 4));
+        assert!(matches!(editor.mode, EditorMode::Edit));
+
+        // The paste comes back out on its own, leaving whatever was typed before it.
+        editor.undo();
+        assert_eq!(editor.buffer.contents(), &"axb");
+
+        // Only the first line goes onto the command line, and nothing is pasted in Navigate mode.
+        editor.mode = EditorMode::Navigate;
+        paste(&mut editor, "dd");
+        assert_eq!(editor.buffer.contents(), &"axb");
+        editor.mode = EditorMode::Command;
+        paste(&mut editor, "set wrap\nq!");
+        assert_eq!(editor.command_line.text(), "set wrap");
+    }
+
+    #[test]
+    fn test_recording_and_playing_macros() {
+        let mut editor = Editor::new(Buffer::new("a\nb\nc\nd\ne"));
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.record_key(key);
+                editor.handle_event(Event::Key(key));
+            }
+        };
+
+        keys(&mut editor, "qaA!<Esc>j");
+        assert_eq!(
+            editor.status_line(42),
+            " NAVIGATE recording @a  [No Name] [+] 2:1 "
+        );
+        keys(&mut editor, "q");
+        assert!(editor.recording.is_none());
+        assert_eq!(editor.registers.get(Some('a')).unwrap().text, "A!<Esc>j");
+        assert_eq!(editor.registers.get(None), None);
+
+        keys(&mut editor, "@a2@@");
+        assert_eq!(editor.buffer.contents(), &"a!\nb!\nc!\nd!\ne");
+        assert_eq!(editor.buffer.cursor().line, 4);
+
+        // Appending to a register adds onto the end of the macro in it.
+        keys(&mut editor, "qAxq");
+        assert_eq!(editor.registers.get(Some('a')).unwrap().text, "A!<Esc>jx");
+
+        // A macro that plays itself stops when it runs into an error.
+        keys(&mut editor, "qbq");
+        keys(&mut editor, "qbx:nope<CR>@bq");
+        keys(&mut editor, "@b");
+        assert!(editor.message_is_error);
+    }
+
+    #[test]
+    fn test_editing_and_saving_macros() {
+        let dir = TempDir::new("save-macro");
+        let path = dir.join("config.toml");
+        let mut editor = Editor::new(Buffer::new("a\nb\nc"));
+        editor.set_config_file(path.clone());
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.record_key(key);
+                editor.handle_event(Event::Key(key));
+            }
+        };
+
+        assert!(!type_command(&mut editor, "savemacro q <M-q>"));
+        assert_eq!(editor.message.as_deref(), Some("Nothing in register q"));
+
+        // The recorded keys can be put into the buffer, fixed up as text, and yanked back into the register.
+        keys(&mut editor, "qqA!<Esc>jq");
+        keys(&mut editor, "Go<Esc>\"qp");
+        assert_eq!(editor.buffer.contents(), &"a!\nb\nc\nA!<Esc>j");
+        keys(&mut editor, "0f!r?0\"qy$ddgg");
+        assert_eq!(editor.registers.get(Some('q')).unwrap().text, "A?<Esc>j");
+
+        assert!(!type_command(&mut editor, "savemacro q <M-q>"));
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("<M-q> now plays register q")
+        );
+        // The mapping goes through the keymap like anything else the user types.
+        editor.send_keys(&keymap::parse_keys("j<M-q>").unwrap());
+        assert_eq!(editor.buffer.contents(), &"a!\nb?\nc");
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "[keys.navigate]\n\"<M-q>\" = \"A?<Esc>j\"\n"
+        );
+        assert_eq!(
+            config::Config::load(&path).unwrap().bindings,
+            [Binding::parse(MapMode::Navigate, "<M-q>", "A?<Esc>j").unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_paragraph_and_bracket_motions() {
+        let mut editor = Editor::new(Buffer::new("a\nb\n\nif (x) {\n  y\n}\n\nc"));
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+
+        keys(&mut editor, "}");
+        assert_eq!(editor.buffer.cursor(), Position::new(2, 0));
+        keys(&mut editor, "j%");
+        assert_eq!(editor.buffer.cursor(), Position::new(3, 5));
+        keys(&mut editor, "$%");
+        assert_eq!(editor.buffer.cursor(), Position::new(5, 0));
+        keys(&mut editor, "{");
+        assert_eq!(editor.buffer.cursor(), Position::new(2, 0));
+
+        keys(&mut editor, "jd%");
+        assert_eq!(editor.buffer.contents(), &"a\nb\n\n {\n  y\n}\n\nc");
+        keys(&mut editor, "jd%");
+        assert_eq!(editor.buffer.contents(), &"a\nb\n\n {\n  y\n}\n\nc");
+        keys(&mut editor, "ggd}");
+        assert_eq!(editor.buffer.contents(), &"\n {\n  y\n}\n\nc");
+    }
+
+    #[test]
+    fn test_finding_chars() {
+        let mut editor = Editor::new(Buffer::new("a.b.c.d(e)"));
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+
+        keys(&mut editor, "f.;");
+        assert_eq!(editor.buffer.cursor().column, 3);
+        keys(&mut editor, ",");
+        assert_eq!(editor.buffer.cursor().column, 1);
+        keys(&mut editor, "2;");
+        assert_eq!(editor.buffer.cursor().column, 5);
+
+        keys(&mut editor, "dt(");
+        assert_eq!(editor.buffer.contents(), &"a.b.c(e)");
+        // Looking for a character that isn't there does nothing, even with an operator waiting.
+        keys(&mut editor, "dfz");
+        assert_eq!(editor.buffer.contents(), &"a.b.c(e)");
+        keys(&mut editor, "dF.");
+        assert_eq!(editor.buffer.contents(), &"a.b(e)");
+        assert_eq!(editor.last_find.map(|search| search.forward), Some(false));
+    }
+
+    #[test]
+    fn test_marks_and_jumps() {
+        let mut editor = Editor::new(Buffer::new("one\n  two\nthree\nfour"));
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+
+        keys(&mut editor, "jlllmaG'a");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 2));
+        keys(&mut editor, "G`a");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 3));
+        keys(&mut editor, "``");
+        assert_eq!(editor.buffer.cursor(), Position::new(3, 0));
+
+        keys(&mut editor, "gg<C-o>");
+        assert_eq!(editor.buffer.cursor(), Position::new(3, 0));
+        keys(&mut editor, "<C-o>");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 3));
+        keys(&mut editor, "<Tab><Tab>");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 0));
+
+        keys(&mut editor, "'b");
+        assert_eq!(editor.message.as_deref(), Some("Mark not set"));
+        keys(&mut editor, "Gd'a");
+        assert_eq!(editor.buffer.contents(), &"one");
+
+        // A jump that stays on the same line leaves nothing to go back to.
+        let mut editor = Editor::new(Buffer::new("foo bar baz"));
+        keys(&mut editor, "/baz<CR><C-o>");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 8));
+    }
+
+    #[test]
+    fn test_repeating_changes() {
+        let mut editor = Editor::new(Buffer::new("one two three four five six\na\nb\nc"));
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+
+        keys(&mut editor, "dw.");
+        assert_eq!(editor.buffer.contents(), &"three four five six\na\nb\nc");
+        keys(&mut editor, "2.");
+        assert_eq!(editor.buffer.contents(), &"five six\na\nb\nc");
+        // The new count sticks for the next time.
+        keys(&mut editor, "u.");
+        assert_eq!(editor.buffer.contents(), &"five six\na\nb\nc");
+
+        // Moving around and undoing don't count as changes, so they don't get in the way.
+        keys(&mut editor, "jA!<Esc>uj.");
+        assert_eq!(editor.buffer.contents(), &"five six\na\nb!\nc");
+
+        keys(&mut editor, "ggddj.");
+        assert_eq!(editor.buffer.contents(), &"a\nc");
+    }
+
+    /// Types a line in at the prompt and runs it, returning whether the editor would quit.
+    fn type_command(editor: &mut Editor, line: &str) -> bool {
+        editor.mode = EditorMode::Command;
+        editor.command_line.clear();
+        editor.command_line.insert_str(line);
+        editor.run_command()
+    }
+
+    #[test]
+    fn test_quit_command() {
+        let mut editor = Editor::new(Buffer::new("abc"));
+        assert!(type_command(&mut editor, "q"));
+
+        editor.buffer.insert('x');
+        assert!(!type_command(&mut editor, "q"));
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("No write since last change (add ! to override)")
+        );
+        assert!(type_command(&mut editor, "q!"));
+    }
+
+    #[test]
+    fn test_errors_stand_out_from_messages() {
+        let mut editor = Editor::new(Buffer::new("abc"));
+        assert!(!type_command(&mut editor, "e"));
+        assert_eq!(editor.message.as_deref(), Some(":e needs an argument"));
+        assert!(editor.message_is_error);
+
+        editor.undo();
+        assert_eq!(editor.message.as_deref(), Some("Already at oldest change"));
+        assert!(!editor.message_is_error);
+        editor.handle_timer(TimerEvent::ClearMessage);
+        assert_eq!(editor.message, None);
+    }
+
+    #[test]
+    fn test_quitting_with_keys() {
+        let key = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        let mut editor = Editor::new(Buffer::new("abc"));
+        assert!(editor.can_quit());
+        assert!(editor.complete_sequence('Z', &key('Z')));
+
+        editor.buffer.insert('x');
+        assert!(!editor.can_quit());
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("No write since last change (add ! to override)")
+        );
+        assert!(editor.complete_sequence('Z', &key('Q')));
+
+        // There's nowhere to write the buffer yet, so the user is asked for somewhere first.
+        assert!(!editor.complete_sequence('Z', &key('Z')));
+        assert!(matches!(editor.mode, EditorMode::SaveAs { quit: true }));
+    }
+
+    #[test]
+    fn test_write_and_edit_commands() {
+        let dir = TempDir::new("commands");
+        let first = dir.join("first.txt");
+        let second = dir.write("second.txt", "second");
+
+        let mut editor = Editor::new(Buffer::new(""));
+        editor.buffer.insert('x');
+        assert!(!type_command(
+            &mut editor,
+            &format!("w {}", first.display())
+        ));
+        assert_eq!(std::fs::read_to_string(&first).unwrap(), "x");
+        assert!(!editor.buffer.is_modified());
+
+        assert!(!type_command(
+            &mut editor,
+            &format!("e {}", second.display())
+        ));
+        assert_eq!(editor.buffer.contents(), &"second");
+        assert_eq!(editor.buffer.path(), Some(second.as_path()));
+    }
+
+    #[test]
+    fn test_writing_over_changed_file() {
+        let dir = TempDir::new("overwrite");
+        let path = dir.write("file.txt", "old");
+
+        let mut editor = Editor::from_path(&path).unwrap();
+        editor.buffer.insert('x');
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+
+        type_command(&mut editor, "w");
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("The file has been changed since reading it (add ! to override)")
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+
+        type_command(&mut editor, "w!");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "xold");
+    }
+
+    #[test]
+    fn test_reloading_files_on_focus() {
+        let dir = TempDir::new("focus");
+        let (one, two) = (dir.write("one.txt", "a\nb"), dir.write("two.txt", "x\nyy"));
+        // Each change is given a time of its own, so that it can't be mistaken for the one before.
+        let change = |path: &Path, text: &str, time: u64| {
+            std::fs::write(path, text).unwrap();
+            let file = std::fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(time))
+                .unwrap();
+        };
+
+        let mut editor = Editor::from_path(&one).unwrap();
+        type_command(&mut editor, &format!("e {}", two.display()));
+        editor.buffer.goto(1, 1);
+        change(&one, "a\nb\nc", 1);
+        change(&two, "x\nyy\nz", 1);
+        assert_eq!(editor.handle_event(Event::FocusGained), None);
+        assert_eq!(editor.message.as_deref(), Some("Reloaded 2 changed files"));
+        assert_eq!(editor.buffer.contents(), &"x\nyy\nz");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 1));
+        assert!(matches!(editor.mode, EditorMode::Navigate));
+        type_command(&mut editor, "b 1");
+        assert_eq!(editor.buffer.contents(), &"a\nb\nc");
+
+        // Unsaved changes aren't thrown away without asking.
+        editor.buffer.insert('!');
+        change(&one, "gone", 2);
+        editor.handle_event(Event::FocusGained);
+        assert!(matches!(editor.mode, EditorMode::Reload));
+        assert_eq!(editor.buffer.contents(), &"!a\nb\nc");
+    }
+
+    #[test]
+    fn test_reloading_changed_file() {
+        let dir = TempDir::new("reload");
+        let path = dir.write("file.txt", "one\ntwo");
+
+        let mut editor = Editor::from_path(&path).unwrap();
+        editor.buffer.goto(1, 2);
+        editor.buffer.insert('x');
+        editor.check_file();
+        assert!(matches!(editor.mode, EditorMode::Navigate));
+
+        std::fs::write(&path, "one\ntwo\nthree").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        editor.check_file();
+        assert!(matches!(editor.mode, EditorMode::Reload));
+        assert_eq!(editor.status_line(13), " CHANGED 2:4 ");
+
+        editor.mode = EditorMode::Navigate;
+        editor.reload().unwrap();
+        assert_eq!(editor.buffer.contents(), &"one\ntwo\nthree");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 2));
+        assert!(!editor.buffer.is_modified());
+        editor.check_file();
+        assert!(matches!(editor.mode, EditorMode::Navigate));
+    }
+
+    #[test]
+    fn test_backups_and_autosave() {
+        let dir = TempDir::new("backup");
+        let path = dir.write("file.txt", "old");
+
+        let mut editor = Editor::from_path(&path).unwrap();
+        editor.set_backup(true);
+        editor.buffer.insert('x');
+
+        // Nothing gets written in the middle of typing.
+        editor.mode = EditorMode::Edit;
+        editor.autosave().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+
+        editor.mode = EditorMode::Navigate;
+        editor.autosave().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "xold");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("file.txt~")).unwrap(),
+            "old"
+        );
+        assert!(!editor.buffer.is_modified());
+    }
+
+    #[test]
+    fn test_saving_as() {
+        let dir = TempDir::new("save-as");
+        let path = dir.join("new.txt");
+
+        let mut editor = Editor::new(Buffer::new("abc"));
+        assert!(!type_command(&mut editor, "wq"));
+        assert!(matches!(editor.mode, EditorMode::SaveAs { quit: true }));
+
+        // Nothing typed in gives up on writing the buffer, without quitting.
+        assert!(!editor.finish_save_as());
+        assert!(matches!(editor.mode, EditorMode::Navigate));
+
+        type_command(&mut editor, "w");
+        editor.command_line.insert_str(&path.display().to_string());
+        assert!(!editor.finish_save_as());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "abc");
+        assert_eq!(editor.buffer.path(), Some(path.as_path()));
+
+        // Once the buffer has a path, it's just written there.
+        assert!(type_command(&mut editor, "wq"));
+    }
+
+    #[test]
+    fn test_multiple_buffers() {
+        let dir = TempDir::new("buffers");
+        let first = dir.join("first.txt");
+        let second = dir.join("second.txt");
+        std::fs::write(&first, "first").unwrap();
+        std::fs::write(&second, "second").unwrap();
+
+        let mut editor = Editor::from_path(&first).unwrap();
+        editor.buffer.insert('x');
+        assert!(!type_command(
+            &mut editor,
+            &format!("e {}", second.display())
+        ));
+        assert_eq!(editor.buffer.contents(), &"second");
+        assert!(editor.status_line(80).starts_with(" NAVIGATE  [2/2] "));
+
+        // The changes in the other buffer stop the editor from quitting.
+        assert!(!type_command(&mut editor, "q"));
+        assert_eq!(
+            editor.message.as_deref(),
+            Some(
+                format!(
+                    "No write since last change for buffer \"{}\" (add ! to override)",
+                    first.display()
+                )
+                .as_str()
+            )
+        );
+
+        type_command(&mut editor, "bn");
+        assert_eq!(editor.buffer.contents(), &"xfirst");
+        type_command(&mut editor, "ls");
+        assert_eq!(
+            editor.message,
+            Some(format!(
+                "1% \"{}\" [+]  2 \"{}\"",
+                first.display(),
+                second.display()
+            ))
+        );
+
+        // Opening a file that's already open just switches to it.
+        type_command(&mut editor, &format!("e {}", second.display()));
+        type_command(&mut editor, "bd");
+        assert_eq!(editor.buffer.contents(), &"xfirst");
+        assert!(!type_command(&mut editor, "b 2"));
+        assert_eq!(editor.message.as_deref(), Some("Buffer 2 does not exist"));
+    }
+
+    #[test]
+    fn test_argument_list() {
+        let dir = TempDir::new("args");
+        let paths = vec![dir.join("a.txt"), dir.join("b.txt"), dir.join("a.txt")];
+        std::fs::write(&paths[0], "a").unwrap();
+        std::fs::write(&paths[1], "b").unwrap();
+
+        let mut editor = Editor::from_paths(paths.clone()).unwrap();
+        assert_eq!(editor.buffer.contents(), &"a");
+        assert_eq!(editor.buffers.len(), 2);
+
+        assert!(!type_command(&mut editor, "prev"));
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("Cannot go before first file")
+        );
+        type_command(&mut editor, "n");
+        assert_eq!(editor.buffer.contents(), &"b");
+        assert_eq!(
+            editor.message,
+            Some(format!("\"{}\" (2 of 3)", paths[1].display()))
+        );
+        type_command(&mut editor, "n");
+        assert_eq!(editor.buffer.contents(), &"a");
+        type_command(&mut editor, "n");
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("Cannot go beyond last file")
+        );
+        type_command(&mut editor, "N");
+        assert_eq!(editor.buffer.contents(), &"b");
+    }
+
+    #[test]
+    fn test_changing_directory() {
+        let dir = TempDir::new("cd");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub").join("a.txt"), "a\n").unwrap();
+        let started_in = env::current_dir().unwrap();
+        env::set_current_dir(&dir).unwrap();
+
+        let mut editor = Editor::from_paths(vec![PathBuf::from("sub/a.txt")]).unwrap();
+        assert!(!type_command(&mut editor, "cd sub"));
+        let sub = dir.join("sub").canonicalize().unwrap();
+        assert_eq!(editor.message, Some(sub.display().to_string()));
+        // The open file is still the same one, now that it's found from somewhere else.
+        assert_eq!(editor.buffer.path(), Some(Path::new("a.txt")));
+        assert_eq!(editor.args, [PathBuf::from("a.txt")]);
+
+        // New paths are found from the new directory.
+        type_command(&mut editor, "e b.txt");
+        editor.buffer.insert('b');
+        type_command(&mut editor, "w");
+        assert_eq!(std::fs::read_to_string(sub.join("b.txt")).unwrap(), "b");
+
+        type_command(&mut editor, "cd ..");
+        assert_eq!(editor.buffer.path(), Some(Path::new("sub/b.txt")));
+        type_command(&mut editor, "pwd");
+        assert_eq!(
+            editor.message,
+            Some(dir.canonicalize().unwrap().display().to_string())
+        );
+        type_command(&mut editor, "cd nope");
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("The directory path nope does not exist")
+        );
+
+        // Another project's configuration only comes into play when the editor starts.
+        std::fs::create_dir_all(dir.join("project")).unwrap();
+        std::fs::write(dir.join("project").join(".te.toml"), "wrap = true\n").unwrap();
+        type_command(&mut editor, "cd project");
+        let project = dir.join("project").canonicalize().unwrap();
+        assert_eq!(
+            editor.message,
+            Some(format!(
+                "{} (its .te.toml isn't used until Té is started there)",
+                project.display()
+            ))
+        );
+
+        env::set_current_dir(started_in).unwrap();
+    }
+
+    #[test]
+    fn test_running_commands_in_each_buffer() {
+        let dir = TempDir::new("bufdo");
+        let paths = vec![dir.join("a.txt"), dir.join("b.txt"), dir.join("c.txt")];
+        for (path, text) in paths.iter().zip(["foo one", "bar", "foo two"]) {
+            std::fs::write(path, text).unwrap();
+        }
+
+        let mut editor = Editor::from_paths(paths.clone()).unwrap();
+        assert!(!type_command(&mut editor, "bufdo %s/foo/baz/g | w"));
+        assert_eq!(std::fs::read_to_string(&paths[0]).unwrap(), "baz one");
+        assert_eq!(std::fs::read_to_string(&paths[2]).unwrap(), "baz two");
+        // What went wrong in one buffer is reported at the end, without stopping the others.
+        assert_eq!(
+            editor.message,
+            Some(format!("{}: Pattern not found: foo", paths[1].display()))
+        );
+        assert!(editor.message_is_error);
+
+        // Each buffer's changes are undone on their own.
+        assert_eq!(editor.buffer.contents(), &"baz two");
+        editor.undo();
+        assert_eq!(editor.buffer.contents(), &"foo two");
+        editor.redo();
+
+        assert!(!type_command(&mut editor, "argdo s/^/> /"));
+        assert_eq!(editor.buffer.contents(), &"> baz two");
+        type_command(&mut editor, "b 2");
+        assert_eq!(editor.buffer.contents(), &"> bar");
+
+        // The `\|` in a pattern separates alternatives rather than commands.
+        assert!(!type_command(&mut editor, "bufdo s/one\\|two\\|bar/x/"));
+        assert_eq!(editor.buffer.contents(), &"> baz x");
+        assert!(!editor.message_is_error);
+        type_command(&mut editor, "b 2");
+        assert_eq!(editor.buffer.contents(), &"> x");
+    }
+
+    #[test]
+    fn test_comparing_files() {
+        use crate::git::LineChange;
+
+        let dir = TempDir::new("diff");
+        let paths = vec![dir.join("old.txt"), dir.join("new.txt")];
+        std::fs::write(&paths[0], "a\nlet x = 1;\nb\ngone\nc\n").unwrap();
+        std::fs::write(&paths[1], "a\nlet y = 1;\nmore\nb\nc\n").unwrap();
+        let mut editor = Editor::from_paths(paths).unwrap();
+        editor.set_display_columns(40);
+        editor.set_display_rows(11);
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+
+        editor.diff_files().unwrap();
+        assert_eq!(editor.windows.len(), 2);
+        assert_eq!(editor.buffers.current(), 0);
+        assert_eq!(editor.buffer.line_change(1), Some(LineChange::Modified));
+        assert_eq!(editor.buffer.line_change(3), Some(LineChange::Added));
+        assert_eq!(editor.buffer.changed_text().len(), 1);
+        assert_eq!(editor.buffer.changed_text()[0], 6..7);
+        let new = editor.buffers.get(&editor.buffer, 1).unwrap();
+        assert_eq!(new.line_change(2), Some(LineChange::Added));
+        assert_eq!(new.line_change(3), Some(LineChange::Removed));
+
+        // `]c` and `[c` jump between the changes, and the other window follows the cursor.
+        keys(&mut editor, "]c");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 0));
+        keys(&mut editor, "]c]c");
+        assert_eq!(editor.buffer.cursor(), Position::new(3, 0));
+        assert_eq!(editor.message.as_deref(), Some("No more changes"));
+        editor.sync_diff_windows();
+        let other = editor.windows.iter_mut().next().unwrap();
+        assert_eq!(other.cursor, Position::new(4, 0));
+        keys(&mut editor, "[c");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 0));
+
+        // Changing either buffer compares them again.
+        keys(&mut editor, "jjdd");
+        editor.update_diff();
+        assert_eq!(editor.buffer.line_change(3), None);
+        let new = editor.buffers.get(&editor.buffer, 1).unwrap();
+        assert_eq!(new.line_change(3), None);
+
+        type_command(&mut editor, "diffoff");
+        assert!(editor.comparison.is_none());
+        assert!(!editor.buffer.has_line_changes());
+        assert!(editor.buffer.changed_text().is_empty());
+        keys(&mut editor, "]c");
+        assert_eq!(editor.message.as_deref(), Some("No more changes"));
+    }
+
+    #[test]
+    fn test_formatting() {
+        let dir = TempDir::new("format");
+        let path = dir.write("shout.txt", "one\ntwo\n");
+        let mut editor = Editor::from_path(&path).unwrap();
+        editor.buffer.goto(1, 2);
+
+        type_command(&mut editor, "format");
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("No formatter for this type of file")
+        );
+        editor.set_formatters(vec![("txt".to_string(), "tr a-z A-Z".to_string())]);
+        type_command(&mut editor, "format");
+        assert_eq!(editor.buffer.contents(), &"ONE\nTWO\n");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 2));
+        editor.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('u'),
+            KeyModifiers::NONE,
+        )));
+        assert_eq!(editor.buffer.contents(), &"one\ntwo\n");
+
+        // Formatting on save happens before the file is written, and a formatter that fails doesn't stop it being written.
+        editor.set_format_on_save(true);
+        type_command(&mut editor, "w");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "ONE\nTWO\n");
+        editor.set_formatters(vec![(
+            "txt".to_string(),
+            "echo oops >&2; false".to_string(),
+        )]);
+        type_command(&mut editor, "w");
+        assert_eq!(editor.message.as_deref(), Some("Formatter failed: oops"));
+        assert!(!editor.buffer.is_modified());
+    }
+
+    #[test]
+    fn test_linting() {
+        let dir = TempDir::new("lint");
+        let path = dir.write("notes.txt", "one\nTODO two\n");
+        let mut editor = Editor::from_path(&path).unwrap();
+        let wait = |editor: &mut Editor| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !editor.lint_runs.is_empty() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+                editor.poll_linters();
+            }
+        };
+
+        let linter = r#"awk '/TODO/ { print FILENAME ":" FNR ": warning: still to do" }'"#;
+        editor.set_linters(vec![("txt".to_string(), linter.to_string())]);
+        wait(&mut editor);
+        let lint = editor.buffer.lint_on(1).unwrap();
+        assert_eq!(lint.severity, lsp::Severity::Warning);
+        assert_eq!(lint.message, "warning: still to do");
+        assert!(editor.buffer.lint_on(0).is_none());
+
+        // The linter looks at the file again whenever it's written.
+        editor.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('O'),
+            KeyModifiers::NONE,
+        )));
+        editor.handle_event(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)));
+        type_command(&mut editor, "w");
+        wait(&mut editor);
+        assert!(editor.buffer.lint_on(1).is_none());
+        assert!(editor.buffer.lint_on(2).is_some());
+    }
+
+    #[test]
+    fn test_hooks() {
+        let dir = TempDir::new("hooks");
+        let path = dir.write("notes.txt", "one  \ntwo\n");
+        let mut editor = Editor::from_path(&path).unwrap();
+        let hook = |event, file_type: &str, command: &str| Hook {
+            event,
+            file_type: file_type.to_string(),
+            command: command.to_string(),
+        };
+
+        editor.set_hooks(vec![
+            hook(HookEvent::BufRead, "txt", "%s/two/TWO/"),
+            hook(HookEvent::BufWritePre, "txt", "%s/ \\+$//"),
+            hook(HookEvent::BufWritePre, "rs", "%s/o/0/"),
+            // Writing the file again from a hook doesn't set the hooks off again.
+            hook(HookEvent::BufWritePost, "*", "w"),
+        ]);
+        assert_eq!(editor.buffer.contents(), &"one  \nTWO\n");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 0));
+
+        editor.buffer.goto(0, 4);
+        type_command(&mut editor, "w");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\nTWO\n");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 2));
+        assert_eq!(
+            editor.message.as_deref(),
+            Some(format!("\"{}\" written", path.display()).as_str())
+        );
+
+        editor.set_hooks(vec![hook(HookEvent::CursorMoved, "*", "nonsense")]);
+        editor.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('j'),
+            KeyModifiers::NONE,
+        )));
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("CursorMoved hook failed: Not an editor command: nonsense")
+        );
+
+        // A hook's shell command is run as the hook runs, and isn't left for the next command to hand the terminal over to.
+        editor.set_hooks(vec![hook(HookEvent::BufWritePost, "*", "!true")]);
+        assert!(!type_command(&mut editor, "w"));
+        assert!(editor.shell_command.is_none());
+        editor.mode = EditorMode::Command;
+        editor.command_line.set_text("set list");
+        assert_eq!(
+            editor.handle_event(Event::Key(KeyEvent::from(KeyCode::Enter))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_split_windows() {
+        let key = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        let mut editor = Editor::new(Buffer::new("one\ntwo\nthree"));
+        editor.set_display_columns(20);
+        editor.set_display_rows(11);
+
+        assert!(!type_command(&mut editor, "vsplit"));
+        assert_eq!(editor.windows.len(), 2);
+        assert_eq!(
+            editor
+                .view
+                .screen_position(&editor.buffer, Position::new(0, 0)),
+            (0, 0)
+        );
+
+        // Each window keeps its own cursor, even on the same buffer.
+        editor.buffer.goto(2, 1);
+        editor.complete_sequence(WINDOW_KEY, &key('l'));
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 0));
+        assert_eq!(
+            editor
+                .view
+                .screen_position(&editor.buffer, Position::new(0, 0)),
+            (10, 0)
+        );
+        editor.complete_sequence(WINDOW_KEY, &key('h'));
+        assert_eq!(editor.buffer.cursor(), Position::new(2, 1));
+
+        // A window can show a different buffer to the others.
+        editor.complete_sequence(WINDOW_KEY, &key('s'));
+        editor.buffers.add(&mut editor.buffer, Buffer::new("other"));
+        editor.complete_sequence(WINDOW_KEY, &key('j'));
+        assert_eq!(editor.buffer.contents(), &"one\ntwo\nthree");
+        assert_eq!(editor.buffer.cursor(), Position::new(2, 1));
+        editor.complete_sequence(WINDOW_KEY, &key('k'));
+        assert_eq!(editor.buffer.contents(), &"other");
+
+        // Quitting closes windows until there's only one left.
+        assert!(!type_command(&mut editor, "q"));
+        assert!(!type_command(&mut editor, "q"));
+        assert_eq!(editor.windows.len(), 1);
+        assert_eq!(
+            editor
+                .view
+                .screen_position(&editor.buffer, Position::new(0, 0)),
+            (0, 0)
+        );
+        editor.complete_sequence(WINDOW_KEY, &key('c'));
+        assert_eq!(editor.message.as_deref(), Some("Cannot close last window"));
+    }
+
+    #[test]
+    fn test_sessions() {
+        let key = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        let dir = TempDir::new("session");
+        let (one, two) = (dir.join("one.txt"), dir.join("two.txt"));
+        std::fs::write(&one, "a\nb\nc\n").unwrap();
+        std::fs::write(&two, "x\ny\n").unwrap();
+        let file = dir.join("Session.te");
+
+        let mut editor = Editor::from_paths(vec![one.clone(), two.clone()]).unwrap();
+        editor.set_display_columns(40);
+        editor.set_display_rows(12);
+        editor.buffer.goto(2, 0);
+        type_command(&mut editor, "vsplit");
+        type_command(&mut editor, "bn");
+        editor.buffer.goto(1, 0);
+        type_command(&mut editor, &format!("mks {}", file.display()));
+        assert_eq!(
+            editor.message.as_deref(),
+            Some(format!("Session saved to \"{}\"", file.display()).as_str())
+        );
+        type_command(&mut editor, &format!("mks {}", file.display()));
+        assert_eq!(
+            editor.message.as_deref(),
+            Some(format!("\"{}\" exists (add ! to override)", file.display()).as_str())
+        );
+
+        let session = Session::parse(&std::fs::read_to_string(&file).unwrap()).unwrap();
+        let mut restored = Editor::from_paths(vec![one, two]).unwrap();
+        restored.set_display_columns(40);
+        restored.set_display_rows(12);
+        restored.restore_session(&session).unwrap();
+        assert_eq!(restored.windows.len(), 2);
+        assert_eq!(restored.buffer.contents(), &"x\ny\n");
+        assert_eq!(restored.buffer.cursor(), Position::new(1, 0));
+        restored.complete_sequence(WINDOW_KEY, &key('l'));
+        assert_eq!(restored.buffer.contents(), &"a\nb\nc\n");
+        assert_eq!(restored.buffer.cursor(), Position::new(2, 0));
+
+        // A buffer without a file can't be opened again, so it can't go in a session.
+        editor
+            .buffers
+            .add(&mut editor.buffer, Buffer::new("scratch"));
+        type_command(&mut editor, &format!("mks! {}", file.display()));
+        assert_eq!(editor.message.as_deref(), Some("Buffer 3 has no file name"));
+    }
+
+    #[test]
+    fn test_cursor_shape_follows_mode() {
+        let mut editor = Editor::new(Buffer::new("abc"));
+        let render = |editor: &mut Editor, key: Option<char>| {
+            if let Some(c) = key {
+                editor.handle_event(Event::Key(KeyEvent::new(
+                    KeyCode::Char(c),
+                    KeyModifiers::NONE,
+                )));
+            }
+            let mut output = Vec::new();
+            editor.render(&mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert!(render(&mut editor, None).contains("\x1b[2 q"));
+        // The shape is only sent when it changes.
+        assert!(!render(&mut editor, None).contains(" q"));
+        assert!(render(&mut editor, Some('i')).contains("\x1b[5 q"));
+        editor.handle_event(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)));
+        assert!(render(&mut editor, Some('r')).contains("\x1b[3 q"));
+        assert!(render(&mut editor, Some('x')).contains("\x1b[2 q"));
+        assert!(render(&mut editor, Some(':')).contains("\x1b[5 q"));
+    }
+
+    #[test]
+    fn test_replace_mode() {
+        let mut editor = Editor::new(Buffer::new("abc\nd"));
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+        keys(&mut editor, "Rxy");
+        assert_eq!(editor.mode_name(), "REPLACE");
+        assert_eq!(editor.buffer.contents().to_string(), "xyc\nd");
+        // Past the end of the line there's nothing to type over, so the line gets longer.
+        keys(&mut editor, "zw");
+        assert_eq!(editor.buffer.contents().to_string(), "xyzw\nd");
+
+        // Backspace takes back what was added, then puts back what was typed over, then just moves.
+        keys(&mut editor, "<BS><BS><BS>");
+        assert_eq!(editor.buffer.contents().to_string(), "xbc\nd");
+        keys(&mut editor, "<BS><BS>");
+        assert_eq!(editor.buffer.contents().to_string(), "abc\nd");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 0));
+
+        // A new line doesn't type over anything.
+        keys(&mut editor, "<Esc>lR1<Enter>2<Esc>");
+        assert_eq!(editor.buffer.contents().to_string(), "a1\n2\nd");
+        assert_eq!(editor.mode_name(), "NAVIGATE");
+        keys(&mut editor, "u");
+        assert_eq!(editor.buffer.contents().to_string(), "abc\nd");
+
+        // It can be repeated with `.`.
+        keys(&mut editor, "0Rq<Esc>j0.");
+        assert_eq!(editor.buffer.contents().to_string(), "qbc\nq");
+    }
+
+    #[test]
+    fn test_visual_lines_and_blocks() {
+        let mut editor = Editor::new(Buffer::new("abcd\nefgh\nij\nklmn"));
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+        keys(&mut editor, "lV");
+        assert_eq!(editor.mode_name(), "VISUAL LINE");
+        assert_eq!(editor.selection(), vec![0..5]);
+        keys(&mut editor, "jd");
+        assert_eq!(editor.buffer.contents().to_string(), "ij\nklmn");
+        keys(&mut editor, "u");
+
+        // A block covers the same cells of every line, and lines that are too short only give what reaches them.
+        keys(&mut editor, "ggll<C-v>jj");
+        assert_eq!(editor.mode_name(), "VISUAL BLOCK");
+        assert_eq!(editor.selection(), vec![1..3, 6..8, 11..12]);
+        keys(&mut editor, "y");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 1));
+        assert_eq!(editor.registers.get(None).unwrap().text, "bc\nfg\nj");
+        assert!(editor.registers.get(None).unwrap().blockwise);
+
+        // Pasting a block puts each piece onto its own line, padding out short lines and the pieces in front of other text.
+        keys(&mut editor, "jjjP");
+        assert_eq!(
+            editor.buffer.contents().to_string(),
+            "abcd\nefgh\nij\nkbclmn\n fg\n j"
+        );
+        keys(&mut editor, "u");
+        assert_eq!(editor.buffer.contents().to_string(), "abcd\nefgh\nij\nklmn");
+
+        keys(&mut editor, "ggll<C-v>jjd");
+        assert_eq!(editor.buffer.contents().to_string(), "ad\neh\ni\nklmn");
+        keys(&mut editor, "u");
+
+        // Typing in front of a block goes onto every line that reaches it, and typing after it pads out the others.
+        keys(&mut editor, "gg0ll<C-v>jjjIX<Esc>");
+        assert_eq!(
+            editor.buffer.contents().to_string(),
+            "abXcd\nefXgh\nij\nklXmn"
+        );
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 2));
+        keys(&mut editor, "u");
+        keys(&mut editor, "gg0ll<C-v>jjA-<Esc>");
+        assert_eq!(
+            editor.buffer.contents().to_string(),
+            "abc-d\nefg-h\nij -\nklmn"
+        );
+        keys(&mut editor, "u");
+        assert_eq!(editor.buffer.contents().to_string(), "abcd\nefgh\nij\nklmn");
+
+        // Pressing the key for another kind of selection switches to it, and pressing the same one again leaves.
+        keys(&mut editor, "vV");
+        assert_eq!(editor.mode_name(), "VISUAL LINE");
+        keys(&mut editor, "V");
+        assert_eq!(editor.mode_name(), "NAVIGATE");
+    }
+
+    #[test]
+    fn test_blocks_to_the_end_of_lines() {
+        let mut editor = Editor::new(Buffer::new("abcd\nef\nghij"));
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+
+        // After `$`, moving up and down keeps to the end of each line.
+        keys(&mut editor, "$j");
+        assert_eq!(editor.displayed_cursor(), Position::new(1, 1));
+        keys(&mut editor, "j");
+        assert_eq!(editor.displayed_cursor(), Position::new(2, 3));
+
+        // So a block reaches the end of every line, rather than stopping at the end of the one the cursor is on.
+        keys(&mut editor, "gg0<C-v>$jj");
+        assert_eq!(editor.selection(), vec![0..4, 5..7, 8..12]);
+        keys(&mut editor, "AX<Esc>");
+        assert_eq!(editor.buffer.contents().to_string(), "abcdX\nefX\nghijX");
+        keys(&mut editor, "u");
+        assert_eq!(editor.buffer.contents().to_string(), "abcd\nef\nghij");
+
+        // Without the `$` the block stops short, and appending to it pads out the shorter line instead.
+        keys(&mut editor, "gg0<C-v>jj$hAX<Esc>");
+        assert_eq!(editor.buffer.contents().to_string(), "abcXd\nef X\nghiXj");
+    }
+
+    #[test]
+    fn test_adding_to_numbers() {
+        let mut editor = Editor::new(Buffer::new("width: 0x0f, 9px\nitem-007"));
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+        keys(&mut editor, "<C-a>");
+        assert_eq!(
+            editor.buffer.contents().to_string(),
+            "width: 0x10, 9px\nitem-007"
+        );
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 10));
+        keys(&mut editor, "w11<C-x>");
+        assert_eq!(
+            editor.buffer.contents().to_string(),
+            "width: 0x10, -2px\nitem-007"
+        );
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 14));
+
+        // It's repeated with `.`, and undone in one go.
+        keys(&mut editor, "j0.");
+        assert_eq!(
+            editor.buffer.contents().to_string(),
+            "width: 0x10, -2px\nitem-018"
+        );
+        keys(&mut editor, "uu");
+        assert_eq!(
+            editor.buffer.contents().to_string(),
+            "width: 0x10, 9px\nitem-007"
+        );
+
+        // A count that doesn't fit is as big as it gets, rather than wrapping around into a negative one.
+        let mut editor = Editor::new(Buffer::new("1"));
+        keys(&mut editor, "99999999999999999999<C-a>");
+        assert_eq!(editor.buffer.contents().to_string(), "9223372036854775808");
+        keys(&mut editor, "99999999999999999999<C-x>");
+        assert_eq!(editor.buffer.contents().to_string(), "1");
+    }
+
+    #[test]
+    fn test_sorting_lines() {
+        let mut editor = Editor::new(Buffer::new("c\nb 10\na 9\nb 10\n"));
+        type_command(&mut editor, "sort");
+        assert_eq!(editor.buffer.contents().to_string(), "a 9\nb 10\nb 10\nc\n");
+        type_command(&mut editor, "sort! nu");
+        assert_eq!(editor.buffer.contents().to_string(), "b 10\na 9\nc\n");
+        type_command(&mut editor, "2,3reverse");
+        assert_eq!(editor.buffer.contents().to_string(), "b 10\nc\na 9\n");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 0));
+
+        // Each one is undone in one go.
+        editor.undo();
+        assert_eq!(editor.buffer.contents().to_string(), "b 10\na 9\nc\n");
+        editor.undo();
+        assert_eq!(editor.buffer.contents().to_string(), "a 9\nb 10\nb 10\nc\n");
+
+        // The prompt starts off with the lines selected in Visual mode as its range, and the last line keeps its missing line ending.
+        let mut editor = Editor::new(Buffer::new("x\nz\r\ny\r\nw"));
+        editor.goto(1, 0);
+        for key in keymap::parse_keys("Vj:").unwrap() {
+            editor.handle_event(Event::Key(key));
+        }
+        assert_eq!(editor.command_line.text(), "2,3");
+        editor.command_line.insert_str("sort");
+        editor.run_command();
+        assert_eq!(editor.buffer.contents().to_string(), "x\ny\r\nz\r\nw");
+        type_command(&mut editor, "%rev");
+        assert_eq!(editor.buffer.contents().to_string(), "w\nz\r\ny\r\nx");
+
+        // One line ending in `\r\n` doesn't give it to every other line.
+        let mut editor = Editor::new(Buffer::new("b\nc\r\na\n"));
+        type_command(&mut editor, "sort");
+        assert_eq!(editor.buffer.contents().to_string(), "a\nb\nc\r\n");
+    }
+
+    #[test]
+    fn test_terminal_title() {
+        let mut editor = Editor::new(Buffer::new("abc"));
+        let render = |editor: &mut Editor| {
+            let mut output = Vec::new();
+            editor.render(&mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert!(render(&mut editor).contains("\x1b]0;[No Name] — te\x07"));
+        // The title is only sent when it changes.
+        assert!(!render(&mut editor).contains("\x1b]0;"));
+        type_command(&mut editor, "s/a/b/");
+        assert!(render(&mut editor).contains("\x1b]0;[No Name] + — te\x07"));
+
+        editor.buffer.set_path(PathBuf::from("/tmp/notes\x1b.txt"));
+        assert!(render(&mut editor).contains("\x1b]0;notes.txt + — te\x07"));
+    }
+
+    #[test]
+    fn test_start_screen() {
+        let mut editor = Editor::new(Buffer::new(""));
+        editor.redraw(40, 20);
+        editor.recent_files = vec![
+            (PathBuf::from("/notes.txt"), Position::default()),
+            (
+                PathBuf::from("/a/very/long/path/that/wont/fit/on/the/screen.rs"),
+                Position::default(),
+            ),
+        ];
+        editor.show_start_screen();
+        let text = editor.render_text().unwrap();
+        assert!(text.iter().any(|row| row.trim() == "Té 0.1.0"));
+        assert!(text.iter().any(|row| row.trim() == "/notes.txt"));
+        assert!(text
+            .iter()
+            .any(|row| row.starts_with("  …") && row.ends_with("/the/screen.rs")));
+        assert!(text.iter().any(|row| row.contains(":q          quit")));
+
+        editor.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('j'),
+            KeyModifiers::NONE,
+        )));
+        let text = editor.render_text().unwrap();
+        assert_eq!(text[0], "");
+        assert!(text[1..19].iter().all(|row| row == "~"));
+    }
+
+    #[test]
+    fn test_matching_brackets() {
+        let mut editor = Editor::new(Buffer::new("f(a[0])\n{"));
+        assert!(editor.matching_brackets().is_empty());
+        editor.goto(0, 1);
+        assert_eq!(editor.matching_brackets(), [1, 6]);
+        editor.goto(0, 5);
+        assert_eq!(editor.matching_brackets(), [5, 3]);
+        // A bracket with nothing to pair up with isn't picked out.
+        editor.goto(1, 0);
+        assert!(editor.matching_brackets().is_empty());
+
+        // While typing, the cursor is between characters, so the one before it counts too.
+        editor.goto(0, 4);
+        editor.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('i'),
+            KeyModifiers::NONE,
+        )));
+        assert_eq!(editor.matching_brackets(), [3, 5]);
+    }
+
+    #[test]
+    fn test_mouse() {
+        let mouse = |kind, column, row| MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        };
+        let lines = (1..=20)
+            .map(|n| format!("line {}", n))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut editor = Editor::new(Buffer::new(&lines));
+        editor.set_display_columns(20);
+        editor.set_display_rows(6);
+
+        editor.handle_mouse(mouse(MouseEventKind::Down(MouseButton::Left), 1, 2));
+        assert_eq!(editor.buffer.cursor(), Position::new(2, 1));
+        editor.handle_mouse(mouse(MouseEventKind::Down(MouseButton::Left), 15, 3));
+        assert_eq!(editor.buffer.cursor(), Position::new(3, 5));
+
+        editor.handle_mouse(mouse(MouseEventKind::Drag(MouseButton::Left), 1, 4));
+        assert_eq!(editor.selection(), vec![26..30]);
+
+        // Scrolling drags the cursor along once it would go off the top of the display.
+        editor.mode = EditorMode::Navigate;
+        editor.handle_mouse(mouse(MouseEventKind::ScrollDown, 0, 0));
+        editor.handle_mouse(mouse(MouseEventKind::ScrollDown, 0, 0));
+        assert_eq!(editor.view.visible_lines(), 6..11);
+        assert_eq!(editor.buffer.cursor().line, 6);
+        editor.handle_mouse(mouse(MouseEventKind::ScrollUp, 0, 0));
+        assert_eq!(editor.buffer.cursor().line, 6);
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let mut editor = Editor::new(Buffer::new("abc"));
+        assert!(!type_command(&mut editor, "frobnicate"));
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("Not an editor command: frobnicate")
+        );
+        assert!(matches!(editor.mode, EditorMode::Navigate));
+        assert_eq!(editor.command_line.text(), "");
+    }
+
+    #[test]
+    fn test_word_motions_from_end_of_line() {
+        // Moving down leaves the cursor past the end of a short line, but motions start from where it's shown.
+        let mut editor = Editor::new(Buffer::new("abcdef\nab cd"));
+        editor.buffer.goto(0, 5);
+        editor.buffer.move_cursor(CursorMovement::Down, false, 8);
+        editor.move_by(Motion::PreviousWordStart);
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 3));
+    }
+
+    #[test]
+    fn test_line_motions_scroll_long_lines() {
+        let mut editor = Editor::new(Buffer::new("0123456789\n  abc"));
+        editor.set_display_columns(4);
+
+        editor.move_by(Motion::LineEnd);
+        editor.scroll_to_cursor();
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 9));
+        assert_eq!(
+            editor
+                .view
+                .screen_position(&editor.buffer, editor.buffer.cursor()),
+            (3, 0)
+        );
+
+        editor.move_by(Motion::LineStart);
+        editor.scroll_to_cursor();
+        assert_eq!(
+            editor
+                .view
+                .screen_position(&editor.buffer, editor.buffer.cursor()),
+            (0, 0)
+        );
+
+        editor.buffer.goto(1, 0);
+        editor.move_by(Motion::FirstNonBlank);
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 2));
+    }
+
+    #[test]
+    fn test_cursor_after_wide_characters() {
+        let mut editor = Editor::new(Buffer::new("漢字xyz"));
+        editor.set_display_columns(5);
+
+        editor.move_by(Motion::LineEnd);
+        editor.scroll_to_cursor();
+        assert_eq!(editor.cursor_cell(), Position::new(0, 6));
+        assert_eq!(
+            editor
+                .view
+                .screen_position(&editor.buffer, editor.cursor_cell()),
+            (4, 0)
+        );
+
+        editor.buffer.move_cursor(CursorMovement::Left, false, 8);
+        editor.buffer.move_cursor(CursorMovement::Left, false, 8);
+        editor.buffer.move_cursor(CursorMovement::Left, false, 8);
+        editor.scroll_to_cursor();
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 3));
+        assert_eq!(
+            editor
+                .view
+                .screen_position(&editor.buffer, editor.cursor_cell()),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn test_scrolling_commands() {
+        let text = (1..=30).map(|n| n.to_string()).collect::<Vec<_>>();
+        let mut editor = Editor::new(Buffer::new(&text.join("\n")));
+        editor.view.set_rows(10);
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+
+        keys(&mut editor, "<C-d>");
+        assert_eq!(editor.view.visible_lines(), 5..15);
+        assert_eq!(editor.buffer.cursor().line, 5);
+        keys(&mut editor, "<C-f>");
+        assert_eq!(editor.view.visible_lines(), 15..25);
+        assert_eq!(editor.buffer.cursor().line, 15);
+        keys(&mut editor, "<C-u>");
+        assert_eq!(editor.view.visible_lines(), 10..20);
+        assert_eq!(editor.buffer.cursor().line, 10);
+
+        keys(&mut editor, "zb");
+        assert_eq!(editor.view.visible_lines(), 1..11);
+        keys(&mut editor, "zz");
+        assert_eq!(editor.view.visible_lines(), 5..15);
+        keys(&mut editor, "zt");
+        assert_eq!(editor.view.visible_lines(), 10..20);
+        keys(&mut editor, "<C-b>");
+        assert_eq!(editor.view.visible_lines(), 0..10);
+        assert_eq!(editor.buffer.cursor().line, 0);
+    }
+
+    #[test]
+    fn test_moving_by_display_rows() {
+        let mut editor = Editor::new(Buffer::new("abcdefghijklmnopqrstuvwxy\nxyz"));
+        editor.view.set_columns(10);
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+
+        // Without wrapping, every line is a single row.
+        keys(&mut editor, "lllgj");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 2));
+        // The short line doesn't make the cursor forget which column it came from.
+        keys(&mut editor, "gk");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 3));
+
+        type_command(&mut editor, "set wrap");
+        keys(&mut editor, "gj");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 13));
+        keys(&mut editor, "gjgj");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 2));
+        keys(&mut editor, "gk");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 23));
+        keys(&mut editor, "2gk");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 3));
+    }
+
+    #[test]
+    fn test_moving_by_display_rows_over_tabs_and_wide_characters() {
+        let mut editor = Editor::new(Buffer::new("a\tb\n漢字漢字x\nab\n漢字漢字漢字漢字"));
+        editor.view.set_columns(10);
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+
+        // The `b` after the tab is drawn in the ninth cell, and so is the `x` after the four wide characters.
+        keys(&mut editor, "llgj");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 12));
+        keys(&mut editor, "gjgj");
+        assert_eq!(editor.buffer.cursor(), Position::new(3, 12));
+        keys(&mut editor, "gkgkgk");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 2));
+
+        // Moving sideways picks a new cell to keep to, which lands inside the tab on the first line.
+        keys(&mut editor, "3gjh");
+        assert_eq!(editor.buffer.cursor(), Position::new(3, 9));
+        keys(&mut editor, "gk");
+        assert_eq!(editor.buffer.cursor(), Position::new(2, 1));
+        keys(&mut editor, "2gk");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 1));
+
+        // With wrapping, the rows of the last line start at its first and sixth characters.
+        type_command(&mut editor, "set wrap");
+        keys(&mut editor, "03gjgj");
+        assert_eq!(editor.buffer.cursor(), Position::new(3, 15));
+        keys(&mut editor, "gk");
+        assert_eq!(editor.buffer.cursor(), Position::new(3, 0));
+    }
+
+    #[test]
+    fn test_inserting_tabs() {
+        let mut editor = Editor::new(Buffer::new("ab"));
+        editor.mode = EditorMode::Edit;
+        editor.insert_tab();
+        assert_eq!(editor.buffer.contents(), &"\tab");
+
+        editor.set_tab_width(4);
+        editor.set_expand_tab(true);
+        editor.buffer.goto(0, 2);
+        editor.insert_tab();
+        assert_eq!(editor.buffer.contents(), &"\ta   b");
+        assert_eq!(editor.cursor_cell(), Position::new(0, 8));
+    }
+
+    #[test]
+    fn test_key_sequences() {
+        let key = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        assert_eq!(
+            Editor::sequence_motion('g', &key('g')),
+            Some(Motion::DocumentStart)
+        );
+        assert_eq!(Editor::sequence_motion('g', &key('x')), None);
+    }
+
+    /// Types a pattern in at the search prompt, one character at a time.
+    fn type_search(editor: &mut Editor, pattern: &str) {
+        editor.start_search();
+        for c in pattern.chars() {
+            editor.command_line.insert_str(c.encode_utf8(&mut [0; 4]));
+            editor.update_search();
+        }
+    }
+
+    #[test]
+    fn test_incremental_search() {
+        let mut editor = Editor::new(Buffer::new("one two\nthree two"));
+        type_search(&mut editor, "t");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 4));
+        editor.command_line.insert_str("hr");
+        editor.update_search();
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 0));
+
+        // Giving up puts the cursor back where it was.
+        editor.cancel_search();
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 0));
+        assert_eq!(editor.last_search, None);
+    }
+
+    #[test]
+    fn test_searching_again() {
+        let mut editor = Editor::new(Buffer::new("one two\nthree two"));
+        type_search(&mut editor, "two");
+        editor.finish_search();
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 4));
+        assert!(matches!(editor.mode, EditorMode::Navigate));
+
+        editor.search_again(true);
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 6));
+        editor.search_again(true);
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 4));
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("Search hit BOTTOM, continuing at TOP")
+        );
+        editor.search_again(false);
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 6));
+
+        // An empty pattern looks for the last one again.
+        type_search(&mut editor, "");
+        editor.finish_search();
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 4));
+    }
+
+    #[test]
+    fn test_searching_from_command_line() {
+        let mut editor = Editor::new(Buffer::new("one two\nthree two"));
+        editor.goto(1, 0);
+        editor.search("two".to_string());
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 6));
+        editor.search_again(true);
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 4));
+    }
+
+    #[test]
+    fn test_search_not_found() {
+        let mut editor = Editor::new(Buffer::new("abc"));
+        editor.search_again(true);
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("No previous search pattern")
+        );
+
+        type_search(&mut editor, "x");
+        editor.finish_search();
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 0));
+        assert_eq!(editor.message.as_deref(), Some("Pattern not found: x"));
+    }
+
+    #[test]
+    fn test_filtering_through_shell_commands() {
+        let mut editor = Editor::new(Buffer::new("c\nb\na\nz"));
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+        keys(&mut editor, "!j");
+        assert!(matches!(editor.mode, EditorMode::Command));
+        assert_eq!(editor.command_line.text(), "1,2!");
+        keys(&mut editor, "sort<Enter>");
+        assert_eq!(editor.buffer.contents(), &"b\nc\na\nz");
+
+        // The whole lot is undone in one go, and `.` runs the same filter again.
+        keys(&mut editor, "u");
+        assert_eq!(editor.buffer.contents(), &"c\nb\na\nz");
+        keys(&mut editor, "j.");
+        assert_eq!(editor.buffer.contents(), &"c\na\nb\nz");
+
+        // The last line keeps its lack of a line ending.
+        type_command(&mut editor, "%!sort -r");
+        assert_eq!(editor.buffer.contents(), &"z\nc\nb\na");
+        keys(&mut editor, "Gvk!");
+        assert_eq!(editor.command_line.text(), "3,4!");
+        keys(&mut editor, "<Esc>j!!");
+        assert_eq!(editor.command_line.text(), "4!");
+        keys(&mut editor, "tr a-z A-Z<Enter>");
+        assert_eq!(editor.buffer.contents(), &"z\nc\nb\nA");
+
+        // A command that fails leaves the lines alone.
+        type_command(&mut editor, "1!exit 1");
+        assert_eq!(editor.buffer.contents(), &"z\nc\nb\nA");
+        assert!(editor.message_is_error);
+    }
+
+    #[test]
+    fn test_reading_shell_command_output() {
+        let mut editor = Editor::new(Buffer::new("one\ntwo"));
+        type_command(&mut editor, "r !printf 'a\\nb\\n'");
+        assert_eq!(editor.buffer.contents(), &"one\na\nb\ntwo");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 0));
+
+        editor.goto(3, 0);
+        type_command(&mut editor, "r !echo three");
+        assert_eq!(editor.buffer.contents(), &"one\na\nb\ntwo\nthree");
+
+        // Running a command on its own hands the terminal over to it.
+        editor.mode = EditorMode::Command;
+        editor.command_line.set_text("!ls");
+        assert_eq!(
+            editor.handle_event(Event::Key(KeyEvent::from(KeyCode::Enter))),
+            Some(Exit::Shell("ls".to_string()))
+        );
+
+        // Without a terminal, the command is run there and then.
+        assert_eq!(editor.execute_line("!echo hi"), Ok(false));
+        assert_eq!(editor.message.as_deref(), Some("hi"));
+        assert!(editor.shell_command.is_none());
+        assert!(editor.execute_line("!exit 3").is_err());
+        assert!(editor.shell_command.is_none());
+    }
+
+    #[test]
+    fn test_stepping_through_errors() {
+        let dir = TempDir::new("quickfix");
+        dir.write("a.txt", "one\ntwo\nthree\n");
+        dir.write("b.txt", "four\n");
+        let errors = format!(
+            "{0}/a.txt:2:3: first\nsomething else\n{0}/a.txt:3: second\n{0}/b.txt:1:1: third\n",
+            dir.display()
+        );
+        std::fs::write(dir.join("errors"), errors).unwrap();
+
+        let mut editor = Editor::new(Buffer::new(""));
+        assert!(!type_command(&mut editor, "cn"));
+        assert_eq!(editor.message.as_deref(), Some("No errors"));
+
+        type_command(&mut editor, &format!("cfile {}/errors", dir.display()));
+        assert_eq!(editor.buffer.path(), Some(dir.join("a.txt").as_path()));
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 2));
+        assert_eq!(editor.message.as_deref(), Some("(1 of 3) first"));
+
+        type_command(&mut editor, "cn");
+        assert_eq!(editor.buffer.cursor(), Position::new(2, 0));
+        type_command(&mut editor, "cn");
+        assert_eq!(editor.buffer.path(), Some(dir.join("b.txt").as_path()));
+        assert_eq!(editor.message.as_deref(), Some("(3 of 3) third"));
+        type_command(&mut editor, "cn");
+        assert_eq!(editor.message.as_deref(), Some("No more errors"));
+        type_command(&mut editor, "cp");
+        assert_eq!(editor.buffer.path(), Some(dir.join("a.txt").as_path()));
+        assert_eq!(editor.message.as_deref(), Some("(2 of 3) second"));
+
+        // `:make` picks the errors out of whatever the build command prints.
+        type_command(&mut editor, "set makeprg=cat");
+        type_command(&mut editor, &format!("make {}/errors", dir.display()));
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 2));
+        assert_eq!(editor.message.as_deref(), Some("(1 of 3) first"));
+    }
+
+    #[test]
+    fn test_acting_on_language_server_notices() {
+        let dir = TempDir::new("lsp");
+        std::fs::write(
+            dir.join("a.rs"),
+            "fn main() {\n    let é = vec![1];\n    é.p\n}\n",
+        )
+        .unwrap();
+        dir.write("b.rs", "fn other() {}\n");
+
+        let mut editor = Editor::from_path(dir.join("a.rs")).unwrap();
+        let diagnostic = lsp::Diagnostic {
+            line: 2,
+            character: 4,
+            severity: lsp::Severity::Error,
+            message: "no method named `p`".to_string(),
+        };
+        editor
+            .handle_notice(Notice::Diagnostics {
+                path: dir.join("a.rs"),
+                diagnostics: vec![diagnostic.clone()],
+            })
+            .unwrap();
+        assert_eq!(editor.buffer.diagnostic_on(2), Some(&diagnostic));
+
+        // Columns from a server are counted in UTF-16, which the `é` is one of but two bytes.
+        let location = |path: &str, line, character| lsp::Location {
+            path: dir.join(path),
+            line,
+            character,
+        };
+        editor
+            .handle_notice(Notice::Definition(location("a.rs", 1, 9)))
+            .unwrap();
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 10));
+        editor
+            .handle_notice(Notice::Definition(location("b.rs", 0, 3)))
+            .unwrap();
+        assert_eq!(editor.buffer.path(), Some(dir.join("b.rs").as_path()));
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 3));
+        editor.buffer.jump_back();
+
+        editor
+            .handle_notice(Notice::Hover("fn main()\n\nThe start.".to_string()))
+            .unwrap();
+        assert_eq!(editor.message.as_deref(), Some("fn main() The start."));
+
+        // Completions only finish off the word as far as they all agree.
+        editor
+            .handle_notice(Notice::Completion(vec!["push".to_string()]))
+            .unwrap();
+        editor.edit(dir.join("a.rs"), false).unwrap();
+        editor.goto(2, 0);
+        editor.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('A'),
+            KeyModifiers::NONE,
+        )));
+        let words = |words: &[&str]| words.iter().map(|w| w.to_string()).collect();
+        editor
+            .handle_notice(Notice::Completion(words(&[
+                "pop", "push", "push_str", "len",
+            ])))
+            .unwrap();
+        assert_eq!(editor.message.as_deref(), Some("pop  push  push_str"));
+        editor
+            .handle_notice(Notice::Completion(words(&["push", "push_str"])))
+            .unwrap();
+        assert_eq!(
+            editor.buffer.contents().to_string(),
+            "fn main() {\n    let é = vec![1];\n    é.push\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_toggling_comments() {
+        let mut buffer = Buffer::new("fn main() {\n    one();\n\n    two();\n}\n");
+        buffer.set_path(PathBuf::from("main.rs"));
+        let mut editor = Editor::new(buffer);
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+
+        keys(&mut editor, "jgcc");
+        assert_eq!(
+            editor.buffer.contents(),
+            &"fn main() {\n    // one();\n\n    two();\n}\n"
+        );
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 4));
+        // Lines that are only partly commented out get commented again, and then all uncommented.
+        keys(&mut editor, "gc2j");
+        assert_eq!(
+            editor.buffer.contents(),
+            &"fn main() {\n    // // one();\n\n    // two();\n}\n"
+        );
+        keys(&mut editor, "uuggj3gcc");
+        let commented = "fn main() {\n    // one();\n\n    // two();\n}\n";
+        assert_eq!(editor.buffer.contents(), &commented);
+        keys(&mut editor, "ggvGgc");
+        assert_eq!(
+            editor.buffer.contents(),
+            &"// fn main() {\n//     // one();\n\n//     // two();\n// }\n"
+        );
+        keys(&mut editor, ".");
+        assert_eq!(editor.buffer.contents(), &commented);
+
+        // The configuration can say how comments are written, and files without a comment string can't be commented.
+        editor.set_comment_strings(vec![("rs".to_string(), "/* %s */".to_string())]);
+        keys(&mut editor, "ggjgcc");
+        assert_eq!(
+            editor.buffer.contents(),
+            &"fn main() {\n    /* // one(); */\n\n    // two();\n}\n"
+        );
+        editor.buffer.set_path(PathBuf::from("notes.txt"));
+        keys(&mut editor, "gcc");
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("No comment string for this type of file")
+        );
+    }
+
+    #[test]
+    fn test_surrounding_text() {
+        let mut editor = Editor::new(Buffer::new("say hello world\n  indented\n"));
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+
+        // The space that `w` moves over is left outside of what gets surrounded.
+        keys(&mut editor, "wysw)");
+        assert_eq!(editor.buffer.contents(), &"say (hello) world\n  indented\n");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 4));
+        keys(&mut editor, "cs)[");
+        assert_eq!(
+            editor.buffer.contents(),
+            &"say [ hello ] world\n  indented\n"
+        );
+        keys(&mut editor, "ds[");
+        assert_eq!(editor.buffer.contents(), &"say hello world\n  indented\n");
+        keys(&mut editor, "u");
+        assert_eq!(
+            editor.buffer.contents(),
+            &"say [ hello ] world\n  indented\n"
+        );
+
+        // Whole lines are surrounded from their indent, and tags are typed in at a prompt.
+        keys(&mut editor, "jyssta href=\"x\"<Enter>");
+        assert_eq!(
+            editor.buffer.contents(),
+            &"say [ hello ] world\n  <a href=\"x\">indented</a>\n"
+        );
+        keys(&mut editor, "fncstt");
+        assert!(matches!(editor.mode, EditorMode::Tag));
+        keys(&mut editor, "em<Enter>");
+        assert_eq!(
+            editor.buffer.contents(),
+            &"say [ hello ] world\n  <em>indented</em>\n"
+        );
+
+        // Visual mode surrounds the selection, and `.` repeats the last surround at the cursor.
+        keys(&mut editor, "ggvllS\"");
+        assert_eq!(
+            editor.buffer.contents(),
+            &"\"say\" [ hello ] world\n  <em>indented</em>\n"
+        );
+        keys(&mut editor, "fwysw'");
+        keys(&mut editor, "0fh.");
+        assert_eq!(
+            editor.buffer.contents(),
+            &"\"say\" [ 'hello' ] 'world'\n  <em>indented</em>\n"
+        );
+
+        keys(&mut editor, "ds(");
+        assert_eq!(editor.message.as_deref(), Some("No ( around the cursor"));
+        keys(&mut editor, "yswx");
+        assert_eq!(editor.message.as_deref(), Some("Cannot surround with x"));
+    }
+
+    #[test]
+    fn test_completing_words_from_buffers() {
+        let mut editor = Editor::new(Buffer::new("hello help\nhe"));
+        editor.buffers.add(&mut editor.buffer, Buffer::new("heap"));
+        editor.buffers.switch(&mut editor.buffer, 0);
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+
+        keys(&mut editor, "GA<C-n>");
+        assert_eq!(editor.buffer.contents(), &"hello help\nhello");
+        keys(&mut editor, "<C-n><C-n>");
+        assert_eq!(editor.buffer.contents(), &"hello help\nheap");
+        keys(&mut editor, "<C-n>");
+        assert_eq!(editor.buffer.contents(), &"hello help\nhe");
+        keys(&mut editor, "<C-p>");
+        assert_eq!(editor.buffer.contents(), &"hello help\nheap");
+        assert_eq!(
+            editor.word_completion.as_ref().unwrap().candidates(),
+            ["hello", "help", "heap"]
+        );
+
+        // Typing carries on from the word that was picked, and `Ctrl-E` puts back what was typed.
+        keys(&mut editor, "s <C-p><C-p><C-e>");
+        assert_eq!(editor.buffer.contents(), &"hello help\nheaps ");
+        assert!(editor.word_completion.is_none());
+        keys(&mut editor, "x<C-n>");
+        assert_eq!(editor.message.as_deref(), Some("No completions"));
+
+        // The whole thing is undone in one go along with the rest of what was typed.
+        keys(&mut editor, "<Esc>u");
+        assert_eq!(editor.buffer.contents(), &"hello help\nhe");
+    }
+
+    #[test]
+    fn test_recalling_history_at_prompt() {
+        let mut editor = Editor::new(Buffer::new("one two\nthree two"));
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+        keys(
+            &mut editor,
+            ":set so=1<Enter>:set nowrap<Enter>/thr<Enter>/tw<Enter>",
+        );
+        assert_eq!(editor.command_history.entries(), ["set so=1", "set nowrap"]);
+        assert_eq!(editor.search_history.entries(), ["thr", "tw"]);
+
+        keys(&mut editor, ":<Up>");
+        assert_eq!(editor.command_line.text(), "set nowrap");
+        keys(&mut editor, "<Up><Up>");
+        assert_eq!(editor.command_line.text(), "set so=1");
+        keys(&mut editor, "<Down><Down>");
+        assert_eq!(editor.command_line.text(), "");
+
+        // Ctrl-R looks for the typed text anywhere in the line, rather than just at the start.
+        keys(&mut editor, "so<C-r>");
+        assert_eq!(editor.command_line.text(), "set so=1");
+        keys(&mut editor, "<Esc>");
+
+        // Recalling a search moves the cursor to it, like typing it in would.
+        editor.goto(0, 0);
+        keys(&mut editor, "/<Up><Up>");
+        assert_eq!(editor.command_line.text(), "thr");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 0));
+    }
+
+    #[test]
+    fn test_completing_at_prompt() {
+        let mut editor = Editor::new(Buffer::new("abc"));
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+        keys(&mut editor, ":se no<Tab>");
+        assert_eq!(editor.command_line.text(), "se nocursorline");
+        keys(&mut editor, "<Tab>");
+        assert_eq!(editor.command_line.text(), "se nolist");
+        assert_eq!(editor.completion.as_ref().unwrap().selected(), Some(1));
+        editor.handle_event(Event::Key(KeyEvent::from(KeyCode::BackTab)));
+        assert_eq!(editor.command_line.text(), "se nocursorline");
+
+        // Typing anything else settles on the candidate that's showing.
+        keys(&mut editor, "<BS>");
+        assert!(editor.completion.is_none());
+        assert_eq!(editor.command_line.text(), "se nocursorlin");
+        keys(&mut editor, "<Esc>");
+
+        // There's nothing to cycle through when there's only one way to finish the word.
+        keys(&mut editor, ":wr<Tab>");
+        assert_eq!(editor.command_line.text(), "write");
+        assert!(editor.completion.is_none());
+        keys(&mut editor, "<Esc>:zzz<Tab>");
+        assert_eq!(editor.command_line.text(), "zzz");
+    }
+
+    #[test]
+    fn test_keeping_history_in_file() {
+        let dir = TempDir::new("history");
+        let path = dir.join("state").join("state");
+
+        let mut editor = Editor::new(Buffer::new("abc"));
+        editor.set_state_file(path.clone());
+        type_command(&mut editor, "set wrap");
+        type_search(&mut editor, "b");
+        editor.finish_search();
+        editor.write_state().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), ":set wrap\n/b\n");
+
+        let mut editor = Editor::new(Buffer::new("abc"));
+        editor.set_state_file(path);
+        assert_eq!(editor.command_history.entries(), ["set wrap"]);
+        assert_eq!(editor.search_history.entries(), ["b"]);
+    }
+
+    #[test]
+    fn test_keeping_state_in_file() {
+        let key = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        let dir = TempDir::new("state");
+        let (one, two) = (dir.join("one.txt"), dir.join("two.txt"));
+        std::fs::write(&one, "a\nb\nc\n").unwrap();
+        std::fs::write(&two, "x\ny\nz\n").unwrap();
+        let path = dir.join("state");
+
+        let mut editor = Editor::from_path(&one).unwrap();
+        editor.set_state_file(path.clone());
+        editor.buffer.goto(2, 0);
+        editor.complete_sequence('m', &key('A'));
+        editor.complete_sequence('"', &key('q'));
+        editor.handle_event(key('y'));
+        editor.handle_event(key('y'));
+        type_command(&mut editor, &format!("e {}", two.display()));
+        editor.buffer.goto(1, 0);
+        editor.write_state().unwrap();
+
+        // The file is back where it was left, and the mark in the other one can be jumped to.
+        let mut editor = Editor::from_path(&two).unwrap();
+        editor.set_state_file(path);
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 0));
+        assert_eq!(
+            editor.registers.get(Some('q')),
+            Some(&Register::new("c\n".to_string(), true))
+        );
+        type_command(&mut editor, "oldfiles");
+        assert_eq!(
+            editor.message.as_deref(),
+            Some(format!("1 \"{}\"  2 \"{}\"", two.display(), one.display()).as_str())
+        );
+        editor.complete_sequence('\'', &key('A'));
+        assert_eq!(editor.buffer.path(), Some(one.as_path()));
+        assert_eq!(editor.buffer.cursor(), Position::new(2, 0));
+        editor.complete_sequence('\'', &key('B'));
+        assert_eq!(editor.message.as_deref(), Some("Mark not set"));
+    }
+
+    #[test]
+    fn test_counting_search_matches() {
+        let mut editor = Editor::new(Buffer::new("one two\nthree two\ntwo"));
+        assert_eq!(editor.status_line(30), " NAVIGATE  [No Name]      1:1 ");
+        editor.search("two".to_string());
+        assert_eq!(
+            editor.status_line(40),
+            " NAVIGATE  [No Name]  match 1 of 3  1:5 "
+        );
+        editor.search_again(false);
+        assert!(editor.status_line(40).ends_with("match 3 of 3  3:1 "));
+
+        // Moving off a match hides the count, but the matches stay highlighted.
+        editor.handle_event(Event::Key(KeyEvent::from(KeyCode::Char('l'))));
+        assert!(editor.status_line(40).ends_with(" 3:2 "));
+        assert!(editor.highlight_search);
+
+        editor.handle_event(Event::Key(KeyEvent::from(KeyCode::Esc)));
+        assert!(!editor.highlight_search);
+        editor.search_again(true);
+        assert!(editor.highlight_search);
+        type_command(&mut editor, "noh");
+        assert!(!editor.highlight_search);
+        assert!(editor.status_line(40).ends_with(" 1:5 "));
+    }
+
+    #[test]
+    fn test_selection_includes_both_ends() {
+        let mut editor = Editor::new(Buffer::new("abc\ndéf"));
+        editor.buffer.goto(1, 1);
+        editor.mode = EditorMode::Visual {
+            anchor: 1,
+            kind: VisualKind::Characters,
+        };
+        assert_eq!(editor.selection(), vec![1..7]);
+
+        // Selecting backwards from the anchor covers the same text.
+        editor.buffer.goto(0, 1);
+        editor.mode = EditorMode::Visual {
+            anchor: 5,
+            kind: VisualKind::Characters,
+        };
+        assert_eq!(editor.selection(), vec![1..7]);
+    }
+
+    #[test]
+    fn test_deleting_selection() {
+        let mut editor = Editor::new(Buffer::new("abc\ndef"));
+        editor.buffer.goto(1, 0);
+        editor.mode = EditorMode::Visual {
+            anchor: 1,
+            kind: VisualKind::Characters,
+        };
+        editor.apply_to_selection(Operator::Delete);
+
+        assert_eq!(editor.buffer.contents(), &"aef");
+        assert_eq!(
+            editor.registers.get(None),
+            Some(&Register::new("bc\nd".to_string(), false))
+        );
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 1));
+        assert!(matches!(editor.mode, EditorMode::Navigate));
+
+        editor.undo();
+        assert_eq!(editor.buffer.contents(), &"abc\ndef");
+    }
+
+    #[test]
+    fn test_yanking_selection() {
+        let mut editor = Editor::new(Buffer::new("abcdef"));
+        editor.buffer.goto(0, 4);
+        editor.mode = EditorMode::Visual {
+            anchor: 2,
+            kind: VisualKind::Characters,
+        };
+        editor.apply_to_selection(Operator::Yank);
+
+        assert_eq!(editor.buffer.contents(), &"abcdef");
+        assert_eq!(
+            editor.registers.get(None),
+            Some(&Register::new("cde".to_string(), false))
+        );
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 2));
+    }
+
+    #[test]
+    fn test_changing_selection() {
+        let mut editor = Editor::new(Buffer::new("abcdef"));
+        editor.buffer.goto(0, 3);
+        editor.mode = EditorMode::Visual {
+            anchor: 1,
+            kind: VisualKind::Characters,
+        };
+        editor.apply_to_selection(Operator::Change);
+        editor.buffer.insert('x');
+        assert_eq!(editor.buffer.contents(), &"axef");
+        assert!(matches!(editor.mode, EditorMode::Edit));
+
+        // The change and whatever replaced it are undone together.
+        editor.buffer.commit();
+        editor.undo();
+        assert_eq!(editor.buffer.contents(), &"abcdef");
+    }
+
+    #[test]
+    fn test_operators_over_motions() {
+        let mut editor = Editor::new(Buffer::new("foo bar\nbaz qux"));
+        editor.operator = Some((Operator::Delete, None));
+        editor.make_movement(Movement::Motion(Motion::NextWordStart));
+        assert_eq!(editor.buffer.contents(), &"bar\nbaz qux");
+
+        // The last word on a line is deleted without its line ending.
+        editor.operator = Some((Operator::Delete, None));
+        editor.make_movement(Movement::Motion(Motion::NextWordStart));
+        assert_eq!(editor.buffer.contents(), &"\nbaz qux");
+
+        editor.buffer.goto(1, 4);
+        editor.operator = Some((Operator::Yank, None));
+        editor.make_movement(Movement::Motion(Motion::PreviousWordStart));
+        assert_eq!(
+            editor.registers.get(None),
+            Some(&Register::new("baz ".to_string(), false))
+        );
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 0));
+
+        editor.operator = Some((Operator::Delete, None));
+        editor.make_movement(Movement::Motion(Motion::LineEnd));
+        assert_eq!(editor.buffer.contents(), &"\n");
+
+        editor.undo();
+        assert_eq!(editor.buffer.contents(), &"\nbaz qux");
+    }
+
+    #[test]
+    fn test_operators_over_lines() {
+        let mut editor = Editor::new(Buffer::new("one\n  two\nthree\nfour"));
+        editor.operator = Some((Operator::Delete, None));
+        editor.make_movement(Movement::Step(CursorMovement::Down));
+        assert_eq!(editor.buffer.contents(), &"three\nfour");
+        assert_eq!(
+            editor.registers.get(None),
+            Some(&Register::new("one\n  two\n".to_string(), true))
+        );
+
+        editor.buffer.goto(1, 2);
+        editor.operator = Some((Operator::Change, None));
+        editor.make_movement(Movement::Step(CursorMovement::Up));
+        assert_eq!(editor.buffer.contents(), &"");
+        assert!(matches!(editor.mode, EditorMode::Edit));
+        assert_eq!(
+            editor.registers.get(None),
+            Some(&Register::new("three\nfour\n".to_string(), true))
+        );
+    }
+
+    #[test]
+    fn test_changing_words() {
+        let mut editor = Editor::new(Buffer::new("one two three"));
+        editor.operator = Some((Operator::Change, None));
+        editor.make_movement(Movement::Motion(Motion::NextWordStart));
+        assert_eq!(editor.buffer.contents(), &" two three");
+
+        editor.mode = EditorMode::Navigate;
+        editor.buffer.goto(0, 1);
+        editor.operator = Some((Operator::Change, None));
+        editor.count = Some(2);
+        editor.make_movement(Movement::Motion(Motion::NextWordStart));
+        assert_eq!(editor.buffer.contents(), &" ");
+    }
+
+    #[test]
+    fn test_counts() {
+        let mut editor = Editor::new(Buffer::new("a b c d e f\n1\n2\n3"));
+        editor.count = Some(3);
+        editor.make_movement(Movement::Motion(Motion::NextWordStart));
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 6));
+
+        // Counts before and after an operator multiply together.
+        editor.operator = Some((Operator::Delete, Some(2)));
+        editor.count = Some(2);
+        editor.make_movement(Movement::Motion(Motion::NextWordStart));
+        assert_eq!(editor.buffer.contents(), &"a b c \n2\n3");
+
+        editor.count = Some(3);
+        editor.make_movement(Movement::Motion(Motion::DocumentEnd));
+        assert_eq!(editor.buffer.cursor(), Position::new(2, 0));
+        assert_eq!(editor.count, None);
+
+        // An operator can take in the last character of a line, even though the cursor can't move past it.
+        editor.operator = Some((Operator::Delete, None));
+        editor.count = Some(2);
+        editor.make_movement(Movement::Step(CursorMovement::Right));
+        assert_eq!(editor.buffer.contents(), &"a b c \n2\n");
+    }
+
+    #[test]
+    fn test_undo_with_empty_history() {
+        let mut editor = Editor::new(Buffer::new("abc"));
+        editor.undo();
+        assert_eq!(editor.buffer.contents(), &"abc");
+        assert_eq!(editor.message.as_deref(), Some("Already at oldest change"));
+    }
+
+    #[test]
+    fn test_substitute_command() {
+        let mut editor = Editor::new(Buffer::new("a1 a2\n  a3\na4"));
+        assert!(!type_command(&mut editor, "1,2s/a\\(\\d\\)/b\\1/g"));
+        assert_eq!(editor.buffer.contents(), &"b1 b2\n  b3\na4");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 2));
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("3 substitutions on 2 lines")
+        );
+
+        // The whole command is undone in one go.
+        editor.undo();
+        assert_eq!(editor.buffer.contents(), &"a1 a2\n  a3\na4");
+
+        type_command(&mut editor, "%s/a/c/");
+        assert_eq!(editor.buffer.contents(), &"c1 a2\n  c3\nc4");
+
+        type_command(&mut editor, "s/x/y/");
+        assert_eq!(editor.message.as_deref(), Some("Pattern not found: x"));
+        type_command(&mut editor, "s/\\(/y/");
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("Invalid pattern: unclosed group")
+        );
+    }
+
+    #[test]
+    fn test_previewing_substitutions() {
+        let mut editor = Editor::new(Buffer::new("a1 a2\na3\na4"));
+        editor.redraw(20, 5);
+        editor.mode = EditorMode::Command;
+        editor.command_line.set_text("1,2s/a/bb/g");
+        let rows = editor.render_text().unwrap();
+        assert_eq!(&rows[..3], ["bb1 bb2", "bb3", "a4"]);
+        assert_eq!(editor.buffer.contents(), &"a1 a2\na3\na4");
+
+        // Until the pattern is finished, it's left as it is.
+        editor.command_line.set_text("%s/a\\(");
+        let rows = editor.render_text().unwrap();
+        assert_eq!(&rows[..3], ["a1 a2", "a3", "a4"]);
+
+        // Replacements that split lines push the rest of the display down.
+        editor.command_line.set_text("s/ /\\n");
+        let rows = editor.render_text().unwrap();
+        let rows: Vec<_> = rows.iter().map(|row| row.trim_end()).collect();
+        assert_eq!(&rows[..4], ["a1", "a2", "a3", "a4"]);
+    }
+
+    #[test]
+    fn test_substituting_last_pattern() {
+        let mut editor = Editor::new(Buffer::new(
+            "one two
+two one",
+        ));
+        type_command(&mut editor, "s//X/");
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("No previous search pattern")
+        );
+
+        // An empty pattern is the last one searched for.
+        editor.search("two".to_string());
+        type_command(&mut editor, "%s//2/");
+        assert_eq!(
+            editor.buffer.contents(),
+            &"one 2
+2 one"
+        );
+
+        // And the pattern from `:s` is the one searched for next.
+        type_command(&mut editor, "1s/one/1/");
+        assert_eq!(
+            editor.buffer.contents(),
+            &"1 2
+2 one"
+        );
+        type_command(&mut editor, "%s//I/");
+        assert_eq!(
+            editor.buffer.contents(),
+            &"1 2
+2 I"
+        );
+    }
+
+    #[test]
+    fn test_confirming_substitutions() {
+        let mut editor = Editor::new(Buffer::new("a a\na a"));
+        type_command(&mut editor, "%s/a/b/gc");
+        assert!(matches!(editor.mode, EditorMode::Confirm));
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 0));
+
+        // This is what pressing `n` and then `y` does.
+        editor.substitution.as_mut().unwrap().skip(&editor.buffer);
+        editor.confirm_next();
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 2));
+        let substitution = editor.substitution.as_mut().unwrap();
+        substitution.replace(&mut editor.buffer);
+        editor.confirm_next();
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 0));
+        editor.finish_substitution();
+
+        assert_eq!(editor.buffer.contents(), &"a b\na a");
+        assert!(matches!(editor.mode, EditorMode::Navigate));
+        assert_eq!(editor.message.as_deref(), Some("1 substitution on 1 line"));
+    }
+
+    #[test]
+    fn test_substitute_in_pager() {
+        let mut editor = Editor::new(Buffer::new("abc"));
+        editor.set_pager(true);
+        type_command(&mut editor, "s/a/b/");
+        assert_eq!(editor.buffer.contents(), &"abc");
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("Cannot make changes while acting as a pager")
+        );
+    }
+
+    #[test]
+    fn test_yanking_and_pasting_lines() {
+        let mut editor = Editor::new(Buffer::new("one\n  two\nthree"));
+        editor.buffer.goto(1, 3);
+        editor.apply_to_lines(Operator::Yank, None);
+        editor.paste(true);
+        assert_eq!(editor.buffer.contents(), &"one\n  two\n  two\nthree");
+        assert_eq!(editor.buffer.cursor(), Position::new(2, 2));
+
+        editor.buffer.goto(3, 0);
+        editor.paste(true);
+        assert_eq!(editor.buffer.contents(), &"one\n  two\n  two\nthree\n  two");
+        editor.buffer.goto(0, 1);
+        editor.paste(false);
+        assert_eq!(
+            editor.buffer.contents(),
+            &"  two\none\n  two\n  two\nthree\n  two"
+        );
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 2));
+    }
+
+    #[test]
+    fn test_deleting_lines() {
+        let mut editor = Editor::new(Buffer::new("one\ntwo\nthree"));
+        editor.buffer.goto(2, 1);
+        editor.apply_to_lines(Operator::Delete, None);
+        assert_eq!(editor.buffer.contents(), &"one\ntwo");
+        assert_eq!(editor.buffer.cursor(), Position::new(1, 0));
+        assert_eq!(
+            editor.registers.get(None),
+            Some(&Register::new("three\n".to_string(), true))
+        );
+
+        editor.buffer.goto(0, 0);
+        editor.apply_to_lines(Operator::Delete, None);
+        assert_eq!(editor.buffer.contents(), &"two");
+        editor.paste(false);
+        assert_eq!(editor.buffer.contents(), &"one\ntwo");
+
+        editor.undo();
+        assert_eq!(editor.buffer.contents(), &"two");
+    }
+
+    #[test]
+    fn test_operating_on_several_lines() {
+        let mut editor = Editor::new(Buffer::new("one\ntwo\nthree\nfour"));
+        editor.buffer.goto(1, 0);
+        editor.apply_to_lines(Operator::Delete, Some(2));
+        assert_eq!(editor.buffer.contents(), &"one\nfour");
+        assert_eq!(
+            editor.registers.get(None),
+            Some(&Register::new("two\nthree\n".to_string(), true))
+        );
+
+        // A count that goes past the last line stops there.
+        editor.buffer.goto(0, 2);
+        editor.apply_to_lines(Operator::Yank, Some(5));
+        assert_eq!(
+            editor.registers.get(None),
+            Some(&Register::new("one\nfour\n".to_string(), true))
+        );
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 2));
+
+        editor.apply_to_lines(Operator::Delete, Some(5));
+        assert_eq!(editor.buffer.contents(), &"");
+        editor.apply_to_lines(Operator::Delete, None);
+        assert_eq!(
+            editor.registers.get(None),
+            Some(&Register::new("one\nfour\n".to_string(), true))
+        );
+    }
+
+    #[test]
+    fn test_changing_lines() {
+        let mut editor = Editor::new(Buffer::new("one\ntwo\nthree"));
+        editor.buffer.goto(2, 1);
+        editor.apply_to_lines(Operator::Change, None);
+        editor.buffer.insert('x');
+        assert_eq!(editor.buffer.contents(), &"one\ntwo\nx");
+        assert!(matches!(editor.mode, EditorMode::Edit));
+        assert_eq!(
+            editor.registers.get(None),
+            Some(&Register::new("three\n".to_string(), true))
+        );
+
+        editor.mode = EditorMode::Navigate;
+        editor.buffer.goto(0, 0);
+        editor.apply_to_lines(Operator::Change, Some(2));
+        assert_eq!(editor.buffer.contents(), &"\nx");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 0));
+    }
+
+    #[test]
+    fn test_appending() {
+        let mut editor = Editor::new(Buffer::new("  abc\r\nd"));
+        editor.buffer.goto(0, 3);
+        editor.append(false);
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 4));
+        assert!(matches!(editor.mode, EditorMode::Edit));
+
+        editor.append(true);
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 5));
+
+        // Appending on an empty line stays put, since there's no character to go after.
+        let mut editor = Editor::new(Buffer::new(""));
+        editor.append(false);
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 0));
+    }
+
+    #[test]
+    fn test_opening_lines() {
+        let mut editor = Editor::new(Buffer::new("one\ntwo"));
+        editor.buffer.goto(0, 1);
+        editor.open_line(true);
+        editor.buffer.insert('x');
+        assert_eq!(editor.buffer.contents(), &"one\nx\ntwo");
+        assert!(matches!(editor.mode, EditorMode::Edit));
+
+        editor.buffer.commit();
+        editor.buffer.goto(0, 2);
+        editor.open_line(false);
+        editor.buffer.insert('y');
+        assert_eq!(editor.buffer.contents(), &"y\none\nx\ntwo");
+
+        editor.buffer.commit();
+        editor.undo();
+        assert_eq!(editor.buffer.contents(), &"one\nx\ntwo");
+
+        editor.buffer.goto(2, 0);
+        editor.open_line(true);
+        assert_eq!(editor.buffer.contents(), &"one\nx\ntwo\n");
+        assert_eq!(editor.buffer.cursor(), Position::new(3, 0));
+    }
+
+    #[test]
+    fn test_deleting_characters() {
+        let mut editor = Editor::new(Buffer::new("abcdef\nghi"));
+        editor.buffer.goto(0, 3);
+        editor.delete_char(Some(2));
+        assert_eq!(editor.buffer.contents(), &"abcf\nghi");
+        editor.delete_char(Some(5));
+        assert_eq!(editor.buffer.contents(), &"abc\nghi");
+        assert_eq!(
+            editor.registers.get(None),
+            Some(&Register::new("f".to_string(), false))
+        );
+    }
+
+    #[test]
+    fn test_replacing_characters() {
+        let mut editor = Editor::new(Buffer::new("héllo\nworld"));
+        editor.buffer.goto(0, 1);
+        editor.replace_char('x', None);
+        assert_eq!(editor.buffer.contents(), &"hxllo\nworld");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 1));
+
+        editor.replace_char('ü', Some(3));
+        assert_eq!(editor.buffer.contents(), &"hüüüo\nworld");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 5));
+
+        // There aren't enough characters left on the line for this one.
+        editor.replace_char('z', Some(3));
+        assert_eq!(editor.buffer.contents(), &"hüüüo\nworld");
+
+        editor.undo();
+        assert_eq!(editor.buffer.contents(), &"hxllo\nworld");
+    }
+
+    #[test]
+    fn test_toggling_case() {
+        let mut editor = Editor::new(Buffer::new("aBc1\nd"));
+        editor.toggle_case(Some(3));
+        assert_eq!(editor.buffer.contents(), &"AbC1\nd");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 3));
+
+        editor.toggle_case(Some(5));
+        assert_eq!(editor.buffer.contents(), &"AbC1\nd");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 3));
+
+        editor.buffer.goto(0, 0);
+        editor.toggle_case(None);
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 1));
+        editor.undo();
+        assert_eq!(editor.buffer.contents(), &"AbC1\nd");
+    }
+
+    #[test]
+    fn test_shifting_lines() {
+        let mut editor = Editor::new(Buffer::new("a\n\n  b\n\t c"));
+        editor.set_tab_width(4);
+        editor.apply_to_lines(Operator::ShiftRight, Some(3));
+        assert_eq!(editor.buffer.contents(), &"\ta\n\n\t  b\n\t c");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 1));
+
+        editor.buffer.goto(2, 0);
+        editor.set_shift_width(2);
+        editor.apply_to_lines(Operator::ShiftLeft, Some(2));
+        assert_eq!(editor.buffer.contents(), &"\ta\n\n\tb\n   c");
+
+        // Shifting is undone in one go, whatever the number of lines.
+        editor.undo();
+        assert_eq!(editor.buffer.contents(), &"\ta\n\n\t  b\n\t c");
+    }
+
+    #[test]
+    fn test_shifting_selection() {
+        let mut editor = Editor::new(Buffer::new("one\ntwo\nthree"));
+        editor.set_expand_tab(true);
+        editor.set_shift_width(2);
+        editor.buffer.goto(1, 1);
+        editor.mode = EditorMode::Visual {
+            anchor: 0,
+            kind: VisualKind::Characters,
+        };
+        editor.apply_to_selection(Operator::ShiftRight);
+        assert_eq!(editor.buffer.contents(), &"  one\n  two\nthree");
+        assert!(matches!(editor.mode, EditorMode::Navigate));
+
+        editor.operator = Some((Operator::ShiftLeft, None));
+        editor.make_movement(Movement::Motion(Motion::DocumentEnd));
+        assert_eq!(editor.buffer.contents(), &"one\ntwo\nthree");
+    }
+
+    #[test]
+    fn test_shifting_lines_ending_in_wide_characters() {
+        let line = "héllo (wörld) \"漢字\"";
+        let keys = |editor: &mut Editor, keys: &str| {
+            for key in keymap::parse_keys(keys).unwrap() {
+                editor.handle_event(Event::Key(key));
+            }
+        };
+        let mut editor = Editor::new(Buffer::new(line));
+        editor.set_expand_tab(true);
+        editor.set_shift_width(2);
+
+        keys(&mut editor, "$>0");
+        assert_eq!(editor.buffer.contents().to_string(), format!("  {}", line));
+        keys(&mut editor, "$<0");
+        assert_eq!(editor.buffer.contents().to_string(), line);
+        keys(&mut editor, "v$>");
+        assert_eq!(editor.buffer.contents().to_string(), format!("  {}", line));
+        keys(&mut editor, "$gc0v$gc<Esc>$!0<Esc>v$!<Esc>");
+        assert_eq!(editor.buffer.contents().to_string(), format!("  {}", line));
+    }
+
+    #[test]
+    fn test_pasting_characters() {
+        let mut editor = Editor::new(Buffer::new("abc"));
+        editor.paste(true);
+        assert_eq!(editor.message.as_deref(), Some("Nothing in register \""));
+
+        editor.delete_char(None);
+        assert_eq!(editor.buffer.contents(), &"bc");
+        editor.paste(true);
+        assert_eq!(editor.buffer.contents(), &"bac");
+        assert_eq!(editor.buffer.cursor(), Position::new(0, 1));
+        editor.paste(false);
+        assert_eq!(editor.buffer.contents(), &"baac");
+    }
+
+    #[test]
+    fn test_named_registers() {
+        let key = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        let mut editor = Editor::new(Buffer::new("one\ntwo"));
+        editor.complete_sequence('"', &key('a'));
+        editor.complete_operator(Operator::Yank, None, &key('y'));
+        editor.buffer.goto(1, 0);
+        editor.complete_operator(Operator::Yank, None, &key('y'));
+
+        editor.complete_sequence('"', &key('a'));
+        editor.paste(true);
+        assert_eq!(editor.buffer.contents(), &"one\ntwo\none");
+        editor.paste(true);
+        assert_eq!(editor.buffer.contents(), &"one\ntwo\none\ntwo");
     }
 }
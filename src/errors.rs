@@ -2,6 +2,9 @@ use std::error::Error;
 use std::fmt::{self, Display};
 use std::path::PathBuf;
 
+use crate::config::ConfigError;
+use crate::regex::RegexError;
+
 /// Wrappers for the different errors that can be encountered while running the `Editor`.
 #[derive(Debug)]
 pub enum EditorError {
@@ -13,6 +16,68 @@ pub enum EditorError {
     DirectoryDoesNotExist(PathBuf),
     /// Occurs when trying to open the path `/`
     CannotOpenRoot,
+    /// Occurs when trying to write a buffer that didn't come from a file, without saying where it should go.
+    NoFileName,
+    /// Occurs when trying to write or change the buffer while acting as a pager.
+    ReadOnly,
+    /// Occurs when trying to change a buffer that has been made read-only.
+    ReadOnlyBuffer,
+    /// Occurs when trying to write over a file that something else has changed since the buffer was read from it.
+    FileChanged,
+    /// Occurs when trying to throw away a buffer that has changes which haven't been written.
+    UnsavedChanges,
+    /// Occurs when trying to quit while a buffer other than the current one has changes which haven't been written.
+    UnsavedBuffer(String),
+    /// Occurs when trying to write a new file over one that's already there, without forcing it.
+    FileExists(PathBuf),
+    /// Occurs when saving a session with a buffer that didn't come from a file, so it can't be opened again.
+    UnnamedBuffer(usize),
+    /// Occurs when trying to switch to a buffer that isn't in the list.
+    NoSuchBuffer(usize),
+    /// Occurs when trying to split a window that's too small to be split.
+    NotEnoughRoom,
+    /// Occurs when trying to close the only window on the display.
+    LastWindow,
+    /// Occurs when trying to move past the last file in the argument list.
+    LastFile,
+    /// Occurs when trying to move back past the first file in the argument list.
+    FirstFile,
+    /// Occurs when trying to jump to an error when the last `:make` didn't find any.
+    NoErrors,
+    /// Occurs when trying to jump past either end of the list of errors.
+    NoMoreErrors,
+    /// Occurs when a shell command run by `:!` or `:r !` fails, with whatever it said about why.
+    ShellCommandFailed(String),
+    /// Occurs when asking a language server about a buffer that there isn't one configured for.
+    NoLanguageServer,
+    /// Occurs when a language server can't be started, or stops listening.
+    LanguageServer(std::io::Error),
+    /// Occurs when commenting out lines in a type of file that there's no comment string for.
+    NoCommentString,
+    /// Occurs when `ys` or `cs` is given a key that doesn't stand for any brackets or quotes.
+    UnknownSurrounding(char),
+    /// Occurs when `ds` or `cs` can't find the brackets, quotes or tag around the cursor.
+    NoSurrounding(char),
+    /// Occurs when formatting a type of file that there's no formatter configured for.
+    NoFormatter,
+    /// Occurs when a formatter fails, with whatever it said about why (which is usually a syntax error.)
+    FormatterFailed(String),
+    /// Occurs when `]c` or `[c` can't find another change to jump to, or no files are being compared.
+    NoMoreChanges,
+    /// Occurs when `:set` is given the name of an option that doesn't exist.
+    UnknownOption(String),
+    /// Occurs when `:set` is given a value that the option can't be set to.
+    InvalidOptionValue(String),
+    /// Occurs when a pattern given to a command isn't a valid regular expression.
+    InvalidPattern(RegexError),
+    /// Occurs when a command leaves its pattern empty to reuse the last one, before there's been one.
+    NoPreviousPattern,
+    /// Occurs when `:bufdo` or `:argdo` runs into problems, with what went wrong in each file.
+    FailedInFiles(Vec<String>),
+    /// Occurs when a register is used for something that needs text in it, like a macro, while it's empty.
+    EmptyRegister(char),
+    /// Occurs when something can't be saved in the configuration file, which is left alone.
+    Config(ConfigError),
 }
 
 impl Display for EditorError {
@@ -32,6 +97,53 @@ impl Display for EditorError {
             EditorError::CannotOpenRoot => {
                 write!(f, "Cannot open the path \"/\"")
             }
+            EditorError::NoFileName => write!(f, "No file name"),
+            EditorError::ReadOnly => write!(f, "Cannot make changes while acting as a pager"),
+            EditorError::ReadOnlyBuffer => write!(
+                f,
+                "The buffer is read-only (use :set noreadonly to change it)"
+            ),
+            EditorError::FileChanged => write!(
+                f,
+                "The file has been changed since reading it (add ! to override)"
+            ),
+            EditorError::UnsavedChanges => {
+                write!(f, "No write since last change (add ! to override)")
+            }
+            EditorError::UnsavedBuffer(name) => write!(
+                f,
+                "No write since last change for buffer \"{}\" (add ! to override)",
+                name
+            ),
+            EditorError::FileExists(path) => {
+                write!(f, "\"{}\" exists (add ! to override)", path.display())
+            }
+            EditorError::UnnamedBuffer(number) => write!(f, "Buffer {} has no file name", number),
+            EditorError::NoSuchBuffer(number) => write!(f, "Buffer {} does not exist", number),
+            EditorError::NotEnoughRoom => write!(f, "Not enough room"),
+            EditorError::LastWindow => write!(f, "Cannot close last window"),
+            EditorError::LastFile => write!(f, "Cannot go beyond last file"),
+            EditorError::FirstFile => write!(f, "Cannot go before first file"),
+            EditorError::NoErrors => write!(f, "No errors"),
+            EditorError::NoMoreErrors => write!(f, "No more errors"),
+            EditorError::ShellCommandFailed(reason) => {
+                write!(f, "Shell command failed: {}", reason)
+            }
+            EditorError::NoLanguageServer => write!(f, "No language server for this buffer"),
+            EditorError::LanguageServer(e) => write!(f, "Language server failed: {}", e),
+            EditorError::NoCommentString => write!(f, "No comment string for this type of file"),
+            EditorError::UnknownSurrounding(c) => write!(f, "Cannot surround with {}", c),
+            EditorError::NoSurrounding(c) => write!(f, "No {} around the cursor", c),
+            EditorError::NoFormatter => write!(f, "No formatter for this type of file"),
+            EditorError::FormatterFailed(reason) => write!(f, "Formatter failed: {}", reason),
+            EditorError::NoMoreChanges => write!(f, "No more changes"),
+            EditorError::UnknownOption(option) => write!(f, "Unknown option: {}", option),
+            EditorError::InvalidOptionValue(value) => write!(f, "Invalid value: {}", value),
+            EditorError::InvalidPattern(e) => write!(f, "Invalid pattern: {}", e),
+            EditorError::NoPreviousPattern => write!(f, "No previous search pattern"),
+            EditorError::FailedInFiles(failures) => write!(f, "{}", failures.join("; ")),
+            EditorError::EmptyRegister(name) => write!(f, "Nothing in register {}", name),
+            EditorError::Config(e) => write!(f, "Cannot save the configuration file: {}", e),
         }
     }
 }
@@ -43,6 +155,37 @@ impl Error for EditorError {
             EditorError::TermIo(e) => Some(e),
             EditorError::DirectoryDoesNotExist(_) => None,
             EditorError::CannotOpenRoot => None,
+            EditorError::NoFileName => None,
+            EditorError::ReadOnly => None,
+            EditorError::ReadOnlyBuffer => None,
+            EditorError::FileChanged => None,
+            EditorError::UnsavedChanges => None,
+            EditorError::UnsavedBuffer(_) => None,
+            EditorError::FileExists(_) => None,
+            EditorError::UnnamedBuffer(_) => None,
+            EditorError::NoSuchBuffer(_) => None,
+            EditorError::NotEnoughRoom => None,
+            EditorError::LastWindow => None,
+            EditorError::LastFile => None,
+            EditorError::FirstFile => None,
+            EditorError::NoErrors => None,
+            EditorError::NoMoreErrors => None,
+            EditorError::ShellCommandFailed(_) => None,
+            EditorError::NoLanguageServer => None,
+            EditorError::LanguageServer(e) => Some(e),
+            EditorError::NoCommentString => None,
+            EditorError::UnknownSurrounding(_) => None,
+            EditorError::NoSurrounding(_) => None,
+            EditorError::NoFormatter => None,
+            EditorError::FormatterFailed(_) => None,
+            EditorError::NoMoreChanges => None,
+            EditorError::UnknownOption(_) => None,
+            EditorError::InvalidOptionValue(_) => None,
+            EditorError::InvalidPattern(e) => Some(e),
+            EditorError::NoPreviousPattern => None,
+            EditorError::FailedInFiles(_) => None,
+            EditorError::EmptyRegister(_) => None,
+            EditorError::Config(e) => Some(e),
         }
     }
 }
@@ -0,0 +1,115 @@
+//! Finding the lines of a file that have changed since the last commit, so that they can be marked in the gutter.
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// How a line differs from the version of the file in the last commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange {
+    /// The line is new.
+    Added,
+    /// The line was there before, but has been changed.
+    Modified,
+    /// Some lines were taken out just after this one (or before it, when it's the first line.)
+    Removed,
+}
+
+/// Finds the lines of the file at `path` that differ from `HEAD`, by asking `git` for a diff of the file as it is on disk.
+///
+/// Files that aren't in a git repository (or when there's no `git` to ask) have no changes.
+pub fn line_changes(path: &Path) -> Vec<(usize, LineChange)> {
+    let (directory, name) = match (path.parent(), path.file_name()) {
+        (Some(directory), Some(name)) => (directory, name),
+        _ => return Vec::new(),
+    };
+    let directory = match directory.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => directory,
+    };
+    // Whatever diff settings the user has, only the hunk headers of a plain diff are looked at.
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(["diff", "--no-color", "--no-ext-diff", "-U0", "HEAD", "--"])
+        .arg(name)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            parse_diff(&String::from_utf8_lossy(&output.stdout))
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Reads the changed lines out of the hunk headers of a diff without any context lines, like `@@ -3,2 +3,0 @@`.
+fn parse_diff(diff: &str) -> Vec<(usize, LineChange)> {
+    let mut changes = Vec::new();
+    for header in diff.lines().filter_map(|line| line.strip_prefix("@@ ")) {
+        let mut ranges = header.split_whitespace();
+        let old = ranges
+            .next()
+            .and_then(|r| r.strip_prefix('-'))
+            .and_then(parse_range);
+        let new = ranges
+            .next()
+            .and_then(|r| r.strip_prefix('+'))
+            .and_then(parse_range);
+        let ((_, removed), (start, added)) = match (old, new) {
+            (Some(old), Some(new)) => (old, new),
+            _ => continue,
+        };
+        // Lines are counted from one, and a hunk that only takes lines out starts at the line before them.
+        match (removed, added) {
+            (0, _) => {
+                changes.extend((start..start + added).map(|line| (line - 1, LineChange::Added)))
+            }
+            (_, 0) => changes.push((start.saturating_sub(1), LineChange::Removed)),
+            _ => {
+                changes.extend((start..start + added).map(|line| (line - 1, LineChange::Modified)))
+            }
+        }
+    }
+    changes
+}
+
+/// Parses a line range from a hunk header, like `3,2` or just `3` for a single line.
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parsing_diffs() {
+        let diff = "diff --git a/f b/f\n\
+                    --- a/f\n\
+                    +++ b/f\n\
+                    @@ -0,0 +1,2 @@\n\
+                    +new\n\
+                    +lines\n\
+                    @@ -5 +7 @@ fn main() {\n\
+                    -old\n\
+                    +changed\n\
+                    @@ -9,3 +10,0 @@\n\
+                    -a\n\
+                    -b\n\
+                    -c\n";
+        assert_eq!(
+            parse_diff(diff),
+            [
+                (0, LineChange::Added),
+                (1, LineChange::Added),
+                (6, LineChange::Modified),
+                (9, LineChange::Removed),
+            ]
+        );
+        assert_eq!(parse_diff("@@ -1,2 +0,0 @@\n"), [(0, LineChange::Removed)]);
+        assert_eq!(parse_diff("not a diff"), []);
+    }
+}
@@ -0,0 +1,153 @@
+//! Lines that have been typed in at a prompt before, which Up and Down bring back again.
+
+/// How many lines a history holds onto before it starts forgetting the oldest.
+pub const MAX_ENTRIES: usize = 100;
+
+/// The lines typed in at one kind of prompt, like `:` or `/`, oldest first.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<String>,
+    /// How far back the user has gone, while they're going back through the history.
+    browsing: Option<Browsing>,
+}
+
+/// Where the user has got to while going back through a history.
+#[derive(Debug)]
+struct Browsing {
+    /// The index of the entry being shown, which is `entries.len()` when it's what the user typed.
+    index: usize,
+    /// What the user had typed before they started, which the entries are filtered by.
+    typed: String,
+    /// Whether entries can have the typed text anywhere in them (for `Ctrl-R`), rather than only at the start.
+    anywhere: bool,
+}
+
+impl History {
+    /// The lines in the history, oldest first.
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Remembers a line that has been typed in, moving it to the end if it was already in the history.
+    pub fn add(&mut self, entry: &str) {
+        self.browsing = None;
+        if entry.is_empty() {
+            return;
+        }
+        self.entries.retain(|e| e != entry);
+        self.entries.push(entry.to_string());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+    }
+
+    /// Forgets how far back the user had gone, so that the next `older` starts again from the newest entry.
+    pub fn stop_browsing(&mut self) {
+        self.browsing = None;
+    }
+
+    /// Goes back to the newest entry older than the one being shown that starts with `typed`, like Up does in Vim.
+    ///
+    /// Only the text typed before going back at all is used, so the entries shown along the way don't narrow it down.
+    pub fn older(&mut self, typed: &str) -> Option<&str> {
+        self.go_back(typed, false)
+    }
+
+    /// Goes back like `older`, but to an entry with `typed` anywhere in it.
+    pub fn search_older(&mut self, typed: &str) -> Option<&str> {
+        self.go_back(typed, true)
+    }
+
+    /// Goes back to the newest matching entry older than the one being shown.
+    fn go_back(&mut self, typed: &str, anywhere: bool) -> Option<&str> {
+        let len = self.entries.len();
+        let browsing = self.browsing.get_or_insert_with(|| Browsing {
+            index: len,
+            typed: typed.to_string(),
+            anywhere,
+        });
+        browsing.anywhere = anywhere;
+        let index = (0..browsing.index)
+            .rev()
+            .find(|&i| browsing.matches(&self.entries[i]))?;
+        browsing.index = index;
+        Some(&self.entries[index])
+    }
+
+    /// Comes forward again to a newer entry that was gone back past, or to whatever the user had typed once there aren't any.
+    pub fn newer(&mut self) -> Option<String> {
+        let browsing = self.browsing.as_mut()?;
+        let entries = &self.entries;
+        match (browsing.index + 1..entries.len()).find(|&i| browsing.matches(&entries[i])) {
+            Some(index) => {
+                browsing.index = index;
+                Some(entries[index].clone())
+            }
+            None => self.browsing.take().map(|browsing| browsing.typed),
+        }
+    }
+}
+
+impl Browsing {
+    fn matches(&self, entry: &str) -> bool {
+        match self.anywhere {
+            true => entry.contains(&self.typed),
+            false => entry.starts_with(&self.typed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn history(entries: &[&str]) -> History {
+        let mut history = History::default();
+        for entry in entries {
+            history.add(entry);
+        }
+        history
+    }
+
+    #[test]
+    fn test_adding_entries() {
+        let mut history = history(&["w", "", "q", "w"]);
+        assert_eq!(history.entries(), ["q", "w"]);
+
+        for n in 0..MAX_ENTRIES {
+            history.add(&n.to_string());
+        }
+        assert_eq!(history.entries().len(), MAX_ENTRIES);
+        assert_eq!(history.entries()[0], "0");
+    }
+
+    #[test]
+    fn test_going_back_and_forward() {
+        let mut history = history(&["set wrap", "w", "s/a/b/", "set nowrap"]);
+        assert_eq!(history.newer(), None);
+        assert_eq!(history.older(""), Some("set nowrap"));
+        assert_eq!(history.older("set nowrap"), Some("s/a/b/"));
+        assert_eq!(history.newer().as_deref(), Some("set nowrap"));
+        assert_eq!(history.newer().as_deref(), Some(""));
+        assert_eq!(history.newer(), None);
+
+        // What was typed before going back picks out the entries that start with it.
+        assert_eq!(history.older("se"), Some("set nowrap"));
+        assert_eq!(history.older("set nowrap"), Some("set wrap"));
+        assert_eq!(history.older("set wrap"), None);
+        assert_eq!(history.newer().as_deref(), Some("set nowrap"));
+        assert_eq!(history.newer().as_deref(), Some("se"));
+    }
+
+    #[test]
+    fn test_searching_back() {
+        let mut history = history(&["set wrap", "w", "s/a/b/", "set nowrap"]);
+        assert_eq!(history.search_older("wrap"), Some("set nowrap"));
+        assert_eq!(history.search_older("wrap"), Some("set wrap"));
+        assert_eq!(history.search_older("wrap"), None);
+        assert_eq!(history.older("wrap"), None);
+
+        history.stop_browsing();
+        assert_eq!(history.search_older("/"), Some("s/a/b/"));
+    }
+}
@@ -0,0 +1,98 @@
+//! Commands that the configuration file asks to have run whenever something happens in the editor, like Vim's autocommands.
+
+/// The things that happen in the editor that commands can be hooked onto.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    /// A file has just been read into a buffer.
+    BufRead,
+    /// The editor has switched to a buffer.
+    BufEnter,
+    /// A buffer is about to be written to its file.
+    BufWritePre,
+    /// A buffer has just been written to its file.
+    BufWritePost,
+    /// The editor has gone from one mode into another.
+    ModeChanged,
+    /// The cursor has moved, while the editor is waiting for a command.
+    CursorMoved,
+}
+
+/// The names that events go by in the configuration file, which are the same as Vim's.
+const NAMES: [(&str, HookEvent); 6] = [
+    ("BufRead", HookEvent::BufRead),
+    ("BufEnter", HookEvent::BufEnter),
+    ("BufWritePre", HookEvent::BufWritePre),
+    ("BufWritePost", HookEvent::BufWritePost),
+    ("ModeChanged", HookEvent::ModeChanged),
+    ("CursorMoved", HookEvent::CursorMoved),
+];
+
+impl HookEvent {
+    /// Finds the event with a name, which doesn't have to be in the same case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        NAMES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, event)| event)
+    }
+
+    /// The name of the event, for telling the user which hook went wrong.
+    pub fn name(self) -> &'static str {
+        NAMES
+            .iter()
+            .find(|&&(_, event)| event == self)
+            .map_or("", |(name, _)| name)
+    }
+}
+
+/// A command to run whenever an event happens to a type of file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hook {
+    pub event: HookEvent,
+    /// The extension (or name, for files without one) of the files that the hook is for, or `*` for every file.
+    pub file_type: String,
+    /// The command to run, as it would be typed at the `:` prompt.
+    pub command: String,
+}
+
+impl Hook {
+    /// Whether the hook should be run when `event` happens in a buffer with the given type of file.
+    ///
+    /// Buffers that don't have a file yet only get the hooks that are for every file.
+    pub fn applies(&self, event: HookEvent, file_type: Option<&str>) -> bool {
+        self.event == event && (self.file_type == "*" || file_type == Some(&self.file_type))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_events_by_name() {
+        assert_eq!(
+            HookEvent::from_name("BufWritePre"),
+            Some(HookEvent::BufWritePre)
+        );
+        assert_eq!(
+            HookEvent::from_name("cursormoved"),
+            Some(HookEvent::CursorMoved)
+        );
+        assert_eq!(HookEvent::from_name("BufLeave"), None);
+        assert_eq!(HookEvent::ModeChanged.name(), "ModeChanged");
+    }
+
+    #[test]
+    fn test_hooks_apply_to_file_types() {
+        let hook = |file_type: &str| Hook {
+            event: HookEvent::BufWritePre,
+            file_type: file_type.to_string(),
+            command: "%s/ +$//".to_string(),
+        };
+        assert!(hook("rs").applies(HookEvent::BufWritePre, Some("rs")));
+        assert!(!hook("rs").applies(HookEvent::BufWritePre, Some("py")));
+        assert!(!hook("rs").applies(HookEvent::BufWritePost, Some("rs")));
+        assert!(!hook("rs").applies(HookEvent::BufWritePre, None));
+        assert!(hook("*").applies(HookEvent::BufWritePre, None));
+    }
+}
@@ -0,0 +1,402 @@
+//! Just enough JSON to talk to a language server, which sends and expects nothing else.
+use std::error::Error;
+use std::fmt::{self, Display, Write};
+
+/// A JSON value.
+///
+/// Objects keep their members in the order they were written, since there are only ever a handful of them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// The reason that some text couldn't be parsed as JSON, and how far into it the problem was.
+#[derive(Debug, PartialEq, Eq)]
+pub struct JsonError {
+    pub offset: usize,
+    pub reason: &'static str,
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.reason, self.offset)
+    }
+}
+
+impl Error for JsonError {}
+
+impl Json {
+    /// Parses a whole JSON document, which mustn't have anything but whitespace after it.
+    pub fn parse(text: &str) -> Result<Self, JsonError> {
+        let mut parser = Parser { text, offset: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        match parser.offset == text.len() {
+            true => Ok(value),
+            false => Err(parser.error("Trailing characters")),
+        }
+    }
+
+    /// Builds an object out of some members.
+    pub fn object<const N: usize>(members: [(&str, Json); N]) -> Self {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// Looks up a member of an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value as a whole number that can't be negative, which is what ids, lines and columns all are.
+    pub fn as_usize(&self) -> Option<usize> {
+        match *self {
+            Json::Number(n) if n >= 0.0 && n.fract() == 0.0 && n <= usize::MAX as f64 => {
+                Some(n as usize)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        Json::String(s)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Json::Bool(b)
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.is_finite() => write!(f, "{}", n),
+            // JSON has no way of writing infinities or NaN.
+            Json::Number(_) => f.write_str("null"),
+            Json::String(s) => write_string(f, s),
+            Json::Array(values) => {
+                f.write_char('[')?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(members) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+/// Writes a string out in quotes, escaping anything that can't go into one as it is.
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Works through some text one value at a time.
+struct Parser<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, reason: &'static str) -> JsonError {
+        JsonError {
+            offset: self.offset,
+            reason,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.offset).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.offset += 1;
+        }
+    }
+
+    /// Moves past `expected` if it's next, after any whitespace.
+    fn eat(&mut self, expected: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.peek() == Some(expected);
+        if found {
+            self.offset += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, expected: u8, reason: &'static str) -> Result<(), JsonError> {
+        match self.eat(expected) {
+            true => Ok(()),
+            false => Err(self.error(reason)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => self.keyword(),
+        }
+    }
+
+    fn keyword(&mut self) -> Result<Json, JsonError> {
+        let rest = &self.text[self.offset..];
+        let (value, len) = if rest.starts_with("null") {
+            (Json::Null, 4)
+        } else if rest.starts_with("true") {
+            (Json::Bool(true), 4)
+        } else if rest.starts_with("false") {
+            (Json::Bool(false), 5)
+        } else {
+            return Err(self.error("Expected a value"));
+        };
+        self.offset += len;
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.offset;
+        while matches!(
+            self.peek(),
+            Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        ) {
+            self.offset += 1;
+        }
+        self.text[start..self.offset]
+            .parse()
+            .map(Json::Number)
+            .map_err(|_| JsonError {
+                offset: start,
+                reason: "Invalid number",
+            })
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect(b'"', "Expected a string")?;
+        let mut s = String::new();
+        loop {
+            let rest = &self.text[self.offset..];
+            let c = rest
+                .chars()
+                .next()
+                .ok_or_else(|| self.error("Unterminated string"))?;
+            self.offset += c.len_utf8();
+            match c {
+                '"' => return Ok(s),
+                '\\' => s.push(self.escape()?),
+                c => s.push(c),
+            }
+        }
+    }
+
+    /// Reads the rest of an escape sequence, after its backslash.
+    fn escape(&mut self) -> Result<char, JsonError> {
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error("Unterminated string"))?;
+        self.offset += 1;
+        Ok(match c {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+                let unit = self.hex_unit()?;
+                // Characters outside the Basic Multilingual Plane are written as a pair of UTF-16 surrogates.
+                let code = match unit {
+                    0xd800..=0xdbff if self.text[self.offset..].starts_with("\\u") => {
+                        self.offset += 2;
+                        let low = self.hex_unit()?;
+                        0x10000 + ((unit - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+                    }
+                    unit => unit,
+                };
+                char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+            }
+            _ => return Err(self.error("Invalid escape")),
+        })
+    }
+
+    fn hex_unit(&mut self) -> Result<u32, JsonError> {
+        let digits = self
+            .text
+            .get(self.offset..self.offset + 4)
+            .ok_or_else(|| self.error("Invalid escape"))?;
+        let unit = u32::from_str_radix(digits, 16).map_err(|_| self.error("Invalid escape"))?;
+        self.offset += 4;
+        Ok(unit)
+    }
+
+    fn array(&mut self) -> Result<Json, JsonError> {
+        self.expect(b'[', "Expected an array")?;
+        let mut values = Vec::new();
+        if self.eat(b']') {
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            if self.eat(b']') {
+                return Ok(Json::Array(values));
+            }
+            self.expect(b',', "Expected , or ]")?;
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, JsonError> {
+        self.expect(b'{', "Expected an object")?;
+        let mut members = Vec::new();
+        if self.eat(b'}') {
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(b':', "Expected :")?;
+            members.push((key, self.value()?));
+            if self.eat(b'}') {
+                return Ok(Json::Object(members));
+            }
+            self.expect(b',', "Expected , or }")?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parsing_values() {
+        let value =
+            Json::parse(r#" {"id": 3, "ok": true, "list": [1.5, -2e2, null], "name": "x"} "#)
+                .unwrap();
+        assert_eq!(value.get("id").and_then(Json::as_usize), Some(3));
+        assert_eq!(value.get("ok"), Some(&Json::Bool(true)));
+        assert_eq!(
+            value.get("list").and_then(Json::as_array),
+            Some(&[Json::Number(1.5), Json::Number(-200.0), Json::Null][..])
+        );
+        assert_eq!(value.get("name").and_then(Json::as_str), Some("x"));
+        assert_eq!(value.get("missing"), None);
+        assert_eq!(Json::parse("[]"), Ok(Json::Array(Vec::new())));
+        assert_eq!(Json::parse("{}"), Ok(Json::Object(Vec::new())));
+    }
+
+    #[test]
+    fn test_parsing_strings() {
+        assert_eq!(
+            Json::parse(r#""a\"b\\c\n\u00e9\ud83d\ude00é""#),
+            Ok(Json::from("a\"b\\c\né😀é"))
+        );
+        assert_eq!(
+            Json::parse(r#""abc"#).unwrap_err().reason,
+            "Unterminated string"
+        );
+    }
+
+    #[test]
+    fn test_parsing_errors() {
+        assert_eq!(
+            Json::parse("[1,]"),
+            Err(JsonError {
+                offset: 3,
+                reason: "Expected a value"
+            })
+        );
+        assert_eq!(
+            Json::parse("1 2").unwrap_err().reason,
+            "Trailing characters"
+        );
+        assert_eq!(Json::parse(r#"{"a" 1}"#).unwrap_err().reason, "Expected :");
+        assert_eq!(Json::parse("-").unwrap_err().reason, "Invalid number");
+    }
+
+    #[test]
+    fn test_writing_values() {
+        let value = Json::object([
+            ("id", Json::from(1)),
+            ("text", Json::from("a\"\n\u{1}")),
+            ("list", Json::Array(vec![Json::Null, Json::from(false)])),
+        ]);
+        let text = value.to_string();
+        assert_eq!(text, r#"{"id":1,"text":"a\"\n\u0001","list":[null,false]}"#);
+        assert_eq!(Json::parse(&text), Ok(value));
+    }
+}
@@ -0,0 +1,367 @@
+//! Key mappings from the configuration file, which turn one sequence of keys into another before the editor sees them.
+//!
+//! Like Vim's `:noremap`, the keys that a mapping produces are never mapped again themselves.
+use std::collections::VecDeque;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// The modes that keys can be mapped in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapMode {
+    Navigate,
+    Edit,
+    Visual,
+}
+
+impl MapMode {
+    /// Looks up a mode from the name it goes by in the configuration file.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "navigate" => Some(MapMode::Navigate),
+            "edit" => Some(MapMode::Edit),
+            "visual" => Some(MapMode::Visual),
+            _ => None,
+        }
+    }
+
+    /// The name that the mode goes by in the configuration file, as the `[keys.navigate]` table and the like.
+    pub fn name(self) -> &'static str {
+        match self {
+            MapMode::Navigate => "navigate",
+            MapMode::Edit => "edit",
+            MapMode::Visual => "visual",
+        }
+    }
+}
+
+/// A sequence of keys that stands in for a different sequence of keys in one of the modes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub mode: MapMode,
+    pub keys: Vec<KeyEvent>,
+    pub replacement: Vec<KeyEvent>,
+}
+
+impl Binding {
+    /// Creates a binding from both sequences of keys written out in Vim's notation, like `jk` or `<C-w>l`.
+    pub fn parse(mode: MapMode, keys: &str, replacement: &str) -> Result<Self, String> {
+        Ok(Self {
+            mode,
+            keys: parse_keys(keys)?,
+            replacement: parse_keys(replacement)?,
+        })
+    }
+}
+
+/// Puts a key into the form that bindings are compared in.
+///
+/// Terminals don't agree on whether a capital letter comes with Shift held, so it's left out.
+fn normalize(mut key: KeyEvent) -> KeyEvent {
+    if let KeyCode::Char(_) = key.code {
+        key.modifiers.remove(KeyModifiers::SHIFT);
+    }
+    key
+}
+
+/// Reads a sequence of keys written in Vim's notation, where special keys go in angle brackets like `<Esc>` or `<C-w>`.
+///
+/// A `<` that doesn't start a special key stands for itself.
+pub fn parse_keys(text: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut keys = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let special = match (c, rest.find('>')) {
+            ('<', Some(end)) if end > 1 && !rest[1..end].contains('<') => {
+                Some((&rest[1..end], end + 1))
+            }
+            _ => None,
+        };
+        match special {
+            Some((name, length)) => {
+                keys.push(parse_special_key(name)?);
+                rest = &rest[length..];
+            }
+            None => {
+                keys.push(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    match keys.is_empty() {
+        true => Err("No keys given".to_string()),
+        false => Ok(keys),
+    }
+}
+
+/// Reads the name of a special key from between its angle brackets, along with any modifiers in front of it.
+fn parse_special_key(name: &str) -> Result<KeyEvent, String> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = name;
+    while let Some((modifier, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+        modifiers |= match modifier.to_ascii_uppercase().as_str() {
+            "C" => KeyModifiers::CONTROL,
+            "A" | "M" => KeyModifiers::ALT,
+            "S" => KeyModifiers::SHIFT,
+            _ => return Err(format!("Unknown modifier in <{}>", name)),
+        };
+        rest = key;
+    }
+
+    let code = match rest.to_ascii_lowercase().as_str() {
+        "esc" => KeyCode::Esc,
+        "cr" | "enter" | "return" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "bs" | "backspace" => KeyCode::Backspace,
+        "del" | "delete" => KeyCode::Delete,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        _ => {
+            let mut chars = rest.chars();
+            match (chars.next(), chars.next()) {
+                // Control characters come through from the terminal in lower case.
+                (Some(c), None) if modifiers.contains(KeyModifiers::CONTROL) => {
+                    KeyCode::Char(c.to_ascii_lowercase())
+                }
+                (Some(c), None) => KeyCode::Char(c),
+                _ => return Err(format!("Unknown key <{}>", name)),
+            }
+        }
+    };
+    Ok(normalize(KeyEvent::new(code, modifiers)))
+}
+
+/// Writes a sequence of keys out in Vim's notation, so that `parse_keys` can read them back in again.
+///
+/// Keys that the notation has no name for are left out.
+pub fn format_keys(keys: &[KeyEvent]) -> String {
+    keys.iter().map(|&key| format_key(normalize(key))).collect()
+}
+
+/// Writes out a single key in Vim's notation.
+fn format_key(key: KeyEvent) -> String {
+    let name = match key.code {
+        KeyCode::Char('<') => "lt".to_string(),
+        KeyCode::Char(c) if key.modifiers.is_empty() => return c.to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Esc => "Esc".to_string(),
+        KeyCode::Enter => "CR".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::Backspace => "BS".to_string(),
+        KeyCode::Delete => "Del".to_string(),
+        KeyCode::Left => "Left".to_string(),
+        KeyCode::Right => "Right".to_string(),
+        KeyCode::Up => "Up".to_string(),
+        KeyCode::Down => "Down".to_string(),
+        KeyCode::Home => "Home".to_string(),
+        KeyCode::End => "End".to_string(),
+        KeyCode::PageUp => "PageUp".to_string(),
+        KeyCode::PageDown => "PageDown".to_string(),
+        _ => return String::new(),
+    };
+    let mut modifiers = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        modifiers.push_str("C-");
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        modifiers.push_str("M-");
+    }
+    if key.modifiers.contains(KeyModifiers::SHIFT) {
+        modifiers.push_str("S-");
+    }
+    format!("<{}{}>", modifiers, name)
+}
+
+/// Runs key presses through the bindings, holding on to them while they could still turn out to be the start of one.
+#[derive(Debug, Default)]
+pub struct Keymap {
+    bindings: Vec<Binding>,
+    /// Keys that have been pressed, but could still be the start of a binding.
+    pending: Vec<KeyEvent>,
+    /// Keys that are ready for the editor, having been through the bindings.
+    ready: VecDeque<KeyEvent>,
+}
+
+impl Keymap {
+    pub fn new(bindings: Vec<Binding>) -> Self {
+        Self {
+            bindings,
+            ..Default::default()
+        }
+    }
+
+    /// Adds a binding, taking the place of any that was already there for the same keys in the same mode.
+    pub fn add(&mut self, binding: Binding) {
+        self.bindings
+            .retain(|b| b.mode != binding.mode || b.keys != binding.keys);
+        self.bindings.push(binding);
+    }
+
+    /// Takes a key press in `mode` (or in a mode where nothing is mapped, if there isn't one), returning whether any keys are left pending.
+    pub fn feed(&mut self, mode: Option<MapMode>, key: KeyEvent) -> bool {
+        self.pending.push(key);
+        self.resolve(mode, false);
+        !self.pending.is_empty()
+    }
+
+    /// Gives up waiting for the pending keys to turn into a longer binding, using whatever they make up on their own.
+    pub fn time_out(&mut self, mode: Option<MapMode>) {
+        self.resolve(mode, true);
+    }
+
+    /// Whether there are keys waiting for the editor.
+    pub fn has_ready(&self) -> bool {
+        !self.ready.is_empty()
+    }
+
+    /// Hands over the next key that is ready for the editor.
+    pub fn next_key(&mut self) -> Option<KeyEvent> {
+        self.ready.pop_front()
+    }
+
+    /// Turns as many of the pending keys as possible into keys that are ready.
+    ///
+    /// Keys are held back while they are the start of a longer binding, unless the user has `timed_out` of waiting for the rest of it.
+    fn resolve(&mut self, mode: Option<MapMode>, timed_out: bool) {
+        while !self.pending.is_empty() {
+            let pending: Vec<_> = self.pending.iter().copied().map(normalize).collect();
+            let bindings = || self.bindings.iter().filter(|b| Some(b.mode) == mode);
+
+            let longer =
+                bindings().any(|b| b.keys.len() > pending.len() && b.keys.starts_with(&pending));
+            if longer && !timed_out {
+                return;
+            }
+
+            match bindings()
+                .filter(|b| pending.starts_with(&b.keys))
+                .max_by_key(|b| b.keys.len())
+            {
+                Some(binding) => {
+                    self.ready.extend(binding.replacement.iter().copied());
+                    self.pending.drain(..binding.keys.len());
+                }
+                None => self.ready.push_back(self.pending.remove(0)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    fn ready(keymap: &mut Keymap) -> Vec<KeyEvent> {
+        std::iter::from_fn(|| keymap.next_key()).collect()
+    }
+
+    #[test]
+    fn test_parsing_keys() {
+        assert_eq!(parse_keys("jk").unwrap(), [key('j'), key('k')]);
+        assert_eq!(
+            parse_keys("<Esc><C-W>l").unwrap(),
+            [
+                KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+                KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL),
+                key('l'),
+            ]
+        );
+        assert_eq!(
+            parse_keys("<<lt>>").unwrap(),
+            [key('<'), key('<'), key('>')]
+        );
+        assert_eq!(parse_keys("<>").unwrap(), [key('<'), key('>')]);
+        assert!(parse_keys("<Nope>").is_err());
+        assert!(parse_keys("<X-a>").is_err());
+        assert!(parse_keys("").is_err());
+    }
+
+    #[test]
+    fn test_formatting_keys() {
+        let keys = [
+            key('d'),
+            KeyEvent::new(KeyCode::Char('W'), KeyModifiers::SHIFT),
+            key('<'),
+            KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+            KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL),
+            KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
+        ];
+        assert_eq!(format_keys(&keys), "dW<lt><Esc><C-w><CR>");
+        assert_eq!(
+            parse_keys(&format_keys(&keys)).unwrap(),
+            [
+                key('d'),
+                key('W'),
+                key('<'),
+                KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+                KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL),
+                KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mapping_keys() {
+        let mut keymap = Keymap::new(vec![
+            Binding::parse(MapMode::Edit, "jk", "<Esc>").unwrap(),
+            Binding::parse(MapMode::Navigate, "<Left>", "h").unwrap(),
+        ]);
+        let edit = Some(MapMode::Edit);
+
+        assert!(keymap.feed(edit, key('j')));
+        assert!(!keymap.has_ready());
+        assert!(!keymap.feed(edit, key('k')));
+        assert_eq!(
+            ready(&mut keymap),
+            [KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)]
+        );
+
+        // A key that can't finish the binding lets the ones before it through, and might start another binding itself.
+        keymap.feed(edit, key('j'));
+        assert!(keymap.feed(edit, key('j')));
+        assert_eq!(ready(&mut keymap), [key('j')]);
+        keymap.time_out(edit);
+        assert_eq!(ready(&mut keymap), [key('j')]);
+
+        // Bindings only apply in their own mode.
+        keymap.feed(Some(MapMode::Navigate), key('j'));
+        keymap.feed(None, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        assert_eq!(
+            ready(&mut keymap),
+            [key('j'), KeyEvent::new(KeyCode::Left, KeyModifiers::NONE)]
+        );
+        keymap.feed(
+            Some(MapMode::Navigate),
+            KeyEvent::new(KeyCode::Left, KeyModifiers::NONE),
+        );
+        assert_eq!(ready(&mut keymap), [key('h')]);
+    }
+
+    #[test]
+    fn test_longest_binding_wins() {
+        let mut keymap = Keymap::new(vec![
+            Binding::parse(MapMode::Navigate, "g", "0").unwrap(),
+            Binding::parse(MapMode::Navigate, "gx", "$").unwrap(),
+        ]);
+        let navigate = Some(MapMode::Navigate);
+
+        assert!(keymap.feed(navigate, key('g')));
+        keymap.feed(navigate, key('x'));
+        assert_eq!(ready(&mut keymap), [key('$')]);
+
+        keymap.feed(navigate, key('g'));
+        keymap.time_out(navigate);
+        assert_eq!(ready(&mut keymap), [key('0')]);
+    }
+}
@@ -0,0 +1,167 @@
+//! A single line of text being typed in at a prompt on the bottom row of the display.
+use crossterm::event::{KeyCode, KeyEvent};
+
+/// What a key press did to a `LineInput`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Input {
+    /// The text was changed.
+    Edited,
+    /// The cursor was moved, or nothing happened at all.
+    Unchanged,
+    /// The user is done typing, and wants whatever they typed to be used.
+    Submitted,
+    /// The user gave up on the prompt.
+    Cancelled,
+}
+
+/// Some text being typed in at a prompt, with a cursor that can be moved around in it.
+#[derive(Debug, Default)]
+pub struct LineInput {
+    text: String,
+    /// The byte index in `text` that typing happens at.
+    cursor: usize,
+}
+
+impl LineInput {
+    /// The text that has been typed in so far.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The number of characters before the cursor, which is how far along the prompt it should be shown.
+    pub fn cursor_column(&self) -> usize {
+        self.text[..self.cursor].chars().count()
+    }
+
+    /// Empties the input, ready for something new to be typed in.
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    /// Empties the input, handing back whatever had been typed in.
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        std::mem::take(&mut self.text)
+    }
+
+    /// Replaces the input with `text`, with the cursor at the end of it.
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.text.len();
+    }
+
+    /// Types some text in at the cursor.
+    pub fn insert_str(&mut self, s: &str) {
+        self.text.insert_str(self.cursor, s);
+        self.cursor += s.len();
+    }
+
+    /// Finds the byte index of the character before the cursor.
+    fn previous(&self) -> Option<usize> {
+        self.text[..self.cursor]
+            .char_indices()
+            .next_back()
+            .map(|(i, _)| i)
+    }
+
+    /// Finds the byte index of the character after the one under the cursor.
+    fn next(&self) -> Option<usize> {
+        self.text[self.cursor..]
+            .chars()
+            .next()
+            .map(|c| self.cursor + c.len_utf8())
+    }
+
+    /// Updates the input for a key press, telling the prompt what happened.
+    ///
+    /// Backspacing over an empty prompt gives up on it, like it does in Vim.
+    pub fn handle(&mut self, key: &KeyEvent) -> Input {
+        match key.code {
+            KeyCode::Esc => Input::Cancelled,
+            KeyCode::Enter => Input::Submitted,
+            KeyCode::Char(c) => {
+                self.text.insert(self.cursor, c);
+                self.cursor += c.len_utf8();
+                Input::Edited
+            }
+            KeyCode::Backspace if self.text.is_empty() => Input::Cancelled,
+            KeyCode::Backspace => match self.previous() {
+                Some(previous) => {
+                    self.text.replace_range(previous..self.cursor, "");
+                    self.cursor = previous;
+                    Input::Edited
+                }
+                None => Input::Unchanged,
+            },
+            KeyCode::Delete => match self.next() {
+                Some(next) => {
+                    self.text.replace_range(self.cursor..next, "");
+                    Input::Edited
+                }
+                None => Input::Unchanged,
+            },
+            KeyCode::Left => {
+                self.cursor = self.previous().unwrap_or(self.cursor);
+                Input::Unchanged
+            }
+            KeyCode::Right => {
+                self.cursor = self.next().unwrap_or(self.cursor);
+                Input::Unchanged
+            }
+            KeyCode::Home => {
+                self.cursor = 0;
+                Input::Unchanged
+            }
+            KeyCode::End => {
+                self.cursor = self.text.len();
+                Input::Unchanged
+            }
+            _ => Input::Unchanged,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    fn press(input: &mut LineInput, code: KeyCode) -> Input {
+        input.handle(&KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn test_typing_and_moving() {
+        let mut input = LineInput::default();
+        input.insert_str("té");
+        assert_eq!(press(&mut input, KeyCode::Left), Input::Unchanged);
+        assert_eq!(input.cursor_column(), 1);
+        assert_eq!(press(&mut input, KeyCode::Char('x')), Input::Edited);
+        assert_eq!(input.text(), "txé");
+
+        press(&mut input, KeyCode::Home);
+        assert_eq!(press(&mut input, KeyCode::Backspace), Input::Unchanged);
+        press(&mut input, KeyCode::Delete);
+        assert_eq!(input.text(), "xé");
+        press(&mut input, KeyCode::End);
+        press(&mut input, KeyCode::Backspace);
+        assert_eq!(input.text(), "x");
+        assert_eq!(input.cursor_column(), 1);
+    }
+
+    #[test]
+    fn test_finishing_input() {
+        let mut input = LineInput::default();
+        assert_eq!(press(&mut input, KeyCode::Backspace), Input::Cancelled);
+        press(&mut input, KeyCode::Char('a'));
+        assert_eq!(press(&mut input, KeyCode::Enter), Input::Submitted);
+        assert_eq!(press(&mut input, KeyCode::Esc), Input::Cancelled);
+        assert_eq!(input.take(), "a");
+        assert_eq!(input.text(), "");
+
+        input.set_text("abc");
+        press(&mut input, KeyCode::Char('d'));
+        assert_eq!(input.text(), "abcd");
+    }
+}
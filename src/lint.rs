@@ -0,0 +1,115 @@
+//! Running linters over files in the background, and picking the problems they find out of what they print.
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use crate::lsp::{self, Diagnostic, Severity};
+use crate::quickfix::QuickfixList;
+
+/// A linter that's been started on a file, and may or may not have finished with it yet.
+#[derive(Debug)]
+pub struct LintRun {
+    path: PathBuf,
+    /// Everything the linter printed, which turns up once it's finished.
+    output: Receiver<String>,
+}
+
+impl LintRun {
+    /// Starts the linter `command` on the file at `path`, in the shell.
+    ///
+    /// A `%` in the command is where the file's path goes, otherwise the path is put on the end.
+    pub fn start(command: &str, path: &Path) -> Self {
+        let quoted = format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"));
+        let command = match command.contains('%') {
+            true => command.replace('%', &quoted),
+            false => format!("{} {}", command, quoted),
+        };
+        let (sender, output) = mpsc::channel();
+        thread::spawn(move || {
+            // Linters disagree about where their findings go, so both are read, and a failed run just has nothing to say.
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .stdin(Stdio::null())
+                .output()
+                .map(|output| {
+                    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+                    text.push_str(&String::from_utf8_lossy(&output.stderr));
+                    text
+                })
+                .unwrap_or_default();
+            let _ = sender.send(output);
+        });
+        Self {
+            path: path.to_path_buf(),
+            output,
+        }
+    }
+
+    /// The file that the linter is looking at.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Picks up the problems the linter found, if it's finished.
+    pub fn poll(&self) -> Option<Vec<Diagnostic>> {
+        match self.output.try_recv() {
+            Ok(output) => Some(parse(&output, &self.path)),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Vec::new()),
+        }
+    }
+}
+
+/// Picks the problems with the file at `path` out of what a linter printed, in the same formats that `:make` understands.
+///
+/// Linters don't agree on how to say how bad a problem is, so anything that calls itself an error is one and everything
+/// else is a warning.
+pub fn parse(output: &str, path: &Path) -> Vec<Diagnostic> {
+    let path = lsp::absolute_path(path);
+    QuickfixList::parse(output)
+        .entries()
+        .iter()
+        .filter(|entry| lsp::absolute_path(&entry.path) == path)
+        .map(|entry| Diagnostic {
+            line: entry.line,
+            character: entry.column,
+            severity: match entry.message.to_lowercase().contains("error") {
+                true => Severity::Error,
+                false => Severity::Warning,
+            },
+            message: entry.message.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parsing_linter_output() {
+        let output = "\
+src/app.py:3:1: E302 expected 2 blank lines
+src/app.py:10: warning: unused variable 'x'
+src/other.py:1:1: error: wrong file
+Found 2 problems
+./src/app.py:12:5: error: undefined name 'y'
+";
+        let problems = parse(output, Path::new("src/app.py"));
+        let found: Vec<_> = problems
+            .iter()
+            .map(|d| (d.line, d.character, d.severity))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (2, 0, Severity::Warning),
+                (9, 0, Severity::Warning),
+                (11, 4, Severity::Error),
+            ]
+        );
+        assert_eq!(problems[1].message, "warning: unused variable 'x'");
+    }
+}
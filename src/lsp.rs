@@ -0,0 +1,700 @@
+//! A client for language servers, which know things about the code in a buffer that a text editor can't work out for itself.
+//!
+//! Each server is a child process that's spoken to in JSON-RPC over its stdin and stdout. A thread reads whatever it
+//! sends back, so that the editor can pick it up between key presses without ever waiting on the server.
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use crate::json::Json;
+
+/// How bad a problem that a server found is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+/// A problem that a server found with a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The line that the problem starts on, counted from zero.
+    pub line: usize,
+    /// How far along the line the problem starts, in UTF-16 code units like everything else from a server.
+    pub character: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// A place in a file that a server pointed at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub line: usize,
+    /// How far along the line the place is, in UTF-16 code units.
+    pub character: usize,
+}
+
+/// The requests that can be made of a server, so that its responses can be made sense of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    Initialize,
+    /// Where the thing under the cursor is defined.
+    Definition,
+    /// What the thing under the cursor is.
+    Hover,
+    /// What could be typed at the cursor.
+    Completion,
+}
+
+/// Something that a server has told the editor, once it's been turned into something the editor can use.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Notice {
+    /// The problems with a document, which replace any that were found before.
+    Diagnostics {
+        path: PathBuf,
+        diagnostics: Vec<Diagnostic>,
+    },
+    Definition(Location),
+    Hover(String),
+    Completion(Vec<String>),
+    /// A request failed, or the server went away.
+    Error(String),
+}
+
+/// A document that a server has been told about.
+#[derive(Debug)]
+struct Document {
+    path: PathBuf,
+    /// The buffer's revision when the server was last sent its text.
+    revision: u64,
+    /// The version number that the server knows the document by, which goes up every time it's sent.
+    version: usize,
+}
+
+/// A running language server.
+#[derive(Debug)]
+pub struct LanguageServer {
+    child: Child,
+    stdin: ChildStdin,
+    messages: Receiver<Json>,
+    next_id: usize,
+    /// The requests that are waiting on a response, by their ids.
+    pending: Vec<(usize, Request)>,
+    /// Messages that are waiting for the server to finish initializing, before which it mustn't be sent anything else.
+    queued: Option<Vec<Json>>,
+    documents: Vec<Document>,
+    /// Whether the server has gone away, which is only worth telling the user once.
+    exited: bool,
+}
+
+impl LanguageServer {
+    /// Starts a server by handing `command` to the shell, and asks it to work on the project in `root`.
+    pub fn start(command: &str, root: &Path) -> io::Result<Self> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            // Anything the server logs would be drawn all over the display.
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            while let Ok(Some(body)) = read_message(&mut reader) {
+                // Anything that isn't JSON is ignored, as there's no one to reply to about it.
+                if let Ok(message) = Json::parse(&body) {
+                    if sender.send(message).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let mut server = Self {
+            child,
+            stdin,
+            messages,
+            next_id: 0,
+            pending: Vec::new(),
+            queued: None,
+            documents: Vec::new(),
+            exited: false,
+        };
+        let capabilities = Json::object([(
+            "textDocument",
+            Json::object([
+                (
+                    "hover",
+                    Json::object([("contentFormat", Json::Array(vec![Json::from("plaintext")]))]),
+                ),
+                (
+                    "completion",
+                    Json::object([(
+                        "completionItem",
+                        Json::object([("snippetSupport", Json::from(false))]),
+                    )]),
+                ),
+                ("publishDiagnostics", Json::object([])),
+            ]),
+        )]);
+        server.request(
+            Request::Initialize,
+            Json::object([
+                ("processId", Json::from(std::process::id() as usize)),
+                ("rootUri", Json::from(path_to_uri(root))),
+                ("capabilities", capabilities),
+            ]),
+        )?;
+        server.queued = Some(Vec::new());
+        Ok(server)
+    }
+
+    /// Whether the server has been told about the document at `path` yet.
+    pub fn is_open(&self, path: &Path) -> bool {
+        self.documents.iter().any(|d| d.path == path)
+    }
+
+    /// Tells the server about a document, and what's in it at `revision`.
+    pub fn open(
+        &mut self,
+        path: &Path,
+        language: &str,
+        text: &str,
+        revision: u64,
+    ) -> io::Result<()> {
+        self.documents.push(Document {
+            path: path.to_path_buf(),
+            revision,
+            version: 0,
+        });
+        self.notify(
+            "textDocument/didOpen",
+            Json::object([(
+                "textDocument",
+                Json::object([
+                    ("uri", Json::from(path_to_uri(path))),
+                    ("languageId", Json::from(language)),
+                    ("version", Json::from(0)),
+                    ("text", Json::from(text)),
+                ]),
+            )]),
+        )
+    }
+
+    /// Sends the server the whole of a document again if it's been changed since `revision`.
+    ///
+    /// The text is only asked for if it's needed, since it means copying the whole buffer.
+    pub fn change(
+        &mut self,
+        path: &Path,
+        revision: u64,
+        text: impl FnOnce() -> String,
+    ) -> io::Result<()> {
+        let document = match self.documents.iter_mut().find(|d| d.path == path) {
+            Some(document) if document.revision != revision => document,
+            _ => return Ok(()),
+        };
+        document.revision = revision;
+        document.version += 1;
+        let version = document.version;
+        self.notify(
+            "textDocument/didChange",
+            Json::object([
+                (
+                    "textDocument",
+                    Json::object([
+                        ("uri", Json::from(path_to_uri(path))),
+                        ("version", Json::from(version)),
+                    ]),
+                ),
+                (
+                    "contentChanges",
+                    Json::Array(vec![Json::object([("text", Json::from(text()))])]),
+                ),
+            ]),
+        )
+    }
+
+    /// Asks the server about the place `character` UTF-16 code units along `line` of a document.
+    pub fn ask(
+        &mut self,
+        request: Request,
+        path: &Path,
+        line: usize,
+        character: usize,
+    ) -> io::Result<()> {
+        let params = Json::object([
+            (
+                "textDocument",
+                Json::object([("uri", Json::from(path_to_uri(path)))]),
+            ),
+            (
+                "position",
+                Json::object([
+                    ("line", Json::from(line)),
+                    ("character", Json::from(character)),
+                ]),
+            ),
+        ]);
+        self.request(request, params)
+    }
+
+    /// Picks up everything the server has sent since last time, without waiting for anything more.
+    pub fn poll(&mut self) -> Vec<Notice> {
+        let mut notices = Vec::new();
+        loop {
+            match self.messages.try_recv() {
+                Ok(message) => match self.receive(&message) {
+                    Ok(notice) => notices.extend(notice),
+                    Err(e) => notices.push(Notice::Error(format!("Language server: {}", e))),
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.exited {
+                        self.exited = true;
+                        notices.push(Notice::Error("Language server exited".to_string()));
+                    }
+                    break;
+                }
+            }
+        }
+        notices
+    }
+
+    /// Deals with a message from the server, turning it into a notice for the editor if there's anything to say.
+    fn receive(&mut self, message: &Json) -> io::Result<Option<Notice>> {
+        let id = message.get("id").cloned();
+        match (message.get("method").and_then(Json::as_str), id) {
+            (Some("textDocument/publishDiagnostics"), _) => {
+                Ok(message.get("params").and_then(diagnostics))
+            }
+            // The server asking for something (like configuration) gets told that there's nothing to give it.
+            (Some(_), Some(id)) => {
+                self.send(Json::object([
+                    ("jsonrpc", Json::from("2.0")),
+                    ("id", id),
+                    ("result", Json::Null),
+                ]))?;
+                Ok(None)
+            }
+            (Some(_), None) => Ok(None),
+            (None, Some(id)) => {
+                let index = match self
+                    .pending
+                    .iter()
+                    .position(|&(pending, _)| Some(pending) == id.as_usize())
+                {
+                    Some(index) => index,
+                    None => return Ok(None),
+                };
+                let (_, request) = self.pending.remove(index);
+                if let Some(error) = message.get("error") {
+                    let reason = error.get("message").and_then(Json::as_str).unwrap_or("");
+                    return Ok(Some(Notice::Error(format!("Language server: {}", reason))));
+                }
+                let result = message.get("result").unwrap_or(&Json::Null);
+                match request {
+                    Request::Initialize => {
+                        self.initialized()?;
+                        Ok(None)
+                    }
+                    request => Ok(Some(response(request, result))),
+                }
+            }
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Finishes the handshake once the server has initialized, then sends it everything it was kept waiting for.
+    fn initialized(&mut self) -> io::Result<()> {
+        let queued = self.queued.take().unwrap_or_default();
+        self.notify("initialized", Json::object([]))?;
+        for message in queued {
+            self.send(message)?;
+        }
+        Ok(())
+    }
+
+    fn request(&mut self, request: Request, params: Json) -> io::Result<()> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.push((id, request));
+        let method = match request {
+            Request::Initialize => "initialize",
+            Request::Definition => "textDocument/definition",
+            Request::Hover => "textDocument/hover",
+            Request::Completion => "textDocument/completion",
+        };
+        self.send(Json::object([
+            ("jsonrpc", Json::from("2.0")),
+            ("id", Json::from(id)),
+            ("method", Json::from(method)),
+            ("params", params),
+        ]))
+    }
+
+    fn notify(&mut self, method: &str, params: Json) -> io::Result<()> {
+        self.send(Json::object([
+            ("jsonrpc", Json::from("2.0")),
+            ("method", Json::from(method)),
+            ("params", params),
+        ]))
+    }
+
+    /// Sends a message straight away, or keeps hold of it until the server has initialized.
+    fn send(&mut self, message: Json) -> io::Result<()> {
+        match &mut self.queued {
+            Some(queued) => {
+                queued.push(message);
+                Ok(())
+            }
+            None => write_message(&mut self.stdin, &message),
+        }
+    }
+}
+
+impl Drop for LanguageServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Reads the body of the next message from a server, or `None` once there aren't going to be any more.
+fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let length = length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Writes a message to a server, with the header that says how long it is.
+fn write_message<W: Write>(writer: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// Makes sense of the result of a request.
+fn response(request: Request, result: &Json) -> Notice {
+    let notice = match request {
+        Request::Definition => definition(result).map(Notice::Definition),
+        Request::Hover => hover(result).map(Notice::Hover),
+        Request::Completion => Some(Notice::Completion(completion(result))),
+        Request::Initialize => None,
+    };
+    notice.unwrap_or_else(|| {
+        Notice::Error(match request {
+            Request::Definition => "No definition found".to_string(),
+            _ => "No information available".to_string(),
+        })
+    })
+}
+
+/// Picks the problems out of a `textDocument/publishDiagnostics` notification.
+fn diagnostics(params: &Json) -> Option<Notice> {
+    let path = uri_to_path(params.get("uri")?.as_str()?)?;
+    let diagnostics = params
+        .get("diagnostics")?
+        .as_array()?
+        .iter()
+        .filter_map(|diagnostic| {
+            let start = diagnostic.get("range")?.get("start")?;
+            let severity = match diagnostic.get("severity").and_then(Json::as_usize) {
+                Some(2) => Severity::Warning,
+                Some(3) => Severity::Information,
+                Some(4) => Severity::Hint,
+                _ => Severity::Error,
+            };
+            Some(Diagnostic {
+                line: start.get("line")?.as_usize()?,
+                character: start.get("character")?.as_usize()?,
+                severity,
+                message: diagnostic.get("message")?.as_str()?.to_string(),
+            })
+        })
+        .collect();
+    Some(Notice::Diagnostics { path, diagnostics })
+}
+
+/// Finds the first place that a definition request pointed at, which could be a `Location` or a `LocationLink`, or a list of either.
+fn definition(result: &Json) -> Option<Location> {
+    let location = match result {
+        Json::Array(locations) => locations.first()?,
+        location => location,
+    };
+    let (uri, range) = match location.get("targetUri") {
+        Some(uri) => (uri, location.get("targetSelectionRange")?),
+        None => (location.get("uri")?, location.get("range")?),
+    };
+    let start = range.get("start")?;
+    Some(Location {
+        path: uri_to_path(uri.as_str()?)?,
+        line: start.get("line")?.as_usize()?,
+        character: start.get("character")?.as_usize()?,
+    })
+}
+
+/// Gathers up the text of a hover, which can be given in several different shapes.
+fn hover(result: &Json) -> Option<String> {
+    fn text(contents: &Json) -> Option<String> {
+        match contents {
+            Json::String(s) => Some(s.clone()),
+            Json::Array(parts) => {
+                Some(parts.iter().filter_map(text).collect::<Vec<_>>().join("\n"))
+            }
+            // Both `MarkupContent` and the old `MarkedString` with a language keep their text in `value`.
+            contents => contents.get("value")?.as_str().map(str::to_string),
+        }
+    }
+    let text = text(result.get("contents")?)?;
+    match text.trim() {
+        "" => None,
+        text => Some(text.to_string()),
+    }
+}
+
+/// Lists the text that each completion would insert, which could come as a plain list of them or inside a `CompletionList`.
+fn completion(result: &Json) -> Vec<String> {
+    let items = match result.get("items") {
+        Some(items) => items,
+        None => result,
+    };
+    let mut words: Vec<String> = Vec::new();
+    for item in items.as_array().unwrap_or(&[]) {
+        let word = item
+            .get("textEdit")
+            .and_then(|edit| edit.get("newText"))
+            .or_else(|| item.get("insertText"))
+            .or_else(|| item.get("label"))
+            .and_then(Json::as_str);
+        if let Some(word) = word {
+            if !words.iter().any(|w| w == word) {
+                words.push(word.to_string());
+            }
+        }
+    }
+    words
+}
+
+/// Makes a path absolute, relative to the working directory, so that it can be compared with (and turned into) a URI.
+pub fn absolute_path(path: &Path) -> PathBuf {
+    match env::current_dir() {
+        Ok(dir) if path.is_relative() => dir.join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Makes a path relative to the working directory if it's inside it, which is how files are usually opened.
+pub fn relative_path(path: &Path) -> PathBuf {
+    env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .filter(|relative| !relative.as_os_str().is_empty())
+        .map_or_else(|| path.to_path_buf(), Path::to_path_buf)
+}
+
+/// Turns a path into a `file://` URI, escaping anything that isn't allowed in one.
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &byte in absolute_path(path).to_string_lossy().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            byte => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// Turns a `file://` URI back into the path that it's for.
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let escaped = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut i = 0;
+    while i < escaped.len() {
+        let byte = match escaped[i] {
+            b'%' => {
+                let hex = std::str::from_utf8(escaped.get(i + 1..i + 3)?).ok()?;
+                i += 2;
+                u8::from_str_radix(hex, 16).ok()?
+            }
+            byte => byte,
+        };
+        bytes.push(byte);
+        i += 1;
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// Counts the UTF-16 code units in some text, which is how servers measure how far along a line something is.
+pub fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+/// Finds the byte index that's `units` UTF-16 code units into `line`, stopping at the end of it.
+pub fn byte_index(line: &str, units: usize) -> usize {
+    let mut counted = 0;
+    for (i, c) in line.char_indices() {
+        if counted >= units {
+            return i;
+        }
+        counted += c.len_utf16();
+    }
+    line.len()
+}
+
+/// The name that servers know the language of a file by, guessed from its extension.
+pub fn language_id(extension: &str) -> &str {
+    match extension {
+        "rs" => "rust",
+        "py" => "python",
+        "js" => "javascript",
+        "ts" => "typescript",
+        "h" => "c",
+        "cc" | "cxx" | "hpp" => "cpp",
+        "sh" => "shellscript",
+        "md" => "markdown",
+        "yml" => "yaml",
+        extension => extension,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_framing_messages() {
+        let mut written = Vec::new();
+        write_message(&mut written, &Json::object([("id", Json::from(1))])).unwrap();
+        assert_eq!(written, b"Content-Length: 8\r\n\r\n{\"id\":1}");
+
+        let mut input =
+            &b"Content-Length: 2\r\nContent-Type: x\r\n\r\n{}Content-Length: 4\r\n\r\nnull"[..];
+        assert_eq!(read_message(&mut input).unwrap().as_deref(), Some("{}"));
+        assert_eq!(read_message(&mut input).unwrap().as_deref(), Some("null"));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn test_converting_uris() {
+        let path = Path::new("/home/me/a b/é.rs");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///home/me/a%20b/%C3%A9.rs");
+        assert_eq!(uri_to_path(&uri).as_deref(), Some(path));
+        assert_eq!(uri_to_path("http://example.com"), None);
+        assert!(path_to_uri(Path::new("relative.rs")).ends_with("/relative.rs"));
+    }
+
+    #[test]
+    fn test_counting_utf16() {
+        assert_eq!(utf16_len("aé😀b"), 5);
+        assert_eq!(byte_index("aé😀b", 2), 3);
+        assert_eq!(byte_index("aé😀b", 4), 7);
+        assert_eq!(byte_index("aé😀b", 99), 8);
+    }
+
+    #[test]
+    fn test_reading_diagnostics() {
+        let params = Json::parse(
+            r#"{"uri": "file:///src/a.rs", "diagnostics": [
+                {"range": {"start": {"line": 2, "character": 4}, "end": {"line": 2, "character": 5}},
+                 "severity": 2, "message": "unused"},
+                {"range": {"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 1}},
+                 "message": "broken"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            diagnostics(&params),
+            Some(Notice::Diagnostics {
+                path: PathBuf::from("/src/a.rs"),
+                diagnostics: vec![
+                    Diagnostic {
+                        line: 2,
+                        character: 4,
+                        severity: Severity::Warning,
+                        message: "unused".to_string()
+                    },
+                    Diagnostic {
+                        line: 0,
+                        character: 0,
+                        severity: Severity::Error,
+                        message: "broken".to_string()
+                    },
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_reading_responses() {
+        let location = Json::parse(
+            r#"[{"uri": "file:///a.rs", "range": {"start": {"line": 3, "character": 7}, "end": {"line": 3, "character": 9}}}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            response(Request::Definition, &location),
+            Notice::Definition(Location {
+                path: PathBuf::from("/a.rs"),
+                line: 3,
+                character: 7
+            })
+        );
+        assert_eq!(
+            response(Request::Definition, &Json::Null),
+            Notice::Error("No definition found".to_string())
+        );
+
+        let hover =
+            Json::parse(r#"{"contents": {"kind": "plaintext", "value": "fn main()"}}"#).unwrap();
+        assert_eq!(
+            response(Request::Hover, &hover),
+            Notice::Hover("fn main()".to_string())
+        );
+        let hover =
+            Json::parse(r#"{"contents": ["a", {"language": "rust", "value": "b"}]}"#).unwrap();
+        assert_eq!(
+            response(Request::Hover, &hover),
+            Notice::Hover("a\nb".to_string())
+        );
+
+        let items = Json::parse(
+            r#"{"isIncomplete": false, "items": [{"label": "len()", "insertText": "len"}, {"label": "push"}, {"label": "len"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            response(Request::Completion, &items),
+            Notice::Completion(vec!["len".to_string(), "push".to_string()])
+        );
+    }
+}
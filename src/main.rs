@@ -1,34 +1,660 @@
 /// Té is a simple modal text editor.
-use std::io;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Stdout, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
 
 use crossterm::cursor;
-use crossterm::{self, execute, terminal};
+use crossterm::tty::IsTty;
+use crossterm::{self, event, execute, style, terminal};
 
+mod buffer;
+mod buffers;
+mod clipboard;
+mod command;
+mod comment;
+mod completion;
+mod config;
+mod diff;
+mod dirs;
 mod editor;
 mod errors;
+mod git;
+mod history;
+mod hooks;
+mod json;
+mod keymap;
+mod line_input;
+mod lint;
+mod lsp;
+mod marks;
+mod motions;
+mod numbers;
+mod quickfix;
+mod range;
+mod regex;
+mod registers;
+mod screen;
+mod search;
+mod server;
+mod session;
+mod sort;
+mod state;
+mod substitute;
+mod surround;
+#[cfg(test)]
+mod test_util;
+mod theme;
+mod timers;
+mod trust;
+mod undo;
+mod unicode;
+mod view;
+mod windows;
+mod word_completion;
 
-use crate::editor::Editor;
-use crate::errors::Result;
+use crate::config::{Config, ConfigError};
+use crate::editor::{Editor, Exit};
+use crate::errors::{EditorError, Result};
+use crate::session::Session;
+use crate::theme::Theme;
+use crate::view::LineNumbers;
+
+/// Splits the `:line` or `:line:column` suffix that compilers and grep print off the end of a path.
+///
+/// Paths that exist on disk are left alone, just in case somebody really did put a colon in their filename.
+fn split_position(arg: &str) -> (&str, Option<usize>, Option<usize>) {
+    if Path::new(arg).exists() {
+        return (arg, None, None);
+    }
+
+    if let [column, line, path] = arg.rsplitn(3, ':').collect::<Vec<_>>()[..] {
+        if let (Ok(line), Ok(column)) = (line.parse(), column.parse()) {
+            return (path, Some(line), Some(column));
+        }
+    }
+
+    if let [line, path] = arg.rsplitn(2, ':').collect::<Vec<_>>()[..] {
+        if let Ok(line) = line.parse() {
+            return (path, Some(line), None);
+        }
+    }
+
+    (arg, None, None)
+}
+
+/// Checks that `--diff` was given two files to compare, saying what's wrong with them if it wasn't.
+///
+/// The same file twice has no differences to show, however its paths are written.
+fn diff_problem(filenames: &[PathBuf]) -> Option<&'static str> {
+    match filenames {
+        [first, second] if same_file(first, second) => {
+            Some("--diff needs two different files to compare")
+        }
+        [_, _] => None,
+        _ => Some("--diff needs two files to compare"),
+    }
+}
+
+/// Checks whether two paths lead to the same file, going by the paths themselves for files that don't exist yet.
+fn same_file(first: &Path, second: &Path) -> bool {
+    match (fs::canonicalize(first), fs::canonicalize(second)) {
+        (Ok(first), Ok(second)) => first == second,
+        _ => first == second,
+    }
+}
 
 fn main() -> Result<()> {
     let mut stdout = io::stdout();
 
-    let filename = std::env::args().nth(1).expect("Did not receive filename");
+    // A broken configuration file is reported before anything else happens, rather than being quietly ignored.
+    let mut config = match dirs::config_dir().map(|dir| dir.join("config.toml")) {
+        Some(path) => Config::load(&path).unwrap_or_else(|e| config_failed(&path, e)),
+        None => Config::default(),
+    };
+    let mut pager = false;
+    let mut diff = false;
+    let mut read_only = false;
+    let mut normalize_on_load = false;
+    let mut normalize_on_save = false;
+    // Options given on the command line go over the configuration, which isn't finished until the files are known.
+    let mut use_clipboard = None;
+    let mut mouse = None;
+    let mut line_numbers = None;
+    let mut tab_width = None;
+    let mut expand_tab = None;
+    let mut shift_width = None;
+    let mut filenames = Vec::new();
+    let mut line = None;
+    let mut column = None;
+    let mut pattern = None;
+    let mut server = None;
+    let mut session = None;
+    let mut commands = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-p" => pager = true,
+            "-c" => match args.next() {
+                Some(command) => commands.push((String::new(), command)),
+                None => {
+                    eprintln!("-c needs a command to run");
+                    process::exit(1);
+                }
+            },
+            "--batch" => match args
+                .next()
+                .map(|script| (fs::read_to_string(&script), script))
+            {
+                Some((Ok(text), script)) => commands.extend(script_commands(&script, &text)),
+                Some((Err(e), script)) => {
+                    eprintln!("{}: {}", script, e);
+                    process::exit(1);
+                }
+                None => {
+                    eprintln!("--batch needs a script to run");
+                    process::exit(1);
+                }
+            },
+            "--server" => match args.next() {
+                Some(socket) => server = Some(PathBuf::from(socket)),
+                None => {
+                    eprintln!("--server needs the path of a socket to listen on");
+                    process::exit(1);
+                }
+            },
+            "--session" => match args
+                .next()
+                .map(|file| (fs::read_to_string(&file).map_err(|e| e.to_string()), file))
+            {
+                Some((text, file)) => match text.and_then(|text| Session::parse(&text)) {
+                    Ok(restored) => session = Some(restored),
+                    Err(e) => {
+                        eprintln!("{}: {}", file, e);
+                        process::exit(1);
+                    }
+                },
+                None => {
+                    eprintln!("--session needs a session file to open");
+                    process::exit(1);
+                }
+            },
+            "-R" => read_only = true,
+            "--diff" => diff = true,
+            "--nfc" => {
+                normalize_on_load = true;
+                normalize_on_save = true;
+            }
+            "--nfc=load" => normalize_on_load = true,
+            "--nfc=save" => normalize_on_save = true,
+            "--clipboard" => use_clipboard = Some(true),
+            // Capturing the mouse gets in the way of the terminal's own text selection, which some people would rather keep.
+            "--no-mouse" => mouse = Some(false),
+            "--number" => line_numbers = Some(LineNumbers::Absolute),
+            "--relative-number" => line_numbers = Some(LineNumbers::Relative),
+            "--expand-tab" => expand_tab = Some(true),
+            _ if arg.starts_with("--tab-width=") => {
+                tab_width = arg["--tab-width=".len()..].parse().ok().or(tab_width)
+            }
+            _ if arg.starts_with("--shift-width=") => {
+                shift_width = arg["--shift-width=".len()..].parse().ok().or(shift_width)
+            }
+            // A bare `+` jumps to the end of the file, like it does in vi.
+            "+" => line = Some(usize::MAX),
+            _ if arg.starts_with("+/") => pattern = Some(arg[2..].to_string()),
+            _ if arg.starts_with('+') && arg[1..].parse::<usize>().is_ok() => {
+                line = arg[1..].parse().ok()
+            }
+            _ => {
+                // Only the file that we start on can be jumped into.
+                let (path, l, c) = split_position(&arg);
+                if filenames.is_empty() {
+                    line = l.or(line);
+                    column = c.or(column);
+                }
+                filenames.push(PathBuf::from(path));
+            }
+        }
+    }
+    // The session says which files to open, and its paths are relative to the directory it was saved in.
+    if let Some(session) = &session {
+        if !filenames.is_empty() || diff {
+            eprintln!("--session opens the files in the session, and can't open others too");
+            process::exit(1);
+        }
+        if let Err(e) = std::env::set_current_dir(&session.directory) {
+            eprintln!("{}: {}", session.directory.display(), e);
+            process::exit(1);
+        }
+        filenames = session
+            .buffers
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect();
+    }
+    if let Some(problem) = diff.then(|| diff_problem(&filenames)).flatten() {
+        eprintln!("{}", problem);
+        process::exit(1);
+    }
+
+    // Only a user at the terminal can be asked whether to trust a project's commands.
+    let interactive = io::stdin().is_tty() && server.is_none() && commands.is_empty();
+    let project_warning = layer_project_config(&mut config, &filenames, interactive);
+    let use_clipboard = use_clipboard.unwrap_or(config.clipboard);
+    let mouse = mouse.unwrap_or(config.mouse);
+    let line_numbers = line_numbers.unwrap_or(config.line_numbers);
+    let tab_width = tab_width.unwrap_or(config.tab_width);
+    let expand_tab = expand_tab.unwrap_or(config.expand_tab);
+    let shift_width = shift_width.unwrap_or(config.shift_width);
+    let theme = match (&config.theme, dirs::config_dir()) {
+        (Some(name), Some(dir)) => {
+            let path = dir.join("themes").join(format!("{}.toml", name));
+            Theme::load(&path, theme::supports_truecolor()).unwrap_or_else(|e| {
+                eprintln!("{}: {}", path.display(), e);
+                process::exit(1);
+            })
+        }
+        _ => Theme::default(),
+    };
 
-    let (columns, rows) = terminal::size().expect("Failed to get terminal size");
+    // Without a terminal the display is the usual size, until a client says otherwise.
+    let (columns, rows) = match server.is_some() || !commands.is_empty() {
+        true => (80, 24),
+        false => terminal::size().expect("Failed to get terminal size"),
+    };
 
-    let mut editor = Editor::from_path(filename)?;
+    // Starting up with nothing to edit shows the files edited lately, along with how to get going.
+    let start_screen = filenames.is_empty()
+        && !pager
+        && io::stdin().is_tty()
+        && server.is_none()
+        && commands.is_empty();
+
+    // Whatever is being piped in ends up in an unnamed buffer, crossterm will go find the TTY for itself.
+    // With nothing piped in the pager reads from the terminal, which at least waits for the user rather than showing nothing.
+    let mut editor = match filenames.as_slice() {
+        _ if server.is_some() => Editor::from_paths(filenames)?,
+        [] if pager || !io::stdin().is_tty() => Editor::from_reader(io::stdin())?,
+        [filename] if filename.as_os_str() == "-" => Editor::from_reader(io::stdin())?,
+        _ => Editor::from_paths(filenames)?,
+    };
+    editor.set_pager(pager);
+    if start_screen {
+        editor.show_start_screen();
+    }
+    if let Some(warning) = project_warning {
+        editor.show_warning(warning);
+    }
+    if read_only {
+        editor.set_read_only();
+    }
+    editor.set_normalize_on_save(normalize_on_save);
+    editor.set_use_clipboard(use_clipboard);
+    // The clipboard only holds what the user has copied when they're at the terminal, rather than running a script or serving
+    // other programs, which shouldn't get to read it.
+    editor.set_read_clipboard(server.is_none() && commands.is_empty());
+    editor.set_line_numbers(line_numbers);
+    editor.set_tab_width(tab_width);
+    editor.set_scroll_off(config.scroll_off);
+    editor.set_wrap(config.wrap);
+    editor.set_list(config.list);
+    editor.set_list_chars(config.list_chars);
+    editor.set_fill_chars(config.fill_chars);
+    editor.set_cursor_line(config.cursor_line);
+    editor.set_color_columns(config.color_columns);
+    editor.set_expand_tab(expand_tab);
+    editor.set_shift_width(shift_width);
+    editor.set_key_bindings(config.bindings);
+    if let Some(dir) = dirs::config_dir() {
+        editor.set_config_file(dir.join("config.toml"));
+    }
+    editor.set_backup(config.backup);
+    editor.set_make_command(config.make_command);
+    editor.set_language_servers(config.language_servers);
+    editor.set_comment_strings(config.comment_strings);
+    editor.set_formatters(config.formatters);
+    editor.set_format_on_save(config.format_on_save);
+    editor.set_linters(config.linters);
+    editor.set_hooks(config.hooks);
+    editor.set_theme(theme);
+    if let Some(seconds) = config.autosave {
+        editor.set_autosave(Duration::from_secs(seconds));
+    }
+    if normalize_on_load {
+        editor.normalize();
+    }
+    if let Some(dir) = dirs::state_dir() {
+        editor.set_recovery_dir(dir.join("recovery"));
+        editor.set_state_file(dir.join("state"));
+    }
     editor.set_display_columns(columns);
     editor.set_display_rows(rows);
+    if diff {
+        editor.diff_files()?;
+    }
+    if let Some(session) = &session {
+        editor.restore_session(session)?;
+    }
+
+    // Lines and columns are counted from 1 on the command line, like every compiler does.
+    if line.is_some() || column.is_some() {
+        editor.goto(
+            line.unwrap_or(1).saturating_sub(1),
+            column.unwrap_or(1).saturating_sub(1),
+        );
+    }
+    // Like `+/pattern` in Vim, the search starts from wherever the cursor has got to, wrapping around the end if it needs to.
+    if let Some(pattern) = pattern.filter(|p| !p.is_empty()) {
+        editor.search(pattern);
+    }
+
+    // The server only stops when a client asks it to, which is as good as quitting.
+    if let Some(socket) = server {
+        let result = server::serve(&mut editor, &socket);
+        editor.remove_recovery_files();
+        return result.map_err(EditorError::FileIo);
+    }
+    // Commands from `-c` and `--batch` are run like `ex -s` runs them, and then the editor exits without touching the terminal.
+    if !commands.is_empty() {
+        let succeeded = run_batch(&mut editor, &commands);
+        editor.remove_recovery_files();
+        process::exit(if succeeded { 0 } else { 1 });
+    }
+
+    // A panic message printed while we're still in raw mode on the alternate screen is lost as soon as the terminal is restored.
+    // So put the terminal back first and then let the default hook report the panic.
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal(&mut io::stdout(), mouse);
+        default_hook(info);
+    }));
+
+    enter_terminal(&mut stdout, mouse);
+
+    // The hook can't get at the editor, so any unsaved changes are rescued here once the panic has unwound back to us.
+    let result = loop {
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| editor.run(&mut stdout))) {
+            Ok(result) => result,
+            Err(panic) => {
+                write_recovery_files(&mut editor);
+                panic::resume_unwind(panic);
+            }
+        };
+        match result {
+            Ok(Exit::Suspend) => {
+                leave_terminal(&mut stdout, mouse);
+                suspend();
+                enter_terminal(&mut stdout, mouse);
+
+                // The terminal could well have been resized while we were stopped, and whatever ran in it has drawn over us.
+                let (columns, rows) = terminal::size().expect("Failed to get terminal size");
+                editor.redraw(columns, rows);
+            }
+            Ok(Exit::Shell(command)) => {
+                leave_terminal(&mut stdout, mouse);
+                run_shell_command(&command);
+                enter_terminal(&mut stdout, mouse);
+                let (columns, rows) = terminal::size().expect("Failed to get terminal size");
+                editor.redraw(columns, rows);
+            }
+            Ok(Exit::Quit) => break Ok(()),
+            Err(e) => break Err(e),
+        }
+    };
+
+    leave_terminal(&mut stdout, mouse);
+
+    if let Err(e) = editor.write_state() {
+        eprintln!("Failed to write state: {}", e);
+    }
+
+    // Quitting is a deliberate choice to throw away any unsaved changes, so there's nothing left to recover.
+    editor.remove_recovery_files();
+
+    result
+}
+
+/// Reads the commands out of a batch script, one to a line, leaving out blank lines and comments that start with `#`.
+///
+/// Each command comes with where it is in the script, for pointing at it if it fails.
+fn script_commands(script: &str, text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| (format!("{}:{}: ", script, i + 1), line.to_string()))
+        .collect()
+}
+
+/// Runs commands one after another as if they'd been typed at the `:` prompt, stopping at the first one that fails.
+///
+/// Returns whether they all worked, having said what went wrong if they didn't.
+fn run_batch(editor: &mut Editor, commands: &[(String, String)]) -> bool {
+    for (place, command) in commands {
+        let command = command.trim_start().trim_start_matches(':');
+        match editor.execute_line(command) {
+            Ok(true) => break,
+            Ok(false) => (),
+            Err(e) => {
+                eprintln!("{}{}", place, e);
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Layers the `.te.toml` of the project that the first file is in (or the current directory, without any files) over the
+/// user's configuration.
+///
+/// The commands that the file sets are only used if the user trusts it, which they're asked about on the terminal the first
+/// time (and again whenever the file changes.) Hands back a warning for the user if any commands were left out.
+fn layer_project_config(
+    config: &mut Config,
+    filenames: &[PathBuf],
+    interactive: bool,
+) -> Option<String> {
+    let dir = match filenames.first() {
+        Some(path) => lsp::absolute_path(path).parent()?.to_path_buf(),
+        None => std::env::current_dir().ok()?,
+    };
+    let path = config::find_project_file(&dir)?;
+    let text =
+        fs::read_to_string(&path).unwrap_or_else(|e| config_failed(&path, ConfigError::Io(e)));
+    let commands = config::commands_in(&text).unwrap_or_else(|e| config_failed(&path, e));
+
+    let trust_list = dirs::state_dir().map(|dir| dir.join("trusted"));
+    let trusted = commands.is_empty()
+        || match &trust_list {
+            Some(list) if trust::is_trusted(list, &path, &text) => true,
+            Some(list) if interactive && ask_to_trust(&path, &commands) => {
+                if let Err(e) = trust::trust(list, &path, &text) {
+                    eprintln!("{}: {}", list.display(), e);
+                }
+                true
+            }
+            _ => false,
+        };
+    let skipped = config
+        .layer(&text, trusted)
+        .unwrap_or_else(|e| config_failed(&path, e));
+    match skipped.is_empty() {
+        true => None,
+        false => Some(format!(
+            "Left out {} from {}, which isn't trusted",
+            skipped.join(", "),
+            path.display()
+        )),
+    }
+}
 
+/// Reports a configuration file that can't be used, before anything else happens, rather than quietly ignoring it.
+fn config_failed(path: &Path, error: ConfigError) -> ! {
+    eprintln!("{}: {}", path.display(), error);
+    process::exit(1);
+}
+
+/// Asks the user on the terminal whether a project's configuration file can run the commands it sets.
+fn ask_to_trust(path: &Path, commands: &[String]) -> bool {
+    eprint!(
+        "{} sets commands to run ({}). Trust it? [y/N] ",
+        path.display(),
+        commands.join(", ")
+    );
+    let mut answer = String::new();
+    match io::stdin().read_line(&mut answer) {
+        Ok(_) => matches!(answer.trim(), "y" | "Y" | "yes"),
+        Err(_) => false,
+    }
+}
+
+/// Takes over the terminal, switching to the alternate screen in raw mode.
+fn enter_terminal(stdout: &mut Stdout, mouse: bool) {
     execute!(stdout, terminal::EnterAlternateScreen).expect("Failed to enter alternate screen");
     terminal::enable_raw_mode().expect("Failed to enable raw mode");
     execute!(stdout, cursor::MoveTo(0, 0)).unwrap();
+    // The editor puts the file's name in the title, so the one from before is pushed onto xterm's stack of titles to come back to.
+    execute!(stdout, style::Print("\x1b[22;0t")).expect("Failed to save the title");
+    if mouse {
+        execute!(stdout, event::EnableMouseCapture).expect("Failed to capture the mouse");
+    }
+    execute!(stdout, event::EnableFocusChange).expect("Failed to enable focus reporting");
+    execute!(stdout, event::EnableBracketedPaste).expect("Failed to enable bracketed paste");
+}
+
+/// Puts the terminal back the way it was before `enter_terminal`.
+fn leave_terminal(stdout: &mut Stdout, mouse: bool) {
+    restore_terminal(stdout, mouse).expect("Failed to restore the terminal");
+}
+
+/// Undoes everything that `enter_terminal` and the editor did to the terminal, for leaving it normally or after a panic.
+///
+/// Every step is tried even if one before it fails, so that as much as possible is put back, and the first failure is returned.
+fn restore_terminal<W: Write>(stdout: &mut W, mouse: bool) -> io::Result<()> {
+    let steps = [
+        // The editor changes the cursor's shape with each mode, and DECSCUSR 0 puts back whatever the user had it set to.
+        execute!(stdout, style::Print("\x1b[0 q")),
+        execute!(stdout, style::Print("\x1b[23;0t")),
+        terminal::disable_raw_mode(),
+        match mouse {
+            true => execute!(stdout, event::DisableMouseCapture),
+            false => Ok(()),
+        },
+        execute!(stdout, event::DisableFocusChange),
+        execute!(stdout, event::DisableBracketedPaste),
+        execute!(stdout, terminal::LeaveAlternateScreen),
+    ];
+    steps.into_iter().collect()
+}
+
+/// Stops the editor until the shell resumes it, like Ctrl-Z does to any other program.
+///
+/// Raw mode stops the terminal from sending SIGTSTP for us, so we send it ourselves and carry on from here once SIGCONT arrives.
+#[cfg(unix)]
+fn suspend() {
+    // SAFETY: raise has no preconditions, and SIGTSTP is a signal that exists.
+    unsafe {
+        libc::raise(libc::SIGTSTP);
+    }
+}
+
+/// There's no job control to hand over to, so the editor just carries on.
+#[cfg(not(unix))]
+fn suspend() {}
+
+/// Runs a command from `:!` on the terminal, then waits for the user to finish reading what it printed.
+fn run_shell_command(command: &str) {
+    match process::Command::new("sh").arg("-c").arg(command).status() {
+        Ok(status) if !status.success() => match status.code() {
+            Some(code) => println!("\nshell returned {}", code),
+            None => println!("\n{}", status),
+        },
+        Ok(_) => (),
+        Err(e) => println!("Failed to run {}: {}", command, e),
+    }
+    print!("\nPress ENTER to continue");
+    let _ = io::stdout().flush();
+
+    // Standard input could be the file being paged through, so the answer comes from the terminal itself if possible.
+    let mut answer = String::new();
+    let _ = match File::open("/dev/tty") {
+        Ok(tty) => BufReader::new(tty).read_line(&mut answer),
+        Err(_) => io::stdin().lock().read_line(&mut answer),
+    };
+}
+
+/// Saves any unsaved changes in the editor's buffers into recovery files, telling the user where they went.
+fn write_recovery_files(editor: &mut Editor) {
+    for result in editor.write_recovery_files() {
+        match result {
+            Ok(path) => eprintln!("Unsaved changes were written to {}", path.display()),
+            Err(e) => eprintln!("Failed to write unsaved changes: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_split_position_plain_path() {
+        assert_eq!(split_position("nowhere.rs"), ("nowhere.rs", None, None));
+    }
+
+    #[test]
+    fn test_split_position_line() {
+        assert_eq!(
+            split_position("nowhere.rs:42"),
+            ("nowhere.rs", Some(42), None)
+        );
+    }
+
+    #[test]
+    fn test_split_position_line_and_column() {
+        assert_eq!(
+            split_position("src/nowhere.rs:42:7"),
+            ("src/nowhere.rs", Some(42), Some(7))
+        );
+    }
 
-    editor.run(&mut stdout)?;
+    #[test]
+    fn test_script_commands() {
+        let script = "# tidy up\n%s/ +$//\n\n  :w out.txt\n";
+        assert_eq!(
+            script_commands("tidy.te", script),
+            [
+                ("tidy.te:2: ".to_string(), "%s/ +$//".to_string()),
+                ("tidy.te:4: ".to_string(), "  :w out.txt".to_string()),
+            ]
+        );
+    }
 
-    terminal::disable_raw_mode().expect("Failed to disable raw mode");
-    execute!(stdout, terminal::LeaveAlternateScreen).expect("Failed to leave alternate screen");
+    #[test]
+    fn test_diff_needs_two_different_files() {
+        let dir = TempDir::new("diff-args");
+        let a = dir.write("a.txt", "a");
+        let b = dir.write("b.txt", "b");
+        assert_eq!(diff_problem(&[a.clone(), b]), None);
+        assert_eq!(
+            diff_problem(&[a.clone(), dir.join(".").join("a.txt")]),
+            Some("--diff needs two different files to compare")
+        );
+        assert_eq!(
+            diff_problem(&[PathBuf::from("new.txt"), PathBuf::from("new.txt")]),
+            Some("--diff needs two different files to compare")
+        );
+        assert_eq!(
+            diff_problem(&[a]),
+            Some("--diff needs two files to compare")
+        );
+    }
 
-    Ok(())
+    #[test]
+    fn test_split_position_non_numeric_suffix() {
+        assert_eq!(split_position("nowhere:rs"), ("nowhere:rs", None, None));
+        assert_eq!(split_position("no:where:12"), ("no:where", Some(12), None));
+    }
 }
@@ -0,0 +1,158 @@
+//! Places in a buffer that the cursor can be sent back to, either marked by the user or left behind by a long jump.
+use crate::buffer::Position;
+
+/// How many places the jump list holds onto before it starts forgetting the oldest.
+const MAX_JUMPS: usize = 100;
+
+/// The marks set in a buffer with `m`, and the list of places the cursor has jumped from.
+#[derive(Debug, Default)]
+pub struct Marks {
+    /// The marks `a` to `z`.
+    named: [Option<Position>; 26],
+    /// The places the cursor jumped from, oldest first, which `Ctrl-O` and `Ctrl-I` move back and forth through.
+    jumps: Vec<Position>,
+    /// How far back through `jumps` the cursor has gone, which is `jumps.len()` when it hasn't gone back at all.
+    index: usize,
+    /// Where the cursor was before the latest jump, for the `'` and `` ` `` marks.
+    previous: Option<Position>,
+}
+
+impl Marks {
+    /// Determines whether `name` is a mark that can be set with `m`.
+    pub fn is_valid_name(name: char) -> bool {
+        name.is_ascii_lowercase()
+    }
+
+    /// Sets the mark `name` to `position`.
+    pub fn set(&mut self, name: char, position: Position) {
+        if Self::is_valid_name(name) {
+            self.named[usize::from(name as u8 - b'a')] = Some(position);
+        }
+    }
+
+    /// Finds the position of a mark, if it's been set.
+    ///
+    /// The marks `'` and `` ` `` are wherever the cursor was before the latest jump.
+    pub fn get(&self, name: char) -> Option<Position> {
+        match name {
+            '\'' | '`' => self.previous,
+            name if Self::is_valid_name(name) => self.named[usize::from(name as u8 - b'a')],
+            _ => None,
+        }
+    }
+
+    /// Remembers that the cursor is about to jump away from `from`.
+    ///
+    /// Like a web browser's history, anything that had been gone back through is forgotten, and so is an older jump from the same line.
+    pub fn push_jump(&mut self, from: Position) {
+        self.jumps.truncate(self.index);
+        self.jumps.retain(|jump| jump.line != from.line);
+        self.jumps.push(from);
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.index = self.jumps.len();
+        self.previous = Some(from);
+    }
+
+    /// Goes back to the place that was jumped from before `current`.
+    pub fn jump_back(&mut self, current: Position) -> Option<Position> {
+        if self.index == 0 {
+            return None;
+        }
+        // The place being left is kept on the end of the list, so that `Ctrl-I` can come back to it.
+        if self.index == self.jumps.len() {
+            self.push_jump(current);
+            // Keeping it may have forgotten a jump from the same line, leaving nowhere to go back to.
+            if self.jumps.len() < 2 {
+                return None;
+            }
+            self.index = self.jumps.len() - 1;
+        }
+        self.index -= 1;
+        self.previous = Some(current);
+        Some(self.jumps[self.index])
+    }
+
+    /// Goes forward again to a place that was gone back from with `jump_back`.
+    pub fn jump_forward(&mut self, current: Position) -> Option<Position> {
+        if self.index + 1 >= self.jumps.len() {
+            return None;
+        }
+        self.index += 1;
+        self.previous = Some(current);
+        Some(self.jumps[self.index])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_setting_marks() {
+        let mut marks = Marks::default();
+        marks.set('a', Position::new(1, 2));
+        marks.set('A', Position::new(3, 4));
+        assert_eq!(marks.get('a'), Some(Position::new(1, 2)));
+        assert_eq!(marks.get('b'), None);
+        assert_eq!(marks.get('A'), None);
+        assert_eq!(marks.get('\''), None);
+
+        marks.push_jump(Position::new(5, 0));
+        assert_eq!(marks.get('\''), Some(Position::new(5, 0)));
+        assert_eq!(marks.get('`'), Some(Position::new(5, 0)));
+    }
+
+    #[test]
+    fn test_moving_through_jumps() {
+        let mut marks = Marks::default();
+        assert_eq!(marks.jump_back(Position::new(0, 0)), None);
+
+        marks.push_jump(Position::new(1, 0));
+        marks.push_jump(Position::new(2, 0));
+        assert_eq!(
+            marks.jump_back(Position::new(3, 0)),
+            Some(Position::new(2, 0))
+        );
+        assert_eq!(
+            marks.jump_back(Position::new(2, 0)),
+            Some(Position::new(1, 0))
+        );
+        assert_eq!(marks.jump_back(Position::new(1, 0)), None);
+        assert_eq!(
+            marks.jump_forward(Position::new(1, 0)),
+            Some(Position::new(2, 0))
+        );
+        assert_eq!(
+            marks.jump_forward(Position::new(2, 0)),
+            Some(Position::new(3, 0))
+        );
+        assert_eq!(marks.jump_forward(Position::new(3, 0)), None);
+
+        // Jumping from the middle of the list forgets everything after it.
+        marks.jump_back(Position::new(3, 0));
+        marks.jump_back(Position::new(2, 0));
+        marks.push_jump(Position::new(1, 5));
+        assert_eq!(marks.jump_forward(Position::new(7, 0)), None);
+        assert_eq!(
+            marks.jump_back(Position::new(7, 0)),
+            Some(Position::new(1, 5))
+        );
+        assert_eq!(marks.jump_back(Position::new(1, 5)), None);
+    }
+
+    #[test]
+    fn test_jumping_back_from_the_same_line() {
+        let mut marks = Marks::default();
+        marks.push_jump(Position::new(0, 0));
+        assert_eq!(marks.jump_back(Position::new(0, 8)), None);
+        assert_eq!(marks.jump_forward(Position::new(0, 8)), None);
+
+        marks.push_jump(Position::new(2, 0));
+        assert_eq!(
+            marks.jump_back(Position::new(0, 3)),
+            Some(Position::new(2, 0))
+        );
+    }
+}
@@ -0,0 +1,594 @@
+//! Motions that move the cursor through the document by more than a single character at a time.
+use std::cmp;
+
+use crate::buffer::Rope;
+
+/// The motions that can be made from a position in the document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    /// Forward to the start of the next word (like Vim's `w`.)
+    NextWordStart,
+    /// Back to the start of the current or previous word (like Vim's `b`.)
+    PreviousWordStart,
+    /// Forward to the end of the current or next word (like Vim's `e`.)
+    WordEnd,
+    /// To the first character of the line (like Vim's `0`.)
+    LineStart,
+    /// To the first character of the line that isn't a space or a tab (like Vim's `^`.)
+    FirstNonBlank,
+    /// To the last character of the line (like Vim's `$`.)
+    LineEnd,
+    /// To the first non-blank character of the first line (like Vim's `gg`.)
+    DocumentStart,
+    /// To the first non-blank character of the last line (like Vim's `G`.)
+    DocumentEnd,
+    /// Forward to the next empty line after the paragraph (like Vim's `}`.)
+    ParagraphForward,
+    /// Back to the empty line before the paragraph (like Vim's `{`.)
+    ParagraphBackward,
+    /// To the bracket that matches the one under the cursor, or the next one along the line (like Vim's `%`.)
+    MatchingBracket,
+    /// To (or next to) a character further along or back on the line (like Vim's `f`, `t`, `F` and `T`.)
+    Find(CharSearch),
+    /// Repeats a `Find`, which steps over a character right next to the cursor when it's only going up to it (like Vim's `;` and `,`.)
+    RepeatFind(CharSearch),
+}
+
+/// A search for a character within the line that the cursor is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharSearch {
+    /// The character being looked for.
+    pub target: char,
+    /// Whether to look further along the line rather than back.
+    pub forward: bool,
+    /// Whether to stop next to the character rather than on it (like Vim's `t` and `T`.)
+    pub till: bool,
+}
+
+impl CharSearch {
+    /// The same search going the other way, which `,` makes.
+    pub fn reversed(self) -> Self {
+        Self {
+            forward: !self.forward,
+            ..self
+        }
+    }
+
+    /// Finds where the search ends up from `idx`, if the character turns up on the line at all.
+    pub fn find(&self, contents: &Rope, idx: usize) -> Option<usize> {
+        let mut i = idx;
+        loop {
+            i = match self.forward {
+                true => contents.next_grapheme(i),
+                false if i == 0 => return None,
+                false => contents.previous_grapheme(i),
+            };
+            match contents.char_at(i) {
+                Some('\n') | None => return None,
+                Some(c) if c == self.target => break,
+                Some(_) => (),
+            }
+        }
+        match (self.till, self.forward) {
+            (false, _) => Some(i),
+            (true, true) => Some(contents.previous_grapheme(i)),
+            (true, false) => Some(contents.next_grapheme(i)),
+        }
+    }
+
+    /// Finds where repeating the search ends up from `idx`, which goes on to the next match when a `till` search is already next to one.
+    fn find_again(&self, contents: &Rope, idx: usize) -> Option<usize> {
+        match self.find(contents, idx) {
+            Some(found) if found == idx && self.till => {
+                let next = match self.forward {
+                    true => contents.next_grapheme(idx),
+                    false => contents.previous_grapheme(idx),
+                };
+                self.find(contents, next)
+            }
+            found => found,
+        }
+    }
+}
+
+/// How much of the text between the two ends of a motion an operator is applied to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionKind {
+    /// Up to, but not including, the character that the motion ends on.
+    Exclusive,
+    /// Up to and including the character that the motion ends on.
+    Inclusive,
+    /// All of every line that the motion touches.
+    Linewise,
+}
+
+/// The kinds of character that words are made up of.
+///
+/// A word is a run of characters of the same class, so `foo.bar()` is made up of the words `foo`, `.`, `bar` and `()`.
+#[derive(Debug, PartialEq, Eq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Blank
+    } else if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+/// Determines whether `idx` is at the start of an empty line, which word motions treat as a word of its own.
+fn is_empty_line(contents: &Rope, idx: usize) -> bool {
+    contents.char_at(idx) == Some('\n') && matches!(contents.char_before(idx), None | Some('\n'))
+}
+
+/// Finds the start of the last character in the document, which is as far as a motion can go.
+fn last_char(contents: &Rope) -> usize {
+    contents.previous_grapheme(contents.len())
+}
+
+impl Motion {
+    /// Works out where the motion ends up when it's made from the byte index `idx` in `contents`.
+    pub fn apply(&self, contents: &Rope, idx: usize) -> usize {
+        match self {
+            Motion::NextWordStart => word_forward(contents, idx),
+            Motion::PreviousWordStart => word_backward(contents, idx),
+            Motion::WordEnd => word_end(contents, idx),
+            Motion::LineStart => line_start(contents, idx),
+            Motion::FirstNonBlank => first_non_blank(contents, idx),
+            Motion::LineEnd => line_end(contents, idx),
+            Motion::DocumentStart => first_non_blank(contents, 0),
+            // A trailing newline doesn't start another line, so the last line is the one holding the last character.
+            Motion::DocumentEnd => first_non_blank(contents, last_char(contents)),
+            Motion::ParagraphForward => paragraph_forward(contents, idx),
+            Motion::ParagraphBackward => paragraph_backward(contents, idx),
+            Motion::MatchingBracket => matching_bracket(contents, idx),
+            Motion::Find(search) => search.find(contents, idx).unwrap_or(idx),
+            Motion::RepeatFind(search) => search.find_again(contents, idx).unwrap_or(idx),
+        }
+    }
+
+    pub fn kind(&self) -> MotionKind {
+        match self {
+            Motion::WordEnd | Motion::LineEnd | Motion::MatchingBracket => MotionKind::Inclusive,
+            Motion::DocumentStart | Motion::DocumentEnd => MotionKind::Linewise,
+            Motion::Find(search) | Motion::RepeatFind(search) if search.forward => {
+                MotionKind::Inclusive
+            }
+            _ => MotionKind::Exclusive,
+        }
+    }
+
+    /// Works out where an operator over the motion should stop, when the last step of the motion went from `from` to `to`.
+    ///
+    /// Like in Vim, `dw` on the last word of a line doesn't take the line ending (or the indent of the next line) with it, and it takes the rest of the word when there isn't another one to go to.
+    pub fn operator_end(&self, contents: &Rope, from: usize, to: usize) -> usize {
+        match self {
+            Motion::NextWordStart if contents.line_of(to) > contents.line_of(from) => {
+                content_end(contents, contents.line_of(from))
+            }
+            Motion::NextWordStart if !is_word_start(contents, to) => {
+                content_end(contents, contents.line_of(to))
+            }
+            // With no empty line to stop at, `d}` takes the rest of the document.
+            Motion::ParagraphForward if !is_blank_line(contents, contents.line_of(to)) => {
+                contents.len()
+            }
+            _ => to,
+        }
+    }
+}
+
+/// Determines whether `idx` is at the start of a word, rather than in the middle of one.
+fn is_word_start(contents: &Rope, idx: usize) -> bool {
+    match (contents.char_before(idx), contents.char_at(idx)) {
+        (_, None) => false,
+        (None, Some(_)) => true,
+        (Some(before), Some(c)) => class(before) != class(c) || is_empty_line(contents, idx),
+    }
+}
+
+/// Finds the end of the word that `idx` is on, which `cw` changes up to instead of the start of the next word.
+pub fn end_of_word(contents: &Rope, idx: usize) -> usize {
+    let start = match contents.char_at(idx) {
+        Some(c) => class(c),
+        None => return idx,
+    };
+    let mut i = idx;
+    loop {
+        let next = contents.next_grapheme(i);
+        match contents.char_at(next) {
+            Some(c) if class(c) == start && !matches!(c, '\r' | '\n') => i = next,
+            _ => return i,
+        }
+    }
+}
+
+fn word_forward(contents: &Rope, idx: usize) -> usize {
+    let mut i = idx;
+
+    // Get off the word the cursor is on, or at least off the blank it's on.
+    match contents.char_at(i) {
+        Some(c) if class(c) != CharClass::Blank => {
+            let start = class(c);
+            while contents.char_at(i).is_some_and(|c| class(c) == start) {
+                i = contents.next_grapheme(i);
+            }
+        }
+        Some(_) => i = contents.next_grapheme(i),
+        None => return idx,
+    }
+
+    while contents.char_at(i).is_some_and(char::is_whitespace) {
+        if is_empty_line(contents, i) {
+            return i;
+        }
+        i = contents.next_grapheme(i);
+    }
+
+    if i >= contents.len() {
+        return last_char(contents);
+    }
+    i
+}
+
+fn word_backward(contents: &Rope, idx: usize) -> usize {
+    let mut i = idx;
+
+    // Step back over any blanks between the cursor and the previous word.
+    loop {
+        if i == 0 {
+            return i;
+        }
+        i = contents.previous_grapheme(i);
+        if !contents.char_at(i).is_some_and(char::is_whitespace) {
+            break;
+        }
+        if is_empty_line(contents, i) {
+            return i;
+        }
+    }
+
+    let start = class(contents.char_at(i).unwrap());
+    while i > 0 {
+        let previous = contents.previous_grapheme(i);
+        if !contents
+            .char_at(previous)
+            .is_some_and(|c| class(c) == start)
+        {
+            break;
+        }
+        i = previous;
+    }
+    i
+}
+
+fn word_end(contents: &Rope, idx: usize) -> usize {
+    if idx >= contents.len() {
+        return idx;
+    }
+    let mut i = contents.next_grapheme(idx);
+
+    while contents.char_at(i).is_some_and(char::is_whitespace) {
+        i = contents.next_grapheme(i);
+    }
+    let start = match contents.char_at(i) {
+        Some(c) => class(c),
+        None => return last_char(contents),
+    };
+
+    while contents.char_at(i).is_some() {
+        let next = contents.next_grapheme(i);
+        match contents.char_at(next) {
+            Some(c) if class(c) == start => i = next,
+            _ => break,
+        }
+    }
+    i
+}
+
+fn line_start(contents: &Rope, idx: usize) -> usize {
+    contents.line_start(contents.line_of(idx)).unwrap_or(0)
+}
+
+fn first_non_blank(contents: &Rope, idx: usize) -> usize {
+    let mut i = line_start(contents, idx);
+    while matches!(contents.char_at(i), Some(' ' | '\t')) {
+        i += 1;
+    }
+
+    // A line that's nothing but blanks leaves the cursor on the last of them.
+    cmp::min(i, line_end(contents, idx))
+}
+
+/// Finds where the text of a line ends, before its line ending.
+pub fn content_end(contents: &Rope, line: usize) -> usize {
+    let start = contents.line_start(line).unwrap_or(contents.len());
+    let end = contents.line_end(line).unwrap_or(contents.len());
+    match end > start && contents.char_before(end) == Some('\r') {
+        true => end - 1,
+        false => end,
+    }
+}
+
+/// Determines whether a line has nothing at all on it, which is what paragraphs are separated by.
+fn is_blank_line(contents: &Rope, line: usize) -> bool {
+    contents.line_start(line) == Some(content_end(contents, line))
+}
+
+fn paragraph_forward(contents: &Rope, idx: usize) -> usize {
+    let last = contents.line_of(last_char(contents));
+    let mut line = contents.line_of(idx);
+    while line < last && is_blank_line(contents, line) {
+        line += 1;
+    }
+    while line < last {
+        line += 1;
+        if is_blank_line(contents, line) {
+            return contents.line_start(line).unwrap_or(0);
+        }
+    }
+    last_char(contents)
+}
+
+fn paragraph_backward(contents: &Rope, idx: usize) -> usize {
+    let mut line = contents.line_of(idx);
+    while line > 0 && is_blank_line(contents, line) {
+        line -= 1;
+    }
+    while line > 0 {
+        line -= 1;
+        if is_blank_line(contents, line) {
+            return contents.line_start(line).unwrap_or(0);
+        }
+    }
+    0
+}
+
+/// Finds the bracket that pairs up with `c`, and whether it's further along the text.
+fn bracket_pair(c: char) -> Option<(char, bool)> {
+    match c {
+        '(' => Some((')', true)),
+        '[' => Some((']', true)),
+        '{' => Some(('}', true)),
+        ')' => Some(('(', false)),
+        ']' => Some(('[', false)),
+        '}' => Some(('{', false)),
+        _ => None,
+    }
+}
+
+fn matching_bracket(contents: &Rope, idx: usize) -> usize {
+    // Like in Vim, the bracket can be further along the line than the cursor.
+    let end = content_end(contents, contents.line_of(idx));
+    let mut i = idx;
+    loop {
+        if i >= end {
+            return idx;
+        }
+        match contents.char_at(i) {
+            Some(c) if bracket_pair(c).is_some() => break,
+            _ => i = contents.next_grapheme(i),
+        }
+    }
+    bracket_partner(contents, i, usize::MAX).unwrap_or(idx)
+}
+
+/// Finds the bracket that pairs up with the one at `idx`, if there is one there, giving up after looking through
+/// `limit` characters.
+pub fn bracket_partner(contents: &Rope, idx: usize, limit: usize) -> Option<usize> {
+    let bracket = contents.char_at(idx)?;
+    let (partner, forward) = bracket_pair(bracket)?;
+
+    let mut i = idx;
+    let mut depth = 0;
+    for _ in 0..limit {
+        match contents.char_at(i) {
+            Some(c) if c == bracket => depth += 1,
+            Some(c) if c == partner => depth -= 1,
+            None => return None,
+            _ => (),
+        }
+        if depth == 0 {
+            return Some(i);
+        }
+        i = match forward {
+            true => contents.next_grapheme(i),
+            false if i == 0 => return None,
+            false => contents.previous_grapheme(i),
+        };
+    }
+    None
+}
+
+/// Finds the start of the last character on the line, not counting its line ending.
+fn line_end(contents: &Rope, idx: usize) -> usize {
+    let line = contents.line_of(idx);
+    let start = contents.line_start(line).unwrap_or(0);
+    let end = content_end(contents, line);
+
+    match end > start {
+        true => contents.previous_grapheme(end),
+        false => start,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Makes a motion repeatedly from the start of `s`, collecting every index it stops at.
+    fn stops(motion: Motion, s: &str, from: usize) -> Vec<usize> {
+        let contents = Rope::from(s);
+        let mut stops = Vec::new();
+        let mut idx = from;
+        loop {
+            let next = motion.apply(&contents, idx);
+            if next == idx {
+                return stops;
+            }
+            stops.push(next);
+            idx = next;
+        }
+    }
+
+    #[test]
+    fn test_word_forward() {
+        assert_eq!(
+            stops(Motion::NextWordStart, "foo.bar(baz)  qux", 0),
+            vec![3, 4, 7, 8, 11, 14, 16]
+        );
+    }
+
+    #[test]
+    fn test_word_forward_across_lines() {
+        assert_eq!(
+            stops(Motion::NextWordStart, "foo\n  bar\n\nbaz", 0),
+            vec![6, 10, 11, 13]
+        );
+    }
+
+    #[test]
+    fn test_word_backward() {
+        assert_eq!(
+            stops(Motion::PreviousWordStart, "foo.bar(baz)  qux", 16),
+            vec![14, 11, 8, 7, 4, 3, 0]
+        );
+        assert_eq!(
+            stops(Motion::PreviousWordStart, "foo\n  bar\n\nbaz", 12),
+            vec![11, 10, 6, 0]
+        );
+    }
+
+    #[test]
+    fn test_word_end() {
+        assert_eq!(
+            stops(Motion::WordEnd, "foo.bar(baz)  qux", 0),
+            vec![2, 3, 6, 7, 10, 11, 16]
+        );
+        assert_eq!(stops(Motion::WordEnd, "a\n\n  bc", 0), vec![6]);
+    }
+
+    #[test]
+    fn test_line_motions() {
+        let contents = Rope::from("abc\n  \tdéf\r\n\n   ");
+        assert_eq!(Motion::LineStart.apply(&contents, 2), 0);
+        assert_eq!(Motion::LineStart.apply(&contents, 10), 4);
+        assert_eq!(Motion::FirstNonBlank.apply(&contents, 4), 7);
+        assert_eq!(Motion::FirstNonBlank.apply(&contents, 10), 7);
+        assert_eq!(Motion::LineEnd.apply(&contents, 0), 2);
+        assert_eq!(Motion::LineEnd.apply(&contents, 4), 10);
+    }
+
+    #[test]
+    fn test_line_motions_on_empty_and_blank_lines() {
+        let contents = Rope::from("abc\n\n   ");
+        assert_eq!(Motion::LineStart.apply(&contents, 4), 4);
+        assert_eq!(Motion::FirstNonBlank.apply(&contents, 4), 4);
+        assert_eq!(Motion::LineEnd.apply(&contents, 4), 4);
+        assert_eq!(Motion::FirstNonBlank.apply(&contents, 5), 7);
+        assert_eq!(Motion::LineEnd.apply(&contents, 5), 7);
+    }
+
+    #[test]
+    fn test_document_motions() {
+        let contents = Rope::from("  abc\ndef\n\tghi\n");
+        assert_eq!(Motion::DocumentStart.apply(&contents, 12), 2);
+        assert_eq!(Motion::DocumentEnd.apply(&contents, 0), 11);
+        assert_eq!(Motion::DocumentEnd.apply(&Rope::from(""), 0), 0);
+    }
+
+    #[test]
+    fn test_paragraph_motions() {
+        let s = "a\nb\n\n\nc\n\nd";
+        assert_eq!(stops(Motion::ParagraphForward, s, 0), vec![4, 8, 9]);
+        assert_eq!(stops(Motion::ParagraphBackward, s, 9), vec![8, 5, 0]);
+
+        let contents = Rope::from("a\nb\n\nc");
+        let forward = Motion::ParagraphForward;
+        assert_eq!(forward.operator_end(&contents, 0, 4), 4);
+        assert_eq!(forward.operator_end(&contents, 0, 5), 6);
+    }
+
+    #[test]
+    fn test_matching_brackets() {
+        let contents = Rope::from("f(a[0], {b}) x\n(");
+        assert_eq!(Motion::MatchingBracket.apply(&contents, 1), 11);
+        assert_eq!(Motion::MatchingBracket.apply(&contents, 11), 1);
+        assert_eq!(Motion::MatchingBracket.apply(&contents, 3), 5);
+        assert_eq!(Motion::MatchingBracket.apply(&contents, 10), 8);
+        // The cursor can be before the bracket on the line.
+        assert_eq!(Motion::MatchingBracket.apply(&contents, 0), 11);
+        assert_eq!(Motion::MatchingBracket.apply(&contents, 12), 12);
+        assert_eq!(Motion::MatchingBracket.apply(&contents, 15), 15);
+
+        // Only a bracket right at the index has a partner, and only if it's found soon enough.
+        assert_eq!(bracket_partner(&contents, 11, 100), Some(1));
+        assert_eq!(bracket_partner(&contents, 0, 100), None);
+        assert_eq!(bracket_partner(&contents, 15, 100), None);
+        assert_eq!(bracket_partner(&contents, 1, 11), Some(11));
+        assert_eq!(bracket_partner(&contents, 1, 10), None);
+    }
+
+    #[test]
+    fn test_finding_chars() {
+        let search = |target, forward, till| CharSearch {
+            target,
+            forward,
+            till,
+        };
+        assert_eq!(
+            stops(Motion::Find(search('a', true, false)), "xaxaé\nxa", 0),
+            vec![1, 3]
+        );
+        assert_eq!(
+            stops(Motion::Find(search('a', false, false)), "xa\naxaé", 8),
+            vec![5, 3]
+        );
+        assert_eq!(
+            stops(Motion::Find(search('é', true, false)), "xéxé", 0),
+            vec![1, 4]
+        );
+
+        // Going up to a character gets stuck next to it, unless the search is being repeated.
+        let till = search('a', true, true);
+        assert_eq!(stops(Motion::Find(till), "xxaxa", 0), vec![1]);
+        assert_eq!(stops(Motion::RepeatFind(till), "xxaxa", 0), vec![1, 3]);
+        let till = till.reversed();
+        assert_eq!(stops(Motion::RepeatFind(till), "axaxx", 4), vec![3, 1]);
+        assert_eq!(till.find(&Rope::from("abc"), 0), None);
+    }
+
+    #[test]
+    fn test_word_motion_under_operators() {
+        let contents = Rope::from("foo bar\n  baz");
+        let w = Motion::NextWordStart;
+        assert_eq!(w.operator_end(&contents, 0, 4), 4);
+        assert_eq!(w.operator_end(&contents, 4, 10), 7);
+        assert_eq!(w.operator_end(&contents, 10, 12), 13);
+        assert_eq!(end_of_word(&contents, 0), 2);
+        assert_eq!(end_of_word(&contents, 2), 2);
+        assert_eq!(end_of_word(&contents, 3), 3);
+    }
+
+    #[test]
+    fn test_words_with_multibyte_chars() {
+        assert_eq!(stops(Motion::NextWordStart, "café olé", 0), vec![6, 8]);
+        assert_eq!(stops(Motion::WordEnd, "café olé", 0), vec![3, 8]);
+        assert_eq!(stops(Motion::PreviousWordStart, "café olé", 8), vec![6, 0]);
+    }
+
+    #[test]
+    fn test_words_with_combining_marks() {
+        let s = "cafe\u{301} ole\u{301}";
+        assert_eq!(stops(Motion::NextWordStart, s, 0), vec![7, 9]);
+        assert_eq!(stops(Motion::WordEnd, s, 0), vec![3, 9]);
+        assert_eq!(stops(Motion::PreviousWordStart, s, 9), vec![7, 0]);
+        assert_eq!(Motion::LineEnd.apply(&Rope::from(s), 0), 9);
+    }
+}
@@ -0,0 +1,114 @@
+//! Adding to and subtracting from the numbers in a line of text, so that `Ctrl-A` and `Ctrl-X` can count them up and down.
+use std::ops::Range;
+
+/// Finds the number under or after the `column` of a line, and works out what it becomes with `delta` added to it.
+///
+/// Hexadecimal numbers start with `0x` and keep their digits' case, while decimal numbers take a `-` in front of them as their
+/// sign. Either kind keeps any leading zeros, so `007` goes up to `008`. Gives the range of the line to replace along with the
+/// text to put there, or `None` if there isn't a number to change.
+pub fn add(line: &str, column: usize, delta: i64) -> Option<(Range<usize>, String)> {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let is_hex = bytes[i] == b'0'
+            && matches!(bytes.get(i + 1), Some(b'x' | b'X'))
+            && bytes.get(i + 2).is_some_and(u8::is_ascii_hexdigit);
+        let (digits, hex) = match (is_hex, bytes[i].is_ascii_digit()) {
+            (true, _) => (i + 2..run_end(bytes, i + 2, u8::is_ascii_hexdigit), true),
+            (false, true) => (i..run_end(bytes, i, u8::is_ascii_digit), false),
+            (false, false) => {
+                i += 1;
+                continue;
+            }
+        };
+        if digits.end <= column {
+            i = digits.end;
+            continue;
+        }
+        return match hex {
+            true => add_hex(&line[digits.clone()], delta).map(|text| (digits, text)),
+            false => add_decimal(line, digits, delta),
+        };
+    }
+    None
+}
+
+/// Finds where a run of bytes that all pass `test` ends, starting from `start`.
+fn run_end(bytes: &[u8], start: usize, test: fn(&u8) -> bool) -> usize {
+    start + bytes[start..].iter().take_while(|byte| test(byte)).count()
+}
+
+/// Adds to the hexadecimal `digits` of a number, wrapping around rather than going negative.
+fn add_hex(digits: &str, delta: i64) -> Option<String> {
+    let value = u64::from_str_radix(digits, 16).ok()?;
+    let value = value.wrapping_add(delta as u64);
+    let width = digits.len();
+    match digits.chars().any(|c| c.is_ascii_uppercase()) {
+        true => Some(format!("{:0width$X}", value)),
+        false => Some(format!("{:0width$x}", value)),
+    }
+}
+
+/// Adds to a decimal number in a line, taking in the `-` before its `digits` if there is one.
+fn add_decimal(line: &str, digits: Range<usize>, delta: i64) -> Option<(Range<usize>, String)> {
+    let negative = line[..digits.start].ends_with('-');
+    let start = digits.start - usize::from(negative);
+    let magnitude: i128 = line[digits.clone()].parse().ok()?;
+    // A number that's already as big as they get stays there, rather than wrapping around to the other end.
+    let value = match negative {
+        true => -magnitude,
+        false => magnitude,
+    }
+    .saturating_add(i128::from(delta));
+
+    // Only a number written with leading zeros is kept at the same width.
+    let width = match line[digits.clone()].starts_with('0') {
+        true => digits.len(),
+        false => 0,
+    };
+    let sign = if value < 0 { "-" } else { "" };
+    let text = format!("{}{:0width$}", sign, value.unsigned_abs());
+    Some((start..digits.end, text))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decimal() {
+        assert_eq!(add("x = 41;", 0, 1), Some((4..6, "42".to_string())));
+        assert_eq!(add("x = 41;", 5, -50), Some((4..6, "-9".to_string())));
+        assert_eq!(add("x = -3", 0, 5), Some((4..6, "2".to_string())));
+        assert_eq!(add("007", 1, 1), Some((0..3, "008".to_string())));
+        assert_eq!(add("-01", 0, -9), Some((0..3, "-10".to_string())));
+        // The cursor picks the number it's on, or the next one after it.
+        assert_eq!(add("1 2 3", 1, 1), Some((2..3, "3".to_string())));
+        assert_eq!(add("1 2 3", 5, 1), None);
+        assert_eq!(add("no numbers", 0, 1), None);
+    }
+
+    #[test]
+    fn test_decimal_limits() {
+        let max = i128::MAX.to_string();
+        assert_eq!(add(&max, 0, i64::MAX), Some((0..max.len(), max.clone())));
+        let min = i128::MIN.to_string();
+        let above_min = (i128::MIN + 1).to_string();
+        assert_eq!(add(&above_min, 0, -5), Some((0..min.len(), min)));
+        // Past the biggest magnitude there is, it isn't a number that can be counted.
+        assert_eq!(add(&format!("{}0", max), 0, 1), None);
+    }
+
+    #[test]
+    fn test_hexadecimal() {
+        assert_eq!(add("0xff", 0, 1), Some((2..4, "100".to_string())));
+        assert_eq!(add("0x0F", 3, 1), Some((2..4, "10".to_string())));
+        assert_eq!(add("a 0x00ab", 1, -1), Some((4..8, "00aa".to_string())));
+        assert_eq!(
+            add("0x0", 0, -1),
+            Some((2..3, "ffffffffffffffff".to_string()))
+        );
+        // Without any digits after it, the `0` is just a decimal number.
+        assert_eq!(add("0x", 0, 1), Some((0..1, "1".to_string())));
+    }
+}
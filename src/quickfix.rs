@@ -0,0 +1,184 @@
+//! The list of errors picked out of a compiler's output, which `:cn` and `:cp` step through.
+use std::path::{Path, PathBuf};
+
+/// An error (or warning) that a compiler reported at some place in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// The line that the error is on, counted from zero.
+    pub line: usize,
+    /// The column that the error starts at, counted from zero, or zero when the compiler didn't say.
+    pub column: usize,
+    pub message: String,
+}
+
+/// The errors from the last `:make` or `:cfile`, and which of them the user has got to.
+#[derive(Debug, Default)]
+pub struct QuickfixList {
+    entries: Vec<Entry>,
+    /// The error that was jumped to last, if any have been yet.
+    index: Option<usize>,
+}
+
+impl QuickfixList {
+    /// Picks the errors out of a compiler's output, ignoring any lines that don't point at a place in a file.
+    ///
+    /// Lines like `file:line:col: message` (with or without the column) are understood, and so are the `--> file:line:col`
+    /// lines that Rust puts under the message they belong to.
+    pub fn parse(output: &str) -> Self {
+        let mut entries = Vec::new();
+        let mut heading = None;
+        for line in output.lines() {
+            if let Some(location) = line.trim_start().strip_prefix("--> ") {
+                if let Some(mut entry) = parse_location(location) {
+                    entry.message = heading.take().unwrap_or_default();
+                    entries.push(entry);
+                }
+            } else if line.starts_with("error") || line.starts_with("warning") {
+                heading = Some(line.to_string());
+            } else if let Some(entry) = parse_location(line) {
+                entries.push(entry);
+            }
+        }
+        Self {
+            entries,
+            index: None,
+        }
+    }
+
+    /// Changes the paths of the files that the errors are in, like when the working directory changes.
+    pub fn relocate(&mut self, relocate: impl Fn(&Path) -> PathBuf) {
+        for entry in &mut self.entries {
+            entry.path = relocate(&entry.path);
+        }
+    }
+
+    /// The number of errors in the list.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// All of the errors, in the order that the compiler reported them.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Whether the compiler's output didn't have any errors in it.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Moves on to the next error, or the first one if none have been jumped to yet, returning it along with its index.
+    pub fn next(&mut self) -> Option<(usize, &Entry)> {
+        let index = self.index.map_or(0, |i| i + 1);
+        self.select(index)
+    }
+
+    /// Moves back to the previous error, returning it along with its index.
+    pub fn previous(&mut self) -> Option<(usize, &Entry)> {
+        let index = self.index?.checked_sub(1)?;
+        self.select(index)
+    }
+
+    /// Makes the error at `index` the current one, if there is one there.
+    fn select(&mut self, index: usize) -> Option<(usize, &Entry)> {
+        let entry = self.entries.get(index)?;
+        self.index = Some(index);
+        Some((index, entry))
+    }
+}
+
+/// Parses `file:line:col: message`, or `file:line: message`, into an `Entry`.
+///
+/// Lines and columns are counted from one in the output, like compilers do.
+fn parse_location(line: &str) -> Option<Entry> {
+    let mut parts = line.splitn(4, ':');
+    let path = parts.next().filter(|path| !path.trim().is_empty())?;
+    let line_number: usize = parts.next()?.trim().parse().ok()?;
+    let rest: Vec<&str> = parts.collect();
+    let (column, message) = match rest.first().map(|part| part.trim().parse::<usize>()) {
+        Some(Ok(column)) => (column, rest.get(1).copied().unwrap_or("")),
+        _ => (1, line.splitn(3, ':').nth(2).unwrap_or("")),
+    };
+    Some(Entry {
+        path: PathBuf::from(path.trim()),
+        line: line_number.saturating_sub(1),
+        column: column.saturating_sub(1),
+        message: message.trim().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(path: &str, line: usize, column: usize, message: &str) -> Entry {
+        Entry {
+            path: PathBuf::from(path),
+            line,
+            column,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parsing_locations() {
+        assert_eq!(
+            parse_location("src/main.c:12:5: error: expected ';'"),
+            Some(entry("src/main.c", 11, 4, "error: expected ';'"))
+        );
+        assert_eq!(
+            parse_location("script.py:3: undefined name"),
+            Some(entry("script.py", 2, 0, "undefined name"))
+        );
+        assert_eq!(parse_location("a.txt:7:"), Some(entry("a.txt", 6, 0, "")));
+        assert_eq!(parse_location("make: *** [all] Error 1"), None);
+        assert_eq!(parse_location(":1:2: nothing"), None);
+        assert_eq!(parse_location("no location here"), None);
+    }
+
+    #[test]
+    fn test_parsing_rust_output() {
+        let output = "\
+error[E0425]: cannot find value `x` in this scope
+  --> src/main.rs:4:13
+   |
+4  |     let y = x;
+   |             ^ not found in this scope
+
+warning: unused variable: `y`
+ --> src/lib.rs:2:9
+";
+        let mut list = QuickfixList::parse(output);
+        assert_eq!(list.len(), 2);
+        assert_eq!(
+            list.next(),
+            Some((
+                0,
+                &entry(
+                    "src/main.rs",
+                    3,
+                    12,
+                    "error[E0425]: cannot find value `x` in this scope"
+                )
+            ))
+        );
+        assert_eq!(
+            list.next().map(|(_, entry)| entry.message.clone()),
+            Some("warning: unused variable: `y`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_stepping_through_errors() {
+        let mut list = QuickfixList::parse("a:1: one\nb:2: two\n");
+        assert_eq!(list.previous(), None);
+        assert_eq!(list.next().map(|(i, _)| i), Some(0));
+        assert_eq!(list.next().map(|(i, _)| i), Some(1));
+        assert_eq!(list.next(), None);
+        assert_eq!(list.previous().map(|(i, _)| i), Some(0));
+        assert_eq!(list.previous(), None);
+
+        assert!(QuickfixList::parse("all good\n").is_empty());
+    }
+}
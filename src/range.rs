@@ -0,0 +1,135 @@
+//! Spans of the document that operators are applied to, whether they come from a motion or from the selection in Visual mode.
+use std::cmp;
+use std::ops::Range;
+
+use crate::buffer::Rope;
+use crate::motions::{self, Motion, MotionKind};
+
+/// A span of the document for an operator to delete, yank or change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextRange {
+    /// The byte indices that are covered.
+    pub range: Range<usize>,
+    /// Whether the span is made up of whole lines, which get put back as lines of their own.
+    pub linewise: bool,
+}
+
+impl TextRange {
+    /// Covers the text that a motion of the given kind moved over, going from the byte index `from` to `to` (in either direction.)
+    pub fn from_motion(contents: &Rope, from: usize, to: usize, kind: MotionKind) -> Self {
+        let start = cmp::min(from, to);
+        let end = cmp::max(from, to);
+        match kind {
+            MotionKind::Linewise => {
+                Self::lines(contents, contents.line_of(start), contents.line_of(end))
+            }
+            // Like in Vim, an exclusive motion that ends at the start of a line stops at the end of the line before it instead.
+            // If it started before the text on its first line too, it takes in the whole of the lines (so `d}` deletes the paragraph.)
+            MotionKind::Exclusive
+                if end > start
+                    && contents.line_of(end) > contents.line_of(start)
+                    && contents.line_start(contents.line_of(end)) == Some(end) =>
+            {
+                let last_line = contents.line_of(end) - 1;
+                if start <= Motion::FirstNonBlank.apply(contents, start) {
+                    return Self::lines(contents, contents.line_of(start), last_line);
+                }
+                let end = motions::content_end(contents, last_line);
+                Self::characters(start..cmp::max(start, end))
+            }
+            MotionKind::Exclusive => Self::characters(start..end),
+            // The character that an inclusive motion ends on is never a line ending, even on an empty line.
+            MotionKind::Inclusive => match contents.char_at(end) {
+                Some('\r' | '\n') | None => Self::characters(start..end),
+                Some(_) => Self::characters(start..contents.next_grapheme(end)),
+            },
+        }
+    }
+
+    /// Covers the selection in Visual mode, which includes the characters under both the `anchor` and the `cursor`.
+    pub fn selection(contents: &Rope, anchor: usize, cursor: usize) -> Self {
+        let start = cmp::min(anchor, cursor);
+        let last = cmp::max(anchor, cursor);
+        Self::characters(start..contents.next_grapheme(last))
+    }
+
+    /// Covers the whole of the lines from `first` to `last`, along with their line endings.
+    ///
+    /// The last line has no line ending of its own, so it takes the one from the line before it instead.
+    pub fn lines(contents: &Rope, first: usize, last: usize) -> Self {
+        let mut start = contents.line_start(first).unwrap_or(contents.len());
+        let end = contents.line_start(last + 1).unwrap_or(contents.len());
+        if start > 0 && end == contents.len() && contents.char_before(end) != Some('\n') {
+            start -= 1;
+            if start > 0 && contents.char_before(start) == Some('\r') {
+                start -= 1;
+            }
+        }
+        Self {
+            range: start..end,
+            linewise: true,
+        }
+    }
+
+    fn characters(range: Range<usize>) -> Self {
+        Self {
+            range,
+            linewise: false,
+        }
+    }
+
+    /// Finds the text in the range without any line endings around whole lines, so that changing them leaves an empty line to type into.
+    pub fn without_line_ending(&self, contents: &Rope) -> Range<usize> {
+        if !self.linewise || self.range.is_empty() {
+            return self.range.clone();
+        }
+
+        let mut first = contents.line_of(self.range.start);
+        if contents.line_start(first) != Some(self.range.start) {
+            first += 1;
+        }
+        let start = contents.line_start(first).unwrap_or(self.range.start);
+        let end = motions::content_end(contents, contents.line_of(self.range.end - 1));
+        start..cmp::max(start, end)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ranges_from_motions() {
+        let contents = Rope::from("foo bar\n\nbaz\n");
+        let range = |from, to, kind| TextRange::from_motion(&contents, from, to, kind).range;
+        assert_eq!(range(0, 4, MotionKind::Exclusive), 0..4);
+        assert_eq!(range(4, 0, MotionKind::Exclusive), 0..4);
+        assert_eq!(range(0, 2, MotionKind::Inclusive), 0..3);
+        assert_eq!(range(4, 8, MotionKind::Inclusive), 4..8);
+        assert_eq!(range(4, 9, MotionKind::Exclusive), 4..8);
+        assert_eq!(range(4, 8, MotionKind::Linewise), 0..9);
+        assert_eq!(range(9, 12, MotionKind::Linewise), 9..13);
+        // Starting at the start of a line and ending at the start of another takes in whole lines.
+        assert_eq!(range(0, 9, MotionKind::Exclusive), 0..9);
+    }
+
+    #[test]
+    fn test_last_line_takes_line_ending_before_it() {
+        let contents = Rope::from("foo\r\nbar");
+        let lines = TextRange::lines(&contents, 1, 1);
+        assert_eq!(lines.range, 3..8);
+        assert_eq!(lines.without_line_ending(&contents), 5..8);
+        assert_eq!(
+            TextRange::lines(&contents, 0, 0).without_line_ending(&contents),
+            0..3
+        );
+        assert_eq!(TextRange::lines(&contents, 0, 1).range, 0..8);
+    }
+
+    #[test]
+    fn test_selection_includes_both_ends() {
+        let contents = Rope::from("héllo");
+        assert_eq!(TextRange::selection(&contents, 4, 1).range, 1..5);
+        assert_eq!(TextRange::selection(&contents, 0, 0).range, 0..1);
+    }
+}
@@ -0,0 +1,305 @@
+//! Vim's flavour of regular expressions, for finding the text that `:s` replaces.
+//!
+//! Patterns are written the way Vim writes them: `\(` and `\)` make a group, `\|` separates the alternatives, and `\+`, `\=`
+//! and `\{n,m}` repeat things, while `(`, `|`, `+` and `{` on their own are just characters. They're translated into the syntax
+//! of the `regex` crate, which does the matching.
+//! Patterns are matched against a line at a time, so `^` and `$` are the start and end of the text being searched.
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::str::Chars;
+
+/// The ways in which a pattern can fail to be a regular expression.
+#[derive(Debug)]
+pub enum RegexError {
+    /// A `\` at the very end of the pattern.
+    TrailingBackslash,
+    /// A `\{` without a `}`, or with something other than the counts in it.
+    InvalidRepetition,
+    /// A pattern that the `regex` crate won't take, like one with a `\(` but no `\)`.
+    Invalid(::regex::Error),
+}
+
+impl Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegexError::TrailingBackslash => write!(f, "trailing \\"),
+            RegexError::InvalidRepetition => write!(f, "invalid \\{{}}"),
+            // The crate's message draws the translated pattern with the problem underlined, then says what it is on the last line.
+            RegexError::Invalid(::regex::Error::Syntax(message)) => {
+                let problem = message.lines().last().unwrap_or("");
+                write!(f, "{}", problem.strip_prefix("error: ").unwrap_or(problem))
+            }
+            RegexError::Invalid(_) => write!(f, "pattern is too big"),
+        }
+    }
+}
+
+impl Error for RegexError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RegexError::Invalid(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A compiled regular expression.
+#[derive(Debug)]
+pub struct Regex {
+    pattern: String,
+    regex: ::regex::Regex,
+}
+
+/// The parts of some text that were matched by a regular expression and its groups.
+#[derive(Debug)]
+pub struct Captures<'t>(::regex::Captures<'t>);
+
+impl Captures<'_> {
+    /// Finds the text matched by a group, where group 0 is the whole match.
+    pub fn get(&self, group: usize) -> Option<Range<usize>> {
+        self.0.get(group).map(|found| found.range())
+    }
+}
+
+impl Regex {
+    /// Compiles a pattern.
+    pub fn new(pattern: &str) -> Result<Self, RegexError> {
+        let regex = ::regex::Regex::new(&translate(pattern)?).map_err(RegexError::Invalid)?;
+        Ok(Self {
+            pattern: pattern.to_string(),
+            regex,
+        })
+    }
+
+    /// The pattern that the regular expression was compiled from.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Finds the first match in `text` that starts at or after the byte index `start`.
+    pub fn captures_at<'t>(&self, text: &'t str, start: usize) -> Option<Captures<'t>> {
+        self.regex.captures_at(text, start).map(Captures)
+    }
+
+    /// Finds the first match in `text`.
+    #[cfg(test)]
+    pub fn find(&self, text: &str) -> Option<Range<usize>> {
+        self.captures_at(text, 0)
+            .and_then(|captures| captures.get(0))
+    }
+}
+
+/// Translates a pattern written the way Vim writes it into the syntax of the `regex` crate.
+fn translate(pattern: &str) -> Result<String, RegexError> {
+    let mut translated = String::new();
+    let mut chars = pattern.chars();
+    // Like in Vim, `^` is only an anchor at the start of an alternative, and `*` is just a star there.
+    let mut at_start = true;
+    while let Some(c) = chars.next() {
+        let start = at_start;
+        at_start = false;
+        match c {
+            '\\' => match chars.next().ok_or(RegexError::TrailingBackslash)? {
+                '(' => {
+                    translated.push('(');
+                    at_start = true;
+                }
+                '%' if chars.as_str().starts_with('(') => {
+                    chars.next();
+                    translated.push_str("(?:");
+                    at_start = true;
+                }
+                '|' => {
+                    translated.push('|');
+                    at_start = true;
+                }
+                ')' => translated.push(')'),
+                '+' => translated.push('+'),
+                '=' | '?' => translated.push('?'),
+                '{' => translated.push_str(&repetition(&mut chars)?),
+                '<' => translated.push_str("\\b{start}"),
+                '>' => translated.push_str("\\b{end}"),
+                c @ ('d' | 'D' | 'w' | 'W' | 's' | 'S' | 'n' | 't' | 'r') => {
+                    translated.push('\\');
+                    translated.push(c);
+                }
+                'a' => translated.push_str("[a-zA-Z]"),
+                'A' => translated.push_str("[^a-zA-Z]"),
+                'l' => translated.push_str("[a-z]"),
+                'L' => translated.push_str("[^a-z]"),
+                'u' => translated.push_str("[A-Z]"),
+                'U' => translated.push_str("[^A-Z]"),
+                'x' => translated.push_str("[0-9a-fA-F]"),
+                'X' => translated.push_str("[^0-9a-fA-F]"),
+                'e' => translated.push_str("\\x1b"),
+                c => push_literal(&mut translated, c),
+            },
+            '^' if start => {
+                translated.push('^');
+                at_start = true;
+            }
+            // And `$` is only an anchor at the end of one.
+            '$' if is_end(chars.as_str()) => translated.push('$'),
+            '*' if !start => translated.push('*'),
+            '.' => translated.push('.'),
+            '[' => match bracket(&mut chars) {
+                Some(class) => translated.push_str(&class),
+                None => push_literal(&mut translated, '['),
+            },
+            c => push_literal(&mut translated, c),
+        }
+    }
+    Ok(translated)
+}
+
+/// Determines whether what's left of a pattern starts after the end of an alternative.
+fn is_end(rest: &str) -> bool {
+    rest.is_empty() || rest.starts_with("\\|") || rest.starts_with("\\)")
+}
+
+/// Adds a character that only stands for itself.
+fn push_literal(translated: &mut String, c: char) {
+    translated.push_str(&::regex::escape(c.encode_utf8(&mut [0; 4])));
+}
+
+/// Translates the counts of a `\{n,m}` (or of a `\{-n,m}`, which matches as few times as it can), from just after the `\{`.
+fn repetition(chars: &mut Chars) -> Result<String, RegexError> {
+    let rest = chars.as_str();
+    let end = rest.find('}').ok_or(RegexError::InvalidRepetition)?;
+    // Vim lets the `}` have a `\` in front of it too.
+    let counts = rest[..end].strip_suffix('\\').unwrap_or(&rest[..end]);
+    *chars = rest[end + 1..].chars();
+
+    let (lazy, counts) = match counts.strip_prefix('-') {
+        Some(counts) => (true, counts),
+        None => (false, counts),
+    };
+    if !counts.chars().all(|c| c.is_ascii_digit() || c == ',') || counts.matches(',').count() > 1 {
+        return Err(RegexError::InvalidRepetition);
+    }
+    let repetition = match counts.split_once(',') {
+        None if counts.is_empty() => "*".to_string(),
+        None => format!("{{{}}}", counts),
+        Some(("", max)) => format!("{{0,{}}}", max),
+        Some((min, max)) => format!("{{{},{}}}", min, max),
+    };
+    Ok(match lazy {
+        true => repetition + "?",
+        false => repetition,
+    })
+}
+
+/// Translates a `[...]`, from just after the `[`.
+///
+/// If there's no `]` to end it then nothing is taken, and the `[` is just a character like it is in Vim.
+fn bracket(chars: &mut Chars) -> Option<String> {
+    let mut rest = chars.clone();
+    let mut class = String::from("[");
+    if rest.as_str().starts_with('^') {
+        rest.next();
+        class.push('^');
+    }
+    // A `]` straight after the `[` is one of the characters, rather than the end.
+    if rest.as_str().starts_with(']') {
+        rest.next();
+        class.push_str("\\]");
+    }
+    loop {
+        match rest.next()? {
+            ']' => break,
+            '\\' => match rest.as_str().chars().next() {
+                Some(c @ ('\\' | ']' | '^' | '-')) => {
+                    rest.next();
+                    class.push('\\');
+                    class.push(c);
+                }
+                Some(c @ ('n' | 't' | 'r')) => {
+                    rest.next();
+                    class.push('\\');
+                    class.push(c);
+                }
+                Some('e') => {
+                    rest.next();
+                    class.push_str("\\x1b");
+                }
+                // Any other `\` is just a backslash.
+                _ => class.push_str("\\\\"),
+            },
+            // These would start a nested class or a set operation in the `regex` crate's syntax.
+            c @ ('[' | '&' | '~') => {
+                class.push('\\');
+                class.push(c);
+            }
+            '-' if rest.as_str().starts_with('-') => class.push_str("\\-"),
+            c => class.push(c),
+        }
+    }
+    class.push(']');
+    *chars = rest;
+    Some(class)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<Range<usize>> {
+        Regex::new(pattern).unwrap().find(text)
+    }
+
+    #[test]
+    fn test_translating_patterns() {
+        assert_eq!(translate("\\(a\\|b\\)\\+").unwrap(), "(a|b)+");
+        assert_eq!(translate("(a|b)+?{1}").unwrap(), "\\(a\\|b\\)\\+\\?\\{1\\}");
+        assert_eq!(
+            translate("a\\{2,}b\\{,3}c\\{-1,2}d\\{}").unwrap(),
+            "a{2,}b{0,3}c{1,2}?d*"
+        );
+        assert_eq!(translate("^*a^$b$").unwrap(), "^\\*a\\^\\$b$");
+        assert_eq!(translate("[]a-z&[]").unwrap(), "[\\]a-z\\&\\[]");
+        assert_eq!(translate("a[b").unwrap(), "a\\[b");
+        assert_eq!(
+            translate("\\<\\w\\>\\.").unwrap(),
+            "\\b{start}\\w\\b{end}\\."
+        );
+    }
+
+    #[test]
+    fn test_matching() {
+        assert_eq!(find("b.d", "abcd"), Some(1..4));
+        assert_eq!(find("a\\.b", "axb a.b"), Some(4..7));
+        assert_eq!(find("é.", "caféx"), Some(3..6));
+        assert_eq!(find("[0-9]\\+", "abc 123 def"), Some(4..7));
+        assert_eq!(find("colou\\=r", "color colour"), Some(0..5));
+        assert_eq!(find("cat\\|dog", "hotdog"), Some(3..6));
+        assert_eq!(find("f(x)", "f(x)"), Some(0..4));
+        assert_eq!(find("\\<cat\\>", "concat cat"), Some(7..10));
+        assert_eq!(find("a$\\|^b", "cba"), Some(2..3));
+
+        let regex = Regex::new("\\(\\w\\+\\)=\\(\\d\\+\\)\\=").unwrap();
+        let captures = regex.captures_at("set x=", 0).unwrap();
+        assert_eq!(captures.get(0), Some(4..6));
+        assert_eq!(captures.get(1), Some(4..5));
+        assert_eq!(captures.get(2), None);
+
+        // Starting part of the way through still sees what came before, so `^` doesn't match there.
+        let regex = Regex::new("^a\\|b").unwrap();
+        assert_eq!(regex.captures_at("abab", 1).unwrap().get(0), Some(1..2));
+        assert_eq!(regex.captures_at("abab", 2).unwrap().get(0), Some(3..4));
+        assert!(regex.captures_at("abab", 4).is_none());
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        let error = |pattern| Regex::new(pattern).unwrap_err().to_string();
+        assert_eq!(error("a\\"), "trailing \\");
+        assert_eq!(error("a\\{2"), "invalid \\{}");
+        assert_eq!(error("a\\{x}"), "invalid \\{}");
+        assert_eq!(error("\\(a"), "unclosed group");
+        assert_eq!(
+            error("[z-a]"),
+            "invalid character class range, the start must be <= the end"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! Somewhere to keep text that has been yanked or deleted, so that it can be put back later.
+use std::iter;
+
+/// Some text that has been yanked or deleted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Register {
+    pub text: String,
+    /// Whether the text is made up of whole lines, which are put back as lines of their own rather than in the middle of one.
+    pub linewise: bool,
+    /// Whether the text is a block cut out of a rectangle of lines (one line of it each), which are put back into the
+    /// same columns of the lines below each other.
+    pub blockwise: bool,
+}
+
+impl Register {
+    pub fn new(text: String, linewise: bool) -> Self {
+        Self {
+            text,
+            linewise,
+            blockwise: false,
+        }
+    }
+
+    /// Holds the pieces of a block selection, one from each line, with a line ending between each of them.
+    pub fn block(pieces: &[String]) -> Self {
+        Self {
+            text: pieces.join("\n"),
+            linewise: false,
+            blockwise: true,
+        }
+    }
+}
+
+/// The registers that yanked and deleted text is stored in.
+///
+/// Like in Vim, the named registers `a` to `z` hold onto text until something else is stored in them, and storing into `A` to `Z` adds onto the end of them instead.
+/// The `+` (or `*`) register is copied into the system clipboard as well.
+#[derive(Debug, Default)]
+pub struct Registers {
+    /// The register that everything goes into, unless another one is asked for.
+    unnamed: Option<Register>,
+    named: [Option<Register>; 26],
+    /// The `+` register, which is shared with the system clipboard.
+    clipboard: Option<Register>,
+    /// Whether text goes into the clipboard when no other register is asked for, like Vim's `clipboard=unnamedplus`.
+    use_clipboard: bool,
+    /// Text that has been stored in the clipboard register, but hasn't been sent on to the system clipboard yet.
+    outgoing: Option<String>,
+}
+
+impl Registers {
+    /// Determines whether `name` is a register that can be picked with `"`.
+    pub fn is_valid_name(name: char) -> bool {
+        matches!(name, '"' | '+' | '*') || name.is_ascii_alphabetic()
+    }
+
+    fn is_clipboard(name: char) -> bool {
+        matches!(name, '+' | '*')
+    }
+
+    /// Makes the clipboard the register that's used when no other one is asked for.
+    pub fn set_use_clipboard(&mut self, use_clipboard: bool) {
+        self.use_clipboard = use_clipboard;
+    }
+
+    /// Takes the text that should be sent on to the system clipboard, if there's anything new.
+    pub fn take_outgoing(&mut self) -> Option<String> {
+        self.outgoing.take()
+    }
+
+    /// Finds the slot in `named` that a name refers to.
+    fn index(name: char) -> Option<usize> {
+        match name.is_ascii_alphabetic() {
+            true => Some(usize::from(name.to_ascii_lowercase() as u8 - b'a')),
+            false => None,
+        }
+    }
+
+    /// Stores some text that has just been yanked or deleted, in the named register as well if one was picked.
+    pub fn store(&mut self, name: Option<char>, register: Register) {
+        let to_clipboard = match name {
+            Some(name) => Self::is_clipboard(name),
+            None => self.use_clipboard,
+        };
+        if to_clipboard {
+            self.outgoing = Some(register.text.clone());
+            self.clipboard = Some(register.clone());
+        }
+
+        let index = match name.and_then(Self::index) {
+            Some(index) => index,
+            None => {
+                self.unnamed = Some(register);
+                return;
+            }
+        };
+
+        let register = match (&mut self.named[index], name) {
+            (Some(existing), Some(name)) if name.is_ascii_uppercase() => {
+                // Adding whole lines onto the end of some text starts them on a line of their own.
+                if register.linewise && !existing.linewise && !existing.text.ends_with('\n') {
+                    existing.text.push('\n');
+                }
+                existing.text.push_str(&register.text);
+                existing.linewise |= register.linewise;
+                existing.blockwise &= register.blockwise;
+                existing.clone()
+            }
+            (slot, _) => slot.insert(register).clone(),
+        };
+        self.unnamed = Some(register);
+    }
+
+    /// Stores the keys recorded with `q` into a named register, which leaves the unnamed register alone (like in Vim.)
+    pub fn record(&mut self, name: char, keys: String) {
+        let index = match Self::index(name) {
+            Some(index) => index,
+            None => return,
+        };
+        match &mut self.named[index] {
+            Some(existing) if name.is_ascii_uppercase() => existing.text.push_str(&keys),
+            slot => *slot = Some(Register::new(keys, false)),
+        }
+    }
+
+    /// The unnamed register (as `"`) and the named ones that have anything in them, for keeping until the next run.
+    ///
+    /// The clipboard isn't included, since the system clipboard keeps hold of it anyway.
+    pub fn saved(&self) -> Vec<(char, Register)> {
+        let named = (b'a'..=b'z').map(char::from).zip(&self.named);
+        iter::once(('"', &self.unnamed))
+            .chain(named)
+            .filter_map(|(name, register)| Some((name, register.clone()?)))
+            .collect()
+    }
+
+    /// Puts back a register that was saved with `saved`.
+    pub fn restore(&mut self, name: char, register: Register) {
+        match Self::index(name) {
+            Some(index) => self.named[index] = Some(register),
+            None if name == '"' => self.unnamed = Some(register),
+            None => {}
+        }
+    }
+
+    /// Whether a register name (or picking no register) means the clipboard.
+    pub fn is_clipboard_name(&self, name: Option<char>) -> bool {
+        match name {
+            Some(name) => Self::is_clipboard(name),
+            None => self.use_clipboard,
+        }
+    }
+
+    /// Takes whatever is in the system clipboard into the clipboard register, for when it has been copied to from outside.
+    ///
+    /// If it's still what was last stored there, the register is left alone so that it stays whole lines or a block.
+    /// Otherwise it's taken as whole lines if it ends with a line ending, like Vim does.
+    pub fn sync_clipboard(&mut self, text: String) {
+        if self
+            .clipboard
+            .as_ref()
+            .is_some_and(|register| register.text == text)
+        {
+            return;
+        }
+        let linewise = text.ends_with('\n');
+        self.clipboard = Some(Register::new(text, linewise));
+    }
+
+    /// Fetches the text stored in a register, or in the unnamed register if one isn't picked.
+    pub fn get(&self, name: Option<char>) -> Option<&Register> {
+        match name {
+            Some(name) if Self::is_clipboard(name) => self.clipboard.as_ref(),
+            None if self.use_clipboard => self.clipboard.as_ref(),
+            _ => match name.and_then(Self::index) {
+                Some(index) => self.named[index].as_ref(),
+                None => self.unnamed.as_ref(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(registers: &Registers, name: Option<char>) -> Option<&str> {
+        registers.get(name).map(|register| register.text.as_str())
+    }
+
+    #[test]
+    fn test_unnamed_register_follows_named_ones() {
+        let mut registers = Registers::default();
+        registers.store(None, Register::new("one".to_string(), false));
+        registers.store(Some('a'), Register::new("two".to_string(), false));
+        assert_eq!(text(&registers, None), Some("two"));
+        assert_eq!(text(&registers, Some('"')), Some("two"));
+        assert_eq!(text(&registers, Some('a')), Some("two"));
+        assert_eq!(text(&registers, Some('b')), None);
+
+        registers.store(None, Register::new("three".to_string(), false));
+        assert_eq!(text(&registers, Some('a')), Some("two"));
+        assert_eq!(text(&registers, None), Some("three"));
+    }
+
+    #[test]
+    fn test_appending_to_named_register() {
+        let mut registers = Registers::default();
+        registers.store(Some('A'), Register::new("one".to_string(), false));
+        registers.store(Some('A'), Register::new(" two".to_string(), false));
+        assert_eq!(text(&registers, Some('a')), Some("one two"));
+
+        registers.store(Some('A'), Register::new("three\n".to_string(), true));
+        assert_eq!(
+            registers.get(Some('a')),
+            Some(&Register::new("one two\nthree\n".to_string(), true))
+        );
+    }
+
+    #[test]
+    fn test_clipboard_register() {
+        let mut registers = Registers::default();
+        registers.store(None, Register::new("one".to_string(), false));
+        assert_eq!(registers.take_outgoing(), None);
+
+        registers.store(Some('+'), Register::new("two".to_string(), false));
+        assert_eq!(registers.take_outgoing().as_deref(), Some("two"));
+        assert_eq!(registers.take_outgoing(), None);
+        assert_eq!(text(&registers, Some('*')), Some("two"));
+
+        registers.set_use_clipboard(true);
+        registers.store(Some('a'), Register::new("three".to_string(), false));
+        assert_eq!(registers.take_outgoing(), None);
+        registers.store(None, Register::new("four".to_string(), false));
+        assert_eq!(registers.take_outgoing().as_deref(), Some("four"));
+        assert_eq!(text(&registers, Some('+')), Some("four"));
+        assert_eq!(text(&registers, Some('a')), Some("three"));
+    }
+
+    #[test]
+    fn test_syncing_clipboard() {
+        let mut registers = Registers::default();
+        assert!(registers.is_clipboard_name(Some('*')));
+        assert!(!registers.is_clipboard_name(None));
+        registers.store(
+            Some('+'),
+            Register::block(&["ab".to_string(), "cd".to_string()]),
+        );
+
+        // The same text as was copied keeps its shape.
+        registers.sync_clipboard("ab\ncd".to_string());
+        assert!(registers.get(Some('+')).unwrap().blockwise);
+
+        registers.sync_clipboard("copied\n".to_string());
+        assert_eq!(
+            registers.get(Some('+')),
+            Some(&Register::new("copied\n".to_string(), true))
+        );
+        registers.sync_clipboard("elsewhere".to_string());
+        assert_eq!(
+            registers.get(Some('+')),
+            Some(&Register::new("elsewhere".to_string(), false))
+        );
+        assert_eq!(registers.take_outgoing(), Some("ab\ncd".to_string()));
+    }
+}
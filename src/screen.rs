@@ -0,0 +1,197 @@
+//! Keeping track of what is already on the terminal, so that only the rows that change get drawn again.
+//!
+//! Clearing the whole terminal and drawing everything on every key press flickers badly over a slow connection.
+use std::cmp;
+use std::io::Write;
+
+use crossterm::style::{Color, SetBackgroundColor};
+use crossterm::{cursor, queue, terminal};
+
+use crate::errors::{EditorError, Result};
+use crate::unicode;
+
+/// A copy of the last frame that was drawn onto the terminal, one row at a time.
+#[derive(Debug, Default)]
+pub struct Screen {
+    /// Everything that was sent to the terminal to draw each row, after moving to the start of it.
+    previous: Vec<Vec<u8>>,
+    /// Whether whatever is on the terminal can't be trusted, so everything has to be drawn again.
+    invalid: bool,
+    /// The colour that the terminal is cleared to, if it isn't the terminal's own.
+    background: Option<Color>,
+}
+
+impl Screen {
+    /// Forgets what is on the terminal, so that the next frame is drawn from scratch.
+    ///
+    /// This is needed whenever the terminal might have been drawn over behind our back, like after it is resized.
+    pub fn invalidate(&mut self) {
+        self.invalid = true;
+    }
+
+    /// Clears the terminal to `background` rather than its own colour, so that the blank space around the text matches it.
+    pub fn set_background(&mut self, background: Option<Color>) {
+        self.background = background;
+    }
+
+    /// Draws a frame onto the terminal, skipping any rows that haven't changed since the last one.
+    pub fn draw<S: Write>(&mut self, stream: &mut S, rows: Vec<Vec<u8>>) -> Result<()> {
+        // Terminals clear to whatever background colour is set at the time.
+        let background = self.background.map(SetBackgroundColor);
+        if self.invalid || self.previous.is_empty() {
+            if let Some(background) = background {
+                queue!(stream, background).map_err(EditorError::TermIo)?;
+            }
+            queue!(stream, terminal::Clear(terminal::ClearType::All))
+                .map_err(EditorError::TermIo)?;
+            self.previous.clear();
+            self.invalid = false;
+        }
+
+        for row in 0..cmp::max(rows.len(), self.previous.len()) {
+            let line = rows.get(row).map_or(&[][..], Vec::as_slice);
+            match self.previous.get(row) {
+                Some(previous) if previous.as_slice() == line => continue,
+                // Nothing was drawn on rows that are new since last time, so empty ones can be left as they are.
+                None if line.is_empty() => continue,
+                _ => (),
+            }
+
+            // Drawing the row before will have reset the colours if it used any.
+            if let Some(background) = background {
+                queue!(stream, background).map_err(EditorError::TermIo)?;
+            }
+            queue!(
+                stream,
+                cursor::MoveTo(0, row.try_into().unwrap_or(u16::MAX)),
+                terminal::Clear(terminal::ClearType::CurrentLine)
+            )
+            .map_err(EditorError::TermIo)?;
+            stream.write_all(line).map_err(EditorError::TermIo)?;
+            if background.is_some() {
+                queue!(stream, SetBackgroundColor(Color::Reset)).map_err(EditorError::TermIo)?;
+            }
+        }
+
+        self.previous = rows;
+        Ok(())
+    }
+
+    /// The text on each row of the last frame, without any of the colours, for when there's no terminal to show it on.
+    pub fn text(&self) -> Vec<String> {
+        self.previous.iter().map(|row| plain_text(row)).collect()
+    }
+}
+
+/// Takes the escape sequences out of a row, leaving the text that would be seen on the terminal.
+///
+/// Moving the cursor along the row (which is how windows side by side are drawn) is filled in with blanks.
+fn plain_text(row: &[u8]) -> String {
+    let row = String::from_utf8_lossy(row);
+    let mut text = String::new();
+    let mut column = 0;
+    let mut chars = row.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            text.push(c);
+            column += unicode::width(c);
+            continue;
+        }
+        match chars.next() {
+            // Control sequences end at the first letter (or the like), and `H` moves the cursor to `row;column`.
+            Some('[') => {
+                let mut parameters = String::new();
+                let end = chars.by_ref().find(|c| {
+                    let end = ('@'..='~').contains(c);
+                    if !end {
+                        parameters.push(*c);
+                    }
+                    end
+                });
+                if end == Some('H') {
+                    let target = parameters
+                        .split(';')
+                        .nth(1)
+                        .and_then(|column| column.parse::<usize>().ok())
+                        .map_or(0, |column| column.saturating_sub(1));
+                    text.push_str(&" ".repeat(target.saturating_sub(column)));
+                    column = cmp::max(column, target);
+                }
+            }
+            // Operating system commands (like setting the clipboard) end with a bell or `ESC \`.
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next() == Some('\\')) {
+                        break;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(rows: &[&str]) -> Vec<Vec<u8>> {
+        rows.iter().map(|row| row.as_bytes().to_vec()).collect()
+    }
+
+    fn draw(screen: &mut Screen, rows: &[&str]) -> String {
+        let mut output = Vec::new();
+        screen.draw(&mut output, frame(rows)).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_unchanged_frame_draws_nothing() {
+        let mut screen = Screen::default();
+        let first = draw(&mut screen, &["one", "two", ""]);
+        assert!(first.contains("one") && first.contains("two"));
+        assert_eq!(draw(&mut screen, &["one", "two", ""]), "");
+    }
+
+    #[test]
+    fn test_only_changed_rows_are_drawn() {
+        let mut screen = Screen::default();
+        draw(&mut screen, &["one", "two", "three"]);
+        let output = draw(&mut screen, &["one", "2", "three"]);
+        assert!(output.contains('2'));
+        assert!(!output.contains("one") && !output.contains("three"));
+
+        // Rows that have gone empty still need to be cleared.
+        assert_ne!(draw(&mut screen, &["one", "2", ""]), "");
+    }
+
+    #[test]
+    fn test_frame_as_text() {
+        let mut screen = Screen::default();
+        let row = "\x1b[31mred\x1b[0m \x1b[1;9H│x\x1b]52;c;aGk=\x07y"
+            .as_bytes()
+            .to_vec();
+        screen.draw(&mut Vec::new(), vec![row, Vec::new()]).unwrap();
+        assert_eq!(screen.text(), ["red     │xy", ""]);
+    }
+
+    #[test]
+    fn test_clearing_to_background() {
+        let mut screen = Screen::default();
+        screen.set_background(Some(Color::AnsiValue(234)));
+        let output = draw(&mut screen, &["one"]);
+        assert!(output.starts_with("\x1b[48;5;234m"));
+        assert!(output.contains("\x1b[48;5;234m\x1b[1;1H\x1b[2Kone\x1b[49m"));
+        assert_eq!(draw(&mut screen, &["one"]), "");
+    }
+
+    #[test]
+    fn test_invalidated_screen_is_drawn_again() {
+        let mut screen = Screen::default();
+        draw(&mut screen, &["one", "two"]);
+        screen.invalidate();
+        let output = draw(&mut screen, &["one", "two"]);
+        assert!(output.contains("one") && output.contains("two"));
+    }
+}
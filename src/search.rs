@@ -0,0 +1,190 @@
+//! Finding text in a buffer.
+//!
+//! Searches are made a line at a time, so a pattern never matches across a line ending.
+use std::cmp;
+use std::ops::Range;
+
+use crate::buffer::Buffer;
+
+/// A piece of text that was found by a search.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Match {
+    /// Where the text is in the buffer.
+    pub range: Range<usize>,
+    /// Whether the search had to go round the end (or the start) of the buffer to find it.
+    pub wrapped: bool,
+}
+
+/// Finds the start of every occurrence of `pattern` in `text`, including ones that overlap.
+fn find_all(text: &str, pattern: &str) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut i = 0;
+    while let Some(found) = text[i..].find(pattern) {
+        let start = i + found;
+        starts.push(start);
+        i = start + text[start..].chars().next().map_or(1, char::len_utf8);
+    }
+    starts
+}
+
+/// Finds every occurrence of `pattern` on a line of the buffer.
+fn line_matches(buffer: &Buffer, pattern: &str, line: usize) -> Vec<Range<usize>> {
+    let range = match buffer.line_range(line) {
+        Some(range) => range,
+        None => return Vec::new(),
+    };
+    let text = buffer.contents().slice(range.clone());
+    find_all(&text, pattern)
+        .into_iter()
+        .map(|i| range.start + i..range.start + i + pattern.len())
+        .collect()
+}
+
+/// Finds every occurrence of `pattern` on some lines of the buffer, so that they can be highlighted.
+pub fn matches_in(buffer: &Buffer, pattern: &str, lines: Range<usize>) -> Vec<Range<usize>> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+    lines
+        .flat_map(|line| line_matches(buffer, pattern, line))
+        .collect()
+}
+
+/// Works out which of the matches of `pattern` in the buffer starts at `index`, for showing something like "match 3 of 17".
+///
+/// Returns the match's number, counted from one, along with how many matches there are altogether.
+pub fn match_number(buffer: &Buffer, pattern: &str, index: usize) -> Option<(usize, usize)> {
+    let matches = matches_in(buffer, pattern, 0..cmp::max(buffer.num_lines(), 1));
+    let number = matches.iter().position(|m| m.start == index)?;
+    Some((number + 1, matches.len()))
+}
+
+/// Finds the next occurrence of `pattern` after the index `from`, going round to the start of the buffer if need be.
+pub fn find_next(buffer: &Buffer, pattern: &str, from: usize) -> Option<Match> {
+    if pattern.is_empty() {
+        return None;
+    }
+
+    // The line that the search starts on is looked at twice, once for what comes after `from` and again after wrapping.
+    let lines = cmp::max(buffer.num_lines(), 1);
+    let first = buffer.contents().line_of(from);
+    (0..=lines).find_map(|n| {
+        let line = (first + n) % lines;
+        line_matches(buffer, pattern, line)
+            .into_iter()
+            .find(|m| n > 0 || m.start > from)
+            .map(|range| Match {
+                range,
+                wrapped: first + n >= lines,
+            })
+    })
+}
+
+/// Finds the previous occurrence of `pattern` before the index `from`, going round to the end of the buffer if need be.
+pub fn find_previous(buffer: &Buffer, pattern: &str, from: usize) -> Option<Match> {
+    if pattern.is_empty() {
+        return None;
+    }
+
+    let lines = cmp::max(buffer.num_lines(), 1);
+    let first = cmp::min(buffer.contents().line_of(from), lines - 1);
+    (0..=lines).find_map(|n| {
+        let line = (first + lines - n % lines) % lines;
+        line_matches(buffer, pattern, line)
+            .into_iter()
+            .rev()
+            .find(|m| n > 0 || m.start < from)
+            .map(|range| Match {
+                range,
+                wrapped: n > first,
+            })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_find_all_overlapping() {
+        assert_eq!(find_all("aaa", "aa"), vec![0, 1]);
+        assert_eq!(find_all("éaéa", "a"), vec![2, 5]);
+        assert_eq!(find_all("abc", "x"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_match_number() {
+        let buffer = Buffer::new("foo bar\nbaz foo\nfoo");
+        assert_eq!(match_number(&buffer, "foo", 0), Some((1, 3)));
+        assert_eq!(match_number(&buffer, "foo", 12), Some((2, 3)));
+        assert_eq!(match_number(&buffer, "foo", 16), Some((3, 3)));
+        assert_eq!(match_number(&buffer, "foo", 1), None);
+        assert_eq!(match_number(&buffer, "", 0), None);
+    }
+
+    #[test]
+    fn test_find_next() {
+        let buffer = Buffer::new("foo bar\nbaz foo\nqux");
+        assert_eq!(
+            find_next(&buffer, "foo", 0),
+            Some(Match {
+                range: 12..15,
+                wrapped: false
+            })
+        );
+        assert_eq!(
+            find_next(&buffer, "foo", 12),
+            Some(Match {
+                range: 0..3,
+                wrapped: true
+            })
+        );
+        assert_eq!(find_next(&buffer, "nothing", 0), None);
+    }
+
+    #[test]
+    fn test_find_next_only_match() {
+        let buffer = Buffer::new("abc\ndef");
+        assert_eq!(
+            find_next(&buffer, "e", 5),
+            Some(Match {
+                range: 5..6,
+                wrapped: true
+            })
+        );
+    }
+
+    #[test]
+    fn test_find_previous() {
+        let buffer = Buffer::new("foo bar\nbaz foo\nqux");
+        assert_eq!(
+            find_previous(&buffer, "foo", 12),
+            Some(Match {
+                range: 0..3,
+                wrapped: false
+            })
+        );
+        assert_eq!(
+            find_previous(&buffer, "foo", 0),
+            Some(Match {
+                range: 12..15,
+                wrapped: true
+            })
+        );
+        assert_eq!(
+            find_previous(&buffer, "ba", 8),
+            Some(Match {
+                range: 4..6,
+                wrapped: false
+            })
+        );
+    }
+
+    #[test]
+    fn test_matches_in_lines() {
+        let buffer = Buffer::new("ab ab\nb\nab");
+        assert_eq!(matches_in(&buffer, "ab", 0..2), vec![0..2, 3..5]);
+        assert_eq!(matches_in(&buffer, "ab", 1..5), vec![8..10]);
+        assert_eq!(matches_in(&buffer, "", 0..3), Vec::<Range<usize>>::new());
+    }
+}
@@ -0,0 +1,329 @@
+//! Driving the editor over a Unix socket instead of a terminal, for GUIs, tests and other tools to build on.
+//!
+//! Requests are JSON-RPC 2.0, one message to a line, and the methods are:
+//!
+//! - `open` with a `path`, to open a file like `:e` does.
+//! - `keys` with some `keys` written like they are in key bindings (`dw`, `<Esc>`, `<C-r>`), to type them.
+//! - `command` with a `command`, to run it as if it had been typed at the `:` prompt.
+//! - `query`, for where the cursor is, the mode, and the like. The three methods above send this back too.
+//! - `text`, for everything in the buffer.
+//! - `render` with the display's `columns` and `rows` if they've changed, for what would be on the terminal as text.
+//! - `shutdown`, to stop the editor.
+//!
+//! Lines and columns are counted from zero, and columns are in bytes.
+#[cfg(unix)]
+use std::fs;
+use std::io;
+#[cfg(unix)]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+use crate::editor::{Editor, Exit};
+use crate::json::Json;
+use crate::keymap;
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+/// The code for anything that the editor itself says went wrong, like a file that can't be opened.
+const EDITOR_ERROR: i32 = -32000;
+
+/// Why a request couldn't be carried out.
+#[derive(Debug, PartialEq)]
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Listens on the socket at `path`, handling requests from one client after another until one of them stops the editor.
+///
+/// A socket that's been left behind by an editor that's no longer running is replaced.
+#[cfg(unix)]
+pub fn serve(editor: &mut Editor, path: &Path) -> io::Result<()> {
+    let listener = match UnixListener::bind(path) {
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse && UnixStream::connect(path).is_err() => {
+            fs::remove_file(path)?;
+            UnixListener::bind(path)?
+        }
+        result => result?,
+    };
+    let result = accept(editor, &listener);
+    let _ = fs::remove_file(path);
+    result
+}
+
+/// There are no Unix sockets to listen on.
+#[cfg(not(unix))]
+pub fn serve(_editor: &mut Editor, _path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "The server needs Unix sockets",
+    ))
+}
+
+#[cfg(unix)]
+fn accept(editor: &mut Editor, listener: &UnixListener) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let mut output = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let (response, stop) = respond(editor, &line?);
+            if let Some(response) = response {
+                writeln!(output, "{}", response)?;
+            }
+            if stop {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Handles one line from a client, returning the response (unless it was a notification) and whether to stop the editor.
+fn respond(editor: &mut Editor, line: &str) -> (Option<Json>, bool) {
+    // Anything the editor was waiting to do in the meantime, like picking up what a linter found, is done first.
+    if editor.tick() == Some(Exit::Quit) {
+        return (None, true);
+    }
+    if line.trim().is_empty() {
+        return (None, false);
+    }
+
+    let request = match Json::parse(line) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = RpcError::new(PARSE_ERROR, e.to_string());
+            return (Some(response(Json::Null, Err(error))), false);
+        }
+    };
+    let id = request.get("id").cloned();
+    let params = request.get("params").cloned().unwrap_or(Json::Null);
+    let (result, stop) = match request.get("method").and_then(Json::as_str) {
+        Some(method) => handle(editor, method, &params),
+        None => (Err(RpcError::new(INVALID_REQUEST, "No method")), false),
+    };
+    (id.map(|id| response(id, result)), stop)
+}
+
+/// Carries out a request, returning its result and whether the editor should stop.
+fn handle(editor: &mut Editor, method: &str, params: &Json) -> (Result<Json, RpcError>, bool) {
+    let string = |name: &str| {
+        params
+            .get(name)
+            .and_then(Json::as_str)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("Expected a string {}", name)))
+    };
+    let editor_error = |e: String| RpcError::new(EDITOR_ERROR, e);
+
+    let mut stop = false;
+    let result = match method {
+        "open" => string("path").and_then(|path| {
+            editor
+                .open(PathBuf::from(path))
+                .map_err(|e| editor_error(e.to_string()))
+        }),
+        "keys" => string("keys").and_then(|keys| {
+            let keys = keymap::parse_keys(keys).map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+            // The editor runs shell commands itself without a terminal, but there's no shell to suspend back to either.
+            match editor.send_keys(&keys) {
+                Some(Exit::Quit) => stop = true,
+                Some(_) => {
+                    return Err(editor_error(
+                        "Can't suspend without a terminal, so the keys after it weren't typed"
+                            .to_string(),
+                    ))
+                }
+                None => (),
+            }
+            Ok(())
+        }),
+        "command" => string("command").and_then(|command| {
+            stop = editor.execute_line(command).map_err(editor_error)?;
+            Ok(())
+        }),
+        "query" => Ok(()),
+        "text" => return (Ok(editor.buffer().contents().to_string().into()), false),
+        "render" => {
+            let size = |name| params.get(name).and_then(Json::as_usize)?.try_into().ok();
+            if let (Some(columns), Some(rows)) = (size("columns"), size("rows")) {
+                editor.redraw(columns, rows);
+            }
+            let rows = match editor.render_text() {
+                Ok(rows) => rows.into_iter().map(Json::from).collect(),
+                Err(e) => return (Err(editor_error(e.to_string())), false),
+            };
+            return (Ok(Json::object([("rows", Json::Array(rows))])), false);
+        }
+        "shutdown" => return (Ok(Json::Null), true),
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Unknown method {}", method),
+        )),
+    };
+    (result.map(|_| state(editor)), stop)
+}
+
+/// Describes where the editor has got to, which is sent back after anything that could have changed it.
+fn state(editor: &Editor) -> Json {
+    let buffer = editor.buffer();
+    let cursor = buffer.cursor();
+    let path = match buffer.path() {
+        Some(path) => Json::from(path.to_string_lossy().into_owned()),
+        None => Json::Null,
+    };
+    let message = editor.message().map_or(Json::Null, Json::from);
+    Json::object([
+        ("path", path),
+        ("line", cursor.line.into()),
+        ("column", cursor.column.into()),
+        ("lines", buffer.num_lines().into()),
+        ("modified", buffer.is_modified().into()),
+        ("mode", editor.mode_name().into()),
+        ("message", message),
+    ])
+}
+
+/// Wraps the result of a request up as a response to it.
+fn response(id: Json, result: Result<Json, RpcError>) -> Json {
+    let outcome = match result {
+        Ok(result) => ("result", result),
+        Err(error) => (
+            "error",
+            Json::object([
+                ("code", Json::Number(error.code.into())),
+                ("message", error.message.into()),
+            ]),
+        ),
+    };
+    Json::object([("jsonrpc", "2.0".into()), ("id", id), outcome])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::buffer::Buffer;
+
+    fn request(editor: &mut Editor, line: &str) -> Json {
+        respond(editor, line).0.unwrap()
+    }
+
+    #[test]
+    fn test_editing_over_rpc() {
+        let mut editor = Editor::new(Buffer::new("one two\nthree\n"));
+        let state = request(
+            &mut editor,
+            r#"{"jsonrpc":"2.0","id":1,"method":"keys","params":{"keys":"wx"}}"#,
+        );
+        let result = state.get("result").unwrap();
+        assert_eq!(state.get("id"), Some(&Json::Number(1.0)));
+        assert_eq!(result.get("column").and_then(Json::as_usize), Some(4));
+        assert_eq!(result.get("modified"), Some(&Json::Bool(true)));
+        assert_eq!(result.get("mode").and_then(Json::as_str), Some("NAVIGATE"));
+
+        request(
+            &mut editor,
+            r#"{"jsonrpc":"2.0","id":2,"method":"command","params":{"command":"s/one/1/"}}"#,
+        );
+        let text = request(&mut editor, r#"{"jsonrpc":"2.0","id":3,"method":"text"}"#);
+        assert_eq!(
+            text.get("result").and_then(Json::as_str),
+            Some("1 wo\nthree\n")
+        );
+
+        // Notifications don't get a response.
+        let (response, stop) = respond(
+            &mut editor,
+            r#"{"jsonrpc":"2.0","method":"keys","params":{"keys":"<Esc>"}}"#,
+        );
+        assert_eq!((response, stop), (None, false));
+        let (_, stop) = respond(
+            &mut editor,
+            r#"{"jsonrpc":"2.0","id":4,"method":"command","params":{"command":"q!"}}"#,
+        );
+        assert!(stop);
+    }
+
+    #[test]
+    fn test_rendering_over_rpc() {
+        let mut editor = Editor::new(Buffer::new("hello"));
+        let response = request(
+            &mut editor,
+            r#"{"jsonrpc":"2.0","id":1,"method":"render","params":{"columns":20,"rows":3}}"#,
+        );
+        let rows = response.get("result").unwrap().get("rows").unwrap();
+        let rows: Vec<_> = rows.as_array().unwrap().iter().map(Json::as_str).collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], Some("hello"));
+        assert!(rows[2].unwrap().contains("NAVIGATE"));
+    }
+
+    #[test]
+    fn test_rpc_errors() {
+        let mut editor = Editor::new(Buffer::new(""));
+        let code = |response: Json| {
+            response
+                .get("error")
+                .and_then(|e| e.get("code").cloned())
+                .unwrap()
+        };
+        assert_eq!(
+            code(request(&mut editor, "{not json")),
+            Json::Number(-32700.0)
+        );
+        assert_eq!(
+            code(request(&mut editor, r#"{"id":1,"method":"fly"}"#)),
+            Json::Number(-32601.0)
+        );
+        assert_eq!(
+            code(request(&mut editor, r#"{"id":1,"method":"keys"}"#)),
+            Json::Number(-32602.0)
+        );
+        assert_eq!(
+            code(request(
+                &mut editor,
+                r#"{"id":1,"method":"command","params":{"command":"nonsense"}}"#
+            )),
+            Json::Number(-32000.0)
+        );
+        assert_eq!(
+            code(request(
+                &mut editor,
+                r#"{"id":1,"method":"keys","params":{"keys":"<C-z>ihello"}}"#
+            )),
+            Json::Number(-32000.0)
+        );
+        assert_eq!(editor.buffer().contents().to_string(), "");
+    }
+
+    #[test]
+    fn test_shell_commands_over_rpc() {
+        let mut editor = Editor::new(Buffer::new("one\ntwo\n"));
+        let result = request(
+            &mut editor,
+            r#"{"id":1,"method":"command","params":{"command":"!echo hi"}}"#,
+        );
+        let result = result.get("result").unwrap();
+        assert_eq!(result.get("message").and_then(Json::as_str), Some("hi"));
+
+        // Running a command doesn't stop the keys after it from being typed.
+        let result = request(
+            &mut editor,
+            r#"{"id":2,"method":"keys","params":{"keys":":!true<CR>!!tr a-z A-Z<CR>"}}"#,
+        );
+        let result = result.get("result").unwrap();
+        assert_eq!(result.get("mode").and_then(Json::as_str), Some("NAVIGATE"));
+        assert_eq!(editor.buffer().contents().to_string(), "ONE\ntwo\n");
+    }
+}
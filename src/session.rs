@@ -0,0 +1,287 @@
+//! Saving the files being edited and how the display is split between them, so that they can all be opened again later.
+//!
+//! A session file has one thing on each line, like this:
+//!
+//! ```text
+//! directory /home/me/project
+//! buffer 12 4 src/main.rs
+//! buffer 0 0 README.md
+//! window 0 0 12 4
+//! window 1 1 0 0
+//! layout (vertical 0 1)
+//! focus 0
+//! ```
+//!
+//! Each buffer has the line and column of its cursor before its path, and each window has its id, the buffer it shows,
+//! and where its cursor is. The layout is how the display is split between the windows, by their ids.
+use std::fmt::{self, Display};
+use std::iter::Peekable;
+use std::path::PathBuf;
+use std::str::SplitWhitespace;
+
+use crate::buffer::Position;
+use crate::windows::{Layout, Split};
+
+/// The file that `:mksession` writes when it isn't given one.
+pub const DEFAULT_SESSION_FILE: &str = "Session.te";
+
+/// A window as it was when the session was saved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionWindow {
+    pub id: usize,
+    /// Where the buffer that the window shows sits in the list.
+    pub buffer: usize,
+    pub cursor: Position,
+}
+
+/// Everything that's needed to open the same files in the same windows again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    /// The working directory, which the paths of the buffers can be relative to.
+    pub directory: PathBuf,
+    /// The file each buffer in the list came from, and where its cursor was.
+    pub buffers: Vec<(PathBuf, Position)>,
+    pub windows: Vec<SessionWindow>,
+    pub layout: Layout,
+    /// The id of the window that had the focus.
+    pub focused: usize,
+}
+
+impl Session {
+    /// Reads a session back out of a file written from it, checking that the windows all fit together.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut directory = None;
+        let mut buffers = Vec::new();
+        let mut windows = Vec::new();
+        let mut layout = None;
+        let mut focused = None;
+        for (i, line) in text.lines().enumerate() {
+            let invalid = |reason: &str| format!("line {}: {}", i + 1, reason);
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+            match keyword {
+                "directory" if !rest.is_empty() => directory = Some(PathBuf::from(rest)),
+                "buffer" => match rest.splitn(3, ' ').collect::<Vec<_>>()[..] {
+                    [line, column, path] if !path.is_empty() => {
+                        let [line, column] = numbers(&[line, column])
+                            .ok_or_else(|| invalid("Expected a line and column"))?;
+                        buffers.push((PathBuf::from(path), Position::new(line, column)));
+                    }
+                    _ => return Err(invalid("Expected a line, column and path")),
+                },
+                "window" => {
+                    let parts: Vec<_> = rest.split_whitespace().collect();
+                    let [id, buffer, line, column] = numbers(&parts)
+                        .ok_or_else(|| invalid("Expected an id, buffer, line and column"))?;
+                    windows.push(SessionWindow {
+                        id,
+                        buffer,
+                        cursor: Position::new(line, column),
+                    });
+                }
+                "layout" => {
+                    layout = Some(parse_layout(rest).ok_or_else(|| invalid("Invalid layout"))?)
+                }
+                "focus" => {
+                    let [id] = numbers(&[rest]).ok_or_else(|| invalid("Expected a window id"))?;
+                    focused = Some(id);
+                }
+                _ => return Err(invalid(&format!("Unexpected {}", keyword))),
+            }
+        }
+
+        let session = Self {
+            directory: directory.ok_or("No directory")?,
+            buffers,
+            windows,
+            layout: layout.ok_or("No layout")?,
+            focused: focused.ok_or("No window has the focus")?,
+        };
+        session.check()?;
+        Ok(session)
+    }
+
+    /// Makes sure that every window in the layout is there once, showing a buffer that's there too.
+    fn check(&self) -> Result<(), String> {
+        let mut ids = window_ids(&self.layout);
+        ids.sort_unstable();
+        let mut windows: Vec<_> = self.windows.iter().map(|w| w.id).collect();
+        windows.sort_unstable();
+        if ids != windows || ids.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err("The windows don't match the layout".to_string());
+        }
+        if !ids.contains(&self.focused) {
+            return Err(format!("There's no window {} to focus", self.focused));
+        }
+        match self.windows.iter().find(|w| w.buffer >= self.buffers.len()) {
+            Some(window) => Err(format!("There's no buffer {}", window.buffer)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# A te session, which te --session opens again")?;
+        writeln!(f, "directory {}", self.directory.display())?;
+        for (path, cursor) in &self.buffers {
+            writeln!(
+                f,
+                "buffer {} {} {}",
+                cursor.line,
+                cursor.column,
+                path.display()
+            )?;
+        }
+        for window in &self.windows {
+            writeln!(
+                f,
+                "window {} {} {} {}",
+                window.id, window.buffer, window.cursor.line, window.cursor.column
+            )?;
+        }
+        write!(f, "layout ")?;
+        write_layout(f, &self.layout)?;
+        writeln!(f)?;
+        writeln!(f, "focus {}", self.focused)
+    }
+}
+
+/// Parses some whole numbers, which there have to be exactly `N` of.
+fn numbers<const N: usize>(parts: &[&str]) -> Option<[usize; N]> {
+    let numbers: Vec<usize> = parts
+        .iter()
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    numbers.try_into().ok()
+}
+
+/// Writes a layout out as a window id, or a split like `(vertical 0 (horizontal 1 2))`.
+fn write_layout(f: &mut fmt::Formatter<'_>, layout: &Layout) -> fmt::Result {
+    let (split, children) = match layout {
+        Layout::Window(id) => return write!(f, "{}", id),
+        Layout::Split(split, children) => (split, children),
+    };
+    match split {
+        Split::Horizontal => write!(f, "(horizontal")?,
+        Split::Vertical => write!(f, "(vertical")?,
+    }
+    for child in children {
+        write!(f, " ")?;
+        write_layout(f, child)?;
+    }
+    write!(f, ")")
+}
+
+/// Reads a layout written by `write_layout`.
+fn parse_layout(text: &str) -> Option<Layout> {
+    let spaced = text.replace('(', " ( ").replace(')', " ) ");
+    let mut tokens = spaced.split_whitespace().peekable();
+    let layout = layout_from(&mut tokens)?;
+    tokens.next().is_none().then_some(layout)
+}
+
+fn layout_from(tokens: &mut Peekable<SplitWhitespace>) -> Option<Layout> {
+    let token = tokens.next()?;
+    if token != "(" {
+        return token.parse().ok().map(Layout::Window);
+    }
+    let split = match tokens.next()? {
+        "horizontal" => Split::Horizontal,
+        "vertical" => Split::Vertical,
+        _ => return None,
+    };
+    let mut children = Vec::new();
+    while tokens.peek() != Some(&")") {
+        children.push(layout_from(tokens)?);
+    }
+    tokens.next();
+    // A split always has at least two windows in it, or it wouldn't be a split.
+    (children.len() > 1).then_some(Layout::Split(split, children))
+}
+
+/// The ids of the windows in a layout.
+fn window_ids(layout: &Layout) -> Vec<usize> {
+    match layout {
+        Layout::Window(id) => vec![*id],
+        Layout::Split(_, children) => children.iter().flat_map(window_ids).collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn session() -> Session {
+        Session {
+            directory: PathBuf::from("/home/me/my project"),
+            buffers: vec![
+                (PathBuf::from("src/main.rs"), Position::new(12, 4)),
+                (PathBuf::from("notes and things.txt"), Position::new(0, 0)),
+            ],
+            windows: vec![
+                SessionWindow {
+                    id: 2,
+                    buffer: 0,
+                    cursor: Position::new(12, 4),
+                },
+                SessionWindow {
+                    id: 0,
+                    buffer: 1,
+                    cursor: Position::new(3, 0),
+                },
+                SessionWindow {
+                    id: 1,
+                    buffer: 0,
+                    cursor: Position::new(40, 2),
+                },
+            ],
+            layout: Layout::Split(
+                Split::Vertical,
+                vec![
+                    Layout::Window(0),
+                    Layout::Split(
+                        Split::Horizontal,
+                        vec![Layout::Window(2), Layout::Window(1)],
+                    ),
+                ],
+            ),
+            focused: 2,
+        }
+    }
+
+    #[test]
+    fn test_writing_and_reading_sessions() {
+        let text = session().to_string();
+        assert!(text.contains("\nbuffer 0 0 notes and things.txt\n"));
+        assert!(text.contains("\nlayout (vertical 0 (horizontal 2 1))\n"));
+        assert_eq!(Session::parse(&text), Ok(session()));
+    }
+
+    #[test]
+    fn test_invalid_sessions() {
+        let text = session().to_string();
+        let broken = |from: &str, to: &str| Session::parse(&text.replace(from, to)).unwrap_err();
+        assert_eq!(broken("focus 2", "focus 5"), "There's no window 5 to focus");
+        assert_eq!(broken("window 0 1", "window 0 7"), "There's no buffer 7");
+        assert_eq!(
+            broken("window 1 0", "window 2 0"),
+            "The windows don't match the layout"
+        );
+        assert_eq!(
+            broken("(horizontal 2 1)", "(horizontal 2"),
+            "line 8: Invalid layout"
+        );
+        assert_eq!(
+            broken("(horizontal 2 1)", "(diagonal 2 1)"),
+            "line 8: Invalid layout"
+        );
+        assert_eq!(
+            broken("buffer 12 4", "buffer x 4"),
+            "line 3: Expected a line and column"
+        );
+        assert_eq!(broken("directory", "folder"), "line 2: Unexpected folder");
+    }
+}
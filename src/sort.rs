@@ -0,0 +1,67 @@
+//! Putting lines in order for `:sort`.
+use std::cmp::Ordering;
+
+/// Sorts some lines, keeping the ones that compare the same in the order they were in.
+///
+/// With `numeric`, lines are compared by the first number in them (which can have a `-` in front of it), and lines without one
+/// come first. With `unique`, only the first of each run of lines that compare the same is kept.
+pub fn sort_lines(mut lines: Vec<&str>, reverse: bool, numeric: bool, unique: bool) -> Vec<&str> {
+    let compare = |a: &&str, b: &&str| match numeric {
+        true => first_number(a).cmp(&first_number(b)),
+        false => a.cmp(b),
+    };
+    match reverse {
+        true => lines.sort_by(|a, b| compare(b, a)),
+        false => lines.sort_by(compare),
+    }
+    if unique {
+        lines.dedup_by(|a, b| compare(a, b) == Ordering::Equal);
+    }
+    lines
+}
+
+/// Finds the first decimal number in a line, if there is one.
+fn first_number(line: &str) -> Option<i128> {
+    let start = line.find(|c: char| c.is_ascii_digit())?;
+    let digits = &line[start..];
+    let end = digits
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(digits.len());
+    let value: i128 = digits[..end].parse().ok()?;
+    match line[..start].ends_with('-') {
+        true => Some(-value),
+        false => Some(value),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sorting() {
+        let lines = vec!["b", "a", "c", "a"];
+        assert_eq!(
+            sort_lines(lines.clone(), false, false, false),
+            ["a", "a", "b", "c"]
+        );
+        assert_eq!(
+            sort_lines(lines.clone(), true, false, false),
+            ["c", "b", "a", "a"]
+        );
+        assert_eq!(sort_lines(lines, false, false, true), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_sorting_numbers() {
+        let lines = vec!["x10", "none", "x9", "x-2", "y9", "other"];
+        assert_eq!(
+            sort_lines(lines.clone(), false, true, false),
+            ["none", "other", "x-2", "x9", "y9", "x10"]
+        );
+        assert_eq!(
+            sort_lines(lines, true, true, true),
+            ["x10", "x9", "x-2", "none"]
+        );
+    }
+}
@@ -0,0 +1,357 @@
+//! What the editor remembers from one run to the next, like Vim's viminfo file.
+//!
+//! The state file has one thing on each line, with the first character saying what it is:
+//!
+//! ```text
+//! :set wrap
+//! /pattern
+//! >12 4 /home/me/project/src/main.rs
+//! 'A 3 0 /home/me/notes.txt
+//! "a l first line\nsecond line\n
+//! ```
+//!
+//! That's a `:` command, a `/` search, a file that was edited recently (with where its cursor was), a global mark, and a
+//! register (`l` for whole lines, `b` for a block, `c` for anything else). Lines it doesn't recognise are skipped, so an older file still reads.
+use std::fmt::{self, Display};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::buffer::{self, Position};
+use crate::history;
+use crate::registers::Register;
+
+/// How many recently edited files are remembered.
+pub const MAX_FILES: usize = 100;
+
+/// Everything that's kept between runs of the editor.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct State {
+    pub command_history: Vec<String>,
+    pub search_history: Vec<String>,
+    /// The files edited most recently, newest first, and where the cursor was left in each of them.
+    pub files: Vec<(PathBuf, Position)>,
+    /// The marks `A` to `Z`, which remember a file as well as a place in it.
+    pub marks: Vec<(char, PathBuf, Position)>,
+    /// The registers with something in them, by name (`"` for the unnamed one.)
+    pub registers: Vec<(char, Register)>,
+}
+
+impl State {
+    /// Reads the state back out of a file written from it.
+    pub fn parse(text: &str) -> Self {
+        let mut state = Self::default();
+        for line in text.lines() {
+            let rest = line.get(1..).unwrap_or("");
+            match line.chars().next() {
+                Some(':') => state.command_history.push(rest.to_string()),
+                Some('/') => state.search_history.push(rest.to_string()),
+                Some('>') => state.files.extend(place(rest)),
+                Some('\'') => {
+                    let mark = named(rest).and_then(|(name, rest)| Some((name, place(rest)?)));
+                    if let Some((name, (path, position))) = mark {
+                        state.marks.push((name, path, position));
+                    }
+                }
+                Some('"') => {
+                    if let Some((name, rest)) = named(rest) {
+                        let register = match rest.split_once(' ') {
+                            Some(("l", text)) => Register::new(unescape(text), true),
+                            Some(("c", text)) => Register::new(unescape(text), false),
+                            Some(("b", text)) => Register {
+                                blockwise: true,
+                                ..Register::new(unescape(text), false)
+                            },
+                            _ => continue,
+                        };
+                        state.registers.push((name, register));
+                    }
+                }
+                _ => {}
+            }
+        }
+        state
+    }
+}
+
+impl State {
+    /// Combines the state from this run with what's in the state file now, so that editors running at the same time don't
+    /// throw away each other's histories and registers when they exit.
+    ///
+    /// This run's things come out on top: its history entries are taken as the newest, and its files, marks and registers
+    /// take the place of any that the file has under the same name.
+    pub fn merge(self, saved: State) -> State {
+        let mut files = self.files;
+        for (path, position) in saved.files {
+            if !files.iter().any(|(p, _)| *p == path) {
+                files.push((path, position));
+            }
+        }
+        files.truncate(MAX_FILES);
+
+        let mut marks = self.marks;
+        for mark in saved.marks {
+            if !marks.iter().any(|(name, _, _)| *name == mark.0) {
+                marks.push(mark);
+            }
+        }
+        marks.sort_by_key(|(name, _, _)| *name);
+
+        let mut registers = self.registers;
+        for register in saved.registers {
+            if !registers.iter().any(|(name, _)| *name == register.0) {
+                registers.push(register);
+            }
+        }
+
+        State {
+            command_history: merge_history(saved.command_history, self.command_history),
+            search_history: merge_history(saved.search_history, self.search_history),
+            files,
+            marks,
+            registers,
+        }
+    }
+
+    /// Writes the state into the file at `path`, which only the user can read since registers and histories can have
+    /// passwords in them.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        write_private(path, &self.to_string())
+    }
+}
+
+/// Writes `text` into the file at `path` so that only its owner can read or write it, for files that can have anything the
+/// user has typed in them.
+///
+/// It goes into a temporary file that's then renamed over the old one, so nothing reading the file sees it half written.
+pub fn write_private(path: &Path, text: &str) -> io::Result<()> {
+    let temp_path = buffer::temp_path_for(path);
+    let written = create_private(&temp_path, text).and_then(|_| fs::rename(&temp_path, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    written
+}
+
+/// Writes `text` into a new file that only its owner can read or write.
+fn create_private(path: &Path, text: &str) -> io::Result<()> {
+    // Whatever was left behind by an editor that crashed part way through writing is out of date anyway.
+    let _ = fs::remove_file(path);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(text.as_bytes())?;
+    file.sync_all()
+}
+
+/// Puts the entries of a history from this run after the ones that were saved, dropping the oldest when there are too many.
+fn merge_history(saved: Vec<String>, ours: Vec<String>) -> Vec<String> {
+    let mut entries: Vec<String> = saved.into_iter().filter(|e| !ours.contains(e)).collect();
+    entries.extend(ours);
+    let excess = entries.len().saturating_sub(history::MAX_ENTRIES);
+    entries.drain(..excess);
+    entries
+}
+
+impl Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.command_history {
+            writeln!(f, ":{}", entry)?;
+        }
+        for entry in &self.search_history {
+            writeln!(f, "/{}", entry)?;
+        }
+        for (path, position) in &self.files {
+            writeln!(
+                f,
+                ">{} {} {}",
+                position.line,
+                position.column,
+                path.display()
+            )?;
+        }
+        for (name, path, position) in &self.marks {
+            writeln!(
+                f,
+                "'{} {} {} {}",
+                name,
+                position.line,
+                position.column,
+                path.display()
+            )?;
+        }
+        for (name, register) in &self.registers {
+            let kind = match (register.linewise, register.blockwise) {
+                (true, _) => 'l',
+                (false, true) => 'b',
+                (false, false) => 'c',
+            };
+            writeln!(f, "\"{} {} {}", name, kind, escape(&register.text))?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits the one-character name off the front of a mark or register, along with the space after it.
+fn named(text: &str) -> Option<(char, &str)> {
+    let mut chars = text.chars();
+    let name = chars.next()?;
+    Some((name, chars.as_str().strip_prefix(' ')?))
+}
+
+/// Reads a line, column and path, which may have spaces in it.
+fn place(text: &str) -> Option<(PathBuf, Position)> {
+    match text.splitn(3, ' ').collect::<Vec<_>>()[..] {
+        [line, column, path] if !path.is_empty() => {
+            let position = Position::new(line.parse().ok()?, column.parse().ok()?);
+            Some((PathBuf::from(path), position))
+        }
+        _ => None,
+    }
+}
+
+/// Keeps text on one line, by writing new lines and backslashes as `\n` and `\\`.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some('r') => unescaped.push('\r'),
+                Some(other) => unescaped.push(other),
+                None => unescaped.push('\\'),
+            },
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_writing_and_reading_state() {
+        let state = State {
+            command_history: vec!["set wrap".to_string()],
+            search_history: vec!["a b".to_string()],
+            files: vec![
+                (PathBuf::from("/my files/one.txt"), Position::new(12, 4)),
+                (PathBuf::from("/two.rs"), Position::new(0, 0)),
+            ],
+            marks: vec![('A', PathBuf::from("/two.rs"), Position::new(3, 1))],
+            registers: vec![
+                ('"', Register::new("one\\two\n".to_string(), true)),
+                ('b', Register::new("x\r\ny".to_string(), false)),
+                ('c', Register::block(&["ab".to_string(), "cd".to_string()])),
+            ],
+        };
+        let text = state.to_string();
+        assert!(text.contains("\n>12 4 /my files/one.txt\n"));
+        assert!(text.contains("\n\"\" l one\\\\two\\n\n"));
+        assert_eq!(State::parse(&text), state);
+    }
+
+    #[test]
+    fn test_merging_with_saved_state() {
+        let ours = State {
+            command_history: vec!["w".to_string(), "set wrap".to_string()],
+            files: vec![(PathBuf::from("/a"), Position::new(1, 0))],
+            marks: vec![('B', PathBuf::from("/a"), Position::new(1, 0))],
+            registers: vec![('a', Register::new("ours".to_string(), false))],
+            ..Default::default()
+        };
+        let saved = State {
+            command_history: vec!["set wrap".to_string(), "make".to_string()],
+            search_history: vec!["theirs".to_string()],
+            files: vec![
+                (PathBuf::from("/b"), Position::new(2, 0)),
+                (PathBuf::from("/a"), Position::new(9, 0)),
+            ],
+            marks: vec![
+                ('A', PathBuf::from("/b"), Position::new(2, 0)),
+                ('B', PathBuf::from("/b"), Position::new(2, 0)),
+            ],
+            registers: vec![
+                ('a', Register::new("theirs".to_string(), false)),
+                ('b', Register::new("theirs".to_string(), false)),
+            ],
+        };
+        let merged = ours.merge(saved);
+        assert_eq!(merged.command_history, ["make", "w", "set wrap"]);
+        assert_eq!(merged.search_history, ["theirs"]);
+        assert_eq!(
+            merged.files,
+            [
+                (PathBuf::from("/a"), Position::new(1, 0)),
+                (PathBuf::from("/b"), Position::new(2, 0)),
+            ]
+        );
+        assert_eq!(
+            merged.marks,
+            [
+                ('A', PathBuf::from("/b"), Position::new(2, 0)),
+                ('B', PathBuf::from("/a"), Position::new(1, 0)),
+            ]
+        );
+        assert_eq!(
+            merged.registers,
+            [
+                ('a', Register::new("ours".to_string(), false)),
+                ('b', Register::new("theirs".to_string(), false)),
+            ]
+        );
+
+        let long: Vec<String> = (0..history::MAX_ENTRIES).map(|i| i.to_string()).collect();
+        let merged = merge_history(long, vec!["new".to_string()]);
+        assert_eq!(merged.len(), history::MAX_ENTRIES);
+        assert_eq!((merged[0].as_str(), merged[99].as_str()), ("1", "new"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_writing_state_privately() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("state-file");
+        let path = dir.write("state", ":old\n");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let state = State {
+            command_history: vec!["new".to_string()],
+            ..Default::default()
+        };
+        state.write(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), ":new\n");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // Nothing is left behind besides the file itself.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_skipping_what_isnt_understood() {
+        let state = State::parse(":w\n\n>x 1 /a\n'A 1 2\n\"a q text\n?what\n'B 1 2 /b\n");
+        assert_eq!(state.command_history, ["w"]);
+        assert!(state.files.is_empty());
+        assert!(state.registers.is_empty());
+        assert_eq!(
+            state.marks,
+            [('B', PathBuf::from("/b"), Position::new(1, 2))]
+        );
+    }
+}
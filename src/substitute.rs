@@ -0,0 +1,330 @@
+//! Replacing the text matched by a regular expression, for the `:s` command.
+use std::ops::{Range, RangeInclusive};
+
+use crate::buffer::Buffer;
+use crate::regex::{Captures, Regex};
+
+/// A match that has been found, along with the text that it would be replaced with.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Replacement {
+    /// Where the match is in the buffer.
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+/// Works through the matches of a regular expression on some lines of a buffer, replacing them one at a time.
+///
+/// Each match is found with `find_next`, then either replaced or skipped, so that the user can be asked about them in between.
+#[derive(Debug)]
+pub struct Substitution {
+    regex: Regex,
+    replacement: String,
+    /// Whether every match on a line is replaced, rather than just the first.
+    global: bool,
+    /// Where in the buffer to carry on looking for matches from, if there's anywhere left to look.
+    next: Option<usize>,
+    /// Where the last replacement ended, since an empty match straight after it isn't one that the user would expect.
+    last_end: Option<usize>,
+    /// The last line to look for matches on, which moves as replacements add lines.
+    last_line: usize,
+    /// The match that was found most recently, which is waiting to be replaced or skipped.
+    current: Option<Replacement>,
+    /// The number of matches that have been found.
+    found: usize,
+    /// The number of matches that have been replaced.
+    replaced: usize,
+    /// The number of lines that have had something replaced on them.
+    lines_changed: usize,
+    /// The line that something was most recently replaced on.
+    changed_line: Option<usize>,
+}
+
+impl Substitution {
+    /// Gets ready to replace the matches of `regex` on some lines of the buffer.
+    pub fn new(
+        regex: Regex,
+        replacement: String,
+        global: bool,
+        buffer: &Buffer,
+        lines: RangeInclusive<usize>,
+    ) -> Self {
+        Self {
+            regex,
+            replacement,
+            global,
+            next: buffer.line_range(*lines.start()).map(|range| range.start),
+            last_end: None,
+            last_line: *lines.end(),
+            current: None,
+            found: 0,
+            replaced: 0,
+            lines_changed: 0,
+            changed_line: None,
+        }
+    }
+
+    /// The pattern that is being replaced.
+    pub fn pattern(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    /// The match that is waiting to be replaced or skipped, if there is one.
+    pub fn current(&self) -> Option<&Replacement> {
+        self.current.as_ref()
+    }
+
+    /// The number of matches that have been found so far.
+    pub fn found(&self) -> usize {
+        self.found
+    }
+
+    /// The number of matches that have been replaced so far.
+    pub fn replaced(&self) -> usize {
+        self.replaced
+    }
+
+    /// The number of lines that have had something replaced on them so far.
+    pub fn lines_changed(&self) -> usize {
+        self.lines_changed
+    }
+
+    /// The line that something was most recently replaced on.
+    pub fn changed_line(&self) -> Option<usize> {
+        self.changed_line
+    }
+
+    /// Looks for the next match, returning where it is if there's one left.
+    pub fn find_next(&mut self, buffer: &Buffer) -> Option<Range<usize>> {
+        self.current = self.search(buffer);
+        if self.current.is_some() {
+            self.found += 1;
+        }
+        self.current.as_ref().map(|found| found.range.clone())
+    }
+
+    fn search(&mut self, buffer: &Buffer) -> Option<Replacement> {
+        loop {
+            let next = self.next?;
+            let line = buffer.contents().line_of(next);
+            if line > self.last_line {
+                return None;
+            }
+            let range = buffer.line_range(line)?;
+            let text = buffer.contents().slice(range.clone());
+
+            let mut offset = next - range.start;
+            while offset <= text.len() {
+                let captures = match self.regex.captures_at(&text, offset) {
+                    Some(captures) => captures,
+                    None => break,
+                };
+                let found = captures.get(0)?;
+                if found.is_empty() && Some(range.start + found.start) == self.last_end {
+                    match text[found.start..].chars().next() {
+                        Some(c) => offset = found.start + c.len_utf8(),
+                        None => break,
+                    }
+                    continue;
+                }
+
+                return Some(Replacement {
+                    range: range.start + found.start..range.start + found.end,
+                    text: expand(&self.replacement, &captures, &text),
+                });
+            }
+
+            self.next = buffer.line_range(line + 1).map(|range| range.start);
+        }
+    }
+
+    /// Replaces the current match.
+    pub fn replace(&mut self, buffer: &mut Buffer) {
+        let current = match self.current.take() {
+            Some(current) => current,
+            None => return,
+        };
+        let start = current.range.start;
+        let end = start + current.text.len();
+        buffer.replace(current.range, &current.text);
+
+        let line = buffer.contents().line_of(start);
+        if self.changed_line != Some(line) {
+            self.lines_changed += 1;
+        }
+        let added_lines = current.text.matches('\n').count();
+        self.changed_line = Some(line + added_lines);
+        self.last_line += added_lines;
+        self.replaced += 1;
+        self.last_end = Some(end);
+        self.next = match self.global {
+            true => Some(end),
+            false => buffer
+                .line_range(line + added_lines + 1)
+                .map(|range| range.start),
+        };
+    }
+
+    /// Leaves the current match as it is, moving on to look for the next one.
+    pub fn skip(&mut self, buffer: &Buffer) {
+        let current = match self.current.take() {
+            Some(current) => current,
+            None => return,
+        };
+        self.last_end = Some(current.range.end);
+        self.next = match self.global {
+            true => Some(current.range.end),
+            false => {
+                let line = buffer.contents().line_of(current.range.start);
+                buffer.line_range(line + 1).map(|range| range.start)
+            }
+        };
+    }
+
+    /// Replaces the current match and every one after it.
+    pub fn replace_all(&mut self, buffer: &mut Buffer) {
+        if self.current.is_none() {
+            self.find_next(buffer);
+        }
+        while self.current.is_some() {
+            self.replace(buffer);
+            self.find_next(buffer);
+        }
+    }
+
+    /// Works out what the buffer would look like with every match replaced, without touching it.
+    ///
+    /// Returns the text that the buffer would have, along with where each replacement would be in it.
+    pub fn preview(mut self, buffer: &Buffer) -> (String, Vec<Range<usize>>) {
+        let contents = buffer.contents();
+        let mut text = String::new();
+        let mut replaced = Vec::new();
+        let mut copied = 0;
+        while self.find_next(buffer).is_some() {
+            if let Some(current) = &self.current {
+                text.push_str(&contents.slice(copied..current.range.start));
+                replaced.push(text.len()..text.len() + current.text.len());
+                text.push_str(&current.text);
+                copied = current.range.end;
+            }
+            self.skip(buffer);
+        }
+        text.push_str(&contents.slice(copied..contents.len()));
+        (text, replaced)
+    }
+}
+
+/// Works out the text that replaces a match, like Vim does.
+///
+/// `&` and `\0` stand for the whole match, `\1` to `\9` stand for the groups in the pattern, and `\n` or `\r` starts a new line.
+fn expand(replacement: &str, captures: &Captures, text: &str) -> String {
+    let group = |n: usize| captures.get(n).map_or("", |range| &text[range]);
+    let mut expanded = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => expanded.push_str(group(0)),
+            '\\' => match chars.next() {
+                Some(c @ '0'..='9') => expanded.push_str(group(c as usize - '0' as usize)),
+                Some('n' | 'r') => expanded.push('\n'),
+                Some('t') => expanded.push('\t'),
+                Some(c) => expanded.push(c),
+                None => expanded.push('\\'),
+            },
+            c => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Replaces every match of `pattern` on some lines of `text`, returning what's left.
+    fn substitute(
+        text: &str,
+        pattern: &str,
+        replacement: &str,
+        global: bool,
+        lines: RangeInclusive<usize>,
+    ) -> String {
+        let mut buffer = Buffer::new(text);
+        let regex = Regex::new(pattern).unwrap();
+        let mut substitution =
+            Substitution::new(regex, replacement.to_string(), global, &buffer, lines);
+        substitution.replace_all(&mut buffer);
+        buffer.contents().to_string()
+    }
+
+    #[test]
+    fn test_replacing_first_match_on_each_line() {
+        assert_eq!(
+            substitute("aa\naa\naa", "a", "b", false, 0..=1),
+            "ba\nba\naa"
+        );
+        assert_eq!(
+            substitute("aa\naa\naa", "a", "b", true, 1..=2),
+            "aa\nbb\nbb"
+        );
+    }
+
+    #[test]
+    fn test_replacing_with_groups() {
+        assert_eq!(
+            substitute(
+                "x = 1, y = 2",
+                "\\(\\w\\) = \\(\\d\\)",
+                "\\2 => \\1 (&)",
+                true,
+                0..=0
+            ),
+            "1 => x (x = 1), 2 => y (y = 2)"
+        );
+        assert_eq!(substitute("a\\b", "\\\\", "\\\\\\&", false, 0..=0), "a\\&b");
+    }
+
+    #[test]
+    fn test_replacing_empty_matches() {
+        assert_eq!(substitute("abc", "x*", "-", true, 0..=0), "-a-b-c-");
+        assert_eq!(substitute("abc", "b*", "-", true, 0..=0), "-a-c-");
+        assert_eq!(substitute("ab\ncd", "$", ";", false, 0..=1), "ab;\ncd;");
+    }
+
+    #[test]
+    fn test_replacing_with_new_lines() {
+        assert_eq!(
+            substitute("a,b\nc,d\ne,f", ",", "\\n", false, 0..=1),
+            "a\nb\nc\nd\ne,f"
+        );
+    }
+
+    #[test]
+    fn test_skipping_matches() {
+        let mut buffer = Buffer::new("a a a");
+        let regex = Regex::new("a").unwrap();
+        let mut substitution = Substitution::new(regex, "b".to_string(), true, &buffer, 0..=0);
+        assert_eq!(substitution.find_next(&buffer), Some(0..1));
+        substitution.skip(&buffer);
+        assert_eq!(substitution.find_next(&buffer), Some(2..3));
+        substitution.replace(&mut buffer);
+        assert_eq!(substitution.find_next(&buffer), Some(4..5));
+        substitution.skip(&buffer);
+        assert_eq!(substitution.find_next(&buffer), None);
+
+        assert_eq!(buffer.contents(), &"a b a");
+        assert_eq!(substitution.found(), 3);
+        assert_eq!(substitution.replaced(), 1);
+    }
+
+    #[test]
+    fn test_previewing_replacements() {
+        let buffer = Buffer::new("a1 a2\na3\na4");
+        let regex = Regex::new("a\\(\\d\\)").unwrap();
+        let substitution = Substitution::new(regex, "<\\1>".to_string(), true, &buffer, 0..=1);
+        assert_eq!(
+            substitution.preview(&buffer),
+            ("<1> <2>\n<3>\na4".to_string(), vec![0..3, 4..7, 8..11])
+        );
+        assert_eq!(buffer.contents(), &"a1 a2\na3\na4");
+    }
+}
@@ -0,0 +1,188 @@
+//! Finding the brackets, quotes and tags around some text, so that `ys`, `cs` and `ds` can add, change and delete them.
+use std::ops::Range;
+
+/// What to put either side of some text to surround it with `c`, like `(` and `)`.
+///
+/// Like vim-surround, an opening bracket puts a space inside each side too, and `b`, `B`, `r` and `a` stand for `)`, `}`, `]` and
+/// `>`. Any other punctuation goes on both sides as it is. Tags are asked for separately, so `<` and `t` don't give anything.
+pub fn delimiters(c: char) -> Option<(String, String)> {
+    let (open, close) = match c {
+        '(' => ("( ", " )"),
+        ')' | 'b' => ("(", ")"),
+        '[' => ("[ ", " ]"),
+        ']' | 'r' => ("[", "]"),
+        '{' => ("{ ", " }"),
+        '}' | 'B' => ("{", "}"),
+        '>' | 'a' => ("<", ">"),
+        '<' | 't' => return None,
+        c if c.is_ascii_punctuation() => return Some((c.to_string(), c.to_string())),
+        _ => return None,
+    };
+    Some((open.to_string(), close.to_string()))
+}
+
+/// Whether `c` stands for a tag, which has to be typed in at a prompt before anything can be surrounded with it.
+pub fn is_tag(c: char) -> bool {
+    matches!(c, '<' | 't')
+}
+
+/// The opening and closing tags for one typed in at the prompt, like `a href="x"`, which only needs the name in the closing tag.
+pub fn tag(text: &str) -> Option<(String, String)> {
+    let text = text.trim().trim_start_matches('<').trim_end_matches('>');
+    let name = text.split_whitespace().next()?;
+    Some((format!("<{}>", text), format!("</{}>", name)))
+}
+
+/// Finds the delimiters that `c` stands for around `index` in `text`, returning where the opening and closing ones are.
+///
+/// The innermost brackets or tags that `index` is inside (or on) are found, while quotes (and other punctuation) have to be
+/// on the same line. An opening bracket takes any spaces just inside the brackets along with them, like vim-surround.
+pub fn find(text: &str, index: usize, c: char) -> Option<(Range<usize>, Range<usize>)> {
+    let (open, close, trim) = match c {
+        '(' => ('(', ')', true),
+        ')' | 'b' => ('(', ')', false),
+        '[' => ('[', ']', true),
+        ']' | 'r' => ('[', ']', false),
+        '{' => ('{', '}', true),
+        '}' | 'B' => ('{', '}', false),
+        '<' => ('<', '>', true),
+        '>' | 'a' => ('<', '>', false),
+        't' => return find_tags(text, index),
+        c if c.is_ascii_punctuation() => return find_quotes(text, index, c),
+        _ => return None,
+    };
+
+    let mut stack = Vec::new();
+    let mut found: Option<(usize, usize)> = None;
+    for (i, ch) in text.char_indices() {
+        if ch == open {
+            stack.push(i);
+        } else if ch == close {
+            if let Some(start) = stack.pop() {
+                let contains = start <= index && index <= i;
+                if contains && found.is_none_or(|(s, _)| start > s) {
+                    found = Some((start, i));
+                }
+            }
+        }
+    }
+    let (start, end) = found?;
+    let mut open_range = start..start + open.len_utf8();
+    let mut close_range = end..end + close.len_utf8();
+    if trim {
+        let inside = &text[open_range.end..close_range.start];
+        let after_open = inside.len() - inside.trim_start_matches([' ', '\t']).len();
+        open_range.end += after_open;
+        let before_close = inside.len() - inside.trim_end_matches([' ', '\t']).len();
+        close_range.start = std::cmp::max(close_range.start - before_close, open_range.end);
+    }
+    Some((open_range, close_range))
+}
+
+/// Finds a pair of `quote`s around `index` on its line, going by the ones before it on the line to tell which are opening ones.
+fn find_quotes(text: &str, index: usize, quote: char) -> Option<(Range<usize>, Range<usize>)> {
+    let line_start = text[..index].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[index..].find('\n').map_or(text.len(), |i| index + i);
+    let quotes: Vec<usize> = text[line_start..line_end]
+        .match_indices(quote)
+        .map(|(i, _)| line_start + i)
+        .collect();
+    let len = quote.len_utf8();
+    quotes
+        .chunks_exact(2)
+        .find(|pair| pair[0] <= index && index <= pair[1])
+        .map(|pair| (pair[0]..pair[0] + len, pair[1]..pair[1] + len))
+}
+
+/// Finds the innermost pair of tags, like `<p>` and `</p>`, that `index` is inside or on.
+fn find_tags(text: &str, index: usize) -> Option<(Range<usize>, Range<usize>)> {
+    let mut stack: Vec<(&str, Range<usize>)> = Vec::new();
+    let mut found: Option<(Range<usize>, Range<usize>)> = None;
+    let mut position = 0;
+    while let Some(offset) = text[position..].find('<') {
+        let start = position + offset;
+        let end = match text[start..].find('>') {
+            Some(offset) => start + offset + 1,
+            None => break,
+        };
+        position = end;
+        let inside = &text[start + 1..end - 1];
+        // Comments, doctypes and tags that close themselves don't have a closing tag to pair up with.
+        if inside.starts_with(['!', '?']) || inside.ends_with('/') {
+            continue;
+        }
+        if let Some(name) = inside.strip_prefix('/') {
+            let name = name.trim();
+            // A closing tag closes any that were left open inside it too.
+            if let Some(i) = stack.iter().rposition(|(open, _)| *open == name) {
+                let open = stack[i].1.clone();
+                stack.truncate(i);
+                let contains = open.start <= index && index < end;
+                if contains && found.as_ref().is_none_or(|(o, _)| open.start > o.start) {
+                    found = Some((open, start..end));
+                }
+            }
+        } else {
+            let name = inside
+                .split(|c: char| c.is_whitespace())
+                .next()
+                .unwrap_or("");
+            stack.push((name, start..end));
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn strings(open: &str, close: &str) -> Option<(String, String)> {
+        Some((open.to_string(), close.to_string()))
+    }
+
+    #[test]
+    fn test_delimiters() {
+        assert_eq!(delimiters(')'), strings("(", ")"));
+        assert_eq!(delimiters('b'), strings("(", ")"));
+        assert_eq!(delimiters('{'), strings("{ ", " }"));
+        assert_eq!(delimiters('"'), strings("\"", "\""));
+        assert_eq!(delimiters('t'), None);
+        assert_eq!(delimiters('x'), None);
+        assert_eq!(tag("a href=\"x\""), strings("<a href=\"x\">", "</a>"));
+        assert_eq!(tag("<em>"), strings("<em>", "</em>"));
+        assert_eq!(tag(" "), None);
+    }
+
+    #[test]
+    fn test_finding_brackets() {
+        let text = "f(a, (b), c)";
+        assert_eq!(find(text, 3, ')'), Some((1..2, 11..12)));
+        assert_eq!(find(text, 6, 'b'), Some((5..6, 7..8)));
+        assert_eq!(find(text, 5, ')'), Some((5..6, 7..8)));
+        assert_eq!(find(text, 0, ')'), None);
+        assert_eq!(find(text, 3, ']'), None);
+
+        // Opening brackets take the spaces inside along with them.
+        assert_eq!(find("[  x ]", 3, '['), Some((0..3, 4..6)));
+        assert_eq!(find("[  ]", 1, '['), Some((0..3, 3..4)));
+    }
+
+    #[test]
+    fn test_finding_quotes() {
+        let text = "say \"hi\" and \"bye\"\n\"next\"";
+        assert_eq!(find(text, 6, '"'), Some((4..5, 7..8)));
+        assert_eq!(find(text, 15, '"'), Some((13..14, 17..18)));
+        // The text between two quoted strings isn't quoted, and quotes don't carry on onto the next line.
+        assert_eq!(find(text, 10, '"'), None);
+        assert_eq!(find(text, 21, '"'), Some((19..20, 24..25)));
+    }
+
+    #[test]
+    fn test_finding_tags() {
+        let text = "<div class=\"x\"><p>one<br/></p><!-- c --></div>";
+        assert_eq!(find(text, 19, 't'), Some((15..18, 26..30)));
+        assert_eq!(find(text, 31, 't'), Some((0..15, 40..46)));
+        assert_eq!(find("<p>unclosed", 4, 't'), None);
+    }
+}
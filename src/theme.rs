@@ -0,0 +1,349 @@
+//! The colours that each part of the display is drawn in, which a theme file can change.
+//!
+//! A theme file is written in the same subset of TOML as the configuration file, with a table for each part of the
+//! display that it changes, like this:
+//!
+//! ```toml
+//! [text]
+//! foreground = "#d0d0d0"
+//! background = "#1c1c1c"
+//!
+//! [status_line]
+//! foreground = 231
+//! background = "dark_blue"
+//! bold = true
+//! ```
+//!
+//! Colours can be written as `#rrggbb`, as one of the terminal's 256 colours by number, or by the name of one of the basic
+//! sixteen (like `dark_grey`.) A table replaces how that part of the display is drawn entirely, and whatever colours it
+//! leaves out come from `[text]`.
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use crossterm::style::{Attribute, Color, ContentStyle, Stylize};
+
+use crate::config::{self, ConfigError, Entry, Value};
+
+/// The parts of the display that a theme file can have a table for.
+const ELEMENTS: &[&str] = &[
+    "text",
+    "selection",
+    "matching_bracket",
+    "changed_text",
+    "clipped",
+    "end_of_buffer",
+    "whitespace",
+    "cursor_line",
+    "color_column",
+    "line_numbers",
+    "status_line",
+    "window_title",
+    "window_title_focused",
+    "error_message",
+    "error",
+    "warning",
+    "information",
+    "added",
+    "modified",
+    "removed",
+    "popup",
+    "popup_selected",
+];
+
+/// How each part of the display is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    /// The text in the buffers, along with the blank space around it.
+    pub text: ContentStyle,
+    /// Selected text and the matches of a search.
+    pub selection: ContentStyle,
+    /// The bracket by the cursor and the one that it pairs up with.
+    pub matching_bracket: ContentStyle,
+    /// Text that's different from the file the buffer is being compared with.
+    pub changed_text: ContentStyle,
+    /// The `<` and `>` that mark lines running off the sides of the display.
+    pub clipped: ContentStyle,
+    /// The `~` at the start of the rows past the end of the buffer.
+    pub end_of_buffer: ContentStyle,
+    /// Tabs, trailing spaces and non-breaking spaces, when `:set list` shows them.
+    pub whitespace: ContentStyle,
+    /// The line the cursor is on, when `:set cursorline` picks it out.
+    pub cursor_line: ContentStyle,
+    /// The rulers that `:set colorcolumn` draws down the view.
+    pub color_column: ContentStyle,
+    pub line_numbers: ContentStyle,
+    pub status_line: ContentStyle,
+    /// The bar along the top of a window, when the display is split.
+    pub window_title: ContentStyle,
+    pub window_title_focused: ContentStyle,
+    /// A message on the bottom row that says something went wrong.
+    pub error_message: ContentStyle,
+    /// Problems that linters and language servers found, by how bad they are.
+    pub error: ContentStyle,
+    pub warning: ContentStyle,
+    pub information: ContentStyle,
+    /// The signs in the gutter for lines that have changed since the last commit.
+    pub added: ContentStyle,
+    pub modified: ContentStyle,
+    pub removed: ContentStyle,
+    /// The list of words to finish one off with, and the one picked out of it.
+    pub popup: ContentStyle,
+    pub popup_selected: ContentStyle,
+}
+
+impl Default for Theme {
+    /// Sticks to the terminal's own colours, along with the few basic ones that every terminal has.
+    fn default() -> Self {
+        let plain = ContentStyle::new();
+        Self {
+            text: plain,
+            selection: plain.reverse(),
+            matching_bracket: plain.on_dark_cyan(),
+            changed_text: plain.black().on_red(),
+            clipped: plain.reverse(),
+            end_of_buffer: plain.blue(),
+            whitespace: plain.dark_grey(),
+            cursor_line: plain.underlined(),
+            color_column: plain.on_dark_red(),
+            line_numbers: plain.dark_grey(),
+            status_line: plain.reverse(),
+            window_title: plain.reverse(),
+            window_title_focused: plain.reverse().bold(),
+            error_message: plain.red(),
+            error: plain.red(),
+            warning: plain.yellow(),
+            information: plain.dark_grey(),
+            added: plain.green(),
+            modified: plain.yellow(),
+            removed: plain.red(),
+            popup: plain.on_dark_grey(),
+            popup_selected: plain.reverse(),
+        }
+    }
+}
+
+impl Theme {
+    /// Reads the theme file at `path`, turning true colours into the nearest of the 256 if `truecolor` isn't set.
+    pub fn load(path: &Path, truecolor: bool) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        Self::parse(&text, truecolor)
+    }
+
+    /// Parses the contents of a theme file, starting from the default theme for anything that it leaves out.
+    pub fn parse(text: &str, truecolor: bool) -> Result<Self, ConfigError> {
+        let mut theme = Self::default();
+        let mut replaced = Vec::new();
+        for entry in config::parse_entries(text)? {
+            let invalid = |reason| ConfigError::Invalid {
+                line: entry.line,
+                reason,
+            };
+            let style = theme
+                .element_mut(&entry.table)
+                .ok_or_else(|| invalid(format!("Unknown table [{}]", entry.table)))?;
+            // The first time a table turns up, it starts again from nothing rather than adding to the default.
+            if !replaced.contains(&entry.table) {
+                *style = ContentStyle::new();
+                replaced.push(entry.table.clone());
+            }
+            set(style, &entry, truecolor).map_err(invalid)?;
+        }
+
+        // Everything is drawn over the text's background, and in its colour unless it has one of its own.
+        let text = theme.text;
+        for name in ELEMENTS {
+            if let Some(style) = theme.element_mut(name) {
+                style.foreground_color = style.foreground_color.or(text.foreground_color);
+                style.background_color = style.background_color.or(text.background_color);
+            }
+        }
+        Ok(theme)
+    }
+
+    /// Finds the style for the part of the display with the given name.
+    fn element_mut(&mut self, name: &str) -> Option<&mut ContentStyle> {
+        Some(match name {
+            "text" => &mut self.text,
+            "selection" => &mut self.selection,
+            "matching_bracket" => &mut self.matching_bracket,
+            "changed_text" => &mut self.changed_text,
+            "clipped" => &mut self.clipped,
+            "end_of_buffer" => &mut self.end_of_buffer,
+            "whitespace" => &mut self.whitespace,
+            "cursor_line" => &mut self.cursor_line,
+            "color_column" => &mut self.color_column,
+            "line_numbers" => &mut self.line_numbers,
+            "status_line" => &mut self.status_line,
+            "window_title" => &mut self.window_title,
+            "window_title_focused" => &mut self.window_title_focused,
+            "error_message" => &mut self.error_message,
+            "error" => &mut self.error,
+            "warning" => &mut self.warning,
+            "information" => &mut self.information,
+            "added" => &mut self.added,
+            "modified" => &mut self.modified,
+            "removed" => &mut self.removed,
+            "popup" => &mut self.popup,
+            "popup_selected" => &mut self.popup_selected,
+            _ => return None,
+        })
+    }
+}
+
+/// Whether the terminal says that it can show true colours, which most that can do announce in `COLORTERM`.
+pub fn supports_truecolor() -> bool {
+    env::var("COLORTERM")
+        .is_ok_and(|value| matches!(value.to_lowercase().as_str(), "truecolor" | "24bit"))
+}
+
+/// Changes one thing about how a part of the display is drawn.
+fn set(style: &mut ContentStyle, entry: &Entry, truecolor: bool) -> Result<(), String> {
+    let key = entry.key.as_str();
+    match key {
+        "foreground" => style.foreground_color = Some(parse_color(key, &entry.value, truecolor)?),
+        "background" => style.background_color = Some(parse_color(key, &entry.value, truecolor)?),
+        "bold" | "italic" | "underline" | "reverse" => {
+            let attribute = match key {
+                "bold" => Attribute::Bold,
+                "italic" => Attribute::Italic,
+                "underline" => Attribute::Underlined,
+                _ => Attribute::Reverse,
+            };
+            match config::expect_bool(key, &entry.value)? {
+                true => style.attributes.set(attribute),
+                false => style.attributes.unset(attribute),
+            }
+        }
+        _ => return Err(format!("Unknown key {}", key)),
+    }
+    Ok(())
+}
+
+/// Reads a colour, which is `#rrggbb`, a number from 0 to 255, or the name of one of the basic colours.
+fn parse_color(key: &str, value: &Value, truecolor: bool) -> Result<Color, String> {
+    let invalid = || {
+        format!(
+            "{} should be \"#rrggbb\", a number from 0 to 255 or a colour's name",
+            key
+        )
+    };
+    match value {
+        Value::Integer(n) => u8::try_from(*n)
+            .map(Color::AnsiValue)
+            .map_err(|_| invalid()),
+        Value::String(s) => match s.strip_prefix('#') {
+            Some(hex) if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0);
+                let (r, g, b) = (channel(0), channel(2), channel(4));
+                Ok(match truecolor {
+                    true => Color::Rgb { r, g, b },
+                    false => Color::AnsiValue(nearest_ansi(r, g, b)),
+                })
+            }
+            Some(_) => Err(invalid()),
+            None => Color::try_from(s.as_str()).map_err(|_| invalid()),
+        },
+        Value::Bool(_) => Err(invalid()),
+    }
+}
+
+/// Finds the closest of the 256 colours to a true colour, out of the 6×6×6 cube and the ramp of greys.
+fn nearest_ansi(r: u8, g: u8, b: u8) -> u8 {
+    // The levels that each channel of the cube has, which aren't evenly spread.
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let level = |c: u8| {
+        (0..LEVELS.len())
+            .min_by_key(|&i| LEVELS[i].abs_diff(c))
+            .unwrap_or(0)
+    };
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = (LEVELS[ri], LEVELS[gi], LEVELS[bi]);
+
+    // The greys go from 8 to 238 in steps of 10.
+    let average = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+    let grey_index = ((average.saturating_sub(3)) / 10).min(23) as u8;
+    let grey = 8 + 10 * grey_index;
+
+    let distance = |(cr, cg, cb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| u32::from(a.abs_diff(b)).pow(2);
+        d(cr, r) + d(cg, g) + d(cb, b)
+    };
+    match distance((grey, grey, grey)) < distance(cube) {
+        true => 232 + grey_index,
+        false => (16 + 36 * ri + 6 * gi + bi) as u8,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parsing_themes() {
+        let text = "\
+[text]
+foreground = '#d0d0d0'
+background = 234
+
+[status_line]
+foreground = 'white'
+bold = true
+";
+        let theme = Theme::parse(text, true).unwrap();
+        assert_eq!(
+            theme.text.foreground_color,
+            Some(Color::Rgb {
+                r: 0xd0,
+                g: 0xd0,
+                b: 0xd0
+            })
+        );
+        // The status line is no longer reversed, and takes the text's background.
+        assert_eq!(
+            theme.status_line,
+            ContentStyle::new().white().on(Color::AnsiValue(234)).bold()
+        );
+        assert_eq!(
+            theme.selection,
+            ContentStyle::new()
+                .reverse()
+                .with(Color::Rgb {
+                    r: 0xd0,
+                    g: 0xd0,
+                    b: 0xd0
+                })
+                .on(Color::AnsiValue(234))
+        );
+
+        let theme = Theme::parse("[text]\nforeground = '#d0d0d0'", false).unwrap();
+        assert_eq!(theme.text.foreground_color, Some(Color::AnsiValue(252)));
+    }
+
+    #[test]
+    fn test_invalid_themes() {
+        let error = |text| Theme::parse(text, true).unwrap_err().to_string();
+        assert_eq!(
+            error("[sidebar]\nbold = true"),
+            "line 2: Unknown table [sidebar]"
+        );
+        assert_eq!(error("[text]\nblink = true"), "line 2: Unknown key blink");
+        assert_eq!(
+            error("[text]\nforeground = '#ffff'"),
+            "line 2: foreground should be \"#rrggbb\", a number from 0 to 255 or a colour's name"
+        );
+        assert_eq!(
+            error("[text]\nbackground = 256"),
+            "line 2: background should be \"#rrggbb\", a number from 0 to 255 or a colour's name"
+        );
+    }
+
+    #[test]
+    fn test_nearest_ansi_colours() {
+        assert_eq!(nearest_ansi(0, 0, 0), 16);
+        assert_eq!(nearest_ansi(255, 255, 255), 231);
+        assert_eq!(nearest_ansi(255, 0, 0), 196);
+        assert_eq!(nearest_ansi(0x80, 0x80, 0x80), 244);
+        assert_eq!(nearest_ansi(0x87, 0xaf, 0xd7), 110);
+    }
+}
@@ -0,0 +1,157 @@
+//! Timers that let the editor get things done while it would otherwise be sat waiting for a key press.
+use std::time::{Duration, Instant};
+
+/// The things that the editor can be asked to do when a timer fires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerEvent {
+    /// Clear the message from the bottom of the display.
+    ClearMessage,
+    /// Write any unsaved changes out into the recovery file.
+    WriteRecoveryFile,
+    /// Write any unsaved changes out into the file itself.
+    Autosave,
+    /// Stop waiting for the rest of a key mapping.
+    KeyTimeout,
+    /// Check whether something else has changed the file being edited.
+    CheckFile,
+    /// Pick up whatever the language servers have sent, and send them any changes.
+    PollLanguageServers,
+    /// Pick up the results of any linters that have finished.
+    PollLinters,
+}
+
+#[derive(Debug)]
+struct Timer {
+    event: TimerEvent,
+    /// How long to wait before firing.
+    delay: Duration,
+    /// When the timer is next due to fire, if it's due to fire at all.
+    deadline: Option<Instant>,
+    /// Idle timers are restarted on every input from the user, rather than being removed once they fire.
+    idle: bool,
+}
+
+/// The set of timers registered with the editor.
+#[derive(Debug, Default)]
+pub struct Timers {
+    timers: Vec<Timer>,
+}
+
+impl Timers {
+    /// Fires `event` once, after `delay` has passed.
+    pub fn after(&mut self, now: Instant, delay: Duration, event: TimerEvent) {
+        self.timers.push(Timer {
+            event,
+            delay,
+            deadline: Some(now + delay),
+            idle: false,
+        });
+    }
+
+    /// Fires `event` each time the user leaves the editor alone for `delay`.
+    pub fn on_idle(&mut self, now: Instant, delay: Duration, event: TimerEvent) {
+        self.timers.push(Timer {
+            event,
+            delay,
+            deadline: Some(now + delay),
+            idle: true,
+        });
+    }
+
+    /// Removes any timers that would fire `event`.
+    pub fn cancel(&mut self, event: TimerEvent) {
+        self.timers.retain(|t| t.event != event);
+    }
+
+    /// Lets the timers know that the user has just done something, which restarts all of the idle timers.
+    pub fn input(&mut self, now: Instant) {
+        for timer in self.timers.iter_mut().filter(|t| t.idle) {
+            timer.deadline = Some(now + timer.delay);
+        }
+    }
+
+    /// Determines how long it is until the next timer fires, if there are any waiting to.
+    pub fn timeout(&self, now: Instant) -> Option<Duration> {
+        self.timers
+            .iter()
+            .filter_map(|t| t.deadline)
+            .min()
+            .map(|deadline| deadline.saturating_duration_since(now))
+    }
+
+    /// Collects the events for every timer that has fired by `now`.
+    pub fn expired(&mut self, now: Instant) -> Vec<TimerEvent> {
+        let mut events = Vec::new();
+        for timer in self.timers.iter_mut() {
+            if timer.deadline.map(|d| d <= now).unwrap_or(false) {
+                events.push(timer.event);
+                timer.deadline = None;
+            }
+        }
+
+        // Idle timers hang around until the next input, everything else is done with.
+        self.timers.retain(|t| t.idle || t.deadline.is_some());
+        events
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_one_shot_timer() {
+        let start = Instant::now();
+        let mut timers = Timers::default();
+        timers.after(start, Duration::from_millis(100), TimerEvent::ClearMessage);
+
+        assert_eq!(timers.timeout(start), Some(Duration::from_millis(100)));
+        assert_eq!(timers.expired(start + Duration::from_millis(50)), vec![]);
+        assert_eq!(
+            timers.expired(start + Duration::from_millis(100)),
+            vec![TimerEvent::ClearMessage]
+        );
+        assert_eq!(timers.timeout(start + Duration::from_millis(100)), None);
+    }
+
+    #[test]
+    fn test_idle_timer_restarts_on_input() {
+        let start = Instant::now();
+        let mut timers = Timers::default();
+        timers.on_idle(
+            start,
+            Duration::from_millis(100),
+            TimerEvent::WriteRecoveryFile,
+        );
+
+        timers.input(start + Duration::from_millis(80));
+        assert_eq!(timers.expired(start + Duration::from_millis(100)), vec![]);
+        assert_eq!(
+            timers.expired(start + Duration::from_millis(180)),
+            vec![TimerEvent::WriteRecoveryFile]
+        );
+
+        // It shouldn't fire again until the user has done something.
+        assert_eq!(timers.timeout(start + Duration::from_millis(500)), None);
+        timers.input(start + Duration::from_millis(500));
+        assert_eq!(
+            timers.timeout(start + Duration::from_millis(500)),
+            Some(Duration::from_millis(100))
+        );
+    }
+
+    #[test]
+    fn test_cancelling_timers() {
+        let start = Instant::now();
+        let mut timers = Timers::default();
+        timers.after(start, Duration::from_millis(100), TimerEvent::ClearMessage);
+        timers.on_idle(
+            start,
+            Duration::from_millis(200),
+            TimerEvent::WriteRecoveryFile,
+        );
+
+        timers.cancel(TimerEvent::ClearMessage);
+        assert_eq!(timers.timeout(start), Some(Duration::from_millis(200)));
+    }
+}
@@ -0,0 +1,70 @@
+//! The project configuration files that the user has agreed to let run commands.
+//!
+//! A `.te.toml` comes along with whatever was cloned or unpacked, so its commands aren't run until the user has said they
+//! can be. Files are trusted as they were when the user looked at them: changing one means asking again.
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Works out a fingerprint of a file's contents (64-bit FNV-1a), which is enough to notice that it has been changed.
+fn fingerprint(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Writes out the line that the list of trusted files keeps for a file.
+fn entry(path: &Path, text: &str) -> String {
+    format!("{:016x} {}", fingerprint(text), path.display())
+}
+
+/// Checks whether the file at `path` has been trusted with exactly the contents `text`, according to the list in `list`.
+pub fn is_trusted(list: &Path, path: &Path, text: &str) -> bool {
+    let entry = entry(path, text);
+    fs::read_to_string(list).is_ok_and(|trusted| trusted.lines().any(|line| line == entry))
+}
+
+/// Adds the file at `path` with the contents `text` to the list in `list`, in place of any earlier version of it.
+pub fn trust(list: &Path, path: &Path, text: &str) -> io::Result<()> {
+    let suffix = format!(" {}", path.display());
+    let mut lines: Vec<String> = match fs::read_to_string(list) {
+        Ok(trusted) => trusted
+            .lines()
+            .filter(|line| !line.ends_with(&suffix))
+            .map(str::to_string)
+            .collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    lines.push(entry(path, text));
+    if let Some(dir) = list.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(list, lines.join("\n") + "\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_trusting_files() {
+        let dir = TempDir::new("trust");
+        let list = dir.join("state").join("trusted");
+        let project = dir.join("project").join(".te.toml");
+        let other = dir.join("other").join(".te.toml");
+
+        assert!(!is_trusted(&list, &project, "make_command = \"make\""));
+        trust(&list, &project, "make_command = \"make\"").unwrap();
+        trust(&list, &other, "make_command = \"cargo build\"").unwrap();
+        assert!(is_trusted(&list, &project, "make_command = \"make\""));
+        assert!(is_trusted(&list, &other, "make_command = \"cargo build\""));
+
+        // Changing the file means it has to be trusted again, and trusting it again forgets the old version.
+        assert!(!is_trusted(&list, &project, "make_command = \"rm -rf ~\""));
+        trust(&list, &project, "make_command = \"make -j\"").unwrap();
+        assert!(!is_trusted(&list, &project, "make_command = \"make\""));
+        assert_eq!(fs::read_to_string(&list).unwrap().lines().count(), 2);
+    }
+}
@@ -0,0 +1,243 @@
+//! Keeps track of the changes made to a buffer, so that they can be undone (and redone.)
+
+/// A single change to the contents of a buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// `text` was inserted into the buffer at `index`.
+    Insert { index: usize, text: String },
+    /// `text` was removed from the buffer, starting at `index`.
+    Remove { index: usize, text: String },
+}
+
+impl Change {
+    /// Produces the change that would put things back the way they were before this one.
+    pub fn inverse(&self) -> Change {
+        match self {
+            Change::Insert { index, text } => Change::Remove {
+                index: *index,
+                text: text.clone(),
+            },
+            Change::Remove { index, text } => Change::Insert {
+                index: *index,
+                text: text.clone(),
+            },
+        }
+    }
+}
+
+/// A group of changes that are undone and redone together, such as everything typed in one visit to Edit mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndoStep {
+    /// The changes that make up the step, in the order that they were made.
+    pub changes: Vec<Change>,
+    /// The position of the cursor in the buffer before the step was made.
+    pub cursor: usize,
+}
+
+/// The history of changes to a buffer.
+#[derive(Debug)]
+pub struct UndoStack {
+    undo: Vec<UndoStep>,
+    redo: Vec<UndoStep>,
+    /// The step that changes are currently being collected into.
+    open: Option<UndoStep>,
+    /// How many steps into the history the buffer was when it was last written, while that point can still be got back to.
+    ///
+    /// Undoing and redoing back to it leaves the buffer the same as the file, so it's no longer modified.
+    saved: Option<usize>,
+}
+
+impl Default for UndoStack {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            open: None,
+            saved: Some(0),
+        }
+    }
+}
+
+impl UndoStack {
+    /// Records a change, adding it to the open step or starting a new step if there isn't one.
+    ///
+    /// `cursor` should be the position of the cursor before the change was made.
+    /// Making a new change means that anything that was undone can't be redone anymore.
+    pub fn record(&mut self, change: Change, cursor: usize) {
+        // Throwing away the steps that could have been redone throws away the written point too, if it was among them.
+        if self.saved.is_some_and(|saved| saved > self.undo.len()) {
+            self.saved = None;
+        }
+        self.redo.clear();
+
+        let step = self.open.get_or_insert_with(|| UndoStep {
+            changes: Vec::new(),
+            cursor,
+        });
+
+        // Typing (or backspacing over) a run of characters is stored as a single change, rather than one per character.
+        match (step.changes.last_mut(), change) {
+            (
+                Some(Change::Insert { index, text }),
+                Change::Insert {
+                    index: next_index,
+                    text: next_text,
+                },
+            ) if *index + text.len() == next_index => text.push_str(&next_text),
+            (
+                Some(Change::Remove { index, text }),
+                Change::Remove {
+                    index: next_index,
+                    text: next_text,
+                },
+            ) if next_index + next_text.len() == *index => {
+                text.insert_str(0, &next_text);
+                *index = next_index;
+            }
+            (_, change) => step.changes.push(change),
+        }
+    }
+
+    /// Closes the open step, so that the next change starts a new one.
+    pub fn commit(&mut self) {
+        if let Some(step) = self.open.take() {
+            self.undo.push(step);
+        }
+    }
+
+    /// Remembers that the buffer has just been written, closing the open step so that the next change starts a new one.
+    pub fn mark_saved(&mut self) {
+        self.commit();
+        self.saved = Some(self.undo.len());
+    }
+
+    /// Whether the changes in the history leave the buffer where it was when it was last written.
+    pub fn is_at_saved(&self) -> bool {
+        self.open.is_none() && self.saved == Some(self.undo.len())
+    }
+
+    /// Takes the most recent step off the history, so that its changes can be reversed (from last to first.)
+    pub fn undo(&mut self) -> Option<UndoStep> {
+        self.commit();
+        let step = self.undo.pop()?;
+        self.redo.push(step.clone());
+        Some(step)
+    }
+
+    /// Takes the most recently undone step, so that its changes can be made again.
+    pub fn redo(&mut self) -> Option<UndoStep> {
+        self.commit();
+        let step = self.redo.pop()?;
+        self.undo.push(step.clone());
+        Some(step)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn insert(index: usize, text: &str) -> Change {
+        Change::Insert {
+            index,
+            text: text.to_string(),
+        }
+    }
+
+    fn remove(index: usize, text: &str) -> Change {
+        Change::Remove {
+            index,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_typing_is_coalesced() {
+        let mut stack = UndoStack::default();
+        stack.record(insert(3, "a"), 3);
+        stack.record(insert(4, "b"), 4);
+        stack.record(insert(0, "c"), 5);
+        stack.commit();
+
+        assert_eq!(
+            stack.undo(),
+            Some(UndoStep {
+                changes: vec![insert(3, "ab"), insert(0, "c")],
+                cursor: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn test_backspacing_is_coalesced() {
+        let mut stack = UndoStack::default();
+        stack.record(remove(4, "c"), 5);
+        stack.record(remove(3, "b"), 4);
+        stack.commit();
+
+        assert_eq!(
+            stack.undo(),
+            Some(UndoStep {
+                changes: vec![remove(3, "bc")],
+                cursor: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn test_undo_and_redo() {
+        let mut stack = UndoStack::default();
+        stack.record(insert(0, "a"), 0);
+        stack.commit();
+        stack.record(insert(5, "b"), 5);
+
+        assert_eq!(stack.undo().map(|s| s.cursor), Some(5));
+        assert_eq!(stack.undo().map(|s| s.cursor), Some(0));
+        assert_eq!(stack.undo(), None);
+
+        assert_eq!(stack.redo().map(|s| s.cursor), Some(0));
+        assert_eq!(stack.redo().map(|s| s.cursor), Some(5));
+        assert_eq!(stack.redo(), None);
+    }
+
+    #[test]
+    fn test_finding_the_saved_point() {
+        let mut stack = UndoStack::default();
+        assert!(stack.is_at_saved());
+        stack.record(insert(0, "a"), 0);
+        assert!(!stack.is_at_saved());
+        stack.mark_saved();
+        assert!(stack.is_at_saved());
+
+        stack.record(insert(1, "b"), 1);
+        stack.undo();
+        assert!(stack.is_at_saved());
+        stack.undo();
+        assert!(!stack.is_at_saved());
+        stack.redo();
+        assert!(stack.is_at_saved());
+
+        // Once a different change takes the place of the written one, there's no getting back to it.
+        stack.undo();
+        stack.record(insert(0, "c"), 0);
+        stack.undo();
+        assert!(!stack.is_at_saved());
+        stack.redo();
+        assert!(!stack.is_at_saved());
+    }
+
+    #[test]
+    fn test_new_changes_clear_redo() {
+        let mut stack = UndoStack::default();
+        stack.record(insert(0, "a"), 0);
+        stack.undo();
+        stack.record(insert(0, "b"), 0);
+        assert_eq!(stack.redo(), None);
+    }
+
+    #[test]
+    fn test_inverse() {
+        assert_eq!(insert(1, "ab").inverse(), remove(1, "ab"));
+        assert_eq!(remove(1, "ab").inverse(), insert(1, "ab"));
+    }
+}
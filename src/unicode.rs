@@ -0,0 +1,196 @@
+//! Bits of Unicode handling that the standard library doesn't provide.
+use std::cmp::Ordering;
+use unicode_width::UnicodeWidthChar;
+
+mod tables;
+
+use tables::{CANONICAL_DECOMPOSITIONS, COMBINING_CLASSES, COMPOSITIONS};
+
+// Hangul syllables are laid out so that they can be (de)composed with some arithmetic, rather than a table.
+const HANGUL_S_BASE: u32 = 0xAC00;
+const HANGUL_L_BASE: u32 = 0x1100;
+const HANGUL_V_BASE: u32 = 0x1161;
+const HANGUL_T_BASE: u32 = 0x11A7;
+const HANGUL_L_COUNT: u32 = 19;
+const HANGUL_V_COUNT: u32 = 21;
+const HANGUL_T_COUNT: u32 = 28;
+const HANGUL_N_COUNT: u32 = HANGUL_V_COUNT * HANGUL_T_COUNT;
+const HANGUL_S_COUNT: u32 = HANGUL_L_COUNT * HANGUL_N_COUNT;
+
+/// Looks up the canonical combining class of a character, which is zero for "starters".
+fn combining_class(c: char) -> u8 {
+    COMBINING_CLASSES
+        .binary_search_by(|&(first, last, _)| {
+            if last < c {
+                Ordering::Less
+            } else if first > c {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+        .map(|i| COMBINING_CLASSES[i].2)
+        .unwrap_or(0)
+}
+
+/// Works out how many cells of the display a character takes up when it's drawn on its own.
+///
+/// Accents and other marks that attach to the character before them don't take up any room of their own.
+/// Control characters don't have a width, so they're given one cell like any other character.
+pub fn width(c: char) -> usize {
+    UnicodeWidthChar::width(c).unwrap_or(1)
+}
+
+/// Pushes the full canonical decomposition of `c` onto the end of `out`.
+fn decompose(c: char, out: &mut Vec<char>) {
+    let s_index = (c as u32).wrapping_sub(HANGUL_S_BASE);
+    if s_index < HANGUL_S_COUNT {
+        let l = HANGUL_L_BASE + s_index / HANGUL_N_COUNT;
+        let v = HANGUL_V_BASE + (s_index % HANGUL_N_COUNT) / HANGUL_T_COUNT;
+        let t = HANGUL_T_BASE + s_index % HANGUL_T_COUNT;
+        out.extend(char::from_u32(l));
+        out.extend(char::from_u32(v));
+        if t != HANGUL_T_BASE {
+            out.extend(char::from_u32(t));
+        }
+        return;
+    }
+
+    match CANONICAL_DECOMPOSITIONS.binary_search_by_key(&c, |&(d, _)| d) {
+        Ok(i) => CANONICAL_DECOMPOSITIONS[i]
+            .1
+            .iter()
+            .for_each(|&d| decompose(d, out)),
+        Err(_) => out.push(c),
+    }
+}
+
+/// Finds the primary composite for a pair of characters, if there is one.
+fn compose(a: char, b: char) -> Option<char> {
+    let l_index = (a as u32).wrapping_sub(HANGUL_L_BASE);
+    let v_index = (b as u32).wrapping_sub(HANGUL_V_BASE);
+    if l_index < HANGUL_L_COUNT && v_index < HANGUL_V_COUNT {
+        return char::from_u32(
+            HANGUL_S_BASE + (l_index * HANGUL_V_COUNT + v_index) * HANGUL_T_COUNT,
+        );
+    }
+
+    let s_index = (a as u32).wrapping_sub(HANGUL_S_BASE);
+    let t_index = (b as u32).wrapping_sub(HANGUL_T_BASE);
+    if s_index < HANGUL_S_COUNT
+        && s_index.is_multiple_of(HANGUL_T_COUNT)
+        && t_index > 0
+        && t_index < HANGUL_T_COUNT
+    {
+        return char::from_u32(a as u32 + t_index);
+    }
+
+    COMPOSITIONS
+        .binary_search_by_key(&(a, b), |&(pair, _)| pair)
+        .ok()
+        .map(|i| COMPOSITIONS[i].1)
+}
+
+/// Converts a string into Unicode Normalization Form C.
+///
+/// This is the form that most platforms produce, but macOS likes to hand out decomposed text (NFD) which looks the same while comparing differently.
+pub fn nfc(s: &str) -> String {
+    let mut decomposed = Vec::with_capacity(s.len());
+    for c in s.chars() {
+        decompose(c, &mut decomposed);
+    }
+
+    // Put each run of combining marks into canonical order, taking care to keep marks of the same class in place.
+    let mut start = 0;
+    while start < decomposed.len() {
+        if combining_class(decomposed[start]) == 0 {
+            start += 1;
+            continue;
+        }
+        let end = (start..decomposed.len())
+            .find(|&i| combining_class(decomposed[i]) == 0)
+            .unwrap_or(decomposed.len());
+        decomposed[start..end].sort_by_key(|&c| combining_class(c));
+        start = end;
+    }
+
+    let mut composed: Vec<char> = Vec::with_capacity(decomposed.len());
+    let mut starter: Option<usize> = None;
+    let mut last_class = 0;
+    for c in decomposed {
+        let class = combining_class(c);
+
+        if let Some(i) = starter {
+            // A mark can only join its starter if nothing in between has the same or a higher class.
+            let adjacent = i + 1 == composed.len();
+            let blocked = !adjacent && (last_class == 0 || last_class >= class);
+            if !blocked {
+                if let Some(composite) = compose(composed[i], c) {
+                    composed[i] = composite;
+                    continue;
+                }
+            }
+        }
+
+        if class == 0 {
+            starter = Some(composed.len());
+        }
+        last_class = class;
+        composed.push(c);
+    }
+
+    composed.into_iter().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_widths() {
+        assert_eq!(width('a'), 1);
+        assert_eq!(width('é'), 1);
+        assert_eq!(width('\u{301}'), 0);
+        assert_eq!(width('漢'), 2);
+        assert_eq!(width('\u{ff21}'), 2);
+        assert_eq!(width('\u{1f44d}'), 2);
+        // A skin tone on its own is drawn as a swatch, but it shares the cell of an emoji that it follows.
+        assert_eq!(width('\u{1f3fd}'), 2);
+        assert_eq!(width('\u{7}'), 1);
+    }
+
+    #[test]
+    fn test_nfc_composes_marks() {
+        assert_eq!(nfc("cafe\u{301}"), "caf\u{e9}");
+    }
+
+    #[test]
+    fn test_nfc_leaves_composed_text_alone() {
+        assert_eq!(nfc("caf\u{e9} na\u{ef}ve"), "caf\u{e9} na\u{ef}ve");
+    }
+
+    #[test]
+    fn test_nfc_reorders_marks() {
+        // A dot below (class 220) sorts before a circumflex (class 230), and both compose into a single character.
+        assert_eq!(nfc("a\u{302}\u{323}"), "\u{1ead}");
+        assert_eq!(nfc("\u{e2}\u{323}"), "\u{1ead}");
+    }
+
+    #[test]
+    fn test_nfc_blocked_marks() {
+        // The acute accent is blocked from the `a` by the grave accent with the same class.
+        assert_eq!(nfc("a\u{300}\u{301}"), "\u{e0}\u{301}");
+    }
+
+    #[test]
+    fn test_nfc_singletons_and_exclusions() {
+        assert_eq!(nfc("\u{212b}"), "\u{c5}");
+        assert_eq!(nfc("\u{958}"), "\u{915}\u{93c}");
+    }
+
+    #[test]
+    fn test_nfc_hangul() {
+        assert_eq!(nfc("\u{1112}\u{1161}\u{11ab}"), "\u{d55c}");
+        assert_eq!(nfc("\u{d55c}"), "\u{d55c}");
+    }
+}
@@ -0,0 +1,1445 @@
+//! Shows a window onto a buffer in the terminal, scrolling it to keep the cursor in sight.
+use std::cmp;
+use std::fmt;
+use std::iter;
+use std::ops::Range;
+
+use crossterm::style::{self, ContentStyle};
+use crossterm::{cursor, queue};
+
+use crate::buffer::{Buffer, Position};
+use crate::errors::{EditorError, Result};
+use crate::git::LineChange;
+use crate::lsp::{Diagnostic, Severity};
+use crate::theme::Theme;
+use crate::unicode;
+
+#[derive(Debug, Clone)]
+struct DisplaySize {
+    columns: u16,
+    rows: u16,
+}
+
+impl DisplaySize {
+    fn new(columns: u16, rows: u16) -> Self {
+        Self { columns, rows }
+    }
+}
+
+impl Default for DisplaySize {
+    fn default() -> Self {
+        DisplaySize::new(80, 24)
+    }
+}
+
+/// The position in the document that is shown in the top left corner of the display.
+///
+/// The column is counted in cells of the display rather than in bytes, since some characters are wider than others.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct DisplayPosition {
+    column: usize,
+    row: usize,
+}
+
+impl DisplayPosition {
+    #[cfg(test)]
+    fn new(column: usize, row: usize) -> Self {
+        Self { column, row }
+    }
+}
+
+/// The ways that line numbers can be shown in the gutter on the left of the display.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineNumbers {
+    #[default]
+    Off,
+    /// Every line shows its own line number.
+    Absolute,
+    /// Lines show how far away they are from the cursor, which makes it easier to count out motions.
+    /// The cursor's own line shows its line number, like Vim does with both `number` and `relativenumber` set.
+    Relative,
+}
+
+/// The smallest width of the gutter, not counting the space between it and the text, like Vim's `numberwidth`.
+const MIN_GUTTER_WIDTH: usize = 3;
+
+/// How far apart tab stops are unless the user says otherwise, which is what terminals use too.
+pub const DEFAULT_TAB_WIDTH: usize = 8;
+
+/// What whitespace is drawn as while it's being shown, like Vim's `listchars`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListChars {
+    /// What goes in the first cell of a tab, and what fills up the rest of it.
+    pub tab: (char, char),
+    /// What spaces at the end of a line are drawn as.
+    pub trail: char,
+    /// What non-breaking spaces are drawn as.
+    pub nbsp: char,
+}
+
+impl Default for ListChars {
+    fn default() -> Self {
+        Self {
+            tab: ('→', ' '),
+            trail: '·',
+            nbsp: '␣',
+        }
+    }
+}
+
+impl ListChars {
+    /// Reads glyphs written like Vim's `listchars`, such as `tab:> ,trail:-,nbsp:+`, keeping the defaults for any left out.
+    ///
+    /// A tab can have one glyph or two, and the rest of it is filled with spaces if it only has one.
+    pub fn parse(text: &str) -> std::result::Result<Self, String> {
+        let mut list_chars = Self::default();
+        for part in text.split(',').filter(|part| !part.is_empty()) {
+            let (name, glyphs) = part
+                .split_once(':')
+                .ok_or_else(|| format!("Expected a name and glyph in {}", part))?;
+            let glyphs: Vec<char> = glyphs.chars().collect();
+            if glyphs.iter().any(|&c| unicode::width(c) != 1) {
+                return Err(format!("The glyphs for {} need to fit in one cell", name));
+            }
+            match (name, &glyphs[..]) {
+                ("tab", &[first]) => list_chars.tab = (first, ' '),
+                ("tab", &[first, fill]) => list_chars.tab = (first, fill),
+                ("trail", &[glyph]) => list_chars.trail = glyph,
+                ("nbsp", &[glyph]) => list_chars.nbsp = glyph,
+                ("tab" | "trail" | "nbsp", _) => {
+                    return Err(format!("Wrong number of glyphs for {}", name))
+                }
+                _ => return Err(format!("Unknown kind of whitespace {}", name)),
+            }
+        }
+        Ok(list_chars)
+    }
+}
+
+impl fmt::Display for ListChars {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (first, fill) = self.tab;
+        write!(
+            f,
+            "tab:{}{},trail:{},nbsp:{}",
+            first, fill, self.trail, self.nbsp
+        )
+    }
+}
+
+/// What fills up the parts of the view that the text doesn't, like Vim's `fillchars`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FillChars {
+    /// What goes at the start of the rows past the end of the buffer, which a space leaves empty.
+    pub eob: char,
+}
+
+impl Default for FillChars {
+    fn default() -> Self {
+        Self { eob: '~' }
+    }
+}
+
+impl FillChars {
+    /// Reads glyphs written like Vim's `fillchars`, such as `eob:-`, keeping the defaults for any left out.
+    pub fn parse(text: &str) -> std::result::Result<Self, String> {
+        let mut fill_chars = Self::default();
+        for part in text.split(',').filter(|part| !part.is_empty()) {
+            let (name, glyph) = part
+                .split_once(':')
+                .ok_or_else(|| format!("Expected a name and glyph in {}", part))?;
+            let mut chars = glyph.chars();
+            let glyph = match (chars.next(), chars.next()) {
+                (Some(c), None) if unicode::width(c) == 1 => c,
+                _ => return Err(format!("The glyph for {} needs to fit in one cell", name)),
+            };
+            match name {
+                "eob" => fill_chars.eob = glyph,
+                _ => return Err(format!("Unknown part of the view {}", name)),
+            }
+        }
+        Ok(fill_chars)
+    }
+}
+
+impl fmt::Display for FillChars {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "eob:{}", self.eob)
+    }
+}
+
+/// Reads the columns to draw rulers at, written like Vim's `colorcolumn` as numbers counted from one, like `80,100`.
+pub fn parse_color_columns(text: &str) -> std::result::Result<Vec<usize>, String> {
+    text.split(',')
+        .filter(|part| !part.is_empty())
+        .map(|part| match part.parse() {
+            Ok(column) if column > 0 => Ok(column),
+            _ => Err(format!("{} isn't a column", part)),
+        })
+        .collect()
+}
+
+/// The part of a buffer that is visible in the terminal.
+#[derive(Debug, Clone)]
+pub struct View {
+    /// The size of the display.
+    size: DisplaySize,
+    /// Where the top left corner of the view is on the display, when it's sharing the display with other windows.
+    origin: (u16, u16),
+    /// The position of the buffer in the display.
+    position: DisplayPosition,
+    /// How line numbers are shown, if they are at all.
+    line_numbers: LineNumbers,
+    /// The number of columns taken up by the gutter, including the space after the line numbers.
+    gutter: usize,
+    /// Whether the gutter starts with a column marking the lines that have changed since the last commit.
+    signs: bool,
+    /// How many cells apart the tab stops are.
+    tab_width: usize,
+    /// How many lines are kept on the display above and below the cursor, like Vim's `scrolloff`.
+    scroll_off: usize,
+    /// Whether long lines carry on onto the rows below, rather than running off the right hand side.
+    wrap: bool,
+    /// Whether tabs, trailing spaces and non-breaking spaces are shown, like Vim's `list`.
+    list: bool,
+    list_chars: ListChars,
+    fill_chars: FillChars,
+    /// Whether the line the cursor is on is picked out, like Vim's `cursorline`.
+    cursor_line: bool,
+    /// The columns (counted from one) that are marked down the whole view as rulers, like Vim's `colorcolumn`.
+    color_columns: Vec<usize>,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            size: Default::default(),
+            origin: (0, 0),
+            position: Default::default(),
+            line_numbers: Default::default(),
+            gutter: 0,
+            signs: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            scroll_off: 0,
+            wrap: false,
+            list: false,
+            list_chars: ListChars::default(),
+            fill_chars: FillChars::default(),
+            cursor_line: false,
+            color_columns: Vec::new(),
+        }
+    }
+}
+
+impl View {
+    pub fn set_columns(&mut self, c: u16) {
+        self.size.columns = c;
+    }
+
+    pub fn set_rows(&mut self, r: u16) {
+        self.size.rows = r;
+    }
+
+    /// Moves the view to another part of the display.
+    pub fn set_origin(&mut self, x: u16, y: u16) {
+        self.origin = (x, y);
+    }
+
+    pub fn set_line_numbers(&mut self, line_numbers: LineNumbers) {
+        self.line_numbers = line_numbers;
+    }
+
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    /// Spaces tab stops `tab_width` cells apart, which can't be any closer together than one.
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = cmp::max(tab_width, 1);
+    }
+
+    pub fn set_scroll_off(&mut self, scroll_off: usize) {
+        self.scroll_off = scroll_off;
+    }
+
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+        self.position.column = 0;
+    }
+
+    /// Draws tabs, trailing spaces and non-breaking spaces as glyphs, so that they can be told apart from other spaces.
+    pub fn set_list(&mut self, list: bool) {
+        self.list = list;
+    }
+
+    pub fn list(&self) -> bool {
+        self.list
+    }
+
+    pub fn set_list_chars(&mut self, list_chars: ListChars) {
+        self.list_chars = list_chars;
+    }
+
+    pub fn list_chars(&self) -> ListChars {
+        self.list_chars
+    }
+
+    /// Picks what fills up the rows past the end of the buffer, so that they can be told apart from empty lines.
+    pub fn set_fill_chars(&mut self, fill_chars: FillChars) {
+        self.fill_chars = fill_chars;
+    }
+
+    pub fn fill_chars(&self) -> FillChars {
+        self.fill_chars
+    }
+
+    pub fn set_cursor_line(&mut self, cursor_line: bool) {
+        self.cursor_line = cursor_line;
+    }
+
+    pub fn cursor_line(&self) -> bool {
+        self.cursor_line
+    }
+
+    pub fn set_color_columns(&mut self, color_columns: Vec<usize>) {
+        self.color_columns = color_columns;
+    }
+
+    pub fn color_columns(&self) -> &[usize] {
+        &self.color_columns
+    }
+
+    /// Makes the gutter wide enough for the line numbers of a document with `num_lines` lines, and for a column of
+    /// signs if `signs` is set.
+    pub fn fit_gutter(&mut self, num_lines: usize, signs: bool) {
+        let numbers = match self.line_numbers {
+            LineNumbers::Off => 0,
+            LineNumbers::Absolute | LineNumbers::Relative => {
+                cmp::max(num_lines.to_string().len(), MIN_GUTTER_WIDTH) + 1
+            }
+        };
+        self.signs = signs;
+        self.gutter = numbers + usize::from(signs);
+    }
+
+    /// The number of columns left over for the text, once the gutter has taken its share.
+    fn text_columns(&self) -> usize {
+        usize::from(self.size.columns).saturating_sub(self.gutter)
+    }
+
+    /// Finds the cells of a line that each of its rows on the display start at, which is only the first one unless lines are wrapped.
+    ///
+    /// A line is broken after the last blank that fits on a row, so that words only get split up when they're too long for a row of their own.
+    pub fn line_breaks(&self, buffer: &Buffer, line: usize) -> Vec<usize> {
+        let mut breaks = vec![0];
+        let range = match (self.wrap, buffer.line_range(line)) {
+            (true, Some(range)) => range,
+            _ => return breaks,
+        };
+        let columns = cmp::max(self.text_columns(), 1);
+        let contents = buffer.contents();
+        let mut row_start = 0;
+        let mut after_blank = 0;
+        let mut end = 0;
+        for (character, width) in buffer.cells(range, self.tab_width) {
+            let start = end;
+            end += width;
+            if end > row_start + columns && start > row_start {
+                row_start = match after_blank > row_start {
+                    true => after_blank,
+                    false => start,
+                };
+                breaks.push(row_start);
+            }
+            if matches!(contents.char_at(character.start), Some(' ' | '\t')) {
+                after_blank = end;
+            }
+        }
+        breaks
+    }
+
+    /// Counts the rows of the display that a line takes up.
+    fn line_height(&self, buffer: &Buffer, line: usize) -> usize {
+        self.line_breaks(buffer, line).len()
+    }
+
+    /// Works out which of the rows of its line a cell is drawn on (counting from zero), and how far along that row it is.
+    pub fn wrapped_cell(&self, buffer: &Buffer, position: Position) -> (usize, usize) {
+        let breaks = self.line_breaks(buffer, position.line);
+        let row = breaks
+            .iter()
+            .rposition(|&start| start <= position.column)
+            .unwrap_or(0);
+        (row, position.column - breaks[row])
+    }
+
+    /// Scrolls the display only as far as it needs to for `cursor` to be on it, along with `scroll_off` lines either side of it.
+    ///
+    /// Like everywhere else in the view, the column of `cursor` is counted in cells of the display.
+    /// The lines below the cursor stop at the end of the document.
+    pub fn scroll_to(&mut self, buffer: &Buffer, cursor: Position) {
+        let rows = usize::from(self.size.rows);
+        let columns = self.text_columns();
+        // A margin too big for the display would leave the cursor nowhere to go, so it gets stuck in the middle instead.
+        let margin = self.margin();
+        let above = cursor.line.saturating_sub(margin);
+        let below = cmp::max(
+            cmp::min(cursor.line + margin, buffer.num_lines().saturating_sub(1)),
+            cursor.line,
+        );
+
+        if above < self.position.row {
+            self.position.row = above;
+        } else if below >= self.position.row + rows {
+            self.position.row = below + 1 - rows;
+        }
+        if self.wrap {
+            // Wrapped lines can take up more than one row each, so the display might have to go further still.
+            while self.position.row < cursor.line
+                && (self.position.row..=below)
+                    .map(|line| self.line_height(buffer, line))
+                    .sum::<usize>()
+                    > rows
+            {
+                self.position.row += 1;
+            }
+            self.position.column = 0;
+            return;
+        }
+
+        if cursor.column < self.position.column {
+            self.position.column = cursor.column;
+        } else if cursor.column >= self.position.column + columns {
+            self.position.column = cursor.column + 1 - columns;
+        }
+    }
+
+    /// Scrolls the display so that `cursor` sits in the middle of it vertically, and as far left as possible.
+    pub fn center_on(&mut self, cursor: Position) {
+        let last_column = self.text_columns().saturating_sub(1);
+
+        self.scroll_to_middle(cursor.line);
+        self.position.column = cursor.column.saturating_sub(last_column);
+    }
+
+    /// Scrolls the display down by a full page, taking the cursor along with it on the same row of the display.
+    pub fn page_down(&mut self, buffer: &mut Buffer) {
+        let rows = usize::from(self.size.rows);
+        let last_page = buffer.num_lines().saturating_sub(rows);
+        let cursor_row = buffer.cursor().line.saturating_sub(self.position.row);
+
+        self.position.row = cmp::min(self.position.row + rows, last_page);
+        buffer.goto_line(self.position.row + cursor_row);
+    }
+
+    /// Scrolls the display up by a full page, taking the cursor along with it on the same row of the display.
+    pub fn page_up(&mut self, buffer: &mut Buffer) {
+        let cursor_row = buffer.cursor().line.saturating_sub(self.position.row);
+
+        self.position.row = self.position.row.saturating_sub(self.size.rows.into());
+        buffer.goto_line(self.position.row + cursor_row);
+    }
+
+    /// How many lines `Ctrl-D` and `Ctrl-U` scroll by, which is half of the display.
+    fn half_page(&self) -> usize {
+        cmp::max(usize::from(self.size.rows / 2), 1)
+    }
+
+    /// Scrolls the display down by half a page, taking the cursor down by as many lines (like Vim's `Ctrl-D`.)
+    ///
+    /// The display stops scrolling once the end of the document is on it, but the cursor carries on down to the last line.
+    pub fn half_page_down(&mut self, buffer: &mut Buffer) {
+        let half_page = self.half_page();
+        let last_page = buffer.num_lines().saturating_sub(self.size.rows.into());
+
+        self.position.row = cmp::max(
+            cmp::min(self.position.row + half_page, last_page),
+            self.position.row,
+        );
+        buffer.goto_line(buffer.cursor().line + half_page);
+        buffer.goto_line(self.nearest_visible_line(buffer, buffer.cursor().line));
+    }
+
+    /// Scrolls the display up by half a page, taking the cursor up by as many lines (like Vim's `Ctrl-U`.)
+    pub fn half_page_up(&mut self, buffer: &mut Buffer) {
+        let half_page = self.half_page();
+
+        self.position.row = self.position.row.saturating_sub(half_page);
+        buffer.goto_line(buffer.cursor().line.saturating_sub(half_page));
+        buffer.goto_line(self.nearest_visible_line(buffer, buffer.cursor().line));
+    }
+
+    /// Scrolls the display so that `line` is at the top of it (like Vim's `zt`.)
+    pub fn scroll_to_top(&mut self, line: usize) {
+        self.position.row = line;
+    }
+
+    /// Scrolls the display so that `line` is in the middle of it (like Vim's `zz`.)
+    pub fn scroll_to_middle(&mut self, line: usize) {
+        self.position.row = line.saturating_sub(usize::from(self.size.rows / 2));
+    }
+
+    /// Scrolls the display so that `line` is at the bottom of it, or as near as it can get (like Vim's `zb`.)
+    pub fn scroll_to_bottom(&mut self, line: usize) {
+        self.position.row = (line + 1).saturating_sub(self.size.rows.into());
+    }
+
+    /// Scrolls the display by some lines (down when `lines` is positive, up otherwise), stopping at either end of the document.
+    pub fn scroll_by(&mut self, lines: isize, num_lines: usize) {
+        let last_line = num_lines.saturating_sub(1);
+        self.position.row = cmp::min(self.position.row.saturating_add_signed(lines), last_line);
+    }
+
+    /// Finds the line nearest to `line` that is on the display, and far enough from the edges of it to leave `scroll_off` lines in sight.
+    ///
+    /// The margin isn't needed at either end of the document.
+    pub fn nearest_visible_line(&self, buffer: &Buffer, line: usize) -> usize {
+        let num_lines = buffer.num_lines();
+        let visible = self.shown_lines(buffer);
+        let margin = self.margin();
+        let first = match visible.start {
+            0 => 0,
+            start => start + margin,
+        };
+        let last = match visible.end >= num_lines {
+            true => visible.end.saturating_sub(1),
+            false => visible.end.saturating_sub(1 + margin),
+        };
+        line.clamp(cmp::min(first, last), cmp::max(first, last))
+    }
+
+    /// How many lines `scroll_off` keeps above and below the cursor, which is never so many that the cursor has nowhere to go.
+    fn margin(&self) -> usize {
+        cmp::min(
+            self.scroll_off,
+            usize::from(self.size.rows).saturating_sub(1) / 2,
+        )
+    }
+
+    /// Works out which line and cell of it is under a cell of the display, if the cell is in this view at all.
+    ///
+    /// Clicking in the gutter picks the start of the line, and clicking past the end of a wrapped row picks the end of that row.
+    pub fn position_at(&self, buffer: &Buffer, column: u16, row: u16) -> Option<Position> {
+        let (x, y) = self.origin;
+        let inside_columns = column >= x && column - x < self.size.columns;
+        let inside_rows = row >= y && row - y < self.size.rows;
+        if !inside_columns || !inside_rows {
+            return None;
+        }
+        let cell = usize::from(column - x).saturating_sub(self.gutter);
+        let row = usize::from(row - y);
+        if !self.wrap {
+            return Some(Position::new(
+                self.position.row + row,
+                self.position.column + cell,
+            ));
+        }
+
+        let mut line = self.position.row;
+        let mut rows_left = row;
+        loop {
+            let breaks = self.line_breaks(buffer, line);
+            if rows_left < breaks.len() || line + 1 >= buffer.num_lines() {
+                let row = cmp::min(rows_left, breaks.len() - 1);
+                let cell = match breaks.get(row + 1) {
+                    Some(next) => cmp::min(breaks[row] + cell, next.saturating_sub(1)),
+                    None => breaks[row] + cell,
+                };
+                return Some(Position::new(line, cell));
+            }
+            rows_left -= breaks.len();
+            line += 1;
+        }
+    }
+
+    /// The lines of the document that are on the display, or that could be when lines are wrapped.
+    pub fn visible_lines(&self) -> Range<usize> {
+        self.position.row..self.position.row + usize::from(self.size.rows)
+    }
+
+    /// The lines of `buffer` that are on the display, taking into account how many rows each one takes up.
+    ///
+    /// A line too tall to fit on the display by itself still counts as being on it.
+    fn shown_lines(&self, buffer: &Buffer) -> Range<usize> {
+        if !self.wrap {
+            return self.visible_lines();
+        }
+        let mut rows_left = usize::from(self.size.rows);
+        let mut end = self.position.row;
+        while end < buffer.num_lines() {
+            let height = self.line_height(buffer, end);
+            if height > rows_left {
+                break;
+            }
+            rows_left -= height;
+            end += 1;
+        }
+        self.position.row..cmp::max(end, self.position.row + 1)
+    }
+
+    /// Works out where on the display a line and cell of it end up, assuming that they're visible.
+    pub fn screen_position(&self, buffer: &Buffer, position: Position) -> (u16, u16) {
+        let (column, row) = match self.wrap {
+            true => {
+                let (row, column) = self.wrapped_cell(buffer, position);
+                let rows_above: usize = (self.position.row..position.line)
+                    .map(|line| self.line_height(buffer, line))
+                    .sum();
+                (
+                    cmp::min(column, self.text_columns().saturating_sub(1)),
+                    rows_above + row,
+                )
+            }
+            false => (
+                position.column.saturating_sub(self.position.column),
+                position.line.saturating_sub(self.position.row),
+            ),
+        };
+        let column = usize::from(self.origin.0) + self.gutter + column;
+        let row = usize::from(self.origin.1) + row;
+        (
+            column.try_into().unwrap_or(u16::MAX),
+            row.try_into().unwrap_or(u16::MAX),
+        )
+    }
+
+    /// Determines whether some of a line `width` cells wide is hidden off the left and right hand sides of the display.
+    fn clipped_sides(&self, width: usize) -> (bool, bool) {
+        let clipped_left = self.position.column > 0 && width > 0;
+        let clipped_right = width > self.position.column + self.text_columns();
+        (clipped_left, clipped_right)
+    }
+
+    /// Builds the line number that goes in the gutter beside a line, padded out to the width of the gutter.
+    fn gutter_label(&self, line: usize, cursor_line: usize) -> String {
+        let number = match self.line_numbers {
+            LineNumbers::Relative if line != cursor_line => line.abs_diff(cursor_line),
+            _ => line + 1,
+        };
+        let width = self.gutter - usize::from(self.signs);
+        match width {
+            0 => String::new(),
+            _ => format!("{:>width$} ", number, width = width - 1),
+        }
+    }
+
+    /// Draws the visible lines of `buffer`, marking any that run off either side (or carrying them on onto the rows below, when lines are wrapped.)
+    ///
+    /// Any text covered by `highlights` (which need to be in order) is drawn as selected, and the characters at `brackets`
+    /// as a pair of matching brackets, in the colours from `theme`.
+    /// Each row of the view is drawn separately, so that only the rows that have changed need to be sent to the terminal.
+    /// Relative line numbers are counted from the line that `cursor` is on.
+    pub fn render(
+        &self,
+        buffer: &Buffer,
+        cursor: Position,
+        highlights: &[Range<usize>],
+        brackets: &[usize],
+        theme: &Theme,
+    ) -> Result<Vec<Vec<u8>>> {
+        let contents = buffer.contents();
+        let columns = self.text_columns();
+        let (x, y) = self.origin;
+        let gutter = x.saturating_add(self.gutter.try_into().unwrap_or(u16::MAX));
+        let mut rows = vec![Vec::new(); self.size.rows.into()];
+        let mut row = 0;
+
+        // The final line ending doesn't start another line, so nothing goes after it unless the cursor has been put there by
+        // typing one in.
+        let lines = cmp::max(buffer.num_lines(), cursor.line + 1);
+        for (number, line) in
+            (self.position.row..lines).map_while(|r| buffer.line_range(r).map(|range| (r, range)))
+        {
+            let breaks = self.line_breaks(buffer, number);
+            for (i, &row_start) in breaks.iter().enumerate() {
+                if row >= rows.len() {
+                    return Ok(rows);
+                }
+                let stream = &mut rows[row];
+                let display_row = y.saturating_add(row.try_into().unwrap_or(u16::MAX));
+                row += 1;
+                let last_row = i + 1 == breaks.len();
+                // Without wrapping, every row shows the same columns of its line.
+                let (left, right) = match self.wrap {
+                    true => (row_start, breaks.get(i + 1).copied().unwrap_or(usize::MAX)),
+                    false => (self.position.column, self.position.column + columns),
+                };
+
+                // Each row starts at the left edge of the display, which isn't where a view beside another one starts.
+                if x > 0 {
+                    queue!(stream, cursor::MoveTo(x, display_row)).map_err(EditorError::TermIo)?;
+                }
+
+                if self.signs {
+                    // A problem the linter found matters more than the line having changed.
+                    let lint = buffer.lint_on(number).map(|d| d.severity);
+                    let sign = match (lint, buffer.line_change(number)) {
+                        _ if i > 0 => theme.text.apply(' '),
+                        (Some(Severity::Error), _) => theme.error.apply('E'),
+                        (Some(Severity::Warning), _) => theme.warning.apply('W'),
+                        (Some(_), _) => theme.information.apply('I'),
+                        (None, Some(LineChange::Added)) => theme.added.apply('+'),
+                        (None, Some(LineChange::Modified)) => theme.modified.apply('~'),
+                        (None, Some(LineChange::Removed)) => theme.removed.apply('_'),
+                        (None, None) => theme.text.apply(' '),
+                    };
+                    queue!(stream, style::PrintStyledContent(sign)).map_err(EditorError::TermIo)?;
+                }
+                if self.gutter > usize::from(self.signs) {
+                    // The rows that a wrapped line carries on onto leave the gutter empty.
+                    let label = match i {
+                        0 => self.gutter_label(number, cursor.line),
+                        _ => " ".repeat(self.gutter - usize::from(self.signs)),
+                    };
+                    queue!(
+                        stream,
+                        style::PrintStyledContent(theme.line_numbers.apply(label))
+                    )
+                    .map_err(EditorError::TermIo)?;
+                }
+
+                // Only the characters that fit on the row in their entirety are drawn, gathered up into runs that are highlighted or not.
+                let trailing = match self.list {
+                    true => {
+                        let text = contents.slice(line.clone());
+                        line.start + text.trim_end_matches([' ', '\t']).len()
+                    }
+                    false => usize::MAX,
+                };
+                // The line the cursor is on, and the rulers, go underneath the text but not over anything highlighted.
+                let on_cursor_line = self.cursor_line && number == cursor.line;
+                let ruler_at = |start: usize, end: usize| {
+                    self.color_columns
+                        .iter()
+                        .any(|&c| (start..end).contains(&(c - 1)))
+                };
+                let layer = |run: Run, start: usize, end: usize| match run {
+                    Run::Plain | Run::Whitespace if ruler_at(start, end) => {
+                        run.over(theme.color_column, theme)
+                    }
+                    Run::Plain | Run::Whitespace if on_cursor_line => {
+                        run.over(theme.cursor_line, theme)
+                    }
+                    run => run.style(theme),
+                };
+                let mut width = 0;
+                let mut drawn = 0;
+                let mut text = String::new();
+                let mut style = theme.text;
+                for (character, character_width) in buffer.cells(line.clone(), self.tab_width) {
+                    let (start, end) = (width, width + character_width);
+                    width = end;
+                    let outside = match self.wrap {
+                        true => start < left || start >= right,
+                        false => end <= left || end > right,
+                    };
+                    if outside {
+                        continue;
+                    }
+
+                    let within = |ranges: &[Range<usize>]| {
+                        ranges.iter().any(|r| r.contains(&character.start))
+                    };
+                    // Whitespace that's being shown is drawn as a glyph, along with what fills up the rest of its cells.
+                    let c = contents.char_at(character.start);
+                    let glyph = match c {
+                        Some('\t') if self.list => Some(self.list_chars.tab),
+                        Some('\u{a0}') if self.list => Some((self.list_chars.nbsp, ' ')),
+                        Some(' ') if character.start >= trailing => {
+                            Some((self.list_chars.trail, ' '))
+                        }
+                        _ => None,
+                    };
+                    let this_run = match (within(highlights), within(buffer.changed_text())) {
+                        (true, _) => Run::Highlighted,
+                        _ if brackets.contains(&character.start) => Run::Bracket,
+                        (false, true) => Run::Changed,
+                        (false, false) if glyph.is_some() => Run::Whitespace,
+                        (false, false) => Run::Plain,
+                    };
+                    let this_style = layer(this_run, start, end);
+                    if this_style != style {
+                        print_run(stream, &mut text, style)?;
+                        style = this_style;
+                    }
+                    // A wide character cut in half by the left hand side can't be drawn, but the half that's showing still takes up room.
+                    // Tabs are spelled out with spaces, rather than leaving the terminal to line them up with stops of its own.
+                    let cells = cmp::min(end, left + columns) - cmp::max(start, left);
+                    drawn += cells;
+                    match glyph {
+                        // The glyph goes in the first cell, which might be the half that's been cut off.
+                        Some((first, fill)) => {
+                            if start >= left {
+                                text.push(first);
+                            }
+                            text.extend(iter::repeat_n(fill, cells - usize::from(start >= left)));
+                        }
+                        None if start < left || c == Some('\t') => {
+                            text.extend(iter::repeat_n(' ', cells))
+                        }
+                        None => text.push_str(&contents.slice(character)),
+                    }
+                }
+
+                // A highlighted line ending gets a cell of its own, otherwise selected blank lines wouldn't show up at all.
+                let line_ending_highlighted = highlights.iter().any(|h| h.contains(&line.end));
+                let line_ending_visible = last_row && width >= left && width - left < columns;
+                if line_ending_highlighted && line_ending_visible {
+                    print_run(stream, &mut text, style)?;
+                    queue!(
+                        stream,
+                        style::PrintStyledContent(theme.selection.apply(' '))
+                    )
+                    .map_err(EditorError::TermIo)?;
+                    drawn += 1;
+                }
+
+                // The worst problem a language server found on the line goes after the end of it, as much as there's room for.
+                if let Some(diagnostic) = buffer.diagnostic_on(number).filter(|_| last_row) {
+                    let used = width.saturating_sub(left) + usize::from(line_ending_highlighted);
+                    if width >= left && used + 2 < columns {
+                        print_run(stream, &mut text, style)?;
+                        drawn += print_diagnostic(stream, diagnostic, columns - used, theme)?;
+                    }
+                }
+
+                // The line the cursor is on carries on across the rest of the view, and the rulers carry on past the end of the text.
+                let fill_to = match on_cursor_line {
+                    true => columns,
+                    false => self
+                        .color_columns
+                        .iter()
+                        .map(|&c| c - 1)
+                        .filter(|&c| c >= left && c - left < columns)
+                        .map(|c| c - left + 1)
+                        .max()
+                        .unwrap_or(0),
+                };
+                for cell in drawn..fill_to {
+                    let this_style = layer(Run::Plain, left + cell, left + cell + 1);
+                    if this_style != style {
+                        print_run(stream, &mut text, style)?;
+                        style = this_style;
+                    }
+                    text.push(' ');
+                }
+                print_run(stream, &mut text, style)?;
+
+                if self.wrap {
+                    continue;
+                }
+                let (clipped_left, clipped_right) = self.clipped_sides(width);
+                if clipped_left {
+                    queue!(
+                        stream,
+                        cursor::MoveTo(gutter, display_row),
+                        style::PrintStyledContent(theme.clipped.apply('<'))
+                    )
+                    .map_err(EditorError::TermIo)?;
+                }
+                if clipped_right {
+                    queue!(
+                        stream,
+                        cursor::MoveTo(
+                            x.saturating_add(self.size.columns).saturating_sub(1),
+                            display_row
+                        ),
+                        style::PrintStyledContent(theme.clipped.apply('>'))
+                    )
+                    .map_err(EditorError::TermIo)?;
+                }
+            }
+        }
+
+        // Like in vi, the rows past the end of the buffer start with a `~`, so they can be told apart from empty lines.
+        if self.fill_chars.eob != ' ' {
+            for (i, stream) in rows.iter_mut().enumerate().skip(row) {
+                if x > 0 {
+                    let display_row = y.saturating_add(i.try_into().unwrap_or(u16::MAX));
+                    queue!(stream, cursor::MoveTo(x, display_row)).map_err(EditorError::TermIo)?;
+                }
+                let glyph = theme.end_of_buffer.apply(self.fill_chars.eob);
+                queue!(stream, style::PrintStyledContent(glyph)).map_err(EditorError::TermIo)?;
+            }
+        }
+        Ok(rows)
+    }
+}
+
+/// Prints the first line of a diagnostic's message after a couple of spaces, cut short to fit in `room` cells and coloured by how bad it is.
+///
+/// Returns the number of cells that it took up.
+fn print_diagnostic(
+    stream: &mut Vec<u8>,
+    diagnostic: &Diagnostic,
+    room: usize,
+    theme: &Theme,
+) -> Result<usize> {
+    let mut text = String::from("  ");
+    let mut width = text.len();
+    for c in diagnostic.message.lines().next().unwrap_or("").chars() {
+        let c = if c == '\t' { ' ' } else { c };
+        if width + unicode::width(c) > room {
+            break;
+        }
+        width += unicode::width(c);
+        text.push(c);
+    }
+    let styled = match diagnostic.severity {
+        Severity::Error => theme.error.apply(text),
+        Severity::Warning => theme.warning.apply(text),
+        Severity::Information | Severity::Hint => theme.information.apply(text),
+    };
+    queue!(stream, style::PrintStyledContent(styled)).map_err(EditorError::TermIo)?;
+    Ok(width)
+}
+
+/// The ways that a run of text on a row can be drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Run {
+    Plain,
+    /// Selected or matching a search.
+    Highlighted,
+    /// A bracket by the cursor, or the one that it pairs up with.
+    Bracket,
+    /// Different from the file that the buffer is being compared with.
+    Changed,
+    /// Whitespace that's being shown as glyphs.
+    Whitespace,
+}
+
+impl Run {
+    fn style(self, theme: &Theme) -> ContentStyle {
+        match self {
+            Run::Plain => theme.text,
+            Run::Highlighted => theme.selection,
+            Run::Bracket => theme.matching_bracket,
+            Run::Changed => theme.changed_text,
+            Run::Whitespace => theme.whitespace,
+        }
+    }
+
+    /// Draws the run over the background of the line the cursor is on or a ruler, which plain text takes entirely.
+    fn over(self, underneath: ContentStyle, theme: &Theme) -> ContentStyle {
+        match self {
+            Run::Plain => underneath,
+            run => ContentStyle {
+                background_color: underneath.background_color,
+                ..run.style(theme)
+            },
+        }
+    }
+}
+
+fn print_run(stream: &mut Vec<u8>, text: &mut String, style: ContentStyle) -> Result<()> {
+    if text.is_empty() {
+        return Ok(());
+    }
+    queue!(
+        stream,
+        style::PrintStyledContent(style.apply(std::mem::take(text)))
+    )
+    .map_err(EditorError::TermIo)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crossterm::style::Stylize;
+
+    /// Makes a buffer with `n` empty lines in it.
+    fn lines(n: usize) -> Buffer {
+        Buffer::new(&"\n".repeat(n - 1))
+    }
+
+    fn view(columns: u16, rows: u16) -> View {
+        View {
+            size: DisplaySize::new(columns, rows),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_scrolling_down() {
+        let mut view = view(80, 3);
+        view.scroll_to(&lines(10), Position::new(3, 0));
+        assert_eq!(view.position, DisplayPosition::new(0, 1));
+    }
+
+    #[test]
+    fn test_keeping_lines_around_cursor() {
+        let mut view = view(80, 5);
+        view.set_scroll_off(1);
+        view.scroll_to(&lines(10), Position::new(4, 0));
+        assert_eq!(view.visible_lines(), 1..6);
+        view.scroll_to(&lines(10), Position::new(2, 0));
+        assert_eq!(view.visible_lines(), 1..6);
+        view.scroll_to(&lines(10), Position::new(1, 0));
+        assert_eq!(view.visible_lines(), 0..5);
+
+        // There's nothing to keep in sight past the end of the document.
+        view.scroll_to(&lines(10), Position::new(9, 0));
+        assert_eq!(view.visible_lines(), 5..10);
+        assert_eq!(view.nearest_visible_line(&lines(10), 0), 6);
+        view.scroll_by(-2, 10);
+        assert_eq!(view.nearest_visible_line(&lines(10), 9), 6);
+
+        // A margin bigger than the display keeps the cursor in the middle of it.
+        view.set_scroll_off(99);
+        view.scroll_to(&lines(10), Position::new(5, 0));
+        assert_eq!(view.visible_lines(), 3..8);
+    }
+
+    #[test]
+    fn test_position_at() {
+        let mut view = view(10, 5);
+        view.set_origin(4, 2);
+        view.scroll_to(&lines(10), Position::new(6, 0));
+        assert_eq!(
+            view.position_at(&lines(10), 4, 2),
+            Some(Position::new(2, 0))
+        );
+        assert_eq!(
+            view.position_at(&lines(10), 13, 6),
+            Some(Position::new(6, 9))
+        );
+        assert_eq!(view.position_at(&lines(10), 3, 2), None);
+        assert_eq!(view.position_at(&lines(10), 14, 2), None);
+        assert_eq!(view.position_at(&lines(10), 4, 7), None);
+
+        view.set_line_numbers(LineNumbers::Absolute);
+        view.fit_gutter(10, false);
+        assert_eq!(
+            view.position_at(&lines(10), 5, 2),
+            Some(Position::new(2, 0))
+        );
+        assert_eq!(
+            view.position_at(&lines(10), 9, 2),
+            Some(Position::new(2, 1))
+        );
+    }
+
+    #[test]
+    fn test_scrolling_by_lines() {
+        let mut view = view(80, 3);
+        view.scroll_by(4, 10);
+        assert_eq!(view.position, DisplayPosition::new(0, 4));
+        assert_eq!(view.nearest_visible_line(&lines(10), 0), 4);
+        assert_eq!(view.nearest_visible_line(&lines(10), 8), 6);
+        view.scroll_by(20, 10);
+        assert_eq!(view.position, DisplayPosition::new(0, 9));
+        view.scroll_by(-20, 10);
+        assert_eq!(view.position, DisplayPosition::new(0, 0));
+    }
+
+    #[test]
+    fn test_scrolling_right() {
+        let mut view = view(3, 24);
+        view.scroll_to(&lines(10), Position::new(0, 3));
+        assert_eq!(view.position, DisplayPosition::new(1, 0));
+    }
+
+    #[test]
+    fn test_scrolling_left() {
+        let mut view = view(80, 24);
+        view.position = DisplayPosition::new(1, 0);
+        view.scroll_to(&lines(10), Position::new(0, 0));
+        assert_eq!(view.position, DisplayPosition::new(0, 0));
+    }
+
+    #[test]
+    fn test_scrolling_up() {
+        let mut view = view(80, 24);
+        view.position = DisplayPosition::new(0, 2);
+        view.scroll_to(&lines(10), Position::new(1, 0));
+        assert_eq!(view.position, DisplayPosition::new(0, 1));
+    }
+
+    #[test]
+    fn test_not_scrolling_when_visible() {
+        let mut view = view(4, 3);
+        view.position = DisplayPosition::new(2, 2);
+        view.scroll_to(&lines(10), Position::new(4, 5));
+        assert_eq!(view.position, DisplayPosition::new(2, 2));
+    }
+
+    #[test]
+    fn test_paging_down() {
+        let mut buffer = Buffer::new("1\n2\n3\n4\n5\n6\n7");
+        let mut view = view(80, 3);
+        buffer.goto(1, 0);
+
+        view.page_down(&mut buffer);
+        assert_eq!(view.position, DisplayPosition::new(0, 3));
+        assert_eq!(buffer.cursor(), Position::new(4, 0));
+
+        view.page_down(&mut buffer);
+        assert_eq!(view.position, DisplayPosition::new(0, 4));
+        assert_eq!(buffer.cursor(), Position::new(5, 0));
+    }
+
+    #[test]
+    fn test_scrolling_half_pages() {
+        let mut buffer = Buffer::new("1\n2\n3\n4\n5\n6\n7\n8");
+        let mut view = view(80, 4);
+        buffer.goto(1, 0);
+
+        view.half_page_down(&mut buffer);
+        assert_eq!(view.position, DisplayPosition::new(0, 2));
+        assert_eq!(buffer.cursor(), Position::new(3, 0));
+
+        // The display stops at the last page, but the cursor keeps going.
+        view.half_page_down(&mut buffer);
+        view.half_page_down(&mut buffer);
+        assert_eq!(view.position, DisplayPosition::new(0, 4));
+        assert_eq!(buffer.cursor(), Position::new(7, 0));
+
+        view.half_page_up(&mut buffer);
+        assert_eq!(view.position, DisplayPosition::new(0, 2));
+        assert_eq!(buffer.cursor(), Position::new(5, 0));
+    }
+
+    #[test]
+    fn test_scrolling_line_to_top_and_bottom() {
+        let mut view = view(80, 4);
+        view.scroll_to_top(5);
+        assert_eq!(view.visible_lines(), 5..9);
+        view.scroll_to_bottom(5);
+        assert_eq!(view.visible_lines(), 2..6);
+        view.scroll_to_middle(5);
+        assert_eq!(view.visible_lines(), 3..7);
+        view.scroll_to_bottom(1);
+        assert_eq!(view.visible_lines(), 0..4);
+    }
+
+    #[test]
+    fn test_paging_down_short_document() {
+        let mut buffer = Buffer::new("1\n2");
+        let mut view = view(80, 3);
+        buffer.goto(1, 0);
+        view.page_down(&mut buffer);
+        assert_eq!(view.position, DisplayPosition::new(0, 0));
+        assert_eq!(buffer.cursor(), Position::new(1, 0));
+    }
+
+    #[test]
+    fn test_paging_up() {
+        let mut buffer = Buffer::new("1\n2\n3\n4\n5\n6\n7");
+        let mut view = view(80, 3);
+        view.position = DisplayPosition::new(0, 4);
+        buffer.goto(5, 0);
+
+        view.page_up(&mut buffer);
+        assert_eq!(view.position, DisplayPosition::new(0, 1));
+        assert_eq!(buffer.cursor(), Position::new(2, 0));
+
+        view.page_up(&mut buffer);
+        assert_eq!(view.position, DisplayPosition::new(0, 0));
+        assert_eq!(buffer.cursor(), Position::new(1, 0));
+    }
+
+    #[test]
+    fn test_centering_display() {
+        let mut view = view(80, 4);
+        view.center_on(Position::new(5, 0));
+        assert_eq!(view.position, DisplayPosition::new(0, 3));
+        assert_eq!(
+            view.screen_position(&lines(10), Position::new(5, 0)),
+            (0, 2)
+        );
+    }
+
+    #[test]
+    fn test_centering_near_top() {
+        let mut view = view(80, 4);
+        view.center_on(Position::new(1, 0));
+        assert_eq!(view.position, DisplayPosition::new(0, 0));
+        assert_eq!(
+            view.screen_position(&lines(10), Position::new(1, 0)),
+            (0, 1)
+        );
+    }
+
+    #[test]
+    fn test_centering_scrolls_horizontally() {
+        let mut view = view(4, 24);
+        view.center_on(Position::new(0, 8));
+        assert_eq!(view.position, DisplayPosition::new(5, 0));
+        assert_eq!(
+            view.screen_position(&lines(10), Position::new(0, 8)),
+            (3, 0)
+        );
+    }
+
+    #[test]
+    fn test_clipped_sides() {
+        let mut view = view(4, 24);
+        assert_eq!(view.clipped_sides(4), (false, false));
+        assert_eq!(view.clipped_sides(5), (false, true));
+
+        view.position = DisplayPosition::new(1, 0);
+        assert_eq!(view.clipped_sides(5), (true, false));
+        assert_eq!(view.clipped_sides(6), (true, true));
+        assert_eq!(view.clipped_sides(0), (false, false));
+    }
+
+    #[test]
+    fn test_wrapping_long_lines() {
+        let buffer = Buffer::new("one two three four\nabcdefghijklmnopqrstuvwxy\nend");
+        let mut view = view(10, 5);
+        assert_eq!(view.line_breaks(&buffer, 0), vec![0]);
+        view.set_wrap(true);
+        // Lines break after a blank where they can, and in the middle of a word where they can't.
+        assert_eq!(view.line_breaks(&buffer, 0), vec![0, 8]);
+        assert_eq!(view.line_breaks(&buffer, 1), vec![0, 10, 20]);
+        assert_eq!(view.line_breaks(&buffer, 2), vec![0]);
+
+        assert_eq!(view.wrapped_cell(&buffer, Position::new(0, 9)), (1, 1));
+        assert_eq!(view.screen_position(&buffer, Position::new(0, 9)), (1, 1));
+        assert_eq!(view.screen_position(&buffer, Position::new(1, 21)), (1, 4));
+        assert_eq!(view.position_at(&buffer, 3, 1), Some(Position::new(0, 11)));
+        assert_eq!(view.position_at(&buffer, 9, 0), Some(Position::new(0, 7)));
+        assert_eq!(view.position_at(&buffer, 9, 4), Some(Position::new(1, 29)));
+
+        let rows = view
+            .render(&buffer, Position::default(), &[], &[], &Theme::default())
+            .unwrap();
+        let rows: Vec<_> = rows
+            .into_iter()
+            .map(|row| String::from_utf8(row).unwrap())
+            .collect();
+        assert!(rows[0].starts_with("one two "));
+        assert!(rows[1].starts_with("three four"));
+        assert!(rows[4].starts_with("uvwxy"));
+
+        // Scrolling down to the last line has to go past every row of the lines before it.
+        view.scroll_to(&buffer, Position::new(2, 0));
+        assert_eq!(view.position, DisplayPosition::new(0, 1));
+        assert_eq!(view.nearest_visible_line(&buffer, 0), 1);
+    }
+
+    #[test]
+    fn test_rendering_wide_characters() {
+        let buffer = Buffer::new("漢字ab");
+        let render = |view: &View| {
+            let rows = view
+                .render(&buffer, Position::default(), &[], &[], &Theme::default())
+                .unwrap();
+            String::from_utf8(rows[0].clone()).unwrap()
+        };
+
+        // A wide character that doesn't fit on the right is left off entirely.
+        let mut view = view(3, 1);
+        assert!(render(&view).starts_with("漢\u{1b}"));
+
+        // One that's half off the left leaves a gap where its other half would be.
+        view.set_columns(5);
+        view.scroll_to(&lines(10), Position::new(0, 5));
+        assert_eq!(view.position, DisplayPosition::new(1, 0));
+        assert!(render(&view).starts_with(" 字ab"));
+    }
+
+    #[test]
+    fn test_rendering_tabs() {
+        let buffer = Buffer::new("\tx\ty");
+        let mut view = view(20, 1);
+        view.set_tab_width(4);
+        let rows = view
+            .render(&buffer, Position::default(), &[], &[], &Theme::default())
+            .unwrap();
+        assert_eq!(String::from_utf8(rows[0].clone()).unwrap(), "    x   y");
+    }
+
+    #[test]
+    fn test_showing_whitespace() {
+        let buffer = Buffer::new("\tx\u{a0}y z \t ");
+        let mut view = view(20, 1);
+        view.set_tab_width(4);
+        view.set_list(true);
+        // Without any colours for the whitespace, the glyphs are all that's left.
+        let theme = Theme {
+            whitespace: ContentStyle::new(),
+            ..Theme::default()
+        };
+        let render = |view: &View| {
+            let rows = view
+                .render(&buffer, Position::default(), &[], &[], &theme)
+                .unwrap();
+            String::from_utf8(rows[0].clone()).unwrap()
+        };
+        assert_eq!(render(&view), "→   x␣y z·→ ·");
+
+        view.set_list_chars(ListChars::parse("tab:>-,trail:~").unwrap());
+        assert_eq!(render(&view), ">---x␣y z~>-~");
+        assert!(Theme::default().whitespace != theme.whitespace);
+    }
+
+    #[test]
+    fn test_cursor_line_color_columns_and_brackets() {
+        let buffer = Buffer::new("abcdef\nx");
+        let mut view = view(8, 2);
+        view.set_cursor_line(true);
+        view.set_color_columns(vec![3, 6]);
+        let theme = Theme {
+            text: ContentStyle::new(),
+            cursor_line: ContentStyle::new().on_blue(),
+            color_column: ContentStyle::new().on_red(),
+            ..Theme::default()
+        };
+        let rows = view
+            .render(&buffer, Position::new(1, 0), &[], &[], &theme)
+            .unwrap();
+        let (plain, cursor, ruler) = (theme.text, theme.cursor_line, theme.color_column);
+        let row = |runs: &[(ContentStyle, &str)]| {
+            let mut expected = Vec::new();
+            for (style, text) in runs {
+                queue!(expected, style::PrintStyledContent(style.apply(*text))).unwrap();
+            }
+            expected
+        };
+        assert_eq!(
+            rows[0],
+            row(&[(plain, "ab"), (ruler, "c"), (plain, "de"), (ruler, "f")])
+        );
+        // The line the cursor is on goes right across, with the rulers still showing past the end of it.
+        assert_eq!(
+            rows[1],
+            row(&[
+                (cursor, "x "),
+                (ruler, " "),
+                (cursor, "  "),
+                (ruler, " "),
+                (cursor, "  ")
+            ])
+        );
+
+        // Matching brackets are drawn over the line the cursor is on and the rulers.
+        let buffer = Buffer::new("(a)");
+        let rows = view
+            .render(&buffer, Position::default(), &[], &[0, 2], &theme)
+            .unwrap();
+        let bracket = theme.matching_bracket;
+        assert_eq!(
+            rows[0],
+            row(&[
+                (bracket, "("),
+                (cursor, "a"),
+                (bracket, ")"),
+                (cursor, "  "),
+                (ruler, " "),
+                (cursor, "  ")
+            ])
+        );
+
+        assert_eq!(parse_color_columns("80,100"), Ok(vec![80, 100]));
+        assert!(parse_color_columns("0").is_err());
+    }
+
+    #[test]
+    fn test_end_of_buffer() {
+        let buffer = Buffer::new("a\n");
+        let mut view = view(10, 4);
+        let theme = Theme {
+            end_of_buffer: ContentStyle::new(),
+            ..Theme::default()
+        };
+        let render = |view: &View| {
+            let rows = view
+                .render(&buffer, Position::default(), &[], &[], &theme)
+                .unwrap();
+            rows.into_iter()
+                .map(|row| String::from_utf8(row).unwrap())
+                .collect::<Vec<_>>()
+        };
+        // The line ending at the end of the buffer doesn't make another line.
+        assert_eq!(render(&view), ["a", "~", "~", "~"]);
+
+        view.set_fill_chars(FillChars::parse("eob:-").unwrap());
+        assert_eq!(render(&view), ["a", "-", "-", "-"]);
+        view.set_fill_chars(FillChars::parse("eob: ").unwrap());
+        assert_eq!(render(&view), ["a", "", "", ""]);
+
+        // An empty buffer still has a line to type on, and so does the end of the buffer once the cursor's been put there.
+        view.set_fill_chars(FillChars::default());
+        let rows = |buffer: &Buffer, cursor: Position| {
+            let rows = view.render(buffer, cursor, &[], &[], &theme).unwrap();
+            rows.into_iter()
+                .map(|row| String::from_utf8(row).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            rows(&Buffer::new(""), Position::default()),
+            ["", "~", "~", "~"]
+        );
+        assert_eq!(rows(&buffer, Position::new(1, 0)), ["a", "", "~", "~"]);
+
+        assert_eq!(FillChars::default().to_string(), "eob:~");
+        assert!(FillChars::parse("eob:").is_err());
+        assert!(FillChars::parse("vert:|").is_err());
+    }
+
+    #[test]
+    fn test_parsing_list_chars() {
+        let list_chars = ListChars::parse("tab:>,nbsp:+").unwrap();
+        assert_eq!(list_chars.tab, ('>', ' '));
+        assert_eq!(list_chars.nbsp, '+');
+        assert_eq!(list_chars.to_string(), "tab:> ,trail:·,nbsp:+");
+        assert_eq!(ListChars::parse(&list_chars.to_string()), Ok(list_chars));
+        assert!(ListChars::parse("tab:>--").is_err());
+        assert!(ListChars::parse("trail:漢").is_err());
+        assert!(ListChars::parse("eol:$").is_err());
+        assert!(ListChars::parse("trail").is_err());
+    }
+
+    #[test]
+    fn test_gutter_width() {
+        let mut view = view(10, 24);
+        view.fit_gutter(100, false);
+        assert_eq!(view.gutter, 0);
+
+        view.set_line_numbers(LineNumbers::Absolute);
+        view.fit_gutter(99, false);
+        assert_eq!(view.gutter, 4);
+        view.fit_gutter(12345, false);
+        assert_eq!(view.gutter, 6);
+        assert_eq!(view.text_columns(), 4);
+
+        // Marking changed lines takes a column of its own, even without line numbers.
+        view.fit_gutter(99, true);
+        assert_eq!(view.gutter, 5);
+        assert_eq!(view.gutter_label(8, 0), "  9 ");
+        view.set_line_numbers(LineNumbers::Off);
+        view.fit_gutter(99, true);
+        assert_eq!(view.gutter, 1);
+    }
+
+    #[test]
+    fn test_gutter_shifts_text() {
+        let mut view = view(10, 24);
+        view.set_line_numbers(LineNumbers::Absolute);
+        view.fit_gutter(10, false);
+        assert_eq!(
+            view.screen_position(&lines(10), Position::new(2, 3)),
+            (7, 2)
+        );
+
+        // Only six columns are left for the text, so the seventh scrolls the display.
+        view.scroll_to(&lines(10), Position::new(0, 6));
+        assert_eq!(view.position, DisplayPosition::new(1, 0));
+        assert_eq!(view.clipped_sides(8), (true, true));
+    }
+
+    #[test]
+    fn test_gutter_labels() {
+        let mut view = view(10, 24);
+        view.set_line_numbers(LineNumbers::Absolute);
+        view.fit_gutter(10, false);
+        assert_eq!(view.gutter_label(0, 5), "  1 ");
+        assert_eq!(view.gutter_label(9, 5), " 10 ");
+
+        view.set_line_numbers(LineNumbers::Relative);
+        assert_eq!(view.gutter_label(2, 5), "  3 ");
+        assert_eq!(view.gutter_label(5, 5), "  6 ");
+        assert_eq!(view.gutter_label(8, 5), "  3 ");
+    }
+}
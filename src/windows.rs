@@ -0,0 +1,445 @@
+//! Splitting the display between several windows, each with its own view onto one of the buffers.
+use std::collections::BTreeMap;
+use std::mem;
+
+use crate::buffer::Position;
+use crate::view::View;
+
+/// The smallest number of rows or columns that a window can be split into, not counting the status bar or border that comes with it.
+const MIN_WINDOW_SIZE: u16 = 2;
+
+/// Which way a window is split in two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Split {
+    /// One window above the other, like `:split`.
+    Horizontal,
+    /// One window beside the other, like `:vsplit`.
+    Vertical,
+}
+
+/// The directions that the focus can be moved between windows in, with `Ctrl-W` and `hjkl`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Down,
+    Up,
+    Right,
+}
+
+/// A part of the display, measured in cells of the terminal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub columns: u16,
+    pub rows: u16,
+}
+
+impl Rect {
+    pub fn new(x: u16, y: u16, columns: u16, rows: u16) -> Self {
+        Self {
+            x,
+            y,
+            columns,
+            rows,
+        }
+    }
+
+    /// Whether a cell of the display is inside the rect.
+    pub fn contains(&self, column: u16, row: u16) -> bool {
+        (self.x..self.x.saturating_add(self.columns)).contains(&column)
+            && (self.y..self.y.saturating_add(self.rows)).contains(&row)
+    }
+
+    /// Splits the rect into `n` parts along the direction of a split, giving any leftover cells to the last one.
+    fn divide(&self, split: Split, n: u16) -> impl Iterator<Item = Rect> + '_ {
+        let length = match split {
+            Split::Horizontal => self.rows,
+            Split::Vertical => self.columns,
+        };
+        let each = length / n;
+        (0..n).map(move |i| {
+            let start = i * each;
+            let size = if i + 1 == n { length - start } else { each };
+            match split {
+                Split::Horizontal => Rect::new(self.x, self.y + start, self.columns, size),
+                Split::Vertical => Rect::new(self.x + start, self.y, size, self.rows),
+            }
+        })
+    }
+
+    /// Whether the ranges covered by two rects overlap along the direction that crosses `direction`.
+    fn overlaps_across(&self, other: &Rect, direction: Direction) -> bool {
+        let (start, length, other_start, other_length) = match direction {
+            Direction::Left | Direction::Right => (self.y, self.rows, other.y, other.rows),
+            Direction::Up | Direction::Down => (self.x, self.columns, other.x, other.columns),
+        };
+        start < other_start + other_length && other_start < start + length
+    }
+}
+
+/// A window that isn't focused, remembering what it was showing for when it is again.
+#[derive(Debug, Clone)]
+pub struct Window {
+    pub view: View,
+    /// Where the buffer that the window shows sits in the buffer list.
+    pub buffer: usize,
+    /// Where the cursor was left in the window's buffer.
+    pub cursor: Position,
+}
+
+/// How the display is split up between the windows, which are referred to by their ids.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Layout {
+    Window(usize),
+    Split(Split, Vec<Layout>),
+}
+
+impl Layout {
+    /// Finds the split that directly holds the window `id`, along with where the window is in it.
+    fn parent_of(&mut self, id: usize) -> Option<(&mut Split, &mut Vec<Layout>, usize)> {
+        let (split, children) = match self {
+            Layout::Window(_) => return None,
+            Layout::Split(split, children) => (split, children),
+        };
+        let position = children
+            .iter()
+            .position(|child| matches!(child, Layout::Window(w) if *w == id));
+        match position {
+            Some(position) => Some((split, children, position)),
+            None => children.iter_mut().find_map(|child| child.parent_of(id)),
+        }
+    }
+
+    /// The first window in the layout, which is the one in its top left corner.
+    fn first_window(&self) -> usize {
+        match self {
+            Layout::Window(id) => *id,
+            Layout::Split(_, children) => children[0].first_window(),
+        }
+    }
+
+    /// Works out where everything in the layout goes in `area`.
+    ///
+    /// Windows that don't reach the bottom of the display need a status bar of their own, which comes out of their last row.
+    fn arrange(&self, area: Rect, needs_bar: bool, arrangement: &mut Arrangement) {
+        let (split, children) = match self {
+            Layout::Window(id) => {
+                let mut rect = area;
+                if needs_bar && rect.rows > 0 {
+                    rect.rows -= 1;
+                    let bar = Rect::new(rect.x, rect.y + rect.rows, rect.columns, 1);
+                    arrangement.bars.push((*id, bar));
+                }
+                arrangement.windows.push((*id, rect));
+                return;
+            }
+            Layout::Split(split, children) => (*split, children),
+        };
+
+        let n = children.len().try_into().unwrap_or(u16::MAX);
+        for (i, (child, mut rect)) in children.iter().zip(area.divide(split, n)).enumerate() {
+            let last = i + 1 == children.len();
+            match split {
+                Split::Horizontal => child.arrange(rect, needs_bar || !last, arrangement),
+                Split::Vertical if last => child.arrange(rect, needs_bar, arrangement),
+                Split::Vertical => {
+                    rect.columns = rect.columns.saturating_sub(1);
+                    let border = Rect::new(rect.x + rect.columns, rect.y, 1, rect.rows);
+                    arrangement.borders.push(border);
+                    child.arrange(rect, needs_bar, arrangement);
+                }
+            }
+        }
+    }
+}
+
+/// Where each window goes on the display, along with the status bars and borders between them.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Arrangement {
+    /// The part of the display that each window's text goes in, by its id.
+    pub windows: Vec<(usize, Rect)>,
+    /// The status bars that name the buffers shown in the windows above them, by the window's id.
+    pub bars: Vec<(usize, Rect)>,
+    /// The lines drawn between windows that are side by side.
+    pub borders: Vec<Rect>,
+}
+
+/// Keeps track of the windows that the display is split between.
+///
+/// Like the buffer list, the focused window is held on to by the editor, which swaps another one in to move the focus.
+#[derive(Debug)]
+pub struct WindowManager {
+    /// Every window apart from the focused one, by id.
+    hidden: BTreeMap<usize, Window>,
+    layout: Layout,
+    focused: usize,
+    next_id: usize,
+}
+
+impl Default for WindowManager {
+    fn default() -> Self {
+        Self {
+            hidden: BTreeMap::new(),
+            layout: Layout::Window(0),
+            focused: 0,
+            next_id: 1,
+        }
+    }
+}
+
+impl WindowManager {
+    /// The number of windows on the display, including the focused one.
+    pub fn len(&self) -> usize {
+        self.hidden.len() + 1
+    }
+
+    /// The id of the focused window.
+    pub fn focused(&self) -> usize {
+        self.focused
+    }
+
+    /// Looks up a window that isn't focused.
+    pub fn get(&self, id: usize) -> Option<&Window> {
+        self.hidden.get(&id)
+    }
+
+    /// Looks up a window that isn't focused, to change it.
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Window> {
+        self.hidden.get_mut(&id)
+    }
+
+    /// Goes through the windows that aren't focused, along with their ids.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Window)> {
+        self.hidden.iter().map(|(&id, window)| (id, window))
+    }
+
+    /// Goes through the windows that aren't focused.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Window> {
+        self.hidden.values_mut()
+    }
+
+    /// How the display is split up between the windows.
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// Puts back windows from a saved layout, with `focused` being the one that's left out of `hidden`.
+    pub fn restore(layout: Layout, hidden: BTreeMap<usize, Window>, focused: usize) -> Self {
+        let next_id = hidden.keys().copied().chain([focused]).max().unwrap_or(0) + 1;
+        Self {
+            hidden,
+            layout,
+            focused,
+            next_id,
+        }
+    }
+
+    /// Works out where each window goes in `area`.
+    pub fn arrange(&self, area: Rect) -> Arrangement {
+        let mut arrangement = Arrangement::default();
+        self.layout.arrange(area, false, &mut arrangement);
+        arrangement
+    }
+
+    /// Splits the focused window in two, putting `window` (usually a copy of the focused one) below or to the right of it.
+    ///
+    /// Returns whether there was room on the display to do so.
+    pub fn split(&mut self, split: Split, window: Window, area: Rect) -> bool {
+        let arrangement = self.arrange(area);
+        let room = arrangement
+            .windows
+            .iter()
+            .find(|(id, _)| *id == self.focused)
+            .is_some_and(|(_, rect)| match split {
+                Split::Horizontal => rect.rows > 2 * MIN_WINDOW_SIZE,
+                Split::Vertical => rect.columns > 2 * MIN_WINDOW_SIZE,
+            });
+        if !room {
+            return false;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.hidden.insert(id, window);
+
+        match self.layout.parent_of(self.focused) {
+            Some((parent, children, position)) if *parent == split => {
+                children.insert(position + 1, Layout::Window(id));
+            }
+            Some((_, children, position)) => {
+                let focused = Layout::Window(self.focused);
+                children[position] = Layout::Split(split, vec![focused, Layout::Window(id)]);
+            }
+            None => {
+                let focused = Layout::Window(self.focused);
+                self.layout = Layout::Split(split, vec![focused, Layout::Window(id)]);
+            }
+        }
+        true
+    }
+
+    /// Closes the focused window, handing back the window that the focus moves on to.
+    ///
+    /// The last window can't be closed, since there'd be nothing left to show.
+    pub fn close(&mut self) -> Option<Window> {
+        let (_, children, position) = self.layout.parent_of(self.focused)?;
+        children.remove(position);
+        let next = children[position.saturating_sub(1)].first_window();
+
+        // A split that's down to one window doesn't need to be a split any more.
+        self.collapse();
+
+        self.focused = next;
+        self.hidden.remove(&next)
+    }
+
+    /// Replaces any splits that only hold one thing with the thing they hold.
+    fn collapse(&mut self) {
+        fn collapse(layout: &mut Layout) {
+            if let Layout::Split(_, children) = layout {
+                children.iter_mut().for_each(collapse);
+                if children.len() == 1 {
+                    *layout = children.remove(0);
+                }
+            }
+        }
+        collapse(&mut self.layout);
+    }
+
+    /// Finds the window next to the focused one in `direction`, if there is one.
+    ///
+    /// When there are several, the one closest to the focused window is picked.
+    pub fn neighbour(&self, direction: Direction, area: Rect) -> Option<usize> {
+        let arrangement = self.arrange(area);
+        let (_, focused) = arrangement
+            .windows
+            .iter()
+            .find(|(id, _)| *id == self.focused)?;
+
+        arrangement
+            .windows
+            .iter()
+            .filter(|(_, rect)| rect.overlaps_across(focused, direction))
+            .filter_map(|(id, rect)| {
+                let distance = match direction {
+                    Direction::Left => focused.x.checked_sub(rect.x + rect.columns),
+                    Direction::Right => rect.x.checked_sub(focused.x + focused.columns),
+                    Direction::Up => focused.y.checked_sub(rect.y + rect.rows),
+                    Direction::Down => rect.y.checked_sub(focused.y + focused.rows),
+                };
+                distance.map(|distance| (distance, rect.x, rect.y, *id))
+            })
+            .min()
+            .map(|(_, _, _, id)| id)
+    }
+
+    /// Finds the window after the focused one, going back around to the first after the last.
+    pub fn next(&self, area: Rect) -> usize {
+        let arrangement = self.arrange(area);
+        let position = arrangement
+            .windows
+            .iter()
+            .position(|(id, _)| *id == self.focused)
+            .unwrap_or(0);
+        arrangement.windows[(position + 1) % arrangement.windows.len()].0
+    }
+
+    /// Moves the focus onto window `id`, putting the window that was focused back with the others.
+    ///
+    /// Hands back the window that is now focused, unless there isn't one with that id.
+    pub fn focus(&mut self, id: usize, focused: Window) -> Option<Window> {
+        let window = self.hidden.remove(&id)?;
+        let previous = mem::replace(&mut self.focused, id);
+        self.hidden.insert(previous, focused);
+        Some(window)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn window() -> Window {
+        Window {
+            view: View::default(),
+            buffer: 0,
+            cursor: Position::new(0, 0),
+        }
+    }
+
+    #[test]
+    fn test_arranging_splits() {
+        let area = Rect::new(0, 0, 21, 16);
+        let mut windows = WindowManager::default();
+        assert!(windows.split(Split::Vertical, window(), area));
+        assert!(windows.split(Split::Horizontal, window(), area));
+
+        assert_eq!(
+            windows.arrange(area),
+            Arrangement {
+                windows: vec![
+                    (0, Rect::new(0, 0, 9, 7)),
+                    (2, Rect::new(0, 8, 9, 8)),
+                    (1, Rect::new(10, 0, 11, 16))
+                ],
+                bars: vec![(0, Rect::new(0, 7, 9, 1))],
+                borders: vec![Rect::new(9, 0, 1, 16)],
+            }
+        );
+
+        // Splitting the same way again adds onto the split that's already there.
+        assert!(windows.split(Split::Horizontal, window(), area));
+        assert_eq!(windows.arrange(area).windows.len(), 4);
+        assert_eq!(windows.arrange(area).bars.len(), 2);
+
+        // Windows can't be split so small that there's nothing left of them.
+        assert!(!windows.split(Split::Horizontal, window(), area));
+        assert_eq!(windows.len(), 4);
+    }
+
+    #[test]
+    fn test_moving_between_windows() {
+        let area = Rect::new(0, 0, 20, 10);
+        let mut windows = WindowManager::default();
+        windows.split(Split::Vertical, window(), area);
+        windows.split(Split::Horizontal, window(), area);
+
+        assert_eq!(windows.neighbour(Direction::Right, area), Some(1));
+        assert_eq!(windows.neighbour(Direction::Down, area), Some(2));
+        assert_eq!(windows.neighbour(Direction::Left, area), None);
+        assert_eq!(windows.next(area), 2);
+
+        assert!(windows.focus(1, window()).is_some());
+        assert_eq!(windows.focused(), 1);
+        assert_eq!(windows.neighbour(Direction::Left, area), Some(0));
+        assert!(windows.focus(1, window()).is_none());
+    }
+
+    #[test]
+    fn test_closing_windows() {
+        let area = Rect::new(0, 0, 20, 10);
+        let mut windows = WindowManager::default();
+        assert!(windows.close().is_none());
+
+        windows.split(Split::Vertical, window(), area);
+        windows.split(Split::Horizontal, window(), area);
+        assert!(windows.close().is_some());
+        assert_eq!(windows.focused(), 2);
+        assert_eq!(
+            windows.arrange(area).windows,
+            vec![(2, Rect::new(0, 0, 9, 10)), (1, Rect::new(10, 0, 10, 10))]
+        );
+
+        assert!(windows.close().is_some());
+        assert_eq!(windows.focused(), 1);
+        assert_eq!(windows.len(), 1);
+        assert_eq!(
+            windows.arrange(area),
+            Arrangement {
+                windows: vec![(1, Rect::new(0, 0, 20, 10))],
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Finishing off a word being typed in Edit mode with one that's already in a buffer, which `Ctrl-N` and `Ctrl-P` go through.
+use crate::buffer::Buffer;
+
+/// The words that the one before the cursor could be finished off with, and which of them is in the buffer at the moment.
+#[derive(Debug)]
+pub struct WordCompletion {
+    /// Where the word being finished off starts in the buffer.
+    start: usize,
+    /// What had been typed of the word, which going through the words comes back round to after the last one.
+    typed: String,
+    /// The words that start with `typed`, in the order that `Ctrl-N` goes through them.
+    candidates: Vec<String>,
+    /// The candidate that's in the buffer, or `None` while it's `typed`.
+    selected: Option<usize>,
+}
+
+impl WordCompletion {
+    /// Gathers up the words that start with the one before the cursor in `buffer`, if there are any.
+    ///
+    /// Like in Vim, the words after the cursor come first, then the ones from the start of the buffer up to the cursor (so that
+    /// `Ctrl-P` finds the nearest one above it first), and then the words in `others`.
+    pub fn new<'a>(buffer: &Buffer, others: impl IntoIterator<Item = &'a Buffer>) -> Option<Self> {
+        let cursor = buffer.cursor_index();
+        let line_start = buffer
+            .line_range(buffer.cursor().line)
+            .map_or(0, |range| range.start);
+        let before = buffer.contents().slice(line_start..cursor);
+        let typed = &before[before.trim_end_matches(is_word_char).len()..];
+        let start = cursor - typed.len();
+
+        let mut candidates: Vec<String> = Vec::new();
+        let mut add = |word: &str| {
+            if word.starts_with(typed) && word != typed && !candidates.iter().any(|c| c == word) {
+                candidates.push(word.to_string());
+            }
+        };
+        if !buffer.is_large() {
+            let text = buffer.contents().to_string();
+            let words: Vec<(usize, &str)> = words(&text).collect();
+            // The word being typed is left out, even when there's more of it after the cursor.
+            let after = words.iter().filter(|&&(index, _)| index > start);
+            let before = words.iter().filter(|&&(index, _)| index < start);
+            for &(_, word) in after.chain(before) {
+                add(word);
+            }
+        }
+        for other in others.into_iter().filter(|other| !other.is_large()) {
+            for (_, word) in words(&other.contents().to_string()) {
+                add(word);
+            }
+        }
+
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(Self {
+            start,
+            typed: typed.to_string(),
+            candidates,
+            selected: None,
+        })
+    }
+
+    /// Where the word being finished off starts in the buffer.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// What had been typed of the word before going through the candidates.
+    pub fn typed(&self) -> &str {
+        &self.typed
+    }
+
+    /// The words that the one being typed could be finished off with.
+    pub fn candidates(&self) -> &[String] {
+        &self.candidates
+    }
+
+    /// Which of the candidates is in the buffer at the moment, if any of them are.
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Moves on to the next candidate, returning the word to put in the buffer.
+    pub fn next(&mut self) -> &str {
+        self.selected = match self.selected {
+            None => Some(0),
+            Some(i) if i + 1 < self.candidates.len() => Some(i + 1),
+            Some(_) => None,
+        };
+        self.word()
+    }
+
+    /// Moves back to the previous candidate, returning the word to put in the buffer.
+    pub fn previous(&mut self) -> &str {
+        self.selected = match self.selected {
+            None => self.candidates.len().checked_sub(1),
+            Some(0) => None,
+            Some(i) => Some(i - 1),
+        };
+        self.word()
+    }
+
+    /// The selected candidate, or what was typed.
+    fn word(&self) -> &str {
+        match self.selected {
+            Some(i) => &self.candidates[i],
+            None => &self.typed,
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Finds each of the words in some text, along with where it starts.
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split(|c: char| !is_word_char(c))
+        .filter(|word| !word.is_empty())
+        .map(move |word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn buffer_at(text: &str, index: usize) -> Buffer {
+        let mut buffer = Buffer::new(text);
+        buffer.set_cursor_index(index);
+        buffer
+    }
+
+    #[test]
+    fn test_finding_words() {
+        assert_eq!(
+            words("  foo_bar(x, é2)").collect::<Vec<_>>(),
+            [(2, "foo_bar"), (10, "x"), (13, "é2")]
+        );
+    }
+
+    #[test]
+    fn test_ordering_candidates() {
+        let text = "alpha apple\nap\napricot alpha apt";
+        let buffer = buffer_at(text, 14);
+        let mut completion = WordCompletion::new(&buffer, []).unwrap();
+        assert_eq!(completion.start(), 12);
+        assert_eq!(completion.typed(), "ap");
+        assert_eq!(completion.candidates(), ["apricot", "apt", "apple"]);
+
+        assert_eq!(completion.next(), "apricot");
+        assert_eq!(completion.next(), "apt");
+        assert_eq!(completion.selected(), Some(1));
+        assert_eq!(completion.previous(), "apricot");
+        assert_eq!(completion.previous(), "ap");
+        assert_eq!(completion.selected(), None);
+        assert_eq!(completion.previous(), "apple");
+        assert_eq!(completion.next(), "ap");
+    }
+
+    #[test]
+    fn test_words_from_other_buffers() {
+        let buffer = buffer_at("pri", 3);
+        let others = [Buffer::new("print private\n"), Buffer::new("println print")];
+        let completion = WordCompletion::new(&buffer, &others).unwrap();
+        assert_eq!(completion.candidates(), ["print", "private", "println"]);
+
+        assert!(WordCompletion::new(&buffer, []).is_none());
+        assert!(WordCompletion::new(&buffer_at("xyz abc", 3), []).is_none());
+    }
+}